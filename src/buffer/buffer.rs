@@ -5,23 +5,31 @@ use crate::{
     log::log_manager::LogManager,
 };
 
+use super::dirty_page_table::DirtyPageTable;
+
 #[derive(Debug, Default)]
 pub struct Buffer {
     file_manager: Arc<Mutex<FileManager>>,
     log_manager: Arc<Mutex<LogManager>>,
-    contents: Page,         // buffer contents
-    block: Option<BlockId>, // block to which this buffer is assigned
-    pins: i32,              // number of times this buffer has been pinned
-    txnum: i32,             // transaction number, if not -1, then this buffer is modified?
-    lsn: i32,               // log sequence number
+    dirty_pages: Arc<Mutex<DirtyPageTable>>, // shared with the other buffers in the pool
+    contents: Page,                          // buffer contents
+    block: Option<BlockId>,                  // block to which this buffer is assigned
+    pins: i32,                               // number of times this buffer has been pinned
+    txnum: i32, // transaction number, if not -1, then this buffer is modified?
+    lsn: i32,   // largest lsn of the log records for the modifications
 }
 
 impl Buffer {
-    pub fn new(file_manager: Arc<Mutex<FileManager>>, log_manager: Arc<Mutex<LogManager>>) -> Self {
+    pub fn new(
+        file_manager: Arc<Mutex<FileManager>>,
+        log_manager: Arc<Mutex<LogManager>>,
+        dirty_pages: Arc<Mutex<DirtyPageTable>>,
+    ) -> Self {
         let contents = Page::new(file_manager.lock().unwrap().block_size);
         Self {
             file_manager,
             log_manager,
+            dirty_pages,
             contents,
             txnum: -1,
             lsn: -1,
@@ -29,6 +37,11 @@ impl Buffer {
         }
     }
 
+    /// contents はバッファの中身を読み込み専用で返す。読み込むだけなら排他的に借りる必要はない
+    pub fn contents(&self) -> &Page {
+        &self.contents
+    }
+
     pub fn contents_mut(&mut self) -> &mut Page {
         &mut self.contents
     }
@@ -37,13 +50,21 @@ impl Buffer {
         self.block.as_ref()
     }
 
+    // set_modified records the modification in the dirty page table
+    // lsn is -1 if the modification is not logged
     pub fn set_modified(&mut self, txnum: i32, lsn: i32) {
         self.txnum = txnum;
-        if lsn >= 0 {
-            self.lsn = lsn;
+        // keep the largest lsn even if the caller passes an older one
+        self.lsn = self.lsn.max(lsn);
+        if let Some(block) = &self.block {
+            self.dirty_pages.lock().unwrap().mark_dirty(block, lsn);
         }
     }
 
+    pub fn is_dirty(&self) -> bool {
+        self.txnum >= 0
+    }
+
     pub fn is_pinned(&self) -> bool {
         self.pins > 0
     }
@@ -63,18 +84,42 @@ impl Buffer {
         self.pins = 0;
     }
 
+    // flush writes the contents to the disk if the buffer is modified
+    // the log records are always flushed before the page (write-ahead logging)
     pub fn flush(&mut self) {
         if self.txnum >= 0 {
-            self.log_manager.lock().unwrap().flush(self.lsn).unwrap();
+            {
+                let mut log_manager = self.log_manager.lock().unwrap();
+                // if no lsn is known, flush the whole log to be safe
+                let lsn = if self.lsn >= 0 {
+                    self.lsn
+                } else {
+                    log_manager.latest_lsn()
+                };
+                log_manager.flush(lsn).unwrap();
+            }
+            let block = self.block.as_ref().unwrap();
             self.file_manager
                 .lock()
                 .unwrap()
-                .write(self.block.as_ref().unwrap(), &mut self.contents)
+                .write(block, &mut self.contents)
                 .unwrap();
+            self.dirty_pages.lock().unwrap().mark_clean(block);
             self.txnum = -1;
+            self.lsn = -1;
         }
     }
 
+    /// discard はディスクに書き込まずにブロックの割り当てを解除する
+    /// 削除したファイルのブロックを後で書き戻してファイルが復活しないようにする
+    pub fn discard(&mut self) {
+        if let Some(block) = self.block.take() {
+            self.dirty_pages.lock().unwrap().mark_clean(&block);
+        }
+        self.txnum = -1;
+        self.lsn = -1;
+    }
+
     pub fn pin(&mut self) {
         self.pins += 1;
     }
@@ -95,8 +140,8 @@ mod tests {
         let log_manager = Arc::new(Mutex::new(
             LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
         ));
-        let mut buffer = Buffer::new(file_manager, log_manager);
-        assert_eq!(buffer.contents.contents().len(), 32);
+        let buffer = Buffer::new(file_manager, log_manager, Default::default());
+        assert_eq!(buffer.contents().contents().len(), 32);
         assert_eq!(buffer.block(), None);
         assert!(!buffer.is_pinned());
     }
@@ -108,16 +153,43 @@ mod tests {
         let log_manager = Arc::new(Mutex::new(
             LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
         ));
-        let mut buffer = Buffer::new(file_manager.clone(), log_manager.clone());
+        let dirty_pages = Arc::new(Mutex::new(DirtyPageTable::default()));
+        let mut buffer = Buffer::new(
+            file_manager.clone(),
+            log_manager.clone(),
+            dirty_pages.clone(),
+        );
         let block = BlockId::new("test".to_string(), 0);
         buffer.assign_to_block(&block);
 
         buffer.contents_mut().set_string(0, "hello");
         buffer.set_modified(0, 1);
+        assert_eq!(dirty_pages.lock().unwrap().rec_lsn(&block), Some(1));
         buffer.flush();
+        assert!(dirty_pages.lock().unwrap().is_empty());
 
-        let mut new_buffer = Buffer::new(file_manager, log_manager);
+        let mut new_buffer = Buffer::new(file_manager, log_manager, dirty_pages);
         new_buffer.assign_to_block(&block);
-        assert_eq!(new_buffer.contents.get_string(0), "hello");
+        assert_eq!(new_buffer.contents().get_string(0), "hello");
+    }
+
+    #[test]
+    fn should_flush_log_before_page() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        let mut buffer = Buffer::new(file_manager, log_manager.clone(), Default::default());
+        buffer.assign_to_block(&BlockId::new("test".to_string(), 0));
+
+        // the modification is logged, but the caller does not pass the lsn
+        let lsn = log_manager.lock().unwrap().append(b"hello").unwrap();
+        buffer.set_modified(0, -1);
+        buffer.flush();
+
+        let log = std::fs::read(tempdir.path().join("log")).unwrap();
+        assert!(log.windows(5).any(|bytes| bytes == b"hello"));
+        assert_eq!(log_manager.lock().unwrap().latest_lsn(), lsn);
     }
 }
@@ -1,20 +1,42 @@
 use crate::{
-    file::{block::BlockId, file_manager::FileManager},
+    clock::{default_clock, Clock},
+    file::{block::BlockId, file_manager::FileManager, temp_file_manager::TempFileManager},
     log::log_manager::LogManager,
-    TIMEOUT,
+    timeout::{TimeoutKind, Timeouts},
 };
 use anyhow::{bail, Result};
 use std::{
-    sync::{Arc, Mutex},
-    time::SystemTime,
+    sync::{Arc, Condvar, Mutex},
+    time::Instant,
 };
 
-use super::buffer::Buffer;
+use super::{buffer::Buffer, buffer_ring::BufferRing, dirty_page_table::DirtyPageTable};
 
+/// BufferShard はバッファプールを分けた1つの区画。区画ごとにロックと空きバッファの数を持つ
+#[derive(Debug, Default)]
+struct BufferShard {
+    buffers: Vec<Arc<Mutex<Buffer>>>,
+    num_available: u64,
+}
+
+impl BufferShard {
+    fn contains(&self, buffer: &Arc<Mutex<Buffer>>) -> bool {
+        self.buffers.iter().any(|b| Arc::ptr_eq(b, buffer))
+    }
+}
+
+/// BufferManager はバッファプールを管理する
+///
+/// プールはブロックのハッシュで決まる区画に分けてあり、ピンとピンの解除はそのブロックの区画だけをロックする
+/// 別の区画のブロックは並行してピンできるので、スレッドが多くてもプール全体のロックを待たない
+/// ブロックは自分の区画のバッファだけを使うので、区画を増やすと1つの区画が埋まりやすくなることに注意する
+/// 空きバッファを待つスレッドは区画の条件変数で待ち、その区画のピンが解除されると起こされる
 #[derive(Debug)]
 pub struct BufferManager {
-    buffer_pool: Vec<Arc<Mutex<Buffer>>>,
-    pub num_available: u64,
+    shards: Vec<(Mutex<BufferShard>, Condvar)>,
+    dirty_pages: Arc<Mutex<DirtyPageTable>>,
+    clock: Arc<dyn Clock>, // used to decide how long to wait for a free buffer
+    timeouts: Timeouts,
 }
 
 impl BufferManager {
@@ -23,22 +45,102 @@ impl BufferManager {
         log_manager: Arc<Mutex<LogManager>>,
         num_buffers: u64,
     ) -> Self {
-        let mut buffer_pool = Vec::with_capacity(num_buffers as usize);
+        let dirty_pages = Arc::new(Mutex::new(DirtyPageTable::default()));
+        let mut buffers = Vec::with_capacity(num_buffers as usize);
         for _ in 0..num_buffers {
-            buffer_pool.push(Arc::new(Mutex::new(Buffer::new(
+            buffers.push(Arc::new(Mutex::new(Buffer::new(
                 file_manager.clone(),
                 log_manager.clone(),
+                dirty_pages.clone(),
             ))));
         }
 
         Self {
-            buffer_pool,
-            num_available: num_buffers,
+            shards: vec![(
+                Mutex::new(BufferShard {
+                    buffers,
+                    num_available: num_buffers,
+                }),
+                Condvar::new(),
+            )],
+            dirty_pages,
+            clock: default_clock(),
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// with_clock は空きバッファを待つ時間の判定に使う時計を設定する
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// with_timeouts は空きバッファを待つ時間と、空きバッファを探し直す間隔を設定する
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// with_shards はバッファプールを num_shards 個の区画に分け直す
+    /// 区画の数はバッファの数を超えないようにし、バッファは区画に順に配る
+    pub fn with_shards(mut self, num_shards: usize) -> Self {
+        let buffers: Vec<_> = self
+            .shards
+            .drain(..)
+            .flat_map(|(shard, _)| shard.into_inner().unwrap().buffers)
+            .collect();
+        let num_shards = num_shards.clamp(1, buffers.len().max(1));
+        let mut shards: Vec<_> = (0..num_shards).map(|_| BufferShard::default()).collect();
+        for (i, buffer) in buffers.into_iter().enumerate() {
+            let shard = &mut shards[i % num_shards];
+            if !buffer.lock().unwrap().is_pinned() {
+                shard.num_available += 1;
+            }
+            shard.buffers.push(buffer);
         }
+        self.shards = shards
+            .into_iter()
+            .map(|shard| (Mutex::new(shard), Condvar::new()))
+            .collect();
+        self
+    }
+
+    /// num_shards はバッファプールの区画の数を返す
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// num_buffers はバッファプールのバッファの数を返す
+    pub fn num_buffers(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|(shard, _)| shard.lock().unwrap().buffers.len() as u64)
+            .sum()
     }
 
-    pub fn flush_all(&mut self, txnum: i32) {
-        for buffer in &mut self.buffer_pool {
+    /// num_available はピンされていないバッファの数を返す
+    pub fn num_available(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|(shard, _)| shard.lock().unwrap().num_available)
+            .sum()
+    }
+
+    /// shard はブロックを受け持つ区画と、その区画の空きバッファを待つ条件変数を返す
+    fn shard(&self, block: &BlockId) -> &(Mutex<BufferShard>, Condvar) {
+        &self.shards[(block.hash() % self.shards.len() as u64) as usize]
+    }
+
+    /// buffers はすべての区画のバッファを返す。区画のロックは返す前に解放する
+    fn buffers(&self) -> Vec<Arc<Mutex<Buffer>>> {
+        self.shards
+            .iter()
+            .flat_map(|(shard, _)| shard.lock().unwrap().buffers.clone())
+            .collect()
+    }
+
+    pub fn flush_all(&self, txnum: i32) {
+        for buffer in self.buffers() {
             let mut x = buffer.lock().unwrap();
             if x.modifying_tx() == txnum {
                 x.flush();
@@ -46,69 +148,258 @@ impl BufferManager {
         }
     }
 
-    pub fn unpin(&mut self, buffer: Arc<Mutex<Buffer>>) {
+    /// discard_temp_file は一時ファイルのブロックに割り当てられたピンされていないバッファを破棄する
+    pub fn discard_temp_file(&self, name: &str) {
+        for (shard, _) in &self.shards {
+            let shard = shard.lock().unwrap();
+            for buffer in &shard.buffers {
+                let mut buffer = buffer.lock().unwrap();
+                let in_file = buffer
+                    .block()
+                    .is_some_and(|block| TempFileManager::belongs_to(name, &block.filename));
+                if in_file && !buffer.is_pinned() {
+                    buffer.discard();
+                }
+            }
+        }
+    }
+
+    /// discard_file はファイルのブロックに割り当てられたバッファをディスクに書き込んでから破棄する
+    /// ファイルを空にしたり置き換えたりする前に使い、古い内容のバッファが残らないようにする
+    /// ピンされているバッファがある場合は何も破棄せずにエラーを返す
+    pub fn discard_file(&self, filename: &str) -> Result<()> {
+        self.discard_where(|block| block.filename == filename, true)
+    }
+
+    /// discard_blocks はファイルの from 番目から後のブロックに割り当てられたバッファを、ディスクに書き込まずに破棄する
+    /// ファイルを短くして取り消すブロックの変更が、後で書き戻されないようにする
+    /// ピンされているバッファがある場合は何も破棄せずにエラーを返す
+    pub fn discard_blocks(&self, filename: &str, from: i32) -> Result<()> {
+        self.discard_where(
+            |block| block.filename == filename && block.num >= from,
+            false,
+        )
+    }
+
+    /// discard_where は matches が true を返すブロックのバッファを破棄する。flush が true の場合は先に書き込む
+    /// 確かめている間にピンされないように、すべての区画をロックしてから確かめる
+    fn discard_where(&self, matches: impl Fn(&BlockId) -> bool, flush: bool) -> Result<()> {
+        let shards: Vec<_> = self
+            .shards
+            .iter()
+            .map(|(shard, _)| shard.lock().unwrap())
+            .collect();
+        let buffers: Vec<_> = shards
+            .iter()
+            .flat_map(|shard| &shard.buffers)
+            .filter(|buffer| buffer.lock().unwrap().block().is_some_and(&matches))
+            .collect();
+        for buffer in &buffers {
+            let buffer = buffer.lock().unwrap();
+            if buffer.is_pinned() {
+                bail!("cannot discard pinned block: {}", buffer.block().unwrap());
+            }
+        }
+        for buffer in buffers {
+            let mut buffer = buffer.lock().unwrap();
+            if flush {
+                buffer.flush();
+            }
+            buffer.discard();
+        }
+        Ok(())
+    }
+
+    /// dirty_pages はまだディスクに書き込まれていないブロックと recLSN を recLSN の昇順に返す
+    pub fn dirty_pages(&self) -> Vec<(BlockId, i32)> {
+        self.dirty_pages.lock().unwrap().pages()
+    }
+
+    /// min_rec_lsn はリカバリで redo を始める必要がある最も古い LSN を返す
+    pub fn min_rec_lsn(&self) -> Option<i32> {
+        self.dirty_pages.lock().unwrap().min_rec_lsn()
+    }
+
+    /// pinned_count はピンされているバッファの数を返す。テストでピンの解放漏れを確かめるのに使う
+    pub fn pinned_count(&self) -> usize {
+        self.buffers()
+            .iter()
+            .filter(|buffer| buffer.lock().unwrap().is_pinned())
+            .count()
+    }
+
+    pub fn unpin(&self, buffer: Arc<Mutex<Buffer>>) {
+        // ピンされている間はブロックが変わらないので、区画を決めてからバッファをロックし直す
+        let Some(block) = buffer.lock().unwrap().block().cloned() else {
+            return;
+        };
+        let (shard, available) = self.shard(&block);
+        let mut shard = shard.lock().unwrap();
         let mut buffer = buffer.lock().unwrap();
         buffer.unpin();
         if !buffer.is_pinned() {
-            self.num_available += 1;
+            shard.num_available += 1;
+            available.notify_all();
         }
     }
 
-    pub fn pin(&mut self, block: &BlockId) -> Result<Arc<Mutex<Buffer>>> {
-        let now = SystemTime::now();
-        let mut buffer = self.try_pin(block);
-        while buffer.is_none() && !self.waiting_too_long(now) {
-            std::thread::sleep(TIMEOUT);
-            buffer = self.try_pin(block);
+    pub fn pin(&self, block: &BlockId) -> Result<Arc<Mutex<Buffer>>> {
+        self.pin_with(block, None)
+    }
+
+    /// pin_with は ring が指定された場合、新しいブロックにリングのバッファを使い回す
+    /// すでにプールにあるブロックはそのバッファを使う
+    /// 空きバッファを待つ間は区画の条件変数で待ち、区画のロックを解放するので、他のスレッドがピンを解除できる
+    pub fn pin_with(
+        &self,
+        block: &BlockId,
+        mut ring: Option<&mut BufferRing>,
+    ) -> Result<Arc<Mutex<Buffer>>> {
+        let (shard, available) = self.shard(block);
+        let start_time = self.clock.now();
+        let mut shard = shard.lock().unwrap();
+        let mut attempt = 0;
+        loop {
+            if let Some(buffer) = self.try_pin_in(&mut shard, block, ring.as_deref_mut()) {
+                return Ok(buffer);
+            }
+            if self.waiting_too_long(start_time) {
+                return Err(self
+                    .timeouts
+                    .error(TimeoutKind::Buffer)
+                    .context("buffer pool is full"));
+            }
+            let wait = self.clock.wait_time(self.timeouts.backoff(attempt));
+            shard = available.wait_timeout(shard, wait).unwrap().0;
+            attempt += 1;
         }
-        let Some(buffer) = buffer else {
-            bail!("buffer pool is full");
-        };
-        Ok(buffer)
     }
 
-    pub fn try_pin(&mut self, block: &BlockId) -> Option<Arc<Mutex<Buffer>>> {
-        let buffer = self.find_existing_buffer(block);
+    pub fn try_pin(&self, block: &BlockId) -> Option<Arc<Mutex<Buffer>>> {
+        let mut shard = self.shard(block).0.lock().unwrap();
+        self.try_pin_in(&mut shard, block, None)
+    }
+
+    /// try_pin_in はロックした区画の中でブロックをピンする。空きバッファがない場合は None を返す
+    fn try_pin_in(
+        &self,
+        shard: &mut BufferShard,
+        block: &BlockId,
+        ring: Option<&mut BufferRing>,
+    ) -> Option<Arc<Mutex<Buffer>>> {
+        let buffer = find_existing_buffer(shard, block);
 
         let buffer = match buffer {
             Some(buffer) => buffer,
             None => {
-                let buffer = self.choose_unpinned_buffer()?;
+                let buffer = match ring {
+                    Some(ring) => self.choose_ring_buffer(shard, ring)?,
+                    None => self.choose_replacement(shard, |_| true)?,
+                };
                 buffer.lock().unwrap().assign_to_block(block);
                 buffer
             }
         };
 
-        if !buffer.lock().unwrap().is_pinned() {
-            self.num_available -= 1;
+        let mut locked = buffer.lock().unwrap();
+        if !locked.is_pinned() {
+            shard.num_available -= 1;
         }
-        buffer.lock().unwrap().pin();
+        locked.pin();
+        drop(locked);
 
         Some(buffer)
     }
 
-    pub fn waiting_too_long(&self, start_time: SystemTime) -> bool {
-        SystemTime::now().duration_since(start_time).unwrap() > TIMEOUT
+    pub fn waiting_too_long(&self, start_time: Instant) -> bool {
+        self.clock.now().duration_since(start_time) > self.timeouts.buffer
     }
 
     pub fn find_existing_buffer(&self, block: &BlockId) -> Option<Arc<Mutex<Buffer>>> {
-        self.buffer_pool
-            .iter()
-            .find(|buffer| buffer.lock().unwrap().block() == Some(block))
-            .cloned()
+        find_existing_buffer(&self.shard(block).0.lock().unwrap(), block)
     }
 
-    pub fn choose_unpinned_buffer(&mut self) -> Option<Arc<Mutex<Buffer>>> {
-        self.buffer_pool
+    /// choose_ring_buffer はリングが埋まっていればリングのバッファを使い回す
+    /// リングが埋まっていないかすべてピンされていれば、リングにないバッファをプールから選んでリングに加える
+    /// プールから選ぶときは、まだブロックを割り当てていないバッファを優先してほかのページを残す
+    /// 使い回すのはブロックの区画にあるバッファだけなので、区画が複数ある場合はリングの中身が区画をまたいで入れ替わる
+    fn choose_ring_buffer(
+        &self,
+        shard: &BufferShard,
+        ring: &mut BufferRing,
+    ) -> Option<Arc<Mutex<Buffer>>> {
+        if ring.is_full() {
+            if let Some(buffer) = ring.next_unpinned(|buffer| shard.contains(buffer)) {
+                return Some(buffer);
+            }
+        }
+        let unassigned = shard
+            .buffers
             .iter()
-            .find(|buffer| !buffer.lock().unwrap().is_pinned())
-            .cloned()
+            .find(|buffer| {
+                let buffer = buffer.lock().unwrap();
+                !buffer.is_pinned() && buffer.block().is_none()
+            })
+            .cloned();
+        let buffer = match unassigned {
+            Some(buffer) => buffer,
+            None => self.choose_replacement(shard, |buffer| !ring.contains(buffer))?,
+        };
+        ring.add(buffer.clone());
+        Some(buffer)
+    }
+
+    /// choose_replacement は区画の中で candidate が true を返すバッファから置き換えるバッファを選ぶ
+    /// 書き込みが不要な変更されていないバッファを優先し、なければ recLSN が最も古いバッファを選ぶ
+    /// 古い変更から書き込むことで、リカバリで遡る必要があるログを短くする
+    fn choose_replacement(
+        &self,
+        shard: &BufferShard,
+        candidate: impl Fn(&Arc<Mutex<Buffer>>) -> bool,
+    ) -> Option<Arc<Mutex<Buffer>>> {
+        let mut dirty = vec![];
+        for buffer in shard.buffers.iter().filter(|buffer| candidate(buffer)) {
+            let locked = buffer.lock().unwrap();
+            if locked.is_pinned() {
+                continue;
+            }
+            if !locked.is_dirty() {
+                return Some(buffer.clone());
+            }
+            dirty.push((locked.block().cloned(), buffer));
+        }
+
+        // Buffer::set_modified はバッファのロックを持ったまま dirty_pages をロックするので、
+        // デッドロックしないようにバッファのロックを解放してから dirty_pages をロックする
+        let dirty_pages = self.dirty_pages.lock().unwrap();
+        dirty
+            .into_iter()
+            .min_by_key(|(block, _)| {
+                block
+                    .as_ref()
+                    .and_then(|block| dirty_pages.rec_lsn(block))
+                    .unwrap_or(-1)
+            })
+            .map(|(_, buffer)| buffer.clone())
     }
 }
 
+fn find_existing_buffer(shard: &BufferShard, block: &BlockId) -> Option<Arc<Mutex<Buffer>>> {
+    shard
+        .buffers
+        .iter()
+        .find(|buffer| buffer.lock().unwrap().block() == Some(block))
+        .cloned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        clock::MockClock,
+        timeout::{WaitTimeout, DEFAULT_TIMEOUT},
+    };
+    use std::time::Duration;
 
     #[test]
     fn should_can_pin() {
@@ -117,12 +408,12 @@ mod tests {
         let log_manager = Arc::new(Mutex::new(
             LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
         ));
-        let mut buffer_manager = BufferManager::new(file_manager, log_manager, 3);
-        assert_eq!(buffer_manager.num_available, 3);
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 3);
+        assert_eq!(buffer_manager.num_available(), 3);
         let block = BlockId::new("test".to_string(), 0);
         let buf = buffer_manager.pin(&block).unwrap();
         assert_eq!(buf.lock().unwrap().block(), Some(&block));
-        assert_eq!(buffer_manager.num_available, 2);
+        assert_eq!(buffer_manager.num_available(), 2);
     }
 
     #[test]
@@ -132,14 +423,89 @@ mod tests {
         let log_manager = Arc::new(Mutex::new(
             LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
         ));
-        let mut buffer_manager = BufferManager::new(file_manager, log_manager, 1);
-        assert_eq!(buffer_manager.num_available, 1);
+        let clock = Arc::new(MockClock::default());
+        let timeouts = Timeouts {
+            buffer: Duration::from_secs(1),
+            ..Timeouts::default()
+        };
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 1)
+            .with_clock(clock.clone())
+            .with_timeouts(timeouts);
+        assert_eq!(buffer_manager.num_available(), 1);
         let block = BlockId::new("test".to_string(), 0);
         let buf = buffer_manager.pin(&block).unwrap();
         assert_eq!(buf.lock().unwrap().block(), Some(&block));
         let block = BlockId::new("test".to_string(), 1);
-        let buf = buffer_manager.pin(&block);
-        assert!(buf.is_err());
+        let err = buffer_manager.pin(&block).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WaitTimeout>().map(|t| t.kind),
+            Some(TimeoutKind::Buffer)
+        );
+        // the mock clock advances instead of sleeping until the timeout
+        assert!(clock.elapsed() > timeouts.buffer);
+        assert!(clock.elapsed() < DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn should_wake_up_waiting_pin_when_buffer_is_unpinned() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        // 確かめ直す間隔を長くして、条件変数で起こされなければ間に合わないようにする
+        let timeouts = Timeouts {
+            backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+            ..Timeouts::default()
+        };
+        let buffer_manager =
+            Arc::new(BufferManager::new(file_manager, log_manager, 1).with_timeouts(timeouts));
+        let buf = buffer_manager
+            .pin(&BlockId::new("test".to_string(), 0))
+            .unwrap();
+
+        let start = Instant::now();
+        let waiter = {
+            let buffer_manager = buffer_manager.clone();
+            std::thread::spawn(move || buffer_manager.pin(&BlockId::new("test".to_string(), 1)))
+        };
+        std::thread::sleep(Duration::from_millis(100));
+        buffer_manager.unpin(buf);
+        let buf = waiter.join().unwrap().unwrap();
+        assert_eq!(
+            buf.lock().unwrap().block(),
+            Some(&BlockId::new("test".to_string(), 1))
+        );
+        assert!(start.elapsed() < timeouts.buffer);
+    }
+
+    #[test]
+    fn should_not_discard_pinned_buffers() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 3);
+        let pinned = BlockId::new("test".to_string(), 2);
+        let unpinned = BlockId::new("test".to_string(), 0);
+        let buf = buffer_manager.pin(&pinned).unwrap();
+        let other = buffer_manager.pin(&unpinned).unwrap();
+        buffer_manager.unpin(other);
+
+        // ピンされているバッファがあれば、他のバッファも破棄しない
+        assert!(buffer_manager.discard_file("test").is_err());
+        assert!(buffer_manager.discard_blocks("test", 1).is_err());
+        assert!(buffer_manager.find_existing_buffer(&unpinned).is_some());
+        assert_eq!(buf.lock().unwrap().block(), Some(&pinned));
+        buffer_manager.discard_blocks("test", 3).unwrap();
+
+        buffer_manager.unpin(buf);
+        assert_eq!(buffer_manager.num_available(), 3);
+        buffer_manager.discard_file("test").unwrap();
+        assert!(buffer_manager.find_existing_buffer(&pinned).is_none());
+        assert!(buffer_manager.find_existing_buffer(&unpinned).is_none());
     }
 
     #[test]
@@ -149,8 +515,8 @@ mod tests {
         let log_manager = Arc::new(Mutex::new(
             LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
         ));
-        let mut buffer_manager = BufferManager::new(file_manager, log_manager, 3);
-        assert_eq!(buffer_manager.num_available, 3);
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 3);
+        assert_eq!(buffer_manager.num_available(), 3);
         let block = BlockId::new("test".to_string(), 0);
         let buf = buffer_manager.pin(&block).unwrap();
         assert_eq!(buf.lock().unwrap().block(), Some(&block));
@@ -165,13 +531,147 @@ mod tests {
         let log_manager = Arc::new(Mutex::new(
             LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
         ));
-        let mut buffer_manager = BufferManager::new(file_manager, log_manager, 3);
-        assert_eq!(buffer_manager.num_available, 3);
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 3);
+        assert_eq!(buffer_manager.num_available(), 3);
         let block = BlockId::new("test".to_string(), 0);
         let buf = buffer_manager.pin(&block).unwrap();
         assert_eq!(buf.lock().unwrap().block(), Some(&block));
-        assert_eq!(buffer_manager.num_available, 2);
+        assert_eq!(buffer_manager.num_available(), 2);
+        assert_eq!(buffer_manager.pinned_count(), 1);
         buffer_manager.unpin(buf);
-        assert_eq!(buffer_manager.num_available, 3);
+        assert_eq!(buffer_manager.num_available(), 3);
+        assert_eq!(buffer_manager.pinned_count(), 0);
+    }
+
+    #[test]
+    fn should_replace_clean_buffer_before_oldest_dirty_buffer() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 3);
+        let blocks: Vec<_> = (0..3)
+            .map(|n| BlockId::new("test".to_string(), n))
+            .collect();
+        for (block, lsn) in blocks.iter().zip([2, -1, 1]) {
+            let buffer = buffer_manager.pin(block).unwrap();
+            if lsn > 0 {
+                buffer.lock().unwrap().set_modified(1, lsn);
+            }
+            buffer_manager.unpin(buffer);
+        }
+        assert_eq!(
+            buffer_manager.dirty_pages(),
+            vec![(blocks[2].clone(), 1), (blocks[0].clone(), 2)]
+        );
+
+        // block 1 is clean, so it is replaced first
+        buffer_manager
+            .pin(&BlockId::new("test".to_string(), 3))
+            .unwrap();
+        assert!(buffer_manager.find_existing_buffer(&blocks[1]).is_none());
+
+        // block 2 has the oldest rec lsn
+        buffer_manager
+            .pin(&BlockId::new("test".to_string(), 4))
+            .unwrap();
+        assert!(buffer_manager.find_existing_buffer(&blocks[2]).is_none());
+        assert_eq!(buffer_manager.min_rec_lsn(), Some(2));
+    }
+
+    #[test]
+    fn should_reuse_ring_buffers_for_bulk_access() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 5);
+        let hot: Vec<_> = (0..2).map(|n| BlockId::new("hot".to_string(), n)).collect();
+        let buffers: Vec<_> = hot
+            .iter()
+            .map(|block| buffer_manager.pin(block).unwrap())
+            .collect();
+        for buffer in buffers {
+            buffer_manager.unpin(buffer);
+        }
+
+        let mut ring = BufferRing::new(2);
+        let mut used = vec![];
+        for n in 0..10 {
+            let block = BlockId::new("bulk".to_string(), n);
+            let buffer = buffer_manager.pin_with(&block, Some(&mut ring)).unwrap();
+            if !used.iter().any(|b| Arc::ptr_eq(b, &buffer)) {
+                used.push(buffer.clone());
+            }
+            buffer_manager.unpin(buffer);
+        }
+        // 一括の読み込みは2つのバッファだけを使い、ほかのページを追い出さない
+        assert_eq!(used.len(), 2);
+        for block in &hot {
+            assert!(buffer_manager.find_existing_buffer(block).is_some());
+        }
+
+        // リングのバッファがすべてピンされている場合はプールから借りる
+        let pinned: Vec<_> = (10..12)
+            .map(|n| {
+                let block = BlockId::new("bulk".to_string(), n);
+                buffer_manager.pin_with(&block, Some(&mut ring)).unwrap()
+            })
+            .collect();
+        let block = BlockId::new("bulk".to_string(), 12);
+        let buffer = buffer_manager.pin_with(&block, Some(&mut ring)).unwrap();
+        assert!(!pinned.iter().any(|b| Arc::ptr_eq(b, &buffer)));
+    }
+
+    #[test]
+    fn should_pin_blocks_in_their_own_shard() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        let buffer_manager =
+            Arc::new(BufferManager::new(file_manager, log_manager, 8).with_shards(4));
+        assert_eq!(buffer_manager.num_shards(), 4);
+        assert_eq!(buffer_manager.num_available(), 8);
+
+        // 区画ごとに別のスレッドからピンしても、ブロックは自分の区画のバッファに入る
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let buffer_manager = buffer_manager.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let block = BlockId::new("test".to_string(), n);
+                        let buffer = buffer_manager.pin(&block).unwrap();
+                        assert_eq!(buffer.lock().unwrap().block(), Some(&block));
+                        buffer_manager.unpin(buffer);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(buffer_manager.num_available(), 8);
+        assert_eq!(buffer_manager.pinned_count(), 0);
+
+        let block = BlockId::new("test".to_string(), 0);
+        let buffer = buffer_manager.pin(&block).unwrap();
+        let shard = buffer_manager.shard(&block).0.lock().unwrap();
+        assert!(shard.contains(&buffer));
+        assert_eq!(shard.num_available, 1);
+        drop(shard);
+        buffer_manager.unpin(buffer);
+
+        // 区画の数はバッファの数までにする
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let log_manager = Arc::new(Mutex::new(
+            LogManager::new(file_manager.clone(), "log".to_string()).unwrap(),
+        ));
+        let buffer_manager = BufferManager::new(file_manager, log_manager, 2).with_shards(8);
+        assert_eq!(buffer_manager.num_shards(), 2);
     }
 }
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex};
+
+use super::buffer::Buffer;
+
+/// BULK_RING_SIZE は一括処理のトランザクションが使うリングのバッファ数
+pub const BULK_RING_SIZE: usize = 4;
+
+/// BufferRing は一括の読み込みや書き込みが使い回すバッファの集まり
+///
+/// 一括処理は同じブロックをほとんど読み直さないので、リングが埋まった後はリングの中の
+/// ピンされていないバッファを順に使い回す。バッファプールのほかのページは追い出さない
+/// リングのバッファがすべてピンされている場合だけ、プールから別のバッファを取ってきて入れ替える
+#[derive(Debug)]
+pub struct BufferRing {
+    size: usize,
+    buffers: Vec<Arc<Mutex<Buffer>>>,
+    next: usize,
+}
+
+impl BufferRing {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            buffers: Vec::with_capacity(size),
+            next: 0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buffers.len() >= self.size
+    }
+
+    pub(crate) fn contains(&self, buffer: &Arc<Mutex<Buffer>>) -> bool {
+        self.buffers.iter().any(|b| Arc::ptr_eq(b, buffer))
+    }
+
+    /// next_unpinned は前回使ったバッファの次から順に、usable が true を返すピンされていないバッファを探す
+    pub(crate) fn next_unpinned(
+        &mut self,
+        usable: impl Fn(&Arc<Mutex<Buffer>>) -> bool,
+    ) -> Option<Arc<Mutex<Buffer>>> {
+        for i in 0..self.buffers.len() {
+            let pos = (self.next + i) % self.buffers.len();
+            if usable(&self.buffers[pos]) && !self.buffers[pos].lock().unwrap().is_pinned() {
+                self.next = (pos + 1) % self.buffers.len();
+                return Some(self.buffers[pos].clone());
+            }
+        }
+        None
+    }
+
+    /// add はプールから取ってきたバッファをリングに加える
+    /// リングが埋まっている場合は次に使う位置のバッファと入れ替える
+    pub(crate) fn add(&mut self, buffer: Arc<Mutex<Buffer>>) {
+        if self.contains(&buffer) {
+            return;
+        }
+        if self.is_full() {
+            self.buffers[self.next] = buffer;
+            self.next = (self.next + 1) % self.buffers.len();
+        } else {
+            self.buffers.push(buffer);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::file::block::BlockId;
+use std::collections::HashMap;
+
+/// DirtyPageTable はバッファプールの中で変更されたが、まだディスクに書き込まれていないブロックを管理する
+/// ブロックごとに recLSN（そのブロックを最初に変更したログレコードの LSN）を持つ
+/// recLSN が小さいブロックほど、リカバリで古いログまで遡って redo する必要がある
+/// ログを書かずに変更されたブロックの recLSN は -1 になる
+#[derive(Debug, Default)]
+pub struct DirtyPageTable {
+    pages: HashMap<BlockId, i32>,
+}
+
+impl DirtyPageTable {
+    /// mark_dirty はブロックが変更されたことを記録する
+    /// すでに記録されているブロックの recLSN は、ログを書いた最初の変更の LSN のまま変わらない
+    pub fn mark_dirty(&mut self, block: &BlockId, lsn: i32) {
+        let rec_lsn = self.pages.entry(block.clone()).or_insert(lsn);
+        if *rec_lsn < 0 {
+            *rec_lsn = lsn;
+        }
+    }
+
+    /// mark_clean はブロックがディスクに書き込まれたことを記録する
+    pub fn mark_clean(&mut self, block: &BlockId) {
+        self.pages.remove(block);
+    }
+
+    pub fn rec_lsn(&self, block: &BlockId) -> Option<i32> {
+        self.pages.get(block).copied()
+    }
+
+    /// min_rec_lsn はログを書いた変更のうち、最も古い recLSN を返す
+    pub fn min_rec_lsn(&self) -> Option<i32> {
+        self.pages.values().copied().filter(|lsn| *lsn >= 0).min()
+    }
+
+    /// pages は変更されたブロックを recLSN の昇順に返す
+    pub fn pages(&self) -> Vec<(BlockId, i32)> {
+        let mut pages: Vec<_> = self
+            .pages
+            .iter()
+            .map(|(block, lsn)| (block.clone(), *lsn))
+            .collect();
+        pages.sort_by_key(|(_, lsn)| *lsn);
+        pages
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_first_rec_lsn() {
+        let mut table = DirtyPageTable::default();
+        let block1 = BlockId::new("test".into(), 1);
+        let block2 = BlockId::new("test".into(), 2);
+
+        table.mark_dirty(&block1, -1);
+        table.mark_dirty(&block1, 5);
+        table.mark_dirty(&block1, 7);
+        table.mark_dirty(&block2, 3);
+        assert_eq!(table.rec_lsn(&block1), Some(5));
+        assert_eq!(table.min_rec_lsn(), Some(3));
+        assert_eq!(
+            table.pages(),
+            vec![(block2.clone(), 3), (block1.clone(), 5)]
+        );
+
+        table.mark_clean(&block2);
+        assert_eq!(table.rec_lsn(&block2), None);
+        assert_eq!(table.len(), 1);
+    }
+}
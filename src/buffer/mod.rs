@@ -1,2 +1,5 @@
+#[allow(clippy::module_inception)]
 pub mod buffer;
 pub mod buffer_manager;
+pub mod buffer_ring;
+pub mod dirty_page_table;
@@ -0,0 +1,102 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Clock はタイムアウトの判定に使う時刻の取得と待機を抽象化する
+///
+/// 通常は SystemClock を使う。テストでは MockClock を使うと、実際に待たずに時間を進めて
+/// タイムアウトの処理をすぐに確認できる
+pub trait Clock: Debug + Send + Sync {
+    /// now は現在の時刻を返す
+    fn now(&self) -> Instant;
+    /// sleep は duration だけ待つ
+    fn sleep(&self, duration: Duration);
+    /// wait_time は条件変数で最大 timeout だけ待つ前に呼び、実際に条件変数で待つ時間を返す
+    fn wait_time(&self, timeout: Duration) -> Duration;
+}
+
+/// default_clock は実際の時刻を使う時計を返す
+pub fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// SystemClock は実際の時刻を使う時計
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    fn wait_time(&self, timeout: Duration) -> Duration {
+        timeout
+    }
+}
+
+/// MockClock はテスト用の時計
+/// 待つ代わりに時刻を進めるので、sleep や wait_time はすぐに戻る
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+}
+
+impl MockClock {
+    /// advance は時刻を duration だけ進める
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// elapsed は作ってから進めた時間の合計を返す
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    fn wait_time(&self, timeout: Duration) -> Duration {
+        self.advance(timeout);
+        Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_advance_mock_clock_without_waiting() {
+        let clock = MockClock::default();
+        let start = clock.now();
+        let real_start = Instant::now();
+        clock.sleep(Duration::from_secs(60));
+        assert_eq!(clock.wait_time(Duration::from_secs(3)), Duration::ZERO);
+        assert_eq!(clock.now() - start, Duration::from_secs(63));
+        assert!(real_start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,624 @@
+use crate::{
+    file::date::Date,
+    parse::parser::Parser,
+    plan::{planner::Planner, ArcPlan},
+    query::{
+        constant::Constant, query_data::QueryData, result_set_metadata::ResultSetMetadata,
+        scan::ArcScan,
+    },
+    server::{
+        db::{finish, DbConfig, TinyDB},
+        session::{read_row, run_query, ExecuteResult},
+    },
+    tx, unlock,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// DEFAULT_BLOCK_SIZE は Database::open で開くデータベースのブロックサイズ
+pub const DEFAULT_BLOCK_SIZE: i32 = 400;
+/// DEFAULT_BUFFER_SIZE は Database::open で開くデータベースのバッファ数
+pub const DEFAULT_BUFFER_SIZE: u64 = 8;
+
+/// Database は tinydb をライブラリとして使うための窓口
+///
+/// SQL の文を実行するための最小限の API だけを公開している
+/// バッファやファイル、ロックなどの内部のモジュールは internals フィーチャーを有効にすると使える
+///
+/// ```no_run
+/// use tinydb::Database;
+///
+/// let db = Database::open("data")?;
+/// db.execute("create table T(A int, B varchar(10))")?;
+/// db.execute("insert into T(A, B) values (1, 'one')")?;
+/// let mut tx = db.begin()?;
+/// for row in tx.query("select A, B from T")? {
+///     println!("{} {}", row.get_int("A")?, row.get_string("B")?);
+/// }
+/// tx.commit()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Database {
+    db: TinyDB,
+}
+
+impl Database {
+    /// open はディレクトリにあるデータベースを既定の設定で開く。ディレクトリがなければ作る
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_with_config(dir, DbConfig::new(DEFAULT_BLOCK_SIZE, DEFAULT_BUFFER_SIZE))
+    }
+
+    /// open_with_config はディレクトリにあるデータベースを config の設定で開く
+    pub fn open_with_config(dir: impl Into<PathBuf>, config: DbConfig) -> Result<Self> {
+        let mut db = TinyDB::with_config(dir, config)?;
+        db.init_planner()?;
+        Ok(Self { db })
+    }
+
+    /// begin は新しいトランザクションを開始する
+    pub fn begin(&self) -> Result<Transaction<'_>> {
+        Ok(Transaction {
+            db: &self.db,
+            tx: Some(self.db.transaction()?),
+        })
+    }
+
+    /// execute は更新の文を1つのトランザクションで実行して、更新したレコード数を返す
+    pub fn execute(&self, statement: impl IntoStatement) -> Result<i32> {
+        let statement = statement.into_statement()?;
+        let mut tx = self.begin()?;
+        let result = tx.execute(statement);
+        tx.finish(result)
+    }
+
+    /// query は問い合わせの文を1つのトランザクションで実行して、結果のレコードを返す
+    pub fn query(&self, statement: impl IntoStatement) -> Result<Vec<Row>> {
+        let statement = statement.into_statement()?;
+        let mut tx = self.begin()?;
+        let result = tx.query(statement);
+        tx.finish(result)
+    }
+
+    /// inner は内部で使っている TinyDB を返す
+    #[cfg(feature = "internals")]
+    pub fn inner(&self) -> &TinyDB {
+        &self.db
+    }
+}
+
+/// Transaction は Database::begin で開始したトランザクション
+///
+/// commit か rollback を呼ばずに破棄すると、ロールバックする
+pub struct Transaction<'a> {
+    db: &'a TinyDB,
+    tx: Option<Arc<Mutex<tx::transaction::Transaction>>>,
+}
+
+impl<'a> Transaction<'a> {
+    /// execute は更新の文を実行して、更新したレコード数を返す
+    pub fn execute(&mut self, statement: impl IntoStatement) -> Result<i32> {
+        let statement = statement.into_statement()?;
+        if statement.is_query() {
+            bail!("statement is a query: {}", statement.sql);
+        }
+        let tx = self.handle()?;
+        self.planner()?.execute_update(&statement.sql, tx)
+    }
+
+    /// query は問い合わせの文を実行して、結果のレコードを返す
+    pub fn query(&mut self, statement: impl IntoStatement) -> Result<Vec<Row>> {
+        let statement = statement.into_statement()?;
+        let Some(query) = statement.query else {
+            bail!("statement is not a query: {}", statement.sql);
+        };
+        let tx = self.handle()?;
+        let ExecuteResult::Rows { metadata, rows } = run_query(&mut *self.planner()?, tx, query)?
+        else {
+            unreachable!("query returns rows");
+        };
+        let metadata = Arc::new(metadata);
+        Ok(rows
+            .into_iter()
+            .map(|values| Row {
+                metadata: metadata.clone(),
+                values,
+            })
+            .collect())
+    }
+
+    /// prepare は問い合わせの文のプランを作って、何度も実行できる PreparedQuery を返す
+    pub fn prepare(&mut self, statement: impl IntoStatement) -> Result<PreparedQuery<'_, 'a>> {
+        let statement = statement.into_statement()?;
+        let Some(query) = statement.query else {
+            bail!("statement is not a query: {}", statement.sql);
+        };
+        let tx = self.handle()?;
+        let plan = self.planner()?.create_plan(query, tx)?;
+        let metadata = Arc::new(unlock!(plan).metadata());
+        Ok(PreparedQuery {
+            _tx: self,
+            plan,
+            metadata,
+            scan: None,
+        })
+    }
+
+    /// describe は問い合わせの文を実行せずに、結果の列の情報を返す
+    pub fn describe(&mut self, statement: impl IntoStatement) -> Result<ResultSetMetadata> {
+        let statement = statement.into_statement()?;
+        let Some(query) = statement.query else {
+            bail!("statement is not a query: {}", statement.sql);
+        };
+        let tx = self.handle()?;
+        let plan = self.planner()?.create_plan(query, tx)?;
+        let metadata = unlock!(plan).metadata();
+        Ok(metadata)
+    }
+
+    /// commit はトランザクションをコミットする
+    pub fn commit(mut self) -> Result<()> {
+        unlock!(self.take()?).commit()
+    }
+
+    /// rollback はトランザクションをロールバックする
+    pub fn rollback(mut self) -> Result<()> {
+        unlock!(self.take()?).rollback()
+    }
+
+    /// finish は結果が成功ならコミットし、失敗ならロールバックする
+    fn finish<T>(mut self, result: Result<T>) -> Result<T> {
+        finish(self.take()?, result)
+    }
+
+    fn handle(&self) -> Result<Arc<Mutex<tx::transaction::Transaction>>> {
+        self.tx
+            .clone()
+            .ok_or_else(|| anyhow!("transaction is already finished"))
+    }
+
+    fn take(&mut self) -> Result<Arc<Mutex<tx::transaction::Transaction>>> {
+        self.tx
+            .take()
+            .ok_or_else(|| anyhow!("transaction is already finished"))
+    }
+
+    fn planner(&self) -> Result<MutexGuard<'_, Planner>> {
+        let planner = self
+            .db
+            .planner
+            .as_ref()
+            .ok_or_else(|| anyhow!("planner is not initialized"))?;
+        Ok(unlock!(planner))
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if let Some(tx) = self.tx.take() {
+            let _ = unlock!(tx).rollback();
+        }
+    }
+}
+
+/// PreparedQuery は Transaction::prepare でプランを作っておいた問い合わせ
+///
+/// 2回目からはプランを作り直さず、前に開いたスキャンを先頭に戻して読み直すので、
+/// テーブルのスキャンを作り直したりカタログを読み直したりしない
+/// スキャンは破棄するまで開いたままにするので、その間はトランザクションでほかの文を実行できない
+pub struct PreparedQuery<'t, 'a> {
+    _tx: &'t mut Transaction<'a>,
+    plan: ArcPlan,
+    metadata: Arc<ResultSetMetadata>,
+    scan: Option<ArcScan>,
+}
+
+impl PreparedQuery<'_, '_> {
+    /// query は問い合わせを実行して、結果のレコードを返す
+    pub fn query(&mut self) -> Result<Vec<Row>> {
+        let scan = match self.scan.take() {
+            Some(scan) => unlock!(self.plan).reopen(scan)?,
+            None => unlock!(self.plan).open()?,
+        };
+        self.scan = Some(scan.clone());
+        let mut scan = unlock!(scan);
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push(Row {
+                metadata: self.metadata.clone(),
+                values: read_row(&mut *scan, &self.metadata)?,
+            });
+        }
+        Ok(rows)
+    }
+
+    pub fn metadata(&self) -> &ResultSetMetadata {
+        &self.metadata
+    }
+}
+
+impl Drop for PreparedQuery<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(scan) = self.scan.take() {
+            unlock!(scan).close();
+        }
+    }
+}
+
+/// Statement は構文を確認した SQL の文
+/// 同じ文を何度も実行する場合は、一度作って使い回すと構文の確認を繰り返さない
+#[derive(Debug, Clone)]
+pub struct Statement {
+    sql: String,
+    query: Option<QueryData>,
+}
+
+impl Statement {
+    /// new は SQL の文の構文を確認して Statement を作る
+    pub fn new(sql: impl Into<String>) -> Result<Self> {
+        let sql = sql.into();
+        let mut parser = Parser::new(&sql);
+        let query = if parser.is_query() {
+            Some(parser.query()?)
+        } else {
+            parser.update_cmd()?;
+            None
+        };
+        Ok(Self { sql, query })
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// is_query は問い合わせの文かどうかを返す
+    pub fn is_query(&self) -> bool {
+        self.query.is_some()
+    }
+}
+
+/// IntoStatement は SQL の文字列と Statement のどちらでも文を実行できるようにする
+pub trait IntoStatement {
+    fn into_statement(self) -> Result<Statement>;
+}
+
+impl IntoStatement for Statement {
+    fn into_statement(self) -> Result<Statement> {
+        Ok(self)
+    }
+}
+
+impl IntoStatement for &Statement {
+    fn into_statement(self) -> Result<Statement> {
+        Ok(self.clone())
+    }
+}
+
+impl IntoStatement for &str {
+    fn into_statement(self) -> Result<Statement> {
+        Statement::new(self)
+    }
+}
+
+impl IntoStatement for String {
+    fn into_statement(self) -> Result<Statement> {
+        Statement::new(self)
+    }
+}
+
+/// Row は問い合わせの結果の1レコード
+/// 値は問い合わせの select に書いた順に並び、同じ結果のレコードは列の情報を共有する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    metadata: Arc<ResultSetMetadata>,
+    values: Vec<Constant>,
+}
+
+impl Row {
+    pub fn metadata(&self) -> &ResultSetMetadata {
+        &self.metadata
+    }
+
+    pub fn values(&self) -> &[Constant] {
+        &self.values
+    }
+
+    /// get はフィールドの値を返す。フィールドがなければ None を返す
+    pub fn get(&self, field_name: &str) -> Option<&Constant> {
+        self.values.get(self.metadata.index_of(field_name)?)
+    }
+
+    pub fn get_int(&self, field_name: &str) -> Result<i32> {
+        match self.get(field_name) {
+            Some(Constant::Int(value)) => Ok(*value),
+            Some(value) => bail!("field is not an int: {} = {}", field_name, value),
+            None => bail!("field not found: {}", field_name),
+        }
+    }
+
+    pub fn get_string(&self, field_name: &str) -> Result<String> {
+        match self.get(field_name) {
+            Some(Constant::String(value)) => Ok(value.clone()),
+            Some(value) => bail!("field is not a string: {} = {}", field_name, value),
+            None => bail!("field not found: {}", field_name),
+        }
+    }
+
+    pub fn get_bool(&self, field_name: &str) -> Result<bool> {
+        match self.get(field_name) {
+            Some(Constant::Bool(value)) => Ok(*value),
+            Some(value) => bail!("field is not a boolean: {} = {}", field_name, value),
+            None => bail!("field not found: {}", field_name),
+        }
+    }
+
+    pub fn get_date(&self, field_name: &str) -> Result<Date> {
+        match self.get(field_name) {
+            Some(Constant::Date(value)) => Ok(*value),
+            Some(value) => bail!("field is not a date: {} = {}", field_name, value),
+            None => bail!("field not found: {}", field_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::schema::FieldTypes;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_execute_statements_through_database() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_execute_statements_through_database");
+        let db = Database::open(test_directory)?;
+        db.execute("create table T(A int, B varchar(10))")?;
+        let insert = Statement::new("insert into T(A, B) values (1, 'one')")?;
+        assert!(!insert.is_query());
+        assert_eq!(db.execute(&insert)?, 1);
+
+        let mut tx = db.begin()?;
+        tx.execute("insert into T(A, B) values (2, 'two')")?;
+        // 破棄したトランザクションはロールバックされる
+        drop(tx);
+        let mut tx = db.begin()?;
+        tx.execute("insert into T(A, B) values (3, 'three')")?;
+        tx.commit()?;
+
+        let rows = db.query("select B, A from T")?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].metadata().field_names(), ["B", "A"]);
+        assert_eq!(rows[0].get_int("A")?, 1);
+        assert_eq!(rows[1].get_string("B")?, "three");
+        assert_eq!(rows[1].get("C"), None);
+        assert!(rows[1].get_int("B").is_err());
+
+        // 実行しなくても結果の列の情報が分かる
+        let metadata = db.begin()?.describe("select B, A from T where A = 0")?;
+        let columns = metadata.columns();
+        assert_eq!(columns[0].field_type, FieldTypes::Varchar);
+        assert_eq!(columns[0].length, 10);
+        assert_eq!(columns[1].display_size(), 11);
+
+        // 文の種類が合わない場合や構文が誤っている場合はエラーになる
+        assert!(db.execute("select A from T").is_err());
+        assert!(db.query(insert).is_err());
+        assert!(Statement::new("selec A from T").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_filter_boolean_field() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_filter_boolean_field");
+        {
+            let db = Database::open(&test_directory)?;
+            db.execute("create table U(A int, ACTIVE boolean)")?;
+            db.execute("insert into U(A, ACTIVE) values (1, true)")?;
+            db.execute("insert into U(A, ACTIVE) values (2, false)")?;
+            db.execute("insert into U(A, ACTIVE) values (3, true)")?;
+            assert!(db
+                .execute("insert into U(A, ACTIVE) values (4, 1)")
+                .is_err());
+        }
+
+        // 開き直してもカタログから boolean のフィールドとして読み込む
+        let db = Database::open(&test_directory)?;
+        let rows = db.query("select A, ACTIVE from U where ACTIVE = true")?;
+        let values: Vec<i32> = rows
+            .iter()
+            .map(|row| row.get_int("A"))
+            .collect::<Result<_>>()?;
+        assert_eq!(values, [1, 3]);
+        assert!(rows[0].get_bool("ACTIVE")?);
+        assert!(rows[0].get_bool("A").is_err());
+
+        assert_eq!(db.execute("update U set ACTIVE = false where A = 3")?, 1);
+        let rows = db.query("select A from U where ACTIVE = false")?;
+        assert_eq!(rows.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn should_filter_by_date() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_filter_by_date");
+        let db = Database::open(test_directory)?;
+        db.execute("create table E(A int, D date)")?;
+        db.execute("insert into E(A, D) values (1, date '2000-01-01')")?;
+        db.execute("insert into E(A, D) values (2, date '2000-03-01')")?;
+        db.execute("insert into E(A, D) values (3, date '2999-12-31')")?;
+        let values = |sql: &str| -> Result<Vec<i32>> {
+            db.query(sql)?.iter().map(|row| row.get_int("A")).collect()
+        };
+
+        assert_eq!(values("select A from E where D < current_date")?, [1, 2]);
+        assert_eq!(values("select A from E where D >= current_date - 7")?, [3]);
+        assert_eq!(
+            values("select A from E where D - 1 = date '2000-02-29'")?,
+            [2]
+        );
+        assert_eq!(
+            values("select A from E where D - date '2000-01-01' <= 60 and A <> 1")?,
+            [2]
+        );
+        assert!(db.query("select A from E where D < 1").is_err());
+
+        // ビューの定義に保存してもう一度パースできる
+        db.execute("create view OLD as select A from E where D < current_date - 30")?;
+        assert_eq!(values("select A from OLD")?, [1, 2]);
+
+        db.execute("update E set D = current_date where A = 3")?;
+        let rows = db.query("select D from E where A = 3")?;
+        let today = rows[0].get_date("D")?;
+        assert_eq!(db.query("select A from E where D = current_date")?.len(), 1);
+        assert!(today > "2000-03-01".parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn should_sample_table_blocks() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_sample_table_blocks");
+        let db = Database::open(test_directory)?;
+        db.execute("create table S(A int, B varchar(100))")?;
+        for i in 0..200 {
+            db.execute(format!("insert into S(A, B) values ({}, 'record {}')", i, i).as_str())?;
+        }
+        db.execute("create index S_A on S(A)")?;
+        let values = |sql: &str| -> Result<Vec<i32>> {
+            db.query(sql)?.iter().map(|row| row.get_int("A")).collect()
+        };
+        let all = values("select A from S")?;
+
+        assert_eq!(values("select A from S sample 100 percent")?, all);
+        assert!(values("select A from S sample 0 percent")?.is_empty());
+        let sampled = values("select A from S sample 30 percent")?;
+        assert!(!sampled.is_empty() && sampled.len() < all.len());
+        assert!(sampled.iter().all(|a| all.contains(a)));
+        // 同じブロックを選ぶので、何度読んでも同じ結果になる
+        assert_eq!(values("select A from S sample 30 percent")?, sampled);
+
+        // 索引があっても標本のブロックから読む
+        let a = sampled[0];
+        let b = all.iter().find(|a| !sampled.contains(a)).unwrap();
+        let query = |a: i32| format!("select A from S sample 30 percent where A = {}", a);
+        assert_eq!(values(&query(a))?, [a]);
+        assert!(values(&query(*b))?.is_empty());
+        let joined =
+            values("select A from S sample 30 percent, generate_series(1, 2) N where A = N + 100")?;
+        assert!(joined.iter().all(|a| sampled.contains(a)));
+        Ok(())
+    }
+
+    #[test]
+    fn should_select_from_generate_series() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_select_from_generate_series");
+        let db = Database::open(test_directory)?;
+        let values = |sql: &str, field_name: &str| -> Result<Vec<i32>> {
+            db.query(sql)?
+                .iter()
+                .map(|row| row.get_int(field_name))
+                .collect()
+        };
+
+        assert_eq!(
+            values(
+                "select generate_series from generate_series(-2, 2) where generate_series <> 0",
+                "generate_series"
+            )?,
+            [-2, -1, 1, 2]
+        );
+        assert!(values("select N from generate_series(3, 1) N", "N")?.is_empty());
+        assert_eq!(
+            db.query("select I, J from generate_series(1, 300) I, generate_series(1, 300) J")?
+                .len(),
+            90000
+        );
+
+        // テーブルと結合したり、ビューに保存したりできる
+        db.execute("create table G(A int, B varchar(10))")?;
+        db.execute("insert into G(A, B) values (2, 'two')")?;
+        db.execute("insert into G(A, B) values (5, 'five')")?;
+        assert_eq!(
+            values("select A from G, generate_series(1, 3) N where A = N", "A")?,
+            [2]
+        );
+        db.execute("create view EVEN as select N from generate_series(1, 6) N where N - 1 <> 0")?;
+        assert_eq!(values("select N from EVEN where N > 4", "N")?, [5, 6]);
+
+        assert!(db.query("select N from unknown_function(1, 2) N").is_err());
+        assert!(db.query("select N from generate_series(1, 'x') N").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_compare_and_update_with_cast() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_compare_and_update_with_cast");
+        let db = Database::open(test_directory)?;
+        db.execute("create table C(A int, B varchar(10))")?;
+        for (a, b) in [(1, "9"), (2, "10"), (3, " 100 "), (4, "x")] {
+            db.execute(format!("insert into C(A, B) values ({}, '{}')", a, b).as_str())?;
+        }
+        let values = |sql: &str| -> Result<Vec<i32>> {
+            db.query(sql)?.iter().map(|row| row.get_int("A")).collect()
+        };
+
+        // 変換できない値があるとエラーになる
+        assert!(db
+            .query("select A from C where cast(B as int) > 9")
+            .is_err());
+        db.execute("delete from C where A = 4")?;
+
+        // 文字列では '10' < '9' だが、整数に変換すると数の大きさで比べる
+        assert_eq!(values("select A from C where B < '9'")?, [2, 3]);
+        assert_eq!(values("select A from C where cast(B as int) > 9")?, [2, 3]);
+
+        db.execute("update C set A = cast(B as int) + A where A = 2")?;
+        assert_eq!(values("select A from C where B = '10'")?, [12]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_reexecute_prepared_query() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_reexecute_prepared_query");
+        let db = Database::open(test_directory)?;
+        db.execute("create table T(A int)")?;
+        db.execute("create table U(B int)")?;
+        for sql in [
+            "insert into T(A) values (1)",
+            "insert into T(A) values (2)",
+            "insert into U(B) values (2)",
+        ] {
+            db.execute(sql)?;
+        }
+
+        let mut tx = db.begin()?;
+        let mut prepared = tx.prepare("select A from T, U where A = B")?;
+        assert_eq!(prepared.metadata().field_names(), ["A"]);
+        let scan = {
+            let rows = prepared.query()?;
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].get_int("A")?, 2);
+            prepared.scan.clone().unwrap()
+        };
+        // 2回目は同じスキャンを先頭に戻して読む
+        let rows = prepared.query()?;
+        assert_eq!(rows.len(), 1);
+        assert!(Arc::ptr_eq(&scan, prepared.scan.as_ref().unwrap()));
+        drop(scan);
+        drop(prepared);
+
+        // 破棄するとスキャンを閉じるので、コミットできる
+        tx.execute("insert into U(B) values (1)")?;
+        assert_eq!(
+            tx.prepare("select A from T, U where A = B")?.query()?.len(),
+            2
+        );
+        assert!(tx.prepare("insert into T(A) values (3)").is_err());
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{bail, Result};
+use std::{
+    fs::{read_to_string, rename, File},
+    io::Write as _,
+    path::Path,
+};
+
+/// CONTROL_FILE はデータベースの形式の情報を記録するファイルの名前
+pub const CONTROL_FILE: &str = "tinydb.control";
+
+/// ControlFile はデータベースのディレクトリの形式の情報
+///
+/// `<key>=<value>` の行を並べたテキストで保存する。知らないキーは読み飛ばす
+/// コントロールファイルを作る前のデータベースにはファイルがないので、そのときは版を 1 として扱う
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlFile {
+    /// catalog_version はカタログの形式の版
+    pub catalog_version: i32,
+    /// segment_blocks はテーブルのファイルを分けるセグメント1つのブロック数。None の場合は分けない
+    /// ブロックを格納するセグメントはこの数で決まるので、一度セグメントに分けたデータベースでは変えられない
+    pub segment_blocks: Option<u64>,
+}
+
+impl Default for ControlFile {
+    fn default() -> Self {
+        Self {
+            catalog_version: 1,
+            segment_blocks: None,
+        }
+    }
+}
+
+impl ControlFile {
+    pub fn new(catalog_version: i32) -> Self {
+        Self {
+            catalog_version,
+            segment_blocks: None,
+        }
+    }
+
+    /// with_segment_blocks はセグメント1つのブロック数を設定する
+    pub fn with_segment_blocks(mut self, segment_blocks: Option<u64>) -> Self {
+        self.segment_blocks = segment_blocks.filter(|&blocks| blocks > 0);
+        self
+    }
+
+    /// read はディレクトリのコントロールファイルを読む。ファイルがなければ None を返す
+    pub fn read(db_dir: &Path) -> Result<Option<Self>> {
+        let path = db_dir.join(CONTROL_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut control = Self::default();
+        for line in read_to_string(&path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                bail!("invalid control file line: {}", line);
+            };
+            match key.trim() {
+                "catalog_version" => {
+                    let Ok(version) = value.trim().parse() else {
+                        bail!("invalid catalog version: {}", value.trim());
+                    };
+                    control.catalog_version = version;
+                }
+                "segment_blocks" => {
+                    let Ok(blocks) = value.trim().parse() else {
+                        bail!("invalid segment blocks: {}", value.trim());
+                    };
+                    control.segment_blocks = Some(blocks).filter(|&blocks| blocks > 0);
+                }
+                _ => {}
+            }
+        }
+        Ok(Some(control))
+    }
+
+    /// write はコントロールファイルを書き込む
+    /// 途中で止まっても古い内容か新しい内容のどちらかが残るように、別のファイルに書いてから置き換える
+    pub fn write(&self, db_dir: &Path) -> Result<()> {
+        let path = db_dir.join(CONTROL_FILE);
+        let new_path = db_dir.join(format!("{}.new", CONTROL_FILE));
+        let mut file = File::create(&new_path)?;
+        writeln!(file, "catalog_version={}", self.catalog_version)?;
+        if let Some(blocks) = self.segment_blocks {
+            writeln!(file, "segment_blocks={}", blocks)?;
+        }
+        file.sync_all()?;
+        rename(&new_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_round_trip_control_file() -> Result<()> {
+        let dir = tempdir()?;
+        assert_eq!(ControlFile::read(dir.path())?, None);
+
+        ControlFile::new(3).write(dir.path())?;
+        assert_eq!(ControlFile::read(dir.path())?, Some(ControlFile::new(3)));
+
+        std::fs::write(
+            dir.path().join(CONTROL_FILE),
+            "unknown=1\ncatalog_version=2\n",
+        )?;
+        assert_eq!(ControlFile::read(dir.path())?, Some(ControlFile::new(2)));
+
+        let control = ControlFile::new(3).with_segment_blocks(Some(64));
+        control.write(dir.path())?;
+        assert_eq!(ControlFile::read(dir.path())?, Some(control));
+
+        std::fs::write(dir.path().join(CONTROL_FILE), "catalog_version=x\n")?;
+        assert!(ControlFile::read(dir.path()).is_err());
+        std::fs::write(dir.path().join(CONTROL_FILE), "segment_blocks=x\n")?;
+        assert!(ControlFile::read(dir.path()).is_err());
+        Ok(())
+    }
+}
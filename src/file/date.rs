@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use std::{fmt::Display, str::FromStr};
+
+/// Date は時刻を持たない日付で、1970-01-01 からの日数として格納する
+/// 日数で比較できるので、古い日付ほど小さくなる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Date(i32);
+
+impl Date {
+    pub fn from_days(days: i32) -> Self {
+        Self(days)
+    }
+
+    /// days は 1970-01-01 からの日数を返す
+    pub fn days(&self) -> i32 {
+        self.0
+    }
+
+    /// from_ymd は年月日から日付を作る。存在しない日付の場合はエラーを返す
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Result<Self> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            bail!("invalid date: {:04}-{:02}-{:02}", year, month, day);
+        }
+        // 3月始まりの年で数えると、うるう日が年の最後に来るので計算が簡単になる
+        let y = if month <= 2 { year - 1 } else { year } as i64;
+        let era = y.div_euclid(400);
+        let yoe = y.rem_euclid(400);
+        let mp = (month as i64 + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        Ok(Self((era * 146097 + doe - 719468) as i32))
+    }
+
+    /// ymd は日付の年月日を返す
+    pub fn ymd(&self) -> (i32, u32, u32) {
+        let z = self.0 as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400) as i32 + (month <= 2) as i32;
+        (year, month, day)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// YYYY-MM-DD の形式の文字列を日付として読み込む
+impl FromStr for Date {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('-').collect();
+        let [year, month, day] = parts.as_slice() else {
+            bail!("invalid date: {}", s);
+        };
+        let (Ok(year), Ok(month), Ok(day)) = (year.parse(), month.parse(), day.parse()) else {
+            bail!("invalid date: {}", s);
+        };
+        Self::from_ymd(year, month, day)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_between_days_and_ymd() -> Result<()> {
+        assert_eq!(Date::from_ymd(1970, 1, 1)?.days(), 0);
+        assert_eq!(Date::from_ymd(1969, 12, 31)?.days(), -1);
+        assert_eq!(Date::from_ymd(2000, 3, 1)?.days(), 11017);
+        for days in [-800_000, -1, 0, 59, 11016, 19782, 2_000_000] {
+            let (year, month, day) = Date::from_days(days).ymd();
+            assert_eq!(Date::from_ymd(year, month, day)?.days(), days);
+        }
+        assert!(Date::from_ymd(2023, 2, 29).is_err());
+        assert!(Date::from_ymd(2024, 13, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_and_format_date() -> Result<()> {
+        let date: Date = "2024-02-29".parse()?;
+        assert_eq!(date.to_string(), "2024-02-29");
+        assert!(date > "2024-02-28".parse()?);
+        assert!("2024-02".parse::<Date>().is_err());
+        assert!("2024-xx-01".parse::<Date>().is_err());
+        Ok(())
+    }
+}
@@ -1,32 +1,69 @@
-use super::{block::BlockId, page::Page};
-use anyhow::Result;
+use super::{block::BlockId, lock::DirLock, page::Page, temp_file_manager::TempFileManager};
+use anyhow::{bail, Result};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{create_dir_all, read_dir, File, OpenOptions},
     io::{Read as _, Seek as _, Write as _},
     path::PathBuf,
 };
 
+/// DEFAULT_MAX_OPEN_FILES は同時に開いておくファイルの数の既定の上限
+pub const DEFAULT_MAX_OPEN_FILES: usize = 128;
+
+/// OpenFile は開いているファイルと、最後に使った順番
+#[derive(Debug)]
+pub struct OpenFile {
+    file: File,
+    last_used: u64,
+}
+
 #[derive(Debug, Default)]
 pub struct FileManager {
     pub db_dir: PathBuf,
     pub block_size: i32,
     pub is_new: bool,
-    pub open_files: HashMap<String, File>,
+    /// 開いているファイル。max_open_files を超えると最も長く使っていないファイルを閉じ、次に使うときに開き直す
+    pub open_files: HashMap<String, OpenFile>,
+    max_open_files: usize,
+    /// 書き込んでからまだディスクに同期していないファイル
+    unsynced: HashSet<String>,
+    use_count: u64,
+    pub temp_files: TempFileManager,
+    /// テーブルのファイルを分けるセグメント1つのブロック数。None の場合は分けない
+    segment_blocks: Option<u64>,
+    /// テーブルのファイルごとに、セグメントに分けて保存しているか
+    segmented: HashMap<String, bool>,
+    /// db_dir ではなく別のディレクトリに置くファイルと、そのディレクトリ
+    file_dirs: HashMap<String, PathBuf>,
+    _lock: Option<DirLock>,
 }
 
 impl FileManager {
+    /// new はデータベースのディレクトリをロックして開く
+    /// 他のプロセスが同じディレクトリを開いている場合はエラーを返す
     pub fn new(db_dir: impl Into<PathBuf>, block_size: i32) -> Result<Self> {
+        Self::open(db_dir, block_size, false)
+    }
+
+    /// open は force が true の場合、他のプロセスが持っているロックを奪って開く
+    pub fn open(db_dir: impl Into<PathBuf>, block_size: i32, force: bool) -> Result<Self> {
         let db_dir = db_dir.into();
         let is_new = !db_dir.exists();
         if is_new {
             create_dir_all(&db_dir)?;
+        }
+        // 一時ファイルを削除する前にロックを取り、使用中のデータベースのファイルに触れないようにする
+        let lock = if force {
+            DirLock::steal(&db_dir)?
         } else {
+            DirLock::acquire(&db_dir)?
+        };
+        if !is_new {
             for entry in read_dir(&db_dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 let name = entry.file_name();
-                if path.is_file() && name.to_string_lossy().starts_with("temp") {
+                if path.is_file() && TempFileManager::is_temp_file(&name.to_string_lossy()) {
                     std::fs::remove_file(&path)?;
                 }
             }
@@ -37,61 +74,360 @@ impl FileManager {
             block_size,
             is_new,
             open_files: HashMap::new(),
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            unsynced: HashSet::new(),
+            use_count: 0,
+            temp_files: TempFileManager::default(),
+            segment_blocks: None,
+            segmented: HashMap::new(),
+            file_dirs: HashMap::new(),
+            _lock: Some(lock),
         })
     }
 
+    /// set_segment_blocks はテーブルのファイルを blocks ブロックごとのセグメント
+    /// (`t.tbl.0`, `t.tbl.1`, ...) に分けて保存するように設定する
+    /// すでに分割されていないファイルがあるテーブルはそのファイルを使い続ける
+    /// テーブルのファイルを開く前に設定すること
+    /// 設定はコントロールファイルに記録し、データベースを開くときにここで設定し直す
+    pub fn set_segment_blocks(&mut self, blocks: Option<u64>) {
+        self.segment_blocks = blocks.filter(|&blocks| blocks > 0);
+        self.segmented.clear();
+    }
+
+    pub fn segment_blocks(&self) -> Option<u64> {
+        self.segment_blocks
+    }
+
+    /// set_file_dir は filename のファイルを db_dir ではなく dir に置くように設定する
+    /// ログを別のディスクに置く場合などに使う。ディレクトリがなければ作る
+    /// ファイルを開く前に設定し、開き直すときも同じディレクトリを設定すること
+    pub fn set_file_dir(&mut self, filename: &str, dir: impl Into<PathBuf>) -> Result<()> {
+        Self::check_filename(filename)?;
+        let dir = dir.into();
+        create_dir_all(&dir)?;
+        self.close_file(filename)?;
+        self.file_dirs.insert(filename.to_string(), dir);
+        Ok(())
+    }
+
+    /// path はファイルを置く場所を返す
+    pub fn path(&self, filename: &str) -> PathBuf {
+        match self.file_dirs.get(filename) {
+            Some(dir) => dir.join(filename),
+            None => self.db_dir.join(filename),
+        }
+    }
+
+    /// set_max_open_files は同時に開いておくファイルの数の上限を設定する。超えている分はすぐに閉じる
+    pub fn set_max_open_files(&mut self, max_open_files: usize) -> Result<()> {
+        self.max_open_files = max_open_files.max(1);
+        while self.open_files.len() > self.max_open_files {
+            self.close_least_recently_used()?;
+        }
+        Ok(())
+    }
+
+    pub fn max_open_files(&self) -> usize {
+        self.max_open_files
+    }
+
+    /// close_least_recently_used は最も長く使っていないファイルを閉じる
+    fn close_least_recently_used(&mut self) -> Result<()> {
+        let oldest = self
+            .open_files
+            .iter()
+            .min_by_key(|(_, open_file)| open_file.last_used)
+            .map(|(filename, _)| filename.clone());
+        match oldest {
+            Some(filename) => self.close_file(&filename),
+            None => Ok(()),
+        }
+    }
+
+    /// close_file は開いているファイルを閉じる。同期していない書き込みがあれば、閉じる前に同期する
+    /// Windows では開いているファイルの名前を変えたり削除したりできないので、その前に閉じておく
+    fn close_file(&mut self, filename: &str) -> Result<()> {
+        if let Some(open_file) = self.open_files.remove(filename) {
+            if self.unsynced.remove(filename) {
+                open_file.file.sync_data()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// forget_file は削除するファイルを同期せずに閉じる
+    fn forget_file(&mut self, filename: &str) {
+        self.open_files.remove(filename);
+        self.unsynced.remove(filename);
+    }
+
+    /// sync は書き込んでから同期していないファイルをディスクに同期する
+    /// ログを書き込んだ後に呼び、ログとそれまでに書き込んだデータが電源が落ちても残るようにする
+    /// 一時ファイルはリカバリで使わないので同期しない
+    pub fn sync(&mut self) -> Result<()> {
+        for filename in std::mem::take(&mut self.unsynced) {
+            if TempFileManager::is_temp_file(&filename) {
+                continue;
+            }
+            if let Some(open_file) = self.open_files.get(&filename) {
+                open_file.file.sync_data()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// check_filename はファイル名がデータベースのディレクトリの中のファイルを指しているかを確かめる
+    /// OS によって区切り文字が違うので、`/` と `\\` はどちらの OS でも使えない
+    fn check_filename(filename: &str) -> Result<()> {
+        if filename.is_empty()
+            || filename == "."
+            || filename == ".."
+            || filename.contains(['/', '\\', ':'])
+        {
+            bail!("invalid file name: {:?}", filename);
+        }
+        Ok(())
+    }
+
+    /// segment は論理ブロックを格納するファイルの名前と、そのファイル内のブロック番号を返す
+    fn segment(&mut self, filename: &str, num: u64) -> (String, u64) {
+        match self.segment_blocks_of(filename) {
+            Some(blocks) => (format!("{}.{}", filename, num / blocks), num % blocks),
+            None => (filename.to_string(), num),
+        }
+    }
+
+    fn segment_blocks_of(&mut self, filename: &str) -> Option<u64> {
+        let blocks = self.segment_blocks?;
+        if !filename.ends_with(".tbl") {
+            return None;
+        }
+        let segmented = match self.segmented.get(filename) {
+            Some(&segmented) => segmented,
+            None => {
+                let segmented =
+                    !self.open_files.contains_key(filename) && !self.path(filename).exists();
+                self.segmented.insert(filename.to_string(), segmented);
+                segmented
+            }
+        };
+        segmented.then_some(blocks)
+    }
+
+    fn segment_exists(&self, filename: &str) -> bool {
+        self.open_files.contains_key(filename) || self.path(filename).exists()
+    }
+
+    /// exists は指定したファイルがあるかどうかを返す
+    /// セグメントに分けられたファイルは最初のセグメントがあればあるとみなす
+    pub fn exists(&self, filename: &str) -> bool {
+        self.segment_exists(filename) || self.segment_exists(&format!("{}.0", filename))
+    }
+
     // TODO: thread safe
     pub fn read(&mut self, block: &BlockId, page: &mut Page) -> Result<()> {
-        let block_size = self.block_size;
-        let mut file = self.get_file(&block.filename)?;
-        let offset = block.num * block_size;
-        file.seek(std::io::SeekFrom::Start(offset as u64))?;
+        let block_size = self.block_size as u64;
+        let (filename, num) = self.segment(&block.filename, block.num as u64);
+        let mut file = self.get_file(&filename)?;
+        file.seek(std::io::SeekFrom::Start(num * block_size))?;
         _ = file.read(page.contents_mut())?;
         Ok(())
     }
 
     // TODO: thread safe
     pub fn write(&mut self, block: &BlockId, page: &mut Page) -> Result<()> {
-        let block_size = self.block_size;
-        let mut file = self.get_file(&block.filename)?;
-        let offset = block.num * block_size;
-        file.seek(std::io::SeekFrom::Start(offset as u64))?;
+        let block_size = self.block_size as u64;
+        let (filename, num) = self.segment(&block.filename, block.num as u64);
+        let mut file = self.get_file(&filename)?;
+        file.seek(std::io::SeekFrom::Start(num * block_size))?;
         file.write_all(page.contents())?;
+        self.unsynced.insert(filename);
         Ok(())
     }
 
+    /// get_file は開いているファイルを返す。開いていなければ開き、上限を超える場合は最も長く使っていないファイルを閉じる
     pub fn get_file<'a>(&'a mut self, filename: &'a str) -> Result<&'a File> {
-        if self.open_files.contains_key(filename) {
-            self.open_files
-                .get(filename)
-                .ok_or(anyhow::anyhow!("cannot open file {}", filename))
-        } else {
+        self.use_count += 1;
+        let last_used = self.use_count;
+        if !self.open_files.contains_key(filename) {
+            Self::check_filename(filename)?;
+            if self.open_files.len() >= self.max_open_files {
+                self.close_least_recently_used()?;
+            }
             let file = OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(true)
                 .truncate(false)
-                .open(self.db_dir.join(filename))?;
-            self.open_files.insert(filename.to_string(), file);
-            Ok(self.open_files.get(filename).unwrap())
+                .open(self.path(filename))?;
+            self.open_files
+                .insert(filename.to_string(), OpenFile { file, last_used });
         }
+        let open_file = self
+            .open_files
+            .get_mut(filename)
+            .ok_or(anyhow::anyhow!("cannot open file {}", filename))?;
+        open_file.last_used = last_used;
+        Ok(&open_file.file)
     }
 
     /// append_block 指定したファイルに新しいブロックを追加して、そのブロックのIDを返す
     pub fn append_block(&mut self, filename: &str) -> Result<BlockId> {
-        let block = BlockId::new(filename.to_string(), self.block_count(filename)? as i32);
-        let offset = block.num * self.block_size;
-        let bytes = vec![0; self.block_size as usize];
-        let mut file = self.get_file(filename)?;
-        file.seek(std::io::SeekFrom::Start(offset as u64))?;
-        file.write_all(&bytes)?;
-        Ok(block)
+        self.append_blocks(filename, 1)
+    }
+
+    /// append_blocks は指定したファイルに 0 で埋めた count 個のブロックを追加して、最初のブロックのIDを返す
+    /// 同じセグメントに入るブロックは1回の書き込みで追加する
+    pub fn append_blocks(&mut self, filename: &str, count: i32) -> Result<BlockId> {
+        if count < 1 {
+            bail!("block count must be positive: {}", count);
+        }
+        let first = self.block_count(filename)?;
+        let end = first + count as u64;
+        let block_size = self.block_size as u64;
+        let mut num = first;
+        while num < end {
+            let segment_end = match self.segment_blocks_of(filename) {
+                Some(blocks) => (num / blocks + 1) * blocks,
+                None => end,
+            };
+            let blocks = segment_end.min(end) - num;
+            let bytes = vec![0; (blocks * block_size) as usize];
+            let (segment, offset) = self.segment(filename, num);
+            let mut file = self.get_file(&segment)?;
+            file.seek(std::io::SeekFrom::Start(offset * block_size))?;
+            file.write_all(&bytes)?;
+            self.unsynced.insert(segment);
+            num += blocks;
+        }
+        Ok(BlockId::new(filename.to_string(), first as i32))
+    }
+
+    /// remove_file は開いているファイルを閉じてから削除する
+    /// セグメントに分けられたファイルはすべてのセグメントを削除する
+    pub fn remove_file(&mut self, filename: &str) -> Result<()> {
+        let mut filenames = vec![filename.to_string()];
+        if self.segment_blocks_of(filename).is_some() {
+            filenames.extend(
+                (0..)
+                    .map(|segment| format!("{}.{}", filename, segment))
+                    .take_while(|segment| self.segment_exists(segment)),
+            );
+        }
+        for filename in filenames {
+            self.forget_file(&filename);
+            let path = self.path(&filename);
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        self.segmented.remove(filename);
+        Ok(())
+    }
+
+    /// rename_file は開いているファイルを閉じてから名前を変える。to のファイルがあれば置き換える
+    /// セグメントに分けられたファイルはすべてのセグメントの名前を変える
+    pub fn rename_file(&mut self, from: &str, to: &str) -> Result<()> {
+        self.remove_file(to)?;
+        let mut renames = vec![(from.to_string(), to.to_string())];
+        renames.extend(
+            (0..)
+                .map(|segment| {
+                    (
+                        format!("{}.{}", from, segment),
+                        format!("{}.{}", to, segment),
+                    )
+                })
+                .take_while(|(segment, _)| self.segment_exists(segment)),
+        );
+        for (from, to) in renames {
+            self.close_file(&from)?;
+            let path = self.path(&from);
+            if path.exists() {
+                std::fs::rename(path, self.path(&to))?;
+            }
+        }
+        if let Some(segmented) = self.segmented.remove(from) {
+            self.segmented.insert(to.to_string(), segmented);
+        }
+        Ok(())
+    }
+
+    /// shrink はファイルのブロック数を blocks に減らす。ブロック数が blocks 以下の場合は何もしない
+    /// セグメントに分けられたファイルは、残すブロックを含まないセグメントを削除する
+    pub fn shrink(&mut self, filename: &str, blocks: u64) -> Result<()> {
+        let block_size = self.block_size as u64;
+        let Some(segment_blocks) = self.segment_blocks_of(filename) else {
+            let file = self.get_file(filename)?;
+            if file.metadata()?.len() > blocks * block_size {
+                file.set_len(blocks * block_size)?;
+                self.unsynced.insert(filename.to_string());
+            }
+            return Ok(());
+        };
+        for segment in 0.. {
+            let name = format!("{}.{}", filename, segment);
+            if !self.segment_exists(&name) {
+                break;
+            }
+            let first = segment * segment_blocks;
+            if first >= blocks {
+                self.forget_file(&name);
+                std::fs::remove_file(self.path(&name))?;
+                continue;
+            }
+            let len = (blocks - first).min(segment_blocks) * block_size;
+            let file = self.get_file(&name)?;
+            if file.metadata()?.len() > len {
+                file.set_len(len)?;
+                self.unsynced.insert(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// remove_temp_file は一時ファイルと、その名前に拡張子を付けたファイルをすべて削除する
+    pub fn remove_temp_file(&mut self, name: &str) -> Result<()> {
+        self.open_files
+            .retain(|filename, _| !TempFileManager::belongs_to(name, filename));
+        self.unsynced
+            .retain(|filename| !TempFileManager::belongs_to(name, filename));
+        for entry in read_dir(&self.db_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_file()
+                && TempFileManager::belongs_to(name, &entry.file_name().to_string_lossy())
+            {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        self.segmented
+            .retain(|filename, _| !TempFileManager::belongs_to(name, filename));
+        Ok(())
     }
 
     // length returns block count
     pub fn block_count(&mut self, filename: &str) -> Result<u64> {
-        let file = self.get_file(filename)?;
-        Ok(file.metadata()?.len() / self.block_size as u64)
+        let block_size = self.block_size as u64;
+        let Some(blocks) = self.segment_blocks_of(filename) else {
+            let file = self.get_file(filename)?;
+            return Ok(file.metadata()?.len() / block_size);
+        };
+        // 最後のセグメント以外はすべて埋まっている
+        let mut count = 0;
+        for segment in 0.. {
+            let segment = format!("{}.{}", filename, segment);
+            if !self.segment_exists(&segment) {
+                break;
+            }
+            let len = self.get_file(&segment)?.metadata()?.len() / block_size;
+            count += len;
+            if len < blocks {
+                break;
+            }
+        }
+        Ok(count)
     }
 }
 
@@ -113,6 +449,29 @@ mod tests {
         assert!(file_manager.is_new);
     }
 
+    #[test]
+    fn should_place_file_in_another_directory() -> Result<()> {
+        let tempdir = tempdir()?;
+        let (db_dir, log_dir) = (tempdir.path().join("db"), tempdir.path().join("log"));
+        let mut file_manager = FileManager::new(&db_dir, 32)?;
+        file_manager.set_file_dir("x.log", &log_dir)?;
+        let block = file_manager.append_block("x.log")?;
+        let mut page = Page::new(32);
+        page.set_int(0, 7);
+        file_manager.write(&block, &mut page)?;
+        assert!(log_dir.join("x.log").exists());
+        assert!(!db_dir.join("x.log").exists());
+        assert!(file_manager.exists("x.log"));
+
+        let mut page = Page::new(32);
+        file_manager.read(&block, &mut page)?;
+        assert_eq!(page.get_int(0), 7);
+        file_manager.remove_file("x.log")?;
+        assert!(!log_dir.join("x.log").exists());
+        assert!(file_manager.set_file_dir("../x.log", &log_dir).is_err());
+        Ok(())
+    }
+
     #[test]
     fn should_remove_temp_file() {
         let tempdir = tempdir().unwrap();
@@ -124,6 +483,174 @@ mod tests {
         assert!(!tmpfile.exists());
     }
 
+    #[test]
+    fn should_remove_file() {
+        let tempdir = tempdir().unwrap();
+        let mut file_manager = FileManager::new(tempdir.path(), 32).unwrap();
+        file_manager.append_block("temp0_0").unwrap();
+        assert!(tempdir.path().join("temp0_0").exists());
+        file_manager.remove_file("temp0_0").unwrap();
+        assert!(!tempdir.path().join("temp0_0").exists());
+        assert!(!file_manager.open_files.contains_key("temp0_0"));
+
+        file_manager.append_block("temp0_1.tbl").unwrap();
+        file_manager.append_block("temp0_1.ovf").unwrap();
+        file_manager.append_block("temp0_10.tbl").unwrap();
+        file_manager.remove_temp_file("temp0_1").unwrap();
+        assert!(!tempdir.path().join("temp0_1.tbl").exists());
+        assert!(!tempdir.path().join("temp0_1.ovf").exists());
+        assert!(tempdir.path().join("temp0_10.tbl").exists());
+    }
+
+    #[test]
+    fn should_split_table_into_segments() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path();
+        let mut file_manager = FileManager::new(path, 32).unwrap();
+        file_manager.append_block("legacy.tbl").unwrap();
+        file_manager.set_segment_blocks(Some(2));
+
+        let mut page = Page::new(32);
+        for i in 0..5 {
+            let block = file_manager.append_block("t.tbl").unwrap();
+            assert_eq!(block.num, i);
+            page.set_int(0, i * 10);
+            file_manager.write(&block, &mut page).unwrap();
+        }
+        assert_eq!(file_manager.block_count("t.tbl").unwrap(), 5);
+        assert!(!path.join("t.tbl").exists());
+        for segment in ["t.tbl.0", "t.tbl.1", "t.tbl.2"] {
+            assert!(path.join(segment).exists());
+        }
+        assert!(!path.join("t.tbl.3").exists());
+        for i in 0..5 {
+            file_manager
+                .read(&BlockId::new("t.tbl".into(), i), &mut page)
+                .unwrap();
+            assert_eq!(page.get_int(0), i * 10);
+        }
+
+        // 分割されていない既存のファイルとテーブル以外のファイルはそのまま使う
+        file_manager.append_block("legacy.tbl").unwrap();
+        file_manager.append_block("idx.leaf").unwrap();
+        assert_eq!(file_manager.block_count("legacy.tbl").unwrap(), 2);
+        assert!(!path.join("legacy.tbl.0").exists());
+        assert!(path.join("idx.leaf").exists());
+
+        // まとめて追加するブロックもセグメントをまたいで分ける
+        let block = file_manager.append_blocks("t.tbl", 3).unwrap();
+        assert_eq!(block.num, 5);
+        assert_eq!(file_manager.block_count("t.tbl").unwrap(), 8);
+        assert!(path.join("t.tbl.3").exists());
+        assert!(file_manager.append_blocks("t.tbl", 0).is_err());
+        file_manager.shrink("t.tbl", 5).unwrap();
+
+        // 減らしたブロックを含まないセグメントは削除する
+        file_manager.append_block("t.tbl").unwrap();
+        file_manager.shrink("t.tbl", 3).unwrap();
+        assert_eq!(file_manager.block_count("t.tbl").unwrap(), 3);
+        assert!(!path.join("t.tbl.2").exists());
+        file_manager.shrink("t.tbl", 5).unwrap();
+        assert_eq!(file_manager.block_count("t.tbl").unwrap(), 3);
+        for i in 3..5 {
+            file_manager.append_block("t.tbl").unwrap();
+            page.set_int(0, i * 10);
+            file_manager
+                .write(&BlockId::new("t.tbl".into(), i), &mut page)
+                .unwrap();
+        }
+
+        // 名前を変えてもセグメントのまま読める
+        file_manager.rename_file("t.tbl", "u.tbl").unwrap();
+        assert!(!path.join("t.tbl.0").exists());
+        assert!(path.join("u.tbl.2").exists());
+        assert_eq!(file_manager.block_count("u.tbl").unwrap(), 5);
+        file_manager
+            .read(&BlockId::new("u.tbl".into(), 4), &mut page)
+            .unwrap();
+        assert_eq!(page.get_int(0), 40);
+        file_manager.rename_file("u.tbl", "t.tbl").unwrap();
+
+        file_manager.remove_file("t.tbl").unwrap();
+        assert!(!path.join("t.tbl.0").exists());
+        assert!(!path.join("t.tbl.2").exists());
+        assert_eq!(file_manager.block_count("t.tbl").unwrap(), 0);
+    }
+
+    #[test]
+    fn should_close_least_recently_used_files() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path();
+        let mut file_manager = FileManager::new(path, 32).unwrap();
+        file_manager.set_max_open_files(2).unwrap();
+
+        let mut page = Page::new(32);
+        for (i, filename) in ["a", "b", "c"].iter().enumerate() {
+            let block = file_manager.append_block(filename).unwrap();
+            page.set_int(0, i as i32 + 1);
+            file_manager.write(&block, &mut page).unwrap();
+            if *filename == "b" {
+                // a を使い直すと、次に閉じるのは b になる
+                file_manager.get_file("a").unwrap();
+            }
+        }
+        assert_eq!(file_manager.open_files.len(), 2);
+        assert!(file_manager.open_files.contains_key("a"));
+        assert!(!file_manager.open_files.contains_key("b"));
+
+        // 閉じたファイルは使うときに開き直す
+        file_manager
+            .read(&BlockId::new("b".into(), 0), &mut page)
+            .unwrap();
+        assert_eq!(page.get_int(0), 2);
+        assert_eq!(file_manager.open_files.len(), 2);
+        assert!(!file_manager.open_files.contains_key("a"));
+
+        file_manager.remove_file("b").unwrap();
+        assert!(!file_manager.open_files.contains_key("b"));
+        file_manager.set_max_open_files(0).unwrap();
+        assert_eq!(file_manager.max_open_files(), 1);
+        assert_eq!(file_manager.open_files.len(), 1);
+    }
+
+    #[test]
+    fn should_sync_written_files() {
+        let tempdir = tempdir().unwrap();
+        let mut file_manager = FileManager::new(tempdir.path(), 32).unwrap();
+        let mut page = Page::new(32);
+        let block = file_manager.append_block("a").unwrap();
+        file_manager.write(&block, &mut page).unwrap();
+        let temp = file_manager.append_block("temp1_0").unwrap();
+        file_manager.write(&temp, &mut page).unwrap();
+        assert!(file_manager.unsynced.contains("a"));
+        assert!(file_manager.unsynced.contains("temp1_0"));
+
+        file_manager.sync().unwrap();
+        assert!(file_manager.unsynced.is_empty());
+
+        // 閉じるときに同期していない書き込みを同期する
+        file_manager.write(&block, &mut page).unwrap();
+        file_manager.get_file("temp1_0").unwrap();
+        file_manager.set_max_open_files(1).unwrap();
+        assert!(!file_manager.open_files.contains_key("a"));
+        assert!(!file_manager.unsynced.contains("a"));
+    }
+
+    #[test]
+    fn should_reject_file_name_with_path_separator() {
+        let tempdir = tempdir().unwrap();
+        let mut file_manager = FileManager::new(tempdir.path(), 32).unwrap();
+        for filename in ["", ".", "..", "../a", "a/b", "a\\b", "C:a"] {
+            assert!(
+                file_manager.get_file(filename).is_err(),
+                "{:?} should be rejected",
+                filename
+            );
+        }
+        assert!(file_manager.open_files.is_empty());
+        assert!(file_manager.get_file("a.tbl.0").is_ok());
+    }
+
     #[test]
     fn should_can_get_new_file() {
         let tempdir = tempdir().unwrap();
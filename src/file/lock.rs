@@ -0,0 +1,168 @@
+use anyhow::{bail, Result};
+use std::{
+    fs::{remove_file, File, OpenOptions},
+    path::Path,
+};
+
+/// LOCK_FILE はデータベースのディレクトリを使っていることを示すロックファイルの名前
+pub const LOCK_FILE: &str = "tinydb.lock";
+
+/// DirLock はデータベースのディレクトリに対するアドバイザリロック
+/// ロックファイルに排他ロック（unix では flock、Windows では LockFileEx）をかけ、DirLock がドロップされてファイルが閉じられると解放される
+/// プロセスが異常終了した場合も OS がロックを解放するので、古いロックが残ることはない
+#[derive(Debug)]
+pub struct DirLock {
+    _file: File,
+}
+
+impl DirLock {
+    /// acquire はロックファイルを排他ロックする
+    /// 他のプロセスがロックしている場合は待たずにエラーを返す
+    pub fn acquire(db_dir: &Path) -> Result<Self> {
+        let path = db_dir.join(LOCK_FILE);
+        let file = open(&path)?;
+        if !try_lock(&file)? {
+            bail!(
+                "database in use: {} is locked by another process",
+                db_dir.display()
+            );
+        }
+        Ok(Self { _file: file })
+    }
+
+    /// steal は他のプロセスがロックしていても、ロックファイルを作り直してロックを奪う
+    /// 元のプロセスは削除されたファイルのロックを持ち続けるだけなので、
+    /// そのプロセスが終了していることを呼び出し側が確認しなければならない
+    /// Windows ではファイルシステムによって開いているロックファイルを削除できないので、その場合はエラーになる
+    pub fn steal(db_dir: &Path) -> Result<Self> {
+        let path = db_dir.join(LOCK_FILE);
+        let file = open(&path)?;
+        if try_lock(&file)? {
+            return Ok(Self { _file: file });
+        }
+        drop(file);
+        if let Err(err) = remove_file(&path) {
+            bail!(
+                "database in use: cannot remove lock file {}: {}",
+                path.display(),
+                err
+            );
+        }
+        Self::acquire(db_dir)
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?)
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> Result<bool> {
+    use std::os::unix::io::AsRawFd as _;
+
+    let ret = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if ret == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err.into())
+    }
+}
+
+#[cfg(windows)]
+fn try_lock(file: &File) -> Result<bool> {
+    use std::os::windows::io::AsRawHandle as _;
+
+    const LOCKFILE_FAIL_IMMEDIATELY: u32 = 0x1;
+    const LOCKFILE_EXCLUSIVE_LOCK: u32 = 0x2;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    #[repr(C)]
+    struct Overlapped {
+        internal: usize,
+        internal_high: usize,
+        offset: u32,
+        offset_high: u32,
+        h_event: *mut std::ffi::c_void,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LockFileEx(
+            file: *mut std::ffi::c_void,
+            flags: u32,
+            reserved: u32,
+            bytes_low: u32,
+            bytes_high: u32,
+            overlapped: *mut Overlapped,
+        ) -> i32;
+    }
+
+    let mut overlapped = Overlapped {
+        internal: 0,
+        internal_high: 0,
+        offset: 0,
+        offset_high: 0,
+        h_event: std::ptr::null_mut(),
+    };
+    // ファイル全体（先頭から最大の長さ）をロックする
+    let ret = unsafe {
+        LockFileEx(
+            file.as_raw_handle() as _,
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if ret != 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION) {
+        Ok(false)
+    } else {
+        Err(err.into())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn try_lock(_file: &File) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(all(test, any(unix, windows)))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_fail_to_lock_twice() {
+        let tempdir = tempdir().unwrap();
+        let lock = DirLock::acquire(tempdir.path()).unwrap();
+        let err = DirLock::acquire(tempdir.path()).unwrap_err();
+        assert!(err.to_string().starts_with("database in use"));
+
+        drop(lock);
+        assert!(DirLock::acquire(tempdir.path()).is_ok());
+    }
+
+    // Windows ではロックファイルを削除できるかがファイルシステムによって変わる
+    #[test]
+    #[cfg(unix)]
+    fn should_steal_lock() {
+        let tempdir = tempdir().unwrap();
+        let _lock = DirLock::acquire(tempdir.path()).unwrap();
+        let _stolen = DirLock::steal(tempdir.path()).unwrap();
+        assert!(DirLock::acquire(tempdir.path()).is_err());
+    }
+}
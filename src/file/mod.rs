@@ -1,3 +1,7 @@
 pub mod block;
+pub mod control_file;
+pub mod date;
 pub mod file_manager;
+pub mod lock;
 pub mod page;
+pub mod temp_file_manager;
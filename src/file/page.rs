@@ -1,58 +1,86 @@
-use anyhow::Result;
-use std::{
-    io::{Cursor, Read, Write},
-    mem::size_of,
-};
+use anyhow::{bail, Result};
+use std::mem::size_of;
 
+use super::date::Date;
 use crate::I32_SIZE;
 
+/// Page はブロックの中身を持つバイト列
+///
+/// 値はオフセットから直接読み書きし、読み書きの位置は持たない
+/// そのため読み込みは &self でき、同じページを複数の参照から読める
 #[derive(Debug, Default)]
 pub struct Page {
-    buffer: Cursor<Vec<u8>>,
+    buffer: Vec<u8>,
 }
 
 impl From<Vec<u8>> for Page {
     fn from(value: Vec<u8>) -> Self {
-        Self {
-            buffer: Cursor::new(value),
-        }
+        Self { buffer: value }
     }
 }
 
 impl Page {
     pub fn new(block_size: i32) -> Page {
         Page {
-            buffer: Cursor::new(vec![0; block_size as usize]),
+            buffer: vec![0; block_size as usize],
         }
     }
 
-    pub fn get_int(&mut self, offset: usize) -> i32 {
-        self.buffer.set_position(offset as u64);
-        let mut bytes = [0; I32_SIZE];
-        self.buffer.read_exact(&mut bytes).unwrap();
-        i32::from_le_bytes(bytes)
+    pub fn get_int(&self, offset: usize) -> i32 {
+        i32::from_le_bytes(self.bytes_at(offset))
     }
 
     pub fn set_int(&mut self, offset: usize, value: i32) {
-        self.buffer.set_position(offset as u64);
-        self.buffer.write_all(&value.to_le_bytes()).unwrap();
+        self.put(offset, &value.to_le_bytes());
+    }
+
+    pub fn get_long(&self, offset: usize) -> i64 {
+        i64::from_le_bytes(self.bytes_at(offset))
+    }
+
+    pub fn set_long(&mut self, offset: usize, value: i64) {
+        self.put(offset, &value.to_le_bytes());
+    }
+
+    pub fn get_double(&self, offset: usize) -> f64 {
+        f64::from_bits(self.get_long(offset) as u64)
+    }
+
+    pub fn set_double(&mut self, offset: usize, value: f64) {
+        self.set_long(offset, value.to_bits() as i64);
+    }
+
+    /// get_bool は1バイトの値を読み込み、0 以外を true として返す
+    pub fn get_bool(&self, offset: usize) -> bool {
+        self.slice(offset, 1)[0] != 0
+    }
+
+    pub fn set_bool(&mut self, offset: usize, value: bool) {
+        self.put(offset, &[value as u8]);
+    }
+
+    /// get_date は 1970-01-01 からの日数として格納された日付を読み込む
+    pub fn get_date(&self, offset: usize) -> Date {
+        Date::from_days(self.get_int(offset))
     }
 
-    pub fn get_bytes(&mut self, offset: usize) -> Vec<u8> {
+    pub fn set_date(&mut self, offset: usize, value: Date) {
+        self.set_int(offset, value.days());
+    }
+
+    /// get_bytes は先頭の4バイトの長さに続くバイト列を読み込む
+    pub fn get_bytes(&self, offset: usize) -> Vec<u8> {
         let length = self.get_int(offset) as usize;
-        let mut bytes = vec![0; length];
-        self.buffer.read_exact(&mut bytes).unwrap();
-        bytes
+        self.slice(offset + I32_SIZE, length).to_vec()
     }
 
+    /// set_bytes はバイト列の長さを4バイトで書き込み、続けてバイト列を書き込む
     pub fn set_bytes(&mut self, offset: usize, bytes: &[u8]) {
-        self.buffer.set_position(offset as u64);
-        let length = bytes.len() as i32;
-        self.set_int(offset, length);
-        self.buffer.write_all(bytes).unwrap();
+        self.set_int(offset, bytes.len() as i32);
+        self.put(offset + I32_SIZE, bytes);
     }
 
-    pub fn get_string(&mut self, offset: usize) -> String {
+    pub fn get_string(&self, offset: usize) -> String {
         let bytes = self.get_bytes(offset);
         String::from_utf8_lossy(&bytes).to_string()
     }
@@ -65,21 +93,46 @@ impl Page {
         size_of::<u32>() + (str_len * size_of::<u8>())
     }
 
-    pub fn contents(&mut self) -> &[u8] {
-        self.buffer.set_position(0);
-        self.buffer.get_ref()
+    pub fn contents(&self) -> &[u8] {
+        &self.buffer
     }
 
     pub fn contents_mut(&mut self) -> &mut [u8] {
-        self.buffer.set_position(0);
-        self.buffer.get_mut()
+        &mut self.buffer
+    }
+
+    pub fn read_bytes(&self, offset: usize, len: usize) -> Result<Vec<u8>> {
+        match self.buffer.get(offset..offset + len) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => bail!("read out of page bounds: offset {} length {}", offset, len),
+        }
     }
 
-    pub fn read_bytes(&mut self, offset: usize, len: usize) -> Result<Vec<u8>> {
-        self.buffer.set_position(offset as u64);
-        let mut bytes = vec![0; len];
-        self.buffer.read_exact(&mut bytes)?;
-        Ok(bytes)
+    /// write_bytes は長さを付けずにバイト列をそのまま書き込む
+    pub fn write_bytes(&mut self, offset: usize, bytes: &[u8]) -> Result<()> {
+        match self.buffer.get_mut(offset..offset + bytes.len()) {
+            Some(dest) => dest.copy_from_slice(bytes),
+            None => bail!(
+                "write out of page bounds: offset {} length {}",
+                offset,
+                bytes.len()
+            ),
+        }
+        Ok(())
+    }
+
+    /// slice は offset から len バイトを返す。ページの外を読もうとした場合はパニックする
+    fn slice(&self, offset: usize, len: usize) -> &[u8] {
+        &self.buffer[offset..offset + len]
+    }
+
+    fn bytes_at<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.slice(offset, N).try_into().unwrap()
+    }
+
+    /// put は offset にバイト列を書き込む。ページの外に書こうとした場合はパニックする
+    fn put(&mut self, offset: usize, bytes: &[u8]) {
+        self.buffer[offset..offset + bytes.len()].copy_from_slice(bytes);
     }
 }
 
@@ -89,7 +142,7 @@ mod tests {
 
     #[test]
     fn should_can_new_page() {
-        let mut page = Page::new(10);
+        let page = Page::new(10);
         assert_eq!(page.contents().len(), 10);
     }
 
@@ -100,11 +153,54 @@ mod tests {
         assert_eq!(page.get_string(2), "hello");
     }
 
+    #[test]
+    fn should_can_set_and_get_typed_values() {
+        let mut page = Page::new(32);
+        page.set_long(0, -1 << 40);
+        page.set_double(8, 1.5);
+        page.set_bool(16, true);
+        page.set_bool(17, false);
+        page.set_date(18, Date::from_ymd(2024, 2, 29).unwrap());
+        assert_eq!(page.get_long(0), -1 << 40);
+        assert_eq!(page.get_double(8), 1.5);
+        assert!(page.get_bool(16));
+        assert!(!page.get_bool(17));
+        assert_eq!(page.get_date(18).to_string(), "2024-02-29");
+    }
+
     #[test]
     fn should_can_get_contents() {
         let mut page = Page::new(10);
         page.set_string(0, "hello");
         assert_eq!(page.contents(), &[5, 0, 0, 0, 104, 101, 108, 108, 111, 0]);
-        assert_eq!(page.buffer.position(), 0);
+    }
+
+    #[test]
+    fn should_can_read_through_shared_references() {
+        let mut page = Page::new(16);
+        page.set_int(0, 42);
+        page.set_string(4, "abc");
+        // 読み込みは位置を使わないので、同じページを複数の参照から交互に読める
+        let (reader1, reader2) = (&page, &page);
+        assert_eq!(reader1.get_string(4), "abc");
+        assert_eq!(reader2.get_int(0), 42);
+        assert_eq!(reader1.get_int(4), 3);
+        assert!(reader2.read_bytes(12, 8).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_not_grow_when_writing_past_the_end() {
+        let mut page = Page::new(6);
+        page.set_int(4, 1);
+    }
+
+    #[test]
+    fn should_can_read_and_write_raw_bytes() {
+        let mut page = Page::new(8);
+        page.write_bytes(2, &[1, 2, 3]).unwrap();
+        assert_eq!(page.read_bytes(1, 4).unwrap(), vec![0, 1, 2, 3]);
+        assert!(page.read_bytes(6, 3).is_err());
+        assert!(page.write_bytes(6, &[1, 2, 3]).is_err());
     }
 }
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+/// TEMP_FILE_PREFIX で始まるファイルは一時ファイルとして起動時に削除される
+pub const TEMP_FILE_PREFIX: &str = "temp";
+
+/// TempFileManager はトランザクションごとに作成した一時ファイルを記録する
+/// 名前は `temp<トランザクション番号>_<連番>` になる
+/// 一時テーブルのように名前に拡張子を付けたファイル (`temp3_0.tbl` など) も同じ一時ファイルとして扱う
+#[derive(Debug, Default)]
+pub struct TempFileManager {
+    files: HashMap<i32, Vec<String>>,
+}
+
+impl TempFileManager {
+    pub fn is_temp_file(filename: &str) -> bool {
+        filename.starts_with(TEMP_FILE_PREFIX)
+    }
+
+    /// belongs_to は filename が name の一時ファイルか、name に拡張子を付けたファイルかどうかを返す
+    pub fn belongs_to(name: &str, filename: &str) -> bool {
+        filename
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    }
+
+    /// create はトランザクションの新しい一時ファイルの名前を登録して返す
+    pub fn create(&mut self, tx_num: i32) -> String {
+        let files = self.files.entry(tx_num).or_default();
+        let filename = format!("{}{}_{}", TEMP_FILE_PREFIX, tx_num, files.len());
+        files.push(filename.clone());
+        filename
+    }
+
+    pub fn files(&self, tx_num: i32) -> &[String] {
+        self.files
+            .get(&tx_num)
+            .map_or(&[], |files| files.as_slice())
+    }
+
+    /// release はトランザクションの一時ファイルの登録を解除して、その名前を返す
+    pub fn release(&mut self, tx_num: i32) -> Vec<String> {
+        self.files.remove(&tx_num).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_name_temp_files_per_transaction() {
+        let mut temp_files = TempFileManager::default();
+        assert_eq!(temp_files.create(3), "temp3_0");
+        assert_eq!(temp_files.create(3), "temp3_1");
+        assert_eq!(temp_files.create(4), "temp4_0");
+        assert!(TempFileManager::is_temp_file("temp3_1"));
+        assert!(TempFileManager::belongs_to("temp3_1", "temp3_1"));
+        assert!(TempFileManager::belongs_to("temp3_1", "temp3_1.tbl.0"));
+        assert!(!TempFileManager::belongs_to("temp3_1", "temp3_10.tbl"));
+
+        assert_eq!(temp_files.release(3), vec!["temp3_0", "temp3_1"]);
+        assert!(temp_files.files(3).is_empty());
+        assert_eq!(temp_files.files(4), ["temp4_0"]);
+    }
+}
@@ -0,0 +1,98 @@
+use super::{btree_page::BTreePage, dir_entry::DirEntry};
+use crate::{
+    file::block::BlockId, query::constant::Constant, record::layout::Layout,
+    tx::transaction::Transaction,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// BTreeDir は B-tree のディレクトリブロックを表す
+/// flag はディレクトリの階層で、0 の場合は子がリーフブロックになる
+pub struct BTreeDir {
+    tx: Arc<Mutex<Transaction>>,
+    layout: Arc<Layout>,
+    contents: BTreePage,
+    filename: String,
+}
+
+impl BTreeDir {
+    pub fn new(tx: Arc<Mutex<Transaction>>, block: BlockId, layout: Arc<Layout>) -> Result<Self> {
+        let filename = block.filename.clone();
+        let contents = BTreePage::new(tx.clone(), block, layout.clone())?;
+        Ok(Self {
+            tx,
+            layout,
+            contents,
+            filename,
+        })
+    }
+
+    pub fn close(&mut self) {
+        self.contents.close();
+    }
+
+    /// search は search_key を含むリーフブロックの番号を返す
+    pub fn search(&mut self, search_key: &Constant) -> Result<i32> {
+        let mut child_block = self.find_child_block(search_key)?;
+        while self.contents.flag()? > 0 {
+            self.contents.close();
+            self.contents = BTreePage::new(self.tx.clone(), child_block, self.layout.clone())?;
+            child_block = self.find_child_block(search_key)?;
+        }
+        Ok(child_block.num)
+    }
+
+    /// make_new_root はルートを分割したときに、ルートの1つ下に既存のレコードを移して
+    /// ルートに2つのレコードだけを持たせる
+    /// ルートのブロック番号は 0 のまま変わらない
+    pub fn make_new_root(&mut self, entry: DirEntry) -> Result<()> {
+        let first_val = self.contents.data_val(0)?;
+        let level = self.contents.flag()?;
+        let new_block = self.contents.split(0, level)?;
+        let old_root = DirEntry::new(first_val, new_block.num);
+        self.insert_entry(old_root)?;
+        self.insert_entry(entry)?;
+        self.contents.set_flag(level + 1)
+    }
+
+    /// insert は子にディレクトリのレコードを追加する
+    /// このブロックを分割した場合は、新しいブロックを指すレコードを返す
+    pub fn insert(&mut self, entry: DirEntry) -> Result<Option<DirEntry>> {
+        if self.contents.flag()? == 0 {
+            return self.insert_entry(entry);
+        }
+        let child_block = self.find_child_block(&entry.data_val)?;
+        let mut child = BTreeDir::new(self.tx.clone(), child_block, self.layout.clone())?;
+        let my_entry = child.insert(entry);
+        child.close();
+        match my_entry? {
+            Some(my_entry) => self.insert_entry(my_entry),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_entry(&mut self, entry: DirEntry) -> Result<Option<DirEntry>> {
+        let new_slot = 1 + self.contents.find_slot_before(&entry.data_val)?;
+        self.contents
+            .insert_dir(new_slot, entry.data_val, entry.block_num)?;
+        if !self.contents.is_full()? {
+            return Ok(None);
+        }
+        let level = self.contents.flag()?;
+        let split_pos = self.contents.num_records()? / 2;
+        let split_val = self.contents.data_val(split_pos)?;
+        let new_block = self.contents.split(split_pos, level)?;
+        Ok(Some(DirEntry::new(split_val, new_block.num)))
+    }
+
+    fn find_child_block(&self, search_key: &Constant) -> Result<BlockId> {
+        let mut slot = self.contents.find_slot_before(search_key)?;
+        if slot + 1 < self.contents.num_records()?
+            && self.contents.data_val(slot + 1)? == *search_key
+        {
+            slot += 1;
+        }
+        let block_num = self.contents.child_num(slot)?;
+        Ok(BlockId::new(self.filename.clone(), block_num))
+    }
+}
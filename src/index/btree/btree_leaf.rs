@@ -0,0 +1,164 @@
+use super::{
+    btree_page::{BTreePage, NO_BLOCK},
+    dir_entry::DirEntry,
+};
+use crate::{
+    file::block::BlockId, query::constant::Constant, record::layout::Layout, record::rid::RID,
+    tx::transaction::Transaction,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// BTreeLeaf は B-tree のリーフブロックを表す
+/// 同じキーのレコードが1ブロックに収まらない場合は、flag にオーバーフローブロックの番号を持つ
+pub struct BTreeLeaf {
+    tx: Arc<Mutex<Transaction>>,
+    layout: Arc<Layout>,
+    search_key: Constant,
+    contents: BTreePage,
+    current_slot: i32,
+    filename: String,
+}
+
+impl BTreeLeaf {
+    /// new は指定したブロックを開いて、search_key の直前のスロットに位置づける
+    pub fn new(
+        tx: Arc<Mutex<Transaction>>,
+        block: BlockId,
+        layout: Arc<Layout>,
+        search_key: Constant,
+    ) -> Result<Self> {
+        let filename = block.filename.clone();
+        let contents = BTreePage::new(tx.clone(), block, layout.clone())?;
+        let current_slot = contents.find_slot_before(&search_key)?;
+        Ok(Self {
+            tx,
+            layout,
+            search_key,
+            contents,
+            current_slot,
+            filename,
+        })
+    }
+
+    pub fn close(&mut self) {
+        self.contents.close();
+    }
+
+    /// next は search_key と同じキーを持つ次のレコードに移動する
+    /// 見つからない場合は false を返す
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<bool> {
+        self.current_slot += 1;
+        if self.current_slot >= self.contents.num_records()? {
+            self.try_overflow()
+        } else if self.contents.data_val(self.current_slot)? == self.search_key {
+            Ok(true)
+        } else {
+            self.try_overflow()
+        }
+    }
+
+    /// next_in_order はキーに関係なく、キーの昇順で次のレコードに移動する
+    /// ブロックの最後まで読んだら右隣のブロックに移動し、リーフの最後まで読んだら false を返す
+    pub fn next_in_order(&mut self) -> Result<bool> {
+        self.current_slot += 1;
+        while self.current_slot >= self.contents.num_records()? {
+            let next = self.contents.next()?;
+            if next == NO_BLOCK {
+                return Ok(false);
+            }
+            self.move_to_block(next)?;
+        }
+        Ok(true)
+    }
+
+    /// data_val は現在のレコードのキーを返す
+    pub fn data_val(&self) -> Result<Constant> {
+        self.contents.data_val(self.current_slot)
+    }
+
+    pub fn data_rid(&self) -> Result<RID> {
+        self.contents.data_rid(self.current_slot)
+    }
+
+    /// delete は search_key と同じキーを持つレコードから data_rid を指すものを削除する
+    pub fn delete(&mut self, data_rid: RID) -> Result<()> {
+        while self.next()? {
+            if self.data_rid()? == data_rid {
+                self.contents.delete(self.current_slot)?;
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// insert は search_key と data_rid のレコードを追加する
+    /// ブロックを分割した場合は、新しいブロックを指すディレクトリのレコードを返す
+    pub fn insert(&mut self, data_rid: RID) -> Result<Option<DirEntry>> {
+        // オーバーフローブロックを持つブロックの先頭より小さいキーを追加する場合は、
+        // 既存のレコードをすべて新しいブロックに移して、このブロックには新しいキーだけを置く
+        if self.contents.flag()? >= 0 && self.contents.data_val(0)? > self.search_key {
+            let first_val = self.contents.data_val(0)?;
+            let new_block = self.contents.split(0, self.contents.flag()?)?;
+            self.current_slot = 0;
+            self.contents.set_flag(NO_BLOCK)?;
+            self.contents
+                .insert_leaf(self.current_slot, self.search_key.clone(), data_rid)?;
+            return Ok(Some(DirEntry::new(first_val, new_block.num)));
+        }
+
+        self.current_slot += 1;
+        self.contents
+            .insert_leaf(self.current_slot, self.search_key.clone(), data_rid)?;
+        if !self.contents.is_full()? {
+            return Ok(None);
+        }
+
+        // ブロックがいっぱいになったので分割する
+        let num_records = self.contents.num_records()?;
+        let first_key = self.contents.data_val(0)?;
+        let last_key = self.contents.data_val(num_records - 1)?;
+        if last_key == first_key {
+            // すべて同じキーの場合はオーバーフローブロックを作る
+            let new_block = self.contents.split(1, self.contents.flag()?)?;
+            self.contents.set_flag(new_block.num)?;
+            return Ok(None);
+        }
+
+        // 同じキーのレコードが2つのブロックに分かれないように分割位置を決める
+        let mut split_pos = num_records / 2;
+        let mut split_key = self.contents.data_val(split_pos)?;
+        if split_key == first_key {
+            while self.contents.data_val(split_pos)? == split_key {
+                split_pos += 1;
+            }
+            split_key = self.contents.data_val(split_pos)?;
+        } else {
+            while self.contents.data_val(split_pos - 1)? == split_key {
+                split_pos -= 1;
+            }
+        }
+        let new_block = self.contents.split(split_pos, NO_BLOCK)?;
+        Ok(Some(DirEntry::new(split_key, new_block.num)))
+    }
+
+    /// try_overflow は search_key と同じキーのオーバーフローブロックがあればそこに移動する
+    fn try_overflow(&mut self) -> Result<bool> {
+        let first_key = self.contents.data_val(0)?;
+        let flag = self.contents.flag()?;
+        if self.search_key != first_key || flag < 0 {
+            return Ok(false);
+        }
+        self.move_to_block(flag)?;
+        Ok(true)
+    }
+
+    fn move_to_block(&mut self, block_num: i32) -> Result<()> {
+        self.contents.close();
+        let block = BlockId::new(self.filename.clone(), block_num);
+        self.contents = BTreePage::new(self.tx.clone(), block, self.layout.clone())?;
+        self.current_slot = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    file::block::BlockId,
+    query::constant::Constant,
+    record::{layout::Layout, rid::RID, schema::FieldTypes},
+    tx::transaction::Transaction,
+    I32_SIZE,
+};
+use anyhow::{anyhow, bail, Result};
+use std::sync::{Arc, Mutex};
+
+/// HEADER_SIZE は B-tree のブロック先頭にあるヘッダのサイズ
+/// ヘッダは以下の3つの値で構成される
+///   - フラグ（ディレクトリは階層、リーフはオーバーフローブロックの番号、なければ -1）
+///   - レコード数
+///   - 右隣のブロック番号（なければ -1）
+pub const HEADER_SIZE: i32 = 3 * I32_SIZE as i32;
+const FLAG_OFFSET: i32 = 0;
+const NUM_RECORDS_OFFSET: i32 = I32_SIZE as i32;
+const NEXT_OFFSET: i32 = 2 * I32_SIZE as i32;
+
+/// NO_BLOCK は参照するブロックがないことを表す
+pub const NO_BLOCK: i32 = -1;
+
+/// BTreePage は B-tree のディレクトリとリーフで共通して使うブロックの操作を提供する
+/// レコードは固定長で、dataval の昇順に並べて格納する
+///
+/// ```text
+/// ┌──────┬─────────┬──────┬──────────┬──────────┬─────┐
+/// │ flag │ records │ next │ record 0 │ record 1 │ ... │
+/// └──────┴─────────┴──────┴──────────┴──────────┴─────┘
+/// ```
+pub struct BTreePage {
+    tx: Arc<Mutex<Transaction>>,
+    pub current_block: BlockId,
+    layout: Arc<Layout>,
+}
+
+impl BTreePage {
+    pub fn new(
+        tx: Arc<Mutex<Transaction>>,
+        current_block: BlockId,
+        layout: Arc<Layout>,
+    ) -> Result<Self> {
+        tx.lock().unwrap().pin(&current_block)?;
+        Ok(Self {
+            tx,
+            current_block,
+            layout,
+        })
+    }
+
+    /// find_slot_before は search_key より小さい最後のスロット番号を返す
+    /// すべてのレコードが search_key 以上の場合は -1 を返す
+    pub fn find_slot_before(&self, search_key: &Constant) -> Result<i32> {
+        let mut slot = 0;
+        while slot < self.num_records()? && self.data_val(slot)? < *search_key {
+            slot += 1;
+        }
+        Ok(slot - 1)
+    }
+
+    pub fn close(&mut self) {
+        self.tx.lock().unwrap().unpin(&self.current_block);
+    }
+
+    /// is_full はもう1レコードを追加する空きがないかどうかを返す
+    pub fn is_full(&self) -> Result<bool> {
+        Ok(self.slot_pos(self.num_records()? + 1) >= self.tx.lock().unwrap().block_size())
+    }
+
+    /// split は split_pos 以降のレコードを新しいブロックに移して、そのブロックを返す
+    /// 新しいブロックはこのブロックの右隣になる
+    pub fn split(&mut self, split_pos: i32, flag: i32) -> Result<BlockId> {
+        let new_block = self.append_new(flag)?;
+        let mut new_page = BTreePage::new(self.tx.clone(), new_block.clone(), self.layout.clone())?;
+        self.transfer_records(split_pos, &mut new_page)?;
+        new_page.set_flag(flag)?;
+        new_page.set_next(self.next()?)?;
+        self.set_next(new_block.num)?;
+        new_page.close();
+        Ok(new_block)
+    }
+
+    pub fn data_val(&self, slot: i32) -> Result<Constant> {
+        self.get_val(slot, "dataval")
+    }
+
+    pub fn flag(&self) -> Result<i32> {
+        self.tx
+            .lock()
+            .unwrap()
+            .get_int(&self.current_block, FLAG_OFFSET)
+    }
+
+    pub fn set_flag(&self, value: i32) -> Result<()> {
+        self.tx
+            .lock()
+            .unwrap()
+            .set_int(&self.current_block, FLAG_OFFSET, value, true)
+    }
+
+    /// next は右隣のブロック番号を返す
+    pub fn next(&self) -> Result<i32> {
+        self.tx
+            .lock()
+            .unwrap()
+            .get_int(&self.current_block, NEXT_OFFSET)
+    }
+
+    pub fn set_next(&self, value: i32) -> Result<()> {
+        self.tx
+            .lock()
+            .unwrap()
+            .set_int(&self.current_block, NEXT_OFFSET, value, true)
+    }
+
+    /// append_new はファイルに新しいブロックを追加してフォーマットする
+    pub fn append_new(&self, flag: i32) -> Result<BlockId> {
+        let block = self
+            .tx
+            .lock()
+            .unwrap()
+            .append(self.current_block.filename.clone())?;
+        self.tx.lock().unwrap().pin(&block)?;
+        self.format(&block, flag)?;
+        self.tx.lock().unwrap().unpin(&block);
+        Ok(block)
+    }
+
+    /// format はブロックのヘッダを書き込み、すべてのスロットを初期値で埋める
+    pub fn format(&self, block: &BlockId, flag: i32) -> Result<()> {
+        let mut tx = self.tx.lock().unwrap();
+        tx.set_int(block, FLAG_OFFSET, flag, false)?;
+        tx.set_int(block, NUM_RECORDS_OFFSET, 0, false)?;
+        tx.set_int(block, NEXT_OFFSET, NO_BLOCK, false)?;
+        let record_size = self.layout.slot_size;
+        let mut pos = HEADER_SIZE;
+        while pos + record_size <= tx.block_size() {
+            for field_name in &self.layout.schema.fields {
+                let offset = pos + self.field_offset(field_name)?;
+                match self.field_type(field_name)? {
+                    FieldTypes::Integer => tx.set_int(block, offset, 0, false)?,
+                    _ => tx.set_string(block, offset, "".into(), false)?,
+                }
+            }
+            pos += record_size;
+        }
+        Ok(())
+    }
+
+    // ディレクトリのレコードで使う
+
+    pub fn child_num(&self, slot: i32) -> Result<i32> {
+        self.get_int(slot, "block")
+    }
+
+    pub fn insert_dir(&mut self, slot: i32, value: Constant, block_num: i32) -> Result<()> {
+        self.insert(slot)?;
+        self.set_val(slot, "dataval", value)?;
+        self.set_int(slot, "block", block_num)
+    }
+
+    // リーフのレコードで使う
+
+    pub fn data_rid(&self, slot: i32) -> Result<RID> {
+        Ok(RID::new(
+            self.get_int(slot, "block")?,
+            self.get_int(slot, "id")?,
+        ))
+    }
+
+    pub fn insert_leaf(&mut self, slot: i32, value: Constant, rid: RID) -> Result<()> {
+        self.insert(slot)?;
+        self.set_val(slot, "dataval", value)?;
+        self.set_int(slot, "block", rid.block_num)?;
+        self.set_int(slot, "id", rid.slot)
+    }
+
+    /// delete は指定したスロットのレコードを削除して、後ろのレコードを前に詰める
+    pub fn delete(&mut self, slot: i32) -> Result<()> {
+        for i in slot + 1..self.num_records()? {
+            self.copy_record(i, i - 1)?;
+        }
+        self.set_num_records(self.num_records()? - 1)
+    }
+
+    pub fn num_records(&self) -> Result<i32> {
+        self.tx
+            .lock()
+            .unwrap()
+            .get_int(&self.current_block, NUM_RECORDS_OFFSET)
+    }
+
+    fn get_int(&self, slot: i32, field_name: &str) -> Result<i32> {
+        let pos = self.field_pos(slot, field_name)?;
+        self.tx.lock().unwrap().get_int(&self.current_block, pos)
+    }
+
+    fn get_string(&self, slot: i32, field_name: &str) -> Result<String> {
+        let pos = self.field_pos(slot, field_name)?;
+        self.tx.lock().unwrap().get_string(&self.current_block, pos)
+    }
+
+    fn get_val(&self, slot: i32, field_name: &str) -> Result<Constant> {
+        match self.field_type(field_name)? {
+            FieldTypes::Integer => Ok(Constant::Int(self.get_int(slot, field_name)?)),
+            FieldTypes::Varchar => Ok(Constant::String(self.get_string(slot, field_name)?)),
+            r#type => bail!("unsupported index field type: {:?}", r#type),
+        }
+    }
+
+    fn set_int(&self, slot: i32, field_name: &str, value: i32) -> Result<()> {
+        let pos = self.field_pos(slot, field_name)?;
+        self.tx
+            .lock()
+            .unwrap()
+            .set_int(&self.current_block, pos, value, true)
+    }
+
+    fn set_string(&self, slot: i32, field_name: &str, value: String) -> Result<()> {
+        let pos = self.field_pos(slot, field_name)?;
+        self.tx
+            .lock()
+            .unwrap()
+            .set_string(&self.current_block, pos, value, true)
+    }
+
+    fn set_val(&self, slot: i32, field_name: &str, value: Constant) -> Result<()> {
+        match value {
+            Constant::Int(value) => self.set_int(slot, field_name, value),
+            Constant::String(value) => self.set_string(slot, field_name, value),
+            Constant::Null => bail!("cannot store NULL in an index"),
+            Constant::Bool(_) => bail!("cannot store boolean in an index"),
+            Constant::Date(_) => bail!("cannot store date in an index"),
+        }
+    }
+
+    fn set_num_records(&self, value: i32) -> Result<()> {
+        self.tx
+            .lock()
+            .unwrap()
+            .set_int(&self.current_block, NUM_RECORDS_OFFSET, value, true)
+    }
+
+    /// insert は指定したスロットを空けるために、後ろのレコードを1つずつずらす
+    fn insert(&mut self, slot: i32) -> Result<()> {
+        let mut i = self.num_records()?;
+        while i > slot {
+            self.copy_record(i - 1, i)?;
+            i -= 1;
+        }
+        self.set_num_records(self.num_records()? + 1)
+    }
+
+    fn copy_record(&self, from: i32, to: i32) -> Result<()> {
+        for field_name in &self.layout.schema.fields {
+            self.set_val(to, field_name, self.get_val(from, field_name)?)?;
+        }
+        Ok(())
+    }
+
+    /// transfer_records は slot 以降のレコードを dest に移す
+    fn transfer_records(&mut self, slot: i32, dest: &mut BTreePage) -> Result<()> {
+        let mut dest_slot = 0;
+        while slot < self.num_records()? {
+            dest.insert(dest_slot)?;
+            for field_name in &self.layout.schema.fields {
+                dest.set_val(dest_slot, field_name, self.get_val(slot, field_name)?)?;
+            }
+            self.delete(slot)?;
+            dest_slot += 1;
+        }
+        Ok(())
+    }
+
+    fn field_type(&self, field_name: &str) -> Result<FieldTypes> {
+        self.layout
+            .schema
+            .r#type(field_name)
+            .ok_or_else(|| anyhow!("field type not found"))
+    }
+
+    fn field_offset(&self, field_name: &str) -> Result<i32> {
+        self.layout
+            .offset(field_name)
+            .ok_or_else(|| anyhow!("field offset not found"))
+    }
+
+    fn field_pos(&self, slot: i32, field_name: &str) -> Result<i32> {
+        Ok(self.slot_pos(slot) + self.field_offset(field_name)?)
+    }
+
+    fn slot_pos(&self, slot: i32) -> i32 {
+        HEADER_SIZE + slot * self.layout.slot_size
+    }
+}
@@ -0,0 +1,18 @@
+use crate::query::constant::Constant;
+
+/// DirEntry はディレクトリのレコードを表す
+/// 子ブロックの先頭のキーと、そのブロック番号を持つ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub data_val: Constant,
+    pub block_num: i32,
+}
+
+impl DirEntry {
+    pub fn new(data_val: Constant, block_num: i32) -> Self {
+        Self {
+            data_val,
+            block_num,
+        }
+    }
+}
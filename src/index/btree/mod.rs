@@ -0,0 +1,611 @@
+use self::{btree_dir::BTreeDir, btree_leaf::BTreeLeaf, btree_page::BTreePage};
+use super::Index;
+use crate::{
+    file::block::BlockId,
+    query::{constant::Constant, scan::Scan as _},
+    record::{
+        layout::Layout,
+        rid::RID,
+        schema::{FieldTypes, Schema},
+        table_scan::TableScan,
+    },
+    tx::transaction::Transaction,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
+
+pub mod btree_dir;
+pub mod btree_leaf;
+pub mod btree_page;
+pub mod dir_entry;
+
+/// PrefixKey は文字列のキーの先頭 length バイトだけを索引に格納するための設定
+/// 長い varchar でも索引のレコードを小さく保てる
+/// 索引からは先頭が一致する候補しか分からないので、テーブルのレコードを読んで値全体を確かめる
+#[derive(Debug, Clone)]
+pub struct PrefixKey {
+    pub length: usize,
+    pub table_name: String,
+    pub table_layout: Arc<Layout>,
+    pub field_name: String,
+}
+
+impl PrefixKey {
+    /// truncate はキーを先頭 length バイトに切り詰める
+    /// 文字の途中で切れないように、length 以下の文字の境界で切る
+    pub fn truncate(&self, key: Constant) -> Constant {
+        match key {
+            Constant::String(s) if s.len() > self.length => {
+                let mut end = self.length;
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+                Constant::String(s[..end].to_string())
+            }
+            key => key,
+        }
+    }
+}
+
+/// SearchRange は索引の検索条件を表す
+enum SearchRange {
+    Equal(Constant),
+    Range(Bound<Constant>, Bound<Constant>),
+}
+
+impl SearchRange {
+    fn contains(&self, value: &Constant) -> bool {
+        match self {
+            SearchRange::Equal(key) => value == key,
+            SearchRange::Range(low, high) => {
+                let above_low = match low {
+                    Bound::Included(low) => value >= low,
+                    Bound::Excluded(low) => value > low,
+                    Bound::Unbounded => true,
+                };
+                let below_high = match high {
+                    Bound::Included(high) => value <= high,
+                    Bound::Excluded(high) => value < high,
+                    Bound::Unbounded => true,
+                };
+                above_low && below_high
+            }
+        }
+    }
+}
+
+/// BTreeIndex は B-tree による索引
+/// ディレクトリ（`<index>.dir`）とリーフ（`<index>.leaf`）の2つのファイルで構成される
+/// 等価検索に加えて、before_first_range で範囲検索もできる
+pub struct BTreeIndex {
+    tx: Arc<Mutex<Transaction>>,
+    dir_layout: Arc<Layout>,
+    leaf_layout: Arc<Layout>,
+    leaf_table: String,
+    leaf: Option<BTreeLeaf>,
+    root_block: BlockId,
+    prefix: Option<PrefixKey>,
+    range: Option<SearchRange>,
+    table_scan: Option<TableScan>,
+}
+
+impl BTreeIndex {
+    /// new は索引を開く
+    /// ファイルがなければ、空のリーフと、そのリーフを指すルートのディレクトリを作る
+    pub fn new(
+        tx: Arc<Mutex<Transaction>>,
+        index_name: &str,
+        leaf_layout: Arc<Layout>,
+    ) -> Result<Self> {
+        let leaf_table = format!("{}.leaf", index_name);
+        let mut dir_schema = Schema::default();
+        dir_schema.add("block", &leaf_layout.schema)?;
+        dir_schema.add("dataval", &leaf_layout.schema)?;
+        let dir_table = format!("{}.dir", index_name);
+        let dir_layout = Arc::new(Layout::try_from_schema(Arc::new(dir_schema))?);
+        let root_block = BlockId::new(dir_table, 0);
+
+        let index = Self {
+            tx,
+            dir_layout,
+            leaf_layout,
+            leaf_table,
+            leaf: None,
+            root_block,
+            prefix: None,
+            range: None,
+            table_scan: None,
+        };
+        index.init_files()?;
+        Ok(index)
+    }
+
+    /// init_files はファイルがなければ、空のリーフと、そのリーフを指すルートのディレクトリを作る
+    fn init_files(&self) -> Result<()> {
+        let tx = &self.tx;
+        if tx.lock().unwrap().size(self.leaf_table.clone())? == 0 {
+            let block = tx.lock().unwrap().append(self.leaf_table.clone())?;
+            let mut node = BTreePage::new(tx.clone(), block.clone(), self.leaf_layout.clone())?;
+            node.format(&block, btree_page::NO_BLOCK)?;
+            node.close();
+        }
+
+        let dir_table = self.root_block.filename.clone();
+        if tx.lock().unwrap().size(dir_table.clone())? == 0 {
+            tx.lock().unwrap().append(dir_table)?;
+            let mut node =
+                BTreePage::new(tx.clone(), self.root_block.clone(), self.dir_layout.clone())?;
+            node.format(&self.root_block, 0)?;
+            // ルートには最小のキーで最初のリーフを指すレコードを入れておく
+            let min_val = Self::min_value(&self.leaf_layout)?;
+            node.insert_dir(0, min_val, 0)?;
+            node.close();
+        }
+        Ok(())
+    }
+
+    /// with_prefix は文字列のキーの先頭だけを格納する索引を開く
+    /// leaf_layout の dataval は prefix.length の長さの varchar にしておく
+    pub fn with_prefix(
+        tx: Arc<Mutex<Transaction>>,
+        index_name: &str,
+        leaf_layout: Arc<Layout>,
+        prefix: PrefixKey,
+    ) -> Result<Self> {
+        let mut index = Self::new(tx, index_name, leaf_layout)?;
+        index.prefix = Some(prefix);
+        Ok(index)
+    }
+
+    /// before_first_range は low から high までのキーを持つレコードの直前に位置づける
+    pub fn before_first_range(
+        &mut self,
+        low: Bound<Constant>,
+        high: Bound<Constant>,
+    ) -> Result<()> {
+        let start = match &low {
+            Bound::Included(key) | Bound::Excluded(key) => self.index_key(key.clone()),
+            Bound::Unbounded => Self::min_value(&self.leaf_layout)?,
+        };
+        self.open_leaf(start)?;
+        self.range = Some(SearchRange::Range(low, high));
+        Ok(())
+    }
+
+    /// search_cost は索引を検索するときにアクセスするブロック数の見積もりを返す
+    pub fn search_cost(num_blocks: u64, rpb: u64) -> u64 {
+        if num_blocks <= 1 || rpb <= 1 {
+            return 1 + num_blocks;
+        }
+        1 + ((num_blocks as f64).ln() / (rpb as f64).ln()) as u64
+    }
+
+    /// index_key は索引に格納するキーを返す
+    fn index_key(&self, key: Constant) -> Constant {
+        match &self.prefix {
+            Some(prefix) => prefix.truncate(key),
+            None => key,
+        }
+    }
+
+    fn min_value(leaf_layout: &Layout) -> Result<Constant> {
+        match leaf_layout.schema.r#type("dataval") {
+            Some(FieldTypes::Integer) => Ok(Constant::Int(i32::MIN)),
+            Some(FieldTypes::Varchar) => Ok(Constant::String("".into())),
+            r#type => bail!("unsupported index field type: {:?}", r#type),
+        }
+    }
+
+    /// open_leaf は key を含むリーフを開く
+    fn open_leaf(&mut self, key: Constant) -> Result<()> {
+        self.close();
+        let mut root = BTreeDir::new(
+            self.tx.clone(),
+            self.root_block.clone(),
+            self.dir_layout.clone(),
+        )?;
+        let block_num = root.search(&key);
+        root.close();
+        let leaf_block = BlockId::new(self.leaf_table.clone(), block_num?);
+        self.leaf = Some(BTreeLeaf::new(
+            self.tx.clone(),
+            leaf_block,
+            self.leaf_layout.clone(),
+            key,
+        )?);
+        Ok(())
+    }
+
+    /// past_high は索引のキーが範囲の上限を超えたかどうかを返す
+    /// 先頭だけを格納している場合は、先頭が一致していれば値全体は上限以下かもしれないので超えたとはみなさない
+    fn past_high(&self, key: &Constant, high: &Bound<Constant>) -> bool {
+        match high {
+            Bound::Unbounded => false,
+            Bound::Included(high) | Bound::Excluded(high) if self.prefix.is_some() => {
+                *key > self.index_key(high.clone())
+            }
+            Bound::Included(high) => key > high,
+            Bound::Excluded(high) => key >= high,
+        }
+    }
+
+    /// matches は先頭だけを格納している場合に、テーブルのレコードを読んで値全体が条件を満たすか確かめる
+    fn matches(&mut self, rid: RID) -> Result<bool> {
+        let Some(prefix) = &self.prefix else {
+            return Ok(true);
+        };
+        if self.table_scan.is_none() {
+            self.table_scan = Some(TableScan::new(
+                self.tx.clone(),
+                prefix.table_name.clone(),
+                prefix.table_layout.clone(),
+            )?);
+        }
+        let field_name = prefix.field_name.clone();
+        let table_scan = self.table_scan.as_mut().unwrap();
+        table_scan.move_to_rid(rid);
+        let value = table_scan.get_value(&field_name)?;
+        let range = self.range.as_ref().ok_or(anyhow!("no search key"))?;
+        Ok(range.contains(&value))
+    }
+}
+
+impl Index for BTreeIndex {
+    fn before_first(&mut self, search_key: Constant) -> Result<()> {
+        let key = self.index_key(search_key.clone());
+        self.open_leaf(key)?;
+        self.range = Some(SearchRange::Equal(search_key));
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        loop {
+            let leaf = self.leaf.as_mut().ok_or(anyhow!("no leaf"))?;
+            let high = match self.range.as_ref().ok_or(anyhow!("no search key"))? {
+                SearchRange::Equal(_) => {
+                    if !leaf.next()? {
+                        return Ok(false);
+                    }
+                    None
+                }
+                SearchRange::Range(_, high) => {
+                    if !leaf.next_in_order()? {
+                        return Ok(false);
+                    }
+                    Some(high.clone())
+                }
+            };
+
+            let leaf = self.leaf.as_ref().ok_or(anyhow!("no leaf"))?;
+            let key = leaf.data_val()?;
+            let rid = leaf.data_rid()?;
+            if let Some(high) = high {
+                if self.past_high(&key, &high) {
+                    return Ok(false);
+                }
+                if self.prefix.is_none()
+                    && !self
+                        .range
+                        .as_ref()
+                        .is_some_and(|range| range.contains(&key))
+                {
+                    continue;
+                }
+            }
+            if self.matches(rid)? {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn get_data_rid(&mut self) -> Result<RID> {
+        self.leaf.as_ref().ok_or(anyhow!("no leaf"))?.data_rid()
+    }
+
+    /// insert は NULL のキーを格納しない。NULL はどの値とも等しくならないので、索引で検索されることはない
+    fn insert(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        if data_value.is_null() {
+            return Ok(());
+        }
+        let key = self.index_key(data_value);
+        self.before_first(key)?;
+        let leaf = self.leaf.as_mut().ok_or(anyhow!("no leaf"))?;
+        let entry = leaf.insert(data_rid);
+        leaf.close();
+        self.leaf = None;
+        let Some(entry) = entry? else {
+            return Ok(());
+        };
+
+        let mut root = BTreeDir::new(
+            self.tx.clone(),
+            self.root_block.clone(),
+            self.dir_layout.clone(),
+        )?;
+        let new_entry = root.insert(entry);
+        let result = match new_entry {
+            Ok(Some(new_entry)) => root.make_new_root(new_entry),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        root.close();
+        result
+    }
+
+    fn delete(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        if data_value.is_null() {
+            return Ok(());
+        }
+        let key = self.index_key(data_value);
+        self.before_first(key)?;
+        let leaf = self.leaf.as_mut().ok_or(anyhow!("no leaf"))?;
+        let result = leaf.delete(data_rid);
+        leaf.close();
+        self.leaf = None;
+        result
+    }
+
+    fn close(&mut self) {
+        if let Some(mut leaf) = self.leaf.take() {
+            leaf.close();
+        }
+        if let Some(mut table_scan) = self.table_scan.take() {
+            table_scan.close();
+        }
+    }
+
+    /// entries は最小のキーからリーフをたどって、すべてのエントリを返す
+    /// 先頭だけを格納している索引では、切り詰めたキーを返す
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>> {
+        self.open_leaf(Self::min_value(&self.leaf_layout)?)?;
+        let mut entries = vec![];
+        let leaf = self.leaf.as_mut().ok_or(anyhow!("no leaf"))?;
+        while leaf.next_in_order()? {
+            entries.push((leaf.data_val()?, leaf.data_rid()?));
+        }
+        self.close();
+        Ok(entries)
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        self.close();
+        {
+            let mut tx = self.tx.lock().unwrap();
+            tx.truncate_file(&self.leaf_table)?;
+            tx.truncate_file(&self.root_block.filename)?;
+        }
+        self.init_files()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::db::TinyDB;
+    use tempfile::tempdir;
+
+    fn leaf_layout(dataval: impl FnOnce(&mut Schema)) -> Arc<Layout> {
+        let mut schema = Schema::default();
+        schema.add_int_field("block");
+        schema.add_int_field("id");
+        dataval(&mut schema);
+        Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap())
+    }
+
+    fn collect_rids(index: &mut BTreeIndex) -> Result<Vec<RID>> {
+        let mut rids = vec![];
+        while index.next()? {
+            rids.push(index.get_data_rid()?);
+        }
+        Ok(rids)
+    }
+
+    #[test]
+    fn should_can_insert_search_and_delete() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_can_insert_search_and_delete");
+        let db = TinyDB::new(test_directory, 200, 8)?;
+        let tx = db.transaction()?;
+
+        let layout = leaf_layout(|schema| schema.add_int_field("dataval"));
+        let mut index = BTreeIndex::new(tx.clone(), "idx", layout)?;
+
+        // ブロックの分割とディレクトリの階層が増えるだけのキーを入れる
+        for n in 0..500 {
+            index.insert(Constant::Int(n % 100), RID::new(n, n % 7))?;
+        }
+        assert!(tx.lock().unwrap().size("idx.leaf".into())? > 1);
+
+        index.before_first(Constant::Int(42))?;
+        let rids = collect_rids(&mut index)?;
+        assert_eq!(rids.len(), 5);
+        assert!(rids.iter().all(|rid| rid.block_num % 100 == 42));
+
+        index.before_first(Constant::Int(1000))?;
+        assert!(!index.next()?);
+
+        index.delete(Constant::Int(42), RID::new(142, 142 % 7))?;
+        index.before_first(Constant::Int(42))?;
+        let rids = collect_rids(&mut index)?;
+        assert_eq!(rids.len(), 4);
+        assert!(!rids.contains(&RID::new(142, 142 % 7)));
+
+        index.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_truncate_and_restore_on_rollback() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_truncate_and_restore_on_rollback");
+        let db = TinyDB::new(test_directory, 200, 8)?;
+        let layout = leaf_layout(|schema| schema.add_int_field("dataval"));
+        let tx = db.transaction()?;
+        let mut index = BTreeIndex::new(tx.clone(), "idx", layout.clone())?;
+        for n in 0..300 {
+            index.insert(Constant::Int(n), RID::new(n, 0))?;
+        }
+        index.close();
+        tx.lock().unwrap().commit()?;
+
+        let tx = db.transaction()?;
+        let mut index = BTreeIndex::new(tx.clone(), "idx", layout.clone())?;
+        index.truncate()?;
+        assert_eq!(tx.lock().unwrap().size("idx.leaf".into())?, 1);
+        assert!(index.entries()?.is_empty());
+        // 空にした後も索引として使える
+        index.insert(Constant::Int(1), RID::new(9, 9))?;
+        index.before_first(Constant::Int(1))?;
+        assert_eq!(collect_rids(&mut index)?, vec![RID::new(9, 9)]);
+        index.close();
+        tx.lock().unwrap().rollback()?;
+
+        let tx = db.transaction()?;
+        let mut index = BTreeIndex::new(tx.clone(), "idx", layout)?;
+        assert_eq!(index.entries()?.len(), 300);
+        index.before_first(Constant::Int(1))?;
+        assert_eq!(collect_rids(&mut index)?, vec![RID::new(1, 0)]);
+        index.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_many_duplicate_keys_in_overflow_blocks() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_keep_many_duplicate_keys_in_overflow_blocks");
+        let db = TinyDB::new(test_directory, 200, 8)?;
+        let tx = db.transaction()?;
+
+        let layout = leaf_layout(|schema| schema.add_int_field("dataval"));
+        let mut index = BTreeIndex::new(tx.clone(), "idx", layout)?;
+        for n in 0..100 {
+            index.insert(Constant::Int(7), RID::new(n, 0))?;
+        }
+        index.insert(Constant::Int(3), RID::new(100, 0))?;
+        index.insert(Constant::Int(9), RID::new(101, 0))?;
+
+        index.before_first(Constant::Int(7))?;
+        assert_eq!(collect_rids(&mut index)?.len(), 100);
+
+        index.before_first_range(Bound::Unbounded, Bound::Unbounded)?;
+        assert_eq!(collect_rids(&mut index)?.len(), 102);
+
+        index.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_can_search_range() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_can_search_range");
+        let db = TinyDB::new(test_directory, 200, 8)?;
+        let tx = db.transaction()?;
+
+        let layout = leaf_layout(|schema| schema.add_int_field("dataval"));
+        let mut index = BTreeIndex::new(tx.clone(), "idx", layout)?;
+        // 順番をばらばらにして入れる
+        for n in 0..300 {
+            let key = (n * 37) % 300;
+            index.insert(Constant::Int(key), RID::new(key, 0))?;
+        }
+
+        index.before_first_range(
+            Bound::Included(Constant::Int(100)),
+            Bound::Excluded(Constant::Int(150)),
+        )?;
+        let keys: Vec<i32> = collect_rids(&mut index)?
+            .iter()
+            .map(|rid| rid.block_num)
+            .collect();
+        assert_eq!(keys, (100..150).collect::<Vec<_>>());
+
+        index.before_first_range(Bound::Excluded(Constant::Int(290)), Bound::Unbounded)?;
+        assert_eq!(collect_rids(&mut index)?.len(), 9);
+
+        index.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_verify_full_key_with_prefix_index() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_verify_full_key_with_prefix_index");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+
+        let mut schema = Schema::default();
+        schema.add_int_field("id");
+        schema.add_string_field("url", 200);
+        let table_layout = Arc::new(Layout::try_from_schema(Arc::new(schema))?);
+
+        // 先頭 8 バイトだけを索引に格納する
+        let layout = leaf_layout(|schema| schema.add_string_field("dataval", 8));
+        let prefix = PrefixKey {
+            length: 8,
+            table_name: "pages".into(),
+            table_layout: table_layout.clone(),
+            field_name: "url".into(),
+        };
+        let mut index = BTreeIndex::with_prefix(tx.clone(), "pages_url", layout, prefix)?;
+
+        let mut table_scan = TableScan::new(tx.clone(), "pages", table_layout)?;
+        let urls: Vec<String> = (0..40)
+            .map(|n| format!("https://example.com/{:02}", n))
+            .collect();
+        for (n, url) in urls.iter().enumerate() {
+            table_scan.insert()?;
+            table_scan.set_int("id", n as i32)?;
+            table_scan.set_string("url", url)?;
+            let rid = table_scan.get_rid()?;
+            index.insert(Constant::String(url.clone()), rid)?;
+        }
+        table_scan.close();
+
+        // すべて同じ先頭を持つが、値全体で絞り込まれる
+        index.before_first(Constant::String(urls[17].clone()))?;
+        assert_eq!(collect_rids(&mut index)?.len(), 1);
+
+        index.before_first(Constant::String("https://example.com/99".into()))?;
+        assert!(!index.next()?);
+
+        index.before_first_range(
+            Bound::Included(Constant::String(urls[10].clone())),
+            Bound::Included(Constant::String(urls[19].clone())),
+        )?;
+        assert_eq!(collect_rids(&mut index)?.len(), 10);
+
+        index.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_truncate_prefix_on_char_boundary() {
+        let prefix = PrefixKey {
+            length: 4,
+            table_name: "t".into(),
+            table_layout: Arc::new(Layout::default()),
+            field_name: "f".into(),
+        };
+        assert_eq!(
+            prefix.truncate(Constant::String("abcdef".into())),
+            Constant::String("abcd".into())
+        );
+        // "あ" は 3 バイト
+        assert_eq!(
+            prefix.truncate(Constant::String("aあい".into())),
+            Constant::String("aあ".into())
+        );
+        assert_eq!(prefix.truncate(Constant::Int(12345)), Constant::Int(12345));
+    }
+}
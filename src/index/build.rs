@@ -0,0 +1,268 @@
+use super::Index;
+use crate::{
+    file::block::BlockId,
+    query::constant::Constant,
+    record::{layout::Layout, record_page::RecordPage, rid::RID, schema::FieldTypes},
+    tx::transaction::Transaction,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    ops::Range,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// MIN_BLOCKS_PER_WORKER より少ないブロックしか割り当てられない場合はスレッドを増やさない
+const MIN_BLOCKS_PER_WORKER: u64 = 4;
+
+/// Run はキーの昇順に並べた索引のレコードの列
+type Run = Vec<(Constant, RID)>;
+
+/// IndexBuilder は既存のテーブルのレコードから索引を作る
+///
+/// テーブルのブロックを連続した範囲に分けて、ワーカースレッドごとに1つの範囲を読み込み、
+/// キーの昇順に並べた run を作る。すべての run をマージして、キーの昇順に索引へ挿入する
+/// ワーカーはすべて同じトランザクションを共有するので、作った索引はそのトランザクションと一緒にコミットやロールバックされる
+pub struct IndexBuilder {
+    tx: Arc<Mutex<Transaction>>,
+    table_name: String,
+    layout: Arc<Layout>,
+    field_name: String,
+    workers: usize,
+}
+
+impl IndexBuilder {
+    /// new はマシンの CPU 数だけワーカーを使う IndexBuilder を返す
+    pub fn new(
+        tx: Arc<Mutex<Transaction>>,
+        table_name: impl Into<String>,
+        layout: Arc<Layout>,
+        field_name: impl Into<String>,
+    ) -> Self {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            tx,
+            table_name: table_name.into(),
+            layout,
+            field_name: field_name.into(),
+            workers,
+        }
+    }
+
+    /// workers は使うワーカーの数を変更する
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// build はテーブルのすべてのレコードを索引に挿入して、挿入したレコード数を返す
+    pub fn build(&self, index: &mut dyn Index) -> Result<i32> {
+        let runs = self.sorted_runs()?;
+        let mut count = 0;
+        for (key, rid) in merge_runs(runs) {
+            index.insert(key, rid)?;
+            count += 1;
+        }
+        index.close();
+        Ok(count)
+    }
+
+    /// sorted_runs はブロックの範囲ごとに run を作る
+    /// ワーカーが1つで足りる場合はスレッドを作らずに読み込む
+    fn sorted_runs(&self) -> Result<Vec<Run>> {
+        match self.layout.schema.r#type(&self.field_name) {
+            Some(FieldTypes::Integer) | Some(FieldTypes::Varchar) => {}
+            Some(_) => bail!("cannot build index on field: {}", self.field_name),
+            None => bail!("field not found: {}", self.field_name),
+        }
+
+        let filename = format!("{}.tbl", self.table_name);
+        let num_blocks = self.tx.lock().unwrap().size(filename.clone())?;
+        let partitions = partition(num_blocks, self.workers);
+        if partitions.len() <= 1 {
+            return partitions
+                .into_iter()
+                .map(|blocks| self.sorted_run(&filename, blocks))
+                .collect();
+        }
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = partitions
+                .into_iter()
+                .map(|blocks| scope.spawn(|| self.sorted_run(&filename, blocks)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .map_err(|_| anyhow!("index build worker panicked"))?
+                })
+                .collect()
+        })
+    }
+
+    /// sorted_run は指定した範囲のブロックにあるレコードを読み込んで、キーの昇順に並べる
+    fn sorted_run(&self, filename: &str, blocks: Range<i32>) -> Result<Run> {
+        let mut run = vec![];
+        for block_num in blocks {
+            let block = BlockId::new(filename.to_string(), block_num);
+            let record_page = RecordPage::new(self.tx.clone(), block.clone(), self.layout.clone())?;
+            let result = self.read_block(&record_page, block_num, &mut run);
+            self.tx.lock().unwrap().unpin(&block);
+            result?;
+        }
+        run.sort();
+        Ok(run)
+    }
+
+    /// read_block はブロックのレコードのキーを run に追加する。NULL のレコードは追加しない
+    /// キーはフィールドの照合順序のキーに変換するので、run は索引に格納する順に並ぶ
+    fn read_block(&self, record_page: &RecordPage, block_num: i32, run: &mut Run) -> Result<()> {
+        let collation = self.layout.schema.collation(&self.field_name);
+        let mut slot = record_page.next_after(-1)?;
+        while slot >= 0 {
+            // NULL は索引に格納しない
+            if record_page.is_null(slot, &self.field_name)? {
+                slot = record_page.next_after(slot)?;
+                continue;
+            }
+            let key = match self.layout.schema.r#type(&self.field_name) {
+                Some(FieldTypes::Integer) => {
+                    Constant::Int(record_page.get_int(slot, &self.field_name)?)
+                }
+                _ => Constant::String(record_page.get_string(slot, &self.field_name)?),
+            };
+            run.push((collation.key(&key), RID::new(block_num, slot)));
+            slot = record_page.next_after(slot)?;
+        }
+        Ok(())
+    }
+}
+
+/// partition は num_blocks 個のブロックを最大 workers 個の連続した範囲に分ける
+/// 1つの範囲が MIN_BLOCKS_PER_WORKER より小さくならないように、範囲の数を減らす
+fn partition(num_blocks: u64, workers: usize) -> Vec<Range<i32>> {
+    if num_blocks == 0 {
+        return vec![];
+    }
+    let count = (workers as u64)
+        .min(num_blocks / MIN_BLOCKS_PER_WORKER)
+        .max(1);
+    let size = num_blocks / count;
+    let remainder = num_blocks % count;
+
+    let mut partitions = vec![];
+    let mut start = 0;
+    for i in 0..count {
+        let len = size + u64::from(i < remainder);
+        partitions.push(start as i32..(start + len) as i32);
+        start += len;
+    }
+    partitions
+}
+
+/// merge_runs は複数の run をキーの昇順に1つにまとめる
+fn merge_runs(runs: Vec<Run>) -> impl Iterator<Item = (Constant, RID)> {
+    let mut heap = BinaryHeap::new();
+    let mut iters: Vec<_> = runs.into_iter().map(|run| run.into_iter()).collect();
+    for (i, iter) in iters.iter_mut().enumerate() {
+        if let Some(entry) = iter.next() {
+            heap.push(Reverse((entry, i)));
+        }
+    }
+
+    std::iter::from_fn(move || {
+        let Reverse((entry, i)) = heap.pop()?;
+        if let Some(next) = iters[i].next() {
+            heap.push(Reverse((next, i)));
+        }
+        Some(entry)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        index::btree::BTreeIndex,
+        query::scan::Scan as _,
+        record::{schema::Schema, table_scan::TableScan},
+        server::db::TinyDB,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_partition_blocks() {
+        assert!(partition(0, 4).is_empty());
+        assert_eq!(partition(3, 4), vec![0..3]);
+        assert_eq!(partition(10, 2), vec![0..5, 5..10]);
+        assert_eq!(partition(10, 3), vec![0..5, 5..10]);
+        assert_eq!(partition(14, 3), vec![0..5, 5..10, 10..14]);
+    }
+
+    #[test]
+    fn should_merge_runs_in_order() {
+        let run = |keys: &[i32]| -> Run {
+            keys.iter()
+                .map(|&key| (Constant::Int(key), RID::new(key, 0)))
+                .collect()
+        };
+        let merged: Vec<i32> = merge_runs(vec![run(&[1, 4, 7]), run(&[]), run(&[2, 3, 8, 9])])
+            .map(|(_, rid)| rid.block_num)
+            .collect();
+        assert_eq!(merged, vec![1, 2, 3, 4, 7, 8, 9]);
+    }
+
+    #[test]
+    fn should_build_index_with_many_workers() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_build_index_with_many_workers");
+        let db = TinyDB::new(test_directory, 400, 16)?;
+        let tx = db.transaction()?;
+
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        schema.add_string_field("B", 8);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema))?);
+
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        for n in 0..1000 {
+            ts.insert()?;
+            ts.set_int("A", n % 50)?;
+            ts.set_string("B", &format!("rec{}", n))?;
+        }
+        ts.close();
+        assert!(tx.lock().unwrap().size("T.tbl".into())? >= 4 * MIN_BLOCKS_PER_WORKER);
+
+        let mut index_schema = Schema::default();
+        index_schema.add_int_field("block");
+        index_schema.add_int_field("id");
+        index_schema.add_int_field("dataval");
+        let index_layout = Arc::new(Layout::try_from_schema(Arc::new(index_schema))?);
+        let mut index = BTreeIndex::new(tx.clone(), "idx", index_layout)?;
+
+        let count = IndexBuilder::new(tx.clone(), "T", layout.clone(), "A")
+            .workers(4)
+            .build(&mut index)?;
+        assert_eq!(count, 1000);
+
+        index.before_first(Constant::Int(7))?;
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        let mut found = 0;
+        while index.next()? {
+            ts.move_to_rid(index.get_data_rid()?);
+            assert_eq!(ts.get_int("A")?, 7);
+            found += 1;
+        }
+        assert_eq!(found, 20);
+
+        index.close();
+        ts.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use super::Index;
+use crate::{query::constant::Constant, record::collation::Collation, record::rid::RID};
+use anyhow::Result;
+
+/// CollatedIndex は照合順序を指定したフィールドの索引
+///
+/// キーを照合順序のキーに変換してから元の索引に渡すので、照合順序で等しい値は同じキーとして格納され、
+/// B-tree の索引のキーは照合順序の順に並ぶ
+/// entries が返すのは変換したキー
+pub struct CollatedIndex {
+    index: Box<dyn Index>,
+    collation: Collation,
+}
+
+impl CollatedIndex {
+    pub fn new(index: Box<dyn Index>, collation: Collation) -> Self {
+        Self { index, collation }
+    }
+}
+
+impl Index for CollatedIndex {
+    fn before_first(&mut self, search_key: Constant) -> Result<()> {
+        self.index.before_first(self.collation.key(&search_key))
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        self.index.next()
+    }
+
+    fn get_data_rid(&mut self) -> Result<RID> {
+        self.index.get_data_rid()
+    }
+
+    fn delete(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        self.index.delete(self.collation.key(&data_value), data_rid)
+    }
+
+    fn insert(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        self.index.insert(self.collation.key(&data_value), data_rid)
+    }
+
+    fn close(&mut self) {
+        self.index.close()
+    }
+
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>> {
+        self.index.entries()
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        self.index.truncate()
+    }
+}
@@ -1,4 +1,4 @@
-use super::Index;
+use super::{Index, INDEX_FILE_PREFIX};
 use crate::{
     query::{constant::Constant, scan::Scan as _},
     record::{layout::Layout, rid::RID, table_scan::TableScan},
@@ -7,7 +7,15 @@ use crate::{
 use anyhow::{anyhow, Result};
 use std::sync::{Arc, Mutex};
 
-const NUM_BUCKETS: u64 = 100;
+pub const NUM_BUCKETS: u64 = 100;
+
+/// bucket_table はバケットのレコードを格納するテーブルの名前を返す
+///
+/// テーブルと重ならないように INDEX_FILE_PREFIX を付け、索引の名前とバケットの番号は `.` で区切る
+/// バケットのテーブルはカタログに登録しないので、名前が MAX_NAME より長くなってもよい
+pub fn bucket_table(index_name: &str, bucket: u64) -> String {
+    format!("{}{}.{}", INDEX_FILE_PREFIX, index_name, bucket)
+}
 
 pub struct HashIndex {
     tx: Arc<Mutex<Transaction>>,
@@ -40,7 +48,7 @@ impl Index for HashIndex {
         self.search_key = Some(search_key);
 
         let bucket = hash_code % NUM_BUCKETS;
-        let table_name = format!("{}{}", self.index_name, bucket);
+        let table_name = bucket_table(&self.index_name, bucket);
 
         self.table_scan = Some(TableScan::new(
             self.tx.clone(),
@@ -74,17 +82,24 @@ impl Index for HashIndex {
         Ok(RID::new(block_num, id))
     }
 
+    /// insert は NULL のキーを格納しない。NULL はどの値とも等しくならないので、索引で検索されることはない
     fn insert(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        if data_value.is_null() {
+            return Ok(());
+        }
         self.before_first(data_value.clone())?;
         let table_scan = self.table_scan.as_mut().ok_or(anyhow!("no table_scan"))?;
         table_scan.insert()?;
         table_scan.set_int("block", data_rid.block_num)?;
         table_scan.set_int("id", data_rid.slot)?;
         table_scan.set_value("dataval", data_value)?;
-        todo!()
+        Ok(())
     }
 
     fn delete(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        if data_value.is_null() {
+            return Ok(());
+        }
         self.before_first(data_value)?;
         while self.next()? {
             if self.get_data_rid()? == data_rid {
@@ -103,4 +118,44 @@ impl Index for HashIndex {
             table_scan.close()
         }
     }
+
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>> {
+        self.close();
+        let mut entries = vec![];
+        for bucket in 0..NUM_BUCKETS {
+            let table_name = bucket_table(&self.index_name, bucket);
+            if !self
+                .tx
+                .lock()
+                .unwrap()
+                .file_exists(&format!("{}.tbl", table_name))
+            {
+                continue;
+            }
+            let mut table_scan = TableScan::new(self.tx.clone(), table_name, self.layout.clone())?;
+            while table_scan.next()? {
+                let rid = RID::new(table_scan.get_int("block")?, table_scan.get_int("id")?);
+                entries.push((table_scan.get_value("dataval")?, rid));
+            }
+            table_scan.close();
+        }
+        Ok(entries)
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        self.close();
+        for bucket in 0..NUM_BUCKETS {
+            let table_name = bucket_table(&self.index_name, bucket);
+            if !self
+                .tx
+                .lock()
+                .unwrap()
+                .file_exists(&format!("{}.tbl", table_name))
+            {
+                continue;
+            }
+            TableScan::new(self.tx.clone(), table_name, self.layout.clone())?.truncate()?;
+        }
+        Ok(())
+    }
 }
@@ -1,7 +1,16 @@
 use crate::{query::constant::Constant, record::rid::RID};
-use anyhow::Result;
+use anyhow::{bail, Result};
+use std::{fmt::Display, str::FromStr};
 
+pub mod btree;
+pub mod build;
+pub mod collated;
 pub mod hash;
+pub mod unique;
+
+/// INDEX_FILE_PREFIX は索引がテーブルとして作るファイルの名前に付ける接頭辞
+/// ユーザーのテーブルのファイルと重ならないように、この接頭辞で始まる名前のテーブルは作れない
+pub const INDEX_FILE_PREFIX: &str = "$";
 
 pub trait Index {
     fn before_first(&mut self, search_key: Constant) -> Result<()>;
@@ -10,4 +19,63 @@ pub trait Index {
     fn delete(&mut self, data_value: Constant, data_rid: RID) -> Result<()>;
     fn insert(&mut self, data_value: Constant, data_rid: RID) -> Result<()>;
     fn close(&mut self);
+    /// entries は索引に格納されているすべてのキーと RID を返す
+    /// 索引とテーブルの整合性を確かめるときに使う
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>>;
+    /// truncate は索引のすべての項目を削除する
+    /// 項目を1つずつ削除する代わりに、索引のファイルを空にする
+    fn truncate(&mut self) -> Result<()>;
+}
+
+/// IndexType は索引の種類
+/// 索引のカタログには Display の文字列で格納する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexType {
+    #[default]
+    Hash,
+    BTree,
+}
+
+impl Display for IndexType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexType::Hash => write!(f, "hash"),
+            IndexType::BTree => write!(f, "btree"),
+        }
+    }
+}
+
+impl FromStr for IndexType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "hash" => Ok(IndexType::Hash),
+            "btree" => Ok(IndexType::BTree),
+            _ => bail!("unknown index type: {}", s),
+        }
+    }
+}
+
+/// IndexOptions は索引を作るときに指定するオプション
+/// prefix_length は B-tree の索引で文字列の先頭何バイトをキーにするかを表す
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexOptions {
+    pub index_type: IndexType,
+    pub unique: bool,
+    pub prefix_length: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_can_convert_index_type() -> Result<()> {
+        assert_eq!("btree".parse::<IndexType>()?, IndexType::BTree);
+        assert_eq!("HASH".parse::<IndexType>()?, IndexType::Hash);
+        assert_eq!(IndexType::BTree.to_string(), "btree");
+        assert!("bitmap".parse::<IndexType>().is_err());
+        Ok(())
+    }
 }
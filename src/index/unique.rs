@@ -0,0 +1,67 @@
+use super::Index;
+use crate::{query::constant::Constant, record::rid::RID};
+use anyhow::{bail, Result};
+
+/// UniqueIndex は一意な索引
+///
+/// キーを挿入する前に同じキーの項目を探し、別のレコードの項目があればエラーを返す
+/// NULL はどの値とも等しくならないので、NULL のキーは重複とみなさない
+pub struct UniqueIndex {
+    index: Box<dyn Index>,
+    index_name: String,
+}
+
+impl UniqueIndex {
+    pub fn new(index: Box<dyn Index>, index_name: impl Into<String>) -> Self {
+        Self {
+            index,
+            index_name: index_name.into(),
+        }
+    }
+}
+
+impl Index for UniqueIndex {
+    fn before_first(&mut self, search_key: Constant) -> Result<()> {
+        self.index.before_first(search_key)
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        self.index.next()
+    }
+
+    fn get_data_rid(&mut self) -> Result<RID> {
+        self.index.get_data_rid()
+    }
+
+    fn delete(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        self.index.delete(data_value, data_rid)
+    }
+
+    fn insert(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        if !data_value.is_null() {
+            self.index.before_first(data_value.clone())?;
+            while self.index.next()? {
+                if self.index.get_data_rid()? != data_rid {
+                    bail!(
+                        "duplicate key {} in unique index {}",
+                        data_value,
+                        self.index_name
+                    );
+                }
+            }
+        }
+        self.index.insert(data_value, data_rid)
+    }
+
+    fn close(&mut self) {
+        self.index.close()
+    }
+
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>> {
+        self.index.entries()
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        self.index.truncate()
+    }
+}
@@ -1,19 +1,40 @@
 use std::mem::size_of;
 
-pub mod buffer;
-pub mod file;
-pub mod index;
-pub mod log;
-pub mod macros;
-pub mod metadata;
-pub mod parse;
-pub mod plan;
-pub mod query;
-pub mod record;
-pub mod server;
-pub mod tx;
+/// internal_mod は internals を有効にした場合だけ公開するモジュールを宣言する
+///
+/// internals を有効にしない場合、これらのモジュールの公開関数には crate の中で使わないものが残るので、
+/// そのモジュールに限って未使用の警告を抑える
+macro_rules! internal_mod {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[cfg(feature = "internals")]
+            pub mod $name;
+            #[cfg(not(feature = "internals"))]
+            #[allow(dead_code, unused_imports)]
+            pub(crate) mod $name;
+        )*
+    };
+}
 
-const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
-const I32_SIZE: usize = size_of::<i32>();
+mod macros;
+
+internal_mod!(
+    buffer, clock, database, file, index, log, metadata, parse, plan, query, record, server,
+    timeout, tools, tx
+);
 
-static LOG_FILE: &str = "tinydb.log";
+pub use clock::{Clock, SystemClock};
+pub use database::{
+    Database, IntoStatement, PreparedQuery, Row, Statement, Transaction, DEFAULT_BLOCK_SIZE,
+    DEFAULT_BUFFER_SIZE,
+};
+pub use file::date::Date;
+pub use query::{
+    constant::Constant,
+    result_set_metadata::{ColumnMetadata, ResultSetMetadata},
+};
+pub use record::schema::FieldTypes;
+pub use server::db::DbConfig;
+pub use timeout::{TimeoutKind, Timeouts, WaitTimeout};
+
+const I32_SIZE: usize = size_of::<i32>();
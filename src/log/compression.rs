@@ -0,0 +1,57 @@
+use anyhow::{bail, Result};
+
+/// COMPRESSED_FLAG is set on the record size in the record header
+/// when the record data is compressed. The record size never uses
+/// the sign bit, so uncompressed records keep the original format.
+pub const COMPRESSED_FLAG: i32 = i32::MIN;
+
+/// MIN_COMPRESS_SIZE is the smallest record worth compressing.
+/// Most log records (start, commit, setint...) are shorter than this.
+pub const MIN_COMPRESS_SIZE: usize = 64;
+
+/// compress returns the compressed record if it is smaller than the original one
+#[cfg(feature = "lz4")]
+pub fn compress(record: &[u8]) -> Option<Vec<u8>> {
+    if record.len() < MIN_COMPRESS_SIZE {
+        return None;
+    }
+    let compressed = lz4_flex::compress_prepend_size(record);
+    (compressed.len() < record.len()).then_some(compressed)
+}
+
+#[cfg(not(feature = "lz4"))]
+pub fn compress(_record: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+/// decompress restores a record written by compress
+#[cfg(feature = "lz4")]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    match lz4_flex::decompress_size_prepended(data) {
+        Ok(record) => Ok(record),
+        Err(e) => bail!("failed to decompress log record: {}", e),
+    }
+}
+
+#[cfg(not(feature = "lz4"))]
+pub fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+    bail!("log record is compressed, but the lz4 feature is disabled")
+}
+
+#[cfg(all(test, feature = "lz4"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_compress_and_decompress_record() {
+        let record = [7u8; 400];
+        let compressed = compress(&record).unwrap();
+        assert!(compressed.len() < record.len());
+        assert_eq!(decompress(&compressed).unwrap(), record);
+    }
+
+    #[test]
+    fn should_not_compress_small_record() {
+        assert_eq!(compress(&[7u8; MIN_COMPRESS_SIZE - 1]), None);
+    }
+}
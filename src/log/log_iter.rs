@@ -1,10 +1,17 @@
-use std::{
-    mem::size_of,
-    sync::{Arc, Mutex},
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    file::{block::BlockId, file_manager::FileManager, page::Page},
+    I32_SIZE,
 };
 
-use crate::file::{block::BlockId, file_manager::FileManager, page::Page};
+use super::compression::{decompress, COMPRESSED_FLAG};
 
+/// LogIterator reads log records from the newest to the oldest.
+///
+/// A record that cannot be read, such as a compressed record read by a build
+/// without the lz4 feature, is returned as an error instead of panicking.
 pub struct LogIterator {
     file_manager: Arc<Mutex<FileManager>>,
     block: BlockId,
@@ -14,7 +21,7 @@ pub struct LogIterator {
 }
 
 impl LogIterator {
-    pub fn new(file_manager: Arc<Mutex<FileManager>>, block: BlockId) -> Self {
+    pub fn new(file_manager: Arc<Mutex<FileManager>>, block: BlockId) -> Result<Self> {
         let block_size = file_manager.lock().unwrap().block_size;
         let page = Page::new(block_size);
         let mut iter = LogIterator {
@@ -24,9 +31,9 @@ impl LogIterator {
             current_pos: 0,
             boundary: 0,
         };
-        iter.move_to_block(block);
+        iter.move_to_block(block)?;
 
-        iter
+        Ok(iter)
     }
 
     pub fn has_next(&self) -> bool {
@@ -34,32 +41,167 @@ impl LogIterator {
             || self.block.num > 0
     }
 
-    pub fn move_to_block(&mut self, block: BlockId) {
+    pub fn move_to_block(&mut self, block: BlockId) -> Result<()> {
         self.file_manager
             .lock()
             .unwrap()
-            .read(&block, &mut self.page)
-            .unwrap();
+            .read(&block, &mut self.page)?;
         self.boundary = self.page.get_int(0) as usize;
         self.current_pos = self.boundary;
+        Ok(())
+    }
+
+    fn read_record(&mut self) -> Result<Vec<u8>> {
+        if self.current_pos == self.file_manager.lock().unwrap().block_size as usize {
+            let block = BlockId::new(self.block.filename.clone(), self.block.num - 1);
+            self.block = block.clone();
+            self.move_to_block(block)?;
+        }
+
+        // the highest bit of the record size tells whether the record is compressed
+        let header = self.page.get_int(self.current_pos);
+        let record_size = (header & !COMPRESSED_FLAG) as usize;
+        let data = self
+            .page
+            .read_bytes(self.current_pos + I32_SIZE, record_size)?;
+        self.current_pos += record_size + I32_SIZE;
+        if header & COMPRESSED_FLAG != 0 {
+            decompress(&data)
+        } else {
+            Ok(data)
+        }
     }
 }
 
 impl Iterator for LogIterator {
-    type Item = Vec<u8>;
+    type Item = Result<Vec<u8>>;
     fn next(&mut self) -> Option<Self::Item> {
         if !self.has_next() {
             return None;
         }
+        Some(self.read_record())
+    }
+}
 
-        if self.current_pos == self.file_manager.lock().unwrap().block_size as usize {
-            let block = BlockId::new(self.block.filename.clone(), self.block.num - 1);
-            self.block = block.clone();
-            self.move_to_block(block);
+/// LogPosition is the place of a log record in the log file:
+/// the block and the offset of the record header in the block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogPosition {
+    pub block: BlockId,
+    pub offset: usize,
+}
+
+/// LogEntry is a log record read by ForwardLogIterator with its lsn and position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub lsn: i32,
+    pub position: LogPosition,
+    pub record: Vec<u8>,
+}
+
+/// ForwardLogIterator reads log records from the oldest to the newest,
+/// starting after a given lsn and ending at the last record of `last_block`.
+///
+/// Records are appended from the end of a block towards its beginning,
+/// so the records of each block are read from the boundary to the end of the block
+/// and returned in reverse. Blocks that only hold records at or before the lsn are skipped
+/// without decompressing them.
+pub struct ForwardLogIterator {
+    file_manager: Arc<Mutex<FileManager>>,
+    last_block: BlockId,
+    block: BlockId,
+    page: Page,
+    // offsets of the records in the current block, the oldest first
+    offsets: Vec<usize>,
+    index: usize,
+    // lsn of the next record
+    lsn: i32,
+}
+
+impl ForwardLogIterator {
+    pub fn new(
+        file_manager: Arc<Mutex<FileManager>>,
+        last_block: BlockId,
+        after_lsn: i32,
+    ) -> Result<Self> {
+        let block_size = file_manager.lock().unwrap().block_size;
+        let mut iter = ForwardLogIterator {
+            file_manager,
+            block: BlockId::new(last_block.filename.clone(), 0),
+            last_block,
+            page: Page::new(block_size),
+            offsets: vec![],
+            index: 0,
+            lsn: 1,
+        };
+        iter.move_to_block(0)?;
+        // skip whole blocks first, then the records of the block holding after_lsn
+        while iter.lsn + (iter.offsets.len() as i32) <= after_lsn
+            && iter.block.num < iter.last_block.num
+        {
+            iter.lsn += iter.offsets.len() as i32;
+            iter.move_to_block(iter.block.num + 1)?;
+        }
+        let skipped = (after_lsn - iter.lsn + 1).clamp(0, iter.offsets.len() as i32);
+        iter.index = skipped as usize;
+        iter.lsn += skipped;
+        Ok(iter)
+    }
+
+    fn move_to_block(&mut self, num: i32) -> Result<()> {
+        self.block = BlockId::new(self.block.filename.clone(), num);
+        let mut file_manager = self.file_manager.lock().unwrap();
+        file_manager.read(&self.block, &mut self.page)?;
+        let block_size = file_manager.block_size as usize;
+        drop(file_manager);
+
+        self.offsets.clear();
+        self.index = 0;
+        let mut offset = self.page.get_int(0) as usize;
+        while offset + I32_SIZE <= block_size {
+            self.offsets.push(offset);
+            let record_size = (self.page.get_int(offset) & !COMPRESSED_FLAG) as usize;
+            offset += record_size + I32_SIZE;
         }
+        self.offsets.reverse();
+        Ok(())
+    }
 
-        let record = self.page.get_bytes(self.current_pos);
-        self.current_pos += record.len() + size_of::<i32>();
-        Some(record)
+    fn read_entry(&mut self) -> Result<LogEntry> {
+        let offset = self.offsets[self.index];
+        let header = self.page.get_int(offset);
+        let record_size = (header & !COMPRESSED_FLAG) as usize;
+        let data = self.page.read_bytes(offset + I32_SIZE, record_size)?;
+        let record = if header & COMPRESSED_FLAG != 0 {
+            decompress(&data)?
+        } else {
+            data
+        };
+        let entry = LogEntry {
+            lsn: self.lsn,
+            position: LogPosition {
+                block: self.block.clone(),
+                offset,
+            },
+            record,
+        };
+        self.index += 1;
+        self.lsn += 1;
+        Ok(entry)
+    }
+}
+
+impl Iterator for ForwardLogIterator {
+    type Item = Result<LogEntry>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index == self.offsets.len() {
+            if self.block.num >= self.last_block.num {
+                return None;
+            }
+            if let Err(e) = self.move_to_block(self.block.num + 1) {
+                return Some(Err(e));
+            }
+        }
+        Some(self.read_entry())
     }
 }
@@ -1,9 +1,18 @@
 use anyhow::Result;
 use std::sync::{Arc, Mutex};
 
-use crate::file::{block::BlockId, file_manager::FileManager, page::Page};
+use crate::{
+    file::{block::BlockId, file_manager::FileManager, page::Page},
+    I32_SIZE,
+};
 
-use super::log_iter::LogIterator;
+use super::{
+    compression::{compress, COMPRESSED_FLAG},
+    log_iter::{ForwardLogIterator, LogIterator},
+};
+
+/// DEFAULT_LOG_FILE is the name of the log file unless DbConfig sets another one
+pub const DEFAULT_LOG_FILE: &str = "tinydb.log";
 
 /// LogManager is responsible for managing the log records
 /// in the log file. The log file is a sequence of blocks
@@ -20,6 +29,10 @@ use super::log_iter::LogIterator;
 ///                  ┗━━━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━━━┛
 ///                                    record
 /// ```
+///
+/// When the `lz4` feature is enabled, large records are compressed and
+/// the highest bit of the record size is set (see `COMPRESSED_FLAG`).
+/// `LogIterator` decompresses them transparently.
 #[derive(Debug, Default)]
 pub struct LogManager {
     file_manager: Arc<Mutex<FileManager>>,
@@ -29,6 +42,7 @@ pub struct LogManager {
     // lsn is log sequence number, a unique identifier for each log record
     latest_lsn: i32,
     last_saved_lsn: i32,
+    compression: bool,
 }
 
 impl LogManager {
@@ -47,27 +61,74 @@ impl LogManager {
             block
         };
 
+        drop(fm);
+        // the lsn of a record is its position in the log file, so it continues after reopening
+        // reading every record also fails here if the log has records this build cannot decompress
+        let record_count = LogIterator::new(file_manager.clone(), current_block.clone())?
+            .collect::<Result<Vec<_>>>()?
+            .len() as i32;
+
         Ok(Self {
             file_manager: file_manager.clone(),
             log_file: log_file.clone(),
             log_page,
             current_block,
-            latest_lsn: 0,
-            last_saved_lsn: 0,
+            latest_lsn: record_count,
+            last_saved_lsn: record_count,
+            compression: cfg!(feature = "lz4"),
         })
     }
 
-    pub fn iter(&mut self) -> LogIterator {
-        self.inner_flush().unwrap();
+    // set_compression enables or disables compression of the records appended from now on
+    // it has no effect unless the lz4 feature is enabled
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
+    // log_file returns the name of the log file
+    pub fn log_file(&self) -> &str {
+        &self.log_file
+    }
+
+    // latest_lsn returns the lsn of the last appended log record
+    pub fn latest_lsn(&self) -> i32 {
+        self.latest_lsn
+    }
+
+    // iter returns an iterator over all log records from the newest to the oldest
+    pub fn iter(&mut self) -> Result<LogIterator> {
+        self.inner_flush()?;
         LogIterator::new(self.file_manager.clone(), self.current_block.clone())
     }
 
+    // iter_forward returns an iterator over all log records from the oldest to the newest
+    pub fn iter_forward(&mut self) -> Result<ForwardLogIterator> {
+        self.iter_after(0)
+    }
+
+    // iter_after returns an iterator over the log records appended after lsn, the oldest first
+    // the records appended after the iterator is created are not returned
+    pub fn iter_after(&mut self, lsn: i32) -> Result<ForwardLogIterator> {
+        self.inner_flush()?;
+        ForwardLogIterator::new(self.file_manager.clone(), self.current_block.clone(), lsn)
+    }
+
     // appends a new log record to the log page or flush the log page if the log record does not fit
     pub fn append(&mut self, record: &[u8]) -> Result<i32> {
         // boundary is the position of the last log record in the log page
         let mut boundary = self.log_page.get_int(0);
+        // compress the log record if it gets smaller
+        let compressed = if self.compression {
+            compress(record)
+        } else {
+            None
+        };
+        let (data, flag) = match &compressed {
+            Some(compressed) => (compressed.as_slice(), COMPRESSED_FLAG),
+            None => (record, 0),
+        };
         // record_size is the size of the log record
-        let record_size = record.len() as i32;
+        let record_size = data.len() as i32;
         // bytes_needed is the size of the log record plus 4 bytes for the boundary
         // record size on the first 4 bytes of the block
         let bytes_needed = record_size + 4;
@@ -83,8 +144,11 @@ impl LogManager {
         }
         // record_pos is the position of the log record in the log page
         let record_pos = boundary - bytes_needed;
-        // set the log record in the log page
-        self.log_page.set_bytes(record_pos as usize, record);
+        // set the log record with the compression flag in the log page
+        self.log_page
+            .set_int(record_pos as usize, record_size | flag);
+        self.log_page
+            .write_bytes(record_pos as usize + I32_SIZE, data)?;
         // set the boundary in the log page
         self.log_page.set_int(0, record_pos);
         self.latest_lsn += 1;
@@ -100,11 +164,12 @@ impl LogManager {
     }
 
     // inner_flush saves the log record to the log file
+    // ログより前に書き込んだデータのファイルも一緒にディスクに同期する
     fn inner_flush(&mut self) -> Result<()> {
-        self.file_manager
-            .lock()
-            .unwrap()
-            .write(&self.current_block, &mut self.log_page)?;
+        let mut file_manager = self.file_manager.lock().unwrap();
+        file_manager.write(&self.current_block, &mut self.log_page)?;
+        file_manager.sync()?;
+        drop(file_manager);
         self.last_saved_lsn = self.latest_lsn;
         Ok(())
     }
@@ -132,7 +197,7 @@ mod tests {
     fn should_can_new_log_manager() {
         let tempdir = tempfile::tempdir().unwrap();
         let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
-        let mut log_manager = LogManager::new(file_manager, "log".to_string()).unwrap();
+        let log_manager = LogManager::new(file_manager, "log".to_string()).unwrap();
         assert_eq!(
             log_manager.current_block,
             BlockId::new("log".to_string(), 0)
@@ -210,6 +275,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_continue_lsn_after_reopen() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32).unwrap()));
+        let mut log_manager = LogManager::new(file_manager.clone(), "log".to_string()).unwrap();
+        for record in [b"hello", b"world", b"again"] {
+            log_manager.append(record).unwrap();
+        }
+        log_manager.flush(3).unwrap();
+
+        let mut log_manager = LogManager::new(file_manager, "log".to_string()).unwrap();
+        assert_eq!(log_manager.latest_lsn(), 3);
+        assert_eq!(log_manager.append(b"next").unwrap(), 4);
+    }
+
     #[test]
     fn should_can_iter_record() {
         let tempdir = tempfile::tempdir().unwrap();
@@ -222,12 +302,103 @@ mod tests {
         let mut log_manager = LogManager::new(file_manager, "log".to_string()).unwrap();
         log_manager.append(record).unwrap();
         log_manager.append(record2).unwrap();
-        let mut iter = log_manager.iter();
-        let record = iter.next().unwrap();
+        let mut iter = log_manager.iter().unwrap();
+        let record = iter.next().unwrap().unwrap();
         assert_eq!(record, b"world");
-        let record = iter.next().unwrap();
+        let record = iter.next().unwrap().unwrap();
         assert_eq!(record, b"hello");
-        assert_eq!(iter.next(), None);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn should_iter_records_forward_across_blocks() -> Result<()> {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 32)?));
+        let mut log_manager = LogManager::new(file_manager, "log".to_string())?;
+        let records: Vec<Vec<u8>> = (0..7).map(|i| format!("rec{}", i).into_bytes()).collect();
+        for record in &records {
+            log_manager.append(record)?;
+        }
+
+        let entries = log_manager.iter_forward()?.collect::<Result<Vec<_>>>()?;
+        let read: Vec<_> = entries.iter().map(|entry| entry.record.clone()).collect();
+        assert_eq!(read, records);
+        let lsns: Vec<_> = entries.iter().map(|entry| entry.lsn).collect();
+        assert_eq!(lsns, (1..=7).collect::<Vec<_>>());
+        // each block of 32 bytes holds 3 records of 8 bytes, the oldest at the end of the block
+        assert_eq!(entries[0].position.block, BlockId::new("log".into(), 0));
+        assert_eq!(entries[0].position.offset, 24);
+        assert_eq!(entries[3].position.block, BlockId::new("log".into(), 1));
+
+        // the records after an lsn, even if the lsn is in an earlier block
+        let after = |log_manager: &mut LogManager, lsn| -> Result<Vec<i32>> {
+            let iter = log_manager.iter_after(lsn)?;
+            Ok(iter.map(|entry| entry.unwrap().lsn).collect())
+        };
+        assert_eq!(after(&mut log_manager, 2)?, vec![3, 4, 5, 6, 7]);
+        assert_eq!(after(&mut log_manager, 3)?, vec![4, 5, 6, 7]);
+        assert_eq!(after(&mut log_manager, 6)?, vec![7]);
+        assert!(after(&mut log_manager, 7)?.is_empty());
+        assert!(after(&mut log_manager, 100)?.is_empty());
+
+        // backward and forward iteration return the same records
+        let mut backward = log_manager.iter()?.collect::<Result<Vec<_>>>()?;
+        backward.reverse();
+        assert_eq!(backward, records);
+        Ok(())
+    }
+
+    #[test]
+    fn should_fail_to_read_record_that_cannot_be_decompressed() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 400)?));
+        let mut log_manager = LogManager::new(file_manager.clone(), "log".to_string())?;
+        log_manager.append(&[5, 0, 0, 0, 0xff])?;
+        // flag the record as compressed, like a compressed record read by a build without lz4
+        let boundary = log_manager.log_page.get_int(0) as usize;
+        let header = log_manager.log_page.get_int(boundary);
+        log_manager
+            .log_page
+            .set_int(boundary, header | COMPRESSED_FLAG);
+
+        assert!(log_manager.iter()?.next().unwrap().is_err());
+        drop(log_manager);
+        // reopening reads every record, so it fails instead of panicking during recovery
+        assert!(LogManager::new(file_manager, "log".to_string()).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn should_can_iter_compressed_record() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let block_size = 400;
+        let record = [b'a'; 300];
+        let file_manager = Arc::new(Mutex::new(
+            FileManager::new(tempdir.path(), block_size).unwrap(),
+        ));
+        let mut log_manager = LogManager::new(file_manager, "log".to_string()).unwrap();
+        log_manager.append(&record).unwrap();
+        log_manager.append(b"hello").unwrap();
+
+        // the large record is compressed and flagged, the small one is stored as is
+        let boundary = log_manager.log_page.get_int(0) as usize;
+        assert_eq!(log_manager.log_page.get_bytes(boundary), b"hello");
+        let header = log_manager.log_page.get_int(boundary + 9);
+        assert_ne!(header & COMPRESSED_FLAG, 0);
+        assert!(((header & !COMPRESSED_FLAG) as usize) < record.len());
+
+        let mut iter = log_manager.iter().unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), b"hello");
+        assert_eq!(iter.next().unwrap().unwrap(), record);
+        assert!(iter.next().is_none());
+
+        let records: Vec<_> = log_manager
+            .iter_forward()
+            .unwrap()
+            .map(|entry| entry.unwrap().record)
+            .collect();
+        assert_eq!(records, vec![record.to_vec(), b"hello".to_vec()]);
     }
 
     // FIXME: this should passed?
@@ -1,2 +1,3 @@
+pub mod compression;
 pub mod log_iter;
 pub mod log_manager;
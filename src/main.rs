@@ -1,3 +1,341 @@
-fn main() {
-    println!("Hello, world!");
+use anyhow::{bail, Result};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead as _, BufWriter, Write as _},
+    path::Path,
+};
+use tinydb::{
+    server::{
+        db::TinyDB,
+        retry::RetryPolicy,
+        session::{ExecuteResult, Session},
+    },
+    tools, unlock, Constant, FieldTypes, ResultSetMetadata,
+};
+
+const BLOCK_SIZE: i32 = 400;
+const BUFFER_SIZE: u64 = 8;
+
+/// 文の実行に失敗した場合の終了コード
+const EXIT_EXECUTION_ERROR: i32 = 1;
+/// 文の構文が正しくない場合の終了コード
+const EXIT_SYNTAX_ERROR: i32 = 2;
+
+/// OutputFormat は問い合わせの結果を表示する形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// 列の幅をそろえた表。更新したレコード数と購読しているテーブルの変更の知らせも表示する
+    Table,
+    /// 1行目が列の名前の CSV。NULL は空の値にする
+    Csv,
+    /// 1文1行の JSON。問い合わせはレコードのオブジェクトの配列、更新は {"updated": n} にする
+    Json,
+}
+
+fn main() -> Result<()> {
+    // --force は他のプロセスが持っているデータベースのロックを奪う
+    // --user=<name> はそのユーザーの権限で文を実行する
+    // --retry=<n> はロックのタイムアウトで失敗した文を合計 n 回まで実行する
+    // `tinydb check [--repair] [dir]` はデータベースの整合性を検査する
+    // `tinydb dump <dir> <file>` はデータベースを SQL の文としてファイルに書き込む
+    // `tinydb restore <dir> <file>` はファイルの SQL の文を実行する
+    // `tinydb import <dir> <csv_dir>` は CSV ファイルのディレクトリからテーブルを作って読み込む
+    // `tinydb advise <dir> <file>` はファイルの問い合わせのコストを下げる索引を提案する
+    // -c <sql> と -f <file> は文を順に実行して終了する。失敗した文があればそこで止め、
+    // 構文の誤りなら終了コード 2、実行の失敗なら終了コード 1 で終了する
+    // --format=table|csv|json は問い合わせの結果を表示する形式
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // -c と -f は次の引数を値として取るので、先に取り出す
+    let mut scripts = vec![];
+    while let Some(i) = args.iter().position(|arg| arg == "-c" || arg == "-f") {
+        if i + 1 == args.len() {
+            bail!("usage: tinydb [dir] -c <sql> | -f <file>");
+        }
+        let value = args.remove(i + 1);
+        let script = if args.remove(i) == "-c" {
+            value
+        } else {
+            fs::read_to_string(value)?
+        };
+        scripts.push(script);
+    }
+    let (flags, mut args): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|arg| arg.starts_with("--"));
+    let force = flags.iter().any(|flag| flag == "--force");
+    let command = args
+        .first()
+        .filter(|arg| ["check", "dump", "restore", "import", "advise"].contains(&arg.as_str()))
+        .cloned();
+    if command.is_some() {
+        args.remove(0);
+    }
+    let mut args = args.into_iter();
+    let dir = args.next().unwrap_or_else(|| "tinydb".into());
+    if matches!(command.as_deref(), Some("check" | "dump" | "advise")) && !Path::new(&dir).exists()
+    {
+        bail!("database not found: {}", dir);
+    }
+    let mut db = TinyDB::open(dir, BLOCK_SIZE, BUFFER_SIZE, force)?;
+    if command.as_deref() == Some("check") {
+        let repair = flags.iter().any(|flag| flag == "--repair");
+        return check(&db, repair);
+    }
+    db.init_planner()?;
+    if let Some(command) = command {
+        let Some(file) = args.next() else {
+            bail!("usage: tinydb {} <dir> <file>", command);
+        };
+        return match command.as_str() {
+            "dump" => dump(&db, &file),
+            "restore" => restore(&db, &file),
+            "advise" => advise(&db, &file),
+            _ => import(&db, &file),
+        };
+    }
+    let mut session = Session::new(&db)?;
+    let user = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--user="))
+        .map(String::from);
+    session.set_user(user);
+    if let Some(retry) = flags.iter().find_map(|flag| flag.strip_prefix("--retry=")) {
+        session.set_retry_policy(RetryPolicy::with_max_attempts(retry.parse()?));
+    }
+    let format = match flags.iter().find_map(|flag| flag.strip_prefix("--format=")) {
+        None | Some("table") => OutputFormat::Table,
+        Some("csv") => OutputFormat::Csv,
+        Some("json") => OutputFormat::Json,
+        Some(format) => bail!("unknown output format: {}", format),
+    };
+
+    if !scripts.is_empty() {
+        for script in &scripts {
+            for sql in tools::restore::split_statements(script) {
+                if let Err(err) = execute(&mut session, sql, format) {
+                    eprintln!("error: {}", err);
+                    let code = if Session::check_syntax(sql).is_err() {
+                        EXIT_SYNTAX_ERROR
+                    } else {
+                        EXIT_EXECUTION_ERROR
+                    };
+                    std::process::exit(code);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let stdin = io::stdin();
+    loop {
+        print!("tinydb> ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        let sql = line.trim().trim_end_matches(';');
+        match sql {
+            "" => continue,
+            "exit" | "quit" => break,
+            _ => {}
+        }
+
+        if let Err(err) = execute(&mut session, sql, format) {
+            eprintln!("error: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// execute は文を実行して結果を表示する
+fn execute(session: &mut Session, sql: &str, format: OutputFormat) -> Result<()> {
+    let result = session.execute(sql)?;
+    match format {
+        OutputFormat::Table => print_table(&result),
+        OutputFormat::Csv => print_csv(&result),
+        OutputFormat::Json => println!("{}", to_json(&result)),
+    }
+    // 購読しているテーブルの変更は、文を実行するたびに表示する
+    // CSV や JSON の出力に混ざらないように、表の形式の場合だけ表示する
+    let notifications = session.notifications();
+    if format == OutputFormat::Table {
+        for notification in notifications {
+            println!(
+                "notification: {} changed by transaction {}",
+                notification.table_name, notification.tx_num
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_table(result: &ExecuteResult) {
+    match result {
+        ExecuteResult::Rows { metadata, rows } => {
+            println!("{}", format_row(metadata, &metadata.field_names(), false));
+            for row in rows {
+                let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                println!("{}", format_row(metadata, &values, true));
+            }
+        }
+        ExecuteResult::Updated(count) => println!("{} records affected", count),
+    }
+}
+
+/// print_csv は問い合わせの結果を CSV で表示する。更新の結果は表示しない
+fn print_csv(result: &ExecuteResult) {
+    let ExecuteResult::Rows { metadata, rows } = result else {
+        return;
+    };
+    let names: Vec<String> = metadata
+        .field_names()
+        .iter()
+        .map(|n| csv_field(n))
+        .collect();
+    println!("{}", names.join(","));
+    for row in rows {
+        let values: Vec<String> = row
+            .iter()
+            .map(|value| match value {
+                Constant::Null => String::new(),
+                value => csv_field(&value.to_string()),
+            })
+            .collect();
+        println!("{}", values.join(","));
+    }
+}
+
+/// csv_field は区切りや引用符、改行を含む値を引用符で囲む
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// to_json は文の結果を1行の JSON にする
+fn to_json(result: &ExecuteResult) -> String {
+    match result {
+        ExecuteResult::Rows { metadata, rows } => {
+            let names = metadata.field_names();
+            let objects: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let members: Vec<String> = names
+                        .iter()
+                        .zip(row)
+                        .map(|(name, value)| {
+                            let value = match value {
+                                Constant::Null => "null".to_string(),
+                                Constant::Int(i) => i.to_string(),
+                                Constant::String(s) => json_string(s),
+                                Constant::Bool(b) => b.to_string(),
+                                Constant::Date(d) => json_string(&d.to_string()),
+                            };
+                            format!("{}: {}", json_string(name), value)
+                        })
+                        .collect();
+                    format!("{{{}}}", members.join(", "))
+                })
+                .collect();
+            format!("[{}]", objects.join(", "))
+        }
+        ExecuteResult::Updated(count) => format!("{{\"updated\": {}}}", count),
+    }
+}
+
+/// json_string は文字列を JSON の文字列にする
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// check は整合性の検査の結果を表示する。直していない問題があれば終了コード 1 で終了する
+/// プランナーを用意すると統計情報のためにテーブルのファイルが作られてしまうので、復旧だけしてから検査する
+fn check(db: &TinyDB, repair: bool) -> Result<()> {
+    let tx = db.transaction()?;
+    unlock!(tx).recover()?;
+    let report = tools::check::check(tx.clone(), repair)?;
+    unlock!(tx).commit()?;
+    println!("{}", report);
+    if !report.is_consistent() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// dump はデータベースを SQL の文としてファイルに書き込む
+fn dump(db: &TinyDB, file: &str) -> Result<()> {
+    db.with_transaction(|tx, _| {
+        let mut writer = BufWriter::new(File::create(file)?);
+        tools::dump::dump(tx, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+/// restore はファイルの SQL の文を1つのトランザクションで実行する
+fn restore(db: &TinyDB, file: &str) -> Result<()> {
+    let script = fs::read_to_string(file)?;
+    let count = db.with_transaction(|tx, planner| {
+        tools::restore::restore(planner, tx, &mut script.as_bytes())
+    })?;
+    println!("{} statements restored", count);
+    Ok(())
+}
+
+/// import は CSV ファイルのディレクトリからテーブルを作ってレコードを読み込む
+fn import(db: &TinyDB, csv_dir: &str) -> Result<()> {
+    let imported =
+        db.with_transaction(|tx, planner| tools::import::import_csv(planner, tx, csv_dir))?;
+    for (table_name, count) in imported {
+        println!("{}: {} records imported", table_name, count);
+    }
+    Ok(())
+}
+
+/// advise はファイルの `;` で区切った問い合わせを調べて、提案する索引を表示する
+fn advise(db: &TinyDB, file: &str) -> Result<()> {
+    let script = fs::read_to_string(file)?;
+    let workload = tools::restore::split_statements(&script);
+    println!("{}", tools::index_advisor::advise(db, &workload)?);
+    Ok(())
+}
+
+/// format_row は値を列の表示幅にそろえて1行にする
+/// align_numbers の場合は数値の列を右にそろえる
+fn format_row(metadata: &ResultSetMetadata, values: &[String], align_numbers: bool) -> String {
+    let cells: Vec<String> = metadata
+        .columns()
+        .iter()
+        .zip(values)
+        .map(|(column, value)| {
+            let width = column.display_size();
+            let number = matches!(
+                column.field_type,
+                FieldTypes::Integer | FieldTypes::BigInt | FieldTypes::Double
+            );
+            if align_numbers && number {
+                format!("{:>width$}", value)
+            } else {
+                format!("{:<width$}", value)
+            }
+        })
+        .collect();
+    cells.join(" | ").trim_end().to_string()
 }
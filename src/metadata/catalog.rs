@@ -0,0 +1,178 @@
+use super::table_manager::MAX_NAME;
+use crate::record::schema::Schema;
+
+/// CATALOG_VERSION は今のカタログの形式の版。カタログの形式を変えたら増やし、古い版から上げる手順を加える
+///
+/// データベースを作ったときの版はコントロールファイルに記録する
+///   - 1: コントロールファイルを作る前の形式。tblcat に作成した時刻と統計の列がないことがある
+///   - 2: tblcat に作成した時刻と統計の列がある
+///   - 3: ハッシュ索引のバケットのテーブルの名前に INDEX_FILE_PREFIX を付ける
+pub const CATALOG_VERSION: i32 = 3;
+
+/// TABLE_CATALOG はテーブルの名前とスロットのサイズ、統計を保持するカタログ
+pub const TABLE_CATALOG: &str = "tblcat";
+/// FIELD_CATALOG はテーブルのフィールドの種類と長さ、オフセットを保持するカタログ
+pub const FIELD_CATALOG: &str = "fldcat";
+/// VIEW_CATALOG はビューの定義を保持するカタログ
+pub const VIEW_CATALOG: &str = "viewcat";
+/// INDEX_CATALOG は索引の定義を保持するカタログ
+pub const INDEX_CATALOG: &str = "idxcat";
+/// PRIVILEGE_CATALOG はテーブルの権限を保持するカタログ
+pub const PRIVILEGE_CATALOG: &str = "privcat";
+/// CLUSTER_CATALOG はキーの順に格納するテーブルを保持するカタログ
+pub const CLUSTER_CATALOG: &str = "clustcat";
+/// COLLATION_CATALOG は Binary 以外の照合順序を指定したフィールドを保持するカタログ
+pub const COLLATION_CATALOG: &str = "collcat";
+/// TRUNCATION_CATALOG は長すぎる文字列を切り詰めるフィールドを保持するカタログ
+pub const TRUNCATION_CATALOG: &str = "trunccat";
+
+/// CATALOG_TABLES はすべてのユーザーが読み込めるカタログのテーブル
+pub const CATALOG_TABLES: [&str; 8] = [
+    TABLE_CATALOG,
+    FIELD_CATALOG,
+    VIEW_CATALOG,
+    INDEX_CATALOG,
+    PRIVILEGE_CATALOG,
+    CLUSTER_CATALOG,
+    COLLATION_CATALOG,
+    TRUNCATION_CATALOG,
+];
+
+/// MAX_VIEWDEF はビューの定義の最大の長さ
+pub const MAX_VIEWDEF: i32 = 100;
+
+/// tblcat のフィールド
+pub mod tblcat {
+    pub const TABLE_NAME: &str = "tblname";
+    pub const SLOT_SIZE: &str = "slotsize";
+    pub const CREATED: &str = "created";
+    pub const ANALYZED: &str = "analyzed";
+    pub const NUM_BLOCKS: &str = "numblocks";
+    pub const NUM_RECORDS: &str = "numrecs";
+}
+
+/// fldcat のフィールド
+pub mod fldcat {
+    pub const TABLE_NAME: &str = "tblname";
+    pub const FIELD_NAME: &str = "fldname";
+    pub const TYPE: &str = "type";
+    pub const LENGTH: &str = "length";
+    pub const OFFSET: &str = "offset";
+}
+
+/// collcat と trunccat に共通のフィールド。設定の名前のフィールドはカタログごとに異なる
+pub mod field_option {
+    pub const TABLE_NAME: &str = "tblname";
+    pub const FIELD_NAME: &str = "fldname";
+    /// COLLATION は collcat の照合順序の名前
+    pub const COLLATION: &str = "collation";
+    /// POLICY は trunccat の TruncationPolicy の名前
+    pub const POLICY: &str = "policy";
+}
+
+/// viewcat のフィールド
+pub mod viewcat {
+    pub const VIEW_NAME: &str = "viewname";
+    pub const VIEW_DEF: &str = "viewdef";
+}
+
+/// idxcat のフィールド
+pub mod idxcat {
+    pub const INDEX_NAME: &str = "indexname";
+    pub const TABLE_NAME: &str = "tablename";
+    pub const FIELD_NAME: &str = "fieldname";
+    pub const INDEX_TYPE: &str = "indextype";
+    pub const IS_UNIQUE: &str = "isunique";
+    pub const PREFIX_LENGTH: &str = "prefixlen";
+}
+
+/// privcat のフィールド
+pub mod privcat {
+    pub const GRANTEE: &str = "grantee";
+    pub const TABLE_NAME: &str = "tablename";
+    pub const PRIVILEGE: &str = "privilege";
+}
+
+/// clustcat のフィールド
+pub mod clustcat {
+    pub const TABLE_NAME: &str = "tblname";
+    pub const FIELD_NAME: &str = "fldname";
+    pub const SORTED: &str = "sorted";
+}
+
+/// legacy_table_catalog_schema は作成した時刻や統計の列を加える前の tblcat のスキーマを返す
+pub fn legacy_table_catalog_schema() -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(tblcat::TABLE_NAME, MAX_NAME);
+    schema.add_int_field(tblcat::SLOT_SIZE);
+    schema
+}
+
+/// table_catalog_schema は tblcat のスキーマを返す
+/// 古いカタログと先頭の列の位置が同じになるように、後から加えた列は末尾に置く
+pub fn table_catalog_schema() -> Schema {
+    let mut schema = legacy_table_catalog_schema();
+    schema.add_int_field(tblcat::CREATED);
+    schema.add_int_field(tblcat::ANALYZED);
+    schema.add_int_field(tblcat::NUM_BLOCKS);
+    schema.add_int_field(tblcat::NUM_RECORDS);
+    schema
+}
+
+pub fn field_catalog_schema() -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(fldcat::TABLE_NAME, MAX_NAME);
+    schema.add_string_field(fldcat::FIELD_NAME, MAX_NAME);
+    schema.add_int_field(fldcat::TYPE);
+    schema.add_int_field(fldcat::LENGTH);
+    schema.add_int_field(fldcat::OFFSET);
+    schema
+}
+
+/// field_option_catalog_schema はフィールドごとの設定を保持するカタログ（テーブル名、フィールド名、設定の名前）のスキーマを返す
+pub fn field_option_catalog_schema(option_field: &str) -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(field_option::TABLE_NAME, MAX_NAME);
+    schema.add_string_field(field_option::FIELD_NAME, MAX_NAME);
+    schema.add_string_field(option_field, MAX_NAME);
+    schema
+}
+
+pub fn view_catalog_schema() -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(viewcat::VIEW_NAME, MAX_NAME);
+    schema.add_string_field(viewcat::VIEW_DEF, MAX_VIEWDEF);
+    schema
+}
+
+pub fn index_catalog_schema() -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(idxcat::INDEX_NAME, MAX_NAME);
+    schema.add_string_field(idxcat::TABLE_NAME, MAX_NAME);
+    schema.add_string_field(idxcat::FIELD_NAME, MAX_NAME);
+    schema.add_string_field(idxcat::INDEX_TYPE, MAX_NAME);
+    schema.add_int_field(idxcat::IS_UNIQUE);
+    schema.add_int_field(idxcat::PREFIX_LENGTH);
+    schema
+}
+
+pub fn privilege_catalog_schema() -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(privcat::GRANTEE, MAX_NAME);
+    schema.add_string_field(privcat::TABLE_NAME, MAX_NAME);
+    schema.add_string_field(privcat::PRIVILEGE, MAX_NAME);
+    schema
+}
+
+pub fn cluster_catalog_schema() -> Schema {
+    let mut schema = Schema::default();
+    schema.add_string_field(clustcat::TABLE_NAME, MAX_NAME);
+    schema.add_string_field(clustcat::FIELD_NAME, MAX_NAME);
+    schema.add_int_field(clustcat::SORTED);
+    schema
+}
+
+/// file_name はカタログのテーブルのファイル名を返す
+pub fn file_name(catalog: &str) -> String {
+    format!("{}.tbl", catalog)
+}
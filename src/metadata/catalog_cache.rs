@@ -0,0 +1,128 @@
+use super::{
+    catalog::{COLLATION_CATALOG, FIELD_CATALOG, INDEX_CATALOG, TABLE_CATALOG, TRUNCATION_CATALOG},
+    metadata_snapshot::IndexDef,
+};
+use crate::record::layout::Layout;
+use arc_swap::ArcSwap;
+use std::{collections::HashMap, sync::Arc};
+
+/// LAYOUT_CATALOGS はテーブルのレイアウトを読むときに読むカタログ
+pub const LAYOUT_CATALOGS: [&str; 4] = [
+    TABLE_CATALOG,
+    FIELD_CATALOG,
+    COLLATION_CATALOG,
+    TRUNCATION_CATALOG,
+];
+/// INDEX_CATALOGS は索引の定義を読むときに読むカタログ
+pub const INDEX_CATALOGS: [&str; 1] = [INDEX_CATALOG];
+
+/// CatalogCache はコミットしたカタログから読み込んだテーブルのメタデータを、トランザクションをまたいで共有するキャッシュ
+///
+/// 読むときはロックを取らずにその時点の中身を参照し、書くときは中身を複製してから差し替える
+/// カタログを変更したトランザクションは、コミットしてロックを解放する前に中身を空にする
+/// カタログを変更したトランザクションが読み込んだメタデータはまだコミットしていないので、キャッシュには入れない
+///
+/// キャッシュから読んでもカタログのファイルを読み直さないだけで、カタログの共有ロックはカタログを読んだときと同じように取る
+#[derive(Debug, Default)]
+pub struct CatalogCache {
+    entries: ArcSwap<CatalogEntries>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct CatalogEntries {
+    /// generation は中身を空にするたびに増やす。読み始めた後に空にした場合は、読んだメタデータを入れない
+    generation: u64,
+    layouts: HashMap<String, Layout>,
+    index_defs: HashMap<String, Vec<IndexDef>>,
+}
+
+impl CatalogCache {
+    /// generation はカタログを読み始める前に取っておき、読んだメタデータを入れるときに渡す
+    pub fn generation(&self) -> u64 {
+        self.entries.load().generation
+    }
+
+    pub fn layout(&self, table_name: &str) -> Option<Layout> {
+        self.entries.load().layouts.get(table_name).cloned()
+    }
+
+    pub fn index_defs(&self, table_name: &str) -> Option<Vec<IndexDef>> {
+        self.entries.load().index_defs.get(table_name).cloned()
+    }
+
+    /// set_layout は generation から中身が空にされていなければ、テーブルのレイアウトを入れる
+    pub fn set_layout(&self, generation: u64, table_name: &str, layout: Layout) {
+        self.update(generation, |entries| {
+            entries
+                .layouts
+                .insert(table_name.to_string(), layout.clone());
+        });
+    }
+
+    /// set_index_defs は generation から中身が空にされていなければ、テーブルの索引の定義を入れる
+    pub fn set_index_defs(&self, generation: u64, table_name: &str, index_defs: Vec<IndexDef>) {
+        self.update(generation, |entries| {
+            entries
+                .index_defs
+                .insert(table_name.to_string(), index_defs.clone());
+        });
+    }
+
+    /// invalidate はすべてのメタデータを忘れる。カタログを変更したトランザクションがコミットするときに呼ぶ
+    pub fn invalidate(&self) {
+        self.entries.rcu(|entries| CatalogEntries {
+            generation: entries.generation + 1,
+            ..Default::default()
+        });
+    }
+
+    fn update(&self, generation: u64, f: impl Fn(&mut CatalogEntries)) {
+        self.entries.rcu(|entries| {
+            let mut entries = CatalogEntries::clone(entries);
+            if entries.generation == generation {
+                f(&mut entries);
+            }
+            Arc::new(entries)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index::IndexOptions, record::schema::Schema};
+
+    #[test]
+    fn should_share_metadata_until_invalidated() -> anyhow::Result<()> {
+        let cache = CatalogCache::default();
+        let mut schema = Schema::default();
+        schema.add_int_field("a");
+        let layout = Layout::try_from_schema(Arc::new(schema))?;
+        let index_defs = vec![IndexDef {
+            index_name: "ta".into(),
+            field_name: "a".into(),
+            options: IndexOptions::default(),
+        }];
+
+        let generation = cache.generation();
+        cache.set_layout(generation, "t", layout.clone());
+        cache.set_index_defs(generation, "t", index_defs.clone());
+        assert_eq!(
+            cache.layout("t").map(|l| l.slot_size),
+            Some(layout.slot_size)
+        );
+        assert_eq!(cache.index_defs("t"), Some(index_defs.clone()));
+        assert!(cache.layout("u").is_none());
+
+        cache.invalidate();
+        assert!(cache.layout("t").is_none());
+        assert!(cache.index_defs("t").is_none());
+
+        // 読み始めた後に空にされたメタデータは古いかもしれないので入れない
+        cache.set_layout(generation, "t", layout);
+        cache.set_index_defs(generation, "t", index_defs);
+        assert!(cache.layout("t").is_none());
+        assert!(cache.index_defs("t").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use super::{
+    catalog::{clustcat, cluster_catalog_schema, CLUSTER_CATALOG},
+    table_manager::TableManager,
+};
+use crate::{
+    query::scan::Scan as _,
+    record::{layout::Layout, table_scan::TableScan},
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::sync::{Arc, Mutex};
+
+/// ClusterInfo はキーの順に格納するテーブルの情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterInfo {
+    /// レコードを並べるキーのフィールド
+    pub field_name: String,
+    /// テーブルのすべてのレコードがキーの順に並んでいるかどうか
+    pub sorted: bool,
+}
+
+/// ClusterManager はキーの順に格納するテーブルのカタログ（clustcat）を管理する
+/// 1つのレコードが1つのテーブルを表す
+///
+/// | tblname | fldname | sorted |
+/// |---------|---------|--------|
+///
+/// sorted はレコードがキーの順に並んでいる間は 1 で、順序を崩す挿入や更新があると 0 になる
+/// cluster 文でテーブルを並べ直すと再び 1 になる
+///
+/// clustcat がない古いデータベースを開いた場合は、clustcat を作る
+pub struct ClusterManager {
+    layout: Arc<Layout>,
+}
+
+impl ClusterManager {
+    pub fn new(
+        table_manager: Arc<Mutex<TableManager>>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Self> {
+        let mut layout = unlock!(table_manager).get_layout(CLUSTER_CATALOG, tx.clone())?;
+        if !layout.schema.has_field(clustcat::FIELD_NAME) {
+            let schema = Arc::new(cluster_catalog_schema());
+            unlock!(table_manager).create_table(CLUSTER_CATALOG, schema, tx.clone())?;
+            layout = unlock!(table_manager).get_layout(CLUSTER_CATALOG, tx)?;
+        }
+
+        Ok(Self {
+            layout: Arc::new(layout),
+        })
+    }
+
+    /// create_cluster_key はテーブルをキーの順に格納するように記録する
+    /// 作ったばかりのテーブルは空なので、並んでいるものとして記録する
+    pub fn create_cluster_key(
+        &self,
+        table_name: &str,
+        field_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        if self.get_cluster_info(table_name, tx.clone())?.is_some() {
+            bail!("cluster key already exists: {}", table_name);
+        }
+        let mut ts = TableScan::new(tx, CLUSTER_CATALOG, self.layout.clone())?;
+        ts.insert()?;
+        ts.set_string(clustcat::TABLE_NAME, table_name)?;
+        ts.set_string(clustcat::FIELD_NAME, field_name)?;
+        ts.set_int(clustcat::SORTED, 1)?;
+        ts.close();
+        Ok(())
+    }
+
+    /// get_cluster_info はテーブルのキーの情報を返す。キーの順に格納しないテーブルは None を返す
+    pub fn get_cluster_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<ClusterInfo>> {
+        let mut ts = TableScan::new(tx, CLUSTER_CATALOG, self.layout.clone())?;
+        let mut info = None;
+        while info.is_none() && ts.next()? {
+            if ts.get_string(clustcat::TABLE_NAME)? == table_name {
+                info = Some(ClusterInfo {
+                    field_name: ts.get_string(clustcat::FIELD_NAME)?,
+                    sorted: ts.get_int(clustcat::SORTED)? != 0,
+                });
+            }
+        }
+        ts.close();
+        Ok(info)
+    }
+
+    /// set_sorted はテーブルのレコードがキーの順に並んでいるかどうかを記録する
+    pub fn set_sorted(
+        &self,
+        table_name: &str,
+        sorted: bool,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        let mut ts = TableScan::new(tx, CLUSTER_CATALOG, self.layout.clone())?;
+        while ts.next()? {
+            if ts.get_string(clustcat::TABLE_NAME)? == table_name {
+                if (ts.get_int(clustcat::SORTED)? != 0) != sorted {
+                    ts.set_int(clustcat::SORTED, sorted as i32)?;
+                }
+                break;
+            }
+        }
+        ts.close();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::db::TinyDB;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_record_cluster_key() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_record_cluster_key");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let table_manager = Arc::new(Mutex::new(TableManager::new(true, tx.clone())?));
+        let cluster_manager = ClusterManager::new(table_manager, tx.clone())?;
+
+        assert_eq!(cluster_manager.get_cluster_info("T", tx.clone())?, None);
+        cluster_manager.create_cluster_key("T", "A", tx.clone())?;
+        assert!(cluster_manager
+            .create_cluster_key("T", "B", tx.clone())
+            .is_err());
+        cluster_manager.set_sorted("T", false, tx.clone())?;
+        assert_eq!(
+            cluster_manager.get_cluster_info("T", tx.clone())?,
+            Some(ClusterInfo {
+                field_name: "A".into(),
+                sorted: false,
+            })
+        );
+
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use super::{
+    cluster_manager::ClusterInfo, index_info::IndexInfo, metadata_provider::MetadataProvider,
+    stat_info::StatInfo,
+};
+use crate::{
+    index::IndexOptions,
+    record::{layout::Layout, schema::Schema},
+    tx::transaction::Transaction,
+};
+use anyhow::{bail, Result};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// FakeMetadata はメモリの上に組み立てるメタデータ
+///
+/// テーブルのスキーマと統計、索引、ビュー、キーの順に並んだテーブルを直接登録できるので、
+/// プランナーのテストで大きなテーブルを作らずに、統計に応じて選ばれるプランの形を確かめられる
+/// カタログを持たないので、作ったプランを開いてもテーブルの中身はない
+#[derive(Debug, Default)]
+pub struct FakeMetadata {
+    tables: HashMap<String, FakeTable>,
+    views: HashMap<String, String>,
+    indexes: Vec<FakeIndex>,
+    clusters: HashMap<String, ClusterInfo>,
+}
+
+#[derive(Debug)]
+struct FakeTable {
+    layout: Arc<Layout>,
+    stat_info: StatInfo,
+}
+
+#[derive(Debug)]
+struct FakeIndex {
+    index_name: String,
+    table_name: String,
+    field_name: String,
+    options: IndexOptions,
+}
+
+impl FakeMetadata {
+    /// add_table はテーブルを登録する。stat_info は統計としてそのまま返す
+    pub fn add_table(
+        &mut self,
+        table_name: &str,
+        schema: Schema,
+        stat_info: StatInfo,
+    ) -> Result<&mut Self> {
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema))?);
+        let table = FakeTable { layout, stat_info };
+        self.tables.insert(table_name.to_string(), table);
+        Ok(self)
+    }
+
+    pub fn add_view(&mut self, view_name: &str, view_def: &str) -> &mut Self {
+        self.views
+            .insert(view_name.to_string(), view_def.to_string());
+        self
+    }
+
+    /// add_index は登録したテーブルのフィールドに索引を登録する
+    pub fn add_index(
+        &mut self,
+        index_name: &str,
+        table_name: &str,
+        field_name: &str,
+        options: IndexOptions,
+    ) -> Result<&mut Self> {
+        let Some(table) = self.tables.get(table_name) else {
+            bail!("table not found: {}", table_name);
+        };
+        if !table.layout.schema.has_field(field_name) {
+            bail!("field not found: {}", field_name);
+        }
+        self.indexes.push(FakeIndex {
+            index_name: index_name.to_string(),
+            table_name: table_name.to_string(),
+            field_name: field_name.to_string(),
+            options,
+        });
+        Ok(self)
+    }
+
+    /// set_cluster_key はテーブルをキーの順に格納するテーブルとして登録する
+    pub fn set_cluster_key(
+        &mut self,
+        table_name: &str,
+        field_name: &str,
+        sorted: bool,
+    ) -> &mut Self {
+        let info = ClusterInfo {
+            field_name: field_name.to_string(),
+            sorted,
+        };
+        self.clusters.insert(table_name.to_string(), info);
+        self
+    }
+}
+
+impl MetadataProvider for FakeMetadata {
+    fn get_layout(&self, table_name: &str, _tx: Arc<Mutex<Transaction>>) -> Result<Layout> {
+        let Some(table) = self.tables.get(table_name) else {
+            return Ok(Layout::default());
+        };
+        Layout::try_from_schema(table.layout.schema.clone())
+    }
+
+    fn get_view_def(
+        &self,
+        view_name: &str,
+        _tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<String>> {
+        Ok(self.views.get(view_name).cloned())
+    }
+
+    fn get_index_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<HashMap<String, IndexInfo>> {
+        let mut index_infos = HashMap::new();
+        let Some(table) = self.tables.get(table_name) else {
+            return Ok(index_infos);
+        };
+        for index in self.indexes.iter().filter(|i| i.table_name == table_name) {
+            let index_info = IndexInfo::new(
+                index.index_name.clone(),
+                index.table_name.clone(),
+                index.field_name.clone(),
+                table.layout.clone(),
+                index.options.clone(),
+                tx.clone(),
+                table.stat_info.clone(),
+            )?;
+            index_infos.insert(index.index_name.clone(), index_info);
+        }
+        Ok(index_infos)
+    }
+
+    fn get_stat_info(
+        &self,
+        table_name: &str,
+        _layout: Arc<Layout>,
+        _tx: Arc<Mutex<Transaction>>,
+    ) -> Result<StatInfo> {
+        Ok(self
+            .tables
+            .get(table_name)
+            .map_or(StatInfo::new(0, 0), |table| table.stat_info.clone()))
+    }
+
+    fn get_cluster_info(
+        &self,
+        table_name: &str,
+        _tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<ClusterInfo>> {
+        Ok(self.clusters.get(table_name).cloned())
+    }
+
+    /// 統計のレコード数が 0 のテーブルを空として扱う
+    fn is_empty(&self, table_name: &str, _tx: Arc<Mutex<Transaction>>) -> Result<bool> {
+        Ok(self
+            .tables
+            .get(table_name)
+            .is_some_and(|table| table.stat_info.num_records == 0))
+    }
+}
@@ -1,9 +1,16 @@
 use super::stat_info::StatInfo;
 use crate::{
-    index::hash::HashIndex,
+    index::{
+        btree::{BTreeIndex, PrefixKey},
+        collated::CollatedIndex,
+        hash::HashIndex,
+        unique::UniqueIndex,
+        Index, IndexOptions, IndexType,
+    },
     record::{
+        collation::Collation,
         layout::Layout,
-        schema::{FieldTypes, Schema},
+        schema::{FieldTypes, Schema, MAX_CHAR_BYTES},
     },
     tx::transaction::Transaction,
 };
@@ -13,62 +20,171 @@ use std::sync::{Arc, Mutex};
 #[derive(Debug)]
 pub struct IndexInfo {
     index_name: String,
+    table_name: String,
     field_name: String,
+    table_layout: Arc<Layout>,
+    options: IndexOptions,
     tx: Arc<Mutex<Transaction>>,
-    table_schema: Arc<Schema>,
     index_layout: Arc<Layout>,
     stat_info: StatInfo,
+    is_virtual: bool,
 }
 
 impl IndexInfo {
     pub fn new(
         index_name: String,
+        table_name: String,
         field_name: String,
-        table_schema: Arc<Schema>,
+        table_layout: Arc<Layout>,
+        options: IndexOptions,
         tx: Arc<Mutex<Transaction>>,
         stat_info: StatInfo,
     ) -> Result<Self> {
+        let table_schema = table_layout.schema.clone();
         let mut schema = Schema::default();
         schema.add_int_field("block");
         schema.add_int_field("id");
         match table_schema.r#type(&field_name) {
             Some(FieldTypes::Integer) => {
+                if options.prefix_length.is_some() {
+                    bail!("prefix length is only for varchar field: {}", field_name)
+                }
                 schema.add_int_field("dataval");
             }
             Some(FieldTypes::Varchar) => {
-                let length = table_schema.length(&field_name).unwrap();
+                // B-tree のページはキーを固定長で格納するので、長さの文字数に必要な最大のバイト数を確保する
+                let mut length = table_schema.length(&field_name).unwrap() * MAX_CHAR_BYTES;
+                if let Some(prefix_length) = options.prefix_length {
+                    // 先頭だけの索引はテーブルの値全体を元の値のまま比べるので、照合順序には従えない
+                    if table_schema.collation(&field_name) != Collation::Binary {
+                        bail!(
+                            "prefix length cannot be used with collation: {}",
+                            field_name
+                        )
+                    }
+                    if options.index_type != IndexType::BTree {
+                        bail!("prefix length is only for btree index: {}", index_name)
+                    }
+                    if prefix_length <= 0 {
+                        bail!("prefix length must be positive: {}", prefix_length)
+                    }
+                    length = length.min(prefix_length);
+                }
                 schema.add_string_field("dataval", length);
             }
+            Some(FieldTypes::Text) | Some(FieldTypes::Blob) => {
+                bail!("cannot create index on text or blob field: {}", field_name)
+            }
+            Some(r#type) => bail!("unsupported index field type: {:?}", r#type),
             None => bail!("field not found"),
         }
 
         let index_info = Self {
             index_name,
+            table_name,
             field_name,
+            table_layout,
+            options,
             tx,
-            table_schema,
             index_layout: Arc::new(Layout::try_from_schema(Arc::new(schema))?),
             stat_info,
+            is_virtual: false,
         };
 
         Ok(index_info)
     }
 
-    pub fn open(&mut self) -> HashIndex {
-        HashIndex::new(
-            self.tx.clone(),
-            self.index_name.clone(),
-            self.index_layout.clone(),
-        )
+    /// into_virtual はコストの見積もりだけに使う仮想の索引にする
+    pub fn into_virtual(mut self) -> Self {
+        self.is_virtual = true;
+        self
+    }
+
+    /// is_virtual は仮想の索引なら true を返す。仮想の索引には中身がないので開けない
+    pub fn is_virtual(&self) -> bool {
+        self.is_virtual
+    }
+
+    /// display_name は EXPLAIN に表示する索引の名前を返す。仮想の索引にはそのことを書き添える
+    pub fn display_name(&self) -> String {
+        if self.is_virtual {
+            format!("{} (virtual)", self.index_name)
+        } else {
+            self.index_name.clone()
+        }
+    }
+
+    /// open はカタログに記録された種類の索引を開く
+    /// 一意な索引は、挿入するキーが別のレコードの項目と重複していればエラーを返す
+    pub fn open(&mut self) -> Result<Box<dyn Index>> {
+        if self.is_virtual {
+            bail!("virtual index cannot be opened: {}", self.index_name);
+        }
+        let index: Box<dyn Index> = match self.options.index_type {
+            IndexType::Hash => Box::new(HashIndex::new(
+                self.tx.clone(),
+                self.index_name.clone(),
+                self.index_layout.clone(),
+            )),
+            IndexType::BTree => match self.prefix_key() {
+                Some(prefix) => Box::new(BTreeIndex::with_prefix(
+                    self.tx.clone(),
+                    &self.index_name,
+                    self.index_layout.clone(),
+                    prefix,
+                )?),
+                None => Box::new(BTreeIndex::new(
+                    self.tx.clone(),
+                    &self.index_name,
+                    self.index_layout.clone(),
+                )?),
+            },
+        };
+        let index: Box<dyn Index> = match self.table_layout.schema.collation(&self.field_name) {
+            Collation::Binary => index,
+            collation => Box::new(CollatedIndex::new(index, collation)),
+        };
+        if self.options.unique {
+            return Ok(Box::new(UniqueIndex::new(index, self.index_name.clone())));
+        }
+        Ok(index)
+    }
+
+    pub fn index_name(&self) -> &str {
+        &self.index_name
+    }
+
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    pub fn index_type(&self) -> IndexType {
+        self.options.index_type
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.options.unique
     }
 
     pub fn blocks_accessed(&self) -> u64 {
-        let rpb = self.tx.lock().unwrap().block_size() / self.index_layout.slot_size;
+        let rpb = (self.tx.lock().unwrap().block_size() / self.index_layout.slot_size).max(1);
         let num_blocks = self.stat_info.num_records / rpb;
-        HashIndex::search_cost(num_blocks as u64, rpb as u64)
+        match self.options.index_type {
+            IndexType::Hash => HashIndex::search_cost(num_blocks as u64, rpb as u64),
+            IndexType::BTree => BTreeIndex::search_cost(num_blocks as u64, rpb as u64),
+        }
     }
 
+    /// records_output は検索キー1つあたりのレコード数の見積もりを返す
+    /// 一意な索引ではキーごとに高々1レコードになる
     pub fn records_output(&self) -> i32 {
+        if self.options.unique {
+            return 1;
+        }
         self.stat_info.num_records / self.stat_info.distinct_values(&self.field_name)
     }
 
@@ -76,6 +192,25 @@ impl IndexInfo {
         if field_name != self.field_name {
             return 1;
         }
+        if self.options.unique {
+            return self.stat_info.num_records;
+        }
         self.stat_info.num_records / self.stat_info.distinct_values(field_name)
     }
+
+    /// prefix_key は索引のキーがフィールドの先頭だけの場合に PrefixKey を返す
+    /// 先頭の長さ（バイト数）がフィールドの値の最大のバイト数以上なら値全体を格納できるので None を返す
+    fn prefix_key(&self) -> Option<PrefixKey> {
+        let prefix_length = self.options.prefix_length?;
+        let field_length = self.table_layout.schema.length(&self.field_name)?;
+        if prefix_length >= field_length * MAX_CHAR_BYTES {
+            return None;
+        }
+        Some(PrefixKey {
+            length: prefix_length as usize,
+            table_name: self.table_name.clone(),
+            table_layout: self.table_layout.clone(),
+            field_name: self.field_name.clone(),
+        })
+    }
 }
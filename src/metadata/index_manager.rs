@@ -1,20 +1,32 @@
 use super::{
+    catalog::{self, idxcat, index_catalog_schema, INDEX_CATALOG},
+    catalog_cache::INDEX_CATALOGS,
     index_info::IndexInfo,
+    metadata_snapshot::IndexDef,
     stat_manager::StatManager,
-    table_manager::{TableManager, MAX_NAME},
+    table_manager::TableManager,
 };
 use crate::{
+    index::{IndexOptions, IndexType},
     query::scan::Scan,
-    record::{layout::Layout, schema::Schema, table_scan::TableScan},
+    record::{layout::Layout, table_scan::TableScan},
     tx::transaction::Transaction,
     unlock,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
 };
 
+/// IndexManager は索引のカタログ（idxcat）を管理する
+/// idxcat には索引の名前と対象のフィールドに加えて、索引の種類とオプションを格納する
+///
+/// | indexname | tablename | fieldname | indextype | isunique | prefixlen |
+/// |-----------|-----------|-----------|-----------|----------|-----------|
+///
+/// prefixlen が 0 の場合は値全体をキーにする
+/// 種類とオプションの列がない古いカタログは、ハッシュ索引として読み込む
 pub struct IndexManager {
     layout: Arc<Layout>,
     table_manager: Arc<Mutex<TableManager>>,
@@ -29,14 +41,11 @@ impl IndexManager {
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Self> {
         if is_new {
-            let mut schema = Schema::default();
-            schema.add_string_field("indexname", MAX_NAME);
-            schema.add_string_field("tablename", MAX_NAME);
-            schema.add_string_field("fieldname", MAX_NAME);
-            unlock!(table_manager).create_table("idxcat", Arc::new(schema), tx.clone())?;
+            let schema = Arc::new(index_catalog_schema());
+            unlock!(table_manager).create_table(INDEX_CATALOG, schema, tx.clone())?;
         }
 
-        let layout = Arc::new(unlock!(table_manager).get_layout("idxcat", tx.clone())?);
+        let layout = Arc::new(unlock!(table_manager).get_layout(INDEX_CATALOG, tx.clone())?);
 
         Ok(Self {
             layout,
@@ -50,13 +59,30 @@ impl IndexManager {
         index_name: &str,
         table_name: &str,
         field_name: &str,
+        options: &IndexOptions,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<()> {
-        let mut ts = TableScan::new(tx, table_name, self.layout.clone())?;
+        let snapshot = unlock!(tx).metadata_snapshot();
+        unlock!(snapshot).forget(table_name);
+        if !self.layout.schema.has_field(idxcat::INDEX_TYPE) && *options != IndexOptions::default()
+        {
+            bail!("index catalog does not support index options");
+        }
+        if options.prefix_length.is_some() && options.index_type != IndexType::BTree {
+            bail!("prefix length is only for btree index: {}", index_name);
+        }
+
+        let mut ts = TableScan::new(tx, INDEX_CATALOG, self.layout.clone())?;
         ts.insert()?;
-        ts.set_string("indexname", index_name)?;
-        ts.set_string("tablename", table_name)?;
-        ts.set_string("fieldname", field_name)?;
+        ts.set_string(idxcat::INDEX_NAME, index_name)?;
+        ts.set_string(idxcat::TABLE_NAME, table_name)?;
+        ts.set_string(idxcat::FIELD_NAME, field_name)?;
+        if self.layout.schema.has_field(idxcat::INDEX_TYPE) {
+            ts.set_string(idxcat::INDEX_TYPE, &options.index_type.to_string())?;
+            ts.set_int(idxcat::IS_UNIQUE, options.unique as i32)?;
+            ts.set_int(idxcat::PREFIX_LENGTH, options.prefix_length.unwrap_or(0))?;
+        }
+        ts.close();
         Ok(())
     }
 
@@ -66,32 +92,90 @@ impl IndexManager {
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<HashMap<String, IndexInfo>> {
         let mut result = HashMap::new();
+        let index_defs = self.get_index_defs(table_name, tx.clone())?;
+        if index_defs.is_empty() {
+            return Ok(result);
+        }
+
+        let table_layout =
+            Arc::new(unlock!(self.table_manager).get_layout(table_name, tx.clone())?);
+        let table_stat_info = self.stat_manager.lock().unwrap().get_stat_info(
+            table_name,
+            table_layout.clone(),
+            tx.clone(),
+        )?;
+        for index_def in index_defs {
+            let index_info = IndexInfo::new(
+                index_def.index_name.clone(),
+                table_name.to_string(),
+                index_def.field_name,
+                table_layout.clone(),
+                index_def.options,
+                tx.clone(),
+                table_stat_info.clone(),
+            )?;
+            result.insert(index_def.index_name, index_info);
+        }
 
-        let mut ts = TableScan::new(tx.clone(), table_name, self.layout.clone())?;
+        Ok(result)
+    }
+
+    /// get_index_defs はテーブルの索引の定義を返す
+    /// 同じトランザクションで読み込んだことがあれば、idxcat を読み直さずにそのときの定義を返す
+    /// 他のトランザクションが読み込んでキャッシュにあれば、idxcat の共有ロックだけを取ってキャッシュの定義を返す
+    fn get_index_defs(
+        &mut self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Vec<IndexDef>> {
+        let snapshot = unlock!(tx).metadata_snapshot();
+        if let Some(index_defs) = unlock!(snapshot).index_defs(table_name) {
+            return Ok(index_defs);
+        }
+        let cache = unlock!(tx).catalog_cache();
+        if let Some(index_defs) = cache
+            .as_ref()
+            .and_then(|cache| cache.index_defs(table_name))
+        {
+            for catalog in INDEX_CATALOGS {
+                unlock!(tx).s_lock_file(&catalog::file_name(catalog))?;
+            }
+            unlock!(snapshot).set_index_defs(table_name, index_defs.clone());
+            return Ok(index_defs);
+        }
 
+        let generation = cache.as_ref().map(|cache| cache.generation());
+        let mut index_defs = vec![];
+        let mut ts = TableScan::new(tx.clone(), INDEX_CATALOG, self.layout.clone())?;
         while ts.next()? {
-            if ts.get_string("tablename")? == table_name {
-                let index_name = ts.get_string("indexname")?;
-                let field_name = ts.get_string("fieldname")?;
-                let table_layout =
-                    Arc::new(unlock!(self.table_manager).get_layout(table_name, tx.clone())?);
-                let table_stat_info = self.stat_manager.lock().unwrap().get_stat_info(
-                    table_name,
-                    table_layout.clone(),
-                    tx.clone(),
-                )?;
-                let index_info = IndexInfo::new(
-                    index_name.clone(),
-                    field_name,
-                    table_layout.schema.clone(),
-                    tx.clone(),
-                    table_stat_info,
-                )?;
-                result.insert(index_name, index_info);
+            if ts.get_string(idxcat::TABLE_NAME)? == table_name {
+                index_defs.push(IndexDef {
+                    index_name: ts.get_string(idxcat::INDEX_NAME)?,
+                    field_name: ts.get_string(idxcat::FIELD_NAME)?,
+                    options: self.read_options(&mut ts)?,
+                });
             }
         }
+        ts.close();
 
-        Ok(result)
+        unlock!(snapshot).set_index_defs(table_name, index_defs.clone());
+        if let (Some(cache), Some(generation)) = (unlock!(tx).catalog_cache(), generation) {
+            cache.set_index_defs(generation, table_name, index_defs.clone());
+        }
+        Ok(index_defs)
+    }
+
+    /// read_options は現在のレコードから索引の種類とオプションを読み込む
+    fn read_options(&self, ts: &mut TableScan) -> Result<IndexOptions> {
+        if !self.layout.schema.has_field(idxcat::INDEX_TYPE) {
+            return Ok(IndexOptions::default());
+        }
+        let prefix_length = ts.get_int(idxcat::PREFIX_LENGTH)?;
+        Ok(IndexOptions {
+            index_type: ts.get_string(idxcat::INDEX_TYPE)?.parse()?,
+            unique: ts.get_int(idxcat::IS_UNIQUE)? != 0,
+            prefix_length: (prefix_length > 0).then_some(prefix_length),
+        })
     }
 }
 
@@ -103,6 +187,7 @@ mod test {
     use tempfile::tempdir;
 
     use crate::{
+        index::{IndexOptions, IndexType},
         metadata::{stat_manager::StatManager, table_manager::TableManager},
         record::schema::Schema,
         server::db::TinyDB,
@@ -136,10 +221,38 @@ mod test {
             tx.clone(),
         )?;
 
-        index_manager.create_index("test_index", "test", "foo", tx.clone())?;
+        index_manager.create_index(
+            "test_index",
+            "test",
+            "foo",
+            &IndexOptions::default(),
+            tx.clone(),
+        )?;
+        let options = IndexOptions {
+            index_type: IndexType::BTree,
+            unique: true,
+            prefix_length: Some(4),
+        };
+        index_manager.create_index("test_btree_index", "test", "foo", &options, tx.clone())?;
         let index_info = index_manager.get_index_info("test", tx.clone())?;
 
-        index_info.get("test_index").expect("index not found");
+        let hash_index = index_info.get("test_index").expect("index not found");
+        assert_eq!(hash_index.index_type(), IndexType::Hash);
+        assert!(!hash_index.is_unique());
+
+        let btree_index = index_info.get("test_btree_index").expect("index not found");
+        assert_eq!(btree_index.index_type(), IndexType::BTree);
+        assert!(btree_index.is_unique());
+        assert_eq!(btree_index.records_output(), 1);
+
+        // ハッシュ索引では先頭だけをキーにできない
+        let options = IndexOptions {
+            prefix_length: Some(4),
+            ..Default::default()
+        };
+        assert!(index_manager
+            .create_index("test_bad_index", "test", "foo", &options, tx.clone())
+            .is_err());
 
         Ok(())
     }
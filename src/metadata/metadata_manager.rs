@@ -1,24 +1,45 @@
 use crate::{
+    index::IndexOptions,
+    query::grant_data::Privilege,
     record::{layout::Layout, schema::Schema},
     tx::transaction::Transaction,
     unlock,
 };
 
 use super::{
-    index_info::IndexInfo, index_manager::IndexManager, stat_info::StatInfo,
-    stat_manager::StatManager, table_manager::TableManager, view_manager::ViewManager,
+    cluster_manager::{ClusterInfo, ClusterManager},
+    index_info::IndexInfo,
+    index_manager::IndexManager,
+    privilege_manager::PrivilegeManager,
+    stat_info::StatInfo,
+    stat_manager::StatManager,
+    table_manager::TableManager,
+    view_manager::ViewManager,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+/// VirtualIndex はカタログに登録せず、コストの見積もりだけに使う索引
+#[derive(Debug, Clone)]
+struct VirtualIndex {
+    index_name: String,
+    table_name: String,
+    field_name: String,
+    options: IndexOptions,
+}
+
 pub struct MetadataManager {
     table_manager: Arc<Mutex<TableManager>>,
     view_manager: Arc<Mutex<ViewManager>>,
     stat_manager: Arc<Mutex<StatManager>>,
     index_manager: Arc<Mutex<IndexManager>>,
+    privilege_manager: Arc<Mutex<PrivilegeManager>>,
+    cluster_manager: Arc<Mutex<ClusterManager>>,
+    virtual_indexes: Vec<VirtualIndex>,
 }
 
 impl MetadataManager {
@@ -42,12 +63,23 @@ impl MetadataManager {
             )
             .unwrap(),
         ));
+        let privilege_manager = Arc::new(Mutex::new(PrivilegeManager::new(
+            table_manager.clone(),
+            tx.clone(),
+        )?));
+        let cluster_manager = Arc::new(Mutex::new(ClusterManager::new(
+            table_manager.clone(),
+            tx.clone(),
+        )?));
 
         Ok(Self {
             table_manager,
             view_manager,
             stat_manager,
             index_manager,
+            privilege_manager,
+            cluster_manager,
+            virtual_indexes: vec![],
         })
     }
 
@@ -60,7 +92,7 @@ impl MetadataManager {
         unlock!(self.table_manager).create_table(table_name, schema, tx.clone())
     }
 
-    pub fn get_layout(&mut self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Layout> {
+    pub fn get_layout(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Layout> {
         unlock!(self.table_manager).get_layout(table_name, tx.clone())
     }
 
@@ -77,17 +109,152 @@ impl MetadataManager {
         index_name: &str,
         table_name: &str,
         field_name: &str,
+        options: &IndexOptions,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<()> {
-        unlock!(self.index_manager).create_index(index_name, table_name, field_name, tx.clone())
+        unlock!(self.index_manager).create_index(
+            index_name,
+            table_name,
+            field_name,
+            options,
+            tx.clone(),
+        )
     }
 
+    /// get_index_info はテーブルの索引の情報を返す。仮想の索引も含む
     pub fn get_index_info(
         &self,
         table_name: &str,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<HashMap<String, IndexInfo>> {
-        unlock!(self.index_manager).get_index_info(table_name, tx.clone())
+        let mut index_infos = unlock!(self.index_manager).get_index_info(table_name, tx.clone())?;
+        for index in &self.virtual_indexes {
+            if index.table_name == table_name && !index_infos.contains_key(&index.index_name) {
+                let index_info = self.virtual_index_info(index, tx.clone())?;
+                index_infos.insert(index.index_name.clone(), index_info);
+            }
+        }
+        Ok(index_infos)
+    }
+
+    /// create_virtual_index は仮想の索引を登録する
+    ///
+    /// 仮想の索引はカタログにもファイルにも書き込まず、この MetadataManager の中にだけある
+    /// プランナーはほかの索引と同じようにコストを見積もるので、EXPLAIN で索引があった場合のプランを確かめられる
+    /// 中身がないので開けず、選ばれたプランを実行するとエラーになる
+    pub fn create_virtual_index(
+        &mut self,
+        index_name: &str,
+        table_name: &str,
+        field_name: &str,
+        options: &IndexOptions,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        if self
+            .get_index_info(table_name, tx.clone())?
+            .contains_key(index_name)
+        {
+            bail!("index already exists: {}", index_name);
+        }
+        let index = VirtualIndex {
+            index_name: index_name.to_string(),
+            table_name: table_name.to_string(),
+            field_name: field_name.to_string(),
+            options: options.clone(),
+        };
+        // 索引を作れないフィールドはここでエラーにする
+        self.virtual_index_info(&index, tx)?;
+        self.virtual_indexes.push(index);
+        Ok(())
+    }
+
+    /// drop_virtual_index は仮想の索引を取り除く。なければエラーを返す
+    pub fn drop_virtual_index(&mut self, index_name: &str) -> Result<()> {
+        let Some(i) = self
+            .virtual_indexes
+            .iter()
+            .position(|index| index.index_name == index_name)
+        else {
+            bail!("virtual index not found: {}", index_name);
+        };
+        self.virtual_indexes.remove(i);
+        Ok(())
+    }
+
+    fn virtual_index_info(
+        &self,
+        index: &VirtualIndex,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<IndexInfo> {
+        let layout =
+            Arc::new(unlock!(self.table_manager).get_layout(&index.table_name, tx.clone())?);
+        let stat_info = self.get_stat_info(&index.table_name, layout.clone(), tx.clone())?;
+        let index_info = IndexInfo::new(
+            index.index_name.clone(),
+            index.table_name.clone(),
+            index.field_name.clone(),
+            layout,
+            index.options.clone(),
+            tx,
+            stat_info,
+        )?;
+        Ok(index_info.into_virtual())
+    }
+
+    pub fn grant(
+        &self,
+        grantee: &str,
+        table_name: &str,
+        privileges: &[Privilege],
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        unlock!(self.privilege_manager).grant(grantee, table_name, privileges, tx.clone())
+    }
+
+    pub fn revoke(
+        &self,
+        grantee: &str,
+        table_name: &str,
+        privileges: &[Privilege],
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<i32> {
+        unlock!(self.privilege_manager).revoke(grantee, table_name, privileges, tx.clone())
+    }
+
+    pub fn has_privilege(
+        &self,
+        grantee: &str,
+        table_name: &str,
+        privilege: Privilege,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<bool> {
+        unlock!(self.privilege_manager).has_privilege(grantee, table_name, privilege, tx.clone())
+    }
+
+    pub fn create_cluster_key(
+        &self,
+        table_name: &str,
+        field_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        unlock!(self.cluster_manager).create_cluster_key(table_name, field_name, tx.clone())
+    }
+
+    pub fn get_cluster_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<ClusterInfo>> {
+        unlock!(self.cluster_manager).get_cluster_info(table_name, tx.clone())
+    }
+
+    pub fn set_sorted(
+        &self,
+        table_name: &str,
+        sorted: bool,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        unlock!(self.cluster_manager).set_sorted(table_name, sorted, tx.clone())
     }
 
     pub fn get_stat_info(
@@ -98,6 +265,57 @@ impl MetadataManager {
     ) -> Result<StatInfo> {
         unlock!(self.stat_manager).get_stat_info(table_name, layout, tx.clone())
     }
+
+    /// is_empty はテーブルにレコードがないかを返す。テーブルがない場合は false を返す
+    pub fn is_empty(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<bool> {
+        let layout = self.get_layout(table_name, tx.clone())?;
+        if layout.schema.fields.is_empty() {
+            return Ok(false);
+        }
+        unlock!(self.stat_manager).is_empty(table_name, Arc::new(layout), tx)
+    }
+
+    /// forget_stat_info はテーブルの統計を忘れて、次に使うときに取り直すようにする
+    /// テーブルの大きさが大きく変わったときに使う
+    pub fn forget_stat_info(&self, table_name: &str) {
+        unlock!(self.stat_manager).forget(table_name)
+    }
+
+    /// analyze はテーブルの統計を sample_fraction の割合のブロックから取り直す
+    /// 1.0 ですべてのブロックを読む
+    pub fn analyze(
+        &mut self,
+        table_name: &str,
+        sample_fraction: f64,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<StatInfo> {
+        let layout = self.get_layout(table_name, tx.clone())?;
+        if layout.schema.fields.is_empty() {
+            bail!("table not found: {}", table_name);
+        }
+        unlock!(self.stat_manager).analyze(table_name, Arc::new(layout), sample_fraction, tx)
+    }
+
+    /// record_modifications はテーブルのレコードを count 件変更したことを記録する
+    /// 変更の数がレコード数の一定の割合を超えると、次に統計を使うときに取り直す
+    pub fn record_modifications(&self, table_name: &str, count: i32) {
+        unlock!(self.stat_manager).record_modifications(table_name, count)
+    }
+
+    /// set_refresh_fraction は統計を取り直すきっかけにする、レコード数に対する変更の数の割合を設定する
+    pub fn set_refresh_fraction(&self, refresh_fraction: f64) -> Result<()> {
+        unlock!(self.stat_manager).set_refresh_fraction(refresh_fraction)
+    }
+
+    /// set_max_stat_age は analyze で tblcat に書き込んだ統計を信用する期間を設定する
+    pub fn set_max_stat_age(&self, max_stat_age: Option<Duration>) {
+        unlock!(self.stat_manager).set_max_stat_age(max_stat_age)
+    }
+
+    /// set_sample_fraction は自動で統計を取り直すときに読むブロックの割合を設定する
+    pub fn set_sample_fraction(&self, sample_fraction: f64) -> Result<()> {
+        unlock!(self.stat_manager).set_sample_fraction(sample_fraction)
+    }
 }
 
 //#[cfg(test)]
@@ -0,0 +1,78 @@
+use super::{
+    cluster_manager::ClusterInfo, index_info::IndexInfo, metadata_manager::MetadataManager,
+    stat_info::StatInfo,
+};
+use crate::{record::layout::Layout, tx::transaction::Transaction};
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// MetadataProvider はプランナーがプランを作るときに読むメタデータ
+///
+/// MetadataManager はカタログから読み込み、FakeMetadata はメモリの上に組み立てたものを返す
+/// プランナーのテストでは FakeMetadata を使うと、ディスクにカタログを作らずにプランの形を確かめられる
+pub trait MetadataProvider: Send {
+    /// get_layout はテーブルのレイアウトを返す。テーブルがない場合はフィールドのないレイアウトを返す
+    fn get_layout(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Layout>;
+    fn get_view_def(&self, view_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Option<String>>;
+    fn get_index_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<HashMap<String, IndexInfo>>;
+    fn get_stat_info(
+        &self,
+        table_name: &str,
+        layout: Arc<Layout>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<StatInfo>;
+    fn get_cluster_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<ClusterInfo>>;
+    /// is_empty はテーブルにレコードがないと確かめられた場合に true を返す
+    /// プランナーはこれを使って、空のテーブルを読むプランを空のプランに置き換える
+    fn is_empty(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<bool>;
+}
+
+impl MetadataProvider for MetadataManager {
+    fn get_layout(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Layout> {
+        MetadataManager::get_layout(self, table_name, tx)
+    }
+
+    fn get_view_def(&self, view_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Option<String>> {
+        MetadataManager::get_view_def(self, view_name, tx)
+    }
+
+    fn get_index_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<HashMap<String, IndexInfo>> {
+        MetadataManager::get_index_info(self, table_name, tx)
+    }
+
+    fn get_stat_info(
+        &self,
+        table_name: &str,
+        layout: Arc<Layout>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<StatInfo> {
+        MetadataManager::get_stat_info(self, table_name, layout, tx)
+    }
+
+    fn get_cluster_info(
+        &self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<ClusterInfo>> {
+        MetadataManager::get_cluster_info(self, table_name, tx)
+    }
+
+    fn is_empty(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<bool> {
+        MetadataManager::is_empty(self, table_name, tx)
+    }
+}
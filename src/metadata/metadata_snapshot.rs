@@ -0,0 +1,53 @@
+use crate::{index::IndexOptions, record::layout::Layout};
+use std::collections::HashMap;
+
+/// IndexDef は idxcat から読み込んだ索引の定義
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexDef {
+    pub index_name: String,
+    pub field_name: String,
+    pub options: IndexOptions,
+}
+
+/// MetadataSnapshot はトランザクションの中で一度カタログから読み込んだテーブルのメタデータ
+///
+/// 同じトランザクションでは、テーブルのレイアウトと索引の定義を最初に読んだときのまま使い続ける
+/// カタログを読んだときに取った共有ロックはコミットかロールバックまで持ち続けるので、
+/// 他のトランザクションは実行中のクエリが使っているテーブルのカタログを書き換えられない
+/// このトランザクション自身がテーブルや索引を作った場合は、そのテーブルの分を忘れて読み直す
+/// 統計は変更に合わせて取り直すので、ここには含めない
+#[derive(Debug, Default)]
+pub struct MetadataSnapshot {
+    layouts: HashMap<String, Layout>,
+    index_defs: HashMap<String, Vec<IndexDef>>,
+}
+
+impl MetadataSnapshot {
+    pub fn layout(&self, table_name: &str) -> Option<Layout> {
+        self.layouts.get(table_name).cloned()
+    }
+
+    pub fn set_layout(&mut self, table_name: &str, layout: Layout) {
+        self.layouts.insert(table_name.to_string(), layout);
+    }
+
+    pub fn index_defs(&self, table_name: &str) -> Option<Vec<IndexDef>> {
+        self.index_defs.get(table_name).cloned()
+    }
+
+    pub fn set_index_defs(&mut self, table_name: &str, index_defs: Vec<IndexDef>) {
+        self.index_defs.insert(table_name.to_string(), index_defs);
+    }
+
+    /// forget はテーブルのメタデータを忘れて、次に使うときにカタログから読み直すようにする
+    pub fn forget(&mut self, table_name: &str) {
+        self.layouts.remove(table_name);
+        self.index_defs.remove(table_name);
+    }
+
+    /// clear はトランザクションが終わったときにすべてのメタデータを忘れる
+    pub fn clear(&mut self) {
+        self.layouts.clear();
+        self.index_defs.clear();
+    }
+}
@@ -1,6 +1,13 @@
+pub mod catalog;
+pub mod catalog_cache;
+pub mod cluster_manager;
+pub mod fake_metadata;
 pub mod index_info;
 pub mod index_manager;
 pub mod metadata_manager;
+pub mod metadata_provider;
+pub mod metadata_snapshot;
+pub mod privilege_manager;
 pub mod stat_info;
 pub mod stat_manager;
 pub mod table_manager;
@@ -0,0 +1,149 @@
+use super::{
+    catalog::{privcat, privilege_catalog_schema, PRIVILEGE_CATALOG},
+    table_manager::TableManager,
+};
+use crate::{
+    query::{grant_data::Privilege, scan::Scan as _},
+    record::{layout::Layout, table_scan::TableScan},
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// PrivilegeManager はテーブルの権限のカタログ（privcat）を管理する
+/// 1つのレコードが1人のユーザーの1つのテーブルに対する1つの権限を表す
+///
+/// | grantee | tablename | privilege |
+/// |---------|-----------|-----------|
+///
+/// privcat がない古いデータベースを開いた場合は、privcat を作る
+pub struct PrivilegeManager {
+    layout: Arc<Layout>,
+}
+
+impl PrivilegeManager {
+    pub fn new(
+        table_manager: Arc<Mutex<TableManager>>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Self> {
+        let mut layout = unlock!(table_manager).get_layout(PRIVILEGE_CATALOG, tx.clone())?;
+        if !layout.schema.has_field(privcat::GRANTEE) {
+            let schema = Arc::new(privilege_catalog_schema());
+            unlock!(table_manager).create_table(PRIVILEGE_CATALOG, schema, tx.clone())?;
+            layout = unlock!(table_manager).get_layout(PRIVILEGE_CATALOG, tx)?;
+        }
+
+        Ok(Self {
+            layout: Arc::new(layout),
+        })
+    }
+
+    /// grant は権限を付与する。すでに持っている権限は追加しない
+    pub fn grant(
+        &self,
+        grantee: &str,
+        table_name: &str,
+        privileges: &[Privilege],
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        for privilege in privileges {
+            if self.has_privilege(grantee, table_name, *privilege, tx.clone())? {
+                continue;
+            }
+            let mut ts = TableScan::new(tx.clone(), PRIVILEGE_CATALOG, self.layout.clone())?;
+            ts.insert()?;
+            ts.set_string(privcat::GRANTEE, grantee)?;
+            ts.set_string(privcat::TABLE_NAME, table_name)?;
+            ts.set_string(privcat::PRIVILEGE, &privilege.to_string())?;
+            ts.close();
+        }
+        Ok(())
+    }
+
+    /// revoke は権限を取り消して、取り消した権限の数を返す
+    pub fn revoke(
+        &self,
+        grantee: &str,
+        table_name: &str,
+        privileges: &[Privilege],
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<i32> {
+        let mut count = 0;
+        let mut ts = TableScan::new(tx, PRIVILEGE_CATALOG, self.layout.clone())?;
+        while ts.next()? {
+            if self.matches(&mut ts, grantee, table_name)?
+                && privileges.contains(&ts.get_string(privcat::PRIVILEGE)?.parse()?)
+            {
+                ts.delete()?;
+                count += 1;
+            }
+        }
+        ts.close();
+        Ok(count)
+    }
+
+    pub fn has_privilege(
+        &self,
+        grantee: &str,
+        table_name: &str,
+        privilege: Privilege,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<bool> {
+        let mut ts = TableScan::new(tx, PRIVILEGE_CATALOG, self.layout.clone())?;
+        let mut found = false;
+        while !found && ts.next()? {
+            found = self.matches(&mut ts, grantee, table_name)?
+                && ts.get_string(privcat::PRIVILEGE)? == privilege.to_string();
+        }
+        ts.close();
+        Ok(found)
+    }
+
+    fn matches(&self, ts: &mut TableScan, grantee: &str, table_name: &str) -> Result<bool> {
+        Ok(ts.get_string(privcat::GRANTEE)? == grantee
+            && ts.get_string(privcat::TABLE_NAME)? == table_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+
+    use super::PrivilegeManager;
+    use crate::{
+        metadata::table_manager::TableManager, query::grant_data::Privilege, server::db::TinyDB,
+    };
+
+    #[test]
+    fn should_grant_and_revoke_privileges() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_grant_and_revoke_privileges");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+
+        let table_manager = Arc::new(Mutex::new(TableManager::new(true, tx.clone())?));
+        let manager = PrivilegeManager::new(table_manager, tx.clone())?;
+
+        manager.grant("alice", "student", &Privilege::ALL, tx.clone())?;
+        manager.grant("alice", "student", &[Privilege::Select], tx.clone())?;
+        assert!(manager.has_privilege("alice", "student", Privilege::Update, tx.clone())?);
+        assert!(!manager.has_privilege("bob", "student", Privilege::Select, tx.clone())?);
+        assert!(!manager.has_privilege("alice", "dept", Privilege::Select, tx.clone())?);
+
+        let count = manager.revoke(
+            "alice",
+            "student",
+            &[Privilege::Select, Privilege::Update],
+            tx.clone(),
+        )?;
+        assert_eq!(count, 2);
+        assert!(!manager.has_privilege("alice", "student", Privilege::Select, tx.clone())?);
+        assert!(manager.has_privilege("alice", "student", Privilege::Insert, tx.clone())?);
+
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// StatInfo はテーブルのブロック数とレコード数の統計
+///
+/// sample_rate は統計を取るときに読んだブロックの割合で、すべて読んだ場合は 1.0 になる
+/// 標本から取った統計の num_records は、読んだブロックのレコード数を sample_rate で割って全体に広げた見積もり
+#[derive(Debug, Clone, PartialEq)]
 pub struct StatInfo {
     pub num_blocks: i32,
     pub num_records: i32,
+    pub sample_rate: f64,
 }
 
 impl StatInfo {
@@ -9,9 +14,26 @@ impl StatInfo {
         Self {
             num_blocks,
             num_records,
+            sample_rate: 1.0,
         }
     }
 
+    /// sampled は sample_rate の割合のブロックで数えた sampled_records 件のレコードから、全体の統計を見積もる
+    pub fn sampled(num_blocks: i32, sampled_records: i32, sample_rate: f64) -> Self {
+        if sample_rate <= 0.0 || sample_rate >= 1.0 {
+            return Self::new(num_blocks, sampled_records);
+        }
+        Self {
+            num_blocks,
+            num_records: (sampled_records as f64 / sample_rate).round() as i32,
+            sample_rate,
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.sample_rate < 1.0
+    }
+
     pub fn distinct_values(&self, _field_name: &str) -> i32 {
         1 + (self.num_records / 3)
     }
@@ -1,20 +1,39 @@
-use super::{stat_info::StatInfo, table_manager::TableManager};
+use super::{
+    catalog::{tblcat, TABLE_CATALOG},
+    stat_info::StatInfo,
+    table_manager::{unix_time, TableManager},
+};
 use crate::{
     query::scan::Scan,
     record::{layout::Layout, table_scan::TableScan},
     tx::transaction::Transaction,
     unlock,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+/// StatManager はテーブルの統計を集めて覚えておく
+///
+/// sample_fraction が 1.0 より小さい場合は、すべてのブロックではなく一定の間隔で選んだブロックだけを読んで統計を見積もる
+/// 大きなテーブルでは統計を取り直すコストが下がるが、見積もりの精度も下がる
+///
+/// 更新のプランナーから知らされたテーブルごとの変更の数がレコード数の refresh_fraction の割合を超えると、
+/// 次に統計を使うときにそのテーブルの統計を取り直す
+///
+/// analyze で取り直した統計は tblcat にも書き込む。max_stat_age を設定すると、統計を取り直すときに
+/// その時間より新しい tblcat の統計はテーブルを読まずにそのまま使い、古い統計は信用せずに取り直す
 pub struct StatManager {
     table_manager: Arc<Mutex<TableManager>>,
     table_stats: HashMap<String, StatInfo>,
-    num_calls: i32,
+    /// 統計を取ってから変更したレコードの数
+    modifications: HashMap<String, i32>,
+    refresh_fraction: f64,
+    sample_fraction: f64,
+    max_stat_age: Option<Duration>,
 }
 
 impl StatManager {
@@ -22,12 +41,13 @@ impl StatManager {
         table_manager: Arc<Mutex<TableManager>>,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Self> {
-        let table_stats = HashMap::new();
-        let num_calls = 0;
         let mut sm = Self {
             table_manager,
-            table_stats,
-            num_calls,
+            table_stats: HashMap::new(),
+            modifications: HashMap::new(),
+            refresh_fraction: DEFAULT_REFRESH_FRACTION,
+            sample_fraction: 1.0,
+            max_stat_age: None,
         };
 
         sm.refresh_statistics(tx)?;
@@ -35,20 +55,79 @@ impl StatManager {
         Ok(sm)
     }
 
+    pub fn sample_fraction(&self) -> f64 {
+        self.sample_fraction
+    }
+
+    /// set_sample_fraction は統計を取り直すときに読むブロックの割合を設定する
+    /// 1.0 ですべてのブロックを読む
+    pub fn set_sample_fraction(&mut self, sample_fraction: f64) -> Result<()> {
+        check_sample_fraction(sample_fraction)?;
+        self.sample_fraction = sample_fraction;
+        Ok(())
+    }
+
+    pub fn refresh_fraction(&self) -> f64 {
+        self.refresh_fraction
+    }
+
+    /// set_refresh_fraction は統計を取り直すきっかけにする、レコード数に対する変更の数の割合を設定する
+    pub fn set_refresh_fraction(&mut self, refresh_fraction: f64) -> Result<()> {
+        if refresh_fraction.is_nan() || refresh_fraction <= 0.0 {
+            bail!("refresh fraction must be positive: {}", refresh_fraction);
+        }
+        self.refresh_fraction = refresh_fraction;
+        Ok(())
+    }
+
+    /// record_modifications はテーブルのレコードを count 件挿入、削除、変更したことを記録する
+    pub fn record_modifications(&mut self, table_name: &str, count: i32) {
+        *self
+            .modifications
+            .entry(table_name.to_string())
+            .or_default() += count;
+    }
+
+    pub fn max_stat_age(&self) -> Option<Duration> {
+        self.max_stat_age
+    }
+
+    /// set_max_stat_age は tblcat に書き込んだ統計を信用する期間を設定する
+    /// None ではいつも統計を取り直す
+    pub fn set_max_stat_age(&mut self, max_stat_age: Option<Duration>) {
+        self.max_stat_age = max_stat_age;
+    }
+
+    /// analyze はテーブルの統計を sample_fraction の割合のブロックから取り直して、覚えておく
+    /// 取り直した時刻と統計は tblcat にも書き込む
+    pub fn analyze(
+        &mut self,
+        table_name: &str,
+        layout: Arc<Layout>,
+        sample_fraction: f64,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<StatInfo> {
+        check_sample_fraction(sample_fraction)?;
+        let stat_info = Self::calc_table_stats(table_name, layout, sample_fraction, tx.clone())?;
+        unlock!(self.table_manager).set_analyzed(table_name, &stat_info, tx)?;
+        self.modifications.remove(table_name);
+        self.table_stats
+            .insert(table_name.to_string(), stat_info.clone());
+        Ok(stat_info)
+    }
+
     pub fn get_stat_info(
         &mut self,
         table_name: &str,
         layout: Arc<Layout>,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<StatInfo> {
-        self.num_calls += 1;
-        if self.num_calls > 100 {
-            self.refresh_statistics(tx.clone())?;
-        }
         match self.table_stats.get(table_name) {
-            Some(stat_info) => Ok(stat_info.clone()),
-            None => {
-                let stat_info = self.calc_table_stats(table_name, layout, tx.clone())?;
+            Some(stat_info) if !self.is_outdated(table_name, stat_info) => Ok(stat_info.clone()),
+            _ => {
+                let stat_info =
+                    Self::calc_table_stats(table_name, layout, self.sample_fraction, tx.clone())?;
+                self.modifications.remove(table_name);
                 self.table_stats
                     .insert(table_name.to_string(), stat_info.clone());
                 Ok(stat_info)
@@ -56,18 +135,63 @@ impl StatManager {
         }
     }
 
+    /// is_outdated は統計を取ってからの変更の数が、統計のレコード数の refresh_fraction の割合を超えたかを返す
+    fn is_outdated(&self, table_name: &str, stat_info: &StatInfo) -> bool {
+        let modifications = self.modifications.get(table_name).copied().unwrap_or(0);
+        modifications as f64 > stat_info.num_records as f64 * self.refresh_fraction
+    }
+
+    /// is_empty はテーブルにレコードがないかを返す
+    ///
+    /// 統計は挿入のたびには取り直さないので、統計でレコードがないとされたテーブルは先頭のブロックを読んで確かめる
+    /// 統計が古かった場合は、次に使うときに取り直すように忘れる
+    pub fn is_empty(
+        &mut self,
+        table_name: &str,
+        layout: Arc<Layout>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<bool> {
+        let stat_info = self.get_stat_info(table_name, layout.clone(), tx.clone())?;
+        if stat_info.num_records > 0 {
+            return Ok(false);
+        }
+        let mut ts = TableScan::new(tx, table_name, layout)?;
+        let empty = ts.block_count()? <= 1 && ts.count_records(0)? == 0;
+        ts.close();
+        if !empty {
+            self.table_stats.remove(table_name);
+        }
+        Ok(empty)
+    }
+
+    /// forget はテーブルの統計を忘れて、次に使うときに取り直すようにする
+    pub fn forget(&mut self, table_name: &str) {
+        self.table_stats.remove(table_name);
+        self.modifications.remove(table_name);
+    }
+
     pub fn refresh_statistics(&mut self, tx: Arc<Mutex<Transaction>>) -> Result<()> {
         self.table_stats = HashMap::new();
-        self.num_calls = 0;
+        self.modifications = HashMap::new();
 
         let table_catalog_layout =
-            Arc::new(unlock!(self.table_manager).get_layout("tblcat", tx.clone())?);
-        let mut ts = TableScan::new(tx.clone(), "tblcat", table_catalog_layout)?;
+            Arc::new(unlock!(self.table_manager).get_layout(TABLE_CATALOG, tx.clone())?);
+        let mut ts = TableScan::new(tx.clone(), TABLE_CATALOG, table_catalog_layout)?;
+        let has_analyzed = ts.has_field(tblcat::ANALYZED);
 
         while ts.next()? {
-            let table_name = ts.get_string("tblname")?;
+            let table_name = ts.get_string(tblcat::TABLE_NAME)?;
+            if has_analyzed && self.is_fresh(ts.get_int(tblcat::ANALYZED)?) {
+                let stat_info = StatInfo::new(
+                    ts.get_int(tblcat::NUM_BLOCKS)?,
+                    ts.get_int(tblcat::NUM_RECORDS)?,
+                );
+                self.table_stats.insert(table_name, stat_info);
+                continue;
+            }
             let layout = Arc::new(unlock!(self.table_manager).get_layout(&table_name, tx.clone())?);
-            let stat_info = self.calc_table_stats(&table_name, layout, tx.clone())?;
+            let stat_info =
+                Self::calc_table_stats(&table_name, layout, self.sample_fraction, tx.clone())?;
             self.table_stats.insert(table_name, stat_info);
         }
         ts.close();
@@ -75,25 +199,58 @@ impl StatManager {
         Ok(())
     }
 
+    /// is_fresh は analyzed の時刻に取り直した統計を信用できるかを返す。0 は取り直していないことを表す
+    fn is_fresh(&self, analyzed: i32) -> bool {
+        let Some(max_stat_age) = self.max_stat_age else {
+            return false;
+        };
+        analyzed > 0 && ((unix_time() - analyzed).max(0) as u64) <= max_stat_age.as_secs()
+    }
+
+    /// calc_table_stats はテーブルの統計を取る
+    ///
+    /// sample_fraction が 1.0 より小さい場合は、先頭から 1 / sample_fraction ブロックおきに読んだブロックのレコード数を数え、
+    /// 読んだブロックの割合で全体のレコード数を見積もる
     fn calc_table_stats(
-        &mut self,
         table_name: impl Into<String>,
         layout: Arc<Layout>,
+        sample_fraction: f64,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<StatInfo> {
-        let mut num_records = 0;
-        let mut num_blocks = 0;
-
+        let step = (1.0 / sample_fraction).round().max(1.0) as usize;
         let mut ts = TableScan::new(tx.clone(), table_name, layout)?;
-        while ts.next()? {
-            num_records += 1;
-            num_blocks = ts.get_rid()?.block_num + 1;
+        let num_blocks = ts.used_block_count()?;
+        let mut sampled_blocks = 0;
+        let mut sampled_records = 0;
+        let mut last_block = -1;
+        for block_num in (0..num_blocks).step_by(step) {
+            let count = ts.count_records(block_num)?;
+            if count > 0 {
+                last_block = block_num;
+            }
+            sampled_records += count;
+            sampled_blocks += 1;
         }
         ts.close();
 
-        let stat_info = StatInfo::new(num_blocks, num_records);
-        Ok(stat_info)
+        // すべて読んだ場合は、末尾の空のブロックを数えない
+        if sampled_blocks == num_blocks {
+            return Ok(StatInfo::new(last_block + 1, sampled_records));
+        }
+        let sample_rate = sampled_blocks as f64 / num_blocks as f64;
+        Ok(StatInfo::sampled(num_blocks, sampled_records, sample_rate))
+    }
+}
+
+/// DEFAULT_REFRESH_FRACTION はレコード数に対する変更の数がこの割合を超えたら統計を取り直すという既定の割合
+pub const DEFAULT_REFRESH_FRACTION: f64 = 0.1;
+
+/// check_sample_fraction は読むブロックの割合が 0 より大きく 1.0 以下かを確かめる
+fn check_sample_fraction(sample_fraction: f64) -> Result<()> {
+    if !(sample_fraction > 0.0 && sample_fraction <= 1.0) {
+        bail!("sample fraction must be in (0, 1]: {}", sample_fraction);
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -102,13 +259,21 @@ mod test {
     use crate::{
         buffer::buffer_manager::BufferManager,
         file::file_manager::FileManager,
-        log::log_manager::LogManager,
-        metadata::{stat_info::StatInfo, table_manager::TableManager},
+        log::log_manager::{LogManager, DEFAULT_LOG_FILE},
+        metadata::{
+            metadata_manager::MetadataManager, stat_info::StatInfo, table_manager::TableManager,
+        },
+        query::scan::Scan as _,
+        record::{schema::Schema, table_scan::TableScan},
+        server::db::TinyDB,
         tx::{concurrency::lock_table::LockTable, transaction::Transaction},
-        LOG_FILE,
+        unlock,
     };
     use anyhow::Result;
-    use std::sync::{Arc, Condvar, Mutex};
+    use std::{
+        sync::{Arc, Condvar, Mutex},
+        time::Duration,
+    };
     use tempfile::tempdir;
 
     #[test]
@@ -117,13 +282,13 @@ mod test {
         let file_manager = Arc::new(Mutex::new(FileManager::new(db_dir, 400)?));
         let log_manager = Arc::new(Mutex::new(LogManager::new(
             file_manager.clone(),
-            LOG_FILE.into(),
+            DEFAULT_LOG_FILE.into(),
         )?));
-        let buffer_manager = Arc::new(Mutex::new(BufferManager::new(
+        let buffer_manager = Arc::new(BufferManager::new(
             file_manager.clone(),
             log_manager.clone(),
             8,
-        )));
+        ));
         let lock_table = Arc::new((Mutex::new(LockTable::default()), Condvar::new()));
 
         let tx = Arc::new(Mutex::new(Transaction::new(
@@ -146,10 +311,146 @@ mod test {
             stat_info,
             StatInfo {
                 num_blocks: 1,
-                num_records: 2,
+                num_records: 4,
+                sample_rate: 1.0,
             }
         );
 
         Ok(())
     }
+
+    #[test]
+    fn should_estimate_stats_from_sampled_blocks() -> Result<()> {
+        let db_dir = tempdir()?
+            .path()
+            .join("should_estimate_stats_from_sampled_blocks");
+        let db = TinyDB::new(db_dir, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut md = MetadataManager::new(true, tx.clone())?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        md.create_table("T", Arc::new(schema), tx.clone())?;
+        let layout = Arc::new(md.get_layout("T", tx.clone())?);
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        for i in 0..500 {
+            ts.insert()?;
+            ts.set_int("A", i)?;
+        }
+        // ファイルはまとめて伸ばすが、末尾のまだ使っていないブロックは数えない
+        let num_blocks = ts.get_rid()?.block_num + 1;
+        assert!(ts.block_count()? > num_blocks);
+        ts.close();
+
+        let full = md.analyze("T", 1.0, tx.clone())?;
+        assert_eq!(full, StatInfo::new(num_blocks, 500));
+        assert!(!full.is_sampled());
+
+        let sampled = md.analyze("T", 0.25, tx.clone())?;
+        assert!(sampled.is_sampled());
+        assert_eq!(sampled.num_blocks, num_blocks);
+        assert!((sampled.sample_rate - 0.25).abs() < 0.05, "{:?}", sampled);
+        assert!((sampled.num_records - 500).abs() <= 50, "{:?}", sampled);
+        // 取り直した統計は覚えておく
+        let layout = Arc::new(md.get_layout("T", tx.clone())?);
+        assert_eq!(md.get_stat_info("T", layout, tx.clone())?, sampled);
+
+        assert!(md.analyze("T", 0.0, tx.clone()).is_err());
+        assert!(md.analyze("T", 1.5, tx.clone()).is_err());
+        assert!(md.analyze("missing", 1.0, tx.clone()).is_err());
+        unlock!(tx).commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_refresh_stats_after_modifications() -> Result<()> {
+        let db_dir = tempdir()?
+            .path()
+            .join("should_refresh_stats_after_modifications");
+        let db = TinyDB::new(db_dir, 400, 8)?;
+        let tx = db.transaction()?;
+        let md = MetadataManager::new(true, tx.clone())?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        md.create_table("T", Arc::new(schema), tx.clone())?;
+        let layout = Arc::new(md.get_layout("T", tx.clone())?);
+        let insert = |count: i32| -> Result<()> {
+            let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+            for i in 0..count {
+                ts.insert()?;
+                ts.set_int("A", i)?;
+            }
+            ts.close();
+            Ok(())
+        };
+        insert(100)?;
+        md.record_modifications("T", 100);
+        let records = |md: &MetadataManager| -> Result<i32> {
+            Ok(md
+                .get_stat_info("T", layout.clone(), tx.clone())?
+                .num_records)
+        };
+        assert_eq!(records(&md)?, 100);
+
+        // 変更の数がレコード数の 10% 以下の間は統計を取り直さない
+        insert(10)?;
+        md.record_modifications("T", 10);
+        assert_eq!(records(&md)?, 100);
+        insert(1)?;
+        md.record_modifications("T", 1);
+        assert_eq!(records(&md)?, 111);
+
+        md.set_refresh_fraction(0.5)?;
+        insert(50)?;
+        md.record_modifications("T", 50);
+        assert_eq!(records(&md)?, 111);
+        assert!(md.set_refresh_fraction(0.0).is_err());
+        unlock!(tx).commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_trust_analyzed_stats_within_max_age() -> Result<()> {
+        let db_dir = tempdir()?
+            .path()
+            .join("should_trust_analyzed_stats_within_max_age");
+        let db = TinyDB::new(db_dir, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut md = MetadataManager::new(true, tx.clone())?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        md.create_table("T", Arc::new(schema), tx.clone())?;
+        let layout = Arc::new(md.get_layout("T", tx.clone())?);
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        for i in 0..10 {
+            ts.insert()?;
+            ts.set_int("A", i)?;
+        }
+        ts.close();
+        let analyzed = md.analyze("T", 1.0, tx.clone())?;
+        assert_eq!(analyzed.num_records, 10);
+
+        // analyze の後に挿入したレコードは tblcat の統計には含まれない
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        ts.insert()?;
+        ts.set_int("A", 10)?;
+        ts.close();
+
+        let table_manager = Arc::new(Mutex::new(TableManager::new(false, tx.clone())?));
+        let mut stat_manager = StatManager::new(table_manager.clone(), tx.clone())?;
+        let layout = Arc::new(unlock!(table_manager).get_layout("T", tx.clone())?);
+        let stat_info = stat_manager.get_stat_info("T", layout.clone(), tx.clone())?;
+        assert_eq!(stat_info.num_records, 11);
+
+        stat_manager.set_max_stat_age(Some(Duration::from_secs(3600)));
+        stat_manager.refresh_statistics(tx.clone())?;
+        let stat_info = stat_manager.get_stat_info("T", layout.clone(), tx.clone())?;
+        assert_eq!(stat_info, analyzed);
+
+        stat_manager.set_max_stat_age(None);
+        stat_manager.refresh_statistics(tx.clone())?;
+        let stat_info = stat_manager.get_stat_info("T", layout, tx.clone())?;
+        assert_eq!(stat_info.num_records, 11);
+        unlock!(tx).commit()?;
+        Ok(())
+    }
 }
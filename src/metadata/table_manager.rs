@@ -1,14 +1,32 @@
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+use super::{
+    catalog::{
+        self, field_catalog_schema, field_option, field_option_catalog_schema, fldcat,
+        legacy_table_catalog_schema, table_catalog_schema, tblcat, COLLATION_CATALOG,
+        FIELD_CATALOG, TABLE_CATALOG, TRUNCATION_CATALOG,
+    },
+    catalog_cache::LAYOUT_CATALOGS,
+    stat_info::StatInfo,
+};
 use crate::{
+    index::INDEX_FILE_PREFIX,
     query::scan::Scan as _,
-    record::{layout::Layout, schema::Schema, table_scan::TableScan},
+    record::{
+        collation::Collation,
+        layout::Layout,
+        schema::{FieldTypes, Schema},
+        table_scan::TableScan,
+        truncation::TruncationPolicy,
+    },
     tx::transaction::Transaction,
+    unlock,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 pub static MAX_NAME: i32 = 16;
 
@@ -17,6 +35,11 @@ pub struct TableManager {
     /// メタデータは以下となる
     ///   - テーブル名
     ///   - スロット（レコード）のサイズ
+    ///   - 作成した時刻（created）
+    ///   - 最後に統計を取り直した時刻（analyzed、取り直していなければ 0）
+    ///   - そのときのブロック数とレコード数の見積もり（numblocks、numrecs）
+    ///
+    /// 時刻は UNIX 時間の秒数で、作成した時刻などの列がない古いデータベースではカタログにあるレイアウトを使う
     table_catlog_layout: Arc<Layout>,
     /// テーブルのカラムごとのメタデータを保持する
     /// メタデータは以下となる
@@ -26,70 +49,339 @@ pub struct TableManager {
     ///   - フィールドの長さ
     ///   - フィールドのオフセット（スロットの先頭からの位置）
     field_catlog_layout: Arc<Layout>,
+    /// Binary 以外の照合順序を指定したフィールドの照合順序を保持する（collcat）
+    /// メタデータは以下となる
+    ///   - テーブル名
+    ///   - フィールド名
+    ///   - 照合順序の名前
+    ///
+    /// collcat がない古いデータベースを開いた場合は、collcat を作る
+    /// カタログのないデータベースでは None になり、どのフィールドも Binary になる
+    collation_catlog_layout: Option<Arc<Layout>>,
+    /// 長すぎる文字列を切り詰めるフィールドを保持する（trunccat）
+    /// メタデータは以下となる
+    ///   - テーブル名
+    ///   - フィールド名
+    ///   - TruncationPolicy の名前
+    ///
+    /// collcat と同じく、ない場合は作り、カタログのないデータベースでは None になる
+    truncation_catlog_layout: Option<Arc<Layout>>,
 }
 
 impl TableManager {
     pub fn new(is_new: bool, tx: Arc<Mutex<Transaction>>) -> Result<Self> {
-        let mut tcs = Schema::default();
-        tcs.add_string_field("tblname", MAX_NAME);
-        tcs.add_int_field("slotsize");
-        let table_catlog_layout = Arc::new(Layout::try_from_schema(Arc::new(tcs))?);
-
-        let mut fcs = Schema::default();
-        fcs.add_string_field("tblname", MAX_NAME);
-        fcs.add_string_field("fldname", MAX_NAME);
-        fcs.add_int_field("type");
-        fcs.add_int_field("length");
-        fcs.add_int_field("offset");
-        let field_catlog_layout = Arc::new(Layout::try_from_schema(Arc::new(fcs))?);
+        // 古いカタログと先頭の列の位置が同じなので、古いレイアウトでも tblcat 自身のレコードは読み込める
+        let legacy_layout = Arc::new(Layout::try_from_schema(Arc::new(
+            legacy_table_catalog_schema(),
+        ))?);
+        let table_catlog_layout = if is_new {
+            Arc::new(Layout::try_from_schema(Arc::new(table_catalog_schema()))?)
+        } else {
+            legacy_layout
+        };
+        let field_catlog_layout =
+            Arc::new(Layout::try_from_schema(Arc::new(field_catalog_schema()))?);
 
         let mut tm = Self {
             table_catlog_layout,
             field_catlog_layout,
+            collation_catlog_layout: None,
+            truncation_catlog_layout: None,
         };
 
         if is_new {
-            tm.create_table("tblcat", tm.table_catlog_layout.schema.clone(), tx.clone())?;
-            tm.create_table("fldcat", tm.field_catlog_layout.schema.clone(), tx.clone())?;
+            tm.create_table(
+                TABLE_CATALOG,
+                tm.table_catlog_layout.schema.clone(),
+                tx.clone(),
+            )?;
+            tm.create_table(
+                FIELD_CATALOG,
+                tm.field_catlog_layout.schema.clone(),
+                tx.clone(),
+            )?;
+        }
+        let layout = tm.read_layout(TABLE_CATALOG, tx.clone())?;
+        if layout.schema.has_field(tblcat::TABLE_NAME) {
+            tm.table_catlog_layout = Arc::new(layout);
+            tm.collation_catlog_layout = Some(tm.open_field_option_catalog(
+                COLLATION_CATALOG,
+                field_option::COLLATION,
+                tx.clone(),
+            )?);
+            tm.truncation_catlog_layout =
+                Some(tm.open_field_option_catalog(TRUNCATION_CATALOG, field_option::POLICY, tx)?);
         }
 
         Ok(tm)
     }
 
+    /// open_field_option_catalog はフィールドごとの設定を保持するカタログ（テーブル名、フィールド名、設定の名前）の
+    /// レイアウトを返す。カタログがなければ作る
+    fn open_field_option_catalog(
+        &mut self,
+        catalog_name: &str,
+        option_field: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Arc<Layout>> {
+        let schema = field_option_catalog_schema(option_field);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema))?);
+        if !self
+            .read_layout(catalog_name, tx.clone())?
+            .schema
+            .has_field(option_field)
+        {
+            self.create_table(catalog_name, layout.schema.clone(), tx)?;
+        }
+        Ok(layout)
+    }
+
     pub fn create_table(
         &mut self,
         table_name: &str,
         schema: Arc<Schema>,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<()> {
+        // 索引のファイルと重なる名前や、カタログに格納できない長さの名前では、ファイルを作る前に止める
+        if table_name.starts_with(INDEX_FILE_PREFIX) {
+            bail!("table name is reserved for index files: {}", table_name);
+        }
+        if table_name.chars().count() > MAX_NAME as usize {
+            bail!("table name is too long: {}", table_name);
+        }
+        // 同じ名前のテーブルをカタログに重ねて登録すると、既存のテーブルのレイアウトや権限を乗っ取れてしまう
+        if self.has_table(table_name, tx.clone())? {
+            bail!("table already exists: {}", table_name);
+        }
+        let snapshot = unlock!(tx).metadata_snapshot();
+        unlock!(snapshot).forget(table_name);
         let layout = Arc::new(Layout::try_from_schema(schema)?);
-        let mut tcat = TableScan::new(tx.clone(), "tblcat", self.table_catlog_layout.clone())?;
+        // レコードがなくてもカタログのテーブルのファイルがディスクにあるように、ここで作っておく
+        TableScan::new(tx.clone(), table_name, layout.clone())?.close();
+        let mut tcat = TableScan::new(tx.clone(), TABLE_CATALOG, self.table_catlog_layout.clone())?;
         tcat.insert()?;
-        tcat.set_string("tblname", table_name)?;
-        tcat.set_int("slotsize", layout.slot_size)?;
+        tcat.set_string(tblcat::TABLE_NAME, table_name)?;
+        tcat.set_int(tblcat::SLOT_SIZE, layout.slot_size)?;
+        if tcat.has_field(tblcat::CREATED) {
+            tcat.set_int(tblcat::CREATED, unix_time())?;
+            tcat.set_int(tblcat::ANALYZED, 0)?;
+            tcat.set_int(tblcat::NUM_BLOCKS, 0)?;
+            tcat.set_int(tblcat::NUM_RECORDS, 0)?;
+        }
         tcat.close();
 
-        let mut fcat = TableScan::new(tx.clone(), "fldcat", self.field_catlog_layout.clone())?;
+        let mut fcat = TableScan::new(tx.clone(), FIELD_CATALOG, self.field_catlog_layout.clone())?;
         for field_name in layout.schema.fields.iter() {
             fcat.insert()?;
-            fcat.set_string("tblname", table_name)?;
-            fcat.set_string("fldname", field_name)?;
-            fcat.set_int("type", layout.schema.r#type(field_name).unwrap() as i32)?;
-            fcat.set_int("length", layout.schema.length(field_name).unwrap())?;
-            fcat.set_int("offset", layout.offset(field_name).unwrap())?;
+            fcat.set_string(fldcat::TABLE_NAME, table_name)?;
+            fcat.set_string(fldcat::FIELD_NAME, field_name)?;
+            fcat.set_int(
+                fldcat::TYPE,
+                layout.schema.r#type(field_name).unwrap() as i32,
+            )?;
+            fcat.set_int(fldcat::LENGTH, layout.schema.length(field_name).unwrap())?;
+            fcat.set_int(fldcat::OFFSET, layout.offset(field_name).unwrap())?;
         }
         fcat.close();
 
+        let schema = &layout.schema;
+        let collations = schema
+            .fields
+            .iter()
+            .filter(|field_name| schema.collation(field_name) != Collation::Binary)
+            .map(|field_name| (field_name, schema.collation(field_name).to_string()))
+            .collect();
+        Self::write_field_options(
+            COLLATION_CATALOG,
+            self.collation_catlog_layout.clone(),
+            field_option::COLLATION,
+            table_name,
+            collations,
+            tx.clone(),
+        )?;
+        let truncations = schema
+            .fields
+            .iter()
+            .filter(|field_name| schema.truncation(field_name) != TruncationPolicy::Error)
+            .map(|field_name| (field_name, schema.truncation(field_name).to_string()))
+            .collect();
+        Self::write_field_options(
+            TRUNCATION_CATALOG,
+            self.truncation_catlog_layout.clone(),
+            field_option::POLICY,
+            table_name,
+            truncations,
+            tx,
+        )
+    }
+
+    /// has_table はテーブルが tblcat に登録されているかを返す
+    fn has_table(&self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<bool> {
+        let mut tcat = TableScan::new(tx, TABLE_CATALOG, self.table_catlog_layout.clone())?;
+        let mut found = false;
+        while tcat.next()? {
+            if tcat.get_string(tblcat::TABLE_NAME)? == table_name {
+                found = true;
+                break;
+            }
+        }
+        tcat.close();
+        Ok(found)
+    }
+
+    /// set_analyzed はテーブルの統計を取り直した時刻と、そのときの統計を tblcat に書き込む
+    ///
+    /// 列がない古いカタログでは何もしない
+    pub fn set_analyzed(
+        &mut self,
+        table_name: &str,
+        stat_info: &StatInfo,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        if !self.table_catlog_layout.schema.has_field(tblcat::ANALYZED) {
+            return Ok(());
+        }
+        let mut tcat = TableScan::new(tx, TABLE_CATALOG, self.table_catlog_layout.clone())?;
+        while tcat.next()? {
+            if tcat.get_string(tblcat::TABLE_NAME)? == table_name {
+                tcat.set_int(tblcat::ANALYZED, unix_time())?;
+                tcat.set_int(tblcat::NUM_BLOCKS, stat_info.num_blocks)?;
+                tcat.set_int(tblcat::NUM_RECORDS, stat_info.num_records)?;
+                break;
+            }
+        }
+        tcat.close();
+        Ok(())
+    }
+
+    /// write_field_options はフィールドごとの設定をカタログに書き込む
+    fn write_field_options(
+        catalog_name: &str,
+        catalog_layout: Option<Arc<Layout>>,
+        option_field: &str,
+        table_name: &str,
+        options: Vec<(&String, String)>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        if options.is_empty() {
+            return Ok(());
+        }
+        let Some(catalog_layout) = catalog_layout else {
+            bail!("catalog not found: {}", catalog_name);
+        };
+        let mut cat = TableScan::new(tx, catalog_name, catalog_layout)?;
+        for (field_name, option) in options {
+            cat.insert()?;
+            cat.set_string(field_option::TABLE_NAME, table_name)?;
+            cat.set_string(field_option::FIELD_NAME, field_name)?;
+            cat.set_string(option_field, &option)?;
+        }
+        cat.close();
         Ok(())
     }
 
+    /// read_field_options はテーブルのフィールドごとの設定をカタログから読み込む
+    fn read_field_options(
+        catalog_name: &str,
+        catalog_layout: Option<Arc<Layout>>,
+        option_field: &str,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Vec<(String, String)>> {
+        let Some(catalog_layout) = catalog_layout else {
+            return Ok(vec![]);
+        };
+        let mut cat = TableScan::new(tx, catalog_name, catalog_layout)?;
+        let mut options = vec![];
+        while cat.next()? {
+            if cat.get_string(field_option::TABLE_NAME)? == table_name {
+                options.push((
+                    cat.get_string(field_option::FIELD_NAME)?,
+                    cat.get_string(option_field)?,
+                ));
+            }
+        }
+        cat.close();
+        Ok(options)
+    }
+
+    /// get_layout はテーブルのレイアウトを、フィールドの照合順序や長すぎる文字列の扱いと一緒に返す
+    /// 同じトランザクションで読み込んだことがあれば、カタログを読み直さずにそのときのレイアウトを返す
+    /// 他のトランザクションが読み込んでキャッシュにあれば、カタログの共有ロックだけを取ってキャッシュのレイアウトを返す
     pub fn get_layout(&mut self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Layout> {
+        let snapshot = unlock!(tx).metadata_snapshot();
+        if let Some(layout) = unlock!(snapshot).layout(table_name) {
+            return Ok(layout);
+        }
+        let cache = unlock!(tx).catalog_cache();
+        if let Some(layout) = cache.as_ref().and_then(|cache| cache.layout(table_name)) {
+            for catalog in LAYOUT_CATALOGS {
+                unlock!(tx).s_lock_file(&catalog::file_name(catalog))?;
+            }
+            unlock!(snapshot).set_layout(table_name, layout.clone());
+            return Ok(layout);
+        }
+        let generation = cache.as_ref().map(|cache| cache.generation());
+        let layout = self.read_catalog_layout(table_name, tx.clone())?;
+        // ないテーブルは覚えておかず、作られた後に読めるようにする
+        if !layout.schema.fields.is_empty() {
+            unlock!(snapshot).set_layout(table_name, layout.clone());
+            // 読んでいる間にこのトランザクションがカタログを変更していれば入れない
+            if let (Some(cache), Some(generation)) = (unlock!(tx).catalog_cache(), generation) {
+                cache.set_layout(generation, table_name, layout.clone());
+            }
+        }
+        Ok(layout)
+    }
+
+    /// read_catalog_layout はテーブルのレイアウトとフィールドごとの設定をカタログから読み込む
+    fn read_catalog_layout(
+        &mut self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Layout> {
+        let layout = self.read_layout(table_name, tx.clone())?;
+        let collations = Self::read_field_options(
+            COLLATION_CATALOG,
+            self.collation_catlog_layout.clone(),
+            field_option::COLLATION,
+            table_name,
+            tx.clone(),
+        )?;
+        let truncations = Self::read_field_options(
+            TRUNCATION_CATALOG,
+            self.truncation_catlog_layout.clone(),
+            field_option::POLICY,
+            table_name,
+            tx,
+        )?;
+        if collations.is_empty() && truncations.is_empty() {
+            return Ok(layout);
+        }
+
+        let mut schema = (*layout.schema).clone();
+        for (field_name, collation) in collations {
+            schema.set_collation(&field_name, collation.parse()?)?;
+        }
+        for (field_name, truncation) in truncations {
+            schema.set_truncation(&field_name, truncation.parse()?)?;
+        }
+        let offsets = schema
+            .fields
+            .iter()
+            .map(|field_name| (field_name.clone(), layout.offset(field_name).unwrap()))
+            .collect();
+        Layout::try_from_metadata(Arc::new(schema), offsets, layout.slot_size)
+    }
+
+    /// read_layout はテーブルとフィールドのカタログからテーブルのレイアウトを読み込む
+    fn read_layout(&mut self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<Layout> {
         let mut size = -1;
-        let mut tcat = TableScan::new(tx.clone(), "tblcat", self.table_catlog_layout.clone())?;
+        let mut tcat = TableScan::new(tx.clone(), TABLE_CATALOG, self.table_catlog_layout.clone())?;
 
         while tcat.next()? {
-            if tcat.get_string("tblname")? == table_name {
-                size = tcat.get_int("slotsize")?;
+            if tcat.get_string(tblcat::TABLE_NAME)? == table_name {
+                size = tcat.get_int(tblcat::SLOT_SIZE)?;
                 break;
             }
         }
@@ -98,15 +390,15 @@ impl TableManager {
         let mut schema = Schema::default();
         let mut offsets: HashMap<String, i32> = HashMap::default();
 
-        let mut fcat = TableScan::new(tx, "fldcat", self.field_catlog_layout.clone())?;
+        let mut fcat = TableScan::new(tx, FIELD_CATALOG, self.field_catlog_layout.clone())?;
 
         while fcat.next()? {
-            if fcat.get_string("tblname")? == table_name {
-                let field_name = fcat.get_string("fldname")?;
-                let field_type = fcat.get_int("type")?;
-                let length = fcat.get_int("length")?;
-                let offset = fcat.get_int("offset")?;
-                schema.add_field(field_name.clone(), field_type.into(), length);
+            if fcat.get_string(fldcat::TABLE_NAME)? == table_name {
+                let field_name = fcat.get_string(fldcat::FIELD_NAME)?;
+                let field_type = FieldTypes::try_from(fcat.get_int(fldcat::TYPE)?)?;
+                let length = fcat.get_int(fldcat::LENGTH)?;
+                let offset = fcat.get_int(fldcat::OFFSET)?;
+                schema.add_field(field_name.clone(), field_type, length);
                 offsets.insert(field_name, offset);
             }
         }
@@ -116,15 +408,28 @@ impl TableManager {
     }
 }
 
+/// unix_time は現在の UNIX 時間を秒で返す
+pub fn unix_time() -> i32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i32)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
-    use super::TableManager;
+    use super::{unix_time, TableManager};
     use crate::{
+        metadata::stat_info::StatInfo,
         query::scan::Scan as _,
-        record::{schema::FieldTypes, table_scan::TableScan},
+        record::{
+            schema::{FieldTypes, Schema},
+            table_scan::TableScan,
+        },
         server::db::TinyDB,
+        unlock,
     };
     use anyhow::Result;
     use tempfile::tempdir;
@@ -141,7 +446,7 @@ mod tests {
 
         let mut ts = TableScan::new(tx.clone(), "tblcat", table_catlog_layout.clone())?;
 
-        let wants = vec![("tblcat", 28), ("fldcat", 56)];
+        let wants = vec![("tblcat", 44), ("fldcat", 56)];
 
         for want in wants {
             ts.next()?;
@@ -156,6 +461,10 @@ mod tests {
         let wants = vec![
             ("tblcat", "tblname", FieldTypes::Varchar, 16, 4),
             ("tblcat", "slotsize", FieldTypes::Integer, 0, 24),
+            ("tblcat", "created", FieldTypes::Integer, 0, 28),
+            ("tblcat", "analyzed", FieldTypes::Integer, 0, 32),
+            ("tblcat", "numblocks", FieldTypes::Integer, 0, 36),
+            ("tblcat", "numrecs", FieldTypes::Integer, 0, 40),
         ];
 
         for want in wants {
@@ -183,6 +492,95 @@ mod tests {
             assert_eq!(ts.get_int("length")?, want.3);
             assert_eq!(ts.get_int("offset")?, want.4);
         }
+        ts.close();
+        Ok(())
+    }
+
+    #[test]
+    fn should_record_creation_and_analyze_time() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_record_creation_and_analyze_time");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+
+        let before = unix_time();
+        let mut table_manager = TableManager::new(true, tx.clone())?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        table_manager.create_table("T", Arc::new(schema), tx.clone())?;
+        table_manager.set_analyzed("T", &StatInfo::new(3, 42), tx.clone())?;
+
+        // 開き直したときは tblcat のレイアウトをカタログから読み込む
+        let mut table_manager = TableManager::new(false, tx.clone())?;
+        let layout = Arc::new(table_manager.get_layout("tblcat", tx.clone())?);
+        let mut ts = TableScan::new(tx.clone(), "tblcat", layout)?;
+        let mut found = false;
+        while ts.next()? {
+            let created = ts.get_int("created")?;
+            assert!(created >= before && created <= unix_time());
+            if ts.get_string("tblname")? == "T" {
+                assert!(ts.get_int("analyzed")? >= created);
+                assert_eq!(ts.get_int("numblocks")?, 3);
+                assert_eq!(ts.get_int("numrecs")?, 42);
+                found = true;
+            } else {
+                assert_eq!(ts.get_int("analyzed")?, 0);
+            }
+        }
+        ts.close();
+        assert!(found);
+        unlock!(tx).commit()?;
+        Ok(())
+    }
+    #[test]
+    fn should_reject_reserved_and_long_table_names() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_reject_reserved_and_long_table_names");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut table_manager = TableManager::new(true, tx.clone())?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        let schema = Arc::new(schema);
+
+        // 索引のファイルと重なる名前と長すぎる名前は、ファイルを作らずにエラーにする
+        for table_name in ["$ix.1", "abcdefghijklmnopq"] {
+            let result = table_manager.create_table(table_name, schema.clone(), tx.clone());
+            assert!(result.is_err(), "{}", table_name);
+            assert!(!unlock!(tx).file_exists(&format!("{}.tbl", table_name)));
+        }
+        table_manager.create_table("abcdefghijklmnop", schema, tx.clone())?;
+        unlock!(tx).commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_fail_to_read_layout_with_unknown_field_type() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_fail_to_read_layout_with_unknown_field_type");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut table_manager = TableManager::new(true, tx.clone())?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        table_manager.create_table("T", Arc::new(schema), tx.clone())?;
+
+        // カタログの型の値が壊れていれば、パニックせずにエラーを返す
+        let layout = Arc::new(table_manager.get_layout("fldcat", tx.clone())?);
+        let mut ts = TableScan::new(tx.clone(), "fldcat", layout)?;
+        while ts.next()? {
+            if ts.get_string("tblname")? == "T" {
+                ts.set_int("type", 999)?;
+            }
+        }
+        ts.close();
+        let mut table_manager = TableManager::new(false, tx.clone())?;
+        let err = table_manager.get_layout("T", tx.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "unknown field type: 999");
+        unlock!(tx).rollback()?;
         Ok(())
     }
 }
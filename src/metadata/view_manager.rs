@@ -1,18 +1,15 @@
-use super::table_manager::{TableManager, MAX_NAME};
+use super::{
+    catalog::{view_catalog_schema, viewcat, VIEW_CATALOG},
+    table_manager::TableManager,
+};
 use crate::{
-    query::scan::Scan as _,
-    record::{schema::Schema, table_scan::TableScan},
-    tx::transaction::Transaction,
-    unlock,
+    query::scan::Scan as _, record::table_scan::TableScan, tx::transaction::Transaction, unlock,
 };
 use anyhow::Result;
 use std::sync::{Arc, Mutex};
 
-static MAX_VIEWDEF: i32 = 100;
-
 pub struct ViewManager {
     table_manager: Arc<Mutex<TableManager>>,
-    max_viewdef: i32,
 }
 
 impl ViewManager {
@@ -22,15 +19,10 @@ impl ViewManager {
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Self> {
         if is_new {
-            let mut sch = Schema::default();
-            sch.add_string_field("viewname", MAX_NAME);
-            sch.add_string_field("viewdef", MAX_VIEWDEF);
-            unlock!(table_manager).create_table("viewcat", Arc::new(sch), tx.clone())?;
+            let schema = Arc::new(view_catalog_schema());
+            unlock!(table_manager).create_table(VIEW_CATALOG, schema, tx.clone())?;
         }
-        Ok(Self {
-            table_manager,
-            max_viewdef: MAX_VIEWDEF,
-        })
+        Ok(Self { table_manager })
     }
 
     pub fn create_view(
@@ -39,11 +31,12 @@ impl ViewManager {
         view_def: &str,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<()> {
-        let layout = Arc::new(unlock!(self.table_manager).get_layout("viewcat", tx.clone())?);
-        let mut ts = TableScan::new(tx, "viewcat", layout)?;
+        let layout = Arc::new(unlock!(self.table_manager).get_layout(VIEW_CATALOG, tx.clone())?);
+        let mut ts = TableScan::new(tx, VIEW_CATALOG, layout)?;
         ts.insert()?;
-        ts.set_string("viewname", vname)?;
-        ts.set_string("viewdef", view_def)?;
+        ts.set_string(viewcat::VIEW_NAME, vname)?;
+        ts.set_string(viewcat::VIEW_DEF, view_def)?;
+        ts.close();
         Ok(())
     }
 
@@ -52,15 +45,17 @@ impl ViewManager {
         view_name: &str,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Option<String>> {
-        let layout = Arc::new(unlock!(self.table_manager).get_layout("viewcat", tx.clone())?);
-        let mut ts = TableScan::new(tx, "viewcat", layout)?;
+        let layout = Arc::new(unlock!(self.table_manager).get_layout(VIEW_CATALOG, tx.clone())?);
+        let mut ts = TableScan::new(tx, VIEW_CATALOG, layout)?;
+        let mut result = None;
         while ts.next()? {
-            if ts.get_string("viewname")? == view_name {
-                let result = ts.get_string("viewdef")?;
-                return Ok(Some(result));
+            if ts.get_string(viewcat::VIEW_NAME)? == view_name {
+                result = Some(ts.get_string(viewcat::VIEW_DEF)?);
+                break;
             }
         }
-        Ok(None)
+        ts.close();
+        Ok(result)
     }
 }
 
@@ -71,7 +66,10 @@ mod test {
     use anyhow::Result;
     use tempfile::tempdir;
 
-    use crate::{metadata::table_manager::TableManager, server::db::TinyDB};
+    use crate::{
+        metadata::{catalog::MAX_VIEWDEF, table_manager::TableManager},
+        server::db::TinyDB,
+    };
 
     use super::ViewManager;
 
@@ -93,6 +91,11 @@ mod test {
             view_manager.get_view_def(view_name, tx.clone())?,
             Some(view_def.into())
         );
+        // 定義の長さはカタログのフィールドの長さで制限される
+        let view_def = "x".repeat(MAX_VIEWDEF as usize + 1);
+        assert!(view_manager
+            .create_view("view2", &view_def, tx.clone())
+            .is_err());
 
         Ok(())
     }
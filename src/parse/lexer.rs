@@ -3,9 +3,15 @@ use std::{iter::Peekable, str::Chars};
 
 use crate::query::constant::Constant;
 
-const KEYWORD: [&str; 18] = [
-    "select", "from", "where", "and", "insert", "into", "values", "delete", "update", "set",
-    "create", "table", "int", "varchar", "view", "as", "index", "on",
+const KEYWORD: [&str; 62] = [
+    "select", "from", "where", "and", "or", "not", "insert", "into", "values", "delete", "update", "set",
+    "create", "table", "int", "varchar", "text", "blob", "view", "as", "index", "on", "unique",
+    "using", "show", "indexes", "declare", "cursor", "for", "fetch", "close", "grant", "revoke",
+    "to", "all", "grants", "null", "is", "in", "explain", "tables",
+    "cluster", "by", "listen", "unlisten", "of", "lsn", "collate", "boolean", "true", "false",
+    "date", "current_date", "current_timestamp", "cast",
+    "sample", "percent", "order", "asc", "desc",
+    "group", "distinct",
 ];
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -17,6 +23,13 @@ pub enum Symbol {
     RParen,
     Semicolon,
     Dot,
+    Plus,
+    Minus,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    NotEqual,
 }
 
 impl From<char> for Symbol {
@@ -29,6 +42,10 @@ impl From<char> for Symbol {
             ')' => Symbol::RParen,
             ';' => Symbol::Semicolon,
             '.' => Symbol::Dot,
+            '+' => Symbol::Plus,
+            '-' => Symbol::Minus,
+            '<' => Symbol::Less,
+            '>' => Symbol::Greater,
             _ => panic!("unexpected symbol: {}", s),
         }
     }
@@ -41,6 +58,8 @@ pub enum Token {
     Keyword(String),
     String(String),
     Symbol(Symbol),
+    /// Hint は `/*+ ... */` の中身
+    Hint(String),
 }
 
 impl Token {
@@ -78,6 +97,7 @@ impl Token {
     }
 }
 
+#[derive(Clone)]
 pub struct Lexer<'a> {
     pub current_token: Option<Token>,
     pub peek_token: Option<Token>,
@@ -194,6 +214,18 @@ impl<'a> Lexer<'a> {
         false
     }
 
+    /// read_comment は `/*` の後から `*/` までを読み込んで、コメントの中身を返す
+    fn read_comment(&mut self) -> String {
+        let mut comment = String::new();
+        while let Some(c) = self.input.next() {
+            if c == '*' && self.input.next_if_eq(&'/').is_some() {
+                break;
+            }
+            comment.push(c);
+        }
+        comment
+    }
+
     fn read_while<F>(&mut self, condition: F) -> String
     where
         F: Fn(char) -> bool,
@@ -210,7 +242,10 @@ impl<'a> Lexer<'a> {
 }
 
 fn is_symbol(c: char) -> bool {
-    matches!(c, '=' | ',' | '*' | '(' | ')' | ';' | '.')
+    matches!(
+        c,
+        '=' | ',' | '*' | '(' | ')' | ';' | '.' | '+' | '-' | '<' | '>'
+    )
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -223,23 +258,51 @@ impl<'a> Iterator for Lexer<'a> {
             }
 
             let token = match c {
+                // `/* ... */` と `-- ...` はコメントとして読み飛ばす
+                // `/*+ ... */` はオプティマイザヒントとして扱う
+                '/' if self.input.next_if_eq(&'*').is_some() => {
+                    let is_hint = self.input.next_if_eq(&'+').is_some();
+                    let comment = self.read_comment();
+                    if !is_hint {
+                        continue;
+                    }
+                    Token::Hint(comment.trim().to_string())
+                }
+                '-' if self.input.next_if_eq(&'-').is_some() => {
+                    self.read_while(|c| c != '\n');
+                    continue;
+                }
                 c if c.is_numeric() => {
                     let mut token = c.to_string();
                     token.push_str(&self.read_while(|c| c.is_numeric()));
                     Token::Number(token.parse().unwrap())
                 }
                 '\'' => {
-                    let token = self.read_while(|c| c != '\'');
+                    let mut token = self.read_while(|c| c != '\'');
                     self.input.next(); // skip closing '
+                                       // 文字列の中の '' は1つの引用符として扱う
+                    while self.input.next_if_eq(&'\'').is_some() {
+                        token.push('\'');
+                        token.push_str(&self.read_while(|c| c != '\''));
+                        self.input.next();
+                    }
                     Token::String(token)
                 }
+                // `<=`、`>=`、`<>` は2文字で1つの記号にする
+                '<' if self.input.next_if_eq(&'=').is_some() => Token::Symbol(Symbol::LessEqual),
+                '<' if self.input.next_if_eq(&'>').is_some() => Token::Symbol(Symbol::NotEqual),
+                '>' if self.input.next_if_eq(&'=').is_some() => {
+                    Token::Symbol(Symbol::GreaterEqual)
+                }
                 c if is_symbol(c) => Token::Symbol(c.into()),
                 _ => {
                     let mut token = c.to_string();
                     token.push_str(&self.read_while(|c| !c.is_whitespace() && !is_symbol(c)));
 
-                    if KEYWORD.contains(&token.as_str()) {
-                        Token::Keyword(token)
+                    // キーワードは大文字と小文字を区別しない
+                    let keyword = token.to_lowercase();
+                    if KEYWORD.contains(&keyword.as_str()) {
+                        Token::Keyword(keyword)
                     } else {
                         Token::Ident(token)
                     }
@@ -259,7 +322,7 @@ impl<'a> Iterator for Lexer<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::parse::lexer::{Lexer, Token};
+    use crate::parse::lexer::{Lexer, Symbol, Token};
     use paste::paste;
 
     macro_rules! test_lexer {
@@ -367,6 +430,26 @@ mod tests {
         assert_eq!(lexer.next(), None);
     }
 
+    test_lexer!(
+        comparison_symbols,
+        "a<=1 b >= 2 c<>3 d < = 4",
+        vec![
+            Token::Ident("a".into()),
+            Token::Symbol(Symbol::LessEqual),
+            Token::Number(1),
+            Token::Ident("b".into()),
+            Token::Symbol(Symbol::GreaterEqual),
+            Token::Number(2),
+            Token::Ident("c".into()),
+            Token::Symbol(Symbol::NotEqual),
+            Token::Number(3),
+            Token::Ident("d".into()),
+            Token::Symbol(Symbol::Less),
+            Token::Symbol(Symbol::Equal),
+            Token::Number(4),
+        ]
+    );
+
     test_lexer!(
         select,
         "select * from users where id = 1",
@@ -467,4 +550,44 @@ mod tests {
             Token::Number(1),
         ]
     );
+
+    test_lexer!(
+        grant,
+        "grant select, insert on users to alice",
+        vec![
+            Token::Keyword("grant".into()),
+            Token::Keyword("select".into()),
+            Token::Symbol(','.into()),
+            Token::Keyword("insert".into()),
+            Token::Keyword("on".into()),
+            Token::Ident("users".into()),
+            Token::Keyword("to".into()),
+            Token::Ident("alice".into()),
+        ]
+    );
+
+    test_lexer!(
+        escaped_quote,
+        "values ('it''s', '')",
+        [
+            Token::Keyword("values".into()),
+            Token::Symbol('('.into()),
+            Token::String("it's".into()),
+            Token::Symbol(','.into()),
+            Token::String("".into()),
+            Token::Symbol(')'.into()),
+        ]
+    );
+
+    test_lexer!(
+        comment_and_hint,
+        "select /*+ use_index(users idx) */ id -- the id\n from /* table */ users",
+        [
+            Token::Keyword("select".into()),
+            Token::Hint("use_index(users idx)".into()),
+            Token::Ident("id".into()),
+            Token::Keyword("from".into()),
+            Token::Ident("users".into()),
+        ]
+    );
 }
@@ -1,23 +1,37 @@
-use std::sync::Arc;
-
 use crate::{
+    index::IndexOptions,
+    metadata::catalog::{
+        idxcat, privcat, tblcat, INDEX_CATALOG, PRIVILEGE_CATALOG, TABLE_CATALOG,
+    },
     query::{
+        aggregation_function::{AggregationFunction, AggregationKind},
+        cast::CastType,
+        cluster_data::ClusterData,
         constant::Constant,
         create_index_data::CreateIndexData,
         create_table_data::CreateTableData,
         create_view_data::CreateViewData,
+        cursor_data::CursorStatement,
         delete_data::DeleteData,
-        expression::Expression,
+        expression::{Expression, Function, Operator},
+        grant_data::{GrantData, Privilege},
+        hint::Hint,
+        in_term::InTerm,
+        null_term::NullTerm,
         insert_data::InsertData,
+        listen_data::ListenStatement,
         modify_data::ModifyData,
         predicate::Predicate,
-        query_data::QueryData,
+        query_data::{split_qualified, QueryData},
+        record_comparator::SortKey,
         statement::{CreateStatement, Statement},
-        term::Term,
+        table_function::TableFunction,
+        term::{Comparison, Term},
     },
-    record::schema::Schema,
+    record::{block_sample::BlockSample, schema::Schema},
 };
 use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
 
 use super::lexer::{Lexer, Symbol, Token};
 
@@ -33,39 +47,235 @@ impl<'a> Parser<'a> {
     }
 
     pub fn constant(&mut self) -> Result<Constant> {
-        if self.lexer.is_string_constant() {
+        if self.lexer.is_keyword("null") {
+            self.lexer.eat_keyword("null")?;
+            Ok(Constant::Null)
+        } else if self.lexer.is_keyword("true") {
+            self.lexer.eat_keyword("true")?;
+            Ok(Constant::Bool(true))
+        } else if self.lexer.is_keyword("false") {
+            self.lexer.eat_keyword("false")?;
+            Ok(Constant::Bool(false))
+        } else if self.lexer.is_keyword("date") {
+            self.lexer.eat_keyword("date")?;
+            Ok(Constant::Date(self.lexer.eat_string_constant()?.parse()?))
+        } else if self.lexer.is_string_constant() {
             Ok(Constant::String(self.lexer.eat_string_constant()?))
+        } else if self.lexer.is_symbol(Symbol::Minus) {
+            self.lexer.next();
+            Ok(Constant::Int(-self.lexer.eat_int_constant()?))
         } else {
             Ok(Constant::Int(self.lexer.eat_int_constant()?))
         }
     }
 
+    /// expression は `<operand> [(+|-) <operand> ...]` を解析する。計算は左から順に行う
     pub fn expression(&mut self) -> Result<Expression> {
+        let mut expression = self.operand()?;
+        loop {
+            let op = if self.lexer.is_symbol(Symbol::Plus) {
+                Operator::Add
+            } else if self.lexer.is_symbol(Symbol::Minus) {
+                Operator::Subtract
+            } else {
+                return Ok(expression);
+            };
+            self.lexer.next();
+            let rhs = self.operand()?;
+            expression = Expression::Arithmetic(Box::new(expression), op, Box::new(rhs));
+        }
+    }
+
+    /// operand はフィールド名、`current_date` と `current_timestamp`、`cast(<expr> as <type>)`、定数のいずれかを解析する
+    fn operand(&mut self) -> Result<Expression> {
         if self.lexer.is_ident() {
             Ok(Expression::FieldName(self.lexer.eat_ident()?))
+        } else if self.lexer.is_keyword("cast") {
+            self.lexer.eat_keyword("cast")?;
+            self.lexer.eat_symbol(Symbol::LParen)?;
+            let expression = self.expression()?;
+            self.lexer.eat_keyword("as")?;
+            let cast_type = self.cast_type()?;
+            self.lexer.eat_symbol(Symbol::RParen)?;
+            Ok(Expression::Cast(Box::new(expression), cast_type))
+        } else if self.lexer.is_keyword("current_date") {
+            self.lexer.eat_keyword("current_date")?;
+            Ok(Expression::Function(Function::CurrentDate))
+        } else if self.lexer.is_keyword("current_timestamp") {
+            self.lexer.eat_keyword("current_timestamp")?;
+            Ok(Expression::Function(Function::CurrentTimestamp))
         } else {
             Ok(Expression::Value(self.constant()?))
         }
     }
 
+    /// cast_type は `int`、`varchar(<n>)`、`text`、`boolean`、`date` のいずれかを解析する
+    fn cast_type(&mut self) -> Result<CastType> {
+        for (keyword, cast_type) in [
+            ("int", CastType::Int),
+            ("text", CastType::Text),
+            ("boolean", CastType::Boolean),
+            ("date", CastType::Date),
+        ] {
+            if self.lexer.is_keyword(keyword) {
+                self.lexer.eat_keyword(keyword)?;
+                return Ok(cast_type);
+            }
+        }
+        self.lexer.eat_keyword("varchar")?;
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let length = self.lexer.eat_int_constant()?;
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        Ok(CastType::Varchar(length))
+    }
+
     pub fn term(&mut self) -> Result<Term> {
         let lhs = self.expression()?;
-        self.lexer.eat_symbol(Symbol::Equal)?;
+        let op = self.comparison()?;
         let rhs = self.expression()?;
 
-        Ok(Term::new(lhs, rhs))
+        Ok(Term::compare(lhs, op, rhs))
     }
 
+    /// comparison は `=`、`<>`、`<`、`<=`、`>`、`>=` のいずれかを解析する
+    fn comparison(&mut self) -> Result<Comparison> {
+        let comparisons = [
+            (Symbol::Equal, Comparison::Equal),
+            (Symbol::NotEqual, Comparison::NotEqual),
+            (Symbol::Less, Comparison::Less),
+            (Symbol::LessEqual, Comparison::LessEqual),
+            (Symbol::Greater, Comparison::Greater),
+            (Symbol::GreaterEqual, Comparison::GreaterEqual),
+        ];
+        for (symbol, comparison) in comparisons {
+            if self.lexer.is_symbol(symbol) {
+                self.lexer.next();
+                return Ok(comparison);
+            }
+        }
+        bail!("Expected comparison operator, found {:?}", self.lexer.current_token)
+    }
+
+    /// predicate は `<conjunction> [or <conjunction> ...]` を解析する。and は or より先に結びつく
     pub fn predicate(&mut self) -> Result<Predicate> {
-        let mut pred = Predicate::new(self.term()?);
-        if self.lexer.is_keyword("and") {
+        let mut preds = vec![self.conjunction()?];
+        while self.lexer.is_keyword("or") {
+            self.lexer.eat_keyword("or")?;
+            preds.push(self.conjunction()?);
+        }
+        Ok(Predicate::or(preds))
+    }
+
+    /// conjunction は `<condition> [and <condition> ...]` を解析する
+    fn conjunction(&mut self) -> Result<Predicate> {
+        let mut pred = self.condition()?;
+        while self.lexer.is_keyword("and") {
             self.lexer.eat_keyword("and")?;
-            pred.con_join_with(&self.predicate()?);
+            pred.con_join_with(&self.condition()?);
         }
+        Ok(pred)
+    }
 
+    /// condition は `not <condition>`、`(<predicate>)`、行の比較、`<expr> [not] in (...)`、
+    /// `<expr> is [not] null`、項のいずれかを解析する
+    fn condition(&mut self) -> Result<Predicate> {
+        if self.lexer.is_keyword("not") {
+            self.lexer.eat_keyword("not")?;
+            return Ok(Predicate::negate(self.condition()?));
+        }
+        if !self.lexer.is_symbol(Symbol::LParen) {
+            return self.comparison_or_in_list();
+        }
+        // `(` で始まるのは行の比較か括弧でくくった述語なので、行の比較として読めなければ述語として読み直す
+        let lexer = self.lexer.clone();
+        if let Ok(pred) = self.row_predicate() {
+            return Ok(pred);
+        }
+        self.lexer = lexer;
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let pred = self.predicate()?;
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        Ok(pred)
+    }
+
+    /// comparison_or_in_list は項か `<expr> [not] in (<constant>, ...)`、`<expr> is [not] null` を解析する
+    /// IN のリストは1列の行を並べた InTerm に書き換え、NOT IN はその NOT にする
+    fn comparison_or_in_list(&mut self) -> Result<Predicate> {
+        let lhs = self.expression()?;
+        if self.lexer.is_keyword("is") {
+            self.lexer.eat_keyword("is")?;
+            let negated = self.lexer.is_keyword("not");
+            if negated {
+                self.lexer.eat_keyword("not")?;
+            }
+            self.lexer.eat_keyword("null")?;
+            return Ok(Predicate::from_null_term(NullTerm::new(lhs, negated)));
+        }
+        let negated = self.lexer.is_keyword("not");
+        if negated {
+            self.lexer.eat_keyword("not")?;
+        }
+        if !negated && !self.lexer.is_keyword("in") {
+            let op = self.comparison()?;
+            let rhs = self.expression()?;
+            return Ok(Predicate::new(Term::compare(lhs, op, rhs)));
+        }
+        self.lexer.eat_keyword("in")?;
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let rows = self.get_constant_list()?.into_iter().map(|value| vec![value]).collect();
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        let pred = Predicate::from_in_term(InTerm::new(vec![lhs], rows)?);
+        Ok(if negated { Predicate::negate(pred) } else { pred })
+    }
+
+    /// row_predicate は `(<expr>, ...) = (<expr>, ...)` と `(<expr>, ...) in ((<constant>, ...), ...)` を解析する
+    /// 行の等しさは列ごとの等しさの AND に書き換える
+    fn row_predicate(&mut self) -> Result<Predicate> {
+        let lhs = self.row()?;
+        if self.lexer.is_keyword("in") {
+            self.lexer.eat_keyword("in")?;
+            self.lexer.eat_symbol(Symbol::LParen)?;
+            let mut rows = vec![self.constant_row()?];
+            while self.lexer.is_symbol(Symbol::Comma) {
+                self.lexer.next();
+                rows.push(self.constant_row()?);
+            }
+            self.lexer.eat_symbol(Symbol::RParen)?;
+            return Ok(Predicate::from_in_term(InTerm::new(lhs, rows)?));
+        }
+
+        self.lexer.eat_symbol(Symbol::Equal)?;
+        let rhs = self.row()?;
+        if lhs.len() != rhs.len() {
+            bail!("row value sizes differ: {} and {}", lhs.len(), rhs.len());
+        }
+        let mut pred = Predicate::default();
+        for (lhs, rhs) in lhs.into_iter().zip(rhs) {
+            pred.con_join_with(&Predicate::new(Term::new(lhs, rhs)));
+        }
         Ok(pred)
     }
 
+    /// row は `(<expr>, ...)` を解析する
+    fn row(&mut self) -> Result<Vec<Expression>> {
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let mut expressions = vec![self.expression()?];
+        while self.lexer.is_symbol(Symbol::Comma) {
+            self.lexer.next();
+            expressions.push(self.expression()?);
+        }
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        Ok(expressions)
+    }
+
+    /// constant_row は `(<constant>, ...)` を解析する
+    fn constant_row(&mut self) -> Result<Vec<Constant>> {
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let values = self.get_constant_list()?;
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        Ok(values)
+    }
+
     pub fn get_select_list(&mut self) -> Result<Vec<String>> {
         let mut fields = vec![self.lexer.eat_ident()?];
         while self.lexer.is_symbol(Symbol::Comma) {
@@ -75,6 +285,59 @@ impl<'a> Parser<'a> {
         Ok(fields)
     }
 
+    /// select_list は SELECT に並べたフィールドと集計関数、`cast(<expr> as <type>)` を解析する
+    /// 集計関数と cast は結果のフィールド名でフィールドの並びにも入れる
+    fn select_list(&mut self) -> Result<(Vec<String>, Vec<AggregationFunction>, Vec<Expression>)> {
+        let mut fields = vec![];
+        let mut aggregates = vec![];
+        let mut expressions = vec![];
+        loop {
+            if self.lexer.is_keyword("cast") {
+                let expression = self.operand()?;
+                fields.push(expression.to_string());
+                expressions.push(expression);
+            } else {
+                let name = self.lexer.eat_ident()?;
+                if self.lexer.is_symbol(Symbol::LParen) {
+                    let aggregate = self.aggregation_function(&name)?;
+                    fields.push(aggregate.field_name());
+                    aggregates.push(aggregate);
+                } else if self.lexer.is_symbol(Symbol::Dot) {
+                    // `<table>.<field>` はテーブル名を付けたまま結果のフィールド名にする
+                    self.lexer.next();
+                    fields.push(format!("{}.{}", name, self.lexer.eat_ident()?));
+                } else {
+                    fields.push(name);
+                }
+            }
+            if !self.lexer.is_symbol(Symbol::Comma) {
+                return Ok((fields, aggregates, expressions));
+            }
+            self.lexer.next();
+        }
+    }
+
+    /// aggregation_function は関数名に続く `(*)` か `([distinct] <field>)` を解析する。`*` は count だけに書ける
+    fn aggregation_function(&mut self, name: &str) -> Result<AggregationFunction> {
+        let Some(kind) = AggregationKind::from_name(name) else {
+            bail!("unknown aggregation function: {}", name);
+        };
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let count_all = kind == AggregationKind::Count && self.lexer.is_symbol(Symbol::Asterisk);
+        let aggregate = if count_all {
+            self.lexer.next();
+            AggregationFunction::new(kind, None, false)
+        } else {
+            let distinct = self.lexer.is_keyword("distinct");
+            if distinct {
+                self.lexer.eat_keyword("distinct")?;
+            }
+            AggregationFunction::new(kind, Some(self.lexer.eat_ident()?), distinct)
+        };
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        Ok(aggregate)
+    }
+
     pub fn get_table_list(&mut self) -> Result<Vec<String>> {
         let mut tables = vec![self.lexer.eat_ident()?];
         while self.lexer.is_symbol(Symbol::Comma) {
@@ -84,6 +347,62 @@ impl<'a> Parser<'a> {
         Ok(tables)
     }
 
+    /// get_from_list は FROM に並べた `<table> [sample <n> percent]` と
+    /// `generate_series(<start>, <stop>) [<name>]` を解析して、FROM の部分だけを設定した QueryData を返す
+    fn get_from_list(&mut self) -> Result<QueryData> {
+        let mut tables = vec![];
+        let mut functions = vec![];
+        let mut samples = HashMap::new();
+        loop {
+            let name = self.lexer.eat_ident()?;
+            if self.lexer.is_symbol(Symbol::LParen) {
+                functions.push(self.table_function(&name)?);
+            } else {
+                if self.lexer.is_keyword("sample") {
+                    self.lexer.eat_keyword("sample")?;
+                    let percent = self.lexer.eat_int_constant()?;
+                    self.lexer.eat_keyword("percent")?;
+                    samples.insert(name.clone(), BlockSample::new(percent)?);
+                }
+                tables.push(name);
+            }
+            if !self.lexer.is_symbol(Symbol::Comma) {
+                return Ok(QueryData::new(vec![], tables, Predicate::default())
+                    .with_table_functions(functions)
+                    .with_samples(samples));
+            }
+            self.lexer.next();
+        }
+    }
+
+    fn table_function(&mut self, name: &str) -> Result<TableFunction> {
+        if !name.eq_ignore_ascii_case(TableFunction::GENERATE_SERIES) {
+            bail!("unknown table function: {}", name);
+        }
+        self.lexer.eat_symbol(Symbol::LParen)?;
+        let start = self.int_argument(name)?;
+        self.lexer.eat_symbol(Symbol::Comma)?;
+        let stop = self.int_argument(name)?;
+        self.lexer.eat_symbol(Symbol::RParen)?;
+        let field_name = if self.lexer.is_ident() {
+            Some(self.lexer.eat_ident()?)
+        } else {
+            None
+        };
+        Ok(TableFunction::generate_series(start, stop, field_name))
+    }
+
+    fn int_argument(&mut self, function_name: &str) -> Result<i32> {
+        match self.constant()? {
+            Constant::Int(value) => Ok(value),
+            value => bail!(
+                "{} expects int arguments, found {}",
+                function_name,
+                value.to_literal()
+            ),
+        }
+    }
+
     pub fn get_field_list(&mut self) -> Result<Vec<String>> {
         let mut fields = vec![self.lexer.eat_ident()?];
 
@@ -107,10 +426,24 @@ impl<'a> Parser<'a> {
     }
 
     pub fn query(&mut self) -> Result<QueryData> {
+        if self.lexer.is_keyword("show") {
+            return self.show();
+        }
         self.lexer.eat_keyword("select")?;
-        let fields = self.get_select_list()?;
+        let hints = self.hints()?;
+        let (fields, aggregates, expressions) = self.select_list()?;
         self.lexer.eat_keyword("from")?;
-        let tables = self.get_table_list()?;
+        let from = self.get_from_list()?;
+        let qualified = fields
+            .iter()
+            .filter(|field| !expressions.iter().any(|e| e.to_string() == **field));
+        for field in qualified {
+            if let (Some(table_name), _) = split_qualified(field) {
+                if !from.tables.iter().any(|table| table == table_name) {
+                    bail!("table not found in from clause: {}", field);
+                }
+            }
+        }
 
         let pred = if self.lexer.is_keyword("where") {
             self.lexer.eat_keyword("where")?;
@@ -118,8 +451,234 @@ impl<'a> Parser<'a> {
         } else {
             Predicate::default()
         };
+        let group_by = if self.lexer.is_keyword("group") {
+            self.lexer.eat_keyword("group")?;
+            self.lexer.eat_keyword("by")?;
+            self.get_select_list()?
+        } else {
+            vec![]
+        };
+        let order_by = if self.lexer.is_keyword("order") {
+            self.order_by()?
+        } else {
+            vec![]
+        };
+
+        // `as of lsn <n>` は過去の時点のデータベースへの問い合わせ
+        let as_of = if self.lexer.is_keyword("as") {
+            self.lexer.eat_keyword("as")?;
+            self.lexer.eat_keyword("of")?;
+            self.lexer.eat_keyword("lsn")?;
+            Some(self.lexer.eat_int_constant()?)
+        } else {
+            None
+        };
+
+        Ok(QueryData::new(fields, from.tables, pred)
+            .with_table_functions(from.table_functions)
+            .with_samples(from.samples)
+            .with_hints(hints)
+            .with_aggregation(group_by, aggregates)
+            .with_expressions(expressions)
+            .with_order_by(order_by)
+            .with_as_of(as_of))
+    }
+
+    /// order_by は `order by <expr> [asc|desc] [, ...]` を解析する。向きを書かなければ昇順
+    fn order_by(&mut self) -> Result<Vec<SortKey>> {
+        self.lexer.eat_keyword("order")?;
+        self.lexer.eat_keyword("by")?;
+        let mut keys = vec![];
+        loop {
+            let expression = self.expression()?;
+            let descending = if self.lexer.is_keyword("desc") {
+                self.lexer.eat_keyword("desc")?;
+                true
+            } else {
+                if self.lexer.is_keyword("asc") {
+                    self.lexer.eat_keyword("asc")?;
+                }
+                false
+            };
+            keys.push(SortKey::new(expression, descending));
+            if !self.lexer.is_symbol(Symbol::Comma) {
+                return Ok(keys);
+            }
+            self.lexer.next();
+        }
+    }
+
+    /// hints は `/*+ ... */` があればオプティマイザヒントとして解析する
+    fn hints(&mut self) -> Result<Vec<Hint>> {
+        let Some(Token::Hint(text)) = self.lexer.current_token.clone() else {
+            return Ok(vec![]);
+        };
+        self.lexer.next();
+        Hint::parse_list(&text)
+    }
+
+    /// show は `show indexes`、`show grants`、`show tables` を解析する
+    pub fn show(&mut self) -> Result<QueryData> {
+        self.lexer.eat_keyword("show")?;
+        if self.lexer.is_keyword("grants") {
+            self.show_grants()
+        } else if self.lexer.is_keyword("tables") {
+            self.show_tables()
+        } else {
+            self.show_indexes()
+        }
+    }
+
+    /// show_indexes は `indexes [on <table>]` を索引のカタログへの問い合わせとして解析する
+    pub fn show_indexes(&mut self) -> Result<QueryData> {
+        self.lexer.eat_keyword("indexes")?;
+        let fields = [
+            idxcat::INDEX_NAME,
+            idxcat::TABLE_NAME,
+            idxcat::FIELD_NAME,
+            idxcat::INDEX_TYPE,
+            idxcat::IS_UNIQUE,
+            idxcat::PREFIX_LENGTH,
+        ]
+        .map(String::from)
+        .to_vec();
+
+        let pred = if self.lexer.is_keyword("on") {
+            self.lexer.eat_keyword("on")?;
+            let table_name = self.lexer.eat_ident()?;
+            Predicate::new(Term::new(
+                Expression::FieldName(idxcat::TABLE_NAME.into()),
+                Expression::Value(Constant::String(table_name)),
+            ))
+        } else {
+            Predicate::default()
+        };
+
+        Ok(QueryData::new(fields, vec![INDEX_CATALOG.into()], pred))
+    }
+
+    /// show_grants は `grants [on <table>]` を権限のカタログへの問い合わせとして解析する
+    pub fn show_grants(&mut self) -> Result<QueryData> {
+        self.lexer.eat_keyword("grants")?;
+        let fields = [privcat::GRANTEE, privcat::TABLE_NAME, privcat::PRIVILEGE]
+            .map(String::from)
+            .to_vec();
+
+        let pred = if self.lexer.is_keyword("on") {
+            self.lexer.eat_keyword("on")?;
+            let table_name = self.lexer.eat_ident()?;
+            Predicate::new(Term::new(
+                Expression::FieldName(privcat::TABLE_NAME.into()),
+                Expression::Value(Constant::String(table_name)),
+            ))
+        } else {
+            Predicate::default()
+        };
+
+        Ok(QueryData::new(fields, vec![PRIVILEGE_CATALOG.into()], pred))
+    }
+
+    /// show_tables は `tables` をテーブルのカタログへの問い合わせとして解析する
+    /// 作成した時刻と、最後に統計を取り直した時刻とそのときのレコード数の見積もりを返す
+    pub fn show_tables(&mut self) -> Result<QueryData> {
+        self.lexer.eat_keyword("tables")?;
+        let fields = [
+            tblcat::TABLE_NAME,
+            tblcat::CREATED,
+            tblcat::ANALYZED,
+            tblcat::NUM_RECORDS,
+        ]
+        .map(String::from)
+        .to_vec();
+        Ok(QueryData::new(
+            fields,
+            vec![TABLE_CATALOG.into()],
+            Predicate::default(),
+        ))
+    }
+
+    /// is_query は入力が問い合わせ（select または show）かどうかを返す
+    pub fn is_query(&self) -> bool {
+        self.lexer.is_keyword("select") || self.lexer.is_keyword("show")
+    }
+
+    /// is_explain は入力が問い合わせのプランを表示する文かどうかを返す
+    pub fn is_explain(&self) -> bool {
+        self.lexer.is_keyword("explain")
+    }
+
+    /// explain は `explain <query>` を解析して、プランを表示する問い合わせを返す
+    pub fn explain(&mut self) -> Result<QueryData> {
+        self.lexer.eat_keyword("explain")?;
+        self.query()
+    }
+
+    /// is_cursor_cmd は入力がカーソルを操作する文かどうかを返す
+    pub fn is_cursor_cmd(&self) -> bool {
+        ["declare", "fetch", "close"]
+            .iter()
+            .any(|keyword| self.lexer.is_keyword(keyword))
+    }
+
+    pub fn cursor_cmd(&mut self) -> Result<CursorStatement> {
+        if self.lexer.is_keyword("declare") {
+            self.lexer.eat_keyword("declare")?;
+            self.lexer.eat_keyword("cursor")?;
+            let cursor_name = self.lexer.eat_ident()?;
+            self.lexer.eat_keyword("for")?;
+            let query = self.query()?;
+            Ok(CursorStatement::Declare {
+                cursor_name,
+                query: Box::new(query),
+            })
+        } else if self.lexer.is_keyword("fetch") {
+            self.lexer.eat_keyword("fetch")?;
+            let count = self.lexer.eat_int_constant()?;
+            if count <= 0 {
+                bail!("fetch count must be positive: {}", count);
+            }
+            self.lexer.eat_keyword("from")?;
+            let cursor_name = self.lexer.eat_ident()?;
+            Ok(CursorStatement::Fetch { cursor_name, count })
+        } else {
+            self.lexer.eat_keyword("close")?;
+            let cursor_name = self.lexer.eat_ident()?;
+            Ok(CursorStatement::Close { cursor_name })
+        }
+    }
+
+    /// is_listen_cmd は入力がテーブルの変更の知らせを購読する文かどうかを返す
+    pub fn is_listen_cmd(&self) -> bool {
+        self.lexer.is_keyword("listen") || self.lexer.is_keyword("unlisten")
+    }
 
-        Ok(QueryData::new(fields, tables, pred))
+    /// listen_cmd は `listen <table>` と `unlisten <table>` を解析する
+    pub fn listen_cmd(&mut self) -> Result<ListenStatement> {
+        if self.lexer.is_keyword("listen") {
+            self.lexer.eat_keyword("listen")?;
+            let table_name = self.lexer.eat_ident()?;
+            Ok(ListenStatement::Listen { table_name })
+        } else {
+            self.lexer.eat_keyword("unlisten")?;
+            let table_name = self.lexer.eat_ident()?;
+            Ok(ListenStatement::Unlisten { table_name })
+        }
+    }
+
+    /// check_syntax は文を実行せずに解析して、構文が正しいかどうかを確かめる
+    pub fn check_syntax(&mut self) -> Result<()> {
+        if self.is_cursor_cmd() {
+            self.cursor_cmd()?;
+        } else if self.is_listen_cmd() {
+            self.listen_cmd()?;
+        } else if self.is_explain() {
+            self.explain()?;
+        } else if self.is_query() {
+            self.query()?;
+        } else {
+            self.update_cmd()?;
+        }
+        Ok(())
     }
 
     pub fn update_cmd(&mut self) -> Result<Statement> {
@@ -133,6 +692,9 @@ impl<'a> Parser<'a> {
                 "create" => self.create()?,
                 "update" => self.modify()?,
                 "delete" => self.delete()?,
+                "grant" => self.grant()?,
+                "revoke" => self.revoke()?,
+                "cluster" => self.cluster()?,
                 _ => bail!("Unknown keyword: {}", k),
             },
             _ => bail!("Expected a keyword, found {:?}", token),
@@ -155,6 +717,59 @@ impl<'a> Parser<'a> {
         Ok(Statement::Delete(DeleteData { table_name, pred }))
     }
 
+    /// grant は `grant <privileges> on <table> to <user>` を解析する
+    pub fn grant(&mut self) -> Result<Statement> {
+        self.lexer.eat_keyword("grant")?;
+        let privileges = self.privileges()?;
+        self.lexer.eat_keyword("on")?;
+        let table_name = self.lexer.eat_ident()?;
+        self.lexer.eat_keyword("to")?;
+        let grantee = self.lexer.eat_ident()?;
+        Ok(Statement::Grant(GrantData {
+            privileges,
+            table_name,
+            grantee,
+        }))
+    }
+
+    /// revoke は `revoke <privileges> on <table> from <user>` を解析する
+    pub fn revoke(&mut self) -> Result<Statement> {
+        self.lexer.eat_keyword("revoke")?;
+        let privileges = self.privileges()?;
+        self.lexer.eat_keyword("on")?;
+        let table_name = self.lexer.eat_ident()?;
+        self.lexer.eat_keyword("from")?;
+        let grantee = self.lexer.eat_ident()?;
+        Ok(Statement::Revoke(GrantData {
+            privileges,
+            table_name,
+            grantee,
+        }))
+    }
+
+    /// privileges は `all` またはカンマで区切った権限の一覧を解析する
+    fn privileges(&mut self) -> Result<Vec<Privilege>> {
+        if self.lexer.is_keyword("all") {
+            self.lexer.eat_keyword("all")?;
+            return Ok(Privilege::ALL.to_vec());
+        }
+        let mut privileges = vec![self.privilege()?];
+        while self.lexer.is_symbol(Symbol::Comma) {
+            self.lexer.next();
+            privileges.push(self.privilege()?);
+        }
+        Ok(privileges)
+    }
+
+    fn privilege(&mut self) -> Result<Privilege> {
+        let Some(Token::Keyword(keyword)) = self.lexer.current_token.clone() else {
+            bail!("Expected a privilege, found {:?}", self.lexer.current_token);
+        };
+        let privilege = keyword.parse()?;
+        self.lexer.next();
+        Ok(privilege)
+    }
+
     pub fn modify(&mut self) -> Result<Statement> {
         self.lexer.eat_keyword("update")?;
         let table_name = self.lexer.eat_ident()?;
@@ -210,7 +825,7 @@ impl<'a> Parser<'a> {
             Token::Keyword(k) => match k.as_str() {
                 "table" => self.create_table()?,
                 "view" => self.create_view()?,
-                "index" => self.create_index()?,
+                "index" | "unique" => self.create_index()?,
                 _ => bail!("Unknown keyword: {}", k),
             },
             _ => bail!("Expected a keyword, found {:?}", token),
@@ -218,19 +833,35 @@ impl<'a> Parser<'a> {
         Ok(stmt)
     }
 
+    /// create_index は `create [unique] index <name> on <table> (<field>[(<prefix>)]) [using <type>]` を解析する
     pub fn create_index(&mut self) -> Result<Statement> {
+        let mut options = IndexOptions::default();
+        if self.lexer.is_keyword("unique") {
+            self.lexer.eat_keyword("unique")?;
+            options.unique = true;
+        }
         self.lexer.eat_keyword("index")?;
         let index_name = self.lexer.eat_ident()?;
         self.lexer.eat_keyword("on")?;
         let table_name = self.lexer.eat_ident()?;
         self.lexer.eat_symbol(Symbol::LParen)?;
         let field_name = self.lexer.eat_ident()?;
+        if self.lexer.is_symbol(Symbol::LParen) {
+            self.lexer.eat_symbol(Symbol::LParen)?;
+            options.prefix_length = Some(self.lexer.eat_int_constant()?);
+            self.lexer.eat_symbol(Symbol::RParen)?;
+        }
         self.lexer.eat_symbol(Symbol::RParen)?;
+        if self.lexer.is_keyword("using") {
+            self.lexer.eat_keyword("using")?;
+            options.index_type = self.lexer.eat_ident()?.parse()?;
+        }
 
         let stmt = CreateIndexData {
             index_name,
             table_name,
             field_name,
+            options,
         };
         Ok(Statement::Create(CreateStatement::CreateIndex(stmt)))
     }
@@ -240,26 +871,48 @@ impl<'a> Parser<'a> {
         let view_name = self.lexer.eat_ident()?;
         self.lexer.eat_keyword("as")?;
         let query = self.query()?;
+        if query.as_of.is_some() {
+            bail!("view cannot be defined as of a past lsn");
+        }
         let stmt = CreateViewData { view_name, query };
-        Ok(Statement::Create(CreateStatement::CreateView(stmt)))
+        Ok(Statement::Create(CreateStatement::CreateView(Box::new(stmt))))
     }
 
+    /// create_table は `create table <name> (<field defs>) [cluster by <field>]` を解析する
     pub fn create_table(&mut self) -> Result<Statement> {
         self.lexer.eat_keyword("table")?;
         let table_name = self.lexer.eat_ident()?;
         self.lexer.eat_symbol(Symbol::LParen)?;
         let schema = self.field_defs()?;
         self.lexer.eat_symbol(Symbol::RParen)?;
+        let cluster_key = if self.lexer.is_keyword("cluster") {
+            self.lexer.eat_keyword("cluster")?;
+            self.lexer.eat_keyword("by")?;
+            Some(self.lexer.eat_ident()?)
+        } else {
+            None
+        };
         Ok(Statement::Create(CreateStatement::CreateTable(
-            CreateTableData { table_name, schema },
+            CreateTableData {
+                table_name,
+                schema,
+                cluster_key,
+            },
         )))
     }
 
+    /// cluster は `cluster <table>` を解析する
+    pub fn cluster(&mut self) -> Result<Statement> {
+        self.lexer.eat_keyword("cluster")?;
+        let table_name = self.lexer.eat_ident()?;
+        Ok(Statement::Cluster(ClusterData { table_name }))
+    }
+
     fn field_defs(&mut self) -> Result<Schema> {
         let mut schema = Schema::default();
         loop {
             let sch = self.field_def()?;
-            schema.add_all(Arc::new(sch))?;
+            schema.add_all(sch)?;
             let Some(ref token) = self.lexer.current_token else {
                 break;
             };
@@ -271,9 +924,25 @@ impl<'a> Parser<'a> {
         Ok(schema)
     }
 
+    /// field_def は `<name> <type> [collate <collation>] [on overflow <error|truncate>]` を解析する
     fn field_def(&mut self) -> Result<Schema> {
         let field_name = self.lexer.eat_ident()?;
-        self.field_type(field_name)
+        let mut schema = self.field_type(field_name.clone())?;
+        if self.lexer.is_keyword("collate") {
+            self.lexer.eat_keyword("collate")?;
+            let collation = self.lexer.eat_ident()?.parse()?;
+            schema.set_collation(&field_name, collation)?;
+        }
+        if self.lexer.is_keyword("on") {
+            self.lexer.eat_keyword("on")?;
+            let word = self.lexer.eat_ident()?;
+            if !word.eq_ignore_ascii_case("overflow") {
+                bail!("Expected 'overflow', found {}", word);
+            }
+            let truncation = self.lexer.eat_ident()?.parse()?;
+            schema.set_truncation(&field_name, truncation)?;
+        }
+        Ok(schema)
     }
 
     fn field_type(&mut self, field_name: String) -> Result<Schema> {
@@ -285,6 +954,18 @@ impl<'a> Parser<'a> {
         if token.is_keyword("int") {
             self.lexer.next();
             schema.add_int_field(field_name);
+        } else if token.is_keyword("boolean") {
+            self.lexer.next();
+            schema.add_bool_field(field_name);
+        } else if token.is_keyword("date") {
+            self.lexer.next();
+            schema.add_date_field(field_name);
+        } else if token.is_keyword("text") {
+            self.lexer.next();
+            schema.add_text_field(field_name);
+        } else if token.is_keyword("blob") {
+            self.lexer.next();
+            schema.add_blob_field(field_name);
         } else {
             self.lexer.eat_keyword("varchar")?;
             self.lexer.eat_symbol(Symbol::LParen)?;
@@ -300,12 +981,19 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use crate::{
+        index::{IndexOptions, IndexType},
         parse::parser::Parser,
         query::{
-            constant::Constant, create_index_data::CreateIndexData, create_table_data::CreateTableData, create_view_data::CreateViewData, delete_data::DeleteData, expression::Expression, insert_data::InsertData, modify_data::ModifyData, predicate::Predicate, query_data::QueryData, statement::{CreateStatement, Statement}, term::Term
+            aggregation_function::{AggregationFunction, AggregationKind}, cast::CastType, cluster_data::ClusterData, constant::Constant, create_index_data::CreateIndexData, create_table_data::CreateTableData, create_view_data::CreateViewData, cursor_data::CursorStatement, delete_data::DeleteData, expression::{Expression, Function, Operator}, grant_data::{GrantData, Privilege}, hint::Hint, in_term::InTerm, insert_data::InsertData, listen_data::ListenStatement, modify_data::ModifyData, null_term::NullTerm, predicate::Predicate, query_data::QueryData, record_comparator::SortKey, statement::{CreateStatement, Statement}, table_function::TableFunction, term::{Comparison, Term}
+        },
+        record::{
+            block_sample::BlockSample,
+            collation::Collation,
+            schema::{FieldTypes, Schema},
+            truncation::TruncationPolicy,
         },
-        record::schema::Schema,
     };
+    use std::collections::HashMap;
 
     #[test]
     fn can_parse_select() {
@@ -317,14 +1005,151 @@ mod tests {
             QueryData {
                 fields: vec!["name".into(), "age".into()],
                 tables: vec!["people".into()],
+                table_functions: vec![],
+                samples: HashMap::new(),
                 pred: Predicate::new(Term::new(
                     Expression::FieldName("age".into()),
                     Expression::Value(Constant::Int(30)),
                 )),
+                hints: vec![],
+                group_by: vec![],
+                aggregates: vec![],
+                expressions: vec![],
+                order_by: vec![],
+                as_of: None,
             }
         )
     }
 
+    #[test]
+    fn can_parse_select_with_hints() {
+        let query = "select /*+ use_index(people idx_age) leading(people dept) */ name from people, dept";
+        let mut parser = Parser::new(query);
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.fields, vec!["name".to_string()]);
+        assert_eq!(
+            query_data.hints,
+            vec![
+                Hint::UseIndex {
+                    table_name: "people".into(),
+                    index_name: "idx_age".into(),
+                },
+                Hint::Leading(vec!["people".into(), "dept".into()]),
+            ]
+        );
+        assert_eq!(
+            query_data.to_string(),
+            "SELECT /*+ use_index(people idx_age) leading(people dept) */ name FROM people, dept"
+        );
+        let query = query_data.to_string();
+        assert_eq!(Parser::new(&query).query().unwrap(), query_data);
+    }
+
+    #[test]
+    fn can_parse_order_by() {
+        let query = "select a, b from t where a = 1 order by a + b desc, b asc, c";
+        let mut parser = Parser::new(query);
+        let query_data = parser.query().unwrap();
+        let sum = Expression::Arithmetic(
+            Box::new(Expression::FieldName("a".into())),
+            Operator::Add,
+            Box::new(Expression::FieldName("b".into())),
+        );
+        assert_eq!(
+            query_data.order_by,
+            vec![SortKey::new(sum, true), "b".into(), "c".into()]
+        );
+        assert_eq!(query_data.required_fields(), vec!["a", "b", "c"]);
+        assert_eq!(
+            query_data.to_string(),
+            "SELECT a, b FROM t WHERE a = 1 ORDER BY a + b desc, b, c"
+        );
+        let query = query_data.to_string();
+        assert_eq!(Parser::new(&query).query().unwrap(), query_data);
+    }
+
+    #[test]
+    fn can_parse_aggregation() {
+        let query = "select dept, count(*), count(distinct age), max(age) from people group by dept";
+        let mut parser = Parser::new(query);
+        let query_data = parser.query().unwrap();
+        assert_eq!(
+            query_data.fields,
+            vec!["dept", "count(*)", "count(distinct age)", "max(age)"]
+        );
+        assert_eq!(
+            query_data.aggregates,
+            vec![
+                AggregationFunction::new(AggregationKind::Count, None, false),
+                AggregationFunction::new(AggregationKind::Count, Some("age".into()), true),
+                AggregationFunction::new(AggregationKind::Max, Some("age".into()), false),
+            ]
+        );
+        assert_eq!(query_data.group_by, vec!["dept"]);
+        assert_eq!(query_data.required_fields(), vec!["dept", "age"]);
+        let query = query_data.to_string();
+        assert_eq!(
+            query,
+            "SELECT dept, count(*), count(distinct age), max(age) FROM people GROUP BY dept"
+        );
+        assert_eq!(Parser::new(&query).query().unwrap(), query_data);
+
+        assert!(Parser::new("select avg(age) from people").query().is_err());
+        assert!(Parser::new("select sum(*) from people").query().is_err());
+    }
+
+    #[test]
+    fn should_reparse_view_def() {
+        let query = "create view v as select name from people where name = 'Alice' and age = 30";
+        let mut parser = Parser::new(query);
+        let Statement::Create(super::CreateStatement::CreateView(data)) = parser.create().unwrap()
+        else {
+            panic!("Expected CreateView");
+        };
+        let view_def = data.view_def();
+        assert_eq!(
+            view_def,
+            "SELECT name FROM people WHERE name = 'Alice' AND age = 30"
+        );
+        assert_eq!(Parser::new(&view_def).query().unwrap(), data.query);
+    }
+
+    #[test]
+    fn can_parse_row_predicates() {
+        let field = |name: &str| Expression::FieldName(name.into());
+        let query = "select a from t where (a, b) = (1, 'x') and (a, b) in ((1, 'x'), (2, 'y'))";
+        let query_data = Parser::new(query).query().unwrap();
+        let mut pred = Predicate::new(Term::new(field("a"), Constant::Int(1).into()));
+        pred.con_join_with(&Predicate::new(Term::new(
+            field("b"),
+            Constant::String("x".into()).into(),
+        )));
+        pred.con_join_with(&Predicate::from_in_term(
+            InTerm::new(
+                vec![field("a"), field("b")],
+                vec![
+                    vec![Constant::Int(1), Constant::String("x".into())],
+                    vec![Constant::Int(2), Constant::String("y".into())],
+                ],
+            )
+            .unwrap(),
+        ));
+        assert_eq!(query_data.pred, pred);
+        assert_eq!(
+            query_data.to_string(),
+            "SELECT a FROM t WHERE a = 1 AND b = 'x' AND (a, b) IN ((1, 'x'), (2, 'y'))"
+        );
+        let query = query_data.to_string();
+        assert_eq!(Parser::new(&query).query().unwrap(), query_data);
+
+        for query in [
+            "select a from t where (a, b) = (1)",
+            "select a from t where (a, b) in ((1, 'x'), (2))",
+        ] {
+            assert!(Parser::new(query).query().is_err(), "{}", query);
+        }
+    }
+
     #[test]
     fn can_parse_create_table() {
         let query = "create table people (name varchar(255), age int)";
@@ -344,11 +1169,373 @@ mod tests {
             create_table_data,
             CreateTableData {
                 table_name: "people".into(),
-                schema
+                schema,
+                cluster_key: None,
+            }
+        )
+    }
+
+    #[test]
+    fn can_parse_create_table_with_text_and_blob() {
+        let query = "create table docs (id int, body text, image blob)";
+        let mut parser = Parser::new(query);
+        let stmt = parser.create().unwrap();
+
+        let create_table_data = match stmt {
+            Statement::Create(CreateStatement::CreateTable(data)) => data,
+            _ => panic!("Expected CreateTable"),
+        };
+
+        let mut schema = Schema::default();
+        schema.add_int_field("id");
+        schema.add_text_field("body");
+        schema.add_blob_field("image");
+
+        assert_eq!(
+            create_table_data,
+            CreateTableData {
+                table_name: "docs".into(),
+                schema,
+                cluster_key: None,
             }
         )
     }
 
+    #[test]
+    fn can_parse_boolean_field_and_literals() {
+        let mut parser = Parser::new("create table users (name varchar(10), active boolean)");
+        let Statement::Create(CreateStatement::CreateTable(data)) = parser.create().unwrap() else {
+            panic!("Expected CreateTable");
+        };
+        let mut schema = Schema::default();
+        schema.add_string_field("name", 10);
+        schema.add_bool_field("active");
+        assert_eq!(data.schema, schema);
+
+        let mut parser = Parser::new("select name from users where active = TRUE");
+        let query_data = parser.query().unwrap();
+        assert_eq!(
+            query_data.pred,
+            Predicate::new(Term::new(
+                Expression::FieldName("active".into()),
+                Expression::Value(Constant::Bool(true)),
+            ))
+        );
+
+        let mut parser = Parser::new("insert into users (name, active) values ('Alice', false)");
+        let Statement::Insert(insert_data) = parser.update_cmd().unwrap() else {
+            panic!("Expected Insert");
+        };
+        assert_eq!(
+            insert_data.values,
+            vec![Constant::String("Alice".into()), Constant::Bool(false)]
+        );
+        assert_eq!(Constant::Bool(false).to_literal(), "false");
+    }
+
+    #[test]
+    fn can_parse_in_list() {
+        let field = |name: &str| Expression::FieldName(name.into());
+        let in_list = |name: &str, values: Vec<i32>| {
+            let rows = values.into_iter().map(|value| vec![Constant::Int(value)]).collect();
+            Predicate::from_in_term(InTerm::new(vec![field(name)], rows).unwrap())
+        };
+
+        let query_data = Parser::new("select id from t where id in (1, 2, 3) and not a not in (4)").query().unwrap();
+        let mut pred = in_list("id", vec![1, 2, 3]);
+        pred.con_join_with(&Predicate::negate(Predicate::negate(in_list("a", vec![4]))));
+        assert_eq!(query_data.pred, pred);
+
+        // 文字列にした述語を解析し直すと同じ述語になる
+        let sql = query_data.to_string();
+        assert_eq!(sql, "SELECT id FROM t WHERE id IN (1, 2, 3) AND NOT (NOT (a IN (4)))");
+        assert_eq!(Parser::new(&sql).query().unwrap().pred, query_data.pred);
+
+        for query in [
+            "select id from t where id in ()",
+            "select id from t where id in (1, 2",
+            "select id from t where id in (a)",
+            "select id from t where id not = 1",
+        ] {
+            assert!(Parser::new(query).query().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn can_parse_is_null() {
+        let field = |name: &str| Expression::FieldName(name.into());
+        let query_data = Parser::new("select a from t where a is null and b is not null or not c is null").query().unwrap();
+        let mut pred = Predicate::from_null_term(NullTerm::new(field("a"), false));
+        pred.con_join_with(&Predicate::from_null_term(NullTerm::new(field("b"), true)));
+        let negated = Predicate::negate(Predicate::from_null_term(NullTerm::new(field("c"), false)));
+        assert_eq!(query_data.pred, Predicate::or(vec![pred, negated]));
+
+        // 文字列にした述語を解析し直すと同じ述語になる
+        let sql = query_data.to_string();
+        assert_eq!(sql, "SELECT a FROM t WHERE (a IS NULL AND b IS NOT NULL OR NOT (c IS NULL))");
+        assert_eq!(Parser::new(&sql).query().unwrap().pred, query_data.pred);
+
+        for query in [
+            "select a from t where a is",
+            "select a from t where a is not 1",
+            "select a from t where is null",
+        ] {
+            assert!(Parser::new(query).query().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn can_parse_or_and_not() {
+        let field = |name: &str| Expression::FieldName(name.into());
+        let eq = |name: &str, value: i32| Predicate::new(Term::new(field(name), Constant::Int(value).into()));
+
+        // and は or より先に結びつき、not は直後の条件だけにかかる
+        let mut parser = Parser::new("select A from E where A = 1 and B = 2 or not C = 3 and (D = 4 or (A, B) = (5, 6))");
+        let query_data = parser.query().unwrap();
+        let mut first = eq("A", 1);
+        first.con_join_with(&eq("B", 2));
+        let mut row = eq("A", 5);
+        row.con_join_with(&eq("B", 6));
+        let mut second = Predicate::negate(eq("C", 3));
+        second.con_join_with(&Predicate::or(vec![eq("D", 4), row]));
+        assert_eq!(query_data.pred, Predicate::or(vec![first, second]));
+
+        // 文字列にした述語を解析し直すと同じ述語になる
+        let sql = query_data.to_string();
+        assert_eq!(sql, "SELECT A FROM E WHERE (A = 1 AND B = 2 OR NOT (C = 3) AND (D = 4 OR A = 5 AND B = 6))");
+        assert_eq!(Parser::new(&sql).query().unwrap().pred, query_data.pred);
+
+        let query_data = Parser::new("select A from E where not (A = 1 or B = 2)").query().unwrap();
+        assert_eq!(query_data.pred, Predicate::negate(Predicate::or(vec![eq("A", 1), eq("B", 2)])));
+
+        for query in [
+            "select A from E where A = 1 or",
+            "select A from E where not",
+            "select A from E where (A = 1 or B = 2",
+        ] {
+            assert!(Parser::new(query).query().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn can_parse_comparisons_and_date_arithmetic() {
+        let mut parser = Parser::new(
+            "select A from E where D >= current_date - 7 and A <> -1 and B < current_timestamp",
+        );
+        let query_data = parser.query().unwrap();
+        let field = |name: &str| Expression::FieldName(name.into());
+        let mut pred = Predicate::new(Term::compare(
+            field("D"),
+            Comparison::GreaterEqual,
+            Expression::Arithmetic(
+                Box::new(Expression::Function(Function::CurrentDate)),
+                Operator::Subtract,
+                Box::new(Constant::Int(7).into()),
+            ),
+        ));
+        pred.con_join_with(&Predicate::new(Term::compare(
+            field("A"),
+            Comparison::NotEqual,
+            Constant::Int(-1).into(),
+        )));
+        pred.con_join_with(&Predicate::new(Term::compare(
+            field("B"),
+            Comparison::Less,
+            Expression::Function(Function::CurrentTimestamp),
+        )));
+        assert_eq!(query_data.pred, pred);
+        assert_eq!(
+            query_data.pred.to_string(),
+            "D >= current_date - 7 AND A <> -1 AND B < current_timestamp"
+        );
+        // 2文字の比較演算子の間には空白を入れられない
+        assert!(Parser::new("select A from E where A < = 1").query().is_err());
+
+        let mut parser = Parser::new("create table E (A int, D date)");
+        let Statement::Create(CreateStatement::CreateTable(data)) = parser.create().unwrap() else {
+            panic!("Expected CreateTable");
+        };
+        assert_eq!(data.schema.r#type("D"), Some(FieldTypes::Date));
+
+        let mut parser = Parser::new("insert into E (A, D) values (1, date '2024-02-29')");
+        let Statement::Insert(insert_data) = parser.update_cmd().unwrap() else {
+            panic!("Expected Insert");
+        };
+        assert_eq!(insert_data.values[1], Constant::Date("2024-02-29".parse().unwrap()));
+        assert_eq!(insert_data.values[1].to_literal(), "date '2024-02-29'");
+        assert!(Parser::new("insert into E (A, D) values (1, date '2023-02-29')")
+            .update_cmd()
+            .is_err());
+        assert!(Parser::new("select A from E where A ! 1").query().is_err());
+    }
+
+    #[test]
+    fn can_parse_table_sample() {
+        let mut parser = Parser::new("select A, B from S sample 10 percent, T where A = B");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.tables, vec!["S".to_string(), "T".to_string()]);
+        assert_eq!(
+            query_data.samples,
+            HashMap::from([("S".to_string(), BlockSample::new(10).unwrap())])
+        );
+        assert_eq!(
+            query_data.to_string(),
+            "SELECT A, B FROM S sample 10 percent, T WHERE A = B"
+        );
+
+        assert!(Parser::new("select A from S sample 101 percent").query().is_err());
+        assert!(Parser::new("select A from S sample 10").query().is_err());
+    }
+
+    #[test]
+    fn can_parse_generate_series() {
+        let mut parser =
+            Parser::new("select A, N from T, generate_series(-1, 10) N, generate_series(1, 2)");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.tables, vec!["T".to_string()]);
+        assert_eq!(
+            query_data.table_functions,
+            vec![
+                TableFunction::generate_series(-1, 10, Some("N".into())),
+                TableFunction::generate_series(1, 2, None),
+            ]
+        );
+        assert_eq!(
+            query_data.to_string(),
+            "SELECT A, N FROM T, generate_series(-1, 10) N, generate_series(1, 2)"
+        );
+
+        assert!(Parser::new("select N from series(1, 2) N").query().is_err());
+        assert!(Parser::new("select N from generate_series(1) N").query().is_err());
+        assert!(Parser::new("select N from generate_series(1, null) N")
+            .query()
+            .is_err());
+    }
+
+    #[test]
+    fn can_parse_qualified_select_list() {
+        let mut parser = Parser::new("select B, T.A, A, T.A from T, U where A = C");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.fields, vec!["B", "T.A", "A", "T.A"]);
+        assert_eq!(query_data.tables, vec!["T", "U"]);
+        assert_eq!(
+            query_data.to_string(),
+            "SELECT B, T.A, A, T.A FROM T, U WHERE A = C"
+        );
+
+        assert!(Parser::new("select V.A from T, U").query().is_err());
+        assert!(Parser::new("select T. from T").query().is_err());
+    }
+
+    #[test]
+    fn can_parse_cast() {
+        let mut parser =
+            Parser::new("select A from T where cast(B as int) + 1 = cast('2' as varchar(3))");
+        let query_data = parser.query().unwrap();
+        let cast = |expression: Expression, cast_type| Expression::Cast(Box::new(expression), cast_type);
+        assert_eq!(
+            query_data.pred,
+            Predicate::new(Term::new(
+                Expression::Arithmetic(
+                    Box::new(cast(Expression::FieldName("B".into()), CastType::Int)),
+                    Operator::Add,
+                    Box::new(Constant::Int(1).into()),
+                ),
+                cast(Constant::String("2".into()).into(), CastType::Varchar(3)),
+            ))
+        );
+        assert_eq!(
+            query_data.pred.to_string(),
+            "cast(B as int) + 1 = cast('2' as varchar(3))"
+        );
+
+        let mut parser = Parser::new("update T set D = cast(B as date) where A = 1");
+        let Statement::Update(modify_data) = parser.update_cmd().unwrap() else {
+            panic!("Expected Update");
+        };
+        assert_eq!(
+            modify_data.new_value,
+            cast(Expression::FieldName("B".into()), CastType::Date)
+        );
+        let mut parser = Parser::new("select A, cast(B as varchar(3)) from T");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.fields, vec!["A", "cast(B as varchar(3))"]);
+        assert_eq!(
+            query_data.expressions,
+            vec![cast(Expression::FieldName("B".into()), CastType::Varchar(3))]
+        );
+        assert_eq!(query_data.required_fields(), vec!["A", "B"]);
+        assert_eq!(query_data.to_string(), "SELECT A, cast(B as varchar(3)) FROM T");
+        assert!(Parser::new("select A from T where cast(B as blob) = 1").query().is_err());
+        assert!(Parser::new("select A from T where cast(B int) = 1").query().is_err());
+    }
+
+    #[test]
+    fn can_parse_create_table_with_collation() {
+        let query = "create table T (A varchar(10) collate nocase, B text collate binary, C int)";
+        let Statement::Create(CreateStatement::CreateTable(data)) =
+            Parser::new(query).update_cmd().unwrap()
+        else {
+            panic!("Expected CreateTable");
+        };
+        assert_eq!(data.schema.collation("A"), Collation::NoCase);
+        assert_eq!(data.schema.collation("B"), Collation::Binary);
+        assert_eq!(data.schema.collation("C"), Collation::Binary);
+
+        for query in [
+            "create table T (A varchar(10) collate german)",
+            "create table T (A int collate nocase)",
+        ] {
+            assert!(Parser::new(query).update_cmd().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn can_parse_create_table_with_overflow_policy() {
+        let query = "create table T (A varchar(3) collate nocase on overflow truncate, B varchar(3) on overflow error, C varchar(3))";
+        let Statement::Create(CreateStatement::CreateTable(data)) =
+            Parser::new(query).update_cmd().unwrap()
+        else {
+            panic!("Expected CreateTable");
+        };
+        assert_eq!(data.schema.truncation("A"), TruncationPolicy::Truncate);
+        assert_eq!(data.schema.collation("A"), Collation::NoCase);
+        assert_eq!(data.schema.truncation("B"), TruncationPolicy::Error);
+        assert_eq!(data.schema.truncation("C"), TruncationPolicy::Error);
+
+        for query in [
+            "create table T (A varchar(3) on overflow ignore)",
+            "create table T (A varchar(3) on error truncate)",
+            "create table T (A int on overflow truncate)",
+        ] {
+            assert!(Parser::new(query).update_cmd().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn can_parse_cluster() {
+        let query = "create table T (A int, B varchar(10)) cluster by A";
+        let Statement::Create(CreateStatement::CreateTable(data)) =
+            Parser::new(query).update_cmd().unwrap()
+        else {
+            panic!("Expected CreateTable");
+        };
+        assert_eq!(data.cluster_key, Some("A".into()));
+
+        let Statement::Cluster(data) = Parser::new("cluster T").update_cmd().unwrap() else {
+            panic!("Expected Cluster");
+        };
+        assert_eq!(
+            data,
+            ClusterData {
+                table_name: "T".into()
+            }
+        );
+        assert!(Parser::new("create table T (A int) cluster A")
+            .update_cmd()
+            .is_err());
+    }
+
     #[test]
     fn can_parse_create_view() {
         let query = "create view people_view as select name, age from people where age = 30";
@@ -356,17 +1543,25 @@ mod tests {
         let stmt = parser.create().unwrap();
 
         let create_view_data = match stmt {
-            Statement::Create(super::CreateStatement::CreateView(data)) => data,
+            Statement::Create(super::CreateStatement::CreateView(data)) => *data,
             _ => panic!("Expected CreateView"),
         };
 
         let query_data = QueryData {
             fields: vec!["name".into(), "age".into()],
             tables: vec!["people".into()],
+            table_functions: vec![],
+            samples: HashMap::new(),
             pred: Predicate::new(Term::new(
                 Expression::FieldName("age".into()),
                 Expression::Value(Constant::Int(30)),
             )),
+            hints: vec![],
+            group_by: vec![],
+            aggregates: vec![],
+            expressions: vec![],
+            order_by: vec![],
+            as_of: None,
         };
 
         assert_eq!(
@@ -394,11 +1589,197 @@ mod tests {
             CreateIndexData {
                 index_name: "people_name_index".into(),
                 table_name: "people".into(),
-                field_name: "name".into()
+                field_name: "name".into(),
+                options: IndexOptions::default(),
             }
         )
     }
 
+    #[test]
+    fn can_parse_create_index_with_options() {
+        let query = "create unique index people_name_index on people (name(8)) using btree";
+        let mut parser = Parser::new(query);
+        let stmt = parser.create().unwrap();
+
+        let create_index_data = match stmt {
+            Statement::Create(super::CreateStatement::CreateIndex(data)) => data,
+            _ => panic!("Expected CreateIndex"),
+        };
+
+        assert_eq!(
+            create_index_data.options,
+            IndexOptions {
+                index_type: IndexType::BTree,
+                unique: true,
+                prefix_length: Some(8),
+            }
+        );
+
+        let query = "create index people_name_index on people (name) using bitmap";
+        let mut parser = Parser::new(query);
+        assert!(parser.create().is_err());
+    }
+
+    #[test]
+    fn can_parse_cursor_cmd() {
+        let mut parser = Parser::new("declare cursor c for select name from people");
+        assert!(parser.is_cursor_cmd());
+        assert_eq!(
+            parser.cursor_cmd().unwrap(),
+            CursorStatement::Declare {
+                cursor_name: "c".into(),
+                query: Box::new(QueryData::new(
+                    vec!["name".into()],
+                    vec!["people".into()],
+                    Predicate::default()
+                )),
+            }
+        );
+
+        let mut parser = Parser::new("fetch 10 from c");
+        assert_eq!(
+            parser.cursor_cmd().unwrap(),
+            CursorStatement::Fetch {
+                cursor_name: "c".into(),
+                count: 10
+            }
+        );
+
+        let mut parser = Parser::new("close c");
+        assert_eq!(
+            parser.cursor_cmd().unwrap(),
+            CursorStatement::Close {
+                cursor_name: "c".into()
+            }
+        );
+
+        let mut parser = Parser::new("fetch 0 from c");
+        assert!(parser.cursor_cmd().is_err());
+
+        let parser = Parser::new("select name from people");
+        assert!(!parser.is_cursor_cmd());
+        assert!(parser.is_query());
+    }
+
+    #[test]
+    fn can_parse_listen_cmd() {
+        let mut parser = Parser::new("listen T");
+        assert!(parser.is_listen_cmd());
+        assert_eq!(
+            parser.listen_cmd().unwrap(),
+            ListenStatement::Listen {
+                table_name: "T".into()
+            }
+        );
+        assert_eq!(
+            Parser::new("unlisten T").listen_cmd().unwrap(),
+            ListenStatement::Unlisten {
+                table_name: "T".into()
+            }
+        );
+        assert!(!Parser::new("select A from T").is_listen_cmd());
+    }
+
+    #[test]
+    fn can_check_syntax() {
+        for sql in [
+            "select A from T where A = 1",
+            "explain select A from T",
+            "insert into T(A) values (1)",
+            "fetch 1 from c",
+            "listen T",
+        ] {
+            assert!(Parser::new(sql).check_syntax().is_ok(), "{}", sql);
+        }
+        for sql in ["select from T", "insert T values (1)", "fetch 0 from c", "drop T"] {
+            assert!(Parser::new(sql).check_syntax().is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn can_parse_select_as_of() {
+        let query_data = Parser::new("select A from T where A = 1 as of lsn 42")
+            .query()
+            .unwrap();
+        assert_eq!(query_data.as_of, Some(42));
+        assert_eq!(query_data.to_string(), "SELECT A FROM T WHERE A = 1 AS OF LSN 42");
+        assert!(Parser::new("select A from T as of 42").query().is_err());
+        assert!(Parser::new("create view V as select A from T as of lsn 1")
+            .update_cmd()
+            .is_err());
+    }
+
+    #[test]
+    fn can_parse_show_indexes() {
+        let mut parser = Parser::new("show indexes on people");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.tables, vec!["idxcat".to_string()]);
+        assert_eq!(query_data.fields[0], "indexname");
+        assert_eq!(
+            query_data.pred,
+            Predicate::new(Term::new(
+                Expression::FieldName("tablename".into()),
+                Expression::Value(Constant::String("people".into())),
+            ))
+        );
+
+        let mut parser = Parser::new("show indexes");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.pred, Predicate::default());
+    }
+
+    #[test]
+    fn can_parse_show_tables() {
+        let mut parser = Parser::new("show tables");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.tables, vec!["tblcat".to_string()]);
+        assert_eq!(
+            query_data.fields,
+            vec!["tblname", "created", "analyzed", "numrecs"]
+        );
+        assert_eq!(query_data.pred, Predicate::default());
+    }
+
+    #[test]
+    fn can_parse_show_grants() {
+        let mut parser = Parser::new("show grants on people");
+        let query_data = parser.query().unwrap();
+        assert_eq!(query_data.tables, vec!["privcat".to_string()]);
+        assert_eq!(query_data.fields, vec!["grantee", "tablename", "privilege"]);
+        assert_eq!(
+            query_data.pred,
+            Predicate::new(Term::new(
+                Expression::FieldName("tablename".into()),
+                Expression::Value(Constant::String("people".into())),
+            ))
+        );
+    }
+
+    #[test]
+    fn can_parse_grant_and_revoke() {
+        let mut parser = Parser::new("grant select, update on people to alice");
+        let Statement::Grant(data) = parser.update_cmd().unwrap() else {
+            panic!("Expected Grant");
+        };
+        assert_eq!(
+            data,
+            GrantData {
+                privileges: vec![Privilege::Select, Privilege::Update],
+                table_name: "people".into(),
+                grantee: "alice".into(),
+            }
+        );
+
+        let mut parser = Parser::new("revoke all on people from alice");
+        let Statement::Revoke(data) = parser.update_cmd().unwrap() else {
+            panic!("Expected Revoke");
+        };
+        assert_eq!(data.privileges, Privilege::ALL.to_vec());
+
+        let mut parser = Parser::new("grant drop on people to alice");
+        assert!(parser.update_cmd().is_err());
+    }
+
     #[test]
     fn can_parse_insert() {
         let query = "insert into people (name, age) values ('Alice', 30)";
@@ -1,12 +1,12 @@
 use super::{query_planner::QueryPlanner, ArcPlan, Plan};
 use crate::{
-    metadata::metadata_manager::MetadataManager,
-    parse::parser::Parser,
+    metadata::metadata_provider::MetadataProvider,
     plan::{
+        empty_plan::EmptyPlan, group_by_plan::GroupByPlan, index_select_plan::IndexSelectPlan,
         product_plan::ProductPlan, project_plan::ProjectPlan, select_plan::SelectPlan,
-        table_plan::TablePlan,
+        sort_plan::SortPlan, table_plan::TablePlan,
     },
-    query::query_data::QueryData,
+    query::{hint::Hint, predicate::NormalizedPredicate, query_data::QueryData},
     tx::transaction::Transaction,
     unlock,
 };
@@ -14,11 +14,11 @@ use anyhow::Result;
 use std::sync::{Arc, Mutex};
 
 pub struct BasicQueryPlanner {
-    metadata_manager: Arc<Mutex<MetadataManager>>,
+    metadata_manager: Arc<Mutex<dyn MetadataProvider>>,
 }
 
 impl BasicQueryPlanner {
-    pub fn new(metadata_manager: Arc<Mutex<MetadataManager>>) -> Self {
+    pub fn new(metadata_manager: Arc<Mutex<dyn MetadataProvider>>) -> Self {
         Self { metadata_manager }
     }
 }
@@ -26,33 +26,107 @@ impl BasicQueryPlanner {
 impl QueryPlanner for BasicQueryPlanner {
     fn create_plan(
         &mut self,
-        data: QueryData,
+        mut data: QueryData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Arc<Mutex<dyn Plan>>> {
+        // 述語がどのレコードでも満たされない場合は、テーブルを読まない空のプランにする
+        let unsatisfiable = match data.pred.normalize() {
+            NormalizedPredicate::Unsatisfiable => true,
+            NormalizedPredicate::Satisfiable(pred) => {
+                data.pred = pred;
+                false
+            }
+        };
+        let required_fields = data.required_fields();
+        let aggregation = data.is_aggregation();
         let mut plans = vec![];
+        // 結合はどれも内部結合なので、空のテーブルが1つでもあれば結果は空になる
+        let mut empty_table = None;
 
-        for table_name in data.tables {
+        // leading ヒントがあればその順にテーブルを結合する
+        let tables = Hint::leading_order(&data.hints, &data.tables)?.unwrap_or(data.tables);
+        for table_name in tables {
             let view_def = unlock!(self.metadata_manager).get_view_def(&table_name, tx.clone())?;
-            if let Some(view_def) = view_def {
-                let mut parser = Parser::new(&view_def);
-                let view_data = parser.query()?;
-                plans.push(self.create_plan(view_data, tx.clone())?);
+            let plan = if let Some(view_def) = view_def {
+                self.create_view_plan(&view_def, &data.pred, tx.clone())?
             } else {
-                let plan = TablePlan::new(table_name, tx.clone(), self.metadata_manager.clone())?;
-                plans.push(Arc::new(Mutex::new(plan)) as ArcPlan);
-            }
+                if empty_table.is_none()
+                    && unlock!(self.metadata_manager).is_empty(&table_name, tx.clone())?
+                {
+                    empty_table = Some(table_name.clone());
+                }
+                // 標本は索引を使わずにテーブルのブロックから選ぶ
+                if let Some(sample) = data.samples.get(&table_name) {
+                    let plan = TablePlan::new(
+                        table_name.clone(),
+                        tx.clone(),
+                        self.metadata_manager.clone(),
+                    )?;
+                    Arc::new(Mutex::new(plan.with_sample(*sample))) as ArcPlan
+                } else {
+                    IndexSelectPlan::table_plan(
+                        table_name.clone(),
+                        &data.pred,
+                        &data.hints,
+                        tx.clone(),
+                        self.metadata_manager.clone(),
+                    )?
+                }
+            };
+            QueryData::check_qualified_fields(&data.fields, &table_name, &unlock!(plan).schema())?;
+            plans.push(plan);
+        }
+        for function in &data.table_functions {
+            plans.push(self.create_table_function_plan(function));
         }
 
+        // 結合する場合は、それぞれのテーブルの項で先にレコードを選び、使うフィールドだけを出す
+        // 入力にまたがる項は直積ごとに結合の条件にして、どの入力にも当てはまらない項だけを最後に評価する
+        let joined = plans.len() > 1;
+        let mut pred = data.pred.clone();
+        if joined {
+            plans = plans
+                .into_iter()
+                .map(|plan| {
+                    ProjectPlan::prune(SelectPlan::push_down(plan, &mut pred), &required_fields)
+                })
+                .collect::<Result<_>>()?;
+        }
         let mut plan = plans.remove(0);
         for next_plan in plans {
-            plan = Arc::new(Mutex::new(ProductPlan::new(
+            let product = Arc::new(Mutex::new(ProductPlan::new(
                 plan.clone(),
                 next_plan.clone(),
             )?)) as ArcPlan;
+            (plan, _) = SelectPlan::join(product, &plan, &next_plan, &mut pred)?;
         }
 
-        plan = Arc::new(Mutex::new(SelectPlan::new(plan, data.pred.clone()))) as ArcPlan;
-        plan = Arc::new(Mutex::new(ProjectPlan::new(plan, data.fields.clone())?)) as ArcPlan;
+        if !joined || !pred.is_empty() {
+            plan = Arc::new(Mutex::new(SelectPlan::new(plan, pred))) as ArcPlan;
+        }
+        // 集計は空の入力からも1件を返すので、結果が空になることは集計する前のプランで表す
+        if aggregation {
+            plan = EmptyPlan::wrap(plan, unsatisfiable, empty_table.clone());
+            plan = Arc::new(Mutex::new(GroupByPlan::new(
+                plan,
+                data.group_by.clone(),
+                data.aggregates.clone(),
+                tx.clone(),
+            )?)) as ArcPlan;
+        }
+        // ソートキーは結果に出さないフィールドも参照できるので、射影する前に並べ替える
+        if !data.order_by.is_empty() {
+            plan = Arc::new(Mutex::new(SortPlan::new(
+                plan,
+                data.order_by.clone(),
+                tx.clone(),
+            )?)) as ArcPlan;
+        }
+        let project = ProjectPlan::with_expressions(plan, data.fields.clone(), &data.expressions)?;
+        plan = Arc::new(Mutex::new(project)) as ArcPlan;
+        if !aggregation {
+            plan = EmptyPlan::wrap(plan, unsatisfiable, empty_table);
+        }
 
         Ok(plan)
     }
@@ -1,83 +1,383 @@
 use crate::{
-    metadata::metadata_manager::MetadataManager,
+    index::{build::IndexBuilder, Index},
+    metadata::{catalog::CATALOG_TABLES, metadata_manager::MetadataManager},
     plan::{select_plan::SelectPlan, table_plan::TablePlan, Plan},
     query::{
-        create_index_data::CreateIndexData, create_table_data::CreateTableData,
-        create_view_data::CreateViewData, delete_data::DeleteData, insert_data::InsertData,
-        modify_data::ModifyData,
+        cluster_data::ClusterData, constant::Constant, create_index_data::CreateIndexData,
+        create_table_data::CreateTableData, create_view_data::CreateViewData,
+        delete_data::DeleteData, grant_data::GrantData, insert_data::InsertData,
+        modify_data::ModifyData, scan::Scan as _,
+    },
+    record::{
+        cluster::{in_order, seek_block},
+        collation::Collation,
+        rid::{RID, RID_FIELD},
+        schema::{FieldTypes, Schema},
+        table_scan::TableScan,
     },
     tx::transaction::Transaction,
     unlock,
 };
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use std::sync::{Arc, Mutex};
 
-use super::{update_planner::UpdatePlanner, ArcPlan};
+use super::{deferred_index::DeferredIndexes, update_planner::UpdatePlanner, ArcPlan};
 
 pub struct BasicUpdatePlanner {
     metadata_manager: Arc<Mutex<MetadataManager>>,
+    deferred_indexes: DeferredIndexes,
 }
 
 impl BasicUpdatePlanner {
     pub fn new(metadata_manager: Arc<Mutex<MetadataManager>>) -> Self {
-        Self { metadata_manager }
+        Self {
+            metadata_manager,
+            deferred_indexes: DeferredIndexes::default(),
+        }
+    }
+
+    /// open_indexes はテーブルのすべての索引を開いて、索引のフィールド名と一緒に返す
+    /// 索引の変更を貯めるテーブルでは、索引の代わりに変更を貯める索引を返す
+    fn open_indexes(
+        &mut self,
+        table_name: &str,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Vec<(String, Box<dyn Index>)>> {
+        let mut indexes = vec![];
+        let index_infos = unlock!(self.metadata_manager).get_index_info(table_name, tx.clone())?;
+        let deferred = self.deferred_indexes.is_deferred(table_name);
+        // 仮想の索引には中身がないので、レコードの変更を反映しない
+        for (_, mut index_info) in index_infos {
+            if index_info.is_virtual() {
+                continue;
+            }
+            let index = if deferred {
+                self.deferred_indexes
+                    .open(&index_info, &tx, &self.metadata_manager)
+            } else {
+                index_info.open()?
+            };
+            indexes.push((index_info.field_name().to_string(), index));
+        }
+        Ok(indexes)
+    }
+
+    /// truncate_table はテーブルのすべてのレコードを削除する。述語のない delete で使う
+    /// レコードと索引の項目を1つずつ削除する代わりに、テーブルと索引のファイルを空にする
+    /// ファイルごとに1つのログレコードしか書かないので、大きなテーブルでも速い
+    fn truncate_table(&mut self, table_name: &str, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        let layout = Arc::new(unlock!(self.metadata_manager).get_layout(table_name, tx.clone())?);
+        let count = TableScan::new(tx.clone(), table_name, layout)?.truncate()?;
+        for (_, index) in self.open_indexes(table_name, tx)?.iter_mut() {
+            index.truncate()?;
+            index.close();
+        }
+        unlock!(self.metadata_manager).forget_stat_info(table_name);
+        Ok(count)
+    }
+
+    /// reorganize はテーブルのレコードをすべて取り出し、key 番目のフィールドの順に挿入し直す
+    fn reorganize(
+        &mut self,
+        table_name: &str,
+        plan: &TablePlan,
+        fields: &[String],
+        key: usize,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<i32> {
+        let mut indexes = self.open_indexes(table_name, tx)?;
+        let mut scan = plan.open_table_scan()?;
+        let mut records: Vec<Vec<Constant>> = vec![];
+        while scan.next()? {
+            let rid = scan.get_rid()?;
+            for (field_name, index) in indexes.iter_mut() {
+                index.delete(scan.get_value(field_name)?, rid)?;
+            }
+            let values = fields
+                .iter()
+                .map(|field_name| scan.get_value(field_name))
+                .collect::<Result<_>>()?;
+            records.push(values);
+            scan.delete()?;
+        }
+        records.sort_by(|a, b| a[key].cmp(&b[key]));
+
+        scan.before_first();
+        for values in &records {
+            scan.insert()?;
+            for (field_name, value) in fields.iter().zip(values) {
+                scan.set_value(field_name, value.clone())?;
+            }
+            let rid = scan.get_rid()?;
+            for (field_name, index) in indexes.iter_mut() {
+                index.insert(scan.get_value(field_name)?, rid)?;
+            }
+        }
+        scan.close();
+        close_indexes(indexes);
+        Ok(records.len() as i32)
+    }
+}
+
+/// target_rids は述語を満たすレコードの RID を、変更を始める前にすべて集める
+///
+/// 変更しながら同じプランのスキャンを進めると、索引や並べ替えを使うプランでは変更したレコードを
+/// もう一度読んで変更を繰り返すことがある（Halloween problem）
+/// 先に RID を集めてから変更すれば、それぞれのレコードをちょうど1回だけ変更する
+fn target_rids(mut plan: impl Plan) -> Result<Vec<RID>> {
+    let scan = plan.open()?;
+    let mut rids = vec![];
+    while unlock!(scan).next()? {
+        rids.push(unlock!(scan).get_rid()?);
+    }
+    unlock!(scan).close();
+    Ok(rids)
+}
+
+/// check_fields はフィールドがすべてテーブルにあるかを確かめる
+/// 述語のフィールドはスキャンの途中で読むので、スキャンを始める前に確かめて分かりやすいエラーにする
+fn check_fields(table_name: &str, schema: &Schema, field_names: &[String]) -> Result<()> {
+    for field_name in field_names {
+        if !schema.has_field(field_name) && field_name != RID_FIELD {
+            bail!("unknown column: {} in table {}", field_name, table_name);
+        }
+    }
+    Ok(())
+}
+
+/// check_new_name は作ろうとするテーブルやビューの名前が、カタログや既存のテーブル、ビューと重ならないかを確かめる
+/// 作ったユーザーにはすべての権限を付与するので、既存のものと同じ名前で作れると他人のテーブルの権限を得られてしまう
+fn check_new_name(md: &MetadataManager, name: &str, tx: Arc<Mutex<Transaction>>) -> Result<()> {
+    if CATALOG_TABLES.contains(&name) {
+        bail!("name is reserved for catalog: {}", name);
+    }
+    if !md.get_layout(name, tx.clone())?.schema.fields.is_empty() {
+        bail!("table already exists: {}", name);
+    }
+    if md.get_view_def(name, tx)?.is_some() {
+        bail!("view already exists: {}", name);
     }
+    Ok(())
+}
+
+fn close_indexes(indexes: Vec<(String, Box<dyn Index>)>) {
+    for (_, mut index) in indexes {
+        index.close();
+    }
+}
+
+/// set_insert_values は挿入したレコードに INSERT の値を書き込む
+/// 列の一覧にないフィールドは NULL にする
+fn set_insert_values(scan: &mut TableScan, schema: &Schema, data: &InsertData) -> Result<()> {
+    for field_name in &schema.fields {
+        if !data.fields.contains(field_name) {
+            scan.set_value(field_name, Constant::Null)?;
+        }
+    }
+    for (field, value) in data.fields.iter().zip(&data.values) {
+        scan.set_value(field, value.clone())?;
+    }
+    Ok(())
 }
 
 impl UpdatePlanner for BasicUpdatePlanner {
+    /// execute_insert はレコードを挿入して、テーブルのすべての索引にも登録する
+    ///
+    /// キーの順に並んだテーブルでは、キーが入るはずのブロックに挿入する
+    /// そのブロックに空きがない場合や、挿入した位置で前後のレコードとキーの順が崩れた場合は、
+    /// テーブルが並んでいないことを記録する
     fn execute_insert(&mut self, data: InsertData, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
-        let mut plan = TablePlan::new(data.table_name.clone(), tx, self.metadata_manager.clone())?;
-        let scan = plan.open()?;
-        let mut scan = unlock!(scan);
+        let plan = TablePlan::new(
+            data.table_name.clone(),
+            tx.clone(),
+            self.metadata_manager.clone(),
+        )?;
+        let cluster_info = unlock!(self.metadata_manager)
+            .get_cluster_info(&data.table_name, tx.clone())?
+            .filter(|info| info.sorted);
+        let mut scan = plan.open_table_scan()?;
+        if let Some(info) = &cluster_info {
+            if let Some(i) = data.fields.iter().position(|f| *f == info.field_name) {
+                let block = seek_block(&mut scan, &info.field_name, &data.values[i], true)?;
+                scan.move_to_rid(RID::new(block, -1));
+            }
+        }
         scan.insert()?;
-        for (field, value) in data.fields.into_iter().zip(data.values) {
-            scan.set_value(&field, value)?;
+        set_insert_values(&mut scan, &plan.schema(), &data)?;
+
+        let rid = scan.get_rid()?;
+        let mut indexes = self.open_indexes(&data.table_name, tx.clone())?;
+        for (field_name, index) in indexes.iter_mut() {
+            index.insert(scan.get_value(field_name)?, rid)?;
+        }
+        close_indexes(indexes);
+        if let Some(info) = cluster_info {
+            if !in_order(&mut scan, &info.field_name, rid)? {
+                unlock!(self.metadata_manager).set_sorted(&data.table_name, false, tx)?;
+            }
         }
         scan.close();
+        unlock!(self.metadata_manager).record_modifications(&data.table_name, 1);
         Ok(1)
     }
 
+    /// execute_inserts は同じテーブルに続けて挿入するレコードをまとめて書き込む
+    ///
+    /// テーブルのスキャンと索引はまとめごとに1回だけ開くので、レコードごとに開くよりピンとカタログの読み込みが少ない
+    /// キーの順に並んだテーブルはキーが入るはずのブロックを探すので、1件ずつ挿入する
+    fn execute_inserts(
+        &mut self,
+        data: Vec<InsertData>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<i32> {
+        let mut count = 0;
+        let mut data = data.into_iter().peekable();
+        while let Some(first) = data.next() {
+            let table_name = first.table_name.clone();
+            let mut batch = vec![first];
+            while let Some(next) = data.next_if(|next| next.table_name == table_name) {
+                batch.push(next);
+            }
+
+            let sorted = unlock!(self.metadata_manager)
+                .get_cluster_info(&table_name, tx.clone())?
+                .is_some_and(|info| info.sorted);
+            if sorted {
+                for data in batch {
+                    count += self.execute_insert(data, tx.clone())?;
+                }
+                continue;
+            }
+
+            let plan = TablePlan::new(
+                table_name.clone(),
+                tx.clone(),
+                self.metadata_manager.clone(),
+            )?;
+            let schema = plan.schema();
+            let mut scan = plan.open_table_scan()?;
+            let mut indexes = self.open_indexes(&table_name, tx.clone())?;
+            let inserted = batch.len() as i32;
+            for data in batch {
+                scan.insert()?;
+                set_insert_values(&mut scan, &schema, &data)?;
+                let rid = scan.get_rid()?;
+                for (field_name, index) in indexes.iter_mut() {
+                    index.insert(scan.get_value(field_name)?, rid)?;
+                }
+                count += 1;
+            }
+            close_indexes(indexes);
+            scan.close();
+            unlock!(self.metadata_manager).record_modifications(&table_name, inserted);
+        }
+        Ok(count)
+    }
+
+    /// execute_delete はレコードを削除する。索引の項目はレコードを削除する前に取り除く
     fn execute_delete(&mut self, data: DeleteData, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
         let plan = Arc::new(Mutex::new(TablePlan::new(
             data.table_name.clone(),
-            tx,
+            tx.clone(),
             self.metadata_manager.clone(),
         )?)) as ArcPlan;
-        let mut plan = SelectPlan::new(plan, data.pred.clone());
-        let scan = plan.open()?;
+        let schema = unlock!(plan).schema();
+        check_fields(&data.table_name, &schema, &data.pred.field_names())?;
+        if data.pred.is_empty() && !self.deferred_indexes.is_deferred(&data.table_name) {
+            return self.truncate_table(&data.table_name, tx);
+        }
+        let rids = target_rids(SelectPlan::new(plan.clone(), data.pred.clone()))?;
+        let mut indexes = self.open_indexes(&data.table_name, tx)?;
+        let scan = unlock!(plan).open()?;
         let mut count = 0;
-        while unlock!(scan).next()? {
-            unlock!(scan).delete()?;
+        for rid in rids {
+            let mut scan = unlock!(scan);
+            scan.move_to_rid(rid);
+            for (field_name, index) in indexes.iter_mut() {
+                index.delete(scan.get_value(field_name)?, rid)?;
+            }
+            scan.delete()?;
             count += 1;
         }
         unlock!(scan).close();
+        close_indexes(indexes);
+        unlock!(self.metadata_manager).record_modifications(&data.table_name, count);
         Ok(count)
     }
 
+    /// execute_modify はレコードの値を変更する。変更するフィールドに索引があれば、古い値の項目を新しい値に置き換える
+    /// キーの順に並んだテーブルのキーの値を変えた場合は、テーブルが並んでいないことを記録する
     fn execute_modify(&mut self, data: ModifyData, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        let cluster_info = unlock!(self.metadata_manager)
+            .get_cluster_info(&data.table_name, tx.clone())?
+            .filter(|info| info.sorted && info.field_name == data.field_name);
+        let mut key_changed = false;
         let plan = Arc::new(Mutex::new(TablePlan::new(
             data.table_name.clone(),
-            tx,
+            tx.clone(),
             self.metadata_manager.clone(),
         )?)) as ArcPlan;
-        let mut plan = SelectPlan::new(plan, data.pred.clone());
-        let scan = plan.open()?;
+        let schema = unlock!(plan).schema();
+        let mut field_names = vec![data.field_name.clone()];
+        field_names.extend(data.new_value.field_name());
+        field_names.extend(data.pred.field_names());
+        check_fields(&data.table_name, &schema, &field_names)?;
+        let rids = target_rids(SelectPlan::new(plan.clone(), data.pred.clone()))?;
+        let mut indexes = self.open_indexes(&data.table_name, tx.clone())?;
+        indexes.retain(|(field_name, _)| *field_name == data.field_name);
+        let scan = unlock!(plan).open()?;
         let mut count = 0;
-        while unlock!(scan).next()? {
+        for rid in rids {
+            unlock!(scan).move_to_rid(rid);
             let value = data.new_value.evaluate(scan.clone())?;
-            unlock!(scan).set_value(&data.field_name, value.clone())?;
+            let mut scan = unlock!(scan);
+            let old_value = scan.get_value(&data.field_name)?;
+            if cluster_info.is_some() && !key_changed {
+                key_changed = old_value != value;
+            }
+            scan.set_value(&data.field_name, value)?;
+            if !indexes.is_empty() {
+                // 長すぎる文字列は書き込むときに切り詰められることがあるので、書き込んだ値を索引に入れる
+                let new_value = scan.get_value(&data.field_name)?;
+                for (_, index) in indexes.iter_mut() {
+                    index.delete(old_value.clone(), rid)?;
+                    index.insert(new_value.clone(), rid)?;
+                }
+            }
             count += 1;
         }
         unlock!(scan).close();
+        close_indexes(indexes);
+        unlock!(self.metadata_manager).record_modifications(&data.table_name, count);
+        if key_changed {
+            unlock!(self.metadata_manager).set_sorted(&data.table_name, false, tx)?;
+        }
         Ok(count)
     }
 
+    /// execute_create_table はテーブルを作る。cluster by を指定した場合はキーのフィールドも記録する
     fn execute_create_table(
         &mut self,
         data: CreateTableData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<i32> {
-        unlock!(self.metadata_manager).create_table(&data.table_name, Arc::new(data.schema), tx)?;
+        if let Some(field_name) = &data.cluster_key {
+            match data.schema.r#type(field_name) {
+                Some(FieldTypes::Integer) | Some(FieldTypes::Varchar) => {}
+                Some(_) => bail!("cannot cluster by text or blob field: {}", field_name),
+                None => bail!("field not found: {}", field_name),
+            }
+            // キーの順に並べたテーブルの探索は値を元のまま比べるので、照合順序には従えない
+            if data.schema.collation(field_name) != Collation::Binary {
+                bail!("cannot cluster by field with collation: {}", field_name);
+            }
+        }
+        let md = unlock!(self.metadata_manager);
+        check_new_name(&md, &data.table_name, tx.clone())?;
+        md.create_table(&data.table_name, Arc::new(data.schema), tx.clone())?;
+        if let Some(field_name) = &data.cluster_key {
+            md.create_cluster_key(&data.table_name, field_name, tx)?;
+        }
         Ok(0)
     }
 
@@ -86,7 +386,9 @@ impl UpdatePlanner for BasicUpdatePlanner {
         data: CreateViewData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<i32> {
-        unlock!(self.metadata_manager).create_view(&data.view_name, &data.view_def(), tx)?;
+        let md = unlock!(self.metadata_manager);
+        check_new_name(&md, &data.view_name, tx.clone())?;
+        md.create_view(&data.view_name, &data.view_def(), tx)?;
         Ok(0)
     }
 
@@ -95,12 +397,82 @@ impl UpdatePlanner for BasicUpdatePlanner {
         data: CreateIndexData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<i32> {
-        unlock!(self.metadata_manager).create_index(
+        let md = unlock!(self.metadata_manager);
+        md.create_index(
             &data.index_name,
             &data.table_name,
             &data.field_name,
+            &data.options,
+            tx.clone(),
+        )?;
+
+        // 既存のレコードを索引に登録する
+        let layout = Arc::new(md.get_layout(&data.table_name, tx.clone())?);
+        if let Some(mut index_info) = md
+            .get_index_info(&data.table_name, tx.clone())?
+            .remove(&data.index_name)
+        {
+            let mut index = index_info.open()?;
+            IndexBuilder::new(tx, &data.table_name, layout, &data.field_name)
+                .build(index.as_mut())?;
+        }
+        Ok(0)
+    }
+
+    fn execute_grant(&mut self, data: GrantData, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        unlock!(self.metadata_manager).grant(
+            &data.grantee,
+            &data.table_name,
+            &data.privileges,
             tx,
         )?;
         Ok(0)
     }
+
+    fn execute_revoke(&mut self, data: GrantData, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        unlock!(self.metadata_manager).revoke(&data.grantee, &data.table_name, &data.privileges, tx)
+    }
+
+    fn defer_index_updates(&mut self, table_name: &str, deferred: bool) -> Result<()> {
+        if deferred {
+            self.deferred_indexes.enable(table_name);
+        } else {
+            self.deferred_indexes.disable(table_name);
+        }
+        Ok(())
+    }
+
+    fn flush_index_updates(&mut self, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        self.deferred_indexes.flush(&tx, &self.metadata_manager)
+    }
+
+    /// execute_cluster はテーブルのすべてのレコードを読み込んでキーの順に並べ、先頭のブロックから挿入し直す
+    /// レコードの位置が変わるので、索引の項目もすべて入れ替える
+    /// 並べ替えはメモリの上で行うので、メモリに載らない大きなテーブルには使えない
+    fn execute_cluster(&mut self, data: ClusterData, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        let Some(cluster_info) =
+            unlock!(self.metadata_manager).get_cluster_info(&data.table_name, tx.clone())?
+        else {
+            bail!("table has no cluster key: {}", data.table_name);
+        };
+        let plan = TablePlan::new(
+            data.table_name.clone(),
+            tx.clone(),
+            self.metadata_manager.clone(),
+        )?;
+        let fields = plan.schema().fields.clone();
+        let key = fields
+            .iter()
+            .position(|field_name| *field_name == cluster_info.field_name)
+            .ok_or_else(|| anyhow!("field not found: {}", cluster_info.field_name))?;
+
+        // すべてのレコードを読み書きするので、ほかのページを追い出さないように一括処理として扱う
+        unlock!(tx).set_bulk(true);
+        let result = self.reorganize(&data.table_name, &plan, &fields, key, tx.clone());
+        unlock!(tx).set_bulk(false);
+        let count = result?;
+
+        unlock!(self.metadata_manager).set_sorted(&data.table_name, true, tx)?;
+        Ok(count)
+    }
 }
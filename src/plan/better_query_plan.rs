@@ -1,12 +1,16 @@
 use super::{query_planner::QueryPlanner, ArcPlan, Plan};
 use crate::{
-    metadata::metadata_manager::MetadataManager,
-    parse::parser::Parser,
+    metadata::metadata_provider::MetadataProvider,
     plan::{
-        product_plan::ProductPlan, project_plan::ProjectPlan, select_plan::SelectPlan,
-        table_plan::TablePlan,
+        empty_plan::EmptyPlan, group_by_plan::GroupByPlan, index_join_plan::IndexJoinPlan,
+        index_select_plan::IndexSelectPlan, product_plan::ProductPlan, project_plan::ProjectPlan,
+        select_plan::SelectPlan, sort_plan::SortPlan, table_plan::TablePlan,
+    },
+    query::{
+        hint::Hint,
+        predicate::{NormalizedPredicate, Predicate},
+        query_data::QueryData,
     },
-    query::query_data::QueryData,
     tx::transaction::Transaction,
     unlock,
 };
@@ -14,54 +18,218 @@ use anyhow::Result;
 use std::sync::{Arc, Mutex};
 
 pub struct BetterQueryPlanner {
-    metadata_manager: Arc<Mutex<MetadataManager>>,
+    metadata_manager: Arc<Mutex<dyn MetadataProvider>>,
 }
 
 impl BetterQueryPlanner {
-    pub fn new(metadata_manager: Arc<Mutex<MetadataManager>>) -> Self {
+    pub fn new(metadata_manager: Arc<Mutex<dyn MetadataProvider>>) -> Self {
         Self { metadata_manager }
     }
+
+    /// index_join_plan は plan のレコードごとに table_name のテーブルを索引で検索して結合するプランのうち、
+    /// 最も安いものを返す
+    /// 述語がテーブルの索引のフィールドと plan のフィールドが等しいことを表していなければ None を返す
+    fn index_join_plan(
+        &self,
+        plan: &ArcPlan,
+        table_name: &str,
+        pred: &Predicate,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Option<ArcPlan>> {
+        let schema = unlock!(plan).schema();
+        let index_infos = unlock!(self.metadata_manager).get_index_info(table_name, tx.clone())?;
+        let mut index_infos: Vec<_> = index_infos.into_values().collect();
+        index_infos.sort_by(|a, b| a.index_name().cmp(b.index_name()));
+
+        let mut best: Option<IndexJoinPlan> = None;
+        for index_info in index_infos {
+            let Some(join_field) = pred
+                .equates_with_field(index_info.field_name())
+                .filter(|field_name| schema.has_field(field_name))
+            else {
+                continue;
+            };
+            let table_plan = TablePlan::new(
+                table_name.to_string(),
+                tx.clone(),
+                self.metadata_manager.clone(),
+            )?;
+            let join = IndexJoinPlan::new(
+                plan.clone(),
+                Arc::new(Mutex::new(table_plan)) as ArcPlan,
+                index_info,
+                join_field,
+            )?;
+            if best
+                .as_ref()
+                .map_or(true, |best| join.blocks_accessed() < best.blocks_accessed())
+            {
+                best = Some(join);
+            }
+        }
+        Ok(best.map(|join| Arc::new(Mutex::new(join)) as ArcPlan))
+    }
 }
 
 impl QueryPlanner for BetterQueryPlanner {
     fn create_plan(
         &mut self,
-        data: QueryData,
+        mut data: QueryData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Arc<Mutex<dyn Plan>>> {
+        // 述語がどのレコードでも満たされない場合は、テーブルを読まない空のプランにする
+        let unsatisfiable = match data.pred.normalize() {
+            NormalizedPredicate::Unsatisfiable => true,
+            NormalizedPredicate::Satisfiable(pred) => {
+                data.pred = pred;
+                false
+            }
+        };
+        let required_fields = data.required_fields();
+        let aggregation = data.is_aggregation();
         let mut plans = vec![];
+        // 結合はどれも内部結合なので、空のテーブルが1つでもあれば結果は空になる
+        let mut empty_table = None;
 
-        for table_name in data.tables {
+        let leading = Hint::leading_order(&data.hints, &data.tables)?;
+        let is_ordered = leading.is_some();
+        let tables = leading.unwrap_or(data.tables);
+        // ビューは索引で検索できないので、テーブルの名前はテーブルのプランにだけ付ける
+        for table_name in tables {
             let view_def = unlock!(self.metadata_manager).get_view_def(&table_name, tx.clone())?;
-            if let Some(view_def) = view_def {
-                let mut parser = Parser::new(&view_def);
-                let view_data = parser.query()?;
-                plans.push(self.create_plan(view_data, tx.clone())?);
+            let (plan, indexed_table) = if let Some(view_def) = view_def {
+                (
+                    self.create_view_plan(&view_def, &data.pred, tx.clone())?,
+                    None,
+                )
             } else {
-                let plan = TablePlan::new(table_name, tx.clone(), self.metadata_manager.clone())?;
-                plans.push(Arc::new(Mutex::new(plan)) as ArcPlan);
-            }
+                if empty_table.is_none()
+                    && unlock!(self.metadata_manager).is_empty(&table_name, tx.clone())?
+                {
+                    empty_table = Some(table_name.clone());
+                }
+                // 標本は索引を使わずにテーブルのブロックから選ぶので、索引で検索して結合するプランも作らない
+                if let Some(sample) = data.samples.get(&table_name) {
+                    let plan = TablePlan::new(
+                        table_name.clone(),
+                        tx.clone(),
+                        self.metadata_manager.clone(),
+                    )?;
+                    (
+                        Arc::new(Mutex::new(plan.with_sample(*sample))) as ArcPlan,
+                        None,
+                    )
+                } else {
+                    let plan = IndexSelectPlan::table_plan(
+                        table_name.clone(),
+                        &data.pred,
+                        &data.hints,
+                        tx.clone(),
+                        self.metadata_manager.clone(),
+                    )?;
+                    (plan, Some(table_name.clone()))
+                }
+            };
+            QueryData::check_qualified_fields(&data.fields, &table_name, &unlock!(plan).schema())?;
+            plans.push((plan, indexed_table));
+        }
+        for function in &data.table_functions {
+            plans.push((self.create_table_function_plan(function), None));
+        }
+
+        // 結合する場合は、それぞれのテーブルの項で先にレコードを選び、使うフィールドだけを出す
+        // 索引で検索して結合するプランはテーブルのプランを作り直すので、名前は残しておく
+        let joined = plans.len() > 1;
+        let mut pred = data.pred.clone();
+        if joined {
+            plans = plans
+                .into_iter()
+                .map(|(plan, table_name)| {
+                    let plan = SelectPlan::push_down(plan, &mut pred);
+                    Ok((ProjectPlan::prune(plan, &required_fields)?, table_name))
+                })
+                .collect::<Result<_>>()?;
         }
 
-        let mut plan = plans.remove(0);
-        for next_plan in plans {
-            let choice1 = Arc::new(Mutex::new(ProductPlan::new(
+        // 結合のコストは今使える空きバッファの数で見積もる
+        // 入力にまたがる項は直積ごとに結合の条件にして、どの入力にも当てはまらない項だけを最後に評価する
+        let buffers = unlock!(tx).available_buffers();
+        let (mut plan, _) = plans.remove(0);
+        for (next_plan, table_name) in plans {
+            // leading ヒントがあればコストを比べずに指定された順で結合する
+            if is_ordered {
+                let product = Arc::new(Mutex::new(ProductPlan::with_buffers(
+                    plan.clone(),
+                    next_plan.clone(),
+                    buffers,
+                )?)) as ArcPlan;
+                (plan, _) = SelectPlan::join(product, &plan, &next_plan, &mut pred)?;
+                continue;
+            }
+            let choice1 = Arc::new(Mutex::new(ProductPlan::with_buffers(
                 plan.clone(),
                 next_plan.clone(),
+                buffers,
             )?)) as ArcPlan;
-            let choice2 = Arc::new(Mutex::new(ProductPlan::new(
+            let choice2 = Arc::new(Mutex::new(ProductPlan::with_buffers(
                 next_plan.clone(),
                 plan.clone(),
+                buffers,
             )?)) as ArcPlan;
-            if unlock!(choice1).blocks_accessed() < unlock!(choice2).blocks_accessed() {
-                plan = choice1;
+            let product = if unlock!(choice1).blocks_accessed() < unlock!(choice2).blocks_accessed()
+            {
+                choice1
             } else {
-                plan = choice2;
+                choice2
+            };
+            let (mut choice, join_pred) = SelectPlan::join(product, &plan, &next_plan, &mut pred)?;
+            // 結合するフィールドに索引があれば、索引で検索して結合するほうが安いかを比べる
+            // 作り直したテーブルのプランにはテーブルの項がないので、結合の条件と一緒に結合の後で評価する
+            if let Some(table_name) = table_name {
+                if let Some(join) =
+                    self.index_join_plan(&plan, &table_name, &data.pred, tx.clone())?
+                {
+                    if unlock!(join).blocks_accessed() < unlock!(choice).blocks_accessed() {
+                        let schema = unlock!(next_plan).schema();
+                        let mut join_filter = data.pred.select_sub_pred(schema).unwrap_or_default();
+                        if let Some(join_pred) = &join_pred {
+                            join_filter.con_join_with(join_pred);
+                        }
+                        let join_filter = Some(join_filter).filter(|pred| !pred.is_empty());
+                        let join = SelectPlan::filter(join, join_filter);
+                        choice = ProjectPlan::prune(join, &required_fields)?;
+                    }
+                }
             }
+            plan = choice;
         }
 
-        plan = Arc::new(Mutex::new(SelectPlan::new(plan, data.pred.clone()))) as ArcPlan;
-        plan = Arc::new(Mutex::new(ProjectPlan::new(plan, data.fields.clone())?)) as ArcPlan;
+        if !joined || !pred.is_empty() {
+            plan = Arc::new(Mutex::new(SelectPlan::new(plan, pred))) as ArcPlan;
+        }
+        // 集計は空の入力からも1件を返すので、結果が空になることは集計する前のプランで表す
+        if aggregation {
+            plan = EmptyPlan::wrap(plan, unsatisfiable, empty_table.clone());
+            plan = Arc::new(Mutex::new(GroupByPlan::new(
+                plan,
+                data.group_by.clone(),
+                data.aggregates.clone(),
+                tx.clone(),
+            )?)) as ArcPlan;
+        }
+        // ソートキーは結果に出さないフィールドも参照できるので、射影する前に並べ替える
+        if !data.order_by.is_empty() {
+            plan = Arc::new(Mutex::new(SortPlan::new(
+                plan,
+                data.order_by.clone(),
+                tx.clone(),
+            )?)) as ArcPlan;
+        }
+        let project = ProjectPlan::with_expressions(plan, data.fields.clone(), &data.expressions)?;
+        plan = Arc::new(Mutex::new(project)) as ArcPlan;
+        if !aggregation {
+            plan = EmptyPlan::wrap(plan, unsatisfiable, empty_table);
+        }
 
         Ok(plan)
     }
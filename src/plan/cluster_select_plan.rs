@@ -0,0 +1,80 @@
+use super::{plan_node::PlanNode, table_plan::TablePlan, Plan};
+use crate::{
+    query::{cluster_select_scan::ClusterSelectScan, constant::Constant, scan::ArcScan},
+    record::schema::Schema,
+    unlock,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// ClusterSelectPlan はキーの順に並んだテーブルから、キーが定数と等しいレコードを読み込むプラン
+pub struct ClusterSelectPlan {
+    plan: Arc<Mutex<TablePlan>>,
+    field_name: String,
+    value: Constant,
+    pub(crate) rejected: Vec<String>,
+}
+
+impl ClusterSelectPlan {
+    pub fn new(
+        plan: Arc<Mutex<TablePlan>>,
+        field_name: impl Into<String>,
+        value: Constant,
+    ) -> Self {
+        Self {
+            plan,
+            field_name: field_name.into(),
+            value,
+            rejected: vec![],
+        }
+    }
+}
+
+unsafe impl Send for ClusterSelectPlan {}
+unsafe impl Sync for ClusterSelectPlan {}
+
+impl Plan for ClusterSelectPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        let ts = unlock!(self.plan).open_table_scan()?;
+        Ok(Arc::new(Mutex::new(ClusterSelectScan::new(
+            ts,
+            self.field_name.clone(),
+            self.value.clone(),
+        ))) as ArcScan)
+    }
+
+    /// 読み始めるブロックを二分探索で探し、そこからキーが等しいレコードのブロックだけを読む
+    fn blocks_accessed(&self) -> i32 {
+        let plan = unlock!(self.plan);
+        let blocks = plan.blocks_accessed();
+        let search = (blocks.max(1) as f64).log2().ceil() as i32;
+        search + 1 + blocks / plan.distinct_values(&self.field_name).max(1)
+    }
+
+    fn records_output(&self) -> i32 {
+        let plan = unlock!(self.plan);
+        plan.records_output() / plan.distinct_values(&self.field_name).max(1)
+    }
+
+    fn distinct_values(&self, field_name: &str) -> i32 {
+        if field_name == self.field_name {
+            1
+        } else {
+            unlock!(self.plan).distinct_values(field_name)
+        }
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        unlock!(self.plan).schema()
+    }
+
+    fn describe(&self) -> PlanNode {
+        // 見積もりが同じプランのロックを取るので、先にテーブル名を取り出しておく
+        let table_name = unlock!(self.plan).table_name().to_string();
+        PlanNode::new("ClusterSelect")
+            .with_table(table_name)
+            .with_predicate(format!("{} = {}", self.field_name, self.value.to_literal()))
+            .with_estimates(self)
+            .with_rejected(self.rejected.clone())
+    }
+}
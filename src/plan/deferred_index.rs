@@ -0,0 +1,255 @@
+use crate::{
+    index::Index,
+    metadata::{index_info::IndexInfo, metadata_manager::MetadataManager},
+    query::constant::Constant,
+    record::rid::RID,
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, Weak},
+};
+
+/// DeferredIndexes は更新の多いテーブルの索引の変更をトランザクションごとにメモリに貯め、まとめて反映する
+///
+/// 貯めた変更は索引ごとにキーの順に並べてから反映するので、索引のブロックを順に読み書きできる
+/// 同じキーの変更は貯めた順に反映するので、削除してから同じ RID に挿入した場合も正しく反映する
+/// 貯めた変更は次の場合に反映する
+///
+/// - 同じトランザクションで問い合わせやほかの文を実行する場合。問い合わせは反映した索引を使える
+/// - トランザクションがコミットする場合。コミットのログより前に索引の変更のログを書く
+///
+/// ロールバックした場合は貯めた変更を捨てる
+#[derive(Default)]
+pub struct DeferredIndexes {
+    tables: HashSet<String>,
+    pending: HashMap<i32, Weak<Mutex<Vec<IndexUpdate>>>>,
+}
+
+/// IndexUpdate は索引に反映する1件の変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexUpdate {
+    table_name: String,
+    index_name: String,
+    op: IndexOp,
+    key: Constant,
+    rid: RID,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndexOp {
+    Delete,
+    Insert,
+}
+
+impl DeferredIndexes {
+    /// enable はテーブルの索引の変更を貯めるようにする
+    pub fn enable(&mut self, table_name: &str) {
+        self.tables.insert(table_name.to_string());
+    }
+
+    /// disable はテーブルの索引の変更を貯めないようにする。貯めた変更はコミットまでに反映する
+    pub fn disable(&mut self, table_name: &str) {
+        self.tables.remove(table_name);
+    }
+
+    pub fn is_deferred(&self, table_name: &str) -> bool {
+        self.tables.contains(table_name)
+    }
+
+    /// open は索引の代わりに、トランザクションの変更を貯める索引を返す
+    /// 返した索引は検索できない
+    pub fn open(
+        &mut self,
+        index_info: &IndexInfo,
+        tx: &Arc<Mutex<Transaction>>,
+        metadata_manager: &Arc<Mutex<MetadataManager>>,
+    ) -> Box<dyn Index> {
+        Box::new(DeferredIndex {
+            table_name: index_info.table_name().to_string(),
+            index_name: index_info.index_name().to_string(),
+            updates: self.updates(tx, metadata_manager),
+        })
+    }
+
+    /// flush はトランザクションが貯めた変更をすべて反映し、反映した変更の数を返す
+    pub fn flush(
+        &mut self,
+        tx: &Arc<Mutex<Transaction>>,
+        metadata_manager: &Arc<Mutex<MetadataManager>>,
+    ) -> Result<i32> {
+        let tx_num = unlock!(tx).tx_num();
+        let Some(updates) = self.pending.get(&tx_num).and_then(Weak::upgrade) else {
+            return Ok(0);
+        };
+        let updates = std::mem::take(&mut *unlock!(updates));
+        apply(updates, metadata_manager, tx.clone())
+    }
+
+    /// updates はトランザクションの変更を貯める場所を返す
+    ///
+    /// 初めて貯める場合は、コミットの前に残りを反映するフックを登録する
+    /// 貯める場所はフックが持つので、ロールバックでフックが捨てられると一緒に捨てられる
+    fn updates(
+        &mut self,
+        tx: &Arc<Mutex<Transaction>>,
+        metadata_manager: &Arc<Mutex<MetadataManager>>,
+    ) -> Arc<Mutex<Vec<IndexUpdate>>> {
+        let tx_num = unlock!(tx).tx_num();
+        if let Some(updates) = self.pending.get(&tx_num).and_then(Weak::upgrade) {
+            return updates;
+        }
+        self.pending.retain(|_, updates| updates.strong_count() > 0);
+
+        let updates = Arc::new(Mutex::new(vec![]));
+        self.pending.insert(tx_num, Arc::downgrade(&updates));
+        let pending = PendingUpdates {
+            updates: updates.clone(),
+            metadata_manager: metadata_manager.clone(),
+        };
+        unlock!(tx).before_commit(move |tx| pending.flush(tx));
+        updates
+    }
+}
+
+/// sort_updates は変更を索引ごとにキーの順に並べる
+/// 並べ替えは安定なので、同じキーの変更は貯めた順のまま残る
+fn sort_updates(updates: &mut [IndexUpdate]) {
+    updates.sort_by(|a, b| {
+        (&a.table_name, &a.index_name, &a.key).cmp(&(&b.table_name, &b.index_name, &b.key))
+    });
+}
+
+/// apply は変更を索引ごとにキーの順に並べて反映し、反映した変更の数を返す
+fn apply(
+    mut updates: Vec<IndexUpdate>,
+    metadata_manager: &Arc<Mutex<MetadataManager>>,
+    tx: Arc<Mutex<Transaction>>,
+) -> Result<i32> {
+    sort_updates(&mut updates);
+    for group in
+        updates.chunk_by(|a, b| a.table_name == b.table_name && a.index_name == b.index_name)
+    {
+        let first = &group[0];
+        let mut index_infos =
+            unlock!(metadata_manager).get_index_info(&first.table_name, tx.clone())?;
+        let Some(mut index_info) = index_infos.remove(&first.index_name) else {
+            bail!("index not found: {}", first.index_name);
+        };
+        let mut index = index_info.open()?;
+        for update in group {
+            match update.op {
+                IndexOp::Delete => index.delete(update.key.clone(), update.rid)?,
+                IndexOp::Insert => index.insert(update.key.clone(), update.rid)?,
+            }
+        }
+        index.close();
+    }
+    Ok(updates.len() as i32)
+}
+
+/// DeferredIndex は索引への挿入と削除を、反映せずにトランザクションの変更として貯める索引
+struct DeferredIndex {
+    table_name: String,
+    index_name: String,
+    updates: Arc<Mutex<Vec<IndexUpdate>>>,
+}
+
+impl DeferredIndex {
+    fn push(&mut self, op: IndexOp, key: Constant, rid: RID) {
+        unlock!(self.updates).push(IndexUpdate {
+            table_name: self.table_name.clone(),
+            index_name: self.index_name.clone(),
+            op,
+            key,
+            rid,
+        });
+    }
+}
+
+impl Index for DeferredIndex {
+    fn before_first(&mut self, _search_key: Constant) -> Result<()> {
+        bail!("cannot search deferred index: {}", self.index_name)
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        bail!("cannot search deferred index: {}", self.index_name)
+    }
+
+    fn get_data_rid(&mut self) -> Result<RID> {
+        bail!("cannot search deferred index: {}", self.index_name)
+    }
+
+    fn delete(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        self.push(IndexOp::Delete, data_value, data_rid);
+        Ok(())
+    }
+
+    fn insert(&mut self, data_value: Constant, data_rid: RID) -> Result<()> {
+        self.push(IndexOp::Insert, data_value, data_rid);
+        Ok(())
+    }
+
+    fn close(&mut self) {}
+
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>> {
+        bail!("cannot search deferred index: {}", self.index_name)
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        bail!("cannot truncate deferred index: {}", self.index_name)
+    }
+}
+
+/// PendingUpdates はコミットの前に反映する、トランザクションが貯めた索引の変更
+struct PendingUpdates {
+    updates: Arc<Mutex<Vec<IndexUpdate>>>,
+    metadata_manager: Arc<Mutex<MetadataManager>>,
+}
+
+unsafe impl Send for PendingUpdates {}
+
+impl PendingUpdates {
+    fn flush(self, tx: Arc<Mutex<Transaction>>) -> Result<()> {
+        let updates = std::mem::take(&mut *unlock!(self.updates));
+        apply(updates, &self.metadata_manager, tx)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(index_name: &str, op: IndexOp, key: i32, slot: i32) -> IndexUpdate {
+        IndexUpdate {
+            table_name: "T".to_string(),
+            index_name: index_name.to_string(),
+            op,
+            key: Constant::Int(key),
+            rid: RID::new(0, slot),
+        }
+    }
+
+    #[test]
+    fn should_sort_updates_by_key_keeping_order_of_same_key() {
+        let mut updates = vec![
+            update("t_a", IndexOp::Insert, 3, 0),
+            update("t_b", IndexOp::Insert, 1, 0),
+            update("t_a", IndexOp::Delete, 1, 1),
+            update("t_a", IndexOp::Insert, 1, 1),
+        ];
+        sort_updates(&mut updates);
+        assert_eq!(
+            updates,
+            vec![
+                update("t_a", IndexOp::Delete, 1, 1),
+                update("t_a", IndexOp::Insert, 1, 1),
+                update("t_a", IndexOp::Insert, 3, 0),
+                update("t_b", IndexOp::Insert, 1, 0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use super::{plan_node::PlanNode, ArcPlan, Plan};
+use crate::{
+    query::{empty_scan::EmptyScan, scan::ArcScan},
+    record::schema::Schema,
+    unlock,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// EmptyPlan は述語がどのレコードでも満たされないか、空のテーブルを読む問い合わせのプラン
+/// スキーマは元のプランと同じだが、元のプランを開かずに空のスキャンを返す
+pub struct EmptyPlan {
+    plan: ArcPlan,
+    /// empty_table は空だったテーブル。None の場合は述語が満たされないことを表す
+    empty_table: Option<String>,
+}
+
+impl EmptyPlan {
+    pub fn new(plan: ArcPlan) -> Self {
+        Self {
+            plan,
+            empty_table: None,
+        }
+    }
+
+    /// empty_table は table_name のテーブルが空なので結果も空になるプランを返す
+    pub fn empty_table(plan: ArcPlan, table_name: impl Into<String>) -> Self {
+        Self {
+            plan,
+            empty_table: Some(table_name.into()),
+        }
+    }
+
+    /// wrap は述語が満たされないか empty_table のテーブルが空なら plan を EmptyPlan にして返し、
+    /// そうでなければ plan をそのまま返す
+    pub fn wrap(plan: ArcPlan, unsatisfiable: bool, empty_table: Option<String>) -> ArcPlan {
+        if unsatisfiable {
+            Arc::new(Mutex::new(Self::new(plan))) as ArcPlan
+        } else if let Some(table_name) = empty_table {
+            Arc::new(Mutex::new(Self::empty_table(plan, table_name))) as ArcPlan
+        } else {
+            plan
+        }
+    }
+}
+
+unsafe impl Send for EmptyPlan {}
+unsafe impl Sync for EmptyPlan {}
+
+impl Plan for EmptyPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        Ok(Arc::new(Mutex::new(EmptyScan::new(self.schema()))) as ArcScan)
+    }
+
+    fn blocks_accessed(&self) -> i32 {
+        0
+    }
+
+    fn records_output(&self) -> i32 {
+        0
+    }
+
+    fn distinct_values(&self, _field_name: &str) -> i32 {
+        0
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        unlock!(self.plan).schema()
+    }
+
+    /// 元のプランは開かないので、結果が空になる理由だけを表示する
+    fn describe(&self) -> PlanNode {
+        match &self.empty_table {
+            Some(table_name) => PlanNode::new("Empty")
+                .with_table(table_name.clone())
+                .with_note("(empty table)"),
+            None => PlanNode::new("Empty").with_note("(unsatisfiable predicate)"),
+        }
+    }
+}
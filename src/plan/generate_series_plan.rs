@@ -0,0 +1,59 @@
+use super::{plan_node::PlanNode, Plan};
+use crate::{
+    query::{generate_series_scan::GenerateSeriesScan, scan::ArcScan},
+    record::schema::Schema,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// GenerateSeriesPlan は `generate_series(<start>, <stop>)` のプラン
+/// レコードはメモリで作るので、ブロックは読まない
+pub struct GenerateSeriesPlan {
+    start: i32,
+    stop: i32,
+    schema: Arc<Schema>,
+}
+
+impl GenerateSeriesPlan {
+    pub fn new(field_name: String, start: i32, stop: i32) -> Self {
+        let mut schema = Schema::default();
+        schema.add_int_field(field_name);
+        Self {
+            start,
+            stop,
+            schema: Arc::new(schema),
+        }
+    }
+}
+
+impl Plan for GenerateSeriesPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        let field_name = self.schema.fields[0].clone();
+        Ok(Arc::new(Mutex::new(GenerateSeriesScan::new(
+            field_name, self.start, self.stop,
+        ))) as ArcScan)
+    }
+
+    fn blocks_accessed(&self) -> i32 {
+        0
+    }
+
+    fn records_output(&self) -> i32 {
+        (self.stop as i64 - self.start as i64 + 1).clamp(0, i32::MAX as i64) as i32
+    }
+
+    fn distinct_values(&self, _field_name: &str) -> i32 {
+        self.records_output()
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("GenerateSeries")
+            .with_fields(self.schema.fields.clone())
+            .with_note(format!("{} to {}", self.start, self.stop))
+            .with_estimates(self)
+    }
+}
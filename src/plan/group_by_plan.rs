@@ -0,0 +1,149 @@
+use super::{plan_node::PlanNode, sort_plan::SortPlan, ArcPlan, Plan};
+use crate::{
+    query::{
+        aggregation_function::{AggregationFunction, AggregationKind},
+        group_by_scan::GroupByScan,
+        record_comparator::SortKey,
+        scan::ArcScan,
+    },
+    record::schema::{FieldTypes, Schema},
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::sync::{Arc, Mutex};
+
+/// GroupByPlan は GROUP BY のフィールドごとにレコードをまとめて集計するプラン
+///
+/// グループのフィールドで入力を並べ替えてから、同じグループのレコードを続けて集計する
+/// distinct の集計があれば、最初の distinct のフィールドでもグループの中を並べ、そのフィールドの重複は前の値と比べて除く
+/// それ以外のフィールドの distinct はグループの値を集合に覚えて除く
+/// グループのフィールドも distinct の集計もなければ並べ替えないので、distinct を付けると並べ替える分だけコストが増える
+pub struct GroupByPlan {
+    plan: ArcPlan,
+    group_fields: Vec<String>,
+    aggregates: Vec<AggregationFunction>,
+    /// sorted_distinct はグループの中で並べた distinct のフィールド
+    sorted_distinct: Option<String>,
+    schema: Schema,
+}
+
+impl GroupByPlan {
+    pub fn new(
+        plan: ArcPlan,
+        group_fields: Vec<String>,
+        aggregates: Vec<AggregationFunction>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Self> {
+        let plan_schema = unlock!(plan).schema();
+        let mut schema = Schema::default();
+        for field in &group_fields {
+            schema.add(field.clone(), &plan_schema)?;
+        }
+        for aggregate in &aggregates {
+            let field_type = match &aggregate.field {
+                Some(field) => match plan_schema.r#type(field) {
+                    Some(field_type) => Some(field_type),
+                    None => bail!("field not found: {}", field),
+                },
+                None => None,
+            };
+            match (aggregate.kind, field_type) {
+                (AggregationKind::Count, _) => schema.add_int_field(aggregate.field_name()),
+                (AggregationKind::Sum, Some(FieldTypes::Integer)) => {
+                    schema.add_int_field(aggregate.field_name())
+                }
+                (AggregationKind::Sum, _) => bail!("cannot sum a non-int field: {}", aggregate),
+                (_, Some(field_type)) => {
+                    let field = aggregate.field.as_ref().unwrap();
+                    let length = plan_schema.length(field).unwrap_or_default();
+                    schema.add_field(aggregate.field_name(), field_type, length);
+                }
+                (_, None) => bail!("{} needs a field", aggregate.kind),
+            }
+        }
+
+        let sorted_distinct = aggregates
+            .iter()
+            .filter(|aggregate| aggregate.distinct)
+            .find_map(|aggregate| aggregate.field.clone());
+        let sort_keys: Vec<SortKey> = group_fields
+            .iter()
+            .chain(&sorted_distinct)
+            .map(|field| field.as_str().into())
+            .collect();
+        let plan = if sort_keys.is_empty() {
+            plan
+        } else {
+            Arc::new(Mutex::new(SortPlan::new(plan, sort_keys, tx)?)) as ArcPlan
+        };
+        Ok(Self {
+            plan,
+            group_fields,
+            aggregates,
+            sorted_distinct,
+            schema,
+        })
+    }
+}
+
+unsafe impl Send for GroupByPlan {}
+unsafe impl Sync for GroupByPlan {}
+
+impl Plan for GroupByPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        let scan = unlock!(self.plan).open()?;
+        let accumulators = self
+            .aggregates
+            .iter()
+            .map(|aggregate| {
+                aggregate.accumulator(
+                    aggregate.field.is_some() && aggregate.field == self.sorted_distinct,
+                )
+            })
+            .collect();
+        Ok(Arc::new(Mutex::new(GroupByScan::new(
+            scan,
+            self.group_fields.clone(),
+            accumulators,
+        ))) as ArcScan)
+    }
+
+    fn blocks_accessed(&self) -> i32 {
+        unlock!(self.plan).blocks_accessed()
+    }
+
+    /// records_output はグループの数を、グループのフィールドの値の種類の積と入力の件数の小さいほうで見積もる
+    fn records_output(&self) -> i32 {
+        if self.group_fields.is_empty() {
+            return 1;
+        }
+        let plan = unlock!(self.plan);
+        let groups = self.group_fields.iter().fold(1i32, |groups, field| {
+            groups.saturating_mul(plan.distinct_values(field).max(1))
+        });
+        groups.min(plan.records_output().max(1))
+    }
+
+    fn distinct_values(&self, field_name: &str) -> i32 {
+        if self.group_fields.iter().any(|field| field == field_name) {
+            unlock!(self.plan).distinct_values(field_name)
+        } else {
+            self.records_output()
+        }
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        Arc::new(self.schema.clone())
+    }
+
+    fn describe(&self) -> PlanNode {
+        let mut node = PlanNode::new("GroupBy")
+            .with_fields(self.schema.fields.clone())
+            .with_estimates(self);
+        if let Some(field) = &self.sorted_distinct {
+            node = node.with_note(format!("distinct sorted on {}", field));
+        }
+        node.with_child(&self.plan)
+    }
+}
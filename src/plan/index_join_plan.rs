@@ -0,0 +1,95 @@
+use super::{plan_node::PlanNode, ArcPlan, Plan};
+use crate::{
+    metadata::index_info::IndexInfo,
+    query::{index_join_scan::IndexJoinScan, index_scan::IndexScan, scan::ArcScan},
+    record::schema::Schema,
+    unlock,
+};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// IndexJoinPlan は lhs のレコードごとに、join_field の値と索引のフィールドが等しい rhs のレコードを
+/// 索引で検索して結合するプラン
+///
+/// rhs はテーブルのプランで、index_info はそのテーブルの索引
+pub struct IndexJoinPlan {
+    lhs: ArcPlan,
+    rhs: ArcPlan,
+    index_info: IndexInfo,
+    join_field: String,
+    schema: Arc<Schema>,
+}
+
+unsafe impl Send for IndexJoinPlan {}
+unsafe impl Sync for IndexJoinPlan {}
+
+impl IndexJoinPlan {
+    pub fn new(
+        lhs: ArcPlan,
+        rhs: ArcPlan,
+        index_info: IndexInfo,
+        join_field: impl Into<String>,
+    ) -> Result<Self> {
+        let mut schema = Schema::default();
+        schema.add_all(unlock!(lhs).schema())?;
+        schema.add_all(unlock!(rhs).schema())?;
+        Ok(Self {
+            lhs,
+            rhs,
+            index_info,
+            join_field: join_field.into(),
+            schema: Arc::new(schema),
+        })
+    }
+}
+
+impl Plan for IndexJoinPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        let index = self.index_info.open()?;
+        let lhs = unlock!(self.lhs).open()?;
+        let table_scan = unlock!(self.rhs).open()?;
+        let rhs = IndexScan::new(table_scan, index);
+        Ok(Arc::new(Mutex::new(IndexJoinScan::new(lhs, rhs, &self.join_field))) as ArcScan)
+    }
+
+    /// lhs のレコードごとに索引を検索し、見つかったレコードごとにテーブルのブロックを1つ読むので
+    /// B1 + R1 * (索引のブロック数 + 1キーあたりのレコード数) になる
+    fn blocks_accessed(&self) -> i32 {
+        let blocks1 = unlock!(self.lhs).blocks_accessed();
+        let records1 = unlock!(self.lhs).records_output();
+        let search = (self.index_info.blocks_accessed() as i32)
+            .saturating_add(self.index_info.records_output());
+        blocks1.saturating_add(records1.saturating_mul(search))
+    }
+
+    fn records_output(&self) -> i32 {
+        unlock!(self.lhs)
+            .records_output()
+            .saturating_mul(self.index_info.records_output())
+    }
+
+    fn distinct_values(&self, field_name: &str) -> i32 {
+        if unlock!(self.lhs).schema().has_field(field_name) {
+            unlock!(self.lhs).distinct_values(field_name)
+        } else {
+            unlock!(self.rhs).distinct_values(field_name)
+        }
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("IndexJoin")
+            .with_table(self.index_info.table_name())
+            .with_index(self.index_info.display_name())
+            .with_predicate(format!(
+                "{} = {}",
+                self.index_info.field_name(),
+                self.join_field
+            ))
+            .with_estimates(self)
+            .with_child(&self.lhs)
+    }
+}
@@ -0,0 +1,190 @@
+use super::{
+    cluster_select_plan::ClusterSelectPlan, plan_node::PlanNode, table_plan::TablePlan, ArcPlan,
+    Plan,
+};
+use crate::{
+    metadata::{index_info::IndexInfo, metadata_provider::MetadataProvider},
+    query::{
+        constant::Constant, hint::Hint, index_scan::IndexScan, predicate::Predicate, scan::ArcScan,
+    },
+    record::schema::Schema,
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{anyhow, bail, Result};
+use std::sync::{Arc, Mutex};
+
+/// IndexSelectPlan は索引のフィールドが定数と等しいレコードを索引で検索するプラン
+pub struct IndexSelectPlan {
+    plan: ArcPlan,
+    index_info: IndexInfo,
+    value: Constant,
+    hinted: bool,
+    rejected: Vec<String>,
+}
+
+impl IndexSelectPlan {
+    pub fn new(plan: ArcPlan, index_info: IndexInfo, value: Constant) -> Self {
+        Self {
+            plan,
+            index_info,
+            value,
+            hinted: false,
+            rejected: vec![],
+        }
+    }
+
+    /// table_plan はテーブルのプランを作る
+    ///
+    /// use_index ヒントでテーブルに索引が指定されている場合は、コストに関係なくその索引で検索するプランを作る
+    /// 指定された索引がない場合や、述語が索引のフィールドを定数と比較していない場合はエラーを返す
+    /// ヒントがない場合は、テーブル全体を読むプラン、述語が定数と比較しているフィールドの索引で検索するプラン、
+    /// キーの順に並んだテーブルをキーで探すプランのうち、アクセスするブロック数の見積もりが最も少ないものを選ぶ
+    /// 小さいテーブルでは索引を読む分だけアクセスが増えるので、テーブル全体を読む
+    pub fn table_plan(
+        table_name: String,
+        pred: &Predicate,
+        hints: &[Hint],
+        tx: Arc<Mutex<Transaction>>,
+        md: Arc<Mutex<dyn MetadataProvider>>,
+    ) -> Result<ArcPlan> {
+        let table_plan = TablePlan::new(table_name.clone(), tx.clone(), md.clone())?;
+        let mut index_infos = unlock!(md).get_index_info(&table_name, tx.clone())?;
+
+        if let Some(index_name) = Hint::index_for(hints, &table_name) {
+            let index_info = index_infos
+                .remove(index_name)
+                .ok_or_else(|| anyhow!("index not found on {}: {}", table_name, index_name))?;
+            let Some(value) = pred.equates_with_constant(index_info.field_name()) else {
+                bail!(
+                    "index {} cannot be used: no condition on {}",
+                    index_name,
+                    index_info.field_name()
+                );
+            };
+            let table_plan = Arc::new(Mutex::new(table_plan)) as ArcPlan;
+            let mut plan = Self::new(table_plan, index_info, value);
+            plan.hinted = true;
+            return Ok(Arc::new(Mutex::new(plan)) as ArcPlan);
+        }
+
+        // 候補のプランをコストと EXPLAIN に表示する1行と一緒に集める
+        let table_plan = Arc::new(Mutex::new(table_plan));
+        let mut paths = vec![];
+        let line = unlock!(table_plan).describe().line();
+        let cost = unlock!(table_plan).blocks_accessed();
+        paths.push((cost, line, AccessPath::Table));
+
+        let cluster_info = unlock!(md).get_cluster_info(&table_name, tx)?;
+        if let Some(cluster_info) = cluster_info.filter(|info| info.sorted) {
+            if let Some(value) = pred.equates_with_constant(&cluster_info.field_name) {
+                let plan =
+                    ClusterSelectPlan::new(table_plan.clone(), cluster_info.field_name, value);
+                paths.push((
+                    plan.blocks_accessed(),
+                    plan.describe().line(),
+                    AccessPath::Cluster(plan),
+                ));
+            }
+        }
+
+        let mut index_plans: Vec<Self> = index_infos
+            .into_values()
+            .filter_map(|index_info| {
+                let value = pred.equates_with_constant(index_info.field_name())?;
+                Some(Self::new(table_plan.clone(), index_info, value))
+            })
+            .collect();
+        index_plans.sort_by(|a, b| a.index_info.index_name().cmp(b.index_info.index_name()));
+        for plan in index_plans {
+            paths.push((
+                plan.blocks_accessed(),
+                plan.describe().line(),
+                AccessPath::Index(plan),
+            ));
+        }
+
+        // コストが同じ場合は、テーブル全体、キーの順に並んだテーブルの探索、索引の名前の順に選ぶ
+        paths.sort_by_key(|(cost, _, _)| *cost);
+        let mut paths = paths.into_iter();
+        let (_, _, chosen) = paths.next().unwrap();
+        let rejected = paths.map(|(_, line, _)| line).collect();
+        Ok(match chosen {
+            AccessPath::Table => {
+                unlock!(table_plan).set_rejected(rejected);
+                table_plan as ArcPlan
+            }
+            AccessPath::Cluster(mut plan) => {
+                plan.rejected = rejected;
+                Arc::new(Mutex::new(plan)) as ArcPlan
+            }
+            AccessPath::Index(mut plan) => {
+                plan.rejected = rejected;
+                Arc::new(Mutex::new(plan)) as ArcPlan
+            }
+        })
+    }
+}
+
+/// AccessPath はテーブルのレコードを読み込む方法の候補
+enum AccessPath {
+    Table,
+    Cluster(ClusterSelectPlan),
+    Index(IndexSelectPlan),
+}
+
+/// index_cost は索引で検索するときにアクセスするブロック数の見積もりを返す
+/// 索引のブロックに加えて、見つかったレコードごとにテーブルのブロックを1つ読む
+fn index_cost(index_info: &IndexInfo) -> i32 {
+    index_info.blocks_accessed() as i32 + index_info.records_output()
+}
+
+unsafe impl Send for IndexSelectPlan {}
+unsafe impl Sync for IndexSelectPlan {}
+
+impl Plan for IndexSelectPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        // 仮想の索引は開けないので、テーブルをピンする前に索引を開く
+        let index = self.index_info.open()?;
+        let scan = unlock!(self.plan).open()?;
+        Ok(Arc::new(Mutex::new(IndexScan::with_key(
+            scan,
+            index,
+            self.value.clone(),
+        )?)) as ArcScan)
+    }
+
+    fn blocks_accessed(&self) -> i32 {
+        index_cost(&self.index_info)
+    }
+
+    fn records_output(&self) -> i32 {
+        self.index_info.records_output()
+    }
+
+    fn distinct_values(&self, field_name: &str) -> i32 {
+        self.index_info.distinct_values(field_name)
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        unlock!(self.plan).schema()
+    }
+
+    fn describe(&self) -> PlanNode {
+        let node = PlanNode::new("IndexSelect")
+            .with_table(self.index_info.table_name())
+            .with_index(self.index_info.display_name())
+            .with_predicate(format!(
+                "{} = {}",
+                self.index_info.field_name(),
+                self.value.to_literal()
+            ))
+            .with_estimates(self)
+            .with_rejected(self.rejected.clone());
+        if self.hinted {
+            node.with_note("by use_index hint")
+        } else {
+            node
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::update_planner::UpdatePlanner;
+use crate::{
+    query::insert_data::InsertData, record::schema::Schema, tx::transaction::Transaction, unlock,
+};
+use anyhow::{bail, Result};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+/// InsertBuffer は挿入の多いテーブルに挿入するレコードをトランザクションごとにメモリに貯め、まとめて書き込む
+///
+/// まとめて書き込むとテーブルのスキャンと索引を開く回数が減り、ピンやロック、カタログの読み込みが少なくなる
+/// 貯めたレコードは次の場合に書き込む
+///
+/// - トランザクションが貯めたレコードの数がテーブルの capacity に達した場合
+/// - 同じトランザクションでほかの文を実行する場合。問い合わせは貯めたレコードも読み込める
+/// - トランザクションがコミットする場合。コミットのログより前に書き込みのログを書くので、
+///   コミットしたレコードはほかのレコードと同じように復旧できる
+///
+/// ロールバックした場合は貯めたレコードを捨てる
+///
+/// 貯めている間のレコードはメモリにだけあり、テーブルにもログにも書かれていない
+/// Planner を通す文は実行する前に書き込むので読み込めるが、普通の挿入とは次の点が違う
+///
+/// - Planner を通さずにテーブルを開いたスキャン（TableScan など）からは、同じトランザクションでも見えない
+/// - 書き込むまでログに記録しないので、書き込む前にクラッシュすると貯めたレコードは残らない
+///   まだコミットしていないレコードなので、復旧した結果はロールバックした場合と変わらない
+/// - 一意索引の違反などのエラーは挿入した文ではなく、書き込むときの文かコミットで返る
+#[derive(Default)]
+pub struct InsertBuffer {
+    tables: HashMap<String, BufferedTable>,
+    pending: HashMap<i32, Weak<Mutex<Vec<InsertData>>>>,
+}
+
+/// BufferedTable はレコードを貯めるテーブルの設定
+struct BufferedTable {
+    capacity: usize,
+    schema: Arc<Schema>,
+}
+
+impl InsertBuffer {
+    /// enable はテーブルに挿入するレコードを capacity 件まで貯めるようにする
+    pub fn enable(&mut self, table_name: &str, schema: Arc<Schema>, capacity: usize) {
+        let table = BufferedTable {
+            capacity: capacity.max(1),
+            schema,
+        };
+        self.tables.insert(table_name.to_string(), table);
+    }
+
+    /// disable はテーブルに挿入するレコードを貯めないようにする。貯めたレコードはコミットまでに書き込む
+    pub fn disable(&mut self, table_name: &str) {
+        self.tables.remove(table_name);
+    }
+
+    pub fn is_buffered(&self, table_name: &str) -> bool {
+        self.tables.contains_key(table_name)
+    }
+
+    /// add はレコードを貯める。トランザクションが貯めたテーブルのレコードが capacity に達したら書き込む
+    /// テーブルにないフィールドは、書き込むときではなくここでエラーにする
+    pub fn add(
+        &mut self,
+        data: InsertData,
+        tx: &Arc<Mutex<Transaction>>,
+        update_planner: &Arc<Mutex<dyn UpdatePlanner + Send>>,
+    ) -> Result<()> {
+        let Some(table) = self.tables.get(&data.table_name) else {
+            bail!("table is not buffered: {}", data.table_name);
+        };
+        if let Some(field_name) = data.fields.iter().find(|f| !table.schema.has_field(f)) {
+            bail!(
+                "unknown column: {} in table {}",
+                field_name,
+                data.table_name
+            );
+        }
+        let capacity = table.capacity;
+
+        let rows = self.rows(tx, update_planner);
+        let count = {
+            let mut rows = unlock!(rows);
+            rows.push(data);
+            let table_name = &rows.last().unwrap().table_name;
+            rows.iter()
+                .filter(|row| row.table_name == *table_name)
+                .count()
+        };
+        if count >= capacity {
+            self.flush(tx, update_planner)?;
+        }
+        Ok(())
+    }
+
+    /// flush はトランザクションが貯めたレコードをすべて書き込み、書き込んだレコード数を返す
+    pub fn flush(
+        &mut self,
+        tx: &Arc<Mutex<Transaction>>,
+        update_planner: &Arc<Mutex<dyn UpdatePlanner + Send>>,
+    ) -> Result<i32> {
+        let tx_num = unlock!(tx).tx_num();
+        let Some(rows) = self.pending.get(&tx_num).and_then(Weak::upgrade) else {
+            return Ok(0);
+        };
+        let rows = std::mem::take(&mut *unlock!(rows));
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        unlock!(update_planner).execute_inserts(rows, tx.clone())
+    }
+
+    /// rows はトランザクションのレコードを貯める場所を返す
+    ///
+    /// 初めて貯める場合は、コミットの前に残りを書き込むフックを登録する
+    /// 貯める場所はフックが持つので、ロールバックでフックが捨てられると一緒に捨てられる
+    fn rows(
+        &mut self,
+        tx: &Arc<Mutex<Transaction>>,
+        update_planner: &Arc<Mutex<dyn UpdatePlanner + Send>>,
+    ) -> Arc<Mutex<Vec<InsertData>>> {
+        let tx_num = unlock!(tx).tx_num();
+        if let Some(rows) = self.pending.get(&tx_num).and_then(Weak::upgrade) {
+            return rows;
+        }
+        self.pending.retain(|_, rows| rows.strong_count() > 0);
+
+        let rows = Arc::new(Mutex::new(vec![]));
+        self.pending.insert(tx_num, Arc::downgrade(&rows));
+        let pending = PendingRows {
+            rows: rows.clone(),
+            update_planner: update_planner.clone(),
+        };
+        unlock!(tx).before_commit(move |tx| pending.flush(tx));
+        rows
+    }
+}
+
+/// PendingRows はコミットの前に書き込む、トランザクションが貯めたレコード
+struct PendingRows {
+    rows: Arc<Mutex<Vec<InsertData>>>,
+    update_planner: Arc<Mutex<dyn UpdatePlanner + Send>>,
+}
+
+impl PendingRows {
+    fn flush(self, tx: Arc<Mutex<Transaction>>) -> Result<()> {
+        let rows = std::mem::take(&mut *unlock!(self.rows));
+        if !rows.is_empty() {
+            unlock!(self.update_planner).execute_inserts(rows, tx)?;
+        }
+        Ok(())
+    }
+}
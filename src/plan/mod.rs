@@ -1,24 +1,71 @@
 pub mod basic_query_plan;
 pub mod basic_update_planner;
 pub mod better_query_plan;
+pub mod cluster_select_plan;
+pub mod deferred_index;
+pub mod empty_plan;
+pub mod generate_series_plan;
+pub mod group_by_plan;
+pub mod index_join_plan;
+pub mod index_select_plan;
+pub mod insert_buffer;
+pub mod plan_node;
 pub mod planner;
 pub mod product_plan;
 pub mod project_plan;
 pub mod query_planner;
 pub mod select_plan;
+pub mod sort_plan;
 pub mod table_plan;
 pub mod update_planner;
 
-use crate::{query::scan::ArcScan, record::schema::Schema};
+use crate::{
+    query::{result_set_metadata::ResultSetMetadata, scan::ArcScan},
+    record::{layout::Layout, record_page::records_per_block, schema::Schema},
+    unlock, I32_SIZE,
+};
 use anyhow::Result;
+use plan_node::PlanNode;
 use std::sync::{Arc, Mutex};
 
 pub trait Plan {
     fn open(&mut self) -> Result<ArcScan>;
+    /// reopen は open で作ったスキャンを、もう一度先頭から読めるようにして返す
+    /// 既定ではスキャンを先頭に戻すだけなので、スキャンを作り直したりカタログを読み直したりしない
+    /// 同じ問い合わせを何度も実行する場合に使う。閉じたスキャンは渡さないこと
+    fn reopen(&mut self, scan: ArcScan) -> Result<ArcScan> {
+        unlock!(scan).before_first();
+        Ok(scan)
+    }
     fn blocks_accessed(&self) -> i32;
     fn records_output(&self) -> i32;
     fn distinct_values(&self, field_name: &str) -> i32;
     fn schema(&self) -> Arc<Schema>;
+    /// record_size は出力するレコード1件を一時テーブルに書き込むときのバイト数を見積もる
+    /// 既定ではスキーマから作ったレイアウトのスロットの大きさで、可変長の文字列は最大長で数える
+    fn record_size(&self) -> i32 {
+        Layout::try_from_schema(self.schema())
+            .map(|layout| layout.slot_size)
+            .unwrap_or(I32_SIZE as i32)
+    }
+    /// metadata は問い合わせの結果の列の情報をスキーマから作って返す
+    fn metadata(&self) -> ResultSetMetadata {
+        ResultSetMetadata::from(&*self.schema())
+    }
+    /// describe はプランの木を、EXPLAIN やテスト、可視化のツールで使うノードの木にして返す
+    fn describe(&self) -> PlanNode;
+    /// explain はプランの木を1ノード1行で返す。子のプランの行は字下げして続ける
+    fn explain(&self) -> Vec<String> {
+        self.describe().lines()
+    }
 }
 
 pub type ArcPlan = Arc<Mutex<dyn Plan>>;
+
+/// materialized_blocks はプランの出力をすべて一時テーブルに書き込んだときのブロック数を見積もる
+/// 結果を一時テーブルに書き込んでから読み直すプランが、読み書きするブロック数を見積もるのに使う
+pub fn materialized_blocks(plan: &dyn Plan, block_size: i32) -> i32 {
+    let records = plan.records_output().max(0) as i64;
+    let per_block = records_per_block(block_size, plan.record_size()) as i64;
+    ((records + per_block - 1) / per_block).min(i32::MAX as i64) as i32
+}
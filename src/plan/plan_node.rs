@@ -0,0 +1,253 @@
+use super::{ArcPlan, Plan};
+use crate::unlock;
+use std::fmt::Write as _;
+
+/// PlanNode はプランの木の1ノードを、実行に使う情報を除いて表す
+///
+/// EXPLAIN はこの木を1ノード1行の文字列にし、テストやプランを可視化するツールは to_json で JSON にする
+/// JSON のキーはいつも同じ順に並べ、値のないキーも null や空の配列で出すので、出力をそのまま比べられる
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlanNode {
+    /// node_type はプランの種類（TableScan、Select など）
+    pub node_type: String,
+    pub table: Option<String>,
+    pub index: Option<String>,
+    pub predicate: Option<String>,
+    /// fields は射影するフィールド
+    pub fields: Vec<String>,
+    pub estimates: Option<Estimates>,
+    /// note は行の最後に付け足す説明
+    pub note: Option<String>,
+    /// rejected はコストを比べて選ばなかったプランの1行の説明
+    pub rejected: Vec<String>,
+    pub children: Vec<PlanNode>,
+}
+
+/// Estimates はプランのコストの見積もり
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Estimates {
+    pub blocks: i32,
+    pub records: i32,
+}
+
+impl PlanNode {
+    pub fn new(node_type: impl Into<String>) -> Self {
+        Self {
+            node_type: node_type.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_table(mut self, table_name: impl Into<String>) -> Self {
+        self.table = Some(table_name.into());
+        self
+    }
+
+    pub fn with_index(mut self, index_name: impl Into<String>) -> Self {
+        self.index = Some(index_name.into());
+        self
+    }
+
+    pub fn with_predicate(mut self, predicate: impl Into<String>) -> Self {
+        self.predicate = Some(predicate.into());
+        self
+    }
+
+    pub fn with_fields(mut self, fields: Vec<String>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// with_estimates はプランのアクセスするブロック数と出力するレコード数の見積もりを記録する
+    pub fn with_estimates(mut self, plan: &dyn Plan) -> Self {
+        self.estimates = Some(Estimates {
+            blocks: plan.blocks_accessed(),
+            records: plan.records_output(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    pub fn with_rejected(mut self, rejected: Vec<String>) -> Self {
+        self.rejected = rejected;
+        self
+    }
+
+    pub fn with_child(mut self, plan: &ArcPlan) -> Self {
+        self.children.push(unlock!(plan).describe());
+        self
+    }
+
+    /// line はこのノードを EXPLAIN に表示する1行にする
+    pub fn line(&self) -> String {
+        let mut line = self.node_type.clone();
+        if let Some(table) = &self.table {
+            let _ = write!(line, " {}", table);
+        }
+        if let Some(index) = &self.index {
+            let _ = write!(line, " using {}", index);
+        }
+        if let Some(predicate) = &self.predicate {
+            let _ = write!(line, " where {}", predicate);
+        }
+        if !self.fields.is_empty() {
+            let _ = write!(line, " {}", self.fields.join(", "));
+        }
+        if let Some(estimates) = &self.estimates {
+            let _ = write!(
+                line,
+                " (blocks={}, records={})",
+                estimates.blocks, estimates.records
+            );
+        }
+        if let Some(note) = &self.note {
+            let _ = write!(line, " {}", note);
+        }
+        line
+    }
+
+    /// lines は木を1ノード1行にする。選ばなかったプランと子のノードの行は字下げして続ける
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![self.line()];
+        for plan in &self.rejected {
+            lines.push(format!("  rejected: {}", plan));
+        }
+        for child in &self.children {
+            lines.extend(child.lines().into_iter().map(|line| format!("  {}", line)));
+        }
+        lines
+    }
+
+    /// to_json は木を字下げした JSON にする
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json, 0);
+        json
+    }
+
+    fn write_json(&self, json: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+        json.push_str("{\n");
+        let _ = writeln!(json, "{}\"node_type\": {},", indent, quote(&self.node_type));
+        let _ = writeln!(json, "{}\"table\": {},", indent, optional(&self.table));
+        let _ = writeln!(json, "{}\"index\": {},", indent, optional(&self.index));
+        let _ = writeln!(
+            json,
+            "{}\"predicate\": {},",
+            indent,
+            optional(&self.predicate)
+        );
+        let _ = writeln!(json, "{}\"fields\": {},", indent, strings(&self.fields));
+        let estimates = match &self.estimates {
+            Some(e) => format!("{{\"blocks\": {}, \"records\": {}}}", e.blocks, e.records),
+            None => "null".to_string(),
+        };
+        let _ = writeln!(json, "{}\"estimates\": {},", indent, estimates);
+        let _ = writeln!(json, "{}\"note\": {},", indent, optional(&self.note));
+        let _ = writeln!(json, "{}\"rejected\": {},", indent, strings(&self.rejected));
+        let _ = write!(json, "{}\"children\": [", indent);
+        for (i, child) in self.children.iter().enumerate() {
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            json.push_str(&"  ".repeat(depth + 2));
+            child.write_json(json, depth + 2);
+        }
+        if !self.children.is_empty() {
+            let _ = write!(json, "\n{}", indent);
+        }
+        let _ = write!(json, "]\n{}}}", "  ".repeat(depth));
+    }
+}
+
+/// quote は文字列を JSON の文字列にする
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn optional(s: &Option<String>) -> String {
+    s.as_deref().map_or("null".to_string(), quote)
+}
+
+fn strings(strings: &[String]) -> String {
+    let quoted: Vec<String> = strings.iter().map(|s| quote(s)).collect();
+    format!("[{}]", quoted.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_lines_and_json() {
+        let scan = PlanNode {
+            estimates: Some(Estimates {
+                blocks: 2,
+                records: 10,
+            }),
+            rejected: vec!["IndexSelect T using t_a where A = 1".to_string()],
+            ..PlanNode::new("TableScan").with_table("T")
+        };
+        let node = PlanNode::new("Select")
+            .with_predicate("B = 'x\"y'")
+            .with_note("(filtered)");
+        let node = PlanNode {
+            children: vec![scan],
+            ..node
+        };
+        assert_eq!(
+            node.lines(),
+            vec![
+                "Select where B = 'x\"y' (filtered)",
+                "  TableScan T (blocks=2, records=10)",
+                "    rejected: IndexSelect T using t_a where A = 1",
+            ]
+        );
+        assert_eq!(
+            node.to_json(),
+            r#"{
+  "node_type": "Select",
+  "table": null,
+  "index": null,
+  "predicate": "B = 'x\"y'",
+  "fields": [],
+  "estimates": null,
+  "note": "(filtered)",
+  "rejected": [],
+  "children": [
+    {
+      "node_type": "TableScan",
+      "table": "T",
+      "index": null,
+      "predicate": null,
+      "fields": [],
+      "estimates": {"blocks": 2, "records": 10},
+      "note": null,
+      "rejected": ["IndexSelect T using t_a where A = 1"],
+      "children": []
+    }
+  ]
+}"#
+        );
+    }
+}
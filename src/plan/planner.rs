@@ -1,16 +1,30 @@
-use super::{query_planner::QueryPlanner, update_planner::UpdatePlanner, Plan};
+use super::{
+    insert_buffer::InsertBuffer, query_planner::QueryPlanner, update_planner::UpdatePlanner, Plan,
+};
 use crate::{
+    metadata::{catalog::CATALOG_TABLES, metadata_manager::MetadataManager},
     parse::parser::Parser,
-    query::statement::{CreateStatement, Statement},
+    query::{
+        grant_data::Privilege,
+        query_data::QueryData,
+        statement::{CreateStatement, Statement},
+    },
     tx::transaction::Transaction,
     unlock,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::sync::{Arc, Mutex};
 
+/// Planner は文を解析して、問い合わせや更新を実行する
+///
+/// トランザクションにユーザーが設定されている場合は、privcat に記録された権限を確認する
+/// ユーザーが設定されていない場合は管理者として扱い、権限を確認しない
+/// grant と revoke は管理者だけが実行でき、テーブルを作ったユーザーにはそのテーブルのすべての権限を付与する
 pub struct Planner {
     query_planner: Arc<Mutex<dyn QueryPlanner>>,
-    update_planner: Arc<Mutex<dyn UpdatePlanner>>,
+    update_planner: Arc<Mutex<dyn UpdatePlanner + Send>>,
+    metadata_manager: Arc<Mutex<MetadataManager>>,
+    insert_buffer: InsertBuffer,
 }
 
 unsafe impl Send for Planner {}
@@ -19,14 +33,67 @@ unsafe impl Sync for Planner {}
 impl Planner {
     pub fn new(
         query_planner: Arc<Mutex<dyn QueryPlanner>>,
-        update_planner: Arc<Mutex<dyn UpdatePlanner>>,
+        update_planner: Arc<Mutex<dyn UpdatePlanner + Send>>,
+        metadata_manager: Arc<Mutex<MetadataManager>>,
     ) -> Self {
         Self {
             query_planner,
             update_planner,
+            metadata_manager,
+            insert_buffer: InsertBuffer::default(),
         }
     }
 
+    /// buffer_inserts はテーブルに挿入するレコードをトランザクションごとに capacity 件まで貯めて、まとめて書き込むようにする
+    /// 挿入の多いテーブルで使う。None を渡すと貯めずに1件ずつ書き込むように戻す
+    pub fn buffer_inserts(
+        &mut self,
+        table_name: &str,
+        capacity: Option<usize>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        let Some(capacity) = capacity else {
+            self.insert_buffer.disable(table_name);
+            return Ok(());
+        };
+        let layout = unlock!(self.metadata_manager).get_layout(table_name, tx)?;
+        if layout.schema.fields.is_empty() {
+            bail!("table not found: {}", table_name);
+        }
+        self.insert_buffer
+            .enable(table_name, layout.schema.clone(), capacity);
+        Ok(())
+    }
+
+    /// flush_inserts はトランザクションが貯めたレコードを書き込み、書き込んだレコード数を返す
+    pub fn flush_inserts(&mut self, tx: &Arc<Mutex<Transaction>>) -> Result<i32> {
+        self.insert_buffer.flush(tx, &self.update_planner)
+    }
+
+    /// defer_index_updates はテーブルの索引の変更をトランザクションごとに貯めて、コミットの前にキーの順にまとめて反映するようにする
+    /// 更新の多いテーブルで使う。false を渡すと、このトランザクションが貯めた変更を反映して1件ずつ反映するように戻す
+    pub fn defer_index_updates(
+        &mut self,
+        table_name: &str,
+        deferred: bool,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        if deferred {
+            let layout = unlock!(self.metadata_manager).get_layout(table_name, tx)?;
+            if layout.schema.fields.is_empty() {
+                bail!("table not found: {}", table_name);
+            }
+        } else {
+            self.flush_index_updates(&tx)?;
+        }
+        unlock!(self.update_planner).defer_index_updates(table_name, deferred)
+    }
+
+    /// flush_index_updates はトランザクションが貯めた索引の変更を反映し、反映した変更の数を返す
+    pub fn flush_index_updates(&mut self, tx: &Arc<Mutex<Transaction>>) -> Result<i32> {
+        unlock!(self.update_planner).flush_index_updates(tx.clone())
+    }
+
     pub fn create_query_plan(
         &mut self,
         query: &str,
@@ -34,27 +101,147 @@ impl Planner {
     ) -> Result<Arc<Mutex<dyn Plan>>> {
         let mut parser = Parser::new(query);
         let query_data = parser.query()?;
+        self.create_plan(query_data, tx)
+    }
+
+    /// create_plan は解析済みの問い合わせからプランを作る
+    pub fn create_plan(
+        &mut self,
+        query_data: QueryData,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Arc<Mutex<dyn Plan>>> {
+        // 過去の時点のデータベースは Session が別に作って問い合わせる
+        if query_data.as_of.is_some() {
+            bail!("as of queries are only supported by sessions");
+        }
+        for table_name in &query_data.tables {
+            self.check_privilege(table_name, Privilege::Select, &tx)?;
+        }
+        // 貯めたレコードも読み込めるように、プランを作る前に書き込む
+        // 問い合わせは索引を使うことがあるので、貯めた索引の変更も反映する
+        self.flush_inserts(&tx)?;
+        self.flush_index_updates(&tx)?;
         unlock!(self.query_planner).create_plan(query_data, tx)
     }
 
     pub fn execute_update(&mut self, query: &str, tx: Arc<Mutex<Transaction>>) -> Result<i32> {
         let mut parser = Parser::new(query);
         let update_data = parser.update_cmd()?;
+        // 貯めたレコードをほかの文より後に書き込まないように、貯めない文の前に書き込む
+        let buffered = matches!(
+            &update_data,
+            Statement::Insert(data) if self.insert_buffer.is_buffered(&data.table_name)
+        );
+        if !buffered {
+            self.flush_inserts(&tx)?;
+        }
+        // 索引の変更を貯めるのはレコードを変更する文だけなので、ほかの文の前に反映する
+        if !matches!(
+            &update_data,
+            Statement::Insert(_) | Statement::Delete(_) | Statement::Update(_)
+        ) {
+            self.flush_index_updates(&tx)?;
+        }
         match update_data {
-            Statement::Insert(data) => unlock!(self.update_planner).execute_insert(data, tx),
-            Statement::Delete(data) => unlock!(self.update_planner).execute_delete(data, tx),
-            Statement::Update(data) => unlock!(self.update_planner).execute_modify(data, tx),
+            Statement::Insert(data) => {
+                self.check_privilege(&data.table_name, Privilege::Insert, &tx)?;
+                if buffered {
+                    self.insert_buffer.add(data, &tx, &self.update_planner)?;
+                    return Ok(1);
+                }
+                unlock!(self.update_planner).execute_insert(data, tx)
+            }
+            Statement::Delete(data) => {
+                self.check_privilege(&data.table_name, Privilege::Delete, &tx)?;
+                unlock!(self.update_planner).execute_delete(data, tx)
+            }
+            Statement::Update(data) => {
+                self.check_privilege(&data.table_name, Privilege::Update, &tx)?;
+                unlock!(self.update_planner).execute_modify(data, tx)
+            }
             Statement::Create(create) => match create {
                 CreateStatement::CreateTable(data) => {
-                    unlock!(self.update_planner).execute_create_table(data, tx)
+                    let table_name = data.table_name.clone();
+                    let count =
+                        unlock!(self.update_planner).execute_create_table(data, tx.clone())?;
+                    self.grant_to_owner(&table_name, &tx)?;
+                    Ok(count)
                 }
                 CreateStatement::CreateView(data) => {
-                    unlock!(self.update_planner).execute_create_view(data, tx)
+                    for table_name in &data.query.tables {
+                        self.check_privilege(table_name, Privilege::Select, &tx)?;
+                    }
+                    let view_name = data.view_name.clone();
+                    let count =
+                        unlock!(self.update_planner).execute_create_view(*data, tx.clone())?;
+                    self.grant_to_owner(&view_name, &tx)?;
+                    Ok(count)
                 }
                 CreateStatement::CreateIndex(data) => {
+                    self.check_privilege(&data.table_name, Privilege::Select, &tx)?;
                     unlock!(self.update_planner).execute_create_index(data, tx)
                 }
             },
+            Statement::Grant(data) => {
+                self.check_admin(&tx)?;
+                unlock!(self.update_planner).execute_grant(data, tx)
+            }
+            Statement::Revoke(data) => {
+                self.check_admin(&tx)?;
+                unlock!(self.update_planner).execute_revoke(data, tx)
+            }
+            Statement::Cluster(data) => {
+                self.check_privilege(&data.table_name, Privilege::Update, &tx)?;
+                unlock!(self.update_planner).execute_cluster(data, tx)
+            }
         }
     }
+
+    /// check_privilege はトランザクションのユーザーがテーブルに対する権限を持っているかを確認する
+    fn check_privilege(
+        &self,
+        table_name: &str,
+        privilege: Privilege,
+        tx: &Arc<Mutex<Transaction>>,
+    ) -> Result<()> {
+        let Some(user) = unlock!(tx).user() else {
+            return Ok(());
+        };
+        if privilege == Privilege::Select && CATALOG_TABLES.contains(&table_name) {
+            return Ok(());
+        }
+        if !unlock!(self.metadata_manager).has_privilege(
+            &user,
+            table_name,
+            privilege,
+            tx.clone(),
+        )? {
+            bail!(
+                "permission denied: {} has no {} privilege on {}",
+                user,
+                privilege,
+                table_name
+            );
+        }
+        Ok(())
+    }
+
+    fn check_admin(&self, tx: &Arc<Mutex<Transaction>>) -> Result<()> {
+        if let Some(user) = unlock!(tx).user() {
+            bail!(
+                "permission denied: {} cannot grant or revoke privileges",
+                user
+            );
+        }
+        Ok(())
+    }
+
+    /// grant_to_owner はテーブルやビューを作ったユーザーにすべての権限を付与する
+    /// 既存の名前では作れないので、作るのに成功した文からだけ呼ぶこと
+    fn grant_to_owner(&self, table_name: &str, tx: &Arc<Mutex<Transaction>>) -> Result<()> {
+        let Some(user) = unlock!(tx).user() else {
+            return Ok(());
+        };
+        unlock!(self.metadata_manager).grant(&user, table_name, &Privilege::ALL, tx.clone())
+    }
 }
@@ -1,4 +1,4 @@
-use super::{ArcPlan, Plan};
+use super::{plan_node::PlanNode, ArcPlan, Plan};
 use crate::{
     query::{product_scan::ProductScan, scan::ArcScan},
     record::schema::Schema,
@@ -11,6 +11,7 @@ pub struct ProductPlan {
     plan1: Arc<Mutex<dyn Plan>>,
     plan2: Arc<Mutex<dyn Plan>>,
     schema: Arc<Schema>,
+    buffers: Option<u64>,
 }
 
 unsafe impl Send for ProductPlan {}
@@ -26,8 +27,16 @@ impl ProductPlan {
             plan1,
             plan2,
             schema: Arc::new(schema),
+            buffers: None,
         })
     }
+
+    /// with_buffers は結合に使える空きバッファの数をコストの見積もりに含めたプランを返す
+    pub fn with_buffers(plan1: ArcPlan, plan2: ArcPlan, buffers: u64) -> Result<Self> {
+        let mut plan = Self::new(plan1, plan2)?;
+        plan.buffers = Some(buffers);
+        Ok(plan)
+    }
 }
 
 impl Plan for ProductPlan {
@@ -37,9 +46,21 @@ impl Plan for ProductPlan {
         Ok(Arc::new(Mutex::new(ProductScan::new(s1, s2))) as ArcScan)
     }
 
+    /// plan1 のレコードごとに plan2 を読み直すので、基本のコストは B1 + R1 * B2 になる
+    ///
+    /// 空きバッファの数がわかっている場合は、読み直す plan2 のブロックがバッファに残るかを考える
+    /// plan1 の読み込みに1つバッファを使うので、plan2 に使えるのは残りのバッファになる
+    /// plan2 がすべて収まれば2回目以降はバッファから読めるので B1 + B2、
+    /// 収まらなければ読み直すたびにブロックが入れ替わるので B1 + R1 * B2 のままになる
     fn blocks_accessed(&self) -> i32 {
-        unlock!(self.plan1).blocks_accessed()
-            + unlock!(self.plan1).records_output() * unlock!(self.plan2).blocks_accessed()
+        let blocks1 = unlock!(self.plan1).blocks_accessed();
+        let records1 = unlock!(self.plan1).records_output();
+        let blocks2 = unlock!(self.plan2).blocks_accessed();
+        let passes = match self.buffers {
+            Some(buffers) if (blocks2 as u64) < buffers => records1.min(1),
+            _ => records1,
+        };
+        blocks1.saturating_add(passes.saturating_mul(blocks2))
     }
 
     fn records_output(&self) -> i32 {
@@ -47,7 +68,7 @@ impl Plan for ProductPlan {
     }
 
     fn distinct_values(&self, field_name: &str) -> i32 {
-        if self.schema().has_field(field_name) {
+        if unlock!(self.plan1).schema().has_field(field_name) {
             unlock!(self.plan1).distinct_values(field_name)
         } else {
             unlock!(self.plan2).distinct_values(field_name)
@@ -57,4 +78,11 @@ impl Plan for ProductPlan {
     fn schema(&self) -> Arc<Schema> {
         self.schema.clone()
     }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("Product")
+            .with_estimates(self)
+            .with_child(&self.plan1)
+            .with_child(&self.plan2)
+    }
 }
@@ -1,24 +1,88 @@
-use super::Plan;
+use super::{plan_node::PlanNode, ArcPlan, Plan};
 use crate::{
-    query::{project_scan::ProjectScan, scan::ArcScan},
-    record::schema::Schema,
+    query::{
+        expression::Expression, project_scan::ProjectScan, query_data::split_qualified,
+        scan::ArcScan,
+    },
+    record::{
+        rid::{RID_FIELD, RID_MAX_LENGTH},
+        schema::Schema,
+    },
     unlock,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::sync::{Arc, Mutex};
 
 pub struct ProjectPlan {
     plan: Arc<Mutex<dyn Plan>>,
     schema: Schema,
+    /// expressions は値を式で求めて出すフィールドと、その式
+    expressions: Vec<(String, Expression)>,
 }
 
 impl ProjectPlan {
+    /// new は fields を並べた順に出すプランを作る。同じフィールドを何度書いてもよい
+    /// `<table>.<field>` と書いたフィールドは、書いた名前のまま plan の <field> を出す
     pub fn new(plan: Arc<Mutex<dyn Plan>>, fields: Vec<String>) -> Result<Self> {
+        Self::with_expressions(plan, fields, &[])
+    }
+
+    /// with_expressions は new と同じように fields を出すプランを作る
+    /// expressions の式を書いた文字列と同じ名前のフィールドは、plan のレコードごとに式を評価した値を出す
+    /// 値の型が決まる式は cast だけなので、ほかの式はエラーにする
+    pub fn with_expressions(
+        plan: Arc<Mutex<dyn Plan>>,
+        fields: Vec<String>,
+        expressions: &[Expression],
+    ) -> Result<Self> {
         let mut schema = Schema::default();
+        let mut computed = vec![];
+        let plan_schema = unlock!(plan).schema();
         for field in fields {
-            schema.add(field, unlock!(plan).schema())?;
+            if let Some(expression) = expressions.iter().find(|e| e.to_string() == field) {
+                let Expression::Cast(_, cast_type) = expression else {
+                    bail!("unsupported expression in select list: {}", expression);
+                };
+                let (field_type, length) = cast_type.field_type();
+                schema.add_field(field.clone(), field_type, length);
+                computed.push((field, expression.clone()));
+                continue;
+            }
+            let source = split_qualified(&field).1.to_string();
+            // rid はテーブルに存在しない仮想カラムなので、文字列型として扱う
+            if source == RID_FIELD && !plan_schema.has_field(&source) {
+                schema.add_string_field(field, RID_MAX_LENGTH);
+                continue;
+            }
+            schema.add_as(field, &source, &plan_schema)?;
+        }
+        Ok(Self {
+            plan,
+            schema,
+            expressions: computed,
+        })
+    }
+
+    /// prune は plan のフィールドのうち fields に含まれるものだけを出すプランを返す
+    /// すべてのフィールドが必要な場合は plan をそのまま返す
+    ///
+    /// 結合の前に使うと、結合したスキャンや一時テーブルが使わないフィールドを読まずに済む
+    /// rid は結合したどのスキャンからも読めるように、必要であれば残す
+    pub fn prune(plan: ArcPlan, fields: &[String]) -> Result<ArcPlan> {
+        let schema = unlock!(plan).schema();
+        let mut pruned: Vec<String> = schema
+            .fields
+            .iter()
+            .filter(|field| fields.contains(field))
+            .cloned()
+            .collect();
+        if pruned.len() == schema.fields.len() {
+            return Ok(plan);
+        }
+        if fields.iter().any(|field| field == RID_FIELD) && !pruned.iter().any(|f| f == RID_FIELD) {
+            pruned.push(RID_FIELD.to_string());
         }
-        Ok(Self { plan, schema })
+        Ok(Arc::new(Mutex::new(Self::new(plan, pruned)?)) as ArcPlan)
     }
 }
 
@@ -28,7 +92,8 @@ unsafe impl Sync for ProjectPlan {}
 impl Plan for ProjectPlan {
     fn open(&mut self) -> Result<ArcScan> {
         let s = unlock!(self.plan).open()?;
-        Ok(Arc::new(Mutex::new(ProjectScan::new(s, self.schema.fields.clone()))) as ArcScan)
+        let scan = ProjectScan::new(s, self.schema.fields.clone(), self.expressions.clone());
+        Ok(Arc::new(Mutex::new(scan)) as ArcScan)
     }
 
     fn blocks_accessed(&self) -> i32 {
@@ -40,10 +105,16 @@ impl Plan for ProjectPlan {
     }
 
     fn distinct_values(&self, field_name: &str) -> i32 {
-        unlock!(self.plan).distinct_values(field_name)
+        unlock!(self.plan).distinct_values(split_qualified(field_name).1)
     }
 
     fn schema(&self) -> Arc<Schema> {
-        unlock!(self.plan).schema()
+        Arc::new(self.schema.clone())
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("Project")
+            .with_fields(self.schema.fields.clone())
+            .with_child(&self.plan)
     }
 }
@@ -1,5 +1,10 @@
-use super::Plan;
-use crate::{query::query_data::QueryData, tx::transaction::Transaction};
+use super::{generate_series_plan::GenerateSeriesPlan, ArcPlan, Plan};
+use crate::{
+    parse::parser::Parser,
+    query::{predicate::Predicate, query_data::QueryData, table_function::TableFunction},
+    tx::transaction::Transaction,
+    unlock,
+};
 use anyhow::Result;
 use std::sync::{Arc, Mutex};
 
@@ -9,4 +14,42 @@ pub trait QueryPlanner {
         data: QueryData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<Arc<Mutex<dyn Plan>>>;
+
+    /// create_view_plan はビューの定義からプランを作る
+    ///
+    /// 外側の述語のうちビューのフィールドだけで評価できる項をビューの述語に加えてから
+    /// プランを作り直すので、ビューの中のテーブルでも索引や述語の押し下げが使われる
+    /// ビューがビューを参照している場合も create_plan を通して同じように押し下げられる
+    fn create_view_plan(
+        &mut self,
+        view_def: &str,
+        outer_pred: &Predicate,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<ArcPlan> {
+        let mut view_data = Parser::new(view_def).query()?;
+        let plan = self.create_plan(view_data.clone(), tx.clone())?;
+        let schema = unlock!(plan).schema();
+        match outer_pred.select_sub_pred(schema) {
+            Some(sub_pred) => {
+                view_data.pred.con_join_with(&sub_pred);
+                self.create_plan(view_data, tx)
+            }
+            None => Ok(plan),
+        }
+    }
+
+    /// create_table_function_plan は FROM に書いたテーブル関数のプランを作る
+    fn create_table_function_plan(&mut self, function: &TableFunction) -> ArcPlan {
+        match function {
+            TableFunction::GenerateSeries {
+                start,
+                stop,
+                field_name,
+            } => Arc::new(Mutex::new(GenerateSeriesPlan::new(
+                field_name.clone(),
+                *start,
+                *stop,
+            ))) as ArcPlan,
+        }
+    }
 }
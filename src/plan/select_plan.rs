@@ -1,4 +1,4 @@
-use super::{ArcPlan, Plan};
+use super::{plan_node::PlanNode, ArcPlan, Plan};
 use crate::{
     query::{predicate::Predicate, scan::ArcScan, select_scan::SelectScan},
     record::schema::Schema,
@@ -16,9 +16,48 @@ pub struct SelectPlan {
 }
 
 impl SelectPlan {
-    pub fn new(plan: ArcPlan, pred: Predicate) -> Self {
+    /// new は述語の項が元のプランのフィールドの照合順序で比べるようにしてプランを作る
+    pub fn new(plan: ArcPlan, mut pred: Predicate) -> Self {
+        let schema = unlock!(plan).schema();
+        pred.collate(&schema);
         Self { plan, pred }
     }
+
+    /// push_down は pred のうち plan のフィールドだけで評価できる項で plan のレコードを選ぶプランを返す
+    /// 評価するようにした項は pred から取り除く。そのような項がなければ plan をそのまま返す
+    pub fn push_down(plan: ArcPlan, pred: &mut Predicate) -> ArcPlan {
+        let schema = unlock!(plan).schema();
+        let sub_pred = pred.select_sub_pred(schema);
+        if let Some(sub_pred) = &sub_pred {
+            pred.remove(sub_pred);
+        }
+        Self::filter(plan, sub_pred)
+    }
+
+    /// join は plan1 と plan2 の直積のプラン product に、両方の入力にまたがる pred の項を結合の条件として重ねる
+    /// 結合の条件にした項は pred から取り除き、その項を返す
+    pub fn join(
+        product: ArcPlan,
+        plan1: &ArcPlan,
+        plan2: &ArcPlan,
+        pred: &mut Predicate,
+    ) -> Result<(ArcPlan, Option<Predicate>)> {
+        let schema1 = unlock!(plan1).schema();
+        let schema2 = unlock!(plan2).schema();
+        let join_pred = pred.join_sub_pred(schema1, schema2)?;
+        if let Some(join_pred) = &join_pred {
+            pred.remove(join_pred);
+        }
+        Ok((Self::filter(product, join_pred.clone()), join_pred))
+    }
+
+    /// filter は述語があれば plan のレコードを述語で選ぶプランを返し、なければ plan をそのまま返す
+    pub fn filter(plan: ArcPlan, pred: Option<Predicate>) -> ArcPlan {
+        match pred {
+            Some(pred) => Arc::new(Mutex::new(Self::new(plan, pred))) as ArcPlan,
+            None => plan,
+        }
+    }
 }
 
 unsafe impl Send for SelectPlan {}
@@ -35,7 +74,9 @@ impl Plan for SelectPlan {
     }
 
     fn records_output(&self) -> i32 {
-        unlock!(self.plan).records_output() / self.pred.reduction_factor(self.plan.clone())
+        // reduction_factor が同じプランのロックを取るので、先にロックを外しておく
+        let records = unlock!(self.plan).records_output();
+        records / self.pred.reduction_factor(self.plan.clone())
     }
 
     fn distinct_values(&self, field_name: &str) -> i32 {
@@ -54,4 +95,12 @@ impl Plan for SelectPlan {
     fn schema(&self) -> Arc<Schema> {
         unlock!(self.plan).schema()
     }
+
+    fn describe(&self) -> PlanNode {
+        let mut node = PlanNode::new("Select");
+        if !self.pred.is_empty() {
+            node = node.with_predicate(self.pred.to_string());
+        }
+        node.with_estimates(self).with_child(&self.plan)
+    }
 }
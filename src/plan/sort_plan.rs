@@ -0,0 +1,266 @@
+use super::{materialized_blocks, plan_node::PlanNode, ArcPlan, Plan};
+use crate::{
+    query::{
+        constant::Constant,
+        memory_sort_scan::MemorySortScan,
+        record_comparator::{RecordComparator, SortKey},
+        scan::{ArcScan, Scan},
+        sort_scan::SortScan,
+    },
+    record::{schema::Schema, temp_table::TempTable},
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::{
+    cmp::Ordering,
+    sync::{Arc, Mutex},
+};
+
+/// DEFAULT_SORT_BUFFERS は並べ替えにメモリを使ってよい量を、バッファ何個分かで表した既定値
+pub const DEFAULT_SORT_BUFFERS: i32 = 3;
+
+/// SortPlan はソートキーの順にレコードを並べ替えるプラン
+///
+/// キーには式と向きを書け、前のキーが等しいレコードだけを次のキーで並べる（RecordComparator を参照）
+/// 入力がメモリの上限に収まる場合は、一時テーブルを作らずにメモリの上で並べ替える
+/// 収まらない場合は上限に収まる分ずつ並べ替えて一時テーブル（ラン）に書き込み、2つ以下になるまで2つずつ併合する
+/// 残ったランは一時テーブルに書き込まずに、SortScan で読みながら併合する
+/// メモリの使用量はレコードの件数と Plan::record_size から見積もる
+pub struct SortPlan {
+    plan: ArcPlan,
+    tx: Arc<Mutex<Transaction>>,
+    comparator: RecordComparator,
+    memory_budget: i32,
+    runs: usize,
+}
+
+impl SortPlan {
+    pub fn new(
+        plan: ArcPlan,
+        sort_keys: Vec<SortKey>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<Self> {
+        let comparator = RecordComparator::new(sort_keys);
+        let schema = unlock!(plan).schema();
+        if let Some(field) = comparator.missing_field(schema) {
+            bail!("field not found: {}", field);
+        }
+        let memory_budget = DEFAULT_SORT_BUFFERS * unlock!(tx).block_size();
+        Ok(Self {
+            plan,
+            tx,
+            comparator,
+            memory_budget,
+            runs: 0,
+        })
+    }
+
+    /// with_memory_budget は並べ替えにメモリを使ってよいバイト数を設定する
+    pub fn with_memory_budget(mut self, bytes: i32) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// runs は最後に open したときに一時テーブルに書き込んだランの数を返す。メモリの上で並べ替えた場合は 0
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    /// capacity はメモリの上限に収まるレコードの件数を返す。少なくとも1件は載せる
+    fn capacity(&self) -> usize {
+        let record_size = unlock!(self.plan).record_size().max(1);
+        (self.memory_budget / record_size).max(1) as usize
+    }
+
+    /// fits_in_memory は見積もったレコードの件数がメモリの上限に収まるかを返す
+    fn fits_in_memory(&self) -> bool {
+        let records = unlock!(self.plan).records_output().max(0) as usize;
+        records <= self.capacity()
+    }
+
+    fn compare(&self, a: &SortRow, b: &SortRow) -> Ordering {
+        self.comparator.compare_values(&a.keys, &b.keys)
+    }
+
+    /// write_run は rows を並べ替えて一時テーブルに書き込む
+    fn write_run(&self, fields: &[String], mut rows: Vec<SortRow>) -> Result<TempTable> {
+        rows.sort_by(|a, b| self.compare(a, b));
+        let run = TempTable::for_transaction(self.tx.clone(), self.schema())?;
+        let mut scan = run.open(self.tx.clone())?;
+        for row in rows {
+            scan.insert()?;
+            for (field, value) in fields.iter().zip(row.values) {
+                scan.set_value(field, value)?;
+            }
+        }
+        scan.close();
+        Ok(run)
+    }
+
+    /// read_runs は src のレコードを capacity 件ずつ読み、上限を超えた分をランに書き込む
+    /// 書き込んだランと、最後に読んだまだ書き込んでいないレコードを返す
+    fn read_runs(
+        &self,
+        src: &ArcScan,
+        fields: &[String],
+        capacity: usize,
+    ) -> Result<(Vec<TempTable>, Vec<SortRow>)> {
+        let mut runs = vec![];
+        let mut rows = vec![];
+        loop {
+            let row = self.read_next(src, fields)?;
+            let Some(row) = row else {
+                break;
+            };
+            if rows.len() == capacity {
+                runs.push(self.write_run(fields, std::mem::take(&mut rows))?);
+            }
+            rows.push(row);
+        }
+        Ok((runs, rows))
+    }
+
+    /// merge_runs は2つのランを併合した新しいランを返し、元のランは削除する
+    fn merge_runs(&self, fields: &[String], run1: TempTable, run2: TempTable) -> Result<TempTable> {
+        let merged = TempTable::for_transaction(self.tx.clone(), self.schema())?;
+        let mut dest = merged.open(self.tx.clone())?;
+        let src1 = Arc::new(Mutex::new(run1.open(self.tx.clone())?)) as ArcScan;
+        let src2 = Arc::new(Mutex::new(run2.open(self.tx.clone())?)) as ArcScan;
+        let mut row1 = self.read_next(&src1, fields)?;
+        let mut row2 = self.read_next(&src2, fields)?;
+        loop {
+            let take_first = match (&row1, &row2) {
+                (Some(a), Some(b)) => self.compare(a, b).is_le(),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let (row, src) = if take_first {
+                (row1.take(), &src1)
+            } else {
+                (row2.take(), &src2)
+            };
+            dest.insert()?;
+            for (field, value) in fields.iter().zip(row.unwrap().values) {
+                dest.set_value(field, value)?;
+            }
+            let next = self.read_next(src, fields)?;
+            if take_first {
+                row1 = next;
+            } else {
+                row2 = next;
+            }
+        }
+        unlock!(src1).close();
+        unlock!(src2).close();
+        dest.close();
+        run1.drop(self.tx.clone())?;
+        run2.drop(self.tx.clone())?;
+        Ok(merged)
+    }
+
+    /// read_next はスキャンを次のレコードに進めてキーと fields の値を返す。レコードがなければ None を返す
+    /// キーの式を評価するときにスキャンをロックするので、ロックは値を1つ読むごとに外す
+    fn read_next(&self, scan: &ArcScan, fields: &[String]) -> Result<Option<SortRow>> {
+        let has_next = unlock!(scan).next()?;
+        if !has_next {
+            return Ok(None);
+        }
+        let values = fields
+            .iter()
+            .map(|field| unlock!(scan).get_value(field))
+            .collect::<Result<_>>()?;
+        let keys = self.comparator.key_values(scan)?;
+        Ok(Some(SortRow { keys, values }))
+    }
+}
+
+/// SortRow は並べ替えるレコード。keys はソートキーを評価した値で、values は fields の順の値
+struct SortRow {
+    keys: Vec<Constant>,
+    values: Vec<Constant>,
+}
+
+unsafe impl Send for SortPlan {}
+unsafe impl Sync for SortPlan {}
+
+impl Plan for SortPlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        let fields = self.schema().fields.clone();
+        let capacity = self.capacity();
+        let src = unlock!(self.plan).open()?;
+        let result = self.read_runs(&src, &fields, capacity);
+        unlock!(src).close();
+        let (mut runs, mut rows) = result?;
+
+        if runs.is_empty() {
+            self.runs = 0;
+            rows.sort_by(|a, b| self.compare(a, b));
+            let rows = rows.into_iter().map(|row| row.values).collect();
+            return Ok(Arc::new(Mutex::new(MemorySortScan::new(fields, rows))) as ArcScan);
+        }
+        runs.push(self.write_run(&fields, rows)?);
+        self.runs = runs.len();
+        while runs.len() > 2 {
+            let mut merged = vec![];
+            let mut iter = runs.into_iter();
+            while let Some(run1) = iter.next() {
+                match iter.next() {
+                    Some(run2) => merged.push(self.merge_runs(&fields, run1, run2)?),
+                    None => merged.push(run1),
+                }
+            }
+            runs = merged;
+        }
+        let scans = runs
+            .iter()
+            .map(|run| Ok(Arc::new(Mutex::new(run.open(self.tx.clone())?)) as ArcScan))
+            .collect::<Result<_>>()?;
+        Ok(Arc::new(Mutex::new(SortScan::new(scans, self.comparator.clone()))) as ArcScan)
+    }
+
+    fn blocks_accessed(&self) -> i32 {
+        let blocks = unlock!(self.plan).blocks_accessed();
+        if self.fits_in_memory() {
+            return blocks;
+        }
+        let block_size = unlock!(self.tx).block_size();
+        blocks.saturating_add(materialized_blocks(&*unlock!(self.plan), block_size))
+    }
+
+    fn records_output(&self) -> i32 {
+        unlock!(self.plan).records_output()
+    }
+
+    fn distinct_values(&self, field_name: &str) -> i32 {
+        unlock!(self.plan).distinct_values(field_name)
+    }
+
+    fn schema(&self) -> Arc<Schema> {
+        unlock!(self.plan).schema()
+    }
+
+    fn record_size(&self) -> i32 {
+        unlock!(self.plan).record_size()
+    }
+
+    fn describe(&self) -> PlanNode {
+        let note = if self.fits_in_memory() {
+            "in memory"
+        } else {
+            "external"
+        };
+        PlanNode::new("Sort")
+            .with_fields(
+                self.comparator
+                    .keys()
+                    .iter()
+                    .map(SortKey::to_string)
+                    .collect(),
+            )
+            .with_note(note)
+            .with_estimates(self)
+            .with_child(&self.plan)
+    }
+}
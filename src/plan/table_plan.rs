@@ -1,8 +1,8 @@
-use super::Plan;
+use super::{plan_node::PlanNode, Plan};
 use crate::{
-    metadata::{metadata_manager::MetadataManager, stat_info::StatInfo},
+    metadata::{metadata_provider::MetadataProvider, stat_info::StatInfo},
     query::scan::ArcScan,
-    record::{layout::Layout, schema::Schema, table_scan::TableScan},
+    record::{block_sample::BlockSample, layout::Layout, schema::Schema, table_scan::TableScan},
     tx::transaction::Transaction,
     unlock,
 };
@@ -14,13 +14,15 @@ pub struct TablePlan {
     tx: Arc<Mutex<Transaction>>,
     layout: Arc<Layout>,
     stat_info: StatInfo,
+    rejected: Vec<String>,
+    sample: Option<BlockSample>,
 }
 
 impl TablePlan {
     pub fn new(
         table_name: String,
         tx: Arc<Mutex<Transaction>>,
-        md: Arc<Mutex<MetadataManager>>,
+        md: Arc<Mutex<dyn MetadataProvider>>,
     ) -> Result<Self> {
         let layout = Arc::new(unlock!(md).get_layout(&table_name, tx.clone())?);
         let stat_info = unlock!(md).get_stat_info(&table_name, layout.clone(), tx.clone())?;
@@ -29,32 +31,82 @@ impl TablePlan {
             tx,
             layout: layout.clone(),
             stat_info,
+            rejected: vec![],
+            sample: None,
         })
     }
-}
 
-impl Plan for TablePlan {
-    fn open(&mut self) -> Result<ArcScan> {
-        Ok(Arc::new(Mutex::new(TableScan::new(
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// set_rejected は選ばなかったプランを EXPLAIN に表示するように記録する
+    pub fn set_rejected(&mut self, rejected: Vec<String>) {
+        self.rejected = rejected;
+    }
+
+    /// with_sample は sample に含まれるブロックだけを読むプランにする
+    pub fn with_sample(mut self, sample: BlockSample) -> Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    /// open_table_scan はテーブルを読み込む TableScan を返す
+    pub fn open_table_scan(&self) -> Result<TableScan> {
+        let scan = TableScan::new(
             self.tx.clone(),
             self.table_name.clone(),
             self.layout.clone(),
-        )?)) as ArcScan)
+        )?;
+        Ok(match self.sample {
+            Some(sample) => scan.with_sample(sample),
+            None => scan,
+        })
+    }
+
+    /// sampled は標本の割合で count を減らした見積もりを返す
+    fn sampled(&self, count: i32) -> i32 {
+        match self.sample {
+            Some(sample) => (count as f64 * sample.fraction()).ceil() as i32,
+            None => count,
+        }
+    }
+}
+
+impl Plan for TablePlan {
+    fn open(&mut self) -> Result<ArcScan> {
+        Ok(Arc::new(Mutex::new(self.open_table_scan()?)) as ArcScan)
     }
 
     fn blocks_accessed(&self) -> i32 {
-        self.stat_info.num_blocks
+        self.sampled(self.stat_info.num_blocks)
     }
 
     fn records_output(&self) -> i32 {
-        self.stat_info.num_records
+        self.sampled(self.stat_info.num_records)
     }
 
     fn distinct_values(&self, field_name: &str) -> i32 {
-        self.stat_info.distinct_values(field_name)
+        let distinct_values = self.stat_info.distinct_values(field_name);
+        distinct_values.min(self.records_output().max(1))
     }
 
     fn schema(&self) -> Arc<Schema> {
         self.layout.schema.clone()
     }
+
+    fn record_size(&self) -> i32 {
+        self.layout.slot_size
+    }
+
+    fn describe(&self) -> PlanNode {
+        let node = PlanNode::new("TableScan")
+            .with_table(self.table_name.clone())
+            .with_estimates(self)
+            .with_rejected(self.rejected.clone());
+        match self.sample {
+            Some(sample) => node.with_note(sample.to_string()),
+            None => node,
+        }
+    }
 }
@@ -1,14 +1,28 @@
+use crate::query::cluster_data::ClusterData;
 use crate::query::create_index_data::CreateIndexData;
 use crate::query::create_table_data::CreateTableData;
 use crate::query::create_view_data::CreateViewData;
+use crate::query::grant_data::GrantData;
 use crate::query::modify_data::ModifyData;
 use crate::query::{delete_data::DeleteData, insert_data::InsertData};
 use crate::tx::transaction::Transaction;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::sync::{Arc, Mutex};
 
 pub trait UpdatePlanner {
     fn execute_insert(&mut self, data: InsertData, tx: Arc<Mutex<Transaction>>) -> Result<i32>;
+    /// execute_inserts は複数のレコードを順に挿入して、挿入したレコード数を返す
+    fn execute_inserts(
+        &mut self,
+        data: Vec<InsertData>,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<i32> {
+        let mut count = 0;
+        for data in data {
+            count += self.execute_insert(data, tx.clone())?;
+        }
+        Ok(count)
+    }
     fn execute_delete(&mut self, data: DeleteData, tx: Arc<Mutex<Transaction>>) -> Result<i32>;
     fn execute_modify(&mut self, data: ModifyData, tx: Arc<Mutex<Transaction>>) -> Result<i32>;
     fn execute_create_table(
@@ -26,4 +40,16 @@ pub trait UpdatePlanner {
         data: CreateIndexData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<i32>;
+    fn execute_grant(&mut self, data: GrantData, tx: Arc<Mutex<Transaction>>) -> Result<i32>;
+    fn execute_revoke(&mut self, data: GrantData, tx: Arc<Mutex<Transaction>>) -> Result<i32>;
+    /// execute_cluster はテーブルをキーの順に並べ直して、並べ直したレコード数を返す
+    fn execute_cluster(&mut self, data: ClusterData, tx: Arc<Mutex<Transaction>>) -> Result<i32>;
+    /// defer_index_updates はテーブルの索引の変更をコミットまで貯めるかどうかを切り替える
+    fn defer_index_updates(&mut self, table_name: &str, _deferred: bool) -> Result<()> {
+        bail!("deferred index updates are not supported: {}", table_name)
+    }
+    /// flush_index_updates はトランザクションが貯めた索引の変更を反映して、反映した変更の数を返す
+    fn flush_index_updates(&mut self, _tx: Arc<Mutex<Transaction>>) -> Result<i32> {
+        Ok(0)
+    }
 }
@@ -0,0 +1,194 @@
+use super::{constant::Constant, expression::Operator, scan::ArcScan};
+use crate::unlock;
+use anyhow::Result;
+use std::{collections::BTreeSet, fmt::Display};
+
+/// AggregationKind は集計関数の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationKind {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+impl AggregationKind {
+    /// from_name は関数の名前から集計関数の種類を返す。集計関数でなければ None を返す
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AggregationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AggregationKind::Count => write!(f, "count"),
+            AggregationKind::Sum => write!(f, "sum"),
+            AggregationKind::Min => write!(f, "min"),
+            AggregationKind::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// AggregationFunction は SELECT に書いた `count(*)`、`<kind>([distinct] <field>)` の集計関数
+///
+/// 結果のフィールド名は関数を書いたとおりの文字列（`count(distinct a)` など）にする
+/// NULL は集計しない。`count(*)` だけはすべてのレコードを数える
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregationFunction {
+    pub kind: AggregationKind,
+    /// field は集計するフィールド。`count(*)` の場合は None
+    pub field: Option<String>,
+    /// distinct はグループの中で重複する値を1つとして集計することを表す
+    pub distinct: bool,
+}
+
+impl AggregationFunction {
+    pub fn new(kind: AggregationKind, field: Option<String>, distinct: bool) -> Self {
+        Self {
+            kind,
+            field,
+            distinct,
+        }
+    }
+
+    pub fn field_name(&self) -> String {
+        self.to_string()
+    }
+
+    /// accumulator はグループごとに値を集計する Accumulator を返す
+    /// sorted は distinct の値がグループの中で並んで届くことを表す
+    pub fn accumulator(&self, sorted: bool) -> Accumulator {
+        Accumulator {
+            function: self.clone(),
+            sorted,
+            count: 0,
+            value: None,
+            previous: None,
+            seen: BTreeSet::new(),
+        }
+    }
+}
+
+impl Display for AggregationFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let distinct = if self.distinct { "distinct " } else { "" };
+        match &self.field {
+            Some(field) => write!(f, "{}({}{})", self.kind, distinct, field),
+            None => write!(f, "{}(*)", self.kind),
+        }
+    }
+}
+
+/// Accumulator は1つのグループのレコードを集計する
+///
+/// distinct の重複は、値がグループの中で並んで届く場合は前の値と比べるだけで除き、
+/// そうでない場合はグループの値を集合に覚えて除く
+pub struct Accumulator {
+    function: AggregationFunction,
+    sorted: bool,
+    count: i32,
+    /// value は sum、min、max のそれまでの結果
+    value: Option<Constant>,
+    previous: Option<Constant>,
+    seen: BTreeSet<Constant>,
+}
+
+impl Accumulator {
+    /// reset は次のグループを集計するために、それまでの結果を忘れる
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.value = None;
+        self.previous = None;
+        self.seen.clear();
+    }
+
+    /// accumulate はスキャンの今のレコードを集計する
+    pub fn accumulate(&mut self, scan: &ArcScan) -> Result<()> {
+        let Some(field) = &self.function.field else {
+            self.count += 1;
+            return Ok(());
+        };
+        let value = unlock!(scan).get_value(field)?;
+        if value.is_null() || (self.function.distinct && !self.first_occurrence(&value)) {
+            return Ok(());
+        }
+        self.count += 1;
+        self.value = match (self.function.kind, self.value.take()) {
+            (AggregationKind::Count, _) => None,
+            (_, None) => Some(value),
+            (AggregationKind::Sum, Some(total)) => Some(Operator::Add.apply(total, value)?),
+            (AggregationKind::Min, Some(min)) => Some(min.min(value)),
+            (AggregationKind::Max, Some(max)) => Some(max.max(value)),
+        };
+        Ok(())
+    }
+
+    /// value はグループを集計した結果を返す。集計した値がなければ count は 0、それ以外は NULL
+    pub fn value(&self) -> Constant {
+        match self.function.kind {
+            AggregationKind::Count => Constant::Int(self.count),
+            _ => self.value.clone().unwrap_or(Constant::Null),
+        }
+    }
+
+    pub fn function(&self) -> &AggregationFunction {
+        &self.function
+    }
+
+    fn first_occurrence(&mut self, value: &Constant) -> bool {
+        if !self.sorted {
+            return self.seen.insert(value.clone());
+        }
+        if self.previous.as_ref() == Some(value) {
+            return false;
+        }
+        self.previous = Some(value.clone());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::memory_sort_scan::MemorySortScan;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn should_skip_duplicates_for_distinct() -> Result<()> {
+        let rows = [3, 1, 3, 2, 1]
+            .into_iter()
+            .map(|i| vec![Constant::Int(i)])
+            .chain([vec![Constant::Null]])
+            .collect();
+        let scan = Arc::new(Mutex::new(MemorySortScan::new(vec!["a".into()], rows))) as ArcScan;
+        let a = || Some("a".to_string());
+        let functions = [
+            AggregationFunction::new(AggregationKind::Count, None, false),
+            AggregationFunction::new(AggregationKind::Count, a(), false),
+            AggregationFunction::new(AggregationKind::Count, a(), true),
+            AggregationFunction::new(AggregationKind::Sum, a(), true),
+            AggregationFunction::new(AggregationKind::Max, a(), false),
+        ];
+        let mut accumulators: Vec<_> = functions.iter().map(|f| f.accumulator(false)).collect();
+        while unlock!(scan).next()? {
+            for accumulator in accumulators.iter_mut() {
+                accumulator.accumulate(&scan)?;
+            }
+        }
+        let values: Vec<_> = accumulators.iter().map(Accumulator::value).collect();
+        assert_eq!(values, [6, 5, 3, 6, 3].map(Constant::Int).to_vec());
+        assert_eq!(functions[2].to_string(), "count(distinct a)");
+        assert_eq!(functions[0].to_string(), "count(*)");
+
+        accumulators[3].reset();
+        assert_eq!(accumulators[3].value(), Constant::Null);
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use super::{
+    predicate::{NormalizedPredicate, Predicate},
+    scan::ArcScan,
+    truth::Truth,
+};
+use crate::{plan::ArcPlan, record::schema::Schema};
+use anyhow::Result;
+use std::{fmt::Display, sync::Arc};
+
+/// BoolTerm は項の AND だけでは表せない、OR でつないだ述語か NOT を付けた述語の項
+///
+/// 述語の中の述語として持つので、`A = 1 OR (B = 2 AND NOT (C = 3))` のように入れ子にできる
+/// 値は三値論理に従い、NULL を含む比較があると Unknown になることがある
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoolTerm {
+    /// Or はいずれかの述語が True なら True、すべて False なら False になる
+    Or(Vec<Predicate>),
+    /// Not は述語の True と False を入れ替える。Unknown は Unknown のまま
+    Not(Box<Predicate>),
+}
+
+impl BoolTerm {
+    /// collate は項の中の述語がスキーマにあるフィールドの照合順序で比べるように設定する
+    pub fn collate(&mut self, schema: &Schema) {
+        match self {
+            BoolTerm::Or(preds) => preds.iter_mut().for_each(|pred| pred.collate(schema)),
+            BoolTerm::Not(pred) => pred.collate(schema),
+        }
+    }
+
+    /// evaluate は項を三値論理で評価する。OR は True になる述語が見つかればそれ以降の述語は評価しない
+    pub fn evaluate(&self, scan: ArcScan) -> Result<Truth> {
+        match self {
+            BoolTerm::Or(preds) => {
+                let mut result = Truth::False;
+                for pred in preds {
+                    result = result.or(pred.evaluate(scan.clone())?);
+                    if result == Truth::True {
+                        break;
+                    }
+                }
+                Ok(result)
+            }
+            BoolTerm::Not(pred) => Ok(!pred.evaluate(scan)?),
+        }
+    }
+
+    /// reduction_factor は OR ではそれぞれの述語で選ぶレコードの割合を足して見積もる
+    /// NOT はほとんどのレコードを選ぶので 1 を返す
+    pub fn reduction_factor(&self, plan: ArcPlan) -> i32 {
+        match self {
+            BoolTerm::Or(preds) => {
+                let selectivity: f64 = preds
+                    .iter()
+                    .map(|pred| 1.0 / pred.reduction_factor(plan.clone()) as f64)
+                    .sum();
+                ((1.0 / selectivity.min(1.0)) as i32).max(1)
+            }
+            BoolTerm::Not(_) => 1,
+        }
+    }
+
+    /// normalize は OR から True になりえない述語を取り除く
+    ///
+    /// - 述語が残らなければ Unsatisfiable を返す
+    /// - 常に真になる述語があれば、項を取り除いた空の述語を返す
+    /// - 述語が1つだけ残れば、その述語を返す
+    ///
+    /// NOT の中で取り除くと Unknown が False に変わって結果が変わるので、NOT の項はそのまま残す
+    pub fn normalize(&self) -> NormalizedPredicate {
+        let BoolTerm::Or(preds) = self else {
+            return NormalizedPredicate::Satisfiable(Predicate::from_bool_term(self.clone()));
+        };
+        let mut satisfiable: Vec<Predicate> = vec![];
+        for pred in preds {
+            if let NormalizedPredicate::Satisfiable(pred) = pred.normalize() {
+                if pred.is_empty() {
+                    return NormalizedPredicate::Satisfiable(Predicate::default());
+                }
+                if !satisfiable.contains(&pred) {
+                    satisfiable.push(pred);
+                }
+            }
+        }
+        match satisfiable.len() {
+            0 => NormalizedPredicate::Unsatisfiable,
+            1 => NormalizedPredicate::Satisfiable(satisfiable.remove(0)),
+            _ => NormalizedPredicate::Satisfiable(Predicate::or(satisfiable)),
+        }
+    }
+
+    /// field_names は項の中の述語が参照するフィールドの名前を返す
+    pub fn field_names(&self) -> Vec<String> {
+        match self {
+            BoolTerm::Or(preds) => preds.iter().flat_map(Predicate::field_names).collect(),
+            BoolTerm::Not(pred) => pred.field_names(),
+        }
+    }
+
+    pub fn applies_to(&self, schema: Arc<Schema>) -> bool {
+        self.field_names()
+            .iter()
+            .all(|field_name| schema.has_field(field_name))
+    }
+}
+
+impl Display for BoolTerm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoolTerm::Or(preds) => {
+                let preds: Vec<String> = preds.iter().map(|pred| pred.to_string()).collect();
+                write!(f, "({})", preds.join(" OR "))
+            }
+            BoolTerm::Not(pred) => write!(f, "NOT ({})", pred),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use super::constant::Constant;
+use crate::record::schema::FieldTypes;
+use anyhow::{bail, Result};
+use std::fmt::Display;
+
+/// CastType は `cast(<expr> as <type>)` で変換する先の型
+///
+/// 値は次の規則で変換する。NULL はどの型にも NULL のまま変換する
+/// - int: 文字列は前後の空白を除いて整数として読む。真偽値は true を 1、false を 0 にする
+/// - varchar(n) と text: 値を文字列として書く。varchar は n 文字を超える分を切り詰める
+/// - boolean: 整数は 0 を false、それ以外を true にする。文字列は true と false だけを読む
+/// - date: 文字列を YYYY-MM-DD の形式の日付として読む
+///
+/// 読めない文字列や、規則にない組み合わせはエラーになる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastType {
+    Int,
+    Varchar(i32),
+    Text,
+    Boolean,
+    Date,
+}
+
+impl CastType {
+    /// field_type は変換した値を結果に出すときのフィールドの型と長さを返す
+    pub fn field_type(&self) -> (FieldTypes, i32) {
+        match self {
+            CastType::Int => (FieldTypes::Integer, 0),
+            CastType::Varchar(length) => (FieldTypes::Varchar, *length),
+            CastType::Text => (FieldTypes::Text, 0),
+            CastType::Boolean => (FieldTypes::Boolean, 0),
+            CastType::Date => (FieldTypes::Date, 0),
+        }
+    }
+
+    pub fn apply(&self, value: Constant) -> Result<Constant> {
+        let converted = match (self, value) {
+            (_, Constant::Null) => Constant::Null,
+            (CastType::Int, Constant::Int(i)) => Constant::Int(i),
+            (CastType::Int, Constant::Bool(b)) => Constant::Int(b as i32),
+            (CastType::Int, Constant::String(s)) => match s.trim().parse() {
+                Ok(i) => Constant::Int(i),
+                Err(_) => bail!("cannot cast '{}' to int", s),
+            },
+            (CastType::Varchar(length), value) => {
+                Constant::String(value.to_string().chars().take(*length as usize).collect())
+            }
+            (CastType::Text, value) => Constant::String(value.to_string()),
+            (CastType::Boolean, Constant::Bool(b)) => Constant::Bool(b),
+            (CastType::Boolean, Constant::Int(i)) => Constant::Bool(i != 0),
+            (CastType::Boolean, Constant::String(s)) => match s.trim().to_lowercase().as_str() {
+                "true" => Constant::Bool(true),
+                "false" => Constant::Bool(false),
+                _ => bail!("cannot cast '{}' to boolean", s),
+            },
+            (CastType::Date, Constant::Date(d)) => Constant::Date(d),
+            (CastType::Date, Constant::String(s)) => match s.trim().parse() {
+                Ok(d) => Constant::Date(d),
+                Err(_) => bail!("cannot cast '{}' to date", s),
+            },
+            (cast_type, value) => bail!("cannot cast {} to {}", value.to_literal(), cast_type),
+        };
+        Ok(converted)
+    }
+}
+
+impl Display for CastType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CastType::Int => write!(f, "int"),
+            CastType::Varchar(length) => write!(f, "varchar({})", length),
+            CastType::Text => write!(f, "text"),
+            CastType::Boolean => write!(f, "boolean"),
+            CastType::Date => write!(f, "date"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_between_constants() -> Result<()> {
+        let string = |s: &str| Constant::String(s.into());
+        let date = Constant::Date("2024-02-29".parse()?);
+
+        assert_eq!(CastType::Int.apply(string(" 42 "))?, Constant::Int(42));
+        assert_eq!(CastType::Int.apply(string("-7"))?, Constant::Int(-7));
+        assert_eq!(CastType::Int.apply(Constant::Bool(true))?, Constant::Int(1));
+        assert!(CastType::Int.apply(string("4x")).is_err());
+        assert!(CastType::Int.apply(date.clone()).is_err());
+
+        assert_eq!(
+            CastType::Varchar(3).apply(Constant::Int(12345))?,
+            string("123")
+        );
+        assert_eq!(
+            CastType::Varchar(10).apply(date.clone())?,
+            string("2024-02-29")
+        );
+        assert_eq!(
+            CastType::Text.apply(Constant::Bool(false))?,
+            string("false")
+        );
+
+        assert_eq!(
+            CastType::Boolean.apply(Constant::Int(2))?,
+            Constant::Bool(true)
+        );
+        assert_eq!(
+            CastType::Boolean.apply(string("FALSE"))?,
+            Constant::Bool(false)
+        );
+        assert!(CastType::Boolean.apply(string("yes")).is_err());
+
+        assert_eq!(CastType::Date.apply(string("2024-02-29"))?, date);
+        assert!(CastType::Date.apply(string("2023-02-29")).is_err());
+        assert!(CastType::Date.apply(Constant::Int(1)).is_err());
+
+        for cast_type in [
+            CastType::Int,
+            CastType::Varchar(1),
+            CastType::Text,
+            CastType::Boolean,
+            CastType::Date,
+        ] {
+            assert_eq!(cast_type.apply(Constant::Null)?, Constant::Null);
+        }
+        Ok(())
+    }
+}
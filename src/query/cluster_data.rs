@@ -0,0 +1,5 @@
+/// ClusterData は `cluster <table>` で、テーブルをキーの順に並べ直す文
+#[derive(Debug, PartialEq, Eq)]
+pub struct ClusterData {
+    pub table_name: String,
+}
@@ -0,0 +1,105 @@
+use super::{constant::Constant, scan::Scan};
+use crate::{
+    file::date::Date,
+    record::{cluster::seek_block, overflow::BlobReader, rid::RID, table_scan::TableScan},
+};
+use anyhow::Result;
+
+/// ClusterSelectScan はキーの順に並んだテーブルから、キーが検索キーと等しいレコードだけを読み込む
+///
+/// 読み始めるブロックを二分探索で決め、キーが検索キーより大きいレコードに着いたら読むのをやめる
+/// 読み始めるブロックは最初に next を呼んだときに探す
+pub struct ClusterSelectScan {
+    ts: TableScan,
+    field_name: String,
+    value: Constant,
+    started: bool,
+    done: bool,
+}
+
+impl ClusterSelectScan {
+    pub fn new(ts: TableScan, field_name: impl Into<String>, value: Constant) -> Self {
+        Self {
+            ts,
+            field_name: field_name.into(),
+            value,
+            started: false,
+            done: false,
+        }
+    }
+}
+
+unsafe impl Send for ClusterSelectScan {}
+unsafe impl Sync for ClusterSelectScan {}
+
+impl Scan for ClusterSelectScan {
+    fn before_first(&mut self) {
+        self.started = false;
+        self.done = false;
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        if !self.started {
+            let block = seek_block(&mut self.ts, &self.field_name, &self.value, false)?;
+            self.ts.move_to_rid(RID::new(block, -1));
+            self.started = true;
+        }
+        while !self.done && self.ts.next()? {
+            let key = self.ts.get_value(&self.field_name)?;
+            if key == self.value {
+                return Ok(true);
+            }
+            self.done = key > self.value;
+        }
+        self.done = true;
+        Ok(false)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        self.ts.get_int(field_name)
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        self.ts.get_string(field_name)
+    }
+
+    fn get_value(&mut self, fieldname: &str) -> Result<Constant> {
+        self.ts.get_value(fieldname)
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.ts.has_field(field_name)
+    }
+
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        self.ts.get_blob_reader(field_name)
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        self.ts.get_long(field_name)
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        self.ts.get_double(field_name)
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        self.ts.get_bool(field_name)
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        self.ts.get_date(field_name)
+    }
+
+    fn close(&mut self) {
+        self.ts.close();
+    }
+
+    fn get_rid(&mut self) -> Result<RID> {
+        self.ts.get_rid()
+    }
+
+    fn move_to_rid(&mut self, rid: RID) {
+        self.ts.move_to_rid(rid);
+    }
+}
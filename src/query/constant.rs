@@ -1,30 +1,61 @@
+use crate::file::date::Date;
 use std::{
     fmt::Display,
     hash::{DefaultHasher, Hash, Hasher},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Constant は比較できるので索引のキーとしても使う
+/// 型が異なる場合は Null が最も小さく、Int、String、Bool、Date の順に大きくなる
+///
+/// Null は値がないことを表す。ここでの比較は並べ替えのためのもので、
+/// 述語の中での NULL の比較は Term::evaluate が Truth::Unknown として扱う
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Constant {
+    Null,
     Int(i32),
     String(String),
+    /// Bool は boolean のフィールドの値と true / false のリテラル。false が true より小さい
+    Bool(bool),
+    /// Date は date のフィールドの値と `date 'YYYY-MM-DD'` のリテラル
+    Date(Date),
 }
 
 impl Constant {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Constant::Null)
+    }
+
     pub fn hash_code(&self) -> u64 {
         let mut state = DefaultHasher::new();
         match self {
+            Constant::Null => 0.hash(&mut state),
             Constant::Int(i) => i.hash(&mut state),
             Constant::String(s) => s.hash(&mut state),
+            Constant::Bool(b) => b.hash(&mut state),
+            Constant::Date(d) => d.hash(&mut state),
         }
         state.finish()
     }
+
+    /// to_literal は SQL のリテラルとして書いた値を返す
+    /// 文字列は引用符で囲み、文字列の中の引用符は '' と書く
+    pub fn to_literal(&self) -> String {
+        match self {
+            Constant::String(s) => format!("'{}'", s.replace('\'', "''")),
+            Constant::Date(d) => format!("date '{}'", d),
+            value => value.to_string(),
+        }
+    }
 }
 
 impl Display for Constant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Constant::Null => write!(f, "NULL"),
             Constant::Int(i) => write!(f, "{}", i),
             Constant::String(s) => write!(f, "{}", s),
+            Constant::Bool(b) => write!(f, "{}", b),
+            Constant::Date(d) => write!(f, "{}", d),
         }
     }
 }
@@ -4,4 +4,6 @@ use crate::record::schema::Schema;
 pub struct CreateTableData {
     pub table_name: String,
     pub schema: Schema,
+    /// cluster by で指定した、レコードを並べるキーのフィールド
+    pub cluster_key: Option<String>,
 }
@@ -0,0 +1,15 @@
+use super::query_data::QueryData;
+
+/// CursorStatement はセッションのカーソルを操作する文
+#[derive(Debug, PartialEq, Eq)]
+pub enum CursorStatement {
+    /// `declare cursor <name> for <query>`
+    Declare {
+        cursor_name: String,
+        query: Box<QueryData>,
+    },
+    /// `fetch <count> from <name>`
+    Fetch { cursor_name: String, count: i32 },
+    /// `close <name>`
+    Close { cursor_name: String },
+}
@@ -0,0 +1,48 @@
+use super::{constant::Constant, scan::Scan};
+use crate::record::schema::Schema;
+use anyhow::{bail, Result};
+use std::sync::Arc;
+
+/// EmptyScan はレコードを1件も返さないスキャン
+/// 述語がどのレコードでも満たされないとわかっている場合に、テーブルを読まずに済ませるために使う
+pub struct EmptyScan {
+    schema: Arc<Schema>,
+}
+
+impl EmptyScan {
+    pub fn new(schema: Arc<Schema>) -> Self {
+        Self { schema }
+    }
+}
+
+impl Scan for EmptyScan {
+    fn before_first(&mut self) {}
+
+    fn next(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn after_last(&mut self) {}
+
+    fn previous(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        bail!("no current record: {}", field_name)
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        bail!("no current record: {}", field_name)
+    }
+
+    fn get_value(&mut self, field_name: &str) -> Result<Constant> {
+        bail!("no current record: {}", field_name)
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.schema.has_field(field_name)
+    }
+
+    fn close(&mut self) {}
+}
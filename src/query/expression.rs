@@ -1,12 +1,105 @@
-use super::{constant::Constant, scan::ArcScan};
-use crate::{record::schema::Schema, unlock};
-use anyhow::Result;
+use super::{cast::CastType, constant::Constant, scan::ArcScan};
+use crate::{file::date::Date, metadata::table_manager::unix_time, record::schema::Schema, unlock};
+use anyhow::{bail, Result};
 use std::{fmt::Display, sync::Arc};
 
+/// SECONDS_PER_DAY は1日の秒数
+const SECONDS_PER_DAY: i32 = 24 * 60 * 60;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expression {
     Value(Constant),
     FieldName(String),
+    /// Function は引数のない関数で、評価するたびに現在の値を返す
+    Function(Function),
+    /// Arithmetic は2つの式の足し算か引き算
+    Arithmetic(Box<Expression>, Operator, Box<Expression>),
+    /// Cast は式の値を別の型に変換する
+    Cast(Box<Expression>, CastType),
+}
+
+/// Function は式に書ける関数
+/// 時刻は UTC で数える。timestamp の型はないので、current_timestamp は UNIX 時間の秒を int で返す
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    CurrentDate,
+    CurrentTimestamp,
+}
+
+impl Function {
+    pub fn evaluate(&self) -> Constant {
+        let now = unix_time();
+        match self {
+            Function::CurrentDate => Constant::Date(Date::from_days(now / SECONDS_PER_DAY)),
+            Function::CurrentTimestamp => Constant::Int(now),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Function::CurrentDate => write!(f, "current_date"),
+            Function::CurrentTimestamp => write!(f, "current_timestamp"),
+        }
+    }
+}
+
+/// Operator は式の足し算と引き算
+///
+/// int どうしは整数の計算をする。date に int を足し引きすると日数だけずらした日付になり、
+/// date から date を引くと日数の差を int で返す。どちらかが NULL の場合は NULL になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+}
+
+impl Operator {
+    pub fn apply(&self, lhs: Constant, rhs: Constant) -> Result<Constant> {
+        let value = match (self, lhs, rhs) {
+            (_, Constant::Null, _) | (_, _, Constant::Null) => Constant::Null,
+            (Operator::Add, Constant::Int(l), Constant::Int(r)) => {
+                Constant::Int(checked(l.checked_add(r))?)
+            }
+            (Operator::Subtract, Constant::Int(l), Constant::Int(r)) => {
+                Constant::Int(checked(l.checked_sub(r))?)
+            }
+            (Operator::Add, Constant::Date(d), Constant::Int(days))
+            | (Operator::Add, Constant::Int(days), Constant::Date(d)) => {
+                Constant::Date(Date::from_days(checked(d.days().checked_add(days))?))
+            }
+            (Operator::Subtract, Constant::Date(d), Constant::Int(days)) => {
+                Constant::Date(Date::from_days(checked(d.days().checked_sub(days))?))
+            }
+            (Operator::Subtract, Constant::Date(l), Constant::Date(r)) => {
+                Constant::Int(checked(l.days().checked_sub(r.days()))?)
+            }
+            (op, l, r) => bail!(
+                "cannot apply {} to {} and {}",
+                op,
+                l.to_literal(),
+                r.to_literal()
+            ),
+        };
+        Ok(value)
+    }
+}
+
+fn checked(value: Option<i32>) -> Result<i32> {
+    match value {
+        Some(value) => Ok(value),
+        None => bail!("integer overflow"),
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Subtract => write!(f, "-"),
+        }
+    }
 }
 
 impl From<Constant> for Expression {
@@ -36,9 +129,27 @@ impl Expression {
         }
     }
 
+    /// field_names は式が参照するフィールドの名前を、現れた順に返す
+    pub fn field_names(&self) -> Vec<String> {
+        match self {
+            Expression::FieldName(field_name) => vec![field_name.clone()],
+            Expression::Arithmetic(lhs, _, rhs) => {
+                let mut field_names = lhs.field_names();
+                field_names.extend(rhs.field_names());
+                field_names
+            }
+            Expression::Cast(expression, _) => expression.field_names(),
+            _ => vec![],
+        }
+    }
+
     pub fn applies_to(&self, schema: Arc<Schema>) -> bool {
         match self {
             Expression::FieldName(field_name) => schema.has_field(field_name),
+            Expression::Arithmetic(lhs, _, rhs) => {
+                lhs.applies_to(schema.clone()) && rhs.applies_to(schema)
+            }
+            Expression::Cast(expression, _) => expression.applies_to(schema),
             _ => true,
         }
     }
@@ -47,6 +158,12 @@ impl Expression {
         match self {
             Expression::Value(value) => Ok(value.clone()),
             Expression::FieldName(field_name) => unlock!(scan).get_value(field_name),
+            Expression::Function(function) => Ok(function.evaluate()),
+            Expression::Arithmetic(lhs, op, rhs) => {
+                let lhs = lhs.evaluate(scan.clone())?;
+                op.apply(lhs, rhs.evaluate(scan)?)
+            }
+            Expression::Cast(expression, cast_type) => cast_type.apply(expression.evaluate(scan)?),
         }
     }
 }
@@ -54,8 +171,67 @@ impl Expression {
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expression::Value(value) => write!(f, "{}", value),
+            // ビューの定義として保存されてもう一度パースされるので、値はリテラルとして書く
+            Expression::Value(value) => write!(f, "{}", value.to_literal()),
             Expression::FieldName(field_name) => write!(f, "{}", field_name),
+            Expression::Function(function) => write!(f, "{}", function),
+            // パーサーは左から順に計算するので、右辺の計算は括弧がないと書けない
+            Expression::Arithmetic(lhs, op, rhs) => write!(f, "{} {} {}", lhs, op, rhs),
+            Expression::Cast(expression, cast_type) => {
+                write!(f, "cast({} as {})", expression, cast_type)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_add_and_subtract_dates() -> Result<()> {
+        let date = Constant::Date("2024-02-28".parse()?);
+        let days = Constant::Int;
+        assert_eq!(
+            Operator::Add.apply(date.clone(), days(2))?,
+            Constant::Date("2024-03-01".parse()?)
+        );
+        assert_eq!(
+            Operator::Add.apply(days(2), date.clone())?,
+            Constant::Date("2024-03-01".parse()?)
+        );
+        assert_eq!(
+            Operator::Subtract.apply(date.clone(), days(59))?,
+            Constant::Date("2023-12-31".parse()?)
+        );
+        assert_eq!(
+            Operator::Subtract.apply(date.clone(), Constant::Date("2024-01-01".parse()?))?,
+            days(58)
+        );
+        assert_eq!(Operator::Subtract.apply(days(5), days(7))?, days(-2));
+        assert_eq!(
+            Operator::Add.apply(date.clone(), Constant::Null)?,
+            Constant::Null
+        );
+
+        assert!(Operator::Subtract.apply(days(1), date.clone()).is_err());
+        assert!(Operator::Add.apply(date.clone(), date).is_err());
+        assert!(Operator::Add.apply(days(i32::MAX), days(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_evaluate_current_date_from_current_timestamp() {
+        let Constant::Int(before) = Function::CurrentTimestamp.evaluate() else {
+            panic!("current_timestamp should be an int");
+        };
+        let Constant::Date(today) = Function::CurrentDate.evaluate() else {
+            panic!("current_date should be a date");
+        };
+        let Constant::Int(after) = Function::CurrentTimestamp.evaluate() else {
+            panic!("current_timestamp should be an int");
+        };
+        assert!(today.days() >= before / SECONDS_PER_DAY);
+        assert!(today.days() <= after / SECONDS_PER_DAY);
+    }
+}
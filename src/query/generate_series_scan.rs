@@ -0,0 +1,134 @@
+use super::{constant::Constant, scan::Scan};
+use anyhow::{bail, Result};
+
+/// GenerateSeriesScan は start から stop までの整数を1つのフィールドに入れて返すスキャン
+/// テーブルを読まずにレコードを作るので、試験用のデータを作ったり結合の速さを測ったりするのに使う
+pub struct GenerateSeriesScan {
+    field_name: String,
+    start: i64,
+    stop: i64,
+    /// current は今のレコードの値。start - 1 と stop + 1 は最初の前と最後の後ろを表す
+    current: i64,
+}
+
+impl GenerateSeriesScan {
+    pub fn new(field_name: String, start: i32, stop: i32) -> Self {
+        let start = start as i64;
+        Self {
+            field_name,
+            start,
+            stop: stop as i64,
+            current: start - 1,
+        }
+    }
+
+    fn current_value(&self, field_name: &str) -> Result<i32> {
+        if !self.has_field(field_name) {
+            bail!("field not found: {}", field_name);
+        }
+        if self.current < self.start || self.current > self.stop {
+            bail!("no current record: {}", field_name);
+        }
+        Ok(self.current as i32)
+    }
+}
+
+impl Scan for GenerateSeriesScan {
+    fn before_first(&mut self) {
+        self.current = self.start - 1;
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        if self.current <= self.stop {
+            self.current += 1;
+        }
+        Ok(self.current <= self.stop)
+    }
+
+    fn after_last(&mut self) {
+        self.current = self.stop.max(self.start - 1) + 1;
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        if self.current >= self.start {
+            self.current -= 1;
+        }
+        Ok(self.current >= self.start && self.current <= self.stop)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        self.current_value(field_name)
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        bail!("field is not a string: {}", field_name)
+    }
+
+    fn get_value(&mut self, field_name: &str) -> Result<Constant> {
+        Ok(Constant::Int(self.current_value(field_name)?))
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.field_name == field_name
+    }
+
+    fn close(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(scan: &mut GenerateSeriesScan) -> Result<Vec<i32>> {
+        let mut values = vec![];
+        while scan.next()? {
+            values.push(scan.get_int("n")?);
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn should_generate_integers_in_both_directions() -> Result<()> {
+        let mut scan = GenerateSeriesScan::new("n".into(), -1, 2);
+        assert_eq!(read_all(&mut scan)?, [-1, 0, 1, 2]);
+        assert!(!scan.next()?);
+        assert!(scan.get_int("n").is_err());
+
+        scan.after_last();
+        let mut values = vec![];
+        while scan.previous()? {
+            values.push(scan.get_int("n")?);
+        }
+        assert_eq!(values, [2, 1, 0, -1]);
+
+        scan.before_first();
+        assert_eq!(read_all(&mut scan)?, [-1, 0, 1, 2]);
+        assert!(scan.get_value("m").is_err());
+
+        let mut scan = GenerateSeriesScan::new("n".into(), 3, 1);
+        assert!(read_all(&mut scan)?.is_empty());
+        scan.after_last();
+        assert!(!scan.previous()?);
+
+        let mut scan = GenerateSeriesScan::new("n".into(), i32::MAX - 1, i32::MAX);
+        assert_eq!(read_all(&mut scan)?, [i32::MAX - 1, i32::MAX]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_return_errors_for_unsupported_accessors() -> Result<()> {
+        let mut scan = GenerateSeriesScan::new("n".into(), 1, 2);
+        assert!(scan.next()?);
+        // 実装していない読み書きはパニックせずにエラーを返す
+        assert!(scan.get_long("n").is_err());
+        assert!(scan.get_double("n").is_err());
+        assert!(scan.get_bool("n").is_err());
+        assert!(scan.get_date("n").is_err());
+        assert!(scan.get_blob_reader("n").is_err());
+        assert!(scan.set_int("n", 3).is_err());
+        assert!(scan.set_blob("n", &mut &b"x"[..]).is_err());
+        assert!(scan.delete().is_err());
+        assert_eq!(scan.get_int("n")?, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::{bail, Result};
+use std::{fmt::Display, str::FromStr};
+
+/// Privilege はテーブルに対する操作の権限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Privilege {
+    /// ALL は `grant all` で付与される権限
+    pub const ALL: [Privilege; 4] = [
+        Privilege::Select,
+        Privilege::Insert,
+        Privilege::Update,
+        Privilege::Delete,
+    ];
+}
+
+impl Display for Privilege {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Privilege::Select => write!(f, "select"),
+            Privilege::Insert => write!(f, "insert"),
+            Privilege::Update => write!(f, "update"),
+            Privilege::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl FromStr for Privilege {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "select" => Ok(Privilege::Select),
+            "insert" => Ok(Privilege::Insert),
+            "update" => Ok(Privilege::Update),
+            "delete" => Ok(Privilege::Delete),
+            _ => bail!("unknown privilege: {}", s),
+        }
+    }
+}
+
+/// GrantData は `grant` と `revoke` の対象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrantData {
+    pub privileges: Vec<Privilege>,
+    pub table_name: String,
+    pub grantee: String,
+}
@@ -0,0 +1,136 @@
+use super::{
+    aggregation_function::Accumulator,
+    constant::Constant,
+    scan::{ArcScan, Scan},
+};
+use crate::unlock;
+use anyhow::{bail, Result};
+
+/// GroupByScan はグループのフィールドで並べた入力を、グループごとに1件のレコードにまとめるスキャン
+///
+/// グループのフィールドがない場合はすべての入力を1つのグループとして、入力が空でも1件を返す
+pub struct GroupByScan {
+    scan: ArcScan,
+    group_fields: Vec<String>,
+    accumulators: Vec<Accumulator>,
+    group_values: Vec<Constant>,
+    /// started は入力を最初のレコードに進めたかを表す
+    started: bool,
+    /// more_groups は入力にまだ集計していないレコードがあるかを表す
+    more_groups: bool,
+    /// done はグループのフィールドがない場合に、1件を返し終わったかを表す
+    done: bool,
+}
+
+impl GroupByScan {
+    pub fn new(scan: ArcScan, group_fields: Vec<String>, accumulators: Vec<Accumulator>) -> Self {
+        Self {
+            scan,
+            group_fields,
+            accumulators,
+            group_values: vec![],
+            started: false,
+            more_groups: false,
+            done: false,
+        }
+    }
+
+    fn current_group(&self) -> Result<Vec<Constant>> {
+        self.group_fields
+            .iter()
+            .map(|field| unlock!(self.scan).get_value(field))
+            .collect()
+    }
+
+    /// accumulate_group は今のレコードから、グループが変わるか入力が終わるまで集計する
+    fn accumulate_group(&mut self) -> Result<()> {
+        for accumulator in self.accumulators.iter_mut() {
+            accumulator.reset();
+        }
+        while self.more_groups {
+            let group = self.current_group()?;
+            if group != self.group_values {
+                break;
+            }
+            for accumulator in self.accumulators.iter_mut() {
+                accumulator.accumulate(&self.scan)?;
+            }
+            self.more_groups = unlock!(self.scan).next()?;
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Send for GroupByScan {}
+unsafe impl Sync for GroupByScan {}
+
+impl Scan for GroupByScan {
+    fn before_first(&mut self) {
+        unlock!(self.scan).before_first();
+        self.started = false;
+        self.done = false;
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        if !self.started {
+            self.started = true;
+            self.more_groups = unlock!(self.scan).next()?;
+        }
+        if self.group_fields.is_empty() {
+            if self.done {
+                return Ok(false);
+            }
+            self.done = true;
+        } else {
+            if !self.more_groups {
+                return Ok(false);
+            }
+            self.group_values = self.current_group()?;
+        }
+        self.accumulate_group()?;
+        Ok(true)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        match self.get_value(field_name)? {
+            Constant::Int(value) => Ok(value),
+            _ => bail!("field is not an int: {}", field_name),
+        }
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        match self.get_value(field_name)? {
+            Constant::String(value) => Ok(value),
+            _ => bail!("field is not a string: {}", field_name),
+        }
+    }
+
+    fn get_value(&mut self, field_name: &str) -> Result<Constant> {
+        if let Some(pos) = self.group_fields.iter().position(|f| f == field_name) {
+            return match self.group_values.get(pos) {
+                Some(value) => Ok(value.clone()),
+                None => bail!("no current group: {}", field_name),
+            };
+        }
+        match self
+            .accumulators
+            .iter()
+            .find(|accumulator| accumulator.function().field_name() == field_name)
+        {
+            Some(accumulator) => Ok(accumulator.value()),
+            None => bail!("field not found: {}", field_name),
+        }
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.group_fields.iter().any(|f| f == field_name)
+            || self
+                .accumulators
+                .iter()
+                .any(|accumulator| accumulator.function().field_name() == field_name)
+    }
+
+    fn close(&mut self) {
+        unlock!(self.scan).close();
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{bail, Result};
+use std::{fmt::Display, iter::Peekable, str::Chars};
+
+/// Hint は `select /*+ ... */` に書くオプティマイザヒント
+///
+/// - `use_index(<table> <index>)` はテーブルを指定した索引で検索する
+/// - `leading(<table> ...)` は指定した順にテーブルを結合する
+///
+/// 知らない名前のヒントは無視する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    UseIndex {
+        table_name: String,
+        index_name: String,
+    },
+    Leading(Vec<String>),
+}
+
+impl Hint {
+    /// parse_list はコメントの中身をヒントの一覧として解析する
+    pub fn parse_list(text: &str) -> Result<Vec<Hint>> {
+        let mut chars = text.chars().peekable();
+        let mut hints = vec![];
+        loop {
+            skip_separators(&mut chars);
+            if chars.peek().is_none() {
+                break;
+            }
+            let name = read_word(&mut chars);
+            if name.is_empty() {
+                bail!("invalid hint: {}", text);
+            }
+            skip_separators(&mut chars);
+            if chars.next() != Some('(') {
+                bail!("expected '(' after hint {}", name);
+            }
+            let mut args = vec![];
+            loop {
+                skip_separators(&mut chars);
+                match chars.peek() {
+                    Some(')') => {
+                        chars.next();
+                        break;
+                    }
+                    None => bail!("expected ')' after hint {}", name),
+                    _ => args.push(read_word(&mut chars)),
+                }
+                if args.last().is_some_and(|arg| arg.is_empty()) {
+                    bail!("invalid argument of hint {}", name);
+                }
+            }
+
+            match (name.to_lowercase().as_str(), args.as_slice()) {
+                ("use_index", [table_name, index_name]) => hints.push(Hint::UseIndex {
+                    table_name: table_name.clone(),
+                    index_name: index_name.clone(),
+                }),
+                ("use_index", _) => bail!("use_index needs a table and an index: {}", text),
+                ("leading", []) => bail!("leading needs tables: {}", text),
+                ("leading", _) => hints.push(Hint::Leading(args)),
+                _ => {}
+            }
+        }
+        Ok(hints)
+    }
+
+    /// index_for は use_index ヒントでテーブルに指定された索引の名前を返す
+    pub fn index_for<'a>(hints: &'a [Hint], table_name: &str) -> Option<&'a str> {
+        hints.iter().find_map(|hint| match hint {
+            Hint::UseIndex {
+                table_name: t,
+                index_name,
+            } if t == table_name => Some(index_name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// leading_order は leading ヒントに従ってテーブルを並べ替える
+    /// ヒントにないテーブルは元の順で後ろに並べる。ヒントがなければ None を返す
+    pub fn leading_order(hints: &[Hint], tables: &[String]) -> Result<Option<Vec<String>>> {
+        let Some(leading) = hints.iter().find_map(|hint| match hint {
+            Hint::Leading(tables) => Some(tables),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+
+        let mut ordered = vec![];
+        for table_name in leading {
+            if !tables.contains(table_name) {
+                bail!(
+                    "table in leading hint is not in from clause: {}",
+                    table_name
+                );
+            }
+            if !ordered.contains(table_name) {
+                ordered.push(table_name.clone());
+            }
+        }
+        for table_name in tables {
+            if !ordered.contains(table_name) {
+                ordered.push(table_name.clone());
+            }
+        }
+        Ok(Some(ordered))
+    }
+}
+
+fn skip_separators(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+}
+
+fn read_word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+        word.push(c);
+    }
+    word
+}
+
+impl Display for Hint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Hint::UseIndex {
+                table_name,
+                index_name,
+            } => write!(f, "use_index({} {})", table_name, index_name),
+            Hint::Leading(tables) => write!(f, "leading({})", tables.join(" ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_hints() -> Result<()> {
+        let hints = Hint::parse_list(" use_index(t idx_a) no_such_hint(x), leading(u, t) ")?;
+        assert_eq!(
+            hints,
+            vec![
+                Hint::UseIndex {
+                    table_name: "t".into(),
+                    index_name: "idx_a".into(),
+                },
+                Hint::Leading(vec!["u".into(), "t".into()]),
+            ]
+        );
+        assert_eq!(hints[0].to_string(), "use_index(t idx_a)");
+        assert_eq!(Hint::index_for(&hints, "t"), Some("idx_a"));
+        assert_eq!(Hint::index_for(&hints, "u"), None);
+
+        assert!(Hint::parse_list("use_index(t)").is_err());
+        assert!(Hint::parse_list("leading(t").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_order_tables_by_leading_hint() -> Result<()> {
+        let tables: Vec<String> = vec!["a".into(), "b".into(), "c".into()];
+        let hints = vec![Hint::Leading(vec!["c".into(), "a".into()])];
+        assert_eq!(
+            Hint::leading_order(&hints, &tables)?,
+            Some(vec!["c".into(), "a".into(), "b".into()])
+        );
+        assert_eq!(Hint::leading_order(&[], &tables)?, None);
+
+        let hints = vec![Hint::Leading(vec!["d".into()])];
+        assert!(Hint::leading_order(&hints, &tables).is_err());
+        Ok(())
+    }
+}
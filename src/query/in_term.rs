@@ -0,0 +1,121 @@
+use super::{constant::Constant, expression::Expression, scan::ArcScan, truth::Truth};
+use crate::{
+    plan::ArcPlan,
+    record::{collation::Collation, schema::Schema},
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::{fmt::Display, sync::Arc};
+
+/// InTerm は `(A, B) IN ((1, 'x'), (2, 'y'))` のように、行の値がいずれかの行と等しいかを表す項
+/// `A IN (1, 2)` は1列の行を並べた項として表す
+///
+/// 各行との比較は列ごとの等しさの AND で、項の値はそれらの OR になる
+/// そのため NULL を含む比較は三値論理に従って Unknown になることがある
+/// 文字列は列ごとにフィールドの照合順序で比べる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InTerm {
+    lhs: Vec<Expression>,
+    rows: Vec<Vec<Constant>>,
+    collations: Vec<Collation>,
+}
+
+impl InTerm {
+    /// new は行の値と候補の行から項を作る。列の数が合わない行がある場合はエラーを返す
+    pub fn new(lhs: Vec<Expression>, rows: Vec<Vec<Constant>>) -> Result<Self> {
+        if rows.is_empty() {
+            bail!("IN list must not be empty");
+        }
+        if let Some(row) = rows.iter().find(|row| row.len() != lhs.len()) {
+            bail!(
+                "row value sizes differ: expected {}, found {}",
+                lhs.len(),
+                row.len()
+            );
+        }
+        let collations = vec![Collation::Binary; lhs.len()];
+        Ok(Self {
+            lhs,
+            rows,
+            collations,
+        })
+    }
+
+    /// collate はスキーマにあるフィールドの照合順序で比べるように設定する
+    pub fn collate(&mut self, schema: &Schema) {
+        for (expression, collation) in self.lhs.iter().zip(&mut self.collations) {
+            if let Some(field_name) = expression.field_name() {
+                if schema.has_field(&field_name) {
+                    *collation = schema.collation(&field_name);
+                }
+            }
+        }
+    }
+
+    pub fn lhs(&self) -> &[Expression] {
+        &self.lhs
+    }
+
+    pub fn rows(&self) -> &[Vec<Constant>] {
+        &self.rows
+    }
+
+    /// evaluate は項を三値論理で評価する。True になる行が見つかればそれ以降の行は比べない
+    pub fn evaluate(&self, scan: ArcScan) -> Result<Truth> {
+        let mut values = vec![];
+        for (expression, collation) in self.lhs.iter().zip(&self.collations) {
+            values.push((expression.evaluate(scan.clone())?, collation));
+        }
+        let mut result = Truth::False;
+        for row in &self.rows {
+            let matched =
+                values
+                    .iter()
+                    .zip(row)
+                    .fold(Truth::True, |acc, ((lhs, collation), rhs)| {
+                        acc.and(Truth::compare(&collation.key(lhs), &collation.key(rhs)))
+                    });
+            result = result.or(matched);
+            if result == Truth::True {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// reduction_factor は列の値の組み合わせの数を候補の行の数で割った値を返す
+    pub fn reduction_factor(&self, plan: ArcPlan) -> i32 {
+        let combinations = self
+            .lhs
+            .iter()
+            .filter_map(|expression| expression.field_name())
+            .map(|field_name| unlock!(plan).distinct_values(&field_name))
+            .fold(1, i32::saturating_mul);
+        (combinations / self.rows.len() as i32).max(1)
+    }
+
+    pub fn applies_to(&self, schema: Arc<Schema>) -> bool {
+        self.lhs
+            .iter()
+            .all(|expression| expression.applies_to(schema.clone()))
+    }
+}
+
+impl Display for InTerm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let [lhs] = self.lhs.as_slice() {
+            let values: Vec<String> = self.rows.iter().map(|row| row[0].to_literal()).collect();
+            return write!(f, "{} IN ({})", lhs, values.join(", "));
+        }
+        let lhs: Vec<String> = self.lhs.iter().map(|e| e.to_string()).collect();
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(|value| value.to_literal()).collect();
+                format!("({})", values.join(", "))
+            })
+            .collect();
+        write!(f, "({}) IN ({})", lhs.join(", "), rows.join(", "))
+    }
+}
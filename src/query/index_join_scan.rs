@@ -0,0 +1,139 @@
+use super::{
+    constant::Constant,
+    index_scan::IndexScan,
+    scan::{ArcScan, Scan},
+};
+use crate::{file::date::Date, record::overflow::BlobReader, unlock};
+use anyhow::Result;
+
+/// IndexJoinScan は外側のスキャンのレコードごとに、結合するフィールドの値で内側のテーブルを索引で検索する
+///
+/// 内側のスキャンは外側のレコードの値を検索キーにして読み直す
+/// 外側のレコードは最初に next を呼んだときに読み始める
+pub struct IndexJoinScan {
+    lhs: ArcScan,
+    rhs: IndexScan,
+    join_field: String,
+    started: bool,
+}
+
+impl IndexJoinScan {
+    pub fn new(lhs: ArcScan, rhs: IndexScan, join_field: impl Into<String>) -> Self {
+        let mut scan = Self {
+            lhs,
+            rhs,
+            join_field: join_field.into(),
+            started: false,
+        };
+        scan.before_first();
+        scan
+    }
+
+    /// reset_index は外側のレコードの結合するフィールドの値で内側を検索し直す
+    fn reset_index(&mut self) -> Result<()> {
+        let key = unlock!(self.lhs).get_value(&self.join_field)?;
+        self.rhs.seek(key)
+    }
+}
+
+unsafe impl Send for IndexJoinScan {}
+unsafe impl Sync for IndexJoinScan {}
+
+impl Scan for IndexJoinScan {
+    fn before_first(&mut self) {
+        unlock!(self.lhs).before_first();
+        self.started = false;
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        if !self.started {
+            self.started = true;
+            if !unlock!(self.lhs).next()? {
+                return Ok(false);
+            }
+            self.reset_index()?;
+        }
+        loop {
+            if self.rhs.next()? {
+                return Ok(true);
+            }
+            if !unlock!(self.lhs).next()? {
+                return Ok(false);
+            }
+            self.reset_index()?;
+        }
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_int(field_name)
+        } else {
+            unlock!(self.lhs).get_int(field_name)
+        }
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_string(field_name)
+        } else {
+            unlock!(self.lhs).get_string(field_name)
+        }
+    }
+
+    fn get_value(&mut self, fieldname: &str) -> Result<Constant> {
+        if self.rhs.has_field(fieldname) {
+            self.rhs.get_value(fieldname)
+        } else {
+            unlock!(self.lhs).get_value(fieldname)
+        }
+    }
+
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_blob_reader(field_name)
+        } else {
+            unlock!(self.lhs).get_blob_reader(field_name)
+        }
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_long(field_name)
+        } else {
+            unlock!(self.lhs).get_long(field_name)
+        }
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_double(field_name)
+        } else {
+            unlock!(self.lhs).get_double(field_name)
+        }
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_bool(field_name)
+        } else {
+            unlock!(self.lhs).get_bool(field_name)
+        }
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        if self.rhs.has_field(field_name) {
+            self.rhs.get_date(field_name)
+        } else {
+            unlock!(self.lhs).get_date(field_name)
+        }
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.rhs.has_field(field_name) || unlock!(self.lhs).has_field(field_name)
+    }
+
+    fn close(&mut self) {
+        unlock!(self.lhs).close();
+        self.rhs.close();
+    }
+}
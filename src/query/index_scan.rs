@@ -0,0 +1,112 @@
+use crate::{
+    file::date::Date,
+    index::Index,
+    record::{overflow::BlobReader, rid::RID},
+    unlock,
+};
+
+use super::{
+    constant::Constant,
+    scan::{ArcScan, Scan},
+};
+use anyhow::Result;
+
+/// IndexScan は索引で検索キーに一致するレコードだけをテーブルから読み込む
+///
+/// 索引が返す RID ごとにテーブルのスキャンを移動して、テーブルのフィールドを読み込めるようにする
+/// 検索キーは seek で変えられるので、結合で外側のレコードごとに別のキーで検索するときにも使う
+/// 検索キーを決める前は1件も読み込まない
+pub struct IndexScan {
+    scan: ArcScan,
+    index: Box<dyn Index>,
+    key: Option<Constant>,
+}
+
+impl IndexScan {
+    pub fn new(scan: ArcScan, index: Box<dyn Index>) -> Self {
+        Self {
+            scan,
+            index,
+            key: None,
+        }
+    }
+
+    /// with_key は key で検索する IndexScan を作る
+    pub fn with_key(scan: ArcScan, index: Box<dyn Index>, key: Constant) -> Result<Self> {
+        let mut scan = Self::new(scan, index);
+        scan.seek(key)?;
+        Ok(scan)
+    }
+
+    /// seek は検索キーを key に変えて、最初のレコードの前に移動する
+    pub fn seek(&mut self, key: Constant) -> Result<()> {
+        self.index.before_first(key.clone())?;
+        self.key = Some(key);
+        Ok(())
+    }
+}
+
+unsafe impl Send for IndexScan {}
+unsafe impl Sync for IndexScan {}
+
+impl Scan for IndexScan {
+    fn before_first(&mut self) {
+        if let Some(key) = self.key.clone() {
+            self.index.before_first(key).unwrap();
+        }
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        if self.key.is_none() || !self.index.next()? {
+            return Ok(false);
+        }
+        let rid = self.index.get_data_rid()?;
+        unlock!(self.scan).move_to_rid(rid);
+        Ok(true)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        unlock!(self.scan).get_int(field_name)
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        unlock!(self.scan).get_string(field_name)
+    }
+
+    fn get_value(&mut self, fieldname: &str) -> Result<Constant> {
+        unlock!(self.scan).get_value(fieldname)
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        unlock!(self.scan).has_field(field_name)
+    }
+
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        unlock!(self.scan).get_blob_reader(field_name)
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        unlock!(self.scan).get_long(field_name)
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        unlock!(self.scan).get_double(field_name)
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        unlock!(self.scan).get_bool(field_name)
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        unlock!(self.scan).get_date(field_name)
+    }
+
+    fn close(&mut self) {
+        self.index.close();
+        unlock!(self.scan).close();
+    }
+
+    fn get_rid(&mut self) -> Result<RID> {
+        unlock!(self.scan).get_rid()
+    }
+}
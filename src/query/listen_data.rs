@@ -0,0 +1,8 @@
+/// ListenStatement はセッションがテーブルの変更の知らせを購読する文
+#[derive(Debug, PartialEq, Eq)]
+pub enum ListenStatement {
+    /// `listen <table>`
+    Listen { table_name: String },
+    /// `unlisten <table>`
+    Unlisten { table_name: String },
+}
@@ -0,0 +1,117 @@
+use super::{constant::Constant, scan::Scan};
+use anyhow::{bail, Result};
+
+/// MemorySortScan はメモリの上で並べ替えたレコードを返すスキャン
+/// SortPlan が入力をすべてメモリに載せられた場合に、一時テーブルを作らずに使う
+pub struct MemorySortScan {
+    fields: Vec<String>,
+    rows: Vec<Vec<Constant>>,
+    /// current は今のレコードの位置。-1 と rows.len() は最初の前と最後の後ろを表す
+    current: isize,
+}
+
+impl MemorySortScan {
+    /// new は fields の順に値を並べたレコードを、並べ替えた順に受け取る
+    pub fn new(fields: Vec<String>, rows: Vec<Vec<Constant>>) -> Self {
+        Self {
+            fields,
+            rows,
+            current: -1,
+        }
+    }
+
+    fn current_value(&self, field_name: &str) -> Result<&Constant> {
+        let Some(pos) = self.fields.iter().position(|field| field == field_name) else {
+            bail!("field not found: {}", field_name);
+        };
+        match usize::try_from(self.current)
+            .ok()
+            .and_then(|current| self.rows.get(current))
+        {
+            Some(row) => Ok(&row[pos]),
+            None => bail!("no current record: {}", field_name),
+        }
+    }
+}
+
+impl Scan for MemorySortScan {
+    fn before_first(&mut self) {
+        self.current = -1;
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        if self.current < self.rows.len() as isize {
+            self.current += 1;
+        }
+        Ok(self.current < self.rows.len() as isize)
+    }
+
+    fn after_last(&mut self) {
+        self.current = self.rows.len() as isize;
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        if self.current >= 0 {
+            self.current -= 1;
+        }
+        Ok(self.current >= 0)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        match self.current_value(field_name)? {
+            Constant::Int(value) => Ok(*value),
+            _ => bail!("field is not an int: {}", field_name),
+        }
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        match self.current_value(field_name)? {
+            Constant::String(value) => Ok(value.clone()),
+            _ => bail!("field is not a string: {}", field_name),
+        }
+    }
+
+    fn get_value(&mut self, field_name: &str) -> Result<Constant> {
+        self.current_value(field_name).cloned()
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.fields.iter().any(|field| field == field_name)
+    }
+
+    fn close(&mut self) {
+        self.rows.clear();
+        self.current = -1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_sorted_rows_in_both_directions() -> Result<()> {
+        let rows = vec![
+            vec![Constant::Int(1), Constant::String("a".into())],
+            vec![Constant::Int(2), Constant::String("b".into())],
+        ];
+        let mut scan = MemorySortScan::new(vec!["A".into(), "B".into()], rows);
+        assert!(scan.get_int("A").is_err());
+        assert!(scan.next()?);
+        assert_eq!(scan.get_int("A")?, 1);
+        assert!(scan.next()?);
+        assert_eq!(scan.get_string("B")?, "b");
+        assert!(!scan.next()?);
+        assert!(scan.get_value("A").is_err());
+
+        scan.after_last();
+        assert!(scan.previous()?);
+        assert_eq!(scan.get_value("B")?, Constant::String("b".into()));
+        assert!(scan.previous()?);
+        assert_eq!(scan.get_int("A")?, 1);
+        assert!(!scan.previous()?);
+        assert!(scan.get_int("X").is_err());
+        assert!(!scan.has_field("X"));
+        Ok(())
+    }
+}
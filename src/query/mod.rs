@@ -1,16 +1,38 @@
+pub mod aggregation_function;
+pub mod bool_term;
+pub mod cast;
+pub mod cluster_data;
+pub mod cluster_select_scan;
 pub mod constant;
 pub mod create_index_data;
 pub mod create_table_data;
 pub mod create_view_data;
+pub mod cursor_data;
 pub mod expression;
+pub mod generate_series_scan;
+pub mod grant_data;
+pub mod group_by_scan;
+pub mod hint;
+pub mod in_term;
+pub mod index_join_scan;
+pub mod index_scan;
 pub mod insert_data;
+pub mod listen_data;
+pub mod memory_sort_scan;
 pub mod modify_data;
+pub mod null_term;
 pub mod predicate;
 pub mod product_scan;
 pub mod project_scan;
 pub mod query_data;
+pub mod record_comparator;
+pub mod result_set_metadata;
 pub mod scan;
 pub mod select_scan;
+pub mod sort_scan;
 pub mod statement;
+pub mod table_function;
 pub mod term;
+pub mod truth;
 pub mod delete_data;
+pub mod empty_scan;
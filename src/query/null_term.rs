@@ -0,0 +1,73 @@
+use super::{constant::Constant, expression::Expression, scan::ArcScan, truth::Truth};
+use crate::{plan::ArcPlan, record::schema::Schema, unlock};
+use anyhow::Result;
+use std::{fmt::Display, sync::Arc};
+
+/// NullTerm は `A IS NULL` と `A IS NOT NULL` のように、式の値が NULL かを表す項
+///
+/// 比較と違って値が NULL でも Unknown にはならず、True か False になる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NullTerm {
+    expression: Expression,
+    negated: bool,
+}
+
+impl NullTerm {
+    /// new は negated が false なら `IS NULL`、true なら `IS NOT NULL` の項を返す
+    pub fn new(expression: Expression, negated: bool) -> Self {
+        Self {
+            expression,
+            negated,
+        }
+    }
+
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// evaluate は式の値が NULL かを調べる。結果は Unknown にならない
+    pub fn evaluate(&self, scan: ArcScan) -> Result<Truth> {
+        let value = self.expression.evaluate(scan)?;
+        Ok(self.holds(&value))
+    }
+
+    /// holds は値がこの項を満たすかを返す
+    pub fn holds(&self, value: &Constant) -> Truth {
+        Truth::from(value.is_null() != self.negated)
+    }
+
+    /// reduction_factor は `IS NULL` を NULL という値との等しさとみなして、フィールドの値の種類の数で割る
+    /// `IS NOT NULL` はほとんどのレコードを選ぶので 1 を返す
+    pub fn reduction_factor(&self, plan: ArcPlan) -> i32 {
+        if let Expression::Value(value) = &self.expression {
+            return match self.holds(value) {
+                Truth::True => 1,
+                _ => i32::MAX,
+            };
+        }
+        if self.negated {
+            return 1;
+        }
+        match &self.expression {
+            Expression::FieldName(field_name) => unlock!(plan).distinct_values(field_name),
+            _ => 1,
+        }
+    }
+
+    pub fn applies_to(&self, schema: Arc<Schema>) -> bool {
+        self.expression.applies_to(schema)
+    }
+}
+
+impl Display for NullTerm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.negated {
+            true => write!(f, "{} IS NOT NULL", self.expression),
+            false => write!(f, "{} IS NULL", self.expression),
+        }
+    }
+}
@@ -1,36 +1,161 @@
-use super::{constant::Constant, scan::ArcScan, term::Term};
-use crate::{plan::ArcPlan, record::schema::Schema};
+use super::{
+    bool_term::BoolTerm, constant::Constant, expression::Expression, in_term::InTerm,
+    null_term::NullTerm, scan::ArcScan, term::Term, truth::Truth,
+};
+use crate::{
+    plan::ArcPlan,
+    record::{collation::Collation, schema::Schema},
+};
 use anyhow::Result;
-use std::{fmt::Display, sync::Arc};
+use std::{collections::HashMap, fmt::Display, sync::Arc};
 
+/// NormalizedPredicate は Predicate::normalize の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NormalizedPredicate {
+    /// どのレコードでも満たされない述語
+    Unsatisfiable,
+    /// 常に真になる項を取り除いた述語
+    Satisfiable(Predicate),
+}
+
+/// Predicate は項の AND
+/// 等しさの項（Term）と、行の値がいずれかの行と等しいかを表す項（InTerm）、
+/// 値が NULL かを表す項（NullTerm）、OR や NOT でつないだ述語の項（BoolTerm）を分けて持つ
+/// 索引やフィールドの等しさを使うのは Term だけで、BoolTerm はレコードごとに評価する
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Predicate {
     terms: Vec<Term>,
+    in_terms: Vec<InTerm>,
+    null_terms: Vec<NullTerm>,
+    bool_terms: Vec<BoolTerm>,
 }
 
 impl Predicate {
     pub fn new(term: Term) -> Self {
-        Self { terms: vec![term] }
+        Self {
+            terms: vec![term],
+            ..Default::default()
+        }
+    }
+
+    pub fn from_in_term(in_term: InTerm) -> Self {
+        Self {
+            in_terms: vec![in_term],
+            ..Default::default()
+        }
+    }
+
+    pub fn from_null_term(null_term: NullTerm) -> Self {
+        Self {
+            null_terms: vec![null_term],
+            ..Default::default()
+        }
+    }
+
+    pub fn from_bool_term(bool_term: BoolTerm) -> Self {
+        Self {
+            bool_terms: vec![bool_term],
+            ..Default::default()
+        }
+    }
+
+    /// or は述語の OR を返す。述語が1つだけならその述語を返す
+    pub fn or(mut preds: Vec<Predicate>) -> Self {
+        if preds.len() == 1 {
+            return preds.remove(0);
+        }
+        Self::from_bool_term(BoolTerm::Or(preds))
+    }
+
+    /// negate は述語の NOT を返す
+    pub fn negate(pred: Predicate) -> Self {
+        Self::from_bool_term(BoolTerm::Not(Box::new(pred)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+            && self.in_terms.is_empty()
+            && self.null_terms.is_empty()
+            && self.bool_terms.is_empty()
+    }
+
+    /// collate は項がスキーマにあるフィールドの照合順序で比べるように設定する
+    pub fn collate(&mut self, schema: &Schema) {
+        for term in &mut self.terms {
+            term.collate(schema);
+        }
+        for in_term in &mut self.in_terms {
+            in_term.collate(schema);
+        }
+        for bool_term in &mut self.bool_terms {
+            bool_term.collate(schema);
+        }
     }
 
     pub fn con_join_with(&mut self, pred: &Self) {
         self.terms.extend(pred.terms.clone());
+        self.in_terms.extend(pred.in_terms.clone());
+        self.null_terms.extend(pred.null_terms.clone());
+        self.bool_terms.extend(pred.bool_terms.clone());
     }
 
-    pub fn is_satisfied(&mut self, scan: ArcScan) -> Result<bool> {
+    /// evaluate はすべての項の AND を三値論理で評価する
+    /// False の項があればそれ以降の項は評価しない
+    pub fn evaluate(&self, scan: ArcScan) -> Result<Truth> {
+        let mut result = Truth::True;
         for term in self.terms.iter() {
-            if !term.is_satisfied(scan.clone())? {
-                return Ok(false);
+            result = result.and(term.evaluate(scan.clone())?);
+            if result == Truth::False {
+                return Ok(result);
             }
         }
-        Ok(true)
+        for in_term in self.in_terms.iter() {
+            result = result.and(in_term.evaluate(scan.clone())?);
+            if result == Truth::False {
+                return Ok(result);
+            }
+        }
+        for null_term in self.null_terms.iter() {
+            result = result.and(null_term.evaluate(scan.clone())?);
+            if result == Truth::False {
+                return Ok(result);
+            }
+        }
+        for bool_term in self.bool_terms.iter() {
+            result = result.and(bool_term.evaluate(scan.clone())?);
+            if result == Truth::False {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// is_satisfied は述語が True になる場合だけ true を返す。Unknown は満たされないものとして扱う
+    pub fn is_satisfied(&mut self, scan: ArcScan) -> Result<bool> {
+        Ok(self.evaluate(scan)? == Truth::True)
     }
 
     pub fn reduction_factor(&self, plan: ArcPlan) -> i32 {
         self.terms
             .iter()
             .map(|term| term.reduction_factor(plan.clone()))
-            .sum()
+            .chain(
+                self.in_terms
+                    .iter()
+                    .map(|in_term| in_term.reduction_factor(plan.clone())),
+            )
+            .chain(
+                self.null_terms
+                    .iter()
+                    .map(|null_term| null_term.reduction_factor(plan.clone())),
+            )
+            .chain(
+                self.bool_terms
+                    .iter()
+                    .map(|bool_term| bool_term.reduction_factor(plan.clone())),
+            )
+            .fold(0, i32::saturating_add)
+            .max(1)
     }
 
     pub fn select_sub_pred(&self, schema: Arc<Schema>) -> Option<Predicate> {
@@ -40,32 +165,106 @@ impl Predicate {
             .filter(|term| term.applies_to(schema.clone()))
             .cloned()
             .collect();
+        let in_terms: Vec<InTerm> = self
+            .in_terms
+            .iter()
+            .filter(|in_term| in_term.applies_to(schema.clone()))
+            .cloned()
+            .collect();
+        let null_terms: Vec<NullTerm> = self
+            .null_terms
+            .iter()
+            .filter(|null_term| null_term.applies_to(schema.clone()))
+            .cloned()
+            .collect();
+        let bool_terms: Vec<BoolTerm> = self
+            .bool_terms
+            .iter()
+            .filter(|bool_term| bool_term.applies_to(schema.clone()))
+            .cloned()
+            .collect();
 
-        if terms.is_empty() {
-            None
-        } else {
-            Some(Predicate { terms })
-        }
+        let pred = Predicate {
+            terms,
+            in_terms,
+            null_terms,
+            bool_terms,
+        };
+        Some(pred).filter(|pred| !pred.is_empty())
     }
 
-    pub fn join_sub_pred(&self, schema1: Arc<Schema>, schema2: Arc<Schema>) -> Result<Predicate> {
+    /// join_sub_pred は 2つのスキーマのどちらか一方だけでは評価できず、合わせると評価できる項を返す
+    /// 直積の入力にまたがる結合の条件になる項で、なければ None を返す
+    pub fn join_sub_pred(
+        &self,
+        schema1: Arc<Schema>,
+        schema2: Arc<Schema>,
+    ) -> Result<Option<Predicate>> {
         let mut schema = Schema::default();
-        schema.add_all(schema1.clone())?;
-        schema.add_all(schema2.clone())?;
+        schema.add_all(&schema1)?;
+        schema.add_all(&schema2)?;
         let schema = Arc::new(schema);
 
         let terms: Vec<Term> = self
             .terms
             .iter()
             .filter(|term| {
-                !term.applies_to(schema1.clone())
+                term.applies_to(schema.clone())
+                    && !term.applies_to(schema1.clone())
                     && !term.applies_to(schema2.clone())
-                    && term.applies_to(schema.clone())
+            })
+            .cloned()
+            .collect();
+        let in_terms: Vec<InTerm> = self
+            .in_terms
+            .iter()
+            .filter(|in_term| {
+                in_term.applies_to(schema.clone())
+                    && !in_term.applies_to(schema1.clone())
+                    && !in_term.applies_to(schema2.clone())
+            })
+            .cloned()
+            .collect();
+        let null_terms: Vec<NullTerm> = self
+            .null_terms
+            .iter()
+            .filter(|null_term| {
+                null_term.applies_to(schema.clone())
+                    && !null_term.applies_to(schema1.clone())
+                    && !null_term.applies_to(schema2.clone())
+            })
+            .cloned()
+            .collect();
+        let bool_terms: Vec<BoolTerm> = self
+            .bool_terms
+            .iter()
+            .filter(|bool_term| {
+                bool_term.applies_to(schema.clone())
+                    && !bool_term.applies_to(schema1.clone())
+                    && !bool_term.applies_to(schema2.clone())
             })
             .cloned()
             .collect();
 
-        Ok(Self { terms })
+        let pred = Predicate {
+            terms,
+            in_terms,
+            null_terms,
+            bool_terms,
+        };
+        Ok(Some(pred).filter(|pred| !pred.is_empty()))
+    }
+
+    /// remove は sub_pred の項を取り除く
+    /// プランの途中で評価するようにした項を、最後に評価する述語から外すのに使う
+    pub fn remove(&mut self, sub_pred: &Predicate) {
+        self.terms.retain(|term| !sub_pred.terms.contains(term));
+        self.in_terms
+            .retain(|in_term| !sub_pred.in_terms.contains(in_term));
+        self.null_terms
+            .retain(|null_term| !sub_pred.null_terms.contains(null_term));
+        self.bool_terms
+            .retain(|bool_term| !sub_pred.bool_terms.contains(bool_term));
     }
 
     pub fn equates_with_constant(&self, field_name: &str) -> Option<Constant> {
@@ -77,6 +276,167 @@ impl Predicate {
         None
     }
 
+    /// normalize は述語を簡単にする
+    ///
+    /// - `1 = 1` のように常に真になる項と、重複した項を取り除く
+    /// - `1 = 2` のように定数だけで偽になる項や、`A = NULL` のように常に Unknown になる項があれば
+    ///   Unsatisfiable を返す
+    /// - `A = A` は A が NULL のときに Unknown になるので取り除かない
+    /// - `A = 1 AND A = 2` や `A = B AND A = 1 AND B = 2` のように、
+    ///   等しいフィールドに異なる定数が求められている場合も Unsatisfiable を返す
+    /// - 等しさ以外の比較と、関数や計算を含む項は重複を取り除くだけでそのまま残す
+    /// - IN の項からは True になりえない行と重複した行を取り除き、行が残らなければ Unsatisfiable を返す
+    ///   行が1つだけ残った場合は列ごとの等しさの項に書き換える
+    /// - `NULL IS NULL` のように定数だけで真になる NULL の項は取り除き、偽になる場合は Unsatisfiable を返す
+    /// - `A IS NULL` と、`A IS NOT NULL` や A を比べる項のように A が NULL でないときだけ真になる項が
+    ///   両方ある場合も Unsatisfiable を返す
+    /// - OR の項は BoolTerm::normalize で簡単にし、述語が1つだけ残った場合はその項をこの述語に加える
+    pub fn normalize(&self) -> NormalizedPredicate {
+        let mut candidates = self.terms.clone();
+        let mut candidate_in_terms = self.in_terms.clone();
+        let mut candidate_null_terms = self.null_terms.clone();
+        let mut bool_terms: Vec<BoolTerm> = vec![];
+        for bool_term in &self.bool_terms {
+            let NormalizedPredicate::Satisfiable(pred) = bool_term.normalize() else {
+                return NormalizedPredicate::Unsatisfiable;
+            };
+            candidates.extend(pred.terms);
+            candidate_in_terms.extend(pred.in_terms);
+            candidate_null_terms.extend(pred.null_terms);
+            for bool_term in pred.bool_terms {
+                if !bool_terms.contains(&bool_term) {
+                    bool_terms.push(bool_term);
+                }
+            }
+        }
+        let mut in_terms: Vec<InTerm> = vec![];
+        for in_term in &candidate_in_terms {
+            let mut rows: Vec<Vec<Constant>> = vec![];
+            for row in in_term.rows() {
+                let possible = in_term.lhs().iter().zip(row).all(|(lhs, value)| match lhs {
+                    Expression::Value(l) => Truth::compare(l, value) == Truth::True,
+                    _ => !value.is_null(),
+                });
+                if possible && !rows.contains(row) {
+                    rows.push(row.clone());
+                }
+            }
+            match rows.as_slice() {
+                [] => return NormalizedPredicate::Unsatisfiable,
+                [row] => candidates.extend(
+                    in_term
+                        .lhs()
+                        .iter()
+                        .zip(row)
+                        .map(|(lhs, value)| Term::new(lhs.clone(), value.clone().into())),
+                ),
+                _ => {
+                    let in_term = InTerm::new(in_term.lhs().to_vec(), rows)
+                        .expect("rows have the same size as lhs");
+                    if !in_terms.contains(&in_term) {
+                        in_terms.push(in_term);
+                    }
+                }
+            }
+        }
+
+        let mut fields = FieldClasses::default();
+        let mut terms: Vec<Term> = vec![];
+        for term in &candidates {
+            let satisfiable = match (term.lhs(), term.rhs()) {
+                // 等しさ以外の比較と、関数や計算を含む項はそのまま残す
+                _ if !term.is_equality() => true,
+                (Expression::Value(l), Expression::Value(r)) => {
+                    if Truth::compare(l, r) != Truth::True {
+                        return NormalizedPredicate::Unsatisfiable;
+                    }
+                    continue;
+                }
+                (Expression::FieldName(_), Expression::Value(value))
+                | (Expression::Value(value), Expression::FieldName(_))
+                    if value.is_null() =>
+                {
+                    return NormalizedPredicate::Unsatisfiable;
+                }
+                (Expression::FieldName(l), Expression::FieldName(r)) if l == r => true,
+                (Expression::FieldName(l), Expression::FieldName(r)) => fields.union(l, r),
+                (Expression::FieldName(field), Expression::Value(value))
+                | (Expression::Value(value), Expression::FieldName(field)) => {
+                    fields.bind(field, value)
+                }
+                _ => true,
+            };
+            if !satisfiable {
+                return NormalizedPredicate::Unsatisfiable;
+            }
+            if !terms.contains(term) && !terms.contains(&term.reversed()) {
+                terms.push(term.clone());
+            }
+        }
+
+        let mut null_terms: Vec<NullTerm> = vec![];
+        for null_term in &candidate_null_terms {
+            if let Expression::Value(value) = null_term.expression() {
+                if null_term.holds(value) != Truth::True {
+                    return NormalizedPredicate::Unsatisfiable;
+                }
+                continue;
+            }
+            if !null_terms.contains(null_term) {
+                null_terms.push(null_term.clone());
+            }
+        }
+        let non_null_fields: Vec<String> = terms
+            .iter()
+            .flat_map(|term| [term.lhs(), term.rhs()])
+            .chain(
+                null_terms
+                    .iter()
+                    .filter(|null_term| null_term.is_negated())
+                    .map(NullTerm::expression),
+            )
+            .filter_map(Expression::field_name)
+            .collect();
+        let contradicts = null_terms.iter().any(|null_term| {
+            !null_term.is_negated()
+                && null_term
+                    .expression()
+                    .field_name()
+                    .is_some_and(|field_name| non_null_fields.contains(&field_name))
+        });
+        if contradicts {
+            return NormalizedPredicate::Unsatisfiable;
+        }
+
+        NormalizedPredicate::Satisfiable(Self {
+            terms,
+            in_terms,
+            null_terms,
+            bool_terms,
+        })
+    }
+
+    /// field_names は述語が参照するフィールドの名前を、最初に現れた順に重複なく返す
+    pub fn field_names(&self) -> Vec<String> {
+        let expressions = self
+            .terms
+            .iter()
+            .flat_map(|term| [term.lhs(), term.rhs()])
+            .chain(self.in_terms.iter().flat_map(|in_term| in_term.lhs()))
+            .chain(self.null_terms.iter().map(NullTerm::expression));
+        let bool_fields = self.bool_terms.iter().flat_map(BoolTerm::field_names);
+        let mut field_names: Vec<String> = vec![];
+        for field_name in expressions
+            .flat_map(Expression::field_names)
+            .chain(bool_fields)
+        {
+            if !field_names.contains(&field_name) {
+                field_names.push(field_name);
+            }
+        }
+        field_names
+    }
+
     pub fn equates_with_field(&self, field_name: &str) -> Option<String> {
         for term in self.terms.iter() {
             if let Some(name) = term.equates_with_field(field_name) {
@@ -87,14 +447,412 @@ impl Predicate {
     }
 }
 
+/// FieldClasses は等しいことがわかっているフィールドの集合（union-find）と、
+/// その集合が等しくなければならない定数を管理する
+#[derive(Default)]
+struct FieldClasses {
+    parents: HashMap<String, String>,
+    values: HashMap<String, Constant>,
+}
+
+impl FieldClasses {
+    fn find(&mut self, field: &str) -> String {
+        let parent = self
+            .parents
+            .entry(field.to_string())
+            .or_insert_with(|| field.to_string())
+            .clone();
+        if parent == field {
+            return parent;
+        }
+        let root = self.find(&parent);
+        self.parents.insert(field.to_string(), root.clone());
+        root
+    }
+
+    /// bind はフィールドの集合が定数と等しいことを記録する。矛盾する場合は false を返す
+    /// フィールドの照合順序はまだ分からないので、いずれかの照合順序で等しくなる定数は矛盾とみなさない
+    fn bind(&mut self, field: &str, value: &Constant) -> bool {
+        let root = self.find(field);
+        match self.values.get(&root) {
+            Some(bound) => Collation::may_equal(bound, value),
+            None => {
+                self.values.insert(root, value.clone());
+                true
+            }
+        }
+    }
+
+    /// union は2つのフィールドの集合をまとめる。それぞれ異なる定数と等しい場合は false を返す
+    fn union(&mut self, field1: &str, field2: &str) -> bool {
+        let (root1, root2) = (self.find(field1), self.find(field2));
+        if root1 == root2 {
+            return true;
+        }
+        self.parents.insert(root2.clone(), root1.clone());
+        match self.values.remove(&root2) {
+            Some(value) => self.bind(&root1, &value),
+            None => true,
+        }
+    }
+}
+
 impl Display for Predicate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut terms = self.terms.iter();
-        if let Some(term) = terms.next() {
-            write!(f, "{}", term)?;
-            for term in terms {
-                write!(f, " AND {}", term)?;
-            }
+        let terms = self.terms.iter().map(|term| term.to_string());
+        let in_terms = self.in_terms.iter().map(|in_term| in_term.to_string());
+        let null_terms = self
+            .null_terms
+            .iter()
+            .map(|null_term| null_term.to_string());
+        let bool_terms = self
+            .bool_terms
+            .iter()
+            .map(|bool_term| bool_term.to_string());
+        let terms: Vec<String> = terms
+            .chain(in_terms)
+            .chain(null_terms)
+            .chain(bool_terms)
+            .collect();
+        write!(f, "{}", terms.join(" AND "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::empty_scan::EmptyScan;
+    use std::sync::Mutex;
+
+    fn term(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Term {
+        Term::new(lhs.into(), rhs.into())
+    }
+
+    fn pred(terms: Vec<Term>) -> Predicate {
+        Predicate {
+            terms,
+            ..Default::default()
+        }
+    }
+
+    fn field(name: &str) -> Expression {
+        Expression::FieldName(name.into())
+    }
+
+    #[test]
+    fn should_remove_tautologies() {
+        let one = Constant::Int(1);
+        let p = pred(vec![
+            term(one.clone(), one.clone()),
+            term(field("A"), one.clone()),
+            term(field("B"), field("B")),
+            term(one.clone(), field("A")),
+        ]);
+        assert_eq!(
+            p.normalize(),
+            NormalizedPredicate::Satisfiable(pred(vec![
+                term(field("A"), one),
+                term(field("B"), field("B")),
+            ]))
+        );
+        assert_eq!(
+            Predicate::default().normalize(),
+            NormalizedPredicate::Satisfiable(Predicate::default())
+        );
+    }
+
+    #[test]
+    fn should_split_terms_across_join_inputs() -> Result<()> {
+        let mut schema1 = Schema::default();
+        schema1.add_int_field("A");
+        schema1.add_int_field("B");
+        let mut schema2 = Schema::default();
+        schema2.add_int_field("C");
+        let (schema1, schema2) = (Arc::new(schema1), Arc::new(schema2));
+        let one = Constant::Int(1);
+        let mut p = pred(vec![
+            term(field("A"), one.clone()),
+            term(field("A"), field("B")),
+            term(field("B"), field("C")),
+            term(field("C"), field("D")),
+        ]);
+
+        let select = p.select_sub_pred(schema1.clone());
+        assert_eq!(
+            select,
+            Some(pred(vec![
+                term(field("A"), one.clone()),
+                term(field("A"), field("B")),
+            ]))
+        );
+        // 一方の入力だけで評価できる項と、どちらの入力にもないフィールドの項は結合の条件にしない
+        let join = p.join_sub_pred(schema1.clone(), schema2.clone())?;
+        assert_eq!(join, Some(pred(vec![term(field("B"), field("C"))])));
+        assert_eq!(p.join_sub_pred(schema2.clone(), schema1.clone())?, join);
+
+        p.remove(&select.unwrap());
+        p.remove(&join.unwrap());
+        assert_eq!(p, pred(vec![term(field("C"), field("D"))]));
+        assert_eq!(p.join_sub_pred(schema1, schema2)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn should_detect_contradictions() {
+        let (one, two) = (Constant::Int(1), Constant::Int(2));
+        let contradictions = [
+            pred(vec![term(one.clone(), two.clone())]),
+            pred(vec![
+                term(field("A"), one.clone()),
+                term(field("A"), two.clone()),
+            ]),
+            pred(vec![
+                term(field("A"), one.clone()),
+                term(field("B"), two.clone()),
+                term(field("B"), field("A")),
+            ]),
+            pred(vec![
+                term(field("A"), field("B")),
+                term(field("B"), field("C")),
+                term(field("A"), one.clone()),
+                term(two.clone(), field("C")),
+            ]),
+            pred(vec![term(Constant::Null, Constant::Null)]),
+            pred(vec![term(field("A"), Constant::Null)]),
+        ];
+        for p in contradictions {
+            assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable, "{}", p);
+        }
+
+        let p = pred(vec![
+            term(field("A"), field("B")),
+            term(field("A"), one.clone()),
+            term(field("B"), one),
+        ]);
+        assert_eq!(p.normalize(), NormalizedPredicate::Satisfiable(p));
+    }
+
+    #[test]
+    fn should_normalize_in_terms() -> Result<()> {
+        let (one, two, null) = (Constant::Int(1), Constant::Int(2), Constant::Null);
+        let in_pred = |lhs: Vec<Expression>, rows: Vec<Vec<Constant>>| -> Result<Predicate> {
+            Ok(Predicate::from_in_term(InTerm::new(lhs, rows)?))
+        };
+
+        // True になりえない行を取り除いて1行だけ残れば、等しさの項に書き換える
+        let p = in_pred(
+            vec![field("A"), one.clone().into()],
+            vec![
+                vec![one.clone(), two.clone()],
+                vec![null.clone(), one.clone()],
+                vec![two.clone(), one.clone()],
+            ],
+        )?;
+        assert_eq!(
+            p.normalize(),
+            NormalizedPredicate::Satisfiable(pred(vec![term(field("A"), two.clone())]))
+        );
+
+        let p = in_pred(
+            vec![field("A")],
+            vec![vec![one.clone()], vec![two.clone()], vec![one.clone()]],
+        )?;
+        assert_eq!(
+            p.normalize(),
+            NormalizedPredicate::Satisfiable(in_pred(
+                vec![field("A")],
+                vec![vec![one.clone()], vec![two.clone()]]
+            )?)
+        );
+
+        let p = in_pred(vec![field("A")], vec![vec![null.clone()]])?;
+        assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable);
+
+        // 書き換えた等しさの項もほかの項と矛盾すれば Unsatisfiable になる
+        let mut p = in_pred(vec![field("A")], vec![vec![one.clone()]])?;
+        p.con_join_with(&pred(vec![term(field("A"), two)]));
+        assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable);
+
+        assert!(InTerm::new(vec![field("A")], vec![]).is_err());
+        assert!(InTerm::new(vec![field("A")], vec![vec![one, null]]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_evaluate_in_terms_with_three_valued_logic() -> Result<()> {
+        let scan: ArcScan = Arc::new(Mutex::new(EmptyScan::new(Arc::new(Schema::default()))));
+        let (one, two, null) = (Constant::Int(1), Constant::Int(2), Constant::Null);
+        let lhs: Vec<Expression> = vec![one.clone().into(), two.clone().into()];
+        let table = [
+            (vec![vec![one.clone(), two.clone()]], Truth::True),
+            (vec![vec![two.clone(), two.clone()]], Truth::False),
+            (vec![vec![null.clone(), two.clone()]], Truth::Unknown),
+            (vec![vec![null.clone(), one.clone()]], Truth::False),
+            (
+                vec![vec![null.clone(), two.clone()], vec![one.clone(), two]],
+                Truth::True,
+            ),
+        ];
+        for (rows, expected) in table {
+            let in_term = InTerm::new(lhs.clone(), rows)?;
+            assert_eq!(in_term.evaluate(scan.clone())?, expected, "{}", in_term);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn should_evaluate_and_normalize_null_terms() -> Result<()> {
+        let scan: ArcScan = Arc::new(Mutex::new(EmptyScan::new(Arc::new(Schema::default()))));
+        let is_null = |expression: Expression| NullTerm::new(expression, false);
+        let is_not_null = |expression: Expression| NullTerm::new(expression, true);
+
+        // 比較と違って NULL でも Unknown にならない
+        let table = [
+            (is_null(Constant::Null.into()), Truth::True),
+            (is_null(Constant::Int(1).into()), Truth::False),
+            (is_not_null(Constant::Null.into()), Truth::False),
+            (is_not_null(Constant::Int(1).into()), Truth::True),
+        ];
+        for (null_term, expected) in table {
+            assert_eq!(null_term.evaluate(scan.clone())?, expected, "{}", null_term);
+        }
+
+        let with_null_term = |terms: Vec<Term>, null_term: NullTerm| {
+            let mut p = pred(terms);
+            p.con_join_with(&Predicate::from_null_term(null_term));
+            p
+        };
+        let contradictions = [
+            with_null_term(vec![], is_null(Constant::Int(1).into())),
+            with_null_term(vec![], is_not_null(Constant::Null.into())),
+            with_null_term(
+                vec![term(field("A"), Constant::Int(1))],
+                is_null(field("A")),
+            ),
+            with_null_term(vec![term(field("B"), field("A"))], is_null(field("A"))),
+        ];
+        for p in contradictions {
+            assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable, "{}", p);
+        }
+        let mut p = Predicate::from_null_term(is_null(field("A")));
+        p.con_join_with(&Predicate::from_null_term(is_not_null(field("A"))));
+        assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable);
+
+        // 定数だけで真になる項と重複した項は取り除く
+        let p = with_null_term(
+            vec![term(field("B"), Constant::Int(1))],
+            is_null(field("A")),
+        );
+        let mut with_duplicates = p.clone();
+        with_duplicates.con_join_with(&Predicate::from_null_term(is_null(field("A"))));
+        with_duplicates.con_join_with(&Predicate::from_null_term(is_null(Constant::Null.into())));
+        assert_eq!(
+            with_duplicates.normalize(),
+            NormalizedPredicate::Satisfiable(p.clone())
+        );
+        assert_eq!(p.to_string(), "B = 1 AND A IS NULL");
+        assert_eq!(p.field_names(), vec!["B", "A"]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_evaluate_or_and_not_with_three_valued_logic() -> Result<()> {
+        let scan: ArcScan = Arc::new(Mutex::new(EmptyScan::new(Arc::new(Schema::default()))));
+        let (one, two, null) = (Constant::Int(1), Constant::Int(2), Constant::Null);
+        let t = pred(vec![term(one.clone(), one.clone())]);
+        let f = pred(vec![term(one.clone(), two.clone())]);
+        let u = pred(vec![term(one.clone(), null.clone())]);
+
+        let table = [
+            (Predicate::or(vec![f.clone(), t.clone()]), Truth::True),
+            (Predicate::or(vec![f.clone(), f.clone()]), Truth::False),
+            (Predicate::or(vec![u.clone(), f.clone()]), Truth::Unknown),
+            (Predicate::or(vec![u.clone(), t.clone()]), Truth::True),
+            (Predicate::negate(t.clone()), Truth::False),
+            (Predicate::negate(f.clone()), Truth::True),
+            (Predicate::negate(u.clone()), Truth::Unknown),
+            (
+                Predicate::negate(Predicate::or(vec![u.clone(), f.clone()])),
+                Truth::Unknown,
+            ),
+        ];
+        for (mut p, expected) in table {
+            assert_eq!(p.evaluate(scan.clone())?, expected, "{}", p);
+            assert_eq!(p.is_satisfied(scan.clone())?, expected == Truth::True);
+        }
+
+        // OR の項もほかの項と AND でつなぐ
+        let mut p = Predicate::or(vec![f.clone(), t.clone()]);
+        p.con_join_with(&u);
+        assert_eq!(p.evaluate(scan.clone())?, Truth::Unknown);
+        p.con_join_with(&f);
+        assert_eq!(p.evaluate(scan)?, Truth::False);
+        Ok(())
+    }
+
+    #[test]
+    fn should_normalize_or_terms() {
+        let (one, two) = (Constant::Int(1), Constant::Int(2));
+        let a1 = pred(vec![term(field("A"), one.clone())]);
+        let b2 = pred(vec![term(field("B"), two.clone())]);
+        let never = pred(vec![term(one.clone(), two.clone())]);
+        let always = pred(vec![term(one.clone(), one.clone())]);
+
+        // True になりえない述語を取り除き、1つだけ残ればその項をほかの項と同じように扱う
+        let p = Predicate::or(vec![never.clone(), a1.clone(), never.clone()]);
+        assert_eq!(p.normalize(), NormalizedPredicate::Satisfiable(a1.clone()));
+        let mut p = Predicate::or(vec![a1.clone(), never.clone()]);
+        p.con_join_with(&pred(vec![term(field("A"), two.clone())]));
+        assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable);
+
+        let p = Predicate::or(vec![a1.clone(), b2.clone(), a1.clone()]);
+        assert_eq!(
+            p.normalize(),
+            NormalizedPredicate::Satisfiable(Predicate::or(vec![a1.clone(), b2.clone()]))
+        );
+        let p = Predicate::or(vec![a1.clone(), always]);
+        assert_eq!(
+            p.normalize(),
+            NormalizedPredicate::Satisfiable(Predicate::default())
+        );
+        let p = Predicate::or(vec![never.clone(), never.clone()]);
+        assert_eq!(p.normalize(), NormalizedPredicate::Unsatisfiable);
+
+        // NOT の中は Unknown と False を区別するので簡単にしない
+        let p = Predicate::negate(Predicate::or(vec![never, b2]));
+        assert_eq!(p.normalize(), NormalizedPredicate::Satisfiable(p.clone()));
+        assert_eq!(p.field_names(), vec!["B"]);
+    }
+
+    #[test]
+    fn should_evaluate_with_three_valued_logic() -> Result<()> {
+        let scan: ArcScan = Arc::new(Mutex::new(EmptyScan::new(Arc::new(Schema::default()))));
+        let (one, two, null) = (Constant::Int(1), Constant::Int(2), Constant::Null);
+        let t = term(one.clone(), one.clone());
+        let f = term(one.clone(), two.clone());
+        let u = term(one.clone(), null.clone());
+
+        assert_eq!(t.evaluate(scan.clone())?, Truth::True);
+        assert_eq!(f.evaluate(scan.clone())?, Truth::False);
+        assert_eq!(u.evaluate(scan.clone())?, Truth::Unknown);
+        assert_eq!(
+            term(null.clone(), null).evaluate(scan.clone())?,
+            Truth::Unknown
+        );
+        assert!(!u.is_satisfied(scan.clone())?);
+
+        let table = [
+            (vec![], Truth::True),
+            (vec![t.clone(), t.clone()], Truth::True),
+            (vec![t.clone(), f.clone()], Truth::False),
+            (vec![t.clone(), u.clone()], Truth::Unknown),
+            (vec![u.clone(), f.clone()], Truth::False),
+            (vec![u.clone(), u.clone()], Truth::Unknown),
+        ];
+        for (terms, expected) in table {
+            let mut p = pred(terms);
+            assert_eq!(p.evaluate(scan.clone())?, expected, "{}", p);
+            assert_eq!(p.is_satisfied(scan.clone())?, expected == Truth::True);
         }
         Ok(())
     }
@@ -1,5 +1,5 @@
 use super::scan::{ArcScan, Scan};
-use crate::unlock;
+use crate::{file::date::Date, record::overflow::BlobReader, unlock};
 use anyhow::Result;
 
 pub struct ProductScan {
@@ -34,8 +34,23 @@ impl Scan for ProductScan {
         }
     }
 
+    fn after_last(&mut self) {
+        unlock!(self.scan1).after_last();
+        let _ = unlock!(self.scan1).previous();
+        unlock!(self.scan2).after_last();
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        if unlock!(self.scan2).previous()? {
+            Ok(true)
+        } else {
+            unlock!(self.scan2).after_last();
+            Ok(unlock!(self.scan2).previous()? && unlock!(self.scan1).previous()?)
+        }
+    }
+
     fn get_int(&mut self, field_name: &str) -> Result<i32> {
-        if self.has_field(field_name) {
+        if unlock!(self.scan1).has_field(field_name) {
             unlock!(self.scan1).get_int(field_name)
         } else {
             unlock!(self.scan2).get_int(field_name)
@@ -43,7 +58,7 @@ impl Scan for ProductScan {
     }
 
     fn get_string(&mut self, field_name: &str) -> Result<String> {
-        if self.has_field(field_name) {
+        if unlock!(self.scan1).has_field(field_name) {
             unlock!(self.scan1).get_string(field_name)
         } else {
             unlock!(self.scan2).get_string(field_name)
@@ -51,13 +66,53 @@ impl Scan for ProductScan {
     }
 
     fn get_value(&mut self, fieldname: &str) -> Result<super::constant::Constant> {
-        if self.has_field(fieldname) {
+        if unlock!(self.scan1).has_field(fieldname) {
             unlock!(self.scan1).get_value(fieldname)
         } else {
             unlock!(self.scan2).get_value(fieldname)
         }
     }
 
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        if unlock!(self.scan1).has_field(field_name) {
+            unlock!(self.scan1).get_blob_reader(field_name)
+        } else {
+            unlock!(self.scan2).get_blob_reader(field_name)
+        }
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        if unlock!(self.scan1).has_field(field_name) {
+            unlock!(self.scan1).get_long(field_name)
+        } else {
+            unlock!(self.scan2).get_long(field_name)
+        }
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        if unlock!(self.scan1).has_field(field_name) {
+            unlock!(self.scan1).get_double(field_name)
+        } else {
+            unlock!(self.scan2).get_double(field_name)
+        }
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        if unlock!(self.scan1).has_field(field_name) {
+            unlock!(self.scan1).get_bool(field_name)
+        } else {
+            unlock!(self.scan2).get_bool(field_name)
+        }
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        if unlock!(self.scan1).has_field(field_name) {
+            unlock!(self.scan1).get_date(field_name)
+        } else {
+            unlock!(self.scan2).get_date(field_name)
+        }
+    }
+
     fn has_field(&self, field_name: &str) -> bool {
         unlock!(self.scan1).has_field(field_name) || unlock!(self.scan2).has_field(field_name)
     }
@@ -1,18 +1,47 @@
 use super::{
     constant::Constant,
+    expression::Expression,
+    query_data::split_qualified,
     scan::{ArcScan, Scan},
 };
-use crate::unlock;
+use crate::{file::date::Date, record::overflow::BlobReader, unlock};
 use anyhow::{bail, Result};
 
 pub struct ProjectScan {
     scan: ArcScan,
     fields: Vec<String>,
+    /// expressions は値を式で求めるフィールドと、その式
+    expressions: Vec<(String, Expression)>,
 }
 
 impl ProjectScan {
-    pub fn new(scan: ArcScan, fields: Vec<String>) -> ProjectScan {
-        ProjectScan { scan, fields }
+    pub fn new(
+        scan: ArcScan,
+        fields: Vec<String>,
+        expressions: Vec<(String, Expression)>,
+    ) -> ProjectScan {
+        ProjectScan {
+            scan,
+            fields,
+            expressions,
+        }
+    }
+
+    /// computed は式で求めるフィールドであれば、現在のレコードで式を評価した値を返す
+    fn computed(&self, field_name: &str) -> Result<Option<Constant>> {
+        match self.expressions.iter().find(|(name, _)| name == field_name) {
+            Some((_, expression)) => Ok(Some(expression.evaluate(self.scan.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// source は出すフィールドの値を読む元のスキャンのフィールド名を返す
+    /// `<table>.<field>` と書いたフィールドは <field> から読む
+    fn source<'a>(&self, field_name: &'a str) -> Result<&'a str> {
+        if !self.has_field(field_name) {
+            bail!("field not found: {}", field_name);
+        }
+        Ok(split_qualified(field_name).1)
     }
 }
 
@@ -28,28 +57,85 @@ impl Scan for ProjectScan {
         unlock!(self.scan).next()
     }
 
+    fn after_last(&mut self) {
+        unlock!(self.scan).after_last();
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        unlock!(self.scan).previous()
+    }
+
     fn get_int(&mut self, field_name: &str) -> Result<i32> {
-        if self.has_field(field_name) {
-            unlock!(self.scan).get_int(field_name)
-        } else {
-            bail!("field not found: {}", field_name);
+        if let Some(value) = self.computed(field_name)? {
+            return match value {
+                Constant::Int(value) => Ok(value),
+                _ => bail!("field is not an int: {}", field_name),
+            };
         }
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_int(field_name)
     }
 
     fn get_string(&mut self, field_name: &str) -> Result<String> {
-        if self.has_field(field_name) {
-            unlock!(self.scan).get_string(field_name)
-        } else {
-            bail!("field not found: {}", field_name);
+        if let Some(value) = self.computed(field_name)? {
+            return match value {
+                Constant::String(value) => Ok(value),
+                _ => bail!("field is not a string: {}", field_name),
+            };
         }
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_string(field_name)
     }
 
     fn get_value(&mut self, fieldname: &str) -> Result<Constant> {
-        if self.has_field(fieldname) {
-            unlock!(self.scan).get_value(fieldname)
-        } else {
-            bail!("field not found: {}", fieldname);
+        if let Some(value) = self.computed(fieldname)? {
+            return Ok(value);
+        }
+        let fieldname = self.source(fieldname)?;
+        unlock!(self.scan).get_value(fieldname)
+    }
+
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        if let Some(value) = self.computed(field_name)? {
+            return match value {
+                Constant::String(value) => Ok(BlobReader::from_bytes(value.into_bytes())),
+                _ => bail!("field is not a string: {}", field_name),
+            };
+        }
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_blob_reader(field_name)
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_long(field_name)
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_double(field_name)
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        if let Some(value) = self.computed(field_name)? {
+            return match value {
+                Constant::Bool(value) => Ok(value),
+                _ => bail!("field is not boolean: {}", field_name),
+            };
+        }
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_bool(field_name)
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        if let Some(value) = self.computed(field_name)? {
+            return match value {
+                Constant::Date(value) => Ok(value),
+                _ => bail!("field is not date: {}", field_name),
+            };
         }
+        let field_name = self.source(field_name)?;
+        unlock!(self.scan).get_date(field_name)
     }
 
     fn has_field(&self, field_name: &str) -> bool {
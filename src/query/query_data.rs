@@ -1,12 +1,32 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
-use super::predicate::Predicate;
+use super::{
+    aggregation_function::AggregationFunction, expression::Expression, hint::Hint,
+    predicate::Predicate, record_comparator::SortKey, table_function::TableFunction,
+};
+use crate::record::{block_sample::BlockSample, schema::Schema};
+use anyhow::{bail, Result};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct QueryData {
     pub fields: Vec<String>,
     pub tables: Vec<String>,
+    /// table_functions は FROM にテーブルと並べて書いたテーブル関数
+    pub table_functions: Vec<TableFunction>,
+    /// samples は `<table> sample <n> percent` と書いたテーブルと、その標本
+    pub samples: HashMap<String, BlockSample>,
     pub pred: Predicate,
+    pub hints: Vec<Hint>,
+    /// group_by は GROUP BY に並べたフィールド
+    pub group_by: Vec<String>,
+    /// aggregates は SELECT に書いた集計関数。fields には集計関数の結果のフィールド名が入る
+    pub aggregates: Vec<AggregationFunction>,
+    /// expressions は SELECT に書いた `cast(<expr> as <type>)`。fields には式を書いた文字列が結果のフィールド名として入る
+    pub expressions: Vec<Expression>,
+    /// order_by は結果を並べるソートキー。空なら並べない
+    pub order_by: Vec<SortKey>,
+    /// as_of はログのこの LSN の時点のデータベースに問い合わせることを表す
+    pub as_of: Option<i32>,
 }
 
 impl QueryData {
@@ -14,14 +34,155 @@ impl QueryData {
         QueryData {
             fields,
             tables,
+            table_functions: vec![],
+            samples: HashMap::new(),
             pred,
+            hints: vec![],
+            group_by: vec![],
+            aggregates: vec![],
+            expressions: vec![],
+            order_by: vec![],
+            as_of: None,
         }
     }
+
+    /// with_table_functions は FROM に書いたテーブル関数を設定する
+    pub fn with_table_functions(mut self, table_functions: Vec<TableFunction>) -> QueryData {
+        self.table_functions = table_functions;
+        self
+    }
+
+    /// with_samples はブロックの一部だけを読むテーブルを設定する
+    pub fn with_samples(mut self, samples: HashMap<String, BlockSample>) -> QueryData {
+        self.samples = samples;
+        self
+    }
+
+    /// with_hints はオプティマイザヒントを設定する
+    pub fn with_hints(mut self, hints: Vec<Hint>) -> QueryData {
+        self.hints = hints;
+        self
+    }
+
+    /// with_aggregation は GROUP BY のフィールドと集計関数を設定する
+    pub fn with_aggregation(
+        mut self,
+        group_by: Vec<String>,
+        aggregates: Vec<AggregationFunction>,
+    ) -> QueryData {
+        self.group_by = group_by;
+        self.aggregates = aggregates;
+        self
+    }
+
+    /// with_expressions は SELECT に書いた式を設定する
+    pub fn with_expressions(mut self, expressions: Vec<Expression>) -> QueryData {
+        self.expressions = expressions;
+        self
+    }
+
+    /// is_expression は結果のフィールドが SELECT に書いた式かを返す
+    pub fn is_expression(&self, field_name: &str) -> bool {
+        self.expressions
+            .iter()
+            .any(|expression| expression.to_string() == field_name)
+    }
+
+    /// check_qualified_fields は fields のうち table_name を付けて書いたフィールドが、そのテーブルの schema にあるかを確かめる
+    /// 結合したスキーマでは名前だけでフィールドを探すので、別のテーブルのフィールドを読まないように先に確かめる
+    pub fn check_qualified_fields(
+        fields: &[String],
+        table_name: &str,
+        schema: &Schema,
+    ) -> Result<()> {
+        for field in fields {
+            if let (Some(qualifier), field_name) = split_qualified(field) {
+                if qualifier == table_name && !schema.has_field(field_name) {
+                    bail!("field not found: {}", field);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// is_aggregation は GROUP BY か集計関数のある問い合わせかを返す
+    pub fn is_aggregation(&self) -> bool {
+        !self.group_by.is_empty() || !self.aggregates.is_empty()
+    }
+
+    /// with_order_by は結果を並べるソートキーを設定する
+    pub fn with_order_by(mut self, order_by: Vec<SortKey>) -> QueryData {
+        self.order_by = order_by;
+        self
+    }
+
+    /// required_fields は結果に出すフィールドと、述語、GROUP BY、集計関数、式、ソートキーで読むフィールドを、重複を除いて返す
+    /// 集計関数と式の結果のフィールドは読むフィールドではないので含めない
+    pub fn required_fields(&self) -> Vec<String> {
+        let aggregated: Vec<String> = self
+            .aggregates
+            .iter()
+            .map(AggregationFunction::field_name)
+            .collect();
+        let mut fields: Vec<String> = vec![];
+        for field in self
+            .fields
+            .iter()
+            .filter(|field| !aggregated.contains(field) && !self.is_expression(field))
+        {
+            let field_name = split_qualified(field).1.to_string();
+            if !fields.contains(&field_name) {
+                fields.push(field_name);
+            }
+        }
+        let order_by = self
+            .order_by
+            .iter()
+            .flat_map(|key| key.expression.field_names());
+        let aggregates = self
+            .aggregates
+            .iter()
+            .filter_map(|aggregate| aggregate.field.clone());
+        let expressions = self.expressions.iter().flat_map(Expression::field_names);
+        for field_name in self
+            .pred
+            .field_names()
+            .into_iter()
+            .chain(self.group_by.iter().cloned())
+            .chain(aggregates)
+            .chain(expressions)
+            .chain(order_by)
+        {
+            if !fields.contains(&field_name) {
+                fields.push(field_name);
+            }
+        }
+        fields
+    }
+
+    /// with_as_of は問い合わせる過去の時点の LSN を設定する
+    pub fn with_as_of(mut self, as_of: Option<i32>) -> QueryData {
+        self.as_of = as_of;
+        self
+    }
+}
+
+/// split_qualified は `<table>.<field>` と書いたフィールド名をテーブル名とフィールド名に分ける
+/// テーブル名を付けていなければ、テーブル名は None になる
+pub fn split_qualified(field_name: &str) -> (Option<&str>, &str) {
+    match field_name.split_once('.') {
+        Some((table_name, field_name)) => (Some(table_name), field_name),
+        None => (None, field_name),
+    }
 }
 
 impl Display for QueryData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "SELECT ")?;
+        if !self.hints.is_empty() {
+            let hints: Vec<String> = self.hints.iter().map(Hint::to_string).collect();
+            write!(f, "/*+ {} */ ", hints.join(" "))?;
+        }
         for (i, field) in self.fields.iter().enumerate() {
             if i > 0 {
                 write!(f, ", ")?;
@@ -29,12 +190,33 @@ impl Display for QueryData {
             write!(f, "{}", field)?;
         }
         write!(f, " FROM ")?;
-        for (i, table) in self.tables.iter().enumerate() {
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| match self.samples.get(table) {
+                Some(sample) => format!("{} {}", table, sample),
+                None => table.clone(),
+            });
+        let functions = self.table_functions.iter().map(TableFunction::to_string);
+        for (i, table) in tables.chain(functions).enumerate() {
             if i > 0 {
                 write!(f, ", ")?;
             }
             write!(f, "{}", table)?;
         }
-        write!(f, " WHERE {}", self.pred)
+        if !self.pred.is_empty() {
+            write!(f, " WHERE {}", self.pred)?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY {}", self.group_by.join(", "))?;
+        }
+        if !self.order_by.is_empty() {
+            let keys: Vec<String> = self.order_by.iter().map(SortKey::to_string).collect();
+            write!(f, " ORDER BY {}", keys.join(", "))?;
+        }
+        if let Some(lsn) = self.as_of {
+            write!(f, " AS OF LSN {}", lsn)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,154 @@
+use super::{constant::Constant, expression::Expression, scan::ArcScan};
+use crate::record::schema::Schema;
+use anyhow::Result;
+use std::{cmp::Ordering, fmt::Display, sync::Arc};
+
+/// SortKey は並べ替えに使う式と向き
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub expression: Expression,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn new(expression: Expression, descending: bool) -> Self {
+        Self {
+            expression,
+            descending,
+        }
+    }
+}
+
+/// フィールド名だけを書いたキーは昇順に並べる
+impl From<&str> for SortKey {
+    fn from(field_name: &str) -> Self {
+        Self::new(Expression::FieldName(field_name.to_string()), false)
+    }
+}
+
+impl From<String> for SortKey {
+    fn from(field_name: String) -> Self {
+        Self::new(Expression::FieldName(field_name), false)
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)?;
+        if self.descending {
+            write!(f, " desc")?;
+        }
+        Ok(())
+    }
+}
+
+/// RecordComparator はキーの並びでレコードの順序を決める
+///
+/// 前のキーが等しい場合だけ次のキーで比べ、降順のキーは比べた結果を逆にする
+/// キーの値を先に取り出しておけば、スキャンを動かした後でも compare_values で比べられる
+/// 並べ替えのほか、キーの等しいレコードを突き合わせる結合や重複の除去でも同じ順序を使う
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordComparator {
+    keys: Vec<SortKey>,
+}
+
+impl RecordComparator {
+    pub fn new(keys: Vec<SortKey>) -> Self {
+        Self { keys }
+    }
+
+    pub fn keys(&self) -> &[SortKey] {
+        &self.keys
+    }
+
+    /// missing_field はキーが参照するフィールドのうち、schema にない最初のものを返す
+    pub fn missing_field(&self, schema: Arc<Schema>) -> Option<String> {
+        self.keys
+            .iter()
+            .flat_map(|key| key.expression.field_names())
+            .find(|field_name| !schema.has_field(field_name))
+    }
+
+    /// key_values はスキャンの今のレコードでキーの式を評価した値を、キーの順に返す
+    pub fn key_values(&self, scan: &ArcScan) -> Result<Vec<Constant>> {
+        self.keys
+            .iter()
+            .map(|key| key.expression.evaluate(scan.clone()))
+            .collect()
+    }
+
+    /// compare_values は key_values で取り出した2つのレコードのキーの値を比べる
+    pub fn compare_values(&self, a: &[Constant], b: &[Constant]) -> Ordering {
+        self.keys
+            .iter()
+            .zip(a.iter().zip(b))
+            .map(|(key, (a, b))| {
+                let ordering = a.cmp(b);
+                if key.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// compare は2つのスキャンの今のレコードを比べる
+    pub fn compare(&self, scan1: &ArcScan, scan2: &ArcScan) -> Result<Ordering> {
+        let a = self.key_values(scan1)?;
+        let b = self.key_values(scan2)?;
+        Ok(self.compare_values(&a, &b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        query::{expression::Operator, memory_sort_scan::MemorySortScan},
+        unlock,
+    };
+    use std::sync::Mutex;
+
+    #[test]
+    fn should_compare_by_keys_in_order() -> Result<()> {
+        let fields = vec!["A".to_string(), "B".to_string()];
+        let scan = |a, b| -> Result<ArcScan> {
+            let rows = vec![vec![Constant::Int(a), Constant::Int(b)]];
+            let scan = Arc::new(Mutex::new(MemorySortScan::new(fields.clone(), rows))) as ArcScan;
+            unlock!(scan).next()?;
+            Ok(scan)
+        };
+        let comparator = RecordComparator::new(vec![
+            SortKey::new(Expression::FieldName("A".into()), true),
+            "B".into(),
+        ]);
+        assert_eq!(
+            comparator.compare(&scan(2, 0)?, &scan(1, 9)?)?,
+            Ordering::Less
+        );
+        assert_eq!(
+            comparator.compare(&scan(1, 0)?, &scan(1, 9)?)?,
+            Ordering::Less
+        );
+        assert_eq!(
+            comparator.compare(&scan(1, 9)?, &scan(1, 9)?)?,
+            Ordering::Equal
+        );
+
+        let sum = Expression::Arithmetic(
+            Box::new(Expression::FieldName("A".into())),
+            Operator::Add,
+            Box::new(Expression::FieldName("B".into())),
+        );
+        let comparator = RecordComparator::new(vec![SortKey::new(sum, false)]);
+        assert_eq!(comparator.key_values(&scan(1, 2)?)?, vec![Constant::Int(3)]);
+        assert_eq!(
+            comparator.compare(&scan(3, 0)?, &scan(1, 1)?)?,
+            Ordering::Greater
+        );
+        assert_eq!(comparator.keys()[0].to_string(), "A + B");
+        Ok(())
+    }
+}
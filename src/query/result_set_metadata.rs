@@ -0,0 +1,111 @@
+use crate::record::schema::{FieldTypes, Schema};
+
+/// ColumnMetadata は問い合わせの結果の1列の情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMetadata {
+    pub name: String,
+    pub field_type: FieldTypes,
+    /// length は Varchar の最大の長さ。それ以外の型では 0
+    pub length: i32,
+}
+
+impl ColumnMetadata {
+    /// display_size は列の値を表示するのに必要な最大の文字数を返す
+    /// 列の名前より短くはならない。長さの上限がない Text と Blob は名前の長さを返す
+    pub fn display_size(&self) -> usize {
+        let value_size = match self.field_type {
+            // i32::MIN の桁数と符号
+            FieldTypes::Integer => 11,
+            // i64::MIN の桁数と符号
+            FieldTypes::BigInt => 20,
+            FieldTypes::Double => 24,
+            FieldTypes::Boolean => "false".len(),
+            FieldTypes::Date => "YYYY-MM-DD".len(),
+            FieldTypes::Varchar => self.length.max(0) as usize,
+            FieldTypes::Text | FieldTypes::Blob => 0,
+        };
+        value_size.max(self.name.chars().count())
+    }
+}
+
+/// ResultSetMetadata は問い合わせの結果の列の情報で、列は結果のレコードの値と同じ順に並ぶ
+/// プランのスキーマから作るので、スキャンを開く前に分かる
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResultSetMetadata {
+    columns: Vec<ColumnMetadata>,
+}
+
+impl ResultSetMetadata {
+    pub fn new(columns: Vec<ColumnMetadata>) -> Self {
+        Self { columns }
+    }
+
+    pub fn columns(&self) -> &[ColumnMetadata] {
+        &self.columns
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// field_names は列の名前を結果の順に返す
+    pub fn field_names(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| column.name.clone())
+            .collect()
+    }
+
+    /// index_of は列の位置を返す。列がなければ None を返す
+    pub fn index_of(&self, field_name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name == field_name)
+    }
+}
+
+impl From<&Schema> for ResultSetMetadata {
+    fn from(schema: &Schema) -> Self {
+        let columns = schema
+            .fields
+            .iter()
+            .filter_map(|field_name| {
+                Some(ColumnMetadata {
+                    name: field_name.clone(),
+                    field_type: schema.r#type(field_name)?,
+                    length: schema.length(field_name)?,
+                })
+            })
+            .collect();
+        Self { columns }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_derive_metadata_from_schema() {
+        let mut schema = Schema::default();
+        schema.add_string_field("NAME", 20);
+        schema.add_int_field("ID");
+        schema.add_text_field("NOTE");
+        schema.add_bool_field("ACTIVE_FLAG");
+        let metadata = ResultSetMetadata::from(&schema);
+
+        assert_eq!(
+            metadata.field_names(),
+            vec!["NAME", "ID", "NOTE", "ACTIVE_FLAG"]
+        );
+        assert_eq!(metadata.index_of("ID"), Some(1));
+        assert_eq!(metadata.index_of("X"), None);
+        let sizes: Vec<usize> = metadata
+            .columns()
+            .iter()
+            .map(ColumnMetadata::display_size)
+            .collect();
+        assert_eq!(sizes, vec![20, 11, 4, 11]);
+        assert_eq!(metadata.columns()[0].field_type, FieldTypes::Varchar);
+    }
+}
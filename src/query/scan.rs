@@ -1,9 +1,23 @@
 #![allow(unused_variables)]
 
 use super::constant::Constant;
-use crate::record::rid::RID;
-use anyhow::Result;
-use std::sync::{Arc, Mutex};
+use crate::{
+    file::date::Date,
+    record::{overflow::BlobReader, rid::RID},
+};
+use anyhow::{bail, Result};
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+/// ScanDirection はスキャンでレコードを読み進める向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScanDirection {
+    #[default]
+    Forward,
+    Backward,
+}
 
 pub trait Scan {
     fn before_first(&mut self);
@@ -14,23 +28,90 @@ pub trait Scan {
     fn has_field(&self, field_name: &str) -> bool;
     fn close(&mut self);
 
+    /// after_last は最後のレコードの後ろに移動する。続けて previous を呼ぶと最後のレコードから逆順に読み込む
+    /// 逆順に読めないスキャンでは何もせず、続けて呼ぶ previous がエラーを返す
+    fn after_last(&mut self) {}
+
+    /// previous は1つ前のレコードに移動する。前のレコードがない場合は false を返す
+    fn previous(&mut self) -> Result<bool> {
+        bail!("backward scan is not supported")
+    }
+
+    /// rewind は direction の向きに読み始める位置に移動する
+    fn rewind(&mut self, direction: ScanDirection) {
+        match direction {
+            ScanDirection::Forward => self.before_first(),
+            ScanDirection::Backward => self.after_last(),
+        }
+    }
+
+    /// advance は direction の向きに次のレコードに移動する
+    fn advance(&mut self, direction: ScanDirection) -> Result<bool> {
+        match direction {
+            ScanDirection::Forward => self.next(),
+            ScanDirection::Backward => self.previous(),
+        }
+    }
+
+    /// get_blob_reader はフィールドの値を少しずつ読み込む BlobReader を返す
+    /// text や blob の値を全部メモリに載せずに読み込むときに使う
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        bail!("blob reader is not supported: {}", field_name)
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        bail!("get_long is not supported: {}", field_name)
+    }
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        bail!("get_double is not supported: {}", field_name)
+    }
+    /// get_bool は get_value で読み込んだ値を返す。値を作り直して返すスキャンはこれで読める
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        match self.get_value(field_name)? {
+            Constant::Bool(value) => Ok(value),
+            _ => bail!("field is not boolean: {}", field_name),
+        }
+    }
+    /// get_date は get_value で読み込んだ値を返す。値を作り直して返すスキャンはこれで読める
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        match self.get_value(field_name)? {
+            Constant::Date(value) => Ok(value),
+            _ => bail!("field is not date: {}", field_name),
+        }
+    }
+
     fn set_value(&mut self, field_name: &str, val: Constant) -> Result<()> {
-        unimplemented!();
+        bail!("set_value is not supported: {}", field_name)
     }
     fn set_int(&mut self, field_name: &str, val: i32) -> Result<()> {
-        unimplemented!();
+        bail!("set_int is not supported: {}", field_name)
     }
     fn set_string(&mut self, field_name: &str, val: &str) -> Result<()> {
-        unimplemented!();
+        bail!("set_string is not supported: {}", field_name)
+    }
+    fn set_long(&mut self, field_name: &str, val: i64) -> Result<()> {
+        bail!("set_long is not supported: {}", field_name)
+    }
+    fn set_double(&mut self, field_name: &str, val: f64) -> Result<()> {
+        bail!("set_double is not supported: {}", field_name)
+    }
+    fn set_bool(&mut self, field_name: &str, val: bool) -> Result<()> {
+        bail!("set_bool is not supported: {}", field_name)
+    }
+    fn set_date(&mut self, field_name: &str, val: Date) -> Result<()> {
+        bail!("set_date is not supported: {}", field_name)
+    }
+    fn set_blob(&mut self, field_name: &str, reader: &mut dyn Read) -> Result<()> {
+        bail!("set_blob is not supported: {}", field_name)
     }
     fn delete(&mut self) -> Result<()> {
-        unimplemented!();
+        bail!("delete is not supported")
     }
     fn insert(&mut self) -> Result<()> {
-        unimplemented!();
+        bail!("insert is not supported")
     }
     fn get_rid(&mut self) -> Result<RID> {
-        unimplemented!();
+        bail!("get_rid is not supported")
     }
     fn move_to_rid(&mut self, rid: RID) {
         unimplemented!();
@@ -1,10 +1,11 @@
-use crate::unlock;
+use crate::{file::date::Date, record::overflow::BlobReader, unlock};
 
 use super::{
     predicate::Predicate,
     scan::{ArcScan, Scan},
 };
 use anyhow::Result;
+use std::io::Read;
 
 pub struct SelectScan {
     scan: ArcScan,
@@ -34,6 +35,19 @@ impl Scan for SelectScan {
         Ok(false)
     }
 
+    fn after_last(&mut self) {
+        unlock!(self.scan).after_last();
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        while unlock!(self.scan).previous()? {
+            if self.pred.is_satisfied(self.scan.clone())? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     fn get_int(&mut self, field_name: &str) -> Result<i32> {
         unlock!(self.scan).get_int(field_name)
     }
@@ -50,6 +64,26 @@ impl Scan for SelectScan {
         unlock!(self.scan).has_field(field_name)
     }
 
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        unlock!(self.scan).get_blob_reader(field_name)
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        unlock!(self.scan).get_long(field_name)
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        unlock!(self.scan).get_double(field_name)
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        unlock!(self.scan).get_bool(field_name)
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        unlock!(self.scan).get_date(field_name)
+    }
+
     fn close(&mut self) {
         unlock!(self.scan).close();
     }
@@ -66,6 +100,26 @@ impl Scan for SelectScan {
         unlock!(self.scan).set_string(field_name, val)
     }
 
+    fn set_long(&mut self, field_name: &str, val: i64) -> Result<()> {
+        unlock!(self.scan).set_long(field_name, val)
+    }
+
+    fn set_double(&mut self, field_name: &str, val: f64) -> Result<()> {
+        unlock!(self.scan).set_double(field_name, val)
+    }
+
+    fn set_bool(&mut self, field_name: &str, val: bool) -> Result<()> {
+        unlock!(self.scan).set_bool(field_name, val)
+    }
+
+    fn set_date(&mut self, field_name: &str, val: Date) -> Result<()> {
+        unlock!(self.scan).set_date(field_name, val)
+    }
+
+    fn set_blob(&mut self, field_name: &str, reader: &mut dyn Read) -> Result<()> {
+        unlock!(self.scan).set_blob(field_name, reader)
+    }
+
     fn delete(&mut self) -> Result<()> {
         unlock!(self.scan).delete()
     }
@@ -0,0 +1,198 @@
+use super::{
+    constant::Constant,
+    record_comparator::RecordComparator,
+    scan::{ArcScan, Scan, ScanDirection},
+};
+use crate::unlock;
+use anyhow::{bail, Result};
+
+/// SortScan は並べ替えたランを読みながら併合して、全体を並べた順にレコードを返すスキャン
+///
+/// SortPlan はランが2つ以下になるまで一時テーブルに併合し、最後の併合は書き込まずにこのスキャンで行う
+/// キーの等しいレコードは前のランから返す。読む向きを変えるときは before_first か after_last で読み直す
+pub struct SortScan {
+    runs: Vec<ArcScan>,
+    comparator: RecordComparator,
+    /// keys は各ランの今のレコードのキーの値。ランを読み終えていれば None
+    keys: Vec<Option<Vec<Constant>>>,
+    /// current は今のレコードを返しているラン
+    current: Option<usize>,
+    direction: ScanDirection,
+    /// started は各ランを最初のレコードに進めたかを表す
+    started: bool,
+}
+
+impl SortScan {
+    pub fn new(runs: Vec<ArcScan>, comparator: RecordComparator) -> Self {
+        let keys = vec![None; runs.len()];
+        Self {
+            runs,
+            comparator,
+            keys,
+            current: None,
+            direction: ScanDirection::Forward,
+            started: false,
+        }
+    }
+
+    fn rewind_runs(&mut self, direction: ScanDirection) {
+        for run in &self.runs {
+            unlock!(run).rewind(direction);
+        }
+        self.keys.fill(None);
+        self.current = None;
+        self.direction = direction;
+        self.started = false;
+    }
+
+    fn advance_run(&mut self, i: usize) -> Result<()> {
+        let found = unlock!(self.runs[i]).advance(self.direction)?;
+        self.keys[i] = if found {
+            Some(self.comparator.key_values(&self.runs[i])?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// step は今のランを1つ進めてから、各ランの今のレコードのうち direction の向きで最初のものを選ぶ
+    fn step(&mut self, direction: ScanDirection) -> Result<bool> {
+        if direction != self.direction {
+            bail!("sort scan cannot change direction without rewinding");
+        }
+        if !self.started {
+            self.started = true;
+            for i in 0..self.runs.len() {
+                self.advance_run(i)?;
+            }
+        } else if let Some(current) = self.current {
+            self.advance_run(current)?;
+        }
+        self.current = None;
+        for (i, keys) in self.keys.iter().enumerate() {
+            let Some(keys) = keys else {
+                continue;
+            };
+            let first = match self.current.and_then(|current| self.keys[current].as_ref()) {
+                None => true,
+                Some(current) => {
+                    let ordering = self.comparator.compare_values(keys, current);
+                    match direction {
+                        ScanDirection::Forward => ordering.is_lt(),
+                        // 逆向きでは、前から読んだ場合のちょうど逆順になるようにキーの等しいレコードは後のランから返す
+                        ScanDirection::Backward => ordering.is_ge(),
+                    }
+                }
+            };
+            if first {
+                self.current = Some(i);
+            }
+        }
+        Ok(self.current.is_some())
+    }
+
+    fn current_run(&self, field_name: &str) -> Result<&ArcScan> {
+        match self.current {
+            Some(current) => Ok(&self.runs[current]),
+            None => bail!("no current record: {}", field_name),
+        }
+    }
+}
+
+unsafe impl Send for SortScan {}
+unsafe impl Sync for SortScan {}
+
+impl Scan for SortScan {
+    fn before_first(&mut self) {
+        self.rewind_runs(ScanDirection::Forward);
+    }
+
+    fn next(&mut self) -> Result<bool> {
+        self.step(ScanDirection::Forward)
+    }
+
+    fn after_last(&mut self) {
+        self.rewind_runs(ScanDirection::Backward);
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        self.step(ScanDirection::Backward)
+    }
+
+    fn get_int(&mut self, field_name: &str) -> Result<i32> {
+        unlock!(self.current_run(field_name)?).get_int(field_name)
+    }
+
+    fn get_string(&mut self, field_name: &str) -> Result<String> {
+        unlock!(self.current_run(field_name)?).get_string(field_name)
+    }
+
+    fn get_value(&mut self, field_name: &str) -> Result<Constant> {
+        unlock!(self.current_run(field_name)?).get_value(field_name)
+    }
+
+    fn has_field(&self, field_name: &str) -> bool {
+        self.runs
+            .first()
+            .is_some_and(|run| unlock!(run).has_field(field_name))
+    }
+
+    fn close(&mut self) {
+        for run in &self.runs {
+            unlock!(run).close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{
+        expression::Expression, memory_sort_scan::MemorySortScan, record_comparator::SortKey,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn should_merge_sorted_runs_in_both_directions() -> Result<()> {
+        let run = |values: &[(i32, &str)]| -> ArcScan {
+            let rows = values
+                .iter()
+                .map(|(a, b)| vec![Constant::Int(*a), Constant::String(b.to_string())])
+                .collect();
+            Arc::new(Mutex::new(MemorySortScan::new(
+                vec!["A".into(), "B".into()],
+                rows,
+            )))
+        };
+        let runs = vec![
+            run(&[(3, "x"), (2, "y"), (1, "x")]),
+            run(&[(3, "y"), (1, "y")]),
+            run(&[]),
+        ];
+        let key = SortKey::new(Expression::FieldName("A".into()), true);
+        let comparator = RecordComparator::new(vec![key]);
+        let mut scan = SortScan::new(runs, comparator);
+        let mut forward = vec![];
+        while scan.next()? {
+            forward.push((scan.get_int("A")?, scan.get_string("B")?));
+        }
+        let mut expected = [(3, "x"), (3, "y"), (2, "y"), (1, "x"), (1, "y")]
+            .map(|(a, b)| (a, b.to_string()))
+            .to_vec();
+        assert_eq!(forward, expected);
+        assert!(!scan.next()?);
+        assert!(scan.get_value("A").is_err());
+        assert!(scan.previous().is_err());
+
+        scan.after_last();
+        let mut backward = vec![];
+        while scan.previous()? {
+            backward.push((scan.get_int("A")?, scan.get_string("B")?));
+        }
+        expected.reverse();
+        assert_eq!(backward, expected);
+        assert!(scan.has_field("B"));
+        scan.close();
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
 use super::{
-    create_index_data::CreateIndexData, create_table_data::CreateTableData,
-    create_view_data::CreateViewData, delete_data::DeleteData, insert_data::InsertData,
-    modify_data::ModifyData,
+    cluster_data::ClusterData, create_index_data::CreateIndexData,
+    create_table_data::CreateTableData, create_view_data::CreateViewData, delete_data::DeleteData,
+    grant_data::GrantData, insert_data::InsertData, modify_data::ModifyData,
 };
 
+#[allow(clippy::enum_variant_names)]
 pub enum CreateStatement {
     CreateTable(CreateTableData),
-    CreateView(CreateViewData),
+    CreateView(Box<CreateViewData>),
     CreateIndex(CreateIndexData),
 }
 
@@ -15,4 +16,7 @@ pub enum Statement {
     Insert(InsertData),
     Update(ModifyData),
     Delete(DeleteData),
+    Grant(GrantData),
+    Revoke(GrantData),
+    Cluster(ClusterData),
 }
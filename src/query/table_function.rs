@@ -0,0 +1,43 @@
+use std::fmt::Display;
+
+/// TableFunction は FROM にテーブルの代わりに書ける、レコードを作り出す関数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableFunction {
+    /// GenerateSeries は `generate_series(<start>, <stop>) [<name>]`
+    /// start から stop までの整数を1ずつ増やして返す。フィールド名を省略すると generate_series になる
+    GenerateSeries {
+        start: i32,
+        stop: i32,
+        field_name: String,
+    },
+}
+
+impl TableFunction {
+    pub const GENERATE_SERIES: &'static str = "generate_series";
+
+    pub fn generate_series(start: i32, stop: i32, field_name: Option<String>) -> Self {
+        TableFunction::GenerateSeries {
+            start,
+            stop,
+            field_name: field_name.unwrap_or_else(|| Self::GENERATE_SERIES.to_string()),
+        }
+    }
+}
+
+impl Display for TableFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableFunction::GenerateSeries {
+                start,
+                stop,
+                field_name,
+            } => {
+                write!(f, "{}({}, {})", Self::GENERATE_SERIES, start, stop)?;
+                if field_name != Self::GENERATE_SERIES {
+                    write!(f, " {}", field_name)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -1,26 +1,198 @@
-use super::{constant::Constant, expression::Expression, scan::ArcScan};
-use crate::{plan::ArcPlan, record::schema::Schema, unlock};
-use anyhow::Result;
-use std::{cmp, fmt::Display, sync::Arc};
+use super::{constant::Constant, expression::Expression, scan::ArcScan, truth::Truth};
+use crate::{
+    plan::ArcPlan,
+    record::{collation::Collation, schema::Schema},
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::{
+    cmp::{self, Ordering},
+    fmt::Display,
+    sync::Arc,
+};
 
+/// RANGE_REDUCTION_FACTOR は範囲の比較の項がレコード数を減らす割合の見積もり
+const RANGE_REDUCTION_FACTOR: i32 = 3;
+
+/// Comparison は項の2つの式の比べ方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    /// reversed は左辺と右辺を入れ替えたときの比べ方を返す
+    pub fn reversed(&self) -> Self {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessEqual => Comparison::GreaterEqual,
+            Comparison::Greater => Comparison::Less,
+            Comparison::GreaterEqual => Comparison::LessEqual,
+            op => *op,
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterEqual => ordering.is_ge(),
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "<>",
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEqual => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// Term は2つの式を比べる項
+/// 文字列は比べるフィールドの照合順序で比べる。collate で設定するまでは Binary で比べる
+///
+/// 等しさ以外の比較は同じ型の値どうしでだけ行い、型が異なる場合はエラーになる
+/// プランナーは等しさの項だけを索引や結合に使い、ほかの項は選択で評価する
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Term {
     lhs: Expression,
+    op: Comparison,
     rhs: Expression,
+    collation: Collation,
 }
 
 impl Term {
     pub fn new(lhs: Expression, rhs: Expression) -> Self {
-        Self { lhs, rhs }
+        Self::compare(lhs, Comparison::Equal, rhs)
     }
 
-    pub fn is_satisfied(&self, scan: ArcScan) -> Result<bool> {
+    /// compare は op で2つの式を比べる項を返す
+    pub fn compare(lhs: Expression, op: Comparison, rhs: Expression) -> Self {
+        Self {
+            lhs,
+            op,
+            rhs,
+            collation: Collation::Binary,
+        }
+    }
+
+    /// reversed は左辺と右辺を入れ替えた同じ意味の項を返す
+    pub fn reversed(&self) -> Self {
+        Self {
+            lhs: self.rhs.clone(),
+            op: self.op.reversed(),
+            rhs: self.lhs.clone(),
+            collation: self.collation,
+        }
+    }
+
+    /// collate はスキーマにあるフィールドの照合順序で比べるように設定する
+    /// 両辺がフィールドで照合順序が異なる場合は左辺のフィールドの照合順序を使う
+    pub fn collate(&mut self, schema: &Schema) {
+        let collation = |expression: &Expression| {
+            expression
+                .field_name()
+                .filter(|field_name| schema.has_field(field_name))
+                .map(|field_name| schema.collation(&field_name))
+        };
+        if let Some(collation) = collation(&self.lhs).or_else(|| collation(&self.rhs)) {
+            self.collation = collation;
+        }
+    }
+
+    pub fn lhs(&self) -> &Expression {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &Expression {
+        &self.rhs
+    }
+
+    pub fn op(&self) -> Comparison {
+        self.op
+    }
+
+    /// is_equality は2つの式が等しいことを表す項かを返す
+    pub fn is_equality(&self) -> bool {
+        self.op == Comparison::Equal
+    }
+
+    /// evaluate は項を三値論理で評価する。どちらかの値が NULL の場合は Unknown になる
+    pub fn evaluate(&self, scan: ArcScan) -> Result<Truth> {
         let lhs_value = self.lhs.evaluate(scan.clone())?;
         let rhs_value = self.rhs.evaluate(scan)?;
-        Ok(lhs_value == rhs_value)
+        self.compare_values(&lhs_value, &rhs_value)
+    }
+
+    /// compare_values は2つの値を項の比べ方で比べる
+    fn compare_values(&self, lhs_value: &Constant, rhs_value: &Constant) -> Result<Truth> {
+        let (lhs, rhs) = (self.collation.key(lhs_value), self.collation.key(rhs_value));
+        let truth = Truth::compare(&lhs, &rhs);
+        match self.op {
+            Comparison::Equal => Ok(truth),
+            Comparison::NotEqual => Ok(!truth),
+            _ if truth == Truth::Unknown => Ok(Truth::Unknown),
+            op => {
+                if std::mem::discriminant(&lhs) != std::mem::discriminant(&rhs) {
+                    bail!(
+                        "cannot compare {} and {}",
+                        lhs_value.to_literal(),
+                        rhs_value.to_literal()
+                    );
+                }
+                Ok(Truth::from(op.holds(lhs.cmp(&rhs))))
+            }
+        }
+    }
+
+    /// is_satisfied は項が True になる場合だけ true を返す。Unknown は満たされないものとして扱う
+    pub fn is_satisfied(&self, scan: ArcScan) -> Result<bool> {
+        Ok(self.evaluate(scan)? == Truth::True)
     }
 
+    /// reduction_factor は項を満たすレコードが何分の1になるかを見積もる
+    /// 定数どうしの比較は、満たす場合は 1、満たさない場合は i32::MAX にする
     pub fn reduction_factor(&self, plan: ArcPlan) -> i32 {
+        if let (Expression::Value(l), Expression::Value(r)) = (&self.lhs, &self.rhs) {
+            return match self.compare_values(l, r) {
+                Ok(Truth::True) => 1,
+                _ => i32::MAX,
+            };
+        }
+        match self.op {
+            Comparison::Equal => {}
+            Comparison::NotEqual => return 1,
+            // 値の種類が少ないフィールドの範囲の比較は、値の種類の数より多くは減らない
+            _ => {
+                return [&self.lhs, &self.rhs]
+                    .into_iter()
+                    .filter_map(|expression| match expression {
+                        Expression::FieldName(field_name) => {
+                            Some(unlock!(plan).distinct_values(field_name))
+                        }
+                        _ => None,
+                    })
+                    .min()
+                    .map_or(RANGE_REDUCTION_FACTOR, |values| {
+                        RANGE_REDUCTION_FACTOR.min(values.max(1))
+                    })
+            }
+        }
         match (&self.lhs, &self.rhs) {
             (Expression::FieldName(l), Expression::FieldName(r)) => {
                 let l_values = unlock!(plan).distinct_values(l);
@@ -29,17 +201,15 @@ impl Term {
             }
             (Expression::FieldName(l), _) => unlock!(plan).distinct_values(l),
             (_, Expression::FieldName(r)) => unlock!(plan).distinct_values(r),
-            (Expression::Value(l), Expression::Value(r)) => {
-                if l == r {
-                    1
-                } else {
-                    i32::MAX
-                }
-            }
+            // 関数や計算の結果は分からないので、範囲の比較と同じだけ減ると見積もる
+            _ => RANGE_REDUCTION_FACTOR,
         }
     }
 
     pub fn equates_with_constant(&self, field_name: &str) -> Option<Constant> {
+        if !self.is_equality() {
+            return None;
+        }
         match (&self.lhs, &self.rhs) {
             (Expression::FieldName(l), Expression::Value(v)) => {
                 if *l == field_name {
@@ -60,6 +230,9 @@ impl Term {
     }
 
     pub fn equates_with_field(&self, field_name: &str) -> Option<String> {
+        if !self.is_equality() {
+            return None;
+        }
         match (&self.lhs, &self.rhs) {
             (Expression::FieldName(l), Expression::FieldName(r)) => {
                 if *l == field_name {
@@ -81,6 +254,6 @@ impl Term {
 
 impl Display for Term {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} = {}", self.lhs, self.rhs)
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
     }
 }
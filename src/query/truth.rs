@@ -0,0 +1,132 @@
+use super::constant::Constant;
+use std::{fmt::Display, ops::Not};
+
+/// Truth は三値論理の真理値
+/// NULL を含む比較の結果は True でも False でもなく Unknown になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+impl From<bool> for Truth {
+    fn from(value: bool) -> Self {
+        if value {
+            Truth::True
+        } else {
+            Truth::False
+        }
+    }
+}
+
+impl Truth {
+    /// compare は2つの値が等しいかを返す。どちらかが NULL の場合は Unknown を返す
+    pub fn compare(lhs: &Constant, rhs: &Constant) -> Self {
+        if lhs.is_null() || rhs.is_null() {
+            Truth::Unknown
+        } else {
+            Truth::from(lhs == rhs)
+        }
+    }
+
+    /// and はどちらかが False なら False、両方が True なら True、それ以外は Unknown を返す
+    pub fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Truth::False, _) | (_, Truth::False) => Truth::False,
+            (Truth::True, Truth::True) => Truth::True,
+            _ => Truth::Unknown,
+        }
+    }
+
+    /// or はどちらかが True なら True、両方が False なら False、それ以外は Unknown を返す
+    pub fn or(self, other: Self) -> Self {
+        match (self, other) {
+            (Truth::True, _) | (_, Truth::True) => Truth::True,
+            (Truth::False, Truth::False) => Truth::False,
+            _ => Truth::Unknown,
+        }
+    }
+}
+
+/// not は True と False を入れ替える。Unknown は Unknown のまま
+impl Not for Truth {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
+}
+
+impl Display for Truth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Truth::True => write!(f, "TRUE"),
+            Truth::False => write!(f, "FALSE"),
+            Truth::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Truth::{False as F, True as T, Unknown as U};
+
+    #[test]
+    fn should_follow_and_truth_table() {
+        let table = [
+            (T, T, T),
+            (T, F, F),
+            (T, U, U),
+            (F, T, F),
+            (F, F, F),
+            (F, U, F),
+            (U, T, U),
+            (U, F, F),
+            (U, U, U),
+        ];
+        for (lhs, rhs, expected) in table {
+            assert_eq!(lhs.and(rhs), expected, "{} AND {}", lhs, rhs);
+        }
+    }
+
+    #[test]
+    fn should_follow_or_truth_table() {
+        let table = [
+            (T, T, T),
+            (T, F, T),
+            (T, U, T),
+            (F, T, T),
+            (F, F, F),
+            (F, U, U),
+            (U, T, T),
+            (U, F, U),
+            (U, U, U),
+        ];
+        for (lhs, rhs, expected) in table {
+            assert_eq!(lhs.or(rhs), expected, "{} OR {}", lhs, rhs);
+        }
+    }
+
+    #[test]
+    fn should_follow_not_truth_table() {
+        assert_eq!(!T, F);
+        assert_eq!(!F, T);
+        assert_eq!(!U, U);
+    }
+
+    #[test]
+    fn should_compare_with_null_as_unknown() {
+        let (one, two, null) = (Constant::Int(1), Constant::Int(2), Constant::Null);
+        assert_eq!(Truth::compare(&one, &one), T);
+        assert_eq!(Truth::compare(&one, &two), F);
+        assert_eq!(Truth::compare(&one, &null), U);
+        assert_eq!(Truth::compare(&null, &one), U);
+        assert_eq!(Truth::compare(&null, &null), U);
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::{bail, Result};
+use std::fmt::Display;
+
+/// BlockSample はテーブルのブロックのうち、percent パーセントを擬似乱数で選ぶ標本
+///
+/// ブロックを選ぶかはブロック番号だけから決めるので、何度読み直しても同じブロックを選ぶ
+/// 結合で読み直すスキャンでも結果が変わらず、統計の標本のように同じ選び方を使い回せる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockSample {
+    percent: u32,
+}
+
+impl BlockSample {
+    pub fn new(percent: i32) -> Result<Self> {
+        if !(0..=100).contains(&percent) {
+            bail!("sample percent must be between 0 and 100: {}", percent);
+        }
+        Ok(Self {
+            percent: percent as u32,
+        })
+    }
+
+    pub fn percent(&self) -> i32 {
+        self.percent as i32
+    }
+
+    /// fraction は選ぶブロックの割合を返す
+    pub fn fraction(&self) -> f64 {
+        self.percent as f64 / 100.0
+    }
+
+    /// includes は block_num 番目のブロックを標本に含めるかを返す
+    pub fn includes(&self, block_num: i32) -> bool {
+        (mix(block_num as u64) % 100) < self.percent as u64
+    }
+}
+
+/// mix は splitmix64 で連続した番号をばらばらな値に散らす
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Display for BlockSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sample {} percent", self.percent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_choose_blocks_by_percent() -> Result<()> {
+        let count = |sample: BlockSample| (0..10000).filter(|&b| sample.includes(b)).count();
+        assert_eq!(count(BlockSample::new(0)?), 0);
+        assert_eq!(count(BlockSample::new(100)?), 10000);
+        let sampled = count(BlockSample::new(10)?);
+        assert!((900..=1100).contains(&sampled), "{}", sampled);
+
+        // 同じブロックはいつも同じように選ぶ
+        let sample = BlockSample::new(30)?;
+        let first: Vec<_> = (0..100).filter(|&b| sample.includes(b)).collect();
+        let second: Vec<_> = (0..100).filter(|&b| sample.includes(b)).collect();
+        assert_eq!(first, second);
+        // 割合を増やしても、前に選んだブロックは選んだままにする
+        let larger = BlockSample::new(60)?;
+        assert!(first.iter().all(|&b| larger.includes(b)));
+
+        assert!(BlockSample::new(-1).is_err());
+        assert!(BlockSample::new(101).is_err());
+        Ok(())
+    }
+}
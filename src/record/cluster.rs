@@ -0,0 +1,112 @@
+use super::{rid::RID, table_scan::TableScan};
+use crate::query::{constant::Constant, scan::Scan as _};
+use anyhow::Result;
+
+/// seek_block はキーの順に並んだテーブルで、key を探し始めるブロックを二分探索で返す
+///
+/// ブロック b 以降の最初のレコードのキーは b について単調に増えるので、
+/// そのキーが key より小さい（inclusive なら key 以下の）最後のブロックを返す
+/// そのようなブロックがない場合は先頭のブロックを返す
+/// inclusive でない場合は key と等しいレコードはすべてそのブロック以降にあり、
+/// inclusive の場合は key をそのブロックに挿入すればキーの順を保てる見込みが高い
+pub fn seek_block(
+    ts: &mut TableScan,
+    field_name: &str,
+    key: &Constant,
+    inclusive: bool,
+) -> Result<i32> {
+    let mut low = 0;
+    let mut high = ts.block_count()?;
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        ts.move_to_rid(RID::new(mid, -1));
+        let before = if ts.next()? {
+            let first = ts.get_value(field_name)?;
+            if inclusive {
+                first <= *key
+            } else {
+                first < *key
+            }
+        } else {
+            false
+        };
+        if before {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
+
+/// in_order は rid のレコードのキーが前後のレコードのキーとの間で順序を保っているかを返す
+pub fn in_order(ts: &mut TableScan, field_name: &str, rid: RID) -> Result<bool> {
+    ts.move_to_rid(rid);
+    let key = ts.get_value(field_name)?;
+    if ts.previous()? && ts.get_value(field_name)? > key {
+        return Ok(false);
+    }
+    ts.move_to_rid(rid);
+    if ts.next()? && ts.get_value(field_name)? < key {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record::{layout::Layout, schema::Schema},
+        server::db::TinyDB,
+    };
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_seek_block_in_sorted_table() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_seek_block_in_sorted_table");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        schema.add_string_field("B", 20);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema))?);
+
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        for n in 0..200 {
+            ts.insert()?;
+            ts.set_int("A", n / 2)?;
+            ts.set_string("B", "x")?;
+        }
+        assert!(ts.block_count()? > 4);
+        // まとめて追加したブロックのうち使っていないものは探さない
+        let last = ts.get_rid()?.block_num;
+
+        // 探し始めるブロックから読めば、等しいキーのレコードをすべて見つけられる
+        for key in [0, 37, 99] {
+            let block = seek_block(&mut ts, "A", &Constant::Int(key), false)?;
+            ts.move_to_rid(RID::new(block, -1));
+            let mut found = 0;
+            while ts.next()? {
+                let value = ts.get_int("A")?;
+                assert!(value <= key || found == 2);
+                if value == key {
+                    found += 1;
+                }
+            }
+            assert_eq!(found, 2);
+        }
+        assert_eq!(seek_block(&mut ts, "A", &Constant::Int(1000), true)?, last);
+        assert_eq!(seek_block(&mut ts, "A", &Constant::Int(-1), true)?, 0);
+
+        assert!(in_order(&mut ts, "A", RID::new(0, 3))?);
+        ts.move_to_rid(RID::new(0, 3));
+        ts.set_int("A", 50)?;
+        assert!(!in_order(&mut ts, "A", RID::new(0, 3))?);
+
+        ts.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+}
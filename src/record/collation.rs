@@ -0,0 +1,128 @@
+use crate::query::constant::Constant;
+use anyhow::{bail, Result};
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+/// Collation は文字列のフィールドの値を比べる規則（照合順序）
+///
+/// フィールドごとに `varchar(10) collate nocase` のように指定する。指定しない場合は Binary になる
+/// 述語の等しさと索引のキーの順序はこの規則に従う
+///
+/// 照合順序は key で値を比べるためのキーに変換して実装する
+/// 索引には変換したキーを格納するので、キーが等しい値は索引でも同じキーとして扱われる
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Collation {
+    /// Binary は UTF-8 のバイト列の順に比べる
+    #[default]
+    Binary,
+    /// NoCase は ASCII の大文字と小文字を区別せずに比べる
+    NoCase,
+    /// Unicode は Unicode の大文字と小文字を区別せずに比べる
+    #[cfg(feature = "unicode-collation")]
+    Unicode,
+}
+
+impl Collation {
+    /// all は使えるすべての照合順序を返す
+    pub fn all() -> &'static [Collation] {
+        &[
+            Collation::Binary,
+            Collation::NoCase,
+            #[cfg(feature = "unicode-collation")]
+            Collation::Unicode,
+        ]
+    }
+
+    /// key は値をこの照合順序で比べるためのキーに変換する。文字列以外の値はそのまま返す
+    pub fn key(&self, value: &Constant) -> Constant {
+        match (self, value) {
+            (Collation::NoCase, Constant::String(s)) => Constant::String(s.to_ascii_lowercase()),
+            #[cfg(feature = "unicode-collation")]
+            (Collation::Unicode, Constant::String(s)) => Constant::String(s.to_lowercase()),
+            (_, value) => value.clone(),
+        }
+    }
+
+    pub fn compare(&self, lhs: &Constant, rhs: &Constant) -> Ordering {
+        self.key(lhs).cmp(&self.key(rhs))
+    }
+
+    /// may_equal は2つの値がいずれかの照合順序で等しくなりうるかを返す
+    /// フィールドの照合順序が分からないうちに、述語が矛盾するかを確かめるのに使う
+    pub fn may_equal(lhs: &Constant, rhs: &Constant) -> bool {
+        Self::all()
+            .iter()
+            .any(|collation| collation.compare(lhs, rhs) == Ordering::Equal)
+    }
+}
+
+impl Display for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Collation::Binary => write!(f, "binary"),
+            Collation::NoCase => write!(f, "nocase"),
+            #[cfg(feature = "unicode-collation")]
+            Collation::Unicode => write!(f, "unicode"),
+        }
+    }
+}
+
+impl FromStr for Collation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "binary" => Ok(Collation::Binary),
+            "nocase" => Ok(Collation::NoCase),
+            #[cfg(feature = "unicode-collation")]
+            "unicode" => Ok(Collation::Unicode),
+            _ => bail!("unknown collation: {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Constant {
+        Constant::String(s.into())
+    }
+
+    #[test]
+    fn should_compare_with_collation() -> Result<()> {
+        assert_eq!(
+            Collation::Binary.compare(&string("a"), &string("B")),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Collation::NoCase.compare(&string("a"), &string("B")),
+            Ordering::Less
+        );
+        assert_eq!(
+            Collation::NoCase.compare(&string("Tiny"), &string("tINY")),
+            Ordering::Equal
+        );
+        assert_ne!(
+            Collation::NoCase.compare(&string("Ä"), &string("ä")),
+            Ordering::Equal
+        );
+        assert_eq!(Collation::NoCase.key(&Constant::Int(1)), Constant::Int(1));
+
+        assert!(Collation::may_equal(&string("X"), &string("x")));
+        assert!(!Collation::may_equal(&string("x"), &string("y")));
+
+        assert_eq!("NOCASE".parse::<Collation>()?, Collation::NoCase);
+        assert_eq!(Collation::NoCase.to_string(), "nocase");
+        assert!("german".parse::<Collation>().is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "unicode-collation")]
+    #[test]
+    fn should_compare_unicode_case_insensitively() {
+        assert_eq!(
+            Collation::Unicode.compare(&string("Ä"), &string("ä")),
+            Ordering::Equal
+        );
+    }
+}
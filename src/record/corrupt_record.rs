@@ -0,0 +1,38 @@
+use crate::file::block::BlockId;
+use std::fmt::Display;
+
+/// CorruptRecord はブロックの中身がレイアウトと合わず、レコードを読めないことを表すエラー
+///
+/// 壊れたページを読んでもプロセスを止めずに、どのブロックのどのスロットとフィールドが読めなかったかを返す
+/// 呼び出し側は anyhow::Error から downcast_ref で取り出して、場所を調べたり検査ツールに渡したりできる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRecord {
+    pub block: BlockId,
+    pub slot: i32,
+    /// field は読めなかったフィールド。スロット全体が読めない場合は None
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+impl CorruptRecord {
+    pub fn new(block: &BlockId, slot: i32, field: Option<&str>, reason: impl Into<String>) -> Self {
+        Self {
+            block: block.clone(),
+            slot,
+            field: field.map(str::to_string),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for CorruptRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "corrupt record in {} slot {}", self.block, self.slot)?;
+        if let Some(field) = &self.field {
+            write!(f, " field {}", field)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+impl std::error::Error for CorruptRecord {}
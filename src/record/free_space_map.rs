@@ -0,0 +1,137 @@
+use crate::{file::block::BlockId, tx::transaction::Transaction, I32_SIZE};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// FreeSpaceMap はテーブルの各ブロックの空き領域のバイト数を記録するファイル
+/// テーブルファイルごとに `<table>.fsm` というファイルを使い、ブロック番号の順に i32 の値が並ぶ
+///
+/// 値は「空きバイト数 + 1」で、0 はまだ記録されていないブロックを表す
+/// 記録のないブロック（空き領域マップがなかった頃のテーブルなど）は空きがあるかもしれないものとして扱う
+///
+/// 空き領域マップはレコードを挿入するブロックを探すためのヒントで、ログは書かない
+/// ロールバックなどで実際の空き領域とずれることがあるので、挿入する側は実際のブロックで確かめて記録し直す
+pub struct FreeSpaceMap {
+    tx: Arc<Mutex<Transaction>>,
+    pub filename: String,
+}
+
+impl FreeSpaceMap {
+    /// new は指定したテーブルファイルに対応する空き領域マップを返す
+    pub fn new(tx: Arc<Mutex<Transaction>>, table_filename: &str) -> Self {
+        let table_name = table_filename
+            .strip_suffix(".tbl")
+            .unwrap_or(table_filename);
+        Self {
+            tx,
+            filename: format!("{}.fsm", table_name),
+        }
+    }
+
+    /// get は指定したブロックの空きバイト数を返す。記録がない場合は None を返す
+    pub fn get(&self, block_num: i32) -> Result<Option<i32>> {
+        let mut tx = self.tx.lock().unwrap();
+        let (block, offset) = self.position(&tx, block_num);
+        if block.num as u64 >= tx.size(self.filename.clone())? {
+            return Ok(None);
+        }
+        tx.pin(&block)?;
+        let value = tx.get_int(&block, offset);
+        tx.unpin(&block);
+        let value = value?;
+        Ok((value > 0).then_some(value - 1))
+    }
+
+    /// set は指定したブロックの空きバイト数を記録する
+    pub fn set(&self, block_num: i32, free: i32) -> Result<()> {
+        let mut tx = self.tx.lock().unwrap();
+        let (block, offset) = self.position(&tx, block_num);
+        while tx.size(self.filename.clone())? <= block.num as u64 {
+            tx.append(self.filename.clone())?;
+        }
+        tx.pin(&block)?;
+        let result = tx.set_int(&block, offset, free.max(0) + 1, false);
+        tx.unpin(&block);
+        result
+    }
+
+    /// find は block_count 個のブロックの中から、required バイト以上の空きがあるか
+    /// 記録のない最初のブロックを返す。見つからない場合は None を返す
+    pub fn find(&self, required: i32, block_count: i32) -> Result<Option<i32>> {
+        let mut tx = self.tx.lock().unwrap();
+        let size = tx.size(self.filename.clone())? as i32;
+        let mut pinned: Option<BlockId> = None;
+        let mut found = None;
+        for block_num in 0..block_count {
+            let (block, offset) = self.position(&tx, block_num);
+            if block.num >= size {
+                found = Some(block_num);
+                break;
+            }
+            if pinned.as_ref() != Some(&block) {
+                if let Some(prev) = pinned.take() {
+                    tx.unpin(&prev);
+                }
+                tx.pin(&block)?;
+                pinned = Some(block.clone());
+            }
+            let value = match tx.get_int(&block, offset) {
+                Ok(value) => value,
+                Err(e) => {
+                    tx.unpin(&block);
+                    return Err(e);
+                }
+            };
+            if value == 0 || value > required {
+                found = Some(block_num);
+                break;
+            }
+        }
+        if let Some(block) = pinned {
+            tx.unpin(&block);
+        }
+        Ok(found)
+    }
+
+    /// position は指定したブロックの値が格納されている空き領域マップのブロックとオフセットを返す
+    fn position(&self, tx: &Transaction, block_num: i32) -> (BlockId, i32) {
+        let entries = tx.block_size() / I32_SIZE as i32;
+        let block = BlockId::new(self.filename.clone(), block_num / entries);
+        (block, (block_num % entries) * I32_SIZE as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::db::TinyDB;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_find_block_with_free_space() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_find_block_with_free_space");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let fsm = FreeSpaceMap::new(tx.clone(), "T.tbl");
+        assert_eq!(fsm.filename, "T.fsm");
+
+        // 1ブロックに100個の値が入るので、150個の値は2ブロックにまたがる
+        for block_num in 0..150 {
+            fsm.set(block_num, 10)?;
+        }
+        fsm.set(1, 100)?;
+        fsm.set(120, 200)?;
+        assert_eq!(fsm.get(0)?, Some(10));
+        assert_eq!(fsm.get(120)?, Some(200));
+        assert_eq!(fsm.get(150)?, None);
+        assert_eq!(fsm.get(250)?, None);
+
+        assert_eq!(fsm.find(50, 150)?, Some(1));
+        assert_eq!(fsm.find(150, 150)?, Some(120));
+        assert_eq!(fsm.find(300, 150)?, None);
+        // 記録のないブロックは空きがあるかもしれないので返す
+        assert_eq!(fsm.find(300, 160)?, Some(150));
+
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+}
@@ -4,13 +4,25 @@ use anyhow::{anyhow, Result};
 use super::schema::{FieldTypes, Schema};
 use std::{collections::HashMap, sync::Arc};
 
+/// SCHEMA_VERSION はテーブルスキーマのバージョン
+/// RecordPage のヘッダに書き込まれ、ブロックがどのスキーマでフォーマットされたかを表す
+/// 2 からはセルの先頭に NULL ビットマップを置く
+pub const SCHEMA_VERSION: i32 = 2;
+
+/// NULL_BITMAP_VERSION はセルの先頭に NULL ビットマップを置くようになったバージョン
+/// これより前のバージョンでフォーマットしたブロックのレコードには NULL を格納できない
+pub const NULL_BITMAP_VERSION: i32 = 2;
+
 /// Layout はテーブルレコードのレイアウトを表す
 /// フィールド名と型、テーブル内の各フィールドのオフセットを保持する
-#[derive(Debug, Default)]
+/// RecordPage は可変長でレコードを格納するので、offsets と slot_size は
+/// すべての Varchar が最大長のときの値で、カタログや統計情報の見積もりに使う
+#[derive(Debug, Clone, Default)]
 pub struct Layout {
     pub schema: Arc<Schema>,
     pub offsets: HashMap<String, i32>,
     pub slot_size: i32,
+    pub version: i32,
 }
 
 impl Layout {
@@ -25,6 +37,7 @@ impl Layout {
             schema: schema.clone(),
             offsets,
             slot_size: pos,
+            version: SCHEMA_VERSION,
         })
     }
 
@@ -37,6 +50,7 @@ impl Layout {
             schema: schema.clone(),
             offsets,
             slot_size,
+            version: SCHEMA_VERSION,
         })
     }
 
@@ -51,7 +65,6 @@ impl Layout {
             .r#type(field_name)
             .ok_or_else(|| anyhow!("field type not found"))?;
         match field_type {
-            FieldTypes::Integer => Ok(I32_SIZE as i32),
             FieldTypes::Varchar => {
                 let length = schema
                     .length(field_name)
@@ -59,6 +72,11 @@ impl Layout {
                     as usize;
                 Ok(Page::max_length(length) as i32)
             }
+            // 値の長さとオーバーフローページのチェーンの先頭のブロック番号
+            FieldTypes::Text | FieldTypes::Blob => Ok(2 * I32_SIZE as i32),
+            _ => field_type
+                .fixed_length()
+                .ok_or_else(|| anyhow!("field length not found")),
         }
     }
 }
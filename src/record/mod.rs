@@ -1,5 +1,13 @@
+pub mod block_sample;
+pub mod cluster;
+pub mod collation;
+pub mod corrupt_record;
+pub mod free_space_map;
 pub mod layout;
+pub mod overflow;
 pub mod record_page;
 pub mod rid;
 pub mod schema;
 pub mod table_scan;
+pub mod temp_table;
+pub mod truncation;
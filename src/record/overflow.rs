@@ -0,0 +1,338 @@
+use crate::{file::block::BlockId, tx::transaction::Transaction, I32_SIZE};
+use anyhow::Result;
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+/// OVERFLOW_HEADER_SIZE はオーバーフローブロックのヘッダのサイズ
+/// ヘッダは次のブロック番号と、このブロックに格納されているバイト数で構成される
+pub const OVERFLOW_HEADER_SIZE: i32 = 2 * I32_SIZE as i32;
+const NEXT_OFFSET: i32 = 0;
+const LENGTH_OFFSET: i32 = I32_SIZE as i32;
+
+/// NO_NEXT_BLOCK はチェーンの最後のブロックであることを表す
+/// 空の値はチェーンを持たないので、先頭のブロック番号も NO_NEXT_BLOCK になる
+pub const NO_NEXT_BLOCK: i32 = -1;
+
+/// OverflowFile はレコードに収まらない大きな値を格納するファイル
+/// テーブルファイルごとに `<table>.ovf` というファイルを使う
+/// 値はブロックのチェーンとして格納され、各ブロックは次のブロック番号を持つ
+///
+/// ```text
+/// ┌──────┬─────┬───────────┐   ┌──────┬─────┬───────────┐
+/// │ next │ len │ bytes ... │ → │  -1  │ len │ bytes ... │
+/// └──────┴─────┴───────────┘   └──────┴─────┴───────────┘
+/// ```
+///
+/// 値を書き換えたり消したりして使われなくなったチェーンは free で空きブロックのリストにつなぎ、
+/// 次に書き込むときに使い直す。リストの先頭は `<table>.ovl` に「ブロック番号 + 1」で記録し、0 は空を表す
+pub struct OverflowFile {
+    tx: Arc<Mutex<Transaction>>,
+    pub filename: String,
+    pub free_list_filename: String,
+}
+
+impl OverflowFile {
+    /// new は指定したテーブルファイルに対応するオーバーフローファイルを返す
+    pub fn new(tx: Arc<Mutex<Transaction>>, table_filename: &str) -> Self {
+        let table_name = table_filename
+            .strip_suffix(".tbl")
+            .unwrap_or(table_filename);
+        Self {
+            tx,
+            filename: format!("{}.ovf", table_name),
+            free_list_filename: format!("{}.ovl", table_name),
+        }
+    }
+
+    /// write は値をブロックのチェーンに書き込んで、先頭のブロック番号を返す
+    pub fn write(&self, bytes: &[u8]) -> Result<i32> {
+        let (block_num, _) = self.write_from(&mut &bytes[..])?;
+        Ok(block_num)
+    }
+
+    /// write_from は reader から読み込んだ値を1ブロック分ずつチェーンに書き込んで、
+    /// 先頭のブロック番号と書き込んだバイト数を返す
+    /// 値全体をメモリに載せないので、大きな値でも書き込める
+    /// 空きブロックのリストにあるブロックを先に使い、足りなければファイルにブロックを追加する
+    /// 使い直したブロックへの書き込みはロールバックで元の内容に戻せるようにログを書く
+    /// 新しく追加したブロックへの書き込みはログを書かない
+    /// ロールバックされた場合はレコードからの参照が元に戻り、追加したブロックは使われなくなる
+    pub fn write_from(&self, reader: &mut dyn Read) -> Result<(i32, i32)> {
+        let mut tx = self.tx.lock().unwrap();
+        let capacity = (tx.block_size() - OVERFLOW_HEADER_SIZE) as usize;
+        let mut chunk = vec![0; capacity];
+
+        let mut first_block = NO_NEXT_BLOCK;
+        let mut prev_block: Option<(BlockId, bool)> = None;
+        let mut total = 0;
+        loop {
+            let length = read_full(reader, &mut chunk)?;
+            if length == 0 {
+                break;
+            }
+
+            let (block, reused) = match self.pop_free_block(&mut tx)? {
+                Some(block) => (block, true),
+                None => (tx.append(self.filename.clone())?, false),
+            };
+            tx.pin(&block)?;
+            tx.set_int(&block, NEXT_OFFSET, NO_NEXT_BLOCK, reused)?;
+            tx.set_int(&block, LENGTH_OFFSET, length as i32, reused)?;
+            write_chunk(&mut tx, &block, &chunk[..length], reused)?;
+
+            match prev_block.take() {
+                Some((prev, prev_reused)) => {
+                    tx.set_int(&prev, NEXT_OFFSET, block.num, prev_reused)?;
+                    tx.unpin(&prev);
+                }
+                None => first_block = block.num,
+            }
+            prev_block = Some((block, reused));
+            total += length as i32;
+
+            if length < capacity {
+                break;
+            }
+        }
+        if let Some((prev, _)) = prev_block {
+            tx.unpin(&prev);
+        }
+
+        Ok((first_block, total))
+    }
+
+    /// free は指定したブロックから始まるチェーンを空きブロックのリストの先頭につなぐ
+    /// チェーンの最後のブロックの次をリストの先頭にして、リストの先頭をチェーンの先頭にする
+    /// どちらの変更もログを書くので、ロールバックするとチェーンは元の値に戻る
+    pub fn free(&self, block_num: i32) -> Result<()> {
+        if block_num == NO_NEXT_BLOCK {
+            return Ok(());
+        }
+        let mut tx = self.tx.lock().unwrap();
+        let mut last = BlockId::new(self.filename.clone(), block_num);
+        loop {
+            tx.pin(&last)?;
+            let next = tx.get_int(&last, NEXT_OFFSET);
+            tx.unpin(&last);
+            match next? {
+                NO_NEXT_BLOCK => break,
+                next => last = BlockId::new(self.filename.clone(), next),
+            }
+        }
+
+        let head = self.free_list_head(&mut tx)?;
+        tx.pin(&last)?;
+        let result = tx.set_int(&last, NEXT_OFFSET, head, true);
+        tx.unpin(&last);
+        result?;
+        self.set_free_list_head(&mut tx, block_num)
+    }
+
+    /// pop_free_block は空きブロックのリストの先頭のブロックを取り出す。リストが空の場合は None を返す
+    fn pop_free_block(&self, tx: &mut Transaction) -> Result<Option<BlockId>> {
+        let head = self.free_list_head(tx)?;
+        if head == NO_NEXT_BLOCK {
+            return Ok(None);
+        }
+        let block = BlockId::new(self.filename.clone(), head);
+        tx.pin(&block)?;
+        let next = tx.get_int(&block, NEXT_OFFSET);
+        tx.unpin(&block);
+        self.set_free_list_head(tx, next?)?;
+        Ok(Some(block))
+    }
+
+    /// free_list_head は空きブロックのリストの先頭のブロック番号を返す。リストが空の場合は NO_NEXT_BLOCK を返す
+    fn free_list_head(&self, tx: &mut Transaction) -> Result<i32> {
+        if tx.size(self.free_list_filename.clone())? == 0 {
+            return Ok(NO_NEXT_BLOCK);
+        }
+        let block = BlockId::new(self.free_list_filename.clone(), 0);
+        tx.pin(&block)?;
+        let value = tx.get_int(&block, 0);
+        tx.unpin(&block);
+        Ok(value? - 1)
+    }
+
+    /// set_free_list_head は空きブロックのリストの先頭のブロック番号を記録する
+    fn set_free_list_head(&self, tx: &mut Transaction, block_num: i32) -> Result<()> {
+        if tx.size(self.free_list_filename.clone())? == 0 {
+            tx.append(self.free_list_filename.clone())?;
+        }
+        let block = BlockId::new(self.free_list_filename.clone(), 0);
+        tx.pin(&block)?;
+        let result = tx.set_int(&block, 0, block_num + 1, true);
+        tx.unpin(&block);
+        result
+    }
+
+    /// read は指定したブロックから始まるチェーンをたどって値を読み込む
+    pub fn read(&self, block_num: i32) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.reader(block_num).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// reader は指定したブロックから始まるチェーンを1ブロックずつ読み込む BlobReader を返す
+    pub fn reader(&self, block_num: i32) -> BlobReader {
+        BlobReader {
+            tx: Some(self.tx.clone()),
+            filename: self.filename.clone(),
+            next_block: block_num,
+            chunk: vec![],
+            pos: 0,
+        }
+    }
+}
+
+/// write_chunk はブロックのヘッダの後ろに値を書き込む
+/// ログのレコードはログのブロックに収まらなければならないので、ログを書く場合は
+/// ブロックの 1/4 ずつに分けて書き込む
+fn write_chunk(tx: &mut Transaction, block: &BlockId, bytes: &[u8], ok_to_log: bool) -> Result<()> {
+    if !ok_to_log {
+        return tx.write_bytes(block, OVERFLOW_HEADER_SIZE, bytes, false);
+    }
+    let piece = (tx.block_size() / 4) as usize;
+    for (i, part) in bytes.chunks(piece).enumerate() {
+        tx.write_bytes(block, OVERFLOW_HEADER_SIZE + (i * piece) as i32, part, true)?;
+    }
+    Ok(())
+}
+
+/// read_full は buf がいっぱいになるか reader が終わるまで読み込んで、読み込んだバイト数を返す
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// BlobReader はフィールドの値を少しずつ読み込むための Read の実装
+/// オーバーフローページのチェーンを1ブロックずつたどるので、値全体をメモリに載せない
+pub struct BlobReader {
+    tx: Option<Arc<Mutex<Transaction>>>,
+    filename: String,
+    next_block: i32,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl BlobReader {
+    /// from_bytes はレコードに直接格納されている値を読み込む BlobReader を返す
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            tx: None,
+            filename: String::new(),
+            next_block: NO_NEXT_BLOCK,
+            chunk: bytes,
+            pos: 0,
+        }
+    }
+
+    /// load_next_chunk はチェーンの次のブロックを読み込む
+    /// チェーンの最後まで読み込んでいる場合は false を返す
+    fn load_next_chunk(&mut self) -> Result<bool> {
+        let Some(tx) = &self.tx else {
+            return Ok(false);
+        };
+        if self.next_block == NO_NEXT_BLOCK {
+            return Ok(false);
+        }
+
+        let mut tx = tx.lock().unwrap();
+        let block = BlockId::new(self.filename.clone(), self.next_block);
+        tx.pin(&block)?;
+        let chunk = tx.get_int(&block, LENGTH_OFFSET).and_then(|length| {
+            let chunk = tx.read_bytes(&block, OVERFLOW_HEADER_SIZE, length)?;
+            Ok((chunk, tx.get_int(&block, NEXT_OFFSET)?))
+        });
+        tx.unpin(&block);
+
+        (self.chunk, self.next_block) = chunk?;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            if !self.load_next_chunk().map_err(std::io::Error::other)? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::db::TinyDB;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_can_write_and_read_chained_value() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_can_write_and_read_chained_value");
+        let db = TinyDB::new(test_directory, 100, 8)?;
+        let tx = db.transaction()?;
+
+        let overflow = OverflowFile::new(tx.clone(), "T.tbl");
+        assert_eq!(overflow.filename, "T.ovf");
+
+        let value: Vec<u8> = (0..250).map(|n| n as u8).collect();
+        let block_num = overflow.write(&value)?;
+        assert_eq!(overflow.read(block_num)?, value);
+        // 1ブロックに 92 バイトずつ格納される
+        assert_eq!(tx.lock().unwrap().size("T.ovf".into())?, 3);
+
+        let block_num = overflow.write(&[])?;
+        assert_eq!(block_num, NO_NEXT_BLOCK);
+        assert!(overflow.read(block_num)?.is_empty());
+
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_can_stream_value() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_can_stream_value");
+        let db = TinyDB::new(test_directory, 100, 8)?;
+        let tx = db.transaction()?;
+
+        let overflow = OverflowFile::new(tx.clone(), "T.tbl");
+        let value: Vec<u8> = (0..184).map(|n| n as u8).collect();
+        let (block_num, length) = overflow.write_from(&mut &value[..])?;
+        assert_eq!(length, 184);
+        // ちょうど2ブロック分
+        assert_eq!(tx.lock().unwrap().size("T.ovf".into())?, 2);
+
+        let mut reader = overflow.reader(block_num);
+        let mut buf = [0; 10];
+        let mut bytes = vec![];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(bytes, value);
+
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+}
@@ -1,20 +1,44 @@
-use super::layout::Layout;
-use crate::{file::block::BlockId, record::schema::FieldTypes, tx::transaction::Transaction};
-use anyhow::{anyhow, Result};
-use std::sync::{Arc, Mutex};
+use super::{
+    corrupt_record::CorruptRecord,
+    layout::{Layout, NULL_BITMAP_VERSION},
+    overflow::{BlobReader, OverflowFile, NO_NEXT_BLOCK},
+};
+use crate::{
+    file::{block::BlockId, date::Date, page::Page},
+    record::schema::FieldTypes,
+    tx::transaction::Transaction,
+    I32_SIZE,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RecordType {
     Empty,
     Used,
 }
 
-impl From<i32> for RecordType {
-    fn from(value: i32) -> Self {
+impl TryFrom<i32> for RecordType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i32) -> Result<Self> {
+        match RecordType::from_code(value) {
+            Some(record_type) => Ok(record_type),
+            None => bail!("invalid record type: {}", value),
+        }
+    }
+}
+
+impl RecordType {
+    /// from_code はスロットに格納された値からレコードタイプを返す。知らない値の場合は None を返す
+    pub fn from_code(value: i32) -> Option<Self> {
         match value {
-            0 => RecordType::Empty,
-            1 => RecordType::Used,
-            _ => panic!("invalid record type"),
+            0 => Some(RecordType::Empty),
+            1 => Some(RecordType::Used),
+            _ => None,
         }
     }
 }
@@ -28,26 +52,58 @@ impl From<RecordType> for i32 {
     }
 }
 
-/// RecordPage はスロットの集まりで構成される
+/// HEADER_SIZE はブロック先頭にあるヘッダのサイズ
+/// ヘッダは以下の3つの値で構成される
+///   - スキーマのバージョン
+///   - スロット数
+///   - 空き領域の終端（free-space pointer）。セルはここから前方に向かって確保される
+pub const HEADER_SIZE: i32 = 3 * I32_SIZE as i32;
+const VERSION_OFFSET: i32 = 0;
+const SLOT_COUNT_OFFSET: i32 = I32_SIZE as i32;
+const FREE_SPACE_OFFSET: i32 = 2 * I32_SIZE as i32;
+
+/// SLOT_ENTRY_SIZE はスロットディレクトリの1エントリのサイズ
+/// エントリはレコードタイプ、セルのオフセット、セルの長さで構成される
+pub const SLOT_ENTRY_SIZE: i32 = 3 * I32_SIZE as i32;
+const CELL_OFFSET: i32 = I32_SIZE as i32;
+const CELL_LENGTH: i32 = 2 * I32_SIZE as i32;
+
+/// records_per_block は record_size バイトのレコードが1つのブロックに入る数を見積もる
+/// ヘッダとスロットディレクトリのエントリの分も数え、レコードがブロックより大きくても1件とする
+pub fn records_per_block(block_size: i32, record_size: i32) -> i32 {
+    ((block_size - HEADER_SIZE) / (record_size + SLOT_ENTRY_SIZE).max(1)).max(1)
+}
+
+/// RecordPage はスロット付きページ（slotted page）としてレコードを管理する
+/// ブロックの先頭からヘッダとスロットディレクトリが並び、レコードの実体（セル）はブロックの末尾から詰めて配置される
 /// スロットはレコードを保持していて、1スロット:1レコードの関係
-/// ファイル・ブロック・スロット・レコードの関係性は以下のとおり
 ///
 /// ```text
-///                                              file
-/// ┏━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┓
-///                                   block                                             other bloks
-/// ┏━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━┻━━━━━━━━━━━┓
-///                        slot                                 other slots
-/// ┏━━━━━━━━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━┻━━━━━━━━━━━┓
-///                                record
-///                 ┏━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━┓
-/// ┌───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┐
-/// │ 1 │ 0 │ 0 │ 0 │ 5 │ 0 │ 0 │ 0 │ h │ e │ l │ l │ o │...│...│...│...│...│...│...│...│...│...│...│...│
-/// └───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┘
-/// ┗━━━━━━━┳━━━━━━━┻━━━━━━━┳━━━━━━━┻━━━━━━━━━┳━━━━━━━━━┛
-///    record type       integer         varchar(5)
-/// (0: emtpy, 1: used)
+///                                              block
+/// ┏━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┓
+/// ┌──────┬──────┬──────┬──────┬────────┬─────┬──────┬────────┬─────┬────────────┬─────────┬─────────┐
+/// │ ver  │ slot │ free │ type │ offset │ len │ type │ offset │ len │ free space │ cell 1  │ cell 0  │
+/// └──────┴──────┴──────┴──────┴────────┴─────┴──────┴────────┴─────┴────────────┴─────────┴─────────┘
+/// ┗━━━━━━━━━┳━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━━━━━━┛            ↑
+///        header                      slot directory                     free-space pointer
+///                                (type 0: emtpy, 1: used)
 /// ```
+///
+/// セルには NULL ビットマップに続けて、各フィールドがスキーマの順に詰めて格納される
+/// ビットマップはスキーマの i 番目のフィールドが NULL のときに i 番目のビットを立て、8 フィールドごとに 1 バイト使う
+/// NULL のフィールドにも値の領域は残し、その値は読まない
+/// Varchar は実際の文字列の長さしか使わないので、短い文字列で領域を無駄にしない
+///
+/// ```text
+/// ┌──────┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┬───┐
+/// │ 0b00 │ 1 │ 0 │ 0 │ 0 │ 5 │ 0 │ 0 │ 0 │ h │ e │ l │ l │ o │
+/// └──────┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┴───┘
+/// ┗━━┳━━━┻━━━━━━━┳━━━━━━━┻━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━━━┛
+///  nulls      integer                varchar
+/// ```
+///
+/// NULL_BITMAP_VERSION より前のバージョンでフォーマットしたブロックのセルにはビットマップがない
+/// そのようなブロックに NULL を書き込むときは、ブロックのすべてのレコードにビットマップを加えてからバージョンを上げる
 pub struct RecordPage {
     tx: Arc<Mutex<Transaction>>,
     pub block: BlockId,
@@ -55,117 +111,480 @@ pub struct RecordPage {
 }
 
 impl RecordPage {
-    pub fn new(tx: Arc<Mutex<Transaction>>, block: BlockId, layout: Arc<Layout>) -> Self {
-        tx.lock().unwrap().pin(&block);
-        Self { tx, block, layout }
+    pub fn new(tx: Arc<Mutex<Transaction>>, block: BlockId, layout: Arc<Layout>) -> Result<Self> {
+        tx.lock().unwrap().pin(&block)?;
+        Ok(Self { tx, block, layout })
     }
 
     /// get_int は指定したスロットにあるフィールドの値を取得する
-    /// フィールドの位置はスロットのオフセット + フィールドのオフセットで求める
+    /// フィールドの位置はセルの先頭から前のフィールドの長さを足していって求める
     pub fn get_int(&self, slot: i32, field_name: &str) -> Result<i32> {
-        let field_pos = self.offset(slot)
-            + self
-                .layout
-                .offset(field_name)
-                .ok_or_else(|| anyhow!("field offset not found"))?;
-        Ok(self.tx.lock().unwrap().get_int(&self.block, field_pos))
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx.lock().unwrap().get_int(&self.block, field_pos)
     }
 
+    /// get_string は指定したスロットにあるフィールドの文字列を取得する
+    /// オーバーフローページに格納されている場合はチェーンをたどって読み込む
     pub fn get_string(&self, slot: i32, field_name: &str) -> Result<String> {
-        let field_pos = self.offset(slot)
-            + self
-                .layout
-                .offset(field_name)
-                .ok_or_else(|| anyhow!("field offset not found"))?;
-        Ok(self.tx.lock().unwrap().get_string(&self.block, field_pos))
+        let mut bytes = vec![];
+        self.get_blob_reader(slot, field_name)?
+            .read_to_end(&mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    /// get_blob_reader は指定したスロットにあるフィールドの値を少しずつ読み込む BlobReader を返す
+    /// Text と Blob のほか、Varchar にも使える
+    pub fn get_blob_reader(&self, slot: i32, field_name: &str) -> Result<BlobReader> {
+        let field_pos = self.field_offset(slot, field_name)?;
+        let mut tx = self.tx.lock().unwrap();
+        match self.field_type(field_name)? {
+            FieldTypes::Text | FieldTypes::Blob => {
+                let block_num = tx.get_int(&self.block, field_pos + I32_SIZE as i32)?;
+                Ok(self.overflow_file().reader(block_num))
+            }
+            FieldTypes::Varchar => {
+                let length = tx.get_int(&self.block, field_pos)?;
+                if length < 0 {
+                    let block_num = tx.get_int(&self.block, field_pos + I32_SIZE as i32)?;
+                    return Ok(self.overflow_file().reader(block_num));
+                }
+                let bytes = tx.read_bytes(&self.block, field_pos + I32_SIZE as i32, length)?;
+                Ok(BlobReader::from_bytes(bytes))
+            }
+            _ => bail!("field is not a string or blob: {}", field_name),
+        }
+    }
+
+    /// set_blob は reader から読み込んだ値を指定したスロットにあるフィールドに書き込む
+    /// Text と Blob は値を1ブロック分ずつオーバーフローページに書き込むので、値全体をメモリに載せない
+    /// 古い値のチェーンは先に解放して、新しい値の書き込みに使い直す
+    pub fn set_blob(&mut self, slot: i32, field_name: &str, reader: &mut dyn Read) -> Result<()> {
+        match self.field_type(field_name)? {
+            FieldTypes::Text | FieldTypes::Blob => {
+                self.set_null(slot, field_name, false)?;
+                let old_chain = self.overflow_chain(slot, field_name)?;
+                self.free_overflow_chain(old_chain)?;
+                let (block_num, length) = self.overflow_file().write_from(reader)?;
+                let bytes = [length.to_le_bytes(), block_num.to_le_bytes()].concat();
+                let field_pos = self.field_offset(slot, field_name)?;
+                self.tx
+                    .lock()
+                    .unwrap()
+                    .write_bytes(&self.block, field_pos, &bytes, true)
+            }
+            FieldTypes::Varchar => {
+                let mut bytes = vec![];
+                reader.read_to_end(&mut bytes)?;
+                self.set_string(slot, field_name, String::from_utf8(bytes)?)
+            }
+            _ => bail!("field is not a string or blob: {}", field_name),
+        }
     }
 
     pub fn set_int(&mut self, slot: i32, field_name: &str, value: i32) -> Result<()> {
-        let field_pos = self.offset(slot)
-            + self
-                .layout
-                .offset(field_name)
-                .ok_or_else(|| anyhow!("field offset not found"))
-                .unwrap();
+        self.set_null(slot, field_name, false)?;
+        let field_pos = self.field_offset(slot, field_name)?;
         self.tx
             .lock()
             .unwrap()
             .set_int(&self.block, field_pos, value, true)
     }
 
-    pub fn set_string(&mut self, slot: i32, field_name: &str, value: String) -> Result<()> {
-        let field_pos = self.offset(slot)
-            + self
-                .layout
-                .offset(field_name)
-                .ok_or_else(|| anyhow!("field offset not found"))?;
+    pub fn get_long(&self, slot: i32, field_name: &str) -> Result<i64> {
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx.lock().unwrap().get_long(&self.block, field_pos)
+    }
+
+    pub fn get_double(&self, slot: i32, field_name: &str) -> Result<f64> {
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx.lock().unwrap().get_double(&self.block, field_pos)
+    }
+
+    pub fn get_bool(&self, slot: i32, field_name: &str) -> Result<bool> {
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx.lock().unwrap().get_bool(&self.block, field_pos)
+    }
+
+    pub fn get_date(&self, slot: i32, field_name: &str) -> Result<Date> {
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx.lock().unwrap().get_date(&self.block, field_pos)
+    }
+
+    pub fn set_long(&mut self, slot: i32, field_name: &str, value: i64) -> Result<()> {
+        self.set_null(slot, field_name, false)?;
+        let field_pos = self.field_offset(slot, field_name)?;
         self.tx
             .lock()
             .unwrap()
-            .set_string(&self.block, field_pos, value, true)
+            .set_long(&self.block, field_pos, value, true)
     }
 
-    pub fn delete(&mut self, slot: i32) -> Result<()> {
-        self.set_record_type(slot, RecordType::Empty)
+    pub fn set_double(&mut self, slot: i32, field_name: &str, value: f64) -> Result<()> {
+        self.set_null(slot, field_name, false)?;
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx
+            .lock()
+            .unwrap()
+            .set_double(&self.block, field_pos, value, true)
     }
 
-    /// format はレコードページを初期化する
-    /// 具体的に以下の処理をする
-    ///   - レコードタイプを Empty にする
-    ///   - 各フィールドを初期値で埋める
-    ///     - Integer の場合は 0
-    ///     - Varchar の場合は空文字
-    pub fn format(&mut self) -> Result<()> {
-        let mut slot = 0;
-        while self.is_valid_slot(slot) {
+    pub fn set_bool(&mut self, slot: i32, field_name: &str, value: bool) -> Result<()> {
+        self.set_null(slot, field_name, false)?;
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx
+            .lock()
+            .unwrap()
+            .set_bool(&self.block, field_pos, value, true)
+    }
+
+    pub fn set_date(&mut self, slot: i32, field_name: &str, value: Date) -> Result<()> {
+        self.set_null(slot, field_name, false)?;
+        let field_pos = self.field_offset(slot, field_name)?;
+        self.tx
+            .lock()
+            .unwrap()
+            .set_date(&self.block, field_pos, value, true)
+    }
+
+    /// set_string は指定したスロットにあるフィールドに文字列を書き込む
+    /// 文字列の長さが変わると後ろのフィールドの位置もずれるので、フィールド以降を書き直す
+    /// セルに収まらない場合は、新しいセルを確保してレコード全体を移動する
+    /// フィールドの長さ（文字数）を超える値は、フィールドの TruncationPolicy に従ってエラーにするか切り詰める
+    /// 古い値がオーバーフローページにある場合は、新しい値を書き込めてからそのチェーンを解放する
+    pub fn set_string(&mut self, slot: i32, field_name: &str, value: String) -> Result<()> {
+        if matches!(
+            self.field_type(field_name)?,
+            FieldTypes::Text | FieldTypes::Blob
+        ) {
+            return self.set_blob(slot, field_name, &mut value.as_bytes());
+        }
+
+        let max_length = self
+            .layout
+            .schema
+            .length(field_name)
+            .ok_or_else(|| anyhow!("field length not found"))?;
+        let value = self
+            .layout
+            .schema
+            .truncation(field_name)
+            .apply(field_name, value, max_length)?;
+
+        self.set_null(slot, field_name, false)?;
+        let cell = self.cell_offset(slot)?;
+        let field_pos = self.field_offset(slot, field_name)?;
+        let field_end = field_pos + self.field_length(slot, field_pos, field_name)?;
+        let record_end = cell + self.record_size(slot)?;
+        let (head, tail) = {
             let mut tx = self.tx.lock().unwrap();
-            tx.set_int(
+            let head = tx.read_bytes(&self.block, cell, field_pos - cell)?;
+            let tail = tx.read_bytes(&self.block, field_end, record_end - field_end)?;
+            (head, tail)
+        };
+
+        let old_chain = self.overflow_chain(slot, field_name)?;
+
+        let inline = Page::max_length(value.len()) as i32 <= self.max_inline_size();
+        let (field, mut new_chain) = if inline {
+            (Self::inline_string(&value), None)
+        } else {
+            let (field, block_num) = self.overflow_string(&value)?;
+            (field, Some(block_num))
+        };
+        if self.put_record(slot, &head, [field, tail.clone()].concat())? {
+            return self.free_overflow_chain(old_chain);
+        }
+
+        // ブロックに空きがなくてレコードを伸ばせない場合は、値をオーバーフローページに逃がす
+        if inline && !value.is_empty() {
+            let (field, block_num) = self.overflow_string(&value)?;
+            if self.put_record(slot, &head, [field, tail].concat())? {
+                return self.free_overflow_chain(old_chain);
+            }
+            new_chain = Some(block_num);
+        }
+        // 書き込めなかった値のチェーンは使われないので解放する
+        self.free_overflow_chain(new_chain)?;
+        bail!("record does not fit in block {}", self.block)
+    }
+
+    /// is_null は指定したスロットにあるフィールドが NULL かを返す
+    pub fn is_null(&self, slot: i32, field_name: &str) -> Result<bool> {
+        let Some((pos, mask)) = self.null_bit(slot, field_name)? else {
+            return Ok(false);
+        };
+        let byte = self.tx.lock().unwrap().read_bytes(&self.block, pos, 1)?[0];
+        Ok(byte & mask != 0)
+    }
+
+    /// set_null は指定したスロットにあるフィールドを NULL にするか、NULL でなくする
+    /// NULL でなくしたフィールドの値は、NULL にする前に格納されていた値になる
+    /// NULL ビットマップのないブロックに NULL を書き込む場合は、先にビットマップを加える
+    pub fn set_null(&mut self, slot: i32, field_name: &str, null: bool) -> Result<()> {
+        if null && self.null_bitmap_length()? == 0 {
+            self.add_null_bitmap()?;
+        }
+        let Some((pos, mask)) = self.null_bit(slot, field_name)? else {
+            return Ok(());
+        };
+        let mut tx = self.tx.lock().unwrap();
+        let byte = tx.read_bytes(&self.block, pos, 1)?[0];
+        let new_byte = if null { byte | mask } else { byte & !mask };
+        if new_byte != byte {
+            tx.write_bytes(&self.block, pos, &[new_byte], true)?;
+        }
+        Ok(())
+    }
+
+    /// null_bit はフィールドの NULL を表すビットがあるバイトの位置と、そのビットのマスクを返す
+    /// NULL ビットマップのないブロックでは None を返す
+    fn null_bit(&self, slot: i32, field_name: &str) -> Result<Option<(i32, u8)>> {
+        // フィールドの位置を求めて、スロットとフィールドがブロックに収まっていることを確かめる
+        self.field_offset(slot, field_name)?;
+        if self.null_bitmap_length()? == 0 {
+            return Ok(None);
+        }
+        let index = self
+            .layout
+            .schema
+            .fields
+            .iter()
+            .position(|field| field == field_name)
+            .ok_or_else(|| anyhow!("field not found: {}", field_name))? as i32;
+        Ok(Some((
+            self.cell_offset(slot)? + index / 8,
+            1 << (index % 8),
+        )))
+    }
+
+    /// null_bitmap_size はレイアウトのレコードの NULL ビットマップのバイト数を返す
+    fn null_bitmap_size(layout: &Layout) -> i32 {
+        layout.schema.fields.len().div_ceil(8) as i32
+    }
+
+    /// null_bitmap_length はこのブロックのセルの先頭にある NULL ビットマップのバイト数を返す
+    fn null_bitmap_length(&self) -> Result<i32> {
+        if self.version()? < NULL_BITMAP_VERSION {
+            return Ok(0);
+        }
+        Ok(Self::null_bitmap_size(&self.layout))
+    }
+
+    /// add_null_bitmap は NULL ビットマップのないブロックのすべてのレコードの先頭にビットマップを加えて、
+    /// ブロックのバージョンを上げる。加えたビットマップではどのフィールドも NULL ではない
+    /// レコードはビットマップの分だけ伸びるので、ブロックの末尾から詰め直す
+    /// 詰め直してもブロックに収まらない場合は何もせずにエラーを返す
+    fn add_null_bitmap(&mut self) -> Result<()> {
+        let bitmap = vec![0; Self::null_bitmap_size(&self.layout) as usize];
+        let reserved_record_size = self.reserved_record_size()? + bitmap.len() as i32;
+        let mut records = vec![];
+        for slot in 0..self.slot_count()? {
+            if self.get_record_type(&self.block, slot)? != Some(RecordType::Used) {
+                continue;
+            }
+            let cell = self.cell_offset(slot)?;
+            let size = self.record_size(slot)?;
+            let bytes = self
+                .tx
+                .lock()
+                .unwrap()
+                .read_bytes(&self.block, cell, size)?;
+            let mut record = [bitmap.as_slice(), &bytes].concat();
+            if (record.len() as i32) < reserved_record_size {
+                record.resize(reserved_record_size as usize, 0);
+            }
+            records.push((slot, record));
+        }
+
+        let block_size = self.tx.lock().unwrap().block_size();
+        let used: i32 = records.iter().map(|(_, record)| record.len() as i32).sum();
+        if self.offset(self.slot_count()?) + used > block_size {
+            bail!("record does not fit in block {}", self.block);
+        }
+        let mut free_space = block_size;
+        for (slot, record) in records {
+            free_space -= record.len() as i32;
+            self.tx
+                .lock()
+                .unwrap()
+                .write_bytes(&self.block, free_space, &record, true)?;
+            self.set_cell(slot, free_space, record.len() as i32)?;
+        }
+        let mut tx = self.tx.lock().unwrap();
+        tx.set_int(&self.block, FREE_SPACE_OFFSET, free_space, true)?;
+        tx.set_int(&self.block, VERSION_OFFSET, self.layout.version, true)
+    }
+
+    /// inline_string はセルに直接格納する文字列のバイト列を返す
+    fn inline_string(value: &str) -> Vec<u8> {
+        let mut bytes = (value.len() as i32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    /// overflow_string は値をオーバーフローページに書き込んで、セルに格納する参照のバイト列と
+    /// チェーンの先頭のブロック番号を返す
+    /// 参照は負にした文字列の長さと、チェーンの先頭のブロック番号で構成される
+    fn overflow_string(&self, value: &str) -> Result<(Vec<u8>, i32)> {
+        let block_num = self.overflow_file().write(value.as_bytes())?;
+        let mut bytes = (-(value.len() as i32)).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&block_num.to_le_bytes());
+        Ok((bytes, block_num))
+    }
+
+    /// free_overflow_chain は指定したブロックから始まるチェーンがあれば解放する
+    fn free_overflow_chain(&self, chain: Option<i32>) -> Result<()> {
+        match chain {
+            Some(block_num) => self.overflow_file().free(block_num),
+            None => Ok(()),
+        }
+    }
+
+    /// put_record は指定したスロットのレコードを head と rest をつなげたものに書き換える
+    /// head は変更しないので、セルに収まる場合は rest だけを書き込む
+    /// セルに収まらない場合は新しいセルを確保してレコード全体を移動する
+    /// ブロックに空きがない場合は何もせずに false を返す
+    fn put_record(&mut self, slot: i32, head: &[u8], rest: Vec<u8>) -> Result<bool> {
+        let cell = self.cell_offset(slot)?;
+        let record_size = (head.len() + rest.len()) as i32;
+        if record_size <= self.cell_length(slot)? {
+            self.tx.lock().unwrap().write_bytes(
                 &self.block,
-                self.offset(slot),
-                RecordType::Empty.into(),
-                false,
+                cell + head.len() as i32,
+                &rest,
+                true,
             )?;
+            return Ok(true);
+        }
+
+        let Some(new_cell) = self.allocate(record_size, 0, Some(slot))? else {
+            return Ok(false);
+        };
+        let record = [head, &rest].concat();
+        self.tx
+            .lock()
+            .unwrap()
+            .write_bytes(&self.block, new_cell, &record, true)?;
+        self.set_cell(slot, new_cell, record_size)?;
+        Ok(true)
+    }
+
+    /// overflow_file はこのブロックのテーブルに対応するオーバーフローファイルを返す
+    fn overflow_file(&self) -> OverflowFile {
+        OverflowFile::new(self.tx.clone(), &self.block.filename)
+    }
 
-            let schema = &self.layout.schema;
-            for field_name in &schema.fields {
-                // ブロックにあるスロットのオフセット + フィールドのオフセット = フィールドの位置
-                // フィールドのオフセット自体は変わらないが、ブロックにあるスロットの断片化を防ぐためスロットの位置が調整されることがあるため
-                // スロットのオフセットは変わることがある
-                let field_pos = self.offset(slot)
-                    + self
-                        .layout
-                        .offset(field_name)
-                        .ok_or_else(|| anyhow!("field offset not found"))?;
-                let field_type = schema
-                    .r#type(field_name)
-                    .ok_or_else(|| anyhow!("field type not found"))?;
-                match field_type {
-                    FieldTypes::Integer => {
-                        tx.set_int(&self.block, field_pos, 0, false)?;
-                    }
-                    FieldTypes::Varchar => {
-                        tx.set_string(&self.block, field_pos, "".into(), false)?;
-                    }
+    /// overflow_chain は指定したスロットにあるフィールドが参照しているオーバーフローページのチェーンの
+    /// 先頭のブロック番号を返す。チェーンを参照していない場合は None を返す
+    fn overflow_chain(&self, slot: i32, field_name: &str) -> Result<Option<i32>> {
+        let field_pos = self.field_offset(slot, field_name)?;
+        let mut tx = self.tx.lock().unwrap();
+        let block_num = match self.field_type(field_name)? {
+            FieldTypes::Text | FieldTypes::Blob => {
+                tx.get_int(&self.block, field_pos + I32_SIZE as i32)?
+            }
+            FieldTypes::Varchar if tx.get_int(&self.block, field_pos)? < 0 => {
+                tx.get_int(&self.block, field_pos + I32_SIZE as i32)?
+            }
+            _ => NO_NEXT_BLOCK,
+        };
+        Ok((block_num != NO_NEXT_BLOCK).then_some(block_num))
+    }
+
+    /// delete は指定したスロットを空きにして、レコードが参照しているオーバーフローページのチェーンを解放する
+    /// セルの領域は次にコンパクションしたときに回収される
+    /// 壊れたレコードでも消せるように、チェーンを読めない場合は解放せずに残す
+    pub fn delete(&mut self, slot: i32) -> Result<()> {
+        let mut chains = vec![];
+        for field_name in &self.layout.schema.fields {
+            match self.overflow_chain(slot, field_name) {
+                Ok(chain) => chains.extend(chain),
+                Err(e) if e.is::<CorruptRecord>() => {
+                    chains.clear();
+                    break;
                 }
+                Err(e) => return Err(e),
             }
-            slot += 1;
         }
+        for block_num in chains {
+            self.overflow_file().free(block_num)?;
+        }
+        self.set_record_type(slot, RecordType::Empty)
+    }
+
+    /// format はレコードページを初期化する
+    /// ヘッダにスキーマのバージョン、スロット数（0）、空き領域の終端（ブロックの末尾）を書き込む
+    /// スロットとセルは insert_after で必要になったときに作られる
+    pub fn format(&mut self) -> Result<()> {
+        let mut tx = self.tx.lock().unwrap();
+        let block_size = tx.block_size();
+        tx.set_int(&self.block, VERSION_OFFSET, self.layout.version, false)?;
+        tx.set_int(&self.block, SLOT_COUNT_OFFSET, 0, false)?;
+        tx.set_int(&self.block, FREE_SPACE_OFFSET, block_size, false)?;
         Ok(())
     }
 
     /// next_after は次の使われているスロット番号を返す
-    pub fn next_after(&self, slot: i32) -> i32 {
+    pub fn next_after(&self, slot: i32) -> Result<i32> {
         self.search_after(slot, RecordType::Used)
     }
 
+    /// prev_before は指定したスロットより前にある使われているスロット番号を返す
+    /// スロット数以上の値を指定すると最後のスロットから探す
+    pub fn prev_before(&self, slot: i32) -> Result<i32> {
+        let mut slot = slot.min(self.slot_count()?) - 1;
+        while slot >= 0 {
+            if self.get_record_type(&self.block, slot)? == Some(RecordType::Used) {
+                return Ok(slot);
+            }
+            slot -= 1;
+        }
+        Ok(-1)
+    }
+
     /// insert_after は指定したスロットのあとに新しい空きスロットを検索して
     /// 利用中に変更して、そのスロット番号を返す
-    /// 空きスロットがない場合は -1 を返す
+    /// 空きスロットがなければスロットディレクトリを伸ばす
+    /// レコードが最大長まで伸びても収まるだけの空きがない場合は -1 を返す
     pub fn insert_after(&mut self, slot: i32) -> Result<i32> {
-        let new_slot = self.search_after(slot, RecordType::Empty);
-        if new_slot >= 0 {
-            self.set_record_type(new_slot, RecordType::Used)?;
+        let empty_slot = self.search_after(slot, RecordType::Empty)?;
+        let (new_slot, entry_size) = if empty_slot >= 0 {
+            (empty_slot, 0)
+        } else {
+            (self.slot_count()?, SLOT_ENTRY_SIZE)
+        };
+
+        if self.total_free_space(None)? - entry_size < self.max_record_size()? {
+            return Ok(-1);
+        }
+
+        // NULL のフィールドはなく、長さが決まっている型は 0、Varchar は長さ 0 の空文字で初期化する
+        // Text と Blob は長さ 0 で、オーバーフローページのチェーンを持たない
+        let mut record = vec![0; self.null_bitmap_length()? as usize];
+        for field_name in &self.layout.schema.fields {
+            let field_type = self.field_type(field_name)?;
+            match field_type.fixed_length() {
+                Some(length) => record.extend(vec![0; length as usize]),
+                None => record.extend(0i32.to_le_bytes()),
+            }
+            if matches!(field_type, FieldTypes::Text | FieldTypes::Blob) {
+                record.extend(NO_NEXT_BLOCK.to_le_bytes());
+            }
         }
+        let cell_length = self.reserved_record_size()?;
+        let Some(cell) = self.allocate(cell_length, entry_size, None)? else {
+            return Ok(-1);
+        };
+
+        {
+            let mut tx = self.tx.lock().unwrap();
+            if entry_size > 0 {
+                tx.set_int(&self.block, SLOT_COUNT_OFFSET, new_slot + 1, true)?;
+            }
+            tx.write_bytes(&self.block, cell, &record, true)?;
+        }
+        self.set_cell(new_slot, cell, cell_length)?;
+        self.set_record_type(new_slot, RecordType::Used)?;
         Ok(new_slot)
     }
 
@@ -179,34 +598,312 @@ impl RecordPage {
     /// search_after は指定したスロットの次のスロットから指定したレコードタイプのスロットを検索して
     /// スロット番号を返す
     /// 見つからない場合は -1 を返す
-    fn search_after(&self, slot: i32, record_type: RecordType) -> i32 {
+    fn search_after(&self, slot: i32, record_type: RecordType) -> Result<i32> {
         let mut slot = slot + 1;
-        while self.is_valid_slot(slot) {
-            if self.get_record_type(&self.block, slot) == record_type {
-                return slot;
+        while self.is_valid_slot(slot)? {
+            if self.get_record_type(&self.block, slot)? == Some(record_type) {
+                return Ok(slot);
             }
             slot += 1;
         }
-        -1
+        Ok(-1)
+    }
+
+    /// record_type_code は指定したスロットのレコードタイプの値を変換せずに返す
+    pub fn record_type_code(&self, slot: i32) -> Result<i32> {
+        let offset = self.offset(slot);
+        self.tx.lock().unwrap().get_int(&self.block, offset)
     }
 
     /// get_record_type は指定したスロットのレコードタイプを返す
-    fn get_record_type(&self, block: &BlockId, slot: i32) -> RecordType {
+    /// 壊れて空きでも使用中でもない値になっているスロットは None を返し、読み書きの対象にしない
+    fn get_record_type(&self, block: &BlockId, slot: i32) -> Result<Option<RecordType>> {
         let offset = self.offset(slot);
         let mut tx = self.tx.lock().unwrap();
-        tx.get_int(block, offset).into()
+        Ok(RecordType::from_code(tx.get_int(block, offset)?))
     }
 
     /// is_valid_slot は指定したスロットが有効かどうかを返す
-    /// 有効なスロットとは、スロットの位置がブロックの範囲内に収まっているかどうか
-    pub fn is_valid_slot(&self, slot: i32) -> bool {
-        self.offset(slot + 1) <= self.tx.lock().unwrap().block_size()
+    /// 有効なスロットとは、ヘッダに記録されたスロット数の範囲内にあるスロット
+    /// フォーマットされていないブロックはスロット数が 0 なので、有効なスロットはない
+    /// スロット数が壊れていても、ディレクトリエントリがブロックからはみ出すスロットは無効とする
+    pub fn is_valid_slot(&self, slot: i32) -> Result<bool> {
+        let block_size = self.tx.lock().unwrap().block_size();
+        Ok(slot >= 0
+            && slot < self.slot_count()?
+            && self.offset(slot) + SLOT_ENTRY_SIZE <= block_size)
+    }
+
+    /// version はブロックをフォーマットしたときのスキーマのバージョンを返す
+    pub fn version(&self) -> Result<i32> {
+        self.tx.lock().unwrap().get_int(&self.block, VERSION_OFFSET)
+    }
+
+    /// slot_count はブロックにあるスロット数を返す
+    pub fn slot_count(&self) -> Result<i32> {
+        self.tx
+            .lock()
+            .unwrap()
+            .get_int(&self.block, SLOT_COUNT_OFFSET)
+    }
+
+    /// is_formatted はブロックがフォーマットされているかを返す
+    /// まとめて追加したまま使っていないブロックは 0 で埋められていて、空き領域の終端が 0 になる
+    pub fn is_formatted(&self) -> Result<bool> {
+        Ok(self.free_space()? > 0)
+    }
+
+    /// free_space はブロック内の空き領域の終端を返す
+    pub fn free_space(&self) -> Result<i32> {
+        self.tx
+            .lock()
+            .unwrap()
+            .get_int(&self.block, FREE_SPACE_OFFSET)
+    }
+
+    /// available_space は新しいレコードのセルに使える空き領域のバイト数を返す
+    /// 空きスロットがなければ、スロットディレクトリを伸ばす分を差し引く
+    pub fn available_space(&self) -> Result<i32> {
+        let entry_size = if self.search_after(-1, RecordType::Empty)? >= 0 {
+            0
+        } else {
+            SLOT_ENTRY_SIZE
+        };
+        Ok(self.total_free_space(None)? - entry_size)
     }
 
-    /// offset は指定したスロットのオフセットを返す
-    /// オフセットはブロックの先頭からの位置を表す
+    /// required_space はレコードを挿入するのに必要な空き領域のバイト数を返す
+    /// available_space がこれ以上あれば insert_after は必ず成功する
+    pub fn required_space(&self) -> Result<i32> {
+        self.max_record_size()
+    }
+
+    /// offset は指定したスロットのディレクトリエントリのオフセットを返す
+    /// オフセットはブロックの先頭からの位置を表し、ヘッダの分だけずれる
     pub fn offset(&self, slot: i32) -> i32 {
-        self.layout.slot_size * slot
+        HEADER_SIZE + SLOT_ENTRY_SIZE * slot
+    }
+
+    /// cell_offset は指定したスロットのセルのオフセットを返す
+    pub fn cell_offset(&self, slot: i32) -> Result<i32> {
+        let offset = self.offset(slot) + CELL_OFFSET;
+        self.tx.lock().unwrap().get_int(&self.block, offset)
+    }
+
+    /// cell_length は指定したスロットに確保されているセルの長さを返す
+    pub fn cell_length(&self, slot: i32) -> Result<i32> {
+        let offset = self.offset(slot) + CELL_LENGTH;
+        self.tx.lock().unwrap().get_int(&self.block, offset)
+    }
+
+    /// record_size は指定したスロットのレコードが実際に使っているバイト数を返す
+    pub fn record_size(&self, slot: i32) -> Result<i32> {
+        let cell = self.cell_offset(slot)?;
+        let mut pos = cell + self.null_bitmap_length()?;
+        for field_name in &self.layout.schema.fields {
+            pos += self.field_length(slot, pos, field_name)?;
+        }
+        Ok(pos - cell)
+    }
+
+    /// set_cell は指定したスロットのディレクトリエントリにセルの位置と長さを書き込む
+    fn set_cell(&self, slot: i32, cell: i32, length: i32) -> Result<()> {
+        let offset = self.offset(slot);
+        let mut tx = self.tx.lock().unwrap();
+        tx.set_int(&self.block, offset + CELL_OFFSET, cell, true)?;
+        tx.set_int(&self.block, offset + CELL_LENGTH, length, true)
+    }
+
+    /// field_offset は指定したスロットにあるフィールドのブロック内での位置を返す
+    /// 前のフィールドとそのフィールドの値がブロックに収まっていなければ CorruptRecord を返す
+    fn field_offset(&self, slot: i32, field_name: &str) -> Result<i32> {
+        if !self.layout.schema.has_field(field_name) {
+            return Err(self.corrupt(slot, Some(field_name), "field is not in the layout"));
+        }
+        if !self.is_valid_slot(slot)? {
+            return Err(self.corrupt(slot, Some(field_name), "slot is not in the directory"));
+        }
+        let mut pos = self.cell_offset(slot)? + self.null_bitmap_length()?;
+        for field in &self.layout.schema.fields {
+            let length = self.field_length(slot, pos, field)?;
+            if field == field_name {
+                return Ok(pos);
+            }
+            pos += length;
+        }
+        bail!("field offset not found: {}", field_name)
+    }
+
+    /// check_bounds は slot のレコードの pos から length バイトがセルを置ける領域に収まっているかを確かめる
+    fn check_bounds(&self, slot: i32, field_name: &str, pos: i32, length: i32) -> Result<()> {
+        let block_size = self.tx.lock().unwrap().block_size();
+        if pos < HEADER_SIZE || length < 0 || pos as i64 + length as i64 > block_size as i64 {
+            let reason = format!("{} bytes at offset {} are out of the block", length, pos);
+            return Err(self.corrupt(slot, Some(field_name), reason));
+        }
+        Ok(())
+    }
+
+    /// corrupt はこのブロックの slot のレコードが壊れていることを表すエラーを返す
+    fn corrupt(
+        &self,
+        slot: i32,
+        field_name: Option<&str>,
+        reason: impl Into<String>,
+    ) -> anyhow::Error {
+        CorruptRecord::new(&self.block, slot, field_name, reason).into()
+    }
+
+    /// field_type は指定したフィールドの型を返す
+    fn field_type(&self, field_name: &str) -> Result<FieldTypes> {
+        self.layout
+            .schema
+            .r#type(field_name)
+            .ok_or_else(|| anyhow!("field type not found"))
+    }
+
+    /// field_length は slot のレコードの指定した位置にあるフィールドが使っているバイト数を返す
+    /// フィールドがブロックからはみ出している場合は CorruptRecord を返す
+    fn field_length(&self, slot: i32, pos: i32, field_name: &str) -> Result<i32> {
+        let field_type = self.field_type(field_name)?;
+        let length = match field_type {
+            // 値の長さとオーバーフローページのチェーンの先頭のブロック番号
+            FieldTypes::Text | FieldTypes::Blob => 2 * I32_SIZE as i32,
+            FieldTypes::Varchar => {
+                self.check_bounds(slot, field_name, pos, I32_SIZE as i32)?;
+                let length = self.tx.lock().unwrap().get_int(&self.block, pos)?;
+                if length < 0 {
+                    // オーバーフローページへの参照（長さとブロック番号）
+                    2 * I32_SIZE as i32
+                } else {
+                    (I32_SIZE as i32).saturating_add(length)
+                }
+            }
+            _ => field_type
+                .fixed_length()
+                .ok_or_else(|| anyhow!("field length not found"))?,
+        };
+        self.check_bounds(slot, field_name, pos, length)?;
+        Ok(length)
+    }
+
+    /// max_inline_size はセルに直接格納する文字列の最大バイト数（長さを含む）を返す
+    /// これを超える文字列はオーバーフローページに格納する
+    fn max_inline_size(&self) -> i32 {
+        self.tx.lock().unwrap().block_size() / 4
+    }
+
+    /// max_record_size はセルに格納されるレコードの最大長を返す
+    /// 大きな文字列はオーバーフローページに逃がすので、1つのブロックに収まる長さまでに抑える
+    fn max_record_size(&self) -> Result<i32> {
+        let max_inline_size = self.max_inline_size();
+        let mut size = self.null_bitmap_length()?;
+        for field_name in &self.layout.schema.fields {
+            size += match self.layout.schema.r#type(field_name) {
+                Some(FieldTypes::Varchar) => {
+                    let length = Layout::length_in_bytes(&self.layout.schema, field_name)?;
+                    length.min(max_inline_size)
+                }
+                Some(_) => Layout::length_in_bytes(&self.layout.schema, field_name)?,
+                None => bail!("field type not found"),
+            };
+        }
+        let block_size = self.tx.lock().unwrap().block_size();
+        Ok(size.min(block_size - HEADER_SIZE - SLOT_ENTRY_SIZE))
+    }
+
+    /// reserved_record_size はセルとして最低限確保しておく長さを返す
+    /// Varchar はオーバーフローページへの参照が収まる分を確保しておくので、
+    /// ブロックに空きがなくなっても値をオーバーフローページに逃がせば必ず書き込める
+    fn reserved_record_size(&self) -> Result<i32> {
+        let mut size = self.null_bitmap_length()?;
+        for field_name in &self.layout.schema.fields {
+            let length = Layout::length_in_bytes(&self.layout.schema, field_name)?;
+            size += length.min(2 * I32_SIZE as i32);
+        }
+        Ok(size)
+    }
+
+    /// total_free_space はコンパクションしたときに使える空き領域の合計を返す
+    /// exclude に指定したスロットのセルも空き領域として数える
+    fn total_free_space(&self, exclude: Option<i32>) -> Result<i32> {
+        let block_size = self.tx.lock().unwrap().block_size();
+        let slot_count = self.slot_count()?;
+        let mut used = HEADER_SIZE + SLOT_ENTRY_SIZE * slot_count;
+        for slot in 0..slot_count {
+            if Some(slot) != exclude
+                && self.get_record_type(&self.block, slot)? == Some(RecordType::Used)
+            {
+                used += self.cell_length(slot)?;
+            }
+        }
+        Ok(block_size - used)
+    }
+
+    /// allocate は空き領域から length バイトのセルを確保して、そのオフセットを返す
+    /// entry_size はスロットディレクトリを伸ばす分のサイズ
+    /// 連続した空き領域が足りない場合はコンパクションしてから確保する
+    /// それでも足りない場合は None を返す
+    fn allocate(
+        &mut self,
+        length: i32,
+        entry_size: i32,
+        exclude: Option<i32>,
+    ) -> Result<Option<i32>> {
+        let directory_end = self.offset(self.slot_count()?) + entry_size;
+        let mut free_space = self.free_space()?;
+        if free_space - directory_end < length {
+            if self.total_free_space(exclude)? - entry_size < length {
+                return Ok(None);
+            }
+            free_space = self.compact(exclude)?;
+        }
+
+        let cell = free_space - length;
+        self.tx
+            .lock()
+            .unwrap()
+            .set_int(&self.block, FREE_SPACE_OFFSET, cell, true)?;
+        Ok(Some(cell))
+    }
+
+    /// compact は使われているセルをブロックの末尾に詰め直して、断片化した空き領域をまとめる
+    /// exclude に指定したスロットのセルは移動しない（呼び出し元で新しいセルに書き直す）
+    /// 詰め直したあとの空き領域の終端を返す
+    fn compact(&mut self, exclude: Option<i32>) -> Result<i32> {
+        let reserved_record_size = self.reserved_record_size()?;
+        let mut cells = vec![];
+        for slot in 0..self.slot_count()? {
+            if Some(slot) == exclude
+                || self.get_record_type(&self.block, slot)? != Some(RecordType::Used)
+            {
+                continue;
+            }
+            let cell = self.cell_offset(slot)?;
+            let size = self.record_size(slot)?.max(reserved_record_size);
+            let bytes = self
+                .tx
+                .lock()
+                .unwrap()
+                .read_bytes(&self.block, cell, size)?;
+            cells.push((slot, bytes));
+        }
+
+        let mut free_space = self.tx.lock().unwrap().block_size();
+        for (slot, bytes) in cells {
+            let length = bytes.len() as i32;
+            free_space -= length;
+            self.tx
+                .lock()
+                .unwrap()
+                .write_bytes(&self.block, free_space, &bytes, true)?;
+            self.set_cell(slot, free_space, length)?;
+        }
+        self.tx
+            .lock()
+            .unwrap()
+            .set_int(&self.block, FREE_SPACE_OFFSET, free_space, true)?;
+        Ok(free_space)
     }
 }
 
@@ -214,9 +911,11 @@ impl RecordPage {
 mod tests {
     use super::*;
     use crate::{
-        buffer::buffer_manager::BufferManager, file::file_manager::FileManager,
-        log::log_manager::LogManager, record::schema::Schema,
-        tx::concurrency::lock_table::LockTable, LOG_FILE,
+        buffer::buffer_manager::BufferManager,
+        file::file_manager::FileManager,
+        log::log_manager::{LogManager, DEFAULT_LOG_FILE},
+        record::{layout::SCHEMA_VERSION, schema::Schema},
+        tx::concurrency::lock_table::LockTable,
     };
     use std::{path::Path, sync::Condvar};
     use tempfile::tempdir;
@@ -225,13 +924,13 @@ mod tests {
         let block_size = 128;
         let file_manager = Arc::new(Mutex::new(FileManager::new(db_dir, block_size).unwrap()));
         let log_manager = Arc::new(Mutex::new(
-            LogManager::new(file_manager.clone(), LOG_FILE.into()).unwrap(),
+            LogManager::new(file_manager.clone(), DEFAULT_LOG_FILE.into()).unwrap(),
         ));
-        let buffer_manager = Arc::new(Mutex::new(BufferManager::new(
+        let buffer_manager = Arc::new(BufferManager::new(
             file_manager.clone(),
             log_manager.clone(),
             10,
-        )));
+        ));
         let lock_table = Arc::new((Mutex::new(LockTable::default()), Condvar::new()));
 
         let tx = Transaction::new(file_manager, log_manager, buffer_manager, lock_table).unwrap();
@@ -239,13 +938,16 @@ mod tests {
         Arc::new(Mutex::new(tx))
     }
 
-    #[test]
-    fn should_can_format() {
+    fn new_layout() -> Arc<Layout> {
         let mut schema = Schema::default();
         schema.add_int_field("id");
         schema.add_string_field("name", 8);
-        let schema = Arc::new(schema);
-        let layout = Arc::new(Layout::try_from_schema(schema.clone()).unwrap());
+        Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap())
+    }
+
+    #[test]
+    fn should_can_format() {
+        let layout = new_layout();
 
         // 4bytes: record type
         // 4bytes: id
@@ -256,59 +958,433 @@ mod tests {
         let db_dir = tempdir().unwrap();
         let tx = new_transaction(db_dir.path());
         let block = BlockId::new("testfile".into(), 0);
-        let mut rp = RecordPage::new(tx.clone(), block, layout);
+        let mut rp = RecordPage::new(tx.clone(), block, layout).unwrap();
 
         rp.format().unwrap();
 
-        let slot = 0;
-        assert_eq!(rp.get_int(slot, "id").unwrap(), 0);
-        assert_eq!(rp.get_string(slot, "name").unwrap(), "");
+        assert_eq!(rp.version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(rp.slot_count().unwrap(), 0);
+        assert_eq!(rp.free_space().unwrap(), 128);
+        assert!(!rp.is_valid_slot(0).unwrap());
     }
 
     #[test]
-    fn should_can_set_record_data() {
+    fn should_have_no_valid_slot_before_format() {
         let mut schema = Schema::default();
         schema.add_int_field("id");
-        schema.add_string_field("name", 8);
-        let schema = Arc::new(schema);
-        let layout = Arc::new(Layout::try_from_schema(schema.clone()).unwrap());
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let rp = RecordPage::new(tx.clone(), block, layout).unwrap();
+
+        assert_eq!(rp.slot_count().unwrap(), 0);
+        assert!(!rp.is_valid_slot(0).unwrap());
+        assert_eq!(rp.next_after(-1).unwrap(), -1);
+    }
+
+    #[test]
+    fn should_find_previous_used_slot() {
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, new_layout()).unwrap();
+        rp.format().unwrap();
+        for _ in 0..3 {
+            rp.insert_after(-1).unwrap();
+        }
+        rp.delete(1).unwrap();
+
+        assert_eq!(rp.prev_before(i32::MAX).unwrap(), 2);
+        assert_eq!(rp.prev_before(2).unwrap(), 0);
+        assert_eq!(rp.prev_before(0).unwrap(), -1);
+    }
+
+    #[test]
+    fn should_report_corrupt_record_instead_of_panicking() {
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block.clone(), new_layout()).unwrap();
+        rp.format().unwrap();
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_string(slot, "name", "hello".into()).unwrap();
+        let corrupt = |result: Result<String>| {
+            let err = result.unwrap_err();
+            err.downcast_ref::<CorruptRecord>().unwrap().clone()
+        };
+
+        // 文字列の長さがブロックからはみ出す
+        let name_pos = rp.field_offset(slot, "name").unwrap();
+        tx.lock()
+            .unwrap()
+            .set_int(&block, name_pos, 10000, false)
+            .unwrap();
+        let err = corrupt(rp.get_string(slot, "name"));
+        assert_eq!(
+            (err.block, err.slot, err.field.as_deref()),
+            (block.clone(), slot, Some("name"))
+        );
+        assert!(rp.record_size(slot).is_err());
+        // 前のフィールドは読める
+        assert_eq!(rp.get_int(slot, "id").unwrap(), 0);
+
+        // セルの位置がブロックの外を指す
+        let cell_pos = rp.offset(slot) + CELL_OFFSET;
+        tx.lock()
+            .unwrap()
+            .set_int(&block, cell_pos, 1000, false)
+            .unwrap();
+        let err = corrupt(rp.get_int(slot, "id").map(|id| id.to_string()));
+        assert_eq!(err.field.as_deref(), Some("id"));
+        assert!(err.to_string().starts_with("corrupt record in"));
+
+        // レイアウトにないフィールドと、ディレクトリにないスロット
+        assert_eq!(
+            corrupt(rp.get_string(slot, "missing")).field.as_deref(),
+            Some("missing")
+        );
+        assert_eq!(corrupt(rp.get_string(slot + 1, "name")).slot, slot + 1);
+
+        // スロット数が壊れていても、ブロックの外のディレクトリエントリは読まない
+        tx.lock()
+            .unwrap()
+            .set_int(&block, SLOT_COUNT_OFFSET, i32::MAX, false)
+            .unwrap();
+        assert_eq!(rp.next_after(slot).unwrap(), -1);
+        assert!(RecordType::try_from(7).is_err());
+        assert_eq!(RecordType::try_from(1).unwrap(), RecordType::Used);
+    }
 
+    #[test]
+    fn should_can_set_record_data() {
         let db_dir = tempdir().unwrap();
         let tx = new_transaction(db_dir.path());
         let block = BlockId::new("testfile".into(), 0);
-        let mut rp = RecordPage::new(tx.clone(), block, layout);
+        let mut rp = RecordPage::new(tx.clone(), block, new_layout()).unwrap();
 
         rp.format().unwrap();
 
-        let slot = 0;
+        let slot = rp.insert_after(-1).unwrap();
+        assert_eq!(slot, 0);
+        assert_eq!(rp.get_int(slot, "id").unwrap(), 0);
+        assert_eq!(rp.get_string(slot, "name").unwrap(), "");
+
         rp.set_int(slot, "id", 1).unwrap();
         rp.set_string(slot, "name", "hello".into()).unwrap();
 
         assert_eq!(rp.get_int(slot, "id").unwrap(), 1);
         assert_eq!(rp.get_string(slot, "name").unwrap(), "hello");
+        // 1byte: null bitmap
+        // 4bytes: id
+        // 4bytes: name length
+        // 5bytes: name
+        assert_eq!(rp.record_size(slot).unwrap(), 14);
+
+        rp.set_string(slot, "name", "hi".into()).unwrap();
+        assert_eq!(rp.get_string(slot, "name").unwrap(), "hi");
+        assert_eq!(rp.get_int(slot, "id").unwrap(), 1);
+        assert_eq!(rp.record_size(slot).unwrap(), 11);
+
+        assert!(rp.set_string(slot, "name", "too long!".into()).is_err());
     }
 
     #[test]
-    fn should_can_delete() {
+    fn should_store_only_actual_string_length() {
         let mut schema = Schema::default();
         schema.add_int_field("id");
-        schema.add_string_field("name", 8);
-        let schema = Arc::new(schema);
-        let layout = Arc::new(Layout::try_from_schema(schema.clone()).unwrap());
+        schema.add_string_field("name", 20);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+        assert_eq!(layout.slot_size, 32);
+
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, layout).unwrap();
+
+        rp.format().unwrap();
+
+        // 固定長スロットだと (128 - 12) / 32 = 3 レコードしか入らない
+        let mut slot = rp.insert_after(-1).unwrap();
+        let mut n = 0;
+        while slot >= 0 {
+            rp.set_int(slot, "id", n).unwrap();
+            rp.set_string(slot, "name", "a".into()).unwrap();
+            n += 1;
+            slot = rp.insert_after(slot).unwrap();
+        }
+        assert!(n > 3, "only {} records inserted", n);
+
+        let mut slot = rp.next_after(-1).unwrap();
+        let mut expected = 0;
+        while slot >= 0 {
+            assert_eq!(rp.get_int(slot, "id").unwrap(), expected);
+            assert_eq!(rp.get_string(slot, "name").unwrap(), "a");
+            expected += 1;
+            slot = rp.next_after(slot).unwrap();
+        }
+        assert_eq!(expected, n);
+    }
 
+    #[test]
+    fn should_compact_when_record_grows() {
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, new_layout()).unwrap();
+
+        rp.format().unwrap();
+
+        let mut slots = vec![];
+        let mut slot = rp.insert_after(-1).unwrap();
+        while slot >= 0 {
+            rp.set_int(slot, "id", slot).unwrap();
+            slots.push(slot);
+            slot = rp.insert_after(slot).unwrap();
+        }
+
+        // 空いたセルをコンパクションで回収して、伸びたレコードを収める
+        for &slot in slots.iter().step_by(2) {
+            rp.delete(slot).unwrap();
+        }
+        for &slot in slots.iter().skip(1).step_by(2) {
+            rp.set_string(slot, "name", "abcdefgh".into()).unwrap();
+        }
+        for &slot in slots.iter().skip(1).step_by(2) {
+            assert_eq!(rp.get_int(slot, "id").unwrap(), slot);
+            assert_eq!(rp.get_string(slot, "name").unwrap(), "abcdefgh");
+        }
+    }
+
+    #[test]
+    fn should_store_large_value_in_overflow_pages() {
+        let mut schema = Schema::default();
+        schema.add_int_field("id");
+        schema.add_string_field("body", 1000);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+        // 1レコードがブロックより大きい
+        assert!(layout.slot_size > 128);
+
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile.tbl".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, layout).unwrap();
+
+        rp.format().unwrap();
+
+        let body = "x".repeat(1000);
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_int(slot, "id", 1).unwrap();
+        rp.set_string(slot, "body", body.clone()).unwrap();
+
+        assert_eq!(rp.get_string(slot, "body").unwrap(), body);
+        assert_eq!(rp.get_int(slot, "id").unwrap(), 1);
+        // 1byte: null bitmap
+        // 4bytes: id
+        // 4bytes: -(body length)
+        // 4bytes: overflow block number
+        assert_eq!(rp.record_size(slot).unwrap(), 13);
+        assert!(tx.lock().unwrap().size("testfile.ovf".into()).unwrap() > 0);
+
+        // 短い値に書き換えるとセルに直接格納される
+        rp.set_string(slot, "body", "short".into()).unwrap();
+        assert_eq!(rp.get_string(slot, "body").unwrap(), "short");
+        assert_eq!(rp.record_size(slot).unwrap(), 14);
+    }
+
+    #[test]
+    fn should_reuse_overflow_pages_of_replaced_value() {
+        let mut schema = Schema::default();
+        schema.add_int_field("id");
+        schema.add_text_field("body");
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile.tbl".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, layout).unwrap();
+
+        rp.format().unwrap();
+
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_string(slot, "body", "a".repeat(500)).unwrap();
+        let size = tx.lock().unwrap().size("testfile.ovf".into()).unwrap();
+
+        // 書き換えるたびに古い値のチェーンを使い直すので、ファイルは伸びない
+        for n in 0..10 {
+            let body = n.to_string().repeat(500);
+            rp.set_string(slot, "body", body.clone()).unwrap();
+            assert_eq!(rp.get_string(slot, "body").unwrap(), body);
+        }
+        assert_eq!(
+            tx.lock().unwrap().size("testfile.ovf".into()).unwrap(),
+            size
+        );
+
+        // 削除したレコードのチェーンも使い直す
+        rp.delete(slot).unwrap();
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_string(slot, "body", "b".repeat(500)).unwrap();
+        assert_eq!(rp.get_string(slot, "body").unwrap(), "b".repeat(500));
+        assert_eq!(
+            tx.lock().unwrap().size("testfile.ovf".into()).unwrap(),
+            size
+        );
+    }
+
+    #[test]
+    fn should_reuse_overflow_pages_of_replaced_string() {
+        let mut schema = Schema::default();
+        schema.add_int_field("id");
+        schema.add_string_field("body", 1000);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile.tbl".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, layout).unwrap();
+
+        rp.format().unwrap();
+
+        // 古いチェーンは新しい値を書き込んでから解放するので、2つ分のチェーンまでしか伸びない
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_string(slot, "body", "a".repeat(1000)).unwrap();
+        rp.set_string(slot, "body", "b".repeat(1000)).unwrap();
+        let size = tx.lock().unwrap().size("testfile.ovf".into()).unwrap();
+        for n in 0..10 {
+            let body = n.to_string().repeat(1000);
+            rp.set_string(slot, "body", body.clone()).unwrap();
+            assert_eq!(rp.get_string(slot, "body").unwrap(), body);
+        }
+
+        // セルに収まる値に書き換えてもチェーンは解放される
+        rp.set_string(slot, "body", "short".into()).unwrap();
+        rp.set_string(slot, "body", "c".repeat(1000)).unwrap();
+        rp.set_string(slot, "body", "d".repeat(1000)).unwrap();
+        assert_eq!(rp.get_string(slot, "body").unwrap(), "d".repeat(1000));
+        assert_eq!(
+            tx.lock().unwrap().size("testfile.ovf".into()).unwrap(),
+            size
+        );
+    }
+
+    #[test]
+    fn should_spill_to_overflow_pages_when_block_is_full() {
+        let mut schema = Schema::default();
+        schema.add_string_field("name", 28);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile.tbl".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, layout).unwrap();
+
+        rp.format().unwrap();
+
+        let mut slots = vec![];
+        let mut slot = rp.insert_after(-1).unwrap();
+        while slot >= 0 {
+            slots.push(slot);
+            slot = rp.insert_after(slot).unwrap();
+        }
+
+        // すべてのレコードを最大長まで伸ばすとブロックに収まらない
+        let value = "y".repeat(28);
+        for &slot in &slots {
+            rp.set_string(slot, "name", value.clone()).unwrap();
+        }
+        for &slot in &slots {
+            assert_eq!(rp.get_string(slot, "name").unwrap(), value);
+        }
+        assert!(tx.lock().unwrap().size("testfile.ovf".into()).unwrap() > 0);
+    }
+
+    #[test]
+    fn should_can_delete() {
         let db_dir = tempdir().unwrap();
         let tx = new_transaction(db_dir.path());
         let block = BlockId::new("testfile".into(), 0);
-        let mut rp = RecordPage::new(tx.clone(), block.clone(), layout);
+        let mut rp = RecordPage::new(tx.clone(), block.clone(), new_layout()).unwrap();
 
         rp.format().unwrap();
 
-        let slot = 0;
+        let slot = rp.insert_after(-1).unwrap();
         rp.set_int(slot, "id", 1).unwrap();
         rp.set_string(slot, "name", "hello".into()).unwrap();
 
         rp.delete(slot).unwrap();
 
-        assert_eq!(rp.get_record_type(&block, slot), RecordType::Empty);
+        assert_eq!(
+            rp.get_record_type(&block, slot).unwrap(),
+            Some(RecordType::Empty)
+        );
+        assert_eq!(rp.next_after(-1).unwrap(), -1);
+        assert_eq!(rp.insert_after(-1).unwrap(), slot);
+    }
+    #[test]
+    fn should_store_null_values() {
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block, new_layout()).unwrap();
+        rp.format().unwrap();
+
+        // 新しいレコードには NULL のフィールドがない
+        let slot = rp.insert_after(-1).unwrap();
+        assert!(!rp.is_null(slot, "id").unwrap());
+        assert!(!rp.is_null(slot, "name").unwrap());
+
+        rp.set_string(slot, "name", "hello".into()).unwrap();
+        rp.set_null(slot, "name", true).unwrap();
+        assert!(rp.is_null(slot, "name").unwrap());
+        assert!(!rp.is_null(slot, "id").unwrap());
+        // 文字列が伸びてセルが移動しても、ほかのフィールドの NULL は残る
+        rp.set_null(slot, "id", true).unwrap();
+        rp.set_string(slot, "name", "longer".into()).unwrap();
+        assert!(!rp.is_null(slot, "name").unwrap());
+        assert_eq!(rp.get_string(slot, "name").unwrap(), "longer");
+        assert!(rp.is_null(slot, "id").unwrap());
+        // 値を書き込むと NULL でなくなる
+        rp.set_int(slot, "id", 7).unwrap();
+        assert!(!rp.is_null(slot, "id").unwrap());
+        assert_eq!(rp.get_int(slot, "id").unwrap(), 7);
+        assert!(rp.is_null(slot, "missing").is_err());
+    }
+
+    #[test]
+    fn should_add_null_bitmap_to_old_blocks() {
+        let mut layout = (*new_layout()).clone();
+        layout.version = 1;
+        let db_dir = tempdir().unwrap();
+        let tx = new_transaction(db_dir.path());
+        let block = BlockId::new("testfile".into(), 0);
+        let mut rp = RecordPage::new(tx.clone(), block.clone(), Arc::new(layout)).unwrap();
+        rp.format().unwrap();
+        let mut slot = -1;
+        for (id, name) in [(1, "a"), (2, "bb"), (3, "ccc")] {
+            slot = rp.insert_after(slot).unwrap();
+            rp.set_int(slot, "id", id).unwrap();
+            rp.set_string(slot, "name", name.into()).unwrap();
+        }
+        rp.delete(1).unwrap();
+        assert_eq!(rp.record_size(0).unwrap(), 9);
+
+        // 古いブロックはビットマップがなく、NULL を書き込むと最新のレイアウトのブロックになる
+        let mut rp = RecordPage::new(tx.clone(), block, new_layout()).unwrap();
+        assert_eq!(rp.version().unwrap(), 1);
+        assert!(!rp.is_null(0, "name").unwrap());
+        rp.set_null(2, "name", true).unwrap();
+        assert_eq!(rp.version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(rp.record_size(0).unwrap(), 10);
+        assert!(rp.is_null(2, "name").unwrap());
+        assert!(!rp.is_null(2, "id").unwrap());
+        assert_eq!(rp.get_int(2, "id").unwrap(), 3);
+        assert_eq!(rp.get_int(0, "id").unwrap(), 1);
+        assert_eq!(rp.get_string(0, "name").unwrap(), "a");
+        assert!(!rp.is_null(0, "name").unwrap());
+        assert_eq!(rp.next_after(0).unwrap(), 2);
+        let slot = rp.insert_after(2).unwrap();
+        assert!(!rp.is_null(slot, "name").unwrap());
     }
 }
@@ -1,4 +1,20 @@
-#[derive(PartialEq, Eq)]
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+/// RID_FIELD はレコードの RID を参照するための仮想カラム名
+/// `select rid, a from t` や `delete from t where rid = '3:7'` のように使う
+pub const RID_FIELD: &str = "rid";
+
+/// RID_MAX_LENGTH は RID を文字列にしたときの最大長
+/// `i32::MAX:i32::MAX` の長さ
+pub const RID_MAX_LENGTH: i32 = 21;
+
+/// RID はテーブル内のレコードの位置（ブロック番号とスロット番号）を表す
+/// 文字列表現は `block:slot` で、Display と FromStr で相互に変換できる
+/// 比較はブロック番号、スロット番号の順に行うので、ファイル内の位置の順になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::upper_case_acronyms)]
 pub struct RID {
     pub block_num: i32,
     pub slot: i32,
@@ -12,6 +28,45 @@ impl RID {
 
 impl std::fmt::Display for RID {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "[block {}, slot {}]", self.block_num, self.slot)
+        write!(f, "{}:{}", self.block_num, self.slot)
+    }
+}
+
+impl FromStr for RID {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (block_num, slot) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid rid '{}', expected 'block:slot'", s))?;
+        let block_num = block_num
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("invalid block number in rid '{}'", s))?;
+        let slot = slot
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("invalid slot in rid '{}'", s))?;
+        Ok(Self::new(block_num, slot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_can_display_and_parse_rid() {
+        let rid = RID::new(3, 7);
+        assert_eq!(rid.to_string(), "3:7");
+        assert_eq!("3:7".parse::<RID>().unwrap(), rid);
+        assert_eq!(rid.to_string().parse::<RID>().unwrap(), rid);
+    }
+
+    #[test]
+    fn should_cannot_parse_invalid_rid() {
+        assert!("3".parse::<RID>().is_err());
+        assert!("a:7".parse::<RID>().is_err());
+        assert!("3:b".parse::<RID>().is_err());
     }
 }
@@ -1,36 +1,88 @@
-use anyhow::{anyhow, Result};
-use std::{collections::HashMap, sync::Arc};
+use super::{collation::Collation, truncation::TruncationPolicy};
+use crate::I32_SIZE;
+use anyhow::{anyhow, bail, Result};
+use std::{collections::HashMap, mem::size_of};
 
 /// From java.sql.Types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FieldTypes {
     Integer = 4,
+    /// BigInt は8バイトの整数
+    BigInt = -5,
+    /// Double は8バイトの浮動小数点数
+    Double = 8,
+    /// Boolean は1バイトで格納する真偽値
+    Boolean = 16,
+    /// Date は 1970-01-01 からの日数を4バイトで格納する日付
+    Date = 91,
     Varchar = 12,
+    /// Blob は任意のバイト列で、値はオーバーフローページに格納される
+    Blob = 2004,
+    /// Text は長さの制限がない文字列で、値はオーバーフローページに格納される
+    Text = 2005,
 }
 
 impl From<FieldTypes> for i32 {
     fn from(value: FieldTypes) -> i32 {
         match value {
             FieldTypes::Integer => 4,
+            FieldTypes::BigInt => -5,
+            FieldTypes::Double => 8,
+            FieldTypes::Boolean => 16,
+            FieldTypes::Date => 91,
             FieldTypes::Varchar => 12,
+            FieldTypes::Blob => 2004,
+            FieldTypes::Text => 2005,
         }
     }
 }
 
-impl From<i32> for FieldTypes {
-    fn from(value: i32) -> FieldTypes {
+impl TryFrom<i32> for FieldTypes {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i32) -> Result<FieldTypes> {
+        FieldTypes::from_code(value).ok_or_else(|| anyhow!("unknown field type: {}", value))
+    }
+}
+
+impl FieldTypes {
+    /// from_code はカタログに格納された値から型を返す。知らない値の場合は None を返す
+    pub fn from_code(value: i32) -> Option<FieldTypes> {
         match value {
-            4 => FieldTypes::Integer,
-            12 => FieldTypes::Varchar,
-            _ => unreachable!("unknown type"),
+            4 => Some(FieldTypes::Integer),
+            -5 => Some(FieldTypes::BigInt),
+            8 => Some(FieldTypes::Double),
+            16 => Some(FieldTypes::Boolean),
+            91 => Some(FieldTypes::Date),
+            12 => Some(FieldTypes::Varchar),
+            2004 => Some(FieldTypes::Blob),
+            2005 => Some(FieldTypes::Text),
+            _ => None,
+        }
+    }
+
+    /// fixed_length は値の長さが決まっている型のバイト数を返す
+    /// Varchar、Text、Blob は値によって長さが変わるので None を返す
+    pub fn fixed_length(&self) -> Option<i32> {
+        match self {
+            FieldTypes::Integer | FieldTypes::Date => Some(I32_SIZE as i32),
+            FieldTypes::BigInt | FieldTypes::Double => Some(size_of::<i64>() as i32),
+            FieldTypes::Boolean => Some(1),
+            FieldTypes::Varchar | FieldTypes::Text | FieldTypes::Blob => None,
         }
     }
 }
 
+/// MAX_CHAR_BYTES は UTF-8 の1文字の最大のバイト数
+/// varchar の長さは文字数なので、長さ n の値は最大で n * MAX_CHAR_BYTES バイトになる
+pub const MAX_CHAR_BYTES: i32 = 4;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FieldInfo {
     r#type: FieldTypes,
     length: i32,
+    collation: Collation,
+    truncation: TruncationPolicy,
 }
 
 /// Schema はテーブルレコードのスキーマを表す
@@ -41,10 +93,21 @@ pub struct Schema {
     info: HashMap<String, FieldInfo>,
 }
 
+impl AsRef<Schema> for Schema {
+    fn as_ref(&self) -> &Schema {
+        self
+    }
+}
+
 impl Schema {
     /// add_field はフィールド名、型、長さを追加する
     pub fn add_field(&mut self, field_name: impl Into<String>, r#type: FieldTypes, length: i32) {
-        let field = FieldInfo { r#type, length };
+        let field = FieldInfo {
+            r#type,
+            length,
+            collation: Collation::Binary,
+            truncation: TruncationPolicy::Error,
+        };
         let fname = field_name.into();
         self.fields.push(fname.clone());
         self.info.insert(fname, field);
@@ -56,29 +119,82 @@ impl Schema {
         self.add_field(field_name, FieldTypes::Integer, 0);
     }
 
+    /// add_long_field は8バイトの整数型のフィールドを追加する
+    pub fn add_long_field(&mut self, field_name: impl Into<String>) {
+        self.add_field(field_name, FieldTypes::BigInt, 0);
+    }
+
+    /// add_double_field は浮動小数点数型のフィールドを追加する
+    pub fn add_double_field(&mut self, field_name: impl Into<String>) {
+        self.add_field(field_name, FieldTypes::Double, 0);
+    }
+
+    /// add_bool_field は真偽値型のフィールドを追加する
+    pub fn add_bool_field(&mut self, field_name: impl Into<String>) {
+        self.add_field(field_name, FieldTypes::Boolean, 0);
+    }
+
+    /// add_date_field は日付型のフィールドを追加する
+    pub fn add_date_field(&mut self, field_name: impl Into<String>) {
+        self.add_field(field_name, FieldTypes::Date, 0);
+    }
+
     /// add_string_field は文字列型のフィールドを追加する
+    /// length は格納できる文字数で、バイト数ではない
     pub fn add_string_field(&mut self, field_name: impl Into<String>, length: i32) {
         self.add_field(field_name, FieldTypes::Varchar, length);
     }
 
-    /// add はスキーマにフィールドを追加する
-    /// スキーマにフィールドの定義がない場合はエラーを返す
-    pub fn add(&mut self, field_name: String, schema: Arc<Schema>) -> Result<()> {
-        let r#type = schema
-            .r#type(&field_name)
-            .ok_or(anyhow!("field type not found"))?;
-        let length = schema
-            .length(&field_name)
-            .ok_or(anyhow!("field length not found"))?;
-        self.add_field(field_name, r#type, length);
+    /// add_text_field はテキスト型のフィールドを追加する
+    /// 値はオーバーフローページに格納されるので、長さは持たない
+    pub fn add_text_field(&mut self, field_name: impl Into<String>) {
+        self.add_field(field_name, FieldTypes::Text, 0);
+    }
+
+    /// add_blob_field はバイナリ型のフィールドを追加する
+    /// 値はオーバーフローページに格納されるので、長さは持たない
+    pub fn add_blob_field(&mut self, field_name: impl Into<String>) {
+        self.add_field(field_name, FieldTypes::Blob, 0);
+    }
+
+    /// add は schema にあるフィールドの定義をこのスキーマに追加する
+    /// schema にフィールドの定義がない場合はエラーを返す
+    pub fn add(&mut self, field_name: impl Into<String>, schema: impl AsRef<Schema>) -> Result<()> {
+        let field_name = field_name.into();
+        let source = field_name.clone();
+        self.add_as(field_name, &source, schema)
+    }
+
+    /// add_as は schema にある source のフィールドの定義を field_name という名前でこのスキーマに追加する
+    /// schema に source の定義がない場合はエラーを返す
+    pub fn add_as(
+        &mut self,
+        field_name: impl Into<String>,
+        source: &str,
+        schema: impl AsRef<Schema>,
+    ) -> Result<()> {
+        let info = *schema
+            .as_ref()
+            .info
+            .get(source)
+            .ok_or_else(|| anyhow!("field not found: {}", source))?;
+        let field_name = field_name.into();
+        self.fields.push(field_name.clone());
+        self.info.insert(field_name, info);
         Ok(())
     }
 
-    pub fn add_all(&mut self, schema: Arc<Schema>) -> Result<()> {
-        for field in &schema.fields {
-            self.add(field.clone(), schema.clone())?
+    /// add_all は schema のすべてのフィールドをこのスキーマに追加して、追加したフィールド名を返す
+    /// 同じ名前のフィールドがすでにある場合は、何も追加せずにエラーを返す
+    pub fn add_all(&mut self, schema: impl AsRef<Schema>) -> Result<Vec<String>> {
+        let schema = schema.as_ref();
+        if let Some(field_name) = schema.fields.iter().find(|field| self.has_field(field)) {
+            bail!("duplicate field: {}", field_name);
         }
-        Ok(())
+        for field_name in &schema.fields {
+            self.add(field_name.as_str(), schema)?;
+        }
+        Ok(schema.fields.clone())
     }
 
     /// has_field は指定したフィールド名がスキーマに存在するかを返す
@@ -95,4 +211,120 @@ impl Schema {
     pub fn length(&self, field_name: &str) -> Option<i32> {
         self.info.get(field_name)?.length.into()
     }
+
+    /// collation は指定したフィールドの照合順序を返す。フィールドがない場合は Binary を返す
+    pub fn collation(&self, field_name: &str) -> Collation {
+        self.info
+            .get(field_name)
+            .map_or(Collation::Binary, |info| info.collation)
+    }
+
+    /// set_collation はフィールドの照合順序を設定する。照合順序を指定できるのは文字列のフィールドだけ
+    pub fn set_collation(&mut self, field_name: &str, collation: Collation) -> Result<()> {
+        let Some(info) = self.info.get_mut(field_name) else {
+            bail!("field not found: {}", field_name);
+        };
+        if collation != Collation::Binary
+            && !matches!(info.r#type, FieldTypes::Varchar | FieldTypes::Text)
+        {
+            bail!(
+                "collation is only for varchar or text field: {}",
+                field_name
+            );
+        }
+        info.collation = collation;
+        Ok(())
+    }
+
+    /// truncation は指定したフィールドに長すぎる文字列を書き込むときの扱いを返す。フィールドがない場合は Error を返す
+    pub fn truncation(&self, field_name: &str) -> TruncationPolicy {
+        self.info
+            .get(field_name)
+            .map_or(TruncationPolicy::Error, |info| info.truncation)
+    }
+
+    /// set_truncation は長すぎる文字列を書き込むときの扱いを設定する。長さを持つ varchar のフィールドだけに指定できる
+    pub fn set_truncation(&mut self, field_name: &str, truncation: TruncationPolicy) -> Result<()> {
+        let Some(info) = self.info.get_mut(field_name) else {
+            bail!("field not found: {}", field_name);
+        };
+        if truncation != TruncationPolicy::Error && info.r#type != FieldTypes::Varchar {
+            bail!("overflow policy is only for varchar field: {}", field_name);
+        }
+        info.truncation = truncation;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn should_merge_schemas() -> Result<()> {
+        let mut schema1 = Schema::default();
+        schema1.add_int_field("A");
+        schema1.add_string_field("B", 10);
+        let mut schema2 = Schema::default();
+        schema2.add_int_field("C");
+
+        let mut schema = Schema::default();
+        assert_eq!(schema.add_all(&schema1)?, vec!["A", "B"]);
+        assert_eq!(schema.add_all(Arc::new(schema2))?, vec!["C"]);
+        assert_eq!(schema.fields, vec!["A", "B", "C"]);
+        assert_eq!(schema.length("B"), Some(10));
+
+        // 同じ名前のフィールドがあれば何も追加しない
+        let mut schema3 = Schema::default();
+        schema3.add_int_field("D");
+        schema3.add_int_field("A");
+        assert!(schema.add_all(schema3).is_err());
+        assert_eq!(schema.fields, vec!["A", "B", "C"]);
+
+        assert!(schema.add("X", &schema1).is_err());
+
+        // 別の名前で追加しても定義は元のフィールドのもので、同じフィールドを何度でも追加できる
+        let mut projected = Schema::default();
+        projected.add("B", &schema1)?;
+        projected.add_as("T.B", "B", &schema1)?;
+        projected.add("B", &schema1)?;
+        assert_eq!(projected.fields, vec!["B", "T.B", "B"]);
+        assert_eq!(projected.length("T.B"), Some(10));
+        assert!(projected.add_as("T.X", "X", &schema1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_collation_when_merging() -> Result<()> {
+        let mut schema1 = Schema::default();
+        schema1.add_int_field("A");
+        schema1.add_string_field("B", 10);
+        schema1.set_collation("B", Collation::NoCase)?;
+        assert!(schema1.set_collation("A", Collation::NoCase).is_err());
+        assert!(schema1.set_collation("X", Collation::NoCase).is_err());
+
+        let mut schema = Schema::default();
+        schema.add("B", &schema1)?;
+        assert_eq!(schema.collation("B"), Collation::NoCase);
+        assert_eq!(schema1.collation("A"), Collation::Binary);
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_truncation_when_merging() -> Result<()> {
+        let mut schema1 = Schema::default();
+        schema1.add_string_field("A", 10);
+        schema1.add_text_field("B");
+        schema1.set_truncation("A", TruncationPolicy::Truncate)?;
+        assert!(schema1
+            .set_truncation("B", TruncationPolicy::Truncate)
+            .is_err());
+
+        let mut schema = Schema::default();
+        schema.add_all(&schema1)?;
+        assert_eq!(schema.truncation("A"), TruncationPolicy::Truncate);
+        assert_eq!(schema.truncation("B"), TruncationPolicy::Error);
+        Ok(())
+    }
 }
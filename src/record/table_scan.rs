@@ -1,12 +1,26 @@
-use super::{record_page::RecordPage, rid::RID, schema::FieldTypes};
+use super::{
+    block_sample::BlockSample,
+    corrupt_record::CorruptRecord,
+    free_space_map::FreeSpaceMap,
+    overflow::{BlobReader, OverflowFile},
+    record_page::RecordPage,
+    rid::{RID, RID_FIELD},
+    schema::FieldTypes,
+};
 use crate::{
-    file::block::BlockId,
+    file::{block::BlockId, date::Date},
     query::{constant::Constant, scan::Scan},
     record::layout::Layout,
     tx::transaction::Transaction,
 };
 use anyhow::{anyhow, bail, Result};
-use std::sync::{Arc, Mutex};
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+/// MAX_EXTENSION_BLOCKS はテーブルのファイルを一度に伸ばすブロック数の上限
+pub const MAX_EXTENSION_BLOCKS: i32 = 16;
 
 pub struct TableScan {
     tx: Arc<Mutex<Transaction>>,
@@ -14,6 +28,14 @@ pub struct TableScan {
     rp: Option<RecordPage>,
     file_name: String,
     current_slot: i32,
+    fsm: FreeSpaceMap,
+    /// 次にファイルを伸ばすときに追加するブロック数
+    extension: i32,
+    /// sample があれば、標本に含まれるブロックのレコードだけを読む
+    sample: Option<BlockSample>,
+    /// before_first などエラーを返せない移動でブロックをピンできなかったときのエラー
+    /// 次に record_page を呼んだときに返す
+    move_error: Option<anyhow::Error>,
 }
 
 impl TableScan {
@@ -29,109 +51,338 @@ impl TableScan {
             rp: None,
             file_name: file_name.clone(),
             current_slot: -1,
+            fsm: FreeSpaceMap::new(tx.clone(), &file_name),
+            extension: 1,
+            sample: None,
+            move_error: None,
         };
 
         let size = tx.lock().unwrap().size(file_name)?;
         if size == 0 {
             scan.move_to_new_block()?
         } else {
-            scan.move_to_block(0);
+            scan.move_to_block(0)?;
         }
         Ok(scan)
     }
 
+    /// with_sample は sample に含まれるブロックだけを読むスキャンにする
+    /// 含まれないブロックはバッファに読み込まずに飛ばす。読むためのスキャンなので、レコードを変更しないこと
+    pub fn with_sample(mut self, sample: BlockSample) -> Self {
+        self.sample = Some(sample);
+        self.before_first();
+        self
+    }
+
+    /// block_count はテーブルファイルのブロック数を返す
+    pub fn block_count(&self) -> Result<i32> {
+        Ok(self.tx.lock().unwrap().size(self.file_name.clone())? as i32)
+    }
+
+    /// used_block_count はファイルの末尾にある、まとめて追加したまま使っていないブロックを除いたブロック数を返す
+    pub fn used_block_count(&mut self) -> Result<i32> {
+        let mut count = self.block_count()?;
+        while count > 0 {
+            self.move_to_block(count - 1)?;
+            if self.record_page()?.is_formatted()? {
+                break;
+            }
+            count -= 1;
+        }
+        Ok(count)
+    }
+
+    /// count_records は block_num 番目のブロックのレコード数を数えて、そのブロックに移動する
+    pub fn count_records(&mut self, block_num: i32) -> Result<i32> {
+        self.move_to_block(block_num)?;
+        let rp = self.record_page()?;
+        let mut count = 0;
+        let mut slot = rp.next_after(-1)?;
+        while slot >= 0 {
+            count += 1;
+            slot = rp.next_after(slot)?;
+        }
+        Ok(count)
+    }
+
+    /// truncate はテーブルのすべてのレコードを削除して、削除したレコードの数を返す
+    /// レコードを1件ずつ削除する代わりに、テーブルのファイルと空き領域マップとオーバーフローファイルを空にする
+    /// スキャンは閉じるので、この後は使わない
+    pub fn truncate(&mut self) -> Result<i32> {
+        let mut count = 0;
+        for block_num in 0..self.block_count()? {
+            count += self.count_records(block_num)?;
+        }
+        self.close();
+        let overflow = OverflowFile::new(self.tx.clone(), &self.file_name);
+        let mut tx = self.tx.lock().unwrap();
+        for filename in [
+            &self.file_name,
+            &self.fsm.filename,
+            &overflow.filename,
+            &overflow.free_list_filename,
+        ] {
+            tx.truncate_file(filename)?;
+        }
+        Ok(count)
+    }
+
     fn record_page(&mut self) -> Result<&mut RecordPage> {
+        if let Some(e) = self.move_error.take() {
+            return Err(e);
+        }
         self.rp.as_mut().ok_or(anyhow!("no record page"))
     }
 
-    // move_to_new_block はファイルに新しいブロックを追加して、そのブロックに移動
+    // move_to_new_block はファイルに新しいブロックを追加して、追加した最初のブロックに移動
+    // 同じスキャンでファイルを伸ばすたびに、追加するブロック数を MAX_EXTENSION_BLOCKS まで倍にしていく
+    // 残りのブロックは 0 で埋めたまま空き領域マップに空のブロックとして記録し、挿入するときにフォーマットする
     fn move_to_new_block(&mut self) -> Result<()> {
         self.close();
+        let count = self.extension;
         let block_id = {
             let mut tx = self.tx.lock().unwrap();
-            tx.append(self.file_name.clone())?
+            tx.append_blocks(self.file_name.clone(), count)?
         };
-        let mut rp = RecordPage::new(self.tx.clone(), block_id, self.layout.clone());
+        let mut rp = RecordPage::new(self.tx.clone(), block_id, self.layout.clone())?;
         rp.format()?;
+        let (first, free) = (rp.block.num, rp.available_space()?);
         self.rp = Some(rp);
         self.current_slot = -1;
+        for block_num in first + 1..first + count {
+            self.fsm.set(block_num, free)?;
+        }
+        self.extension = (count * 2).min(MAX_EXTENSION_BLOCKS);
+        Ok(())
+    }
+
+    /// format_preallocated は現在のブロックがまとめて追加したまま使っていないブロックであればフォーマットする
+    fn format_preallocated(&mut self) -> Result<()> {
+        let rp = self.record_page()?;
+        if !rp.is_formatted()? {
+            rp.format()?;
+        }
         Ok(())
     }
 
     // move_to_block は指定したブロックに移動
     // ブロックへの操作はRecordPageを通して行うので、RecordPageを生成して保持する
-    fn move_to_block(&mut self, block_num: i32) {
+    fn move_to_block(&mut self, block_num: i32) -> Result<()> {
         self.close();
         let block_id = BlockId::new(self.file_name.clone(), block_num);
         self.rp = Some(RecordPage::new(
             self.tx.clone(),
             block_id,
             self.layout.clone(),
-        ));
+        )?);
         self.current_slot = -1;
+        Ok(())
+    }
+
+    /// defer_error は before_first などエラーを返せない移動で起きたエラーを、次の読み込みで返すように取っておく
+    fn defer_error(&mut self, result: Result<()>) {
+        if let Err(e) = result {
+            self.move_error = Some(e);
+        }
+    }
+
+    /// is_sampled は block_num 番目のブロックのレコードを読むかを返す
+    fn is_sampled(&self, block_num: i32) -> bool {
+        self.sample
+            .map_or(true, |sample| sample.includes(block_num))
+    }
+
+    /// next_sampled_block は block_num 番目より後ろで、最初に読むブロックの番号を返す
+    fn next_sampled_block(&self, block_num: i32) -> Result<Option<i32>> {
+        let size = self.block_count()?;
+        Ok((block_num + 1..size).find(|&block_num| self.is_sampled(block_num)))
     }
 
-    /// at_last_block は最後のブロックにいるかどうかを返す
-    fn at_last_block(&self) -> bool {
-        let size = self
-            .tx
-            .lock()
-            .unwrap()
-            .size(self.file_name.clone())
-            .unwrap() as i32;
-        self.rp.as_ref().unwrap().block.num == size - 1
+    /// previous_sampled_block は block_num 番目より前で、最後に読むブロックの番号を返す
+    fn previous_sampled_block(&self, block_num: i32) -> Option<i32> {
+        (0..block_num)
+            .rev()
+            .find(|&block_num| self.is_sampled(block_num))
+    }
+
+    /// check_block は block_num 番目のブロックがテーブルファイルにあるかを確かめる
+    /// ファイルが外から切り詰められたり削除されたりしていると、ファイルの末尾より後ろのブロックは
+    /// バッファに前の内容が残ったまま読まれるので、レコードを読む前にエラーにする
+    fn check_block(&self, block_num: i32) -> Result<()> {
+        let size = self.block_count()?;
+        if block_num >= size {
+            bail!(
+                "block {} is past the end of {} ({} blocks)",
+                block_num,
+                self.file_name,
+                size
+            );
+        }
+        Ok(())
+    }
+
+    /// record_free_space は現在のブロックの空き領域を空き領域マップに記録する
+    fn record_free_space(&mut self) -> Result<()> {
+        let rp = self.record_page()?;
+        let (block_num, free) = (rp.block.num, rp.available_space()?);
+        self.fsm.set(block_num, free)
+    }
+
+    /// is_rid_field は指定したフィールドが仮想カラムの rid かどうかを返す
+    /// テーブルに同名のカラムがある場合はそちらを優先する
+    fn is_rid_field(&self, field_name: &str) -> bool {
+        field_name == RID_FIELD && !self.layout.schema.has_field(field_name)
     }
 }
 
 impl Scan for TableScan {
     fn before_first(&mut self) {
-        self.move_to_block(0);
+        // 読むブロックが1つもなければ先頭のブロックに移動し、next で読まずに飛ばす
+        let first = match self.is_sampled(0) {
+            true => 0,
+            false => self.next_sampled_block(0).ok().flatten().unwrap_or(0),
+        };
+        let result = self.move_to_block(first);
+        self.defer_error(result);
     }
 
     fn next(&mut self) -> Result<bool> {
         loop {
-            let current_slot = self.current_slot;
-            self.current_slot = self.record_page()?.next_after(current_slot);
-            if self.current_slot >= 0 {
-                break;
+            let block_num = self.record_page()?.block.num;
+            if self.is_sampled(block_num) {
+                let current_slot = self.current_slot;
+                if current_slot < 0 {
+                    self.check_block(block_num)?;
+                }
+                self.current_slot = self.record_page()?.next_after(current_slot)?;
+                if self.current_slot >= 0 {
+                    break;
+                }
             }
-            if self.at_last_block() {
-                return Ok(false);
-            } else {
-                let block_num = self.record_page()?.block.num;
-                self.move_to_block(block_num + 1);
+            match self.next_sampled_block(block_num)? {
+                Some(block_num) => self.move_to_block(block_num)?,
+                None => return Ok(false),
             }
         }
 
         Ok(true)
     }
 
+    fn after_last(&mut self) {
+        // ブロック数を読めない場合やブロックがない場合は先頭のブロックに移動し、previous でエラーにする
+        let last = self.block_count().map_or(0, |size| (size - 1).max(0));
+        let result = self
+            .move_to_block(last)
+            .and_then(|_| self.record_page()?.slot_count());
+        match result {
+            Ok(slot_count) => self.current_slot = slot_count,
+            Err(e) => self.move_error = Some(e),
+        }
+    }
+
+    fn previous(&mut self) -> Result<bool> {
+        loop {
+            let block_num = self.record_page()?.block.num;
+            if self.is_sampled(block_num) {
+                let current_slot = self.current_slot;
+                if current_slot >= self.record_page()?.slot_count()? {
+                    self.check_block(block_num)?;
+                }
+                self.current_slot = self.record_page()?.prev_before(current_slot)?;
+                if self.current_slot >= 0 {
+                    return Ok(true);
+                }
+            }
+            let Some(block_num) = self.previous_sampled_block(block_num) else {
+                return Ok(false);
+            };
+            self.move_to_block(block_num)?;
+            self.current_slot = self.record_page()?.slot_count()?;
+        }
+    }
+
     fn get_int(&mut self, field_name: &str) -> Result<i32> {
         let slot = self.current_slot;
         self.record_page()?.get_int(slot, field_name)
     }
 
     fn get_string(&mut self, field_name: &str) -> Result<String> {
+        if self.is_rid_field(field_name) {
+            return Ok(self.get_rid()?.to_string());
+        }
         let slot = self.current_slot;
         self.record_page()?.get_string(slot, field_name)
     }
 
+    /// get_value は NULL のフィールドに Constant::Null を返す
+    /// get_int などの型ごとの読み出しは NULL かどうかを見ずに、格納されている値を返す
     fn get_value(&mut self, field_name: &str) -> Result<Constant> {
+        if self.is_rid_field(field_name) {
+            return Ok(Constant::String(self.get_rid()?.to_string()));
+        }
+        if self.layout.schema.has_field(field_name) {
+            let slot = self.current_slot;
+            if self.record_page()?.is_null(slot, field_name)? {
+                return Ok(Constant::Null);
+            }
+        }
         match self.layout.schema.r#type(field_name) {
             Some(FieldTypes::Integer) => {
                 let val = self.get_int(field_name)?;
                 Ok(Constant::Int(val))
             }
-            Some(FieldTypes::Varchar) => {
+            Some(FieldTypes::Varchar) | Some(FieldTypes::Text) | Some(FieldTypes::Blob) => {
                 let val = self.get_string(field_name)?;
                 Ok(Constant::String(val))
             }
-            _ => bail!("field type not found: {}", field_name),
+            Some(FieldTypes::Boolean) => {
+                let val = self.get_bool(field_name)?;
+                Ok(Constant::Bool(val))
+            }
+            Some(FieldTypes::Date) => {
+                let val = self.get_date(field_name)?;
+                Ok(Constant::Date(val))
+            }
+            Some(field_type) => bail!(
+                "{:?} field cannot be read as a value: {}",
+                field_type,
+                field_name
+            ),
+            None => {
+                let block = self.record_page()?.block.clone();
+                let reason = "field is not in the layout";
+                Err(CorruptRecord::new(&block, self.current_slot, Some(field_name), reason).into())
+            }
         }
     }
 
     fn has_field(&self, field_name: &str) -> bool {
-        self.layout.schema.has_field(field_name)
+        self.layout.schema.has_field(field_name) || field_name == RID_FIELD
+    }
+
+    fn get_blob_reader(&mut self, field_name: &str) -> Result<BlobReader> {
+        let slot = self.current_slot;
+        self.record_page()?.get_blob_reader(slot, field_name)
+    }
+
+    fn get_long(&mut self, field_name: &str) -> Result<i64> {
+        let slot = self.current_slot;
+        self.record_page()?.get_long(slot, field_name)
+    }
+
+    fn get_double(&mut self, field_name: &str) -> Result<f64> {
+        let slot = self.current_slot;
+        self.record_page()?.get_double(slot, field_name)
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> Result<bool> {
+        let slot = self.current_slot;
+        self.record_page()?.get_bool(slot, field_name)
+    }
+
+    fn get_date(&mut self, field_name: &str) -> Result<Date> {
+        let slot = self.current_slot;
+        self.record_page()?.get_date(slot, field_name)
     }
 
     fn close(&mut self) {
@@ -149,7 +400,15 @@ impl Scan for TableScan {
 
         match (field_type, value) {
             (FieldTypes::Integer, Constant::Int(val)) => self.set_int(field_name, val),
-            (FieldTypes::Varchar, Constant::String(val)) => self.set_string(field_name, &val),
+            (FieldTypes::Varchar | FieldTypes::Text | FieldTypes::Blob, Constant::String(val)) => {
+                self.set_string(field_name, &val)
+            }
+            (FieldTypes::Boolean, Constant::Bool(val)) => self.set_bool(field_name, val),
+            (FieldTypes::Date, Constant::Date(val)) => self.set_date(field_name, val),
+            (_, Constant::Null) => {
+                let slot = self.current_slot;
+                self.record_page()?.set_null(slot, field_name, true)
+            }
             _ => bail!("type mismatch"),
         }
     }
@@ -165,23 +424,60 @@ impl Scan for TableScan {
             .set_string(slot, field_name, value.into())
     }
 
+    fn set_long(&mut self, field_name: &str, value: i64) -> Result<()> {
+        let slot = self.current_slot;
+        self.record_page()?.set_long(slot, field_name, value)
+    }
+
+    fn set_double(&mut self, field_name: &str, value: f64) -> Result<()> {
+        let slot = self.current_slot;
+        self.record_page()?.set_double(slot, field_name, value)
+    }
+
+    fn set_bool(&mut self, field_name: &str, value: bool) -> Result<()> {
+        let slot = self.current_slot;
+        self.record_page()?.set_bool(slot, field_name, value)
+    }
+
+    fn set_date(&mut self, field_name: &str, value: Date) -> Result<()> {
+        let slot = self.current_slot;
+        self.record_page()?.set_date(slot, field_name, value)
+    }
+
+    fn set_blob(&mut self, field_name: &str, reader: &mut dyn Read) -> Result<()> {
+        let slot = self.current_slot;
+        self.record_page()?.set_blob(slot, field_name, reader)
+    }
+
     fn delete(&mut self) -> Result<()> {
         let slot = self.current_slot;
-        self.record_page()?.delete(slot)
+        self.record_page()?.delete(slot)?;
+        self.record_free_space()
     }
 
+    /// insert はまず現在のブロックに挿入を試み、空きがなければ空き領域マップで
+    /// 空きのあるブロックを探して移動する。見つからなければ新しいブロックを追加する
     fn insert(&mut self) -> Result<()> {
         loop {
+            self.format_preallocated()?;
             let current_slot = self.current_slot;
             self.current_slot = self.record_page()?.insert_after(current_slot)?;
             if self.current_slot >= 0 {
-                return Ok(());
+                return self.record_free_space();
             }
-            if self.at_last_block() {
-                self.move_to_new_block()?;
+
+            let block_num = self.record_page()?.block.num;
+            if current_slot < 0 {
+                // ブロック全体を探しても空きがなかったので、再び選ばれないように空きなしとして記録する
+                self.fsm.set(block_num, 0)?;
             } else {
-                let block_num = self.record_page()?.block.num;
-                self.move_to_block(block_num + 1);
+                self.record_free_space()?;
+            }
+            let required = self.record_page()?.required_space()?;
+            let size = self.tx.lock().unwrap().size(self.file_name.clone())? as i32;
+            match self.fsm.find(required, size)? {
+                Some(block_num) => self.move_to_block(block_num)?,
+                None => self.move_to_new_block()?,
             }
         }
     }
@@ -192,14 +488,9 @@ impl Scan for TableScan {
     }
 
     fn move_to_rid(&mut self, rid: RID) {
-        self.close();
-        let block_id = BlockId::new(self.file_name.clone(), rid.block_num);
-        self.rp = Some(RecordPage::new(
-            self.tx.clone(),
-            block_id,
-            self.layout.clone(),
-        ));
-        self.current_slot = rid.block_num;
+        let result = self.move_to_block(rid.block_num);
+        self.defer_error(result);
+        self.current_slot = rid.slot;
     }
 }
 
@@ -209,8 +500,10 @@ mod tests {
 
     use super::TableScan;
     use crate::{
-        query::scan::Scan as _,
-        record::{layout::Layout, schema::Schema},
+        file::date::Date,
+        query::scan::{Scan as _, ScanDirection},
+        record::block_sample::BlockSample,
+        record::{layout::Layout, rid::RID, schema::Schema},
         server::db::TinyDB,
     };
     use anyhow::Result;
@@ -257,4 +550,244 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn should_insert_into_block_with_free_space() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_insert_into_block_with_free_space");
+        let db = TinyDB::new(test_directory, 100, 8)?;
+        let tx = db.transaction()?;
+        let mut sch = Schema::default();
+        sch.add_int_field("A");
+        sch.add_string_field("B", 8);
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(sch))?);
+
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        for n in 0..50 {
+            ts.insert()?;
+            ts.set_int("A", n)?;
+        }
+        let size = tx.lock().unwrap().size("T.tbl".into())?;
+        assert!(size > 2);
+
+        // 途中のブロックのレコードを削除すると、空き領域マップにそのブロックの空きが記録される
+        ts.move_to_rid(RID::new(3, -1));
+        for _ in 0..2 {
+            ts.next()?;
+            ts.delete()?;
+        }
+        let required = ts.record_page()?.required_space()?;
+        ts.close();
+        assert!(ts.fsm.get(1)?.unwrap() < required);
+        assert!(ts.fsm.get(3)?.unwrap() >= required);
+
+        // 先頭のブロックには空きがないので、空き領域マップで見つけたブロックに挿入する
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        ts.insert()?;
+        assert_eq!(ts.get_rid()?.block_num, 3);
+        assert_eq!(tx.lock().unwrap().size("T.tbl".into())?, size);
+        ts.close();
+
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_extend_file_with_preallocated_blocks() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_extend_file_with_preallocated_blocks");
+        let db = TinyDB::new(test_directory, 100, 8)?;
+        let tx = db.transaction()?;
+        let mut sch = Schema::default();
+        sch.add_int_field("A");
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(sch))?);
+
+        // ファイルを伸ばすたびに追加するブロック数が 1, 2, 4, ... と増える
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        let (mut blocks, mut sizes) = (vec![], vec![]);
+        for n in 0..100 {
+            ts.insert()?;
+            ts.set_int("A", n)?;
+            let block_num = ts.get_rid()?.block_num;
+            if blocks.last() != Some(&block_num) {
+                blocks.push(block_num);
+            }
+            let size = ts.block_count()?;
+            if sizes.last() != Some(&size) {
+                sizes.push(size);
+            }
+        }
+        assert_eq!(sizes, vec![1, 3, 7, 15, 31][..sizes.len()]);
+        assert!(sizes.len() > 2);
+        // 追加したブロックは順に使うので、使っていないブロックは末尾にだけ残る
+        let last = ts.get_rid()?.block_num;
+        assert_eq!(blocks, (0..=last).collect::<Vec<_>>());
+        assert!(ts.block_count()? > last + 1);
+        assert_eq!(ts.used_block_count()?, last + 1);
+        let required = {
+            ts.move_to_block(0)?;
+            ts.record_page()?.required_space()?
+        };
+        assert!(ts.fsm.get(last + 1)?.unwrap() >= required);
+        ts.close();
+
+        // 別のスキャンも空き領域マップからまとめて追加したブロックを見つけて、フォーマットしてから挿入する
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        let size = ts.block_count()?;
+        ts.move_to_rid(RID::new(last, -1));
+        loop {
+            ts.insert()?;
+            if ts.get_rid()?.block_num != last {
+                break;
+            }
+        }
+        assert_eq!(ts.get_rid()?.block_num, last + 1);
+        assert_eq!(ts.block_count()?, size);
+        ts.before_first();
+        let mut count = 0;
+        while ts.next()? {
+            count += 1;
+        }
+        assert!(count > 100);
+        ts.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_scan_table_backward() -> Result<()> {
+        let mut ts = create_table_scan()?;
+        ts.after_last();
+        for i in (0..50).rev() {
+            assert!(ts.previous()?);
+            assert_eq!(ts.get_int("A")?, i);
+        }
+        assert!(!ts.previous()?);
+
+        // 向きを指定して読み込む
+        ts.rewind(ScanDirection::Backward);
+        assert!(ts.advance(ScanDirection::Backward)?);
+        assert_eq!(ts.get_int("A")?, 49);
+        ts.rewind(ScanDirection::Forward);
+        assert!(ts.advance(ScanDirection::Forward)?);
+        assert_eq!(ts.get_int("A")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn should_set_and_get_typed_fields() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_set_and_get_typed_fields");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut sch = Schema::default();
+        sch.add_long_field("A");
+        sch.add_string_field("B", 8);
+        sch.add_double_field("C");
+        sch.add_bool_field("D");
+        sch.add_date_field("E");
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(sch))?);
+        let date = Date::from_ymd(2024, 2, 29)?;
+
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        ts.insert()?;
+        // 新しいレコードは 0 で初期化されている
+        assert_eq!(ts.get_long("A")?, 0);
+        assert!(!ts.get_bool("D")?);
+        ts.set_long("A", i64::MAX)?;
+        ts.set_string("B", "abc")?;
+        ts.set_double("C", -2.5)?;
+        ts.set_bool("D", true)?;
+        ts.set_date("E", date)?;
+        ts.close();
+        tx.lock().unwrap().commit()?;
+
+        // ロールバックすると書き込む前の値に戻る
+        let tx = db.transaction()?;
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        assert!(ts.next()?);
+        ts.set_long("A", -1)?;
+        ts.set_bool("D", false)?;
+        ts.set_date("E", Date::default())?;
+        ts.close();
+        tx.lock().unwrap().rollback()?;
+
+        let tx = db.transaction()?;
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        assert!(ts.next()?);
+        assert_eq!(ts.get_long("A")?, i64::MAX);
+        assert_eq!(ts.get_string("B")?, "abc");
+        assert_eq!(ts.get_double("C")?, -2.5);
+        assert!(ts.get_bool("D")?);
+        assert_eq!(ts.get_date("E")?, date);
+        assert!(!ts.next()?);
+        ts.close();
+        tx.lock().unwrap().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_read_only_sampled_blocks() -> Result<()> {
+        let mut ts = create_table_scan()?;
+        let mut expected = vec![];
+        while ts.next()? {
+            expected.push((ts.get_rid()?.block_num, ts.get_int("A")?));
+        }
+
+        let sample = BlockSample::new(50)?;
+        expected.retain(|(block_num, _)| sample.includes(*block_num));
+        assert!(!expected.is_empty());
+        let mut ts = ts.with_sample(sample);
+        let mut forward = vec![];
+        while ts.next()? {
+            forward.push((ts.get_rid()?.block_num, ts.get_int("A")?));
+        }
+        assert_eq!(forward, expected);
+
+        ts.after_last();
+        let mut backward = vec![];
+        while ts.previous()? {
+            backward.push((ts.get_rid()?.block_num, ts.get_int("A")?));
+        }
+        backward.reverse();
+        assert_eq!(backward, expected);
+
+        let mut ts = ts.with_sample(BlockSample::new(0)?);
+        assert!(!ts.next()?);
+        ts.after_last();
+        assert!(!ts.previous()?);
+        ts.close();
+        Ok(())
+    }
+
+    #[test]
+    fn should_fail_to_scan_blocks_past_the_end_of_file() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_fail_to_scan_blocks_past_the_end_of_file");
+        let db = TinyDB::new(test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut sch = Schema::default();
+        sch.add_int_field("A");
+        let layout = Arc::new(Layout::try_from_schema(Arc::new(sch))?);
+        let mut ts = TableScan::new(tx.clone(), "T", layout)?;
+        ts.insert()?;
+        ts.set_int("A", 1)?;
+        ts.close();
+
+        // ブロックがなくなったファイルを読んでも panic せず、next と previous がエラーを返す
+        tx.lock().unwrap().truncate_file("T.tbl")?;
+        ts.before_first();
+        let err = ts.next().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "block 0 is past the end of T.tbl (0 blocks)"
+        );
+        ts.after_last();
+        assert!(ts.previous().is_err());
+        ts.close();
+        tx.lock().unwrap().rollback()?;
+        Ok(())
+    }
 }
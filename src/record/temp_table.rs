@@ -0,0 +1,51 @@
+use super::{layout::Layout, schema::Schema, table_scan::TableScan};
+use crate::tx::transaction::Transaction;
+use anyhow::Result;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+static NEXT_TABLE_NUM: AtomicUsize = AtomicUsize::new(0);
+
+/// TempTable は問い合わせの結果を一時的に格納するテーブル
+/// カタログには登録せず、`temp` で始まるファイルは起動時に FileManager が削除する
+/// for_transaction で作った一時テーブルはトランザクションの終了時に削除される
+#[derive(Debug, Clone)]
+pub struct TempTable {
+    pub table_name: String,
+    pub layout: Arc<Layout>,
+}
+
+impl TempTable {
+    pub fn new(schema: Arc<Schema>) -> Result<Self> {
+        Ok(Self {
+            table_name: Self::next_table_name(),
+            layout: Arc::new(Layout::try_from_schema(schema)?),
+        })
+    }
+
+    /// for_transaction はトランザクションの終了時に削除される一時テーブルを作る
+    pub fn for_transaction(tx: Arc<Mutex<Transaction>>, schema: Arc<Schema>) -> Result<Self> {
+        let table_name = tx.lock().unwrap().create_temp_file();
+        Ok(Self {
+            table_name,
+            layout: Arc::new(Layout::try_from_schema(schema)?),
+        })
+    }
+
+    /// open は一時テーブルを読み書きする TableScan を返す
+    pub fn open(&self, tx: Arc<Mutex<Transaction>>) -> Result<TableScan> {
+        TableScan::new(tx, self.table_name.clone(), self.layout.clone())
+    }
+
+    /// drop は一時テーブルのファイルを削除する
+    pub fn drop(&self, tx: Arc<Mutex<Transaction>>) -> Result<()> {
+        tx.lock().unwrap().drop_temp_file(&self.table_name)
+    }
+
+    fn next_table_name() -> String {
+        let num = NEXT_TABLE_NUM.fetch_add(1, Ordering::SeqCst) + 1;
+        format!("temp{}", num)
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{bail, Result};
+use std::{fmt::Display, str::FromStr};
+
+/// TruncationPolicy は varchar のフィールドに長さを超える文字列を書き込もうとしたときの扱い
+///
+/// フィールドごとに `varchar(10) on overflow truncate` のように指定する。指定しない場合は Error になる
+/// varchar の長さはバイト数ではなく文字数で数える
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TruncationPolicy {
+    /// Error は書き込みをエラーにする
+    #[default]
+    Error,
+    /// Truncate は先頭から長さの文字数までに切り詰めて書き込む
+    Truncate,
+}
+
+impl TruncationPolicy {
+    /// apply は値を length 文字に収める。収まらない場合はこの規則に従ってエラーにするか切り詰める
+    pub fn apply(&self, field_name: &str, value: String, length: i32) -> Result<String> {
+        let chars = value.chars().count();
+        if chars <= length.max(0) as usize {
+            return Ok(value);
+        }
+        match self {
+            TruncationPolicy::Error => bail!(
+                "value for field '{}' is too long: {} > {}",
+                field_name,
+                chars,
+                length
+            ),
+            TruncationPolicy::Truncate => Ok(value.chars().take(length as usize).collect()),
+        }
+    }
+}
+
+impl Display for TruncationPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TruncationPolicy::Error => write!(f, "error"),
+            TruncationPolicy::Truncate => write!(f, "truncate"),
+        }
+    }
+}
+
+impl FromStr for TruncationPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(TruncationPolicy::Error),
+            "truncate" => Ok(TruncationPolicy::Truncate),
+            _ => bail!("unknown overflow policy: {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_apply_policy_at_boundary_lengths() -> Result<()> {
+        for policy in [TruncationPolicy::Error, TruncationPolicy::Truncate] {
+            assert_eq!(policy.apply("A", "".into(), 0)?, "");
+            assert_eq!(policy.apply("A", "abc".into(), 3)?, "abc");
+            // 長さはバイト数ではなく文字数で数える
+            assert_eq!(policy.apply("A", "あいう".into(), 3)?, "あいう");
+        }
+
+        assert!(TruncationPolicy::Error
+            .apply("A", "abcd".into(), 3)
+            .is_err());
+        assert!(TruncationPolicy::Error.apply("A", "a".into(), 0).is_err());
+        assert_eq!(
+            TruncationPolicy::Truncate.apply("A", "abcd".into(), 3)?,
+            "abc"
+        );
+        assert_eq!(TruncationPolicy::Truncate.apply("A", "a".into(), 0)?, "");
+        assert_eq!(
+            TruncationPolicy::Truncate.apply("A", "あいうえ".into(), 3)?,
+            "あいう"
+        );
+
+        assert_eq!(
+            "TRUNCATE".parse::<TruncationPolicy>()?,
+            TruncationPolicy::Truncate
+        );
+        assert_eq!(TruncationPolicy::Truncate.to_string(), "truncate");
+        assert!("ignore".parse::<TruncationPolicy>().is_err());
+        Ok(())
+    }
+}
@@ -1,43 +1,215 @@
 use crate::{
     buffer::buffer_manager::BufferManager,
-    file::file_manager::FileManager,
-    log::log_manager::LogManager,
+    clock::{default_clock, Clock},
+    file::{
+        control_file::ControlFile,
+        file_manager::{FileManager, DEFAULT_MAX_OPEN_FILES},
+    },
+    log::log_manager::{LogManager, DEFAULT_LOG_FILE},
+    metadata::catalog::CATALOG_VERSION,
+    metadata::catalog_cache::CatalogCache,
     metadata::metadata_manager::MetadataManager,
     plan::{
         basic_query_plan::BasicQueryPlanner, basic_update_planner::BasicUpdatePlanner,
         planner::Planner, query_planner::QueryPlanner, update_planner::UpdatePlanner,
     },
-    tx::{concurrency::lock_table::LockTable, transaction::Transaction},
-    unlock, LOG_FILE,
+    timeout::Timeouts,
+    tx::{
+        commit_listener::{CommitEvent, CommitListeners},
+        concurrency::lock_table::{LockTable, DEFAULT_ESCALATION_THRESHOLD},
+        recovery::recovery_manager::RecoveryProgress,
+        transaction::Transaction,
+    },
+    unlock,
 };
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context as _, Result};
 use std::{
     path::PathBuf,
     sync::{Arc, Condvar, Mutex},
 };
 
+use super::{
+    migration::{pending_migrations, Migration, MIGRATIONS},
+    notification::{NotificationBus, Subscription},
+    retry::RetryPolicy,
+};
+
+/// DbConfig はデータベースを開くときの設定
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    pub block_size: i32,
+    pub buffer_size: u64,
+    /// true の場合は他のプロセスのロックを奪って開く
+    pub force: bool,
+    /// ロックや空きバッファを待つタイムアウトの判定に使う時計
+    pub clock: Arc<dyn Clock>,
+    /// 1つのファイルのブロックロックをファイルロックにまとめる数。0 の場合はまとめない
+    pub lock_escalation_threshold: usize,
+    /// 空きバッファ、共有ロック、排他ロックを待つ時間と、確認し直す間隔
+    pub timeouts: Timeouts,
+    /// true の場合はトランザクションがロックを取らない。1つのスレッドだけで使う組み込みの用途向け
+    pub single_threaded: bool,
+    /// 同時に開いておくファイルの数の上限。テーブルや索引が多い場合にファイル記述子を使い切らないようにする
+    pub max_open_files: usize,
+    /// バッファプールを分ける区画の数。多くのスレッドが同時にピンする場合に増やす
+    pub buffer_shards: usize,
+    /// ログファイルの名前
+    pub log_file: String,
+    /// ログファイルを置くディレクトリ。None の場合はデータベースのディレクトリに置く
+    pub log_dir: Option<PathBuf>,
+    /// テーブルのファイルを分けるセグメント1つのブロック数。None の場合はコントロールファイルに記録した設定を使う
+    pub segment_blocks: Option<u64>,
+}
+
+impl DbConfig {
+    pub fn new(block_size: i32, buffer_size: u64) -> Self {
+        Self {
+            block_size,
+            buffer_size,
+            force: false,
+            clock: default_clock(),
+            lock_escalation_threshold: DEFAULT_ESCALATION_THRESHOLD,
+            timeouts: Timeouts::default(),
+            single_threaded: false,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            buffer_shards: 1,
+            log_file: DEFAULT_LOG_FILE.into(),
+            log_dir: None,
+            segment_blocks: None,
+        }
+    }
+
+    /// with_clock は時計を設定する。テストでは MockClock を渡すと、タイムアウトを待たずに確認できる
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// with_timeouts は待つ時間を設定する
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// with_max_open_files は同時に開いておくファイルの数の上限を設定する
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = max_open_files;
+        self
+    }
+
+    /// with_buffer_shards はバッファプールを分ける区画の数を設定する
+    /// ブロックは自分の区画のバッファだけを使うので、区画ごとのバッファが少なすぎると空きを待ちやすくなる
+    pub fn with_buffer_shards(mut self, buffer_shards: usize) -> Self {
+        self.buffer_shards = buffer_shards;
+        self
+    }
+
+    /// with_log_file はログファイルの名前を設定する
+    /// 1つのディレクトリに複数のデータベースのログを置く場合は、データベースごとに異なる名前にする
+    pub fn with_log_file(mut self, log_file: impl Into<String>) -> Self {
+        self.log_file = log_file.into();
+        self
+    }
+
+    /// with_log_dir はログファイルをデータベースとは別のディレクトリに置くように設定する
+    /// データファイルと別のディスクに置くと、ログの追記がデータの読み書きと競合しなくなる
+    /// リカバリではこのディレクトリのログを読むので、開き直すときも同じ設定にすること
+    pub fn with_log_dir(mut self, log_dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(log_dir.into());
+        self
+    }
+
+    /// with_segment_blocks はテーブルのファイルを blocks ブロックごとのセグメントに分けて保存するように設定する
+    /// 設定はコントロールファイルに記録するので、開き直すときに設定しなくてもセグメントに分けたファイルを読める
+    /// すでにセグメントに分けたデータベースでは、記録したブロック数と異なる数を設定するとエラーになる
+    pub fn with_segment_blocks(mut self, blocks: u64) -> Self {
+        self.segment_blocks = Some(blocks);
+        self
+    }
+
+    /// single_threaded はロックを取らない設定にする
+    /// 同時に複数のトランザクションを使うと、ロックで防いでいた読み書きの競合が起きるので注意すること
+    pub fn single_threaded(mut self) -> Self {
+        self.single_threaded = true;
+        self
+    }
+}
+
 pub struct TinyDB {
     pub file_manager: Arc<Mutex<FileManager>>,
     pub log_manager: Arc<Mutex<LogManager>>,
-    pub buffer_manager: Arc<Mutex<BufferManager>>,
+    pub buffer_manager: Arc<BufferManager>,
     pub lock_table: Arc<(Mutex<LockTable>, Condvar)>,
     pub planner: Option<Arc<Mutex<Planner>>>,
+    single_threaded: bool,
+    catalog_cache: Arc<CatalogCache>,
+    commit_listeners: CommitListeners,
+    notifications: NotificationBus,
+    recovery_progress: Option<Arc<dyn Fn(RecoveryProgress) + Send + Sync>>,
+    control: ControlFile,
 }
 
 impl TinyDB {
+    /// new はデータベースのディレクトリを開く
+    /// 他のプロセスが同じディレクトリを開いている場合は "database in use" エラーを返す
     pub fn new(dir: impl Into<PathBuf>, block_size: i32, buffer_size: u64) -> Result<Self> {
+        Self::open(dir, block_size, buffer_size, false)
+    }
+
+    /// open は force が true の場合、他のプロセスのロックを奪ってデータベースを開く
+    /// ロックを持っていたプロセスが終了していることを確認してから使うこと
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        block_size: i32,
+        buffer_size: u64,
+        force: bool,
+    ) -> Result<Self> {
+        let config = DbConfig {
+            force,
+            ..DbConfig::new(block_size, buffer_size)
+        };
+        Self::with_config(dir, config)
+    }
+
+    /// with_config は設定に従ってデータベースのディレクトリを開く
+    pub fn with_config(dir: impl Into<PathBuf>, config: DbConfig) -> Result<Self> {
         let db_dir = dir.into();
-        let file_manager = Arc::new(Mutex::new(FileManager::new(db_dir, block_size)?));
+        let mut file_manager = FileManager::open(db_dir, config.block_size, config.force)?;
+        file_manager.set_max_open_files(config.max_open_files)?;
+        if let Some(log_dir) = &config.log_dir {
+            file_manager.set_file_dir(&config.log_file, log_dir)?;
+        }
+        let control = open_control_file(&file_manager, config.segment_blocks)?;
+        // テーブルのファイルを開く前に、セグメントに分けて保存しているかを設定する
+        file_manager.set_segment_blocks(control.segment_blocks);
+        let file_manager = Arc::new(Mutex::new(file_manager));
         let log_manager = Arc::new(Mutex::new(LogManager::new(
             file_manager.clone(),
-            LOG_FILE.into(),
+            config.log_file.clone(),
         )?));
-        let buffer_manager = Arc::new(Mutex::new(BufferManager::new(
-            file_manager.clone(),
-            log_manager.clone(),
-            buffer_size,
-        )));
-        let lock_table = Arc::new((Mutex::new(LockTable::default()), Condvar::new()));
+        let buffer_manager = Arc::new(
+            BufferManager::new(
+                file_manager.clone(),
+                log_manager.clone(),
+                config.buffer_size,
+            )
+            .with_clock(config.clock.clone())
+            .with_timeouts(config.timeouts)
+            .with_shards(config.buffer_shards),
+        );
+        let lock_table = Arc::new((
+            Mutex::new(
+                LockTable::new(config.clock.clone())
+                    .with_escalation_threshold(config.lock_escalation_threshold)
+                    .with_timeouts(config.timeouts),
+            ),
+            Condvar::new(),
+        ));
+
+        let commit_listeners = CommitListeners::default();
+        let notifications = NotificationBus::default();
+        let bus = notifications.clone();
+        commit_listeners.add(move |event| bus.publish(event));
 
         Ok(Self {
             file_manager,
@@ -45,30 +217,43 @@ impl TinyDB {
             buffer_manager,
             lock_table,
             planner: None,
+            single_threaded: config.single_threaded,
+            catalog_cache: Default::default(),
+            commit_listeners,
+            notifications,
+            recovery_progress: None,
+            control,
         })
     }
 
     pub fn init_planner(&mut self) -> Result<()> {
-        let tx = Arc::new(Mutex::new(Transaction::new(
-            self.file_manager.clone(),
-            self.log_manager.clone(),
-            self.buffer_manager.clone(),
-            self.lock_table.clone(),
-        )?));
-
         let is_new = unlock!(self.file_manager).is_new;
         if !is_new {
-            unlock!(tx).recover()?;
+            let tx = self.system_transaction()?;
+            let progress = self.recovery_progress.clone();
+            unlock!(tx).recover_with_progress(&mut |p| {
+                if let Some(progress) = &progress {
+                    progress(p);
+                }
+            })?;
+            unlock!(tx).commit()?;
+            self.migrate(&MIGRATIONS)?;
         }
+
+        let tx = self.system_transaction()?;
         let metadata_manager = Arc::new(Mutex::new(MetadataManager::new(is_new, tx.clone())?));
 
         let query_planner = Arc::new(Mutex::new(BasicQueryPlanner::new(metadata_manager.clone())))
             as Arc<Mutex<dyn QueryPlanner>>;
         let update_planner = Arc::new(Mutex::new(BasicUpdatePlanner::new(
             metadata_manager.clone(),
-        ))) as Arc<Mutex<dyn UpdatePlanner>>;
+        ))) as Arc<Mutex<dyn UpdatePlanner + Send>>;
 
-        let planner = Arc::new(Mutex::new(Planner::new(query_planner, update_planner)));
+        let planner = Arc::new(Mutex::new(Planner::new(
+            query_planner,
+            update_planner,
+            metadata_manager,
+        )));
 
         unlock!(tx).commit()?;
 
@@ -76,13 +261,163 @@ impl TinyDB {
         Ok(())
     }
 
+    /// migrate はコントロールファイルの版より新しい手順を順に実行して、データベースの形式を上げる
+    /// 手順ごとにコミットしてから、コントロールファイルの版を書き換える
+    pub fn migrate(&mut self, migrations: &[Migration]) -> Result<()> {
+        let db_dir = unlock!(self.file_manager).db_dir.clone();
+        for migration in pending_migrations(self.control.catalog_version, migrations)? {
+            let tx = self.system_transaction()?;
+            let result = (migration.run)(tx.clone()).with_context(|| {
+                format!(
+                    "migration to version {} failed: {}",
+                    migration.version, migration.description
+                )
+            });
+            finish(tx, result)?;
+            self.control.catalog_version = migration.version;
+            self.control.write(&db_dir)?;
+        }
+        Ok(())
+    }
+
+    /// system_transaction はリカバリやカタログの読み込みに使うトランザクションを作る
+    /// コミットしたことを知らせず、カタログのキャッシュも使わない
+    fn system_transaction(&self) -> Result<Arc<Mutex<Transaction>>> {
+        let mut tx = Transaction::new(
+            self.file_manager.clone(),
+            self.log_manager.clone(),
+            self.buffer_manager.clone(),
+            self.lock_table.clone(),
+        )?;
+        if self.single_threaded {
+            tx.disable_locks();
+        }
+        Ok(Arc::new(Mutex::new(tx)))
+    }
+
     pub fn transaction(&self) -> Result<Arc<Mutex<Transaction>>> {
-        let tx = Arc::new(Mutex::new(Transaction::new(
+        let mut tx = Transaction::new(
             self.file_manager.clone(),
             self.log_manager.clone(),
             self.buffer_manager.clone(),
             self.lock_table.clone(),
-        )?));
-        Ok(tx)
+        )?;
+        tx.set_commit_listeners(self.commit_listeners.clone());
+        tx.set_catalog_cache(self.catalog_cache.clone());
+        if self.single_threaded {
+            tx.disable_locks();
+        }
+        Ok(Arc::new(Mutex::new(tx)))
+    }
+
+    /// with_transaction は f を新しいトランザクションで実行して、成功したらコミット、失敗したらロールバックする
+    /// f の中で実行した複数の文はまとめてコミットされる
+    /// ロックのタイムアウトで失敗した場合は、ロールバックしてから新しいトランザクションで f を実行し直す
+    /// そのため f は何度呼ばれても問題ないように書くこと
+    ///
+    /// f はプランナーを共有したまま受け取るので、文を実行するたびに unlock! でロックを取る
+    /// f の実行中ずっとプランナーを占有しないので、ほかのスレッドも間に文を実行できる
+    pub fn with_transaction<T>(
+        &self,
+        f: impl FnMut(Arc<Mutex<Transaction>>, &Arc<Mutex<Planner>>) -> Result<T>,
+    ) -> Result<T> {
+        self.with_transaction_retry(RetryPolicy::default(), f)
+    }
+
+    /// with_transaction_retry は retry に従ってやり直す with_transaction
+    pub fn with_transaction_retry<T>(
+        &self,
+        retry: RetryPolicy,
+        mut f: impl FnMut(Arc<Mutex<Transaction>>, &Arc<Mutex<Planner>>) -> Result<T>,
+    ) -> Result<T> {
+        let planner = self
+            .planner
+            .clone()
+            .ok_or_else(|| anyhow!("planner is not initialized"))?;
+        retry.run(|| {
+            let tx = self.transaction()?;
+            let result = f(tx.clone(), &planner);
+            finish(tx, result)
+        })
+    }
+
+    /// on_commit はこのデータベースで作ったトランザクションがコミットした後に呼び出すコールバックを登録する
+    /// コールバックはコミットのログがディスクに書き込まれ、ロックが解放された後に呼び出され、
+    /// トランザクションが変更したテーブルの一覧を受け取る
+    pub fn on_commit(&self, callback: impl Fn(&CommitEvent) + Send + Sync + 'static) {
+        self.commit_listeners.add(callback);
+    }
+
+    /// on_recovery_progress は init_planner のリカバリでログを読んだ進み具合を受け取るコールバックを登録する
+    /// 大きなログを読む間、読んだログレコードの数と LSN が一定の間隔と最後に届く
+    pub fn on_recovery_progress(
+        &mut self,
+        callback: impl Fn(RecoveryProgress) + Send + Sync + 'static,
+    ) {
+        self.recovery_progress = Some(Arc::new(callback));
+    }
+
+    /// catalog_version はデータベースのコントロールファイルに記録したカタログの形式の版を返す
+    pub fn catalog_version(&self) -> i32 {
+        self.control.catalog_version
+    }
+
+    /// subscribe はテーブルの変更の知らせを受け取る Subscription を返す
+    /// 知らせはトランザクションがコミットした後に、変更したテーブルごとに届く
+    pub fn subscribe(&self) -> Subscription {
+        self.notifications.subscribe()
+    }
+}
+
+/// open_control_file は新しいデータベースには今のカタログの版を記録し、既存のデータベースでは記録した版を読む
+/// このバージョンより新しい形式のカタログは読めないので、エラーを返す
+///
+/// segment_blocks を指定した場合は、まだセグメントに分けていないデータベースに記録する
+/// 記録したブロック数と異なる場合は、既存のセグメントのブロックを読めなくなるのでエラーを返す
+fn open_control_file(
+    file_manager: &FileManager,
+    segment_blocks: Option<u64>,
+) -> Result<ControlFile> {
+    if file_manager.is_new {
+        let control = ControlFile::new(CATALOG_VERSION).with_segment_blocks(segment_blocks);
+        control.write(&file_manager.db_dir)?;
+        return Ok(control);
+    }
+    let mut control = ControlFile::read(&file_manager.db_dir)?.unwrap_or_default();
+    if control.catalog_version > CATALOG_VERSION {
+        bail!(
+            "unsupported catalog version: {} (supported up to {})",
+            control.catalog_version,
+            CATALOG_VERSION
+        );
+    }
+    let segment_blocks = segment_blocks.filter(|&blocks| blocks > 0);
+    match (control.segment_blocks, segment_blocks) {
+        (_, None) => {}
+        (None, Some(_)) => {
+            control = control.with_segment_blocks(segment_blocks);
+            control.write(&file_manager.db_dir)?;
+        }
+        (Some(recorded), Some(blocks)) if recorded != blocks => bail!(
+            "segment blocks mismatch: {} (database uses {})",
+            blocks,
+            recorded
+        ),
+        (Some(_), Some(_)) => {}
+    }
+    Ok(control)
+}
+
+/// finish は結果が成功ならコミットし、失敗ならロールバックする
+pub(crate) fn finish<T>(tx: Arc<Mutex<Transaction>>, result: Result<T>) -> Result<T> {
+    match result {
+        Ok(value) => {
+            unlock!(tx).commit()?;
+            Ok(value)
+        }
+        Err(err) => {
+            unlock!(tx).rollback()?;
+            Err(err)
+        }
     }
 }
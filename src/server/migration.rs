@@ -0,0 +1,229 @@
+use crate::{
+    index::{
+        hash::{bucket_table, NUM_BUCKETS},
+        IndexType,
+    },
+    metadata::{
+        catalog::{
+            field_catalog_schema, fldcat, idxcat, legacy_table_catalog_schema,
+            table_catalog_schema, tblcat, FIELD_CATALOG, INDEX_CATALOG, TABLE_CATALOG,
+        },
+        table_manager::TableManager,
+    },
+    query::scan::Scan as _,
+    record::{layout::Layout, schema::FieldTypes, table_scan::TableScan},
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::sync::{Arc, Mutex};
+
+/// Migration はデータベースの形式を1つ前の版から version に上げる手順
+///
+/// 手順はトランザクションの中で実行し、コミットしてからコントロールファイルの版を書き換える
+/// コミットしてから版を書き換えるまでに止まると次に開いたときにもう一度実行するので、
+/// すでに新しい形式になっていれば何もしないように書くこと
+#[derive(Clone, Copy)]
+pub struct Migration {
+    /// version はこの手順を実行した後の版
+    pub version: i32,
+    pub description: &'static str,
+    pub run: fn(Arc<Mutex<Transaction>>) -> Result<()>,
+}
+
+/// MIGRATIONS は古い版から今の版まで、版の順に並べた手順
+pub const MIGRATIONS: [Migration; 2] = [
+    Migration {
+        version: 2,
+        description: "add creation time and statistics columns to tblcat",
+        run: upgrade_table_catalog,
+    },
+    Migration {
+        version: 3,
+        description: "move hash index buckets to the index file namespace",
+        run: rename_hash_buckets,
+    },
+];
+
+/// pending_migrations は version の版のデータベースに実行する手順を順に返す
+/// 手順の版が1つずつ増えていなければエラーを返す
+pub fn pending_migrations(version: i32, migrations: &[Migration]) -> Result<Vec<Migration>> {
+    let pending: Vec<Migration> = migrations
+        .iter()
+        .filter(|migration| migration.version > version)
+        .copied()
+        .collect();
+    for (expected, migration) in (version + 1..).zip(&pending) {
+        if migration.version != expected {
+            bail!(
+                "missing migration to version {} (found version {})",
+                expected,
+                migration.version
+            );
+        }
+    }
+    Ok(pending)
+}
+
+/// upgrade_table_catalog は作成した時刻と統計の列がない tblcat を、列を加えたレイアウトで書き直す
+///
+/// スロットのサイズが変わるので、元のレコードを読んでからファイルを空にして入れ直す
+/// 作成した時刻は分からないので 0 にし、統計は取り直していないものとする
+pub fn upgrade_table_catalog(tx: Arc<Mutex<Transaction>>) -> Result<()> {
+    let legacy_layout = Arc::new(Layout::try_from_schema(Arc::new(
+        legacy_table_catalog_schema(),
+    ))?);
+    let layout = Arc::new(Layout::try_from_schema(Arc::new(table_catalog_schema()))?);
+    let field_layout = Arc::new(Layout::try_from_schema(Arc::new(field_catalog_schema()))?);
+
+    let mut fcat = TableScan::new(tx.clone(), FIELD_CATALOG, field_layout.clone())?;
+    let mut upgraded = false;
+    while fcat.next()? {
+        if fcat.get_string(fldcat::TABLE_NAME)? == TABLE_CATALOG
+            && fcat.get_string(fldcat::FIELD_NAME)? == tblcat::CREATED
+        {
+            upgraded = true;
+            break;
+        }
+    }
+    fcat.close();
+    if upgraded {
+        return Ok(());
+    }
+
+    let mut tables = vec![];
+    let mut tcat = TableScan::new(tx.clone(), TABLE_CATALOG, legacy_layout)?;
+    while tcat.next()? {
+        tables.push((
+            tcat.get_string(tblcat::TABLE_NAME)?,
+            tcat.get_int(tblcat::SLOT_SIZE)?,
+        ));
+    }
+    // カタログを作る前のデータベースには書き直すものがない
+    if tables.is_empty() {
+        tcat.close();
+        return Ok(());
+    }
+    tcat.truncate()?;
+
+    // 古いレイアウトのままでも読み込めるように、tblcat 自身のレコードは先頭のスロットに置く
+    tables.sort_by_key(|(table_name, _)| table_name != TABLE_CATALOG);
+    let mut tcat = TableScan::new(tx.clone(), TABLE_CATALOG, layout.clone())?;
+    for (table_name, slot_size) in tables {
+        let slot_size = if table_name == TABLE_CATALOG {
+            layout.slot_size
+        } else {
+            slot_size
+        };
+        tcat.insert()?;
+        tcat.set_string(tblcat::TABLE_NAME, &table_name)?;
+        tcat.set_int(tblcat::SLOT_SIZE, slot_size)?;
+        tcat.set_int(tblcat::CREATED, 0)?;
+        tcat.set_int(tblcat::ANALYZED, 0)?;
+        tcat.set_int(tblcat::NUM_BLOCKS, 0)?;
+        tcat.set_int(tblcat::NUM_RECORDS, 0)?;
+    }
+    tcat.close();
+
+    let mut fcat = TableScan::new(tx, FIELD_CATALOG, field_layout)?;
+    for field_name in [
+        tblcat::CREATED,
+        tblcat::ANALYZED,
+        tblcat::NUM_BLOCKS,
+        tblcat::NUM_RECORDS,
+    ] {
+        fcat.insert()?;
+        fcat.set_string(fldcat::TABLE_NAME, TABLE_CATALOG)?;
+        fcat.set_string(fldcat::FIELD_NAME, field_name)?;
+        fcat.set_int(fldcat::TYPE, FieldTypes::Integer as i32)?;
+        fcat.set_int(fldcat::LENGTH, 0)?;
+        fcat.set_int(fldcat::OFFSET, layout.offset(field_name).unwrap())?;
+    }
+    fcat.close();
+    Ok(())
+}
+
+/// rename_hash_buckets はハッシュ索引のバケットのテーブルを、INDEX_FILE_PREFIX を付けた名前に移す
+///
+/// 前の版ではバケットを `<index><bucket>.tbl` に格納していたので、同じ名前のテーブルと重なることがあった
+/// 同じ名前のテーブルがカタログにあれば、そのファイルはテーブルのものとして移さない
+/// 移した後のファイルがあるバケットは飛ばすので、途中で止まってももう一度実行すれば続きから移す
+pub fn rename_hash_buckets(tx: Arc<Mutex<Transaction>>) -> Result<()> {
+    let mut table_manager = TableManager::new(false, tx.clone())?;
+    let index_layout = Arc::new(table_manager.get_layout(INDEX_CATALOG, tx.clone())?);
+    if !index_layout.schema.has_field(idxcat::INDEX_NAME) {
+        return Ok(());
+    }
+    // 索引の種類の列がない古いカタログの索引はすべてハッシュ索引
+    let typed = index_layout.schema.has_field(idxcat::INDEX_TYPE);
+    let mut indexes = vec![];
+    let mut icat = TableScan::new(tx.clone(), INDEX_CATALOG, index_layout)?;
+    while icat.next()? {
+        let index_type = if typed {
+            icat.get_string(idxcat::INDEX_TYPE)?.parse()?
+        } else {
+            IndexType::Hash
+        };
+        if index_type == IndexType::Hash {
+            indexes.push(icat.get_string(idxcat::INDEX_NAME)?);
+        }
+    }
+    icat.close();
+
+    let table_layout = Arc::new(Layout::try_from_schema(Arc::new(table_catalog_schema()))?);
+    let mut tables = vec![];
+    let mut tcat = TableScan::new(tx.clone(), TABLE_CATALOG, table_layout)?;
+    while tcat.next()? {
+        tables.push(tcat.get_string(tblcat::TABLE_NAME)?);
+    }
+    tcat.close();
+
+    let mut tx = unlock!(tx);
+    for index_name in indexes {
+        for bucket in 0..NUM_BUCKETS {
+            let legacy_table = format!("{}{}", index_name, bucket);
+            let from = format!("{}.tbl", legacy_table);
+            let to = format!("{}.tbl", bucket_table(&index_name, bucket));
+            if tables.contains(&legacy_table) || !tx.file_exists(&from) || tx.file_exists(&to) {
+                continue;
+            }
+            tx.rename_file(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::catalog::CATALOG_VERSION;
+
+    fn noop(_: Arc<Mutex<Transaction>>) -> Result<()> {
+        Ok(())
+    }
+
+    #[test]
+    fn should_return_pending_migrations_in_order() -> Result<()> {
+        assert_eq!(MIGRATIONS.last().unwrap().version, CATALOG_VERSION);
+        let versions = |version| -> Result<Vec<i32>> {
+            Ok(pending_migrations(version, &MIGRATIONS)?
+                .iter()
+                .map(|migration| migration.version)
+                .collect())
+        };
+        assert_eq!(versions(1)?, (2..=CATALOG_VERSION).collect::<Vec<_>>());
+        assert!(versions(CATALOG_VERSION)?.is_empty());
+
+        let migration = |version| Migration {
+            version,
+            description: "noop",
+            run: noop,
+        };
+        let gap = [migration(2), migration(4)];
+        assert!(pending_migrations(1, &gap).is_err());
+        assert!(pending_migrations(2, &gap).is_err());
+        assert_eq!(pending_migrations(4, &gap)?.len(), 0);
+        assert!(pending_migrations(0, &[migration(2)]).is_err());
+        Ok(())
+    }
+}
@@ -1 +1,5 @@
 pub mod db;
+pub mod migration;
+pub mod notification;
+pub mod retry;
+pub mod session;
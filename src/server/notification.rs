@@ -0,0 +1,179 @@
+use crate::tx::commit_listener::CommitEvent;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::Duration,
+};
+
+/// Notification は購読しているテーブルを変更したトランザクションがコミットしたことを知らせる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub table_name: String,
+    pub tx_num: i32,
+}
+
+#[derive(Default)]
+struct Inbox {
+    tables: BTreeSet<String>,
+    queue: VecDeque<Notification>,
+}
+
+type InboxCell = (Mutex<Inbox>, Condvar);
+type SharedInbox = Arc<InboxCell>;
+
+/// NotificationBus はコミットしたトランザクションが変更したテーブルを、そのテーブルを購読している
+/// Subscription に知らせる
+///
+/// TinyDB はコミットリスナーとして publish を登録するので、コミットのたびに知らせが届く
+/// 知らせは Subscription ごとのキューに溜まり、受け取るまで残る
+/// clone したものは同じ購読者のリストを共有する
+#[derive(Clone, Default)]
+pub struct NotificationBus {
+    inboxes: Arc<Mutex<Vec<Weak<InboxCell>>>>,
+}
+
+impl NotificationBus {
+    /// subscribe はまだどのテーブルも購読していない Subscription を返す
+    pub fn subscribe(&self) -> Subscription {
+        let inbox: SharedInbox = Default::default();
+        self.inboxes.lock().unwrap().push(Arc::downgrade(&inbox));
+        Subscription { inbox }
+    }
+
+    /// publish はイベントのテーブルを購読している Subscription に知らせを届ける
+    /// 破棄された Subscription はここでリストから取り除く
+    pub fn publish(&self, event: &CommitEvent) {
+        let mut inboxes = self.inboxes.lock().unwrap();
+        inboxes.retain(|inbox| inbox.strong_count() > 0);
+        for inbox in inboxes.iter().filter_map(Weak::upgrade) {
+            let (lock, cvar) = &*inbox;
+            let mut inbox = lock.lock().unwrap();
+            let mut notified = false;
+            for table_name in &event.tables {
+                if inbox.tables.contains(table_name) {
+                    inbox.queue.push_back(Notification {
+                        table_name: table_name.clone(),
+                        tx_num: event.tx_num,
+                    });
+                    notified = true;
+                }
+            }
+            if notified {
+                cvar.notify_all();
+            }
+        }
+    }
+}
+
+/// Subscription はテーブルの変更の知らせを受け取る窓口
+/// 破棄すると購読をやめる
+pub struct Subscription {
+    inbox: SharedInbox,
+}
+
+impl Subscription {
+    /// listen はテーブルを購読する。購読する前のコミットの知らせは届かない
+    pub fn listen(&self, table_name: impl Into<String>) {
+        self.inbox
+            .0
+            .lock()
+            .unwrap()
+            .tables
+            .insert(table_name.into());
+    }
+
+    /// unlisten はテーブルの購読をやめる。すでに届いている知らせは残る
+    pub fn unlisten(&self, table_name: &str) {
+        self.inbox.0.lock().unwrap().tables.remove(table_name);
+    }
+
+    /// tables は購読しているテーブルの名前を昇順で返す
+    pub fn tables(&self) -> Vec<String> {
+        self.inbox
+            .0
+            .lock()
+            .unwrap()
+            .tables
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// drain は届いている知らせを届いた順にすべて受け取る
+    pub fn drain(&self) -> Vec<Notification> {
+        self.inbox.0.lock().unwrap().queue.drain(..).collect()
+    }
+
+    /// recv_timeout は知らせが届くまで最大 timeout だけ待って1つ受け取る
+    /// 時間内に届かなければ None を返す
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Notification> {
+        let (lock, cvar) = &*self.inbox;
+        let inbox = lock.lock().unwrap();
+        let (mut inbox, _) = cvar
+            .wait_timeout_while(inbox, timeout, |inbox| inbox.queue.is_empty())
+            .unwrap();
+        inbox.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn event(tx_num: i32, tables: &[&str]) -> CommitEvent {
+        CommitEvent {
+            tx_num,
+            tables: tables.iter().map(|table| table.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn should_deliver_notifications_for_listened_tables() {
+        let bus = NotificationBus::default();
+        let subscription = bus.subscribe();
+        subscription.listen("T");
+        subscription.listen("U");
+
+        bus.publish(&event(1, &["T", "V"]));
+        bus.publish(&event(2, &["U"]));
+        subscription.unlisten("U");
+        bus.publish(&event(3, &["U"]));
+        assert_eq!(
+            subscription.drain(),
+            vec![
+                Notification {
+                    table_name: "T".into(),
+                    tx_num: 1
+                },
+                Notification {
+                    table_name: "U".into(),
+                    tx_num: 2
+                },
+            ]
+        );
+        assert!(subscription.drain().is_empty());
+
+        // 破棄した購読者には届けない
+        drop(subscription);
+        bus.publish(&event(4, &["T"]));
+        assert!(bus.inboxes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_wait_for_notification() {
+        let bus = NotificationBus::default();
+        let subscription = bus.subscribe();
+        subscription.listen("T");
+        assert_eq!(subscription.recv_timeout(Duration::from_millis(10)), None);
+
+        let publisher = bus.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            publisher.publish(&event(5, &["T"]));
+        });
+        let notification = subscription.recv_timeout(Duration::from_secs(5));
+        handle.join().unwrap();
+        assert_eq!(notification.map(|n| n.tx_num), Some(5));
+    }
+}
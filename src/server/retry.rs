@@ -0,0 +1,101 @@
+use crate::tx::error::TransientError as _;
+use anyhow::Result;
+use std::{thread, time::Duration};
+
+/// RetryPolicy は一時的なエラーで失敗した処理をやり直す回数と間隔を決める
+/// やり直す前に待つ時間は backoff から始めて、やり直すたびに倍にする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// none はやり直さないポリシーを返す
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    pub fn with_max_attempts(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// run は f を実行して、一時的なエラーで失敗した場合は max_attempts 回まで f を実行し直す
+    /// f はトランザクションの開始からコミットまたはロールバックまでを行うこと
+    pub fn run<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(err) if err.is_transient() && attempt < self.max_attempts => {
+                    thread::sleep(self.backoff * 2u32.pow(attempt - 1));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx::concurrency::lock_timeout::LockTimeout;
+    use anyhow::anyhow;
+
+    #[test]
+    fn should_retry_only_transient_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::ZERO,
+        };
+
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            if attempts < 3 {
+                return Err(anyhow!(LockTimeout));
+            }
+            Ok(attempts)
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<()> = policy.run(|| {
+            attempts += 1;
+            Err(anyhow!(LockTimeout))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result: Result<()> = policy.run(|| {
+            attempts += 1;
+            Err(anyhow!("table not found"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result: Result<()> = RetryPolicy::none().run(|| {
+            attempts += 1;
+            Err(anyhow!(LockTimeout))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
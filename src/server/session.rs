@@ -0,0 +1,320 @@
+use super::{
+    db::{finish, TinyDB},
+    notification::{Notification, Subscription},
+    retry::RetryPolicy,
+};
+use crate::{
+    parse::parser::Parser,
+    plan::planner::Planner,
+    query::{
+        constant::Constant,
+        cursor_data::CursorStatement,
+        listen_data::ListenStatement,
+        query_data::QueryData,
+        result_set_metadata::{ColumnMetadata, ResultSetMetadata},
+        scan::Scan,
+    },
+    record::{rid::RID, schema::FieldTypes, temp_table::TempTable},
+    tools::as_of::open_as_of,
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// ExecuteResult は Session::execute の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecuteResult {
+    /// 問い合わせや fetch で読み込んだレコード
+    /// metadata の列はレコードの値と同じ順に並ぶ
+    Rows {
+        metadata: ResultSetMetadata,
+        rows: Vec<Vec<Constant>>,
+    },
+    /// 更新したレコード数
+    Updated(i32),
+}
+
+/// Cursor は declare cursor で作ったカーソル
+/// 問い合わせの結果は一時テーブルに格納してあり、last_rid は最後に fetch したレコードを指す
+struct Cursor {
+    table: TempTable,
+    metadata: ResultSetMetadata,
+    last_rid: Option<RID>,
+}
+
+/// Session は CLI やドライバが文を1つずつ実行するための窓口
+/// 文ごとにトランザクションを開始して、成功したらコミット、失敗したらロールバックする
+///
+/// カーソルは declare cursor の時点で問い合わせの結果を一時テーブルに書き出す
+/// そのトランザクションはすぐにコミットするので、fetch の間は元のテーブルのロックを持ち続けない
+///
+/// ユーザーを設定すると、文はそのユーザーの権限で実行される
+///
+/// リトライポリシーを設定すると、ロックのタイムアウトで失敗した文を新しいトランザクションで実行し直す
+/// 既定ではやり直さない
+///
+/// listen でテーブルを購読すると、そのテーブルを変更したトランザクションがコミットするたびに知らせが溜まり、
+/// notifications で受け取れる。キャッシュを捨てるきっかけなどに使う
+///
+/// `select ... as of lsn <n>` はその時点のデータベースを一時ディレクトリに作って問い合わせる（実験的な機能）
+pub struct Session<'a> {
+    db: &'a TinyDB,
+    planner: Arc<Mutex<Planner>>,
+    cursors: HashMap<String, Cursor>,
+    user: Option<String>,
+    retry: RetryPolicy,
+    subscription: Option<Subscription>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(db: &'a TinyDB) -> Result<Self> {
+        let planner = db
+            .planner
+            .clone()
+            .ok_or_else(|| anyhow!("planner is not initialized"))?;
+        Ok(Self {
+            db,
+            planner,
+            cursors: HashMap::new(),
+            user: None,
+            retry: RetryPolicy::none(),
+            subscription: None,
+        })
+    }
+
+    /// set_retry_policy は一時的なエラーで失敗した文をやり直すポリシーを設定する
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// set_user は文を実行するユーザーを設定する。None の場合は管理者として実行する
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    /// check_syntax は文を実行せずに構文だけを確かめる
+    /// 失敗した文が構文の誤りか実行の失敗かを区別するのに使う
+    pub fn check_syntax(sql: &str) -> Result<()> {
+        Parser::new(sql).check_syntax()
+    }
+
+    pub fn execute(&mut self, sql: &str) -> Result<ExecuteResult> {
+        let mut parser = Parser::new(sql);
+        if parser.is_cursor_cmd() {
+            return match parser.cursor_cmd()? {
+                CursorStatement::Declare { cursor_name, query } => {
+                    self.declare_cursor(cursor_name, *query)
+                }
+                CursorStatement::Fetch { cursor_name, count } => self.fetch(&cursor_name, count),
+                CursorStatement::Close { cursor_name } => self.close_cursor(&cursor_name),
+            };
+        }
+
+        if parser.is_listen_cmd() {
+            match parser.listen_cmd()? {
+                ListenStatement::Listen { table_name } => {
+                    let db = self.db;
+                    self.subscription
+                        .get_or_insert_with(|| db.subscribe())
+                        .listen(table_name);
+                }
+                ListenStatement::Unlisten { table_name } => {
+                    if let Some(subscription) = &self.subscription {
+                        subscription.unlisten(&table_name);
+                    }
+                }
+            }
+            return Ok(ExecuteResult::Updated(0));
+        }
+
+        // プランの各ノードを1行ずつ返す
+        if parser.is_explain() {
+            let query = parser.explain()?;
+            return self.in_transaction(|planner, tx| {
+                let plan = planner.create_plan(query.clone(), tx)?;
+                let lines = unlock!(plan).explain();
+                let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+                Ok(ExecuteResult::Rows {
+                    metadata: ResultSetMetadata::new(vec![ColumnMetadata {
+                        name: "plan".into(),
+                        field_type: FieldTypes::Varchar,
+                        length: width as i32,
+                    }]),
+                    rows: lines
+                        .into_iter()
+                        .map(|line| vec![Constant::String(line)])
+                        .collect(),
+                })
+            });
+        }
+
+        if parser.is_query() {
+            let query = parser.query()?;
+            if let Some(lsn) = query.as_of {
+                return self.query_as_of(query.with_as_of(None), lsn);
+            }
+            return self.in_transaction(|planner, tx| run_query(planner, tx, query.clone()));
+        }
+
+        self.in_transaction(|planner, tx| {
+            Ok(ExecuteResult::Updated(planner.execute_update(sql, tx)?))
+        })
+    }
+
+    /// query_as_of は LSN の時点のデータベースを一時ディレクトリに作って問い合わせる
+    /// 作ったデータベースは問い合わせの後に削除する。バッファの数は元のデータベースと同じにする
+    fn query_as_of(&mut self, query: QueryData, lsn: i32) -> Result<ExecuteResult> {
+        let dir = std::env::temp_dir().join(format!("tinydb_as_of_{}", Uuid::new_v4()));
+        let buffer_size = self.db.buffer_manager.num_buffers();
+        let result = open_as_of(self.db, lsn, &dir, buffer_size).and_then(|snapshot| {
+            snapshot.with_transaction(|tx, planner| {
+                unlock!(tx).set_user(self.user.clone());
+                run_query(&mut unlock!(planner), tx, query.clone())
+            })
+        });
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    /// notifications は購読しているテーブルの変更の知らせを、届いた順にすべて受け取る
+    pub fn notifications(&mut self) -> Vec<Notification> {
+        self.subscription
+            .as_ref()
+            .map_or_else(Vec::new, Subscription::drain)
+    }
+
+    /// cursor_names は開いているカーソルの名前を返す
+    pub fn cursor_names(&self) -> Vec<String> {
+        self.cursors.keys().cloned().collect()
+    }
+
+    fn declare_cursor(&mut self, cursor_name: String, query: QueryData) -> Result<ExecuteResult> {
+        if self.cursors.contains_key(&cursor_name) {
+            bail!("cursor already exists: {}", cursor_name);
+        }
+
+        let (table, metadata, count) = self.in_transaction(|planner, tx| {
+            let plan = planner.create_plan(query.clone(), tx.clone())?;
+            let schema = unlock!(plan).schema();
+            let metadata = unlock!(plan).metadata();
+            let table = TempTable::new(schema.clone())?;
+
+            let scan = unlock!(plan).open()?;
+            let mut scan = unlock!(scan);
+            let mut dest = table.open(tx)?;
+            let mut count = 0;
+            while scan.next()? {
+                dest.insert()?;
+                for field_name in &schema.fields {
+                    dest.set_value(field_name, scan.get_value(field_name)?)?;
+                }
+                count += 1;
+            }
+            scan.close();
+            dest.close();
+            Ok((table, metadata, count))
+        })?;
+
+        self.cursors.insert(
+            cursor_name,
+            Cursor {
+                table,
+                metadata,
+                last_rid: None,
+            },
+        );
+        Ok(ExecuteResult::Updated(count))
+    }
+
+    /// fetch はカーソルの続きから count 件のレコードを読み込む
+    /// 最後まで読み込んでいる場合は空の結果を返す
+    fn fetch(&mut self, cursor_name: &str, count: i32) -> Result<ExecuteResult> {
+        let db = self.db;
+        let cursor = self
+            .cursors
+            .get_mut(cursor_name)
+            .ok_or_else(|| anyhow!("cursor not found: {}", cursor_name))?;
+
+        let (rows, last_rid) = self.retry.run(|| {
+            let tx = db.transaction()?;
+            let result = (|| {
+                let mut scan = cursor.table.open(tx.clone())?;
+                match cursor.last_rid {
+                    Some(rid) => scan.move_to_rid(rid),
+                    None => scan.before_first(),
+                }
+                let mut rows = vec![];
+                let mut last_rid = cursor.last_rid;
+                while rows.len() < count as usize && scan.next()? {
+                    rows.push(read_row(&mut scan, &cursor.metadata)?);
+                    last_rid = Some(scan.get_rid()?);
+                }
+                scan.close();
+                Ok((rows, last_rid))
+            })();
+            finish(tx, result)
+        })?;
+
+        cursor.last_rid = last_rid;
+        Ok(ExecuteResult::Rows {
+            metadata: cursor.metadata.clone(),
+            rows,
+        })
+    }
+
+    fn close_cursor(&mut self, cursor_name: &str) -> Result<ExecuteResult> {
+        let cursor = self
+            .cursors
+            .remove(cursor_name)
+            .ok_or_else(|| anyhow!("cursor not found: {}", cursor_name))?;
+        let tx = self.db.transaction()?;
+        let result = cursor.table.drop(tx.clone());
+        finish(tx, result)?;
+        Ok(ExecuteResult::Updated(0))
+    }
+
+    /// in_transaction は新しいトランザクションで f を実行する
+    /// 一時的なエラーで失敗した場合は、リトライポリシーに従って新しいトランザクションで f を実行し直す
+    fn in_transaction<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Planner, Arc<Mutex<Transaction>>) -> Result<T>,
+    ) -> Result<T> {
+        self.retry.run(|| {
+            let tx = self.db.transaction()?;
+            unlock!(tx).set_user(self.user.clone());
+            let result = f(&mut unlock!(self.planner), tx.clone());
+            finish(tx, result)
+        })
+    }
+}
+
+pub(crate) fn run_query(
+    planner: &mut Planner,
+    tx: Arc<Mutex<Transaction>>,
+    query: QueryData,
+) -> Result<ExecuteResult> {
+    let plan = planner.create_plan(query, tx)?;
+    let metadata = unlock!(plan).metadata();
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut rows = vec![];
+    while scan.next()? {
+        rows.push(read_row(&mut *scan, &metadata)?);
+    }
+    scan.close();
+    Ok(ExecuteResult::Rows { metadata, rows })
+}
+
+pub(crate) fn read_row(scan: &mut dyn Scan, metadata: &ResultSetMetadata) -> Result<Vec<Constant>> {
+    metadata
+        .columns()
+        .iter()
+        .map(|column| scan.get_value(&column.name))
+        .collect()
+}
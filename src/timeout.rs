@@ -0,0 +1,169 @@
+use crate::tx::concurrency::lock_timeout::LockTimeout;
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// DEFAULT_TIMEOUT は空きバッファやロックを待つ既定の時間
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// TimeoutKind は何を待っていたかを表す
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutKind {
+    /// 空きバッファを待つ
+    Buffer,
+    /// 共有ロックを待つ
+    SLock,
+    /// 排他ロックを待つ
+    XLock,
+}
+
+impl Display for TimeoutKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TimeoutKind::Buffer => write!(f, "buffer wait"),
+            TimeoutKind::SLock => write!(f, "S-lock wait"),
+            TimeoutKind::XLock => write!(f, "X-lock wait"),
+        }
+    }
+}
+
+/// Timeouts は空きバッファ、共有ロック、排他ロックを待つ時間と、待つ間に確認し直す間隔を決める
+///
+/// 確認し直す間隔は backoff から始めて、確認するたびに max_backoff まで倍にする
+/// 同時に待ち始めたトランザクションが同じ時刻に確認し直さないように、間隔はその半分から全体までの間でばらつかせる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub buffer: Duration,
+    pub s_lock: Duration,
+    pub x_lock: Duration,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            buffer: DEFAULT_TIMEOUT,
+            s_lock: DEFAULT_TIMEOUT,
+            x_lock: DEFAULT_TIMEOUT,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Timeouts {
+    /// get は kind を待つ時間を返す
+    pub fn get(&self, kind: TimeoutKind) -> Duration {
+        match kind {
+            TimeoutKind::Buffer => self.buffer,
+            TimeoutKind::SLock => self.s_lock,
+            TimeoutKind::XLock => self.x_lock,
+        }
+    }
+
+    /// backoff は attempt 回目（0 から数える）に確認し直すまでに待つ時間を返す
+    /// 待つ時間が 0 だと時計が進まずに確認し続けるので、少なくとも 1ms 待つ
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.backoff.max(Duration::from_millis(1));
+        let max = self.max_backoff.max(base);
+        let backoff = base
+            .checked_mul(2u32.saturating_pow(attempt))
+            .map_or(max, |backoff| backoff.min(max));
+        let half = backoff / 2;
+        half + jitter(backoff - half)
+    }
+
+    /// error は kind を待つ時間を過ぎたことを表すエラーを返す
+    /// ロックの場合は原因に LockTimeout を含めるので、一時的なエラーとしてやり直せる
+    pub fn error(&self, kind: TimeoutKind) -> anyhow::Error {
+        let timeout = WaitTimeout {
+            kind,
+            timeout: self.get(kind),
+        };
+        match kind {
+            TimeoutKind::Buffer => timeout.into(),
+            TimeoutKind::SLock | TimeoutKind::XLock => {
+                anyhow::Error::new(LockTimeout).context(timeout)
+            }
+        }
+    }
+}
+
+/// jitter は 0 から max までのばらついた時間を返す
+fn jitter(max: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let nanos = max.as_nanos().min(u64::MAX as u128 - 1) as u64;
+    Duration::from_nanos(random % (nanos + 1))
+}
+
+/// WaitTimeout は空きバッファやロックを待つ時間を過ぎたことを表すエラー
+/// kind でどれを待っていたかがわかる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitTimeout {
+    pub kind: TimeoutKind,
+    pub timeout: Duration,
+}
+
+impl Display for WaitTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} timed out after {:?}", self.kind, self.timeout)
+    }
+}
+
+impl std::error::Error for WaitTimeout {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx::error::TransientError as _;
+
+    #[test]
+    fn should_back_off_with_jitter() {
+        let timeouts = Timeouts {
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(40),
+            ..Timeouts::default()
+        };
+        for attempt in 0..5 {
+            let expected = Duration::from_millis((10 << attempt).min(40));
+            let backoff = timeouts.backoff(attempt);
+            assert!(
+                backoff >= expected / 2 && backoff <= expected,
+                "{:?}",
+                backoff
+            );
+        }
+        assert!(timeouts.backoff(u32::MAX) <= Duration::from_millis(40));
+
+        let zero = Timeouts {
+            backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            ..Timeouts::default()
+        };
+        assert!(zero.backoff(0) > Duration::ZERO);
+    }
+
+    #[test]
+    fn should_tell_which_timeout_fired() {
+        let timeouts = Timeouts {
+            x_lock: Duration::from_secs(1),
+            ..Timeouts::default()
+        };
+        let err = timeouts.error(TimeoutKind::XLock);
+        assert_eq!(err.to_string(), "X-lock wait timed out after 1s");
+        assert_eq!(
+            err.downcast_ref::<WaitTimeout>().map(|t| t.kind),
+            Some(TimeoutKind::XLock)
+        );
+        assert!(err.downcast_ref::<LockTimeout>().is_some());
+        assert!(err.is_transient());
+
+        let err = timeouts.error(TimeoutKind::Buffer);
+        assert_eq!(err.to_string(), "buffer wait timed out after 3s");
+        assert!(!err.is_transient());
+    }
+}
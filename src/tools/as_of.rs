@@ -0,0 +1,142 @@
+use crate::{
+    file::{lock::LOCK_FILE, temp_file_manager::TempFileManager},
+    server::db::TinyDB,
+    tx::recovery::record::{create_log_record, LogRecordType},
+    unlock,
+};
+use anyhow::{bail, Result};
+use std::{collections::HashSet, fs, path::PathBuf};
+
+/// open_as_of はログの lsn 番目のレコードの時点のデータベースを dest に作って開く（実験的な機能）
+///
+/// LSN はログファイルの先頭から数えたレコードの位置で、現在の値は LogManager::latest_lsn で分かる
+/// ログには変更前の値しか記録していないので、今のファイルを dest に複製してから、
+/// lsn より後のレコードと、lsn の時点でコミットしていなかったトランザクションのレコードを新しい順に取り消す
+/// そのため lsn の時点でコミット済みの変更だけが残る
+///
+/// ファイルを複製してからログを読むので、複製に含まれる変更は必ずログに記録されている
+/// 作ったデータベースは過去の状態を読むためのもので、変更しても元のデータベースには反映されない
+/// buffer_size は作ったデータベースのバッファの数
+pub fn open_as_of(
+    db: &TinyDB,
+    lsn: i32,
+    dest: impl Into<PathBuf>,
+    buffer_size: u64,
+) -> Result<TinyDB> {
+    let dest = dest.into();
+    if dest.exists() {
+        bail!("directory already exists: {}", dest.display());
+    }
+    let (db_dir, block_size) = {
+        let fm = unlock!(db.file_manager);
+        (fm.db_dir.clone(), fm.block_size)
+    };
+    let log_file = unlock!(db.log_manager).log_file().to_string();
+
+    fs::create_dir_all(&dest)?;
+    for entry in fs::read_dir(db_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.path().is_file()
+            || name == LOCK_FILE
+            || name == log_file
+            || TempFileManager::is_temp_file(&name)
+        {
+            continue;
+        }
+        fs::copy(entry.path(), dest.join(&name))?;
+    }
+
+    // ログを古い順に読むので、i 番目のレコードの LSN は i + 1 になる
+    let iter = unlock!(db.log_manager).iter_forward()?;
+    let mut records = vec![];
+    for entry in iter {
+        records.push(create_log_record(&entry?.record)?);
+    }
+    if lsn < 0 || lsn as usize > records.len() {
+        bail!("lsn out of range: {} (latest {})", lsn, records.len());
+    }
+    let committed: HashSet<i32> = records[..lsn as usize]
+        .iter()
+        .filter(|record| matches!(record.op(), LogRecordType::Commit | LogRecordType::Rollback))
+        .map(|record| record.tx_number())
+        .collect();
+
+    // コントロールファイルも複製するので、セグメントに分けたファイルはそのまま読める
+    let snapshot = TinyDB::new(&dest, block_size, buffer_size)?;
+    let tx = snapshot.transaction()?;
+    for (i, record) in records.iter_mut().enumerate().rev() {
+        if i < lsn as usize && committed.contains(&record.tx_number()) {
+            continue;
+        }
+        // 一時ファイルのように複製していないファイルへの変更は読む必要がない
+        let copied = record
+            .block()
+            .is_some_and(|block| unlock!(snapshot.file_manager).exists(&block.filename));
+        if copied {
+            record.undo(&mut unlock!(tx))?;
+        }
+    }
+    unlock!(tx).commit()?;
+
+    let mut snapshot = snapshot;
+    snapshot.init_planner()?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_open_database_as_of_lsn() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_open_database_as_of_lsn");
+        let mut db = TinyDB::new(test_directory.join("db"), 400, 8)?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("create table T(A int)", tx.clone())?;
+            unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+        })?;
+        let lsn = unlock!(db.log_manager).latest_lsn();
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("update T set A = 2 where A = 1", tx.clone())?;
+            unlock!(planner).execute_update("insert into T(A) values (3)", tx)
+        })?;
+        // コミットしていない変更は過去の時点にも現在の時点にも残らない
+        let pending = db.transaction()?;
+        db.planner
+            .clone()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .execute_update("insert into T(A) values (4)", pending.clone())?;
+
+        let read = |db: &TinyDB| {
+            db.with_transaction(|tx, planner| {
+                let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+                let scan = unlock!(plan).open()?;
+                let mut scan = unlock!(scan);
+                let mut rows = vec![];
+                while scan.next()? {
+                    rows.push(scan.get_int("A")?);
+                }
+                scan.close();
+                Ok(rows)
+            })
+        };
+        let past = open_as_of(&db, lsn, test_directory.join("past"), 8)?;
+        assert_eq!(read(&past)?, vec![1]);
+        let latest = unlock!(db.log_manager).latest_lsn();
+        let now = open_as_of(&db, latest, test_directory.join("now"), 8)?;
+        assert_eq!(read(&now)?, vec![2, 3]);
+
+        // 表を作る前の時点には表がない
+        let empty = open_as_of(&db, 0, test_directory.join("empty"), 8)?;
+        assert!(read(&empty).is_err());
+        assert!(open_as_of(&db, i32::MAX, test_directory.join("future"), 8).is_err());
+
+        unlock!(pending).rollback()?;
+        Ok(())
+    }
+}
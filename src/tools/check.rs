@@ -0,0 +1,560 @@
+use crate::{
+    file::block::BlockId,
+    metadata::{
+        catalog::{fldcat, idxcat, tblcat, FIELD_CATALOG, INDEX_CATALOG, TABLE_CATALOG},
+        metadata_manager::MetadataManager,
+        table_manager::TableManager,
+    },
+    query::{constant::Constant, scan::Scan as _},
+    record::{
+        layout::Layout,
+        record_page::{RecordPage, HEADER_SIZE, SLOT_ENTRY_SIZE},
+        rid::RID,
+        schema::{FieldTypes, Schema},
+        table_scan::TableScan,
+    },
+    tx::transaction::Transaction,
+    unlock, I32_SIZE,
+};
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+/// Issue は整合性の検査で見つかった問題
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// カタログにあるテーブルのファイルがない
+    MissingTableFile { table_name: String },
+    /// fldcat に tblcat にないテーブルのフィールドがある
+    OrphanField {
+        table_name: String,
+        field_name: String,
+    },
+    /// フィールドの型が不明か、オフセットがスロットの範囲を超えている
+    InvalidField {
+        table_name: String,
+        field_name: String,
+        reason: String,
+    },
+    /// ヘッダのスロット数がブロックに収まらない
+    InvalidBlock { table_name: String, block_num: i32 },
+    /// スロットのレコードタイプが空きでも使用中でもない
+    InvalidRecordType {
+        table_name: String,
+        rid: RID,
+        value: i32,
+    },
+    /// 使用中のスロットのセルがブロックの範囲を超えている
+    InvalidCell { table_name: String, rid: RID },
+    /// idxcat にないテーブルやフィールドの索引がある
+    OrphanIndex {
+        index_name: String,
+        table_name: String,
+    },
+    /// 索引のエントリが使われていないスロットを指している
+    OrphanIndexEntry {
+        index_name: String,
+        key: Constant,
+        rid: RID,
+    },
+}
+
+impl Issue {
+    /// is_repairable は repair で直せる問題かどうかを返す
+    /// 孤立したカタログのレコードと索引のエントリは削除し、ないファイルは空のファイルを作る
+    /// 壊れたブロックやフィールドはデータが失われるので直さない
+    pub fn is_repairable(&self) -> bool {
+        matches!(
+            self,
+            Issue::MissingTableFile { .. }
+                | Issue::OrphanField { .. }
+                | Issue::OrphanIndex { .. }
+                | Issue::OrphanIndexEntry { .. }
+        )
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::MissingTableFile { table_name } => {
+                write!(f, "table {}: file not found", table_name)
+            }
+            Issue::OrphanField {
+                table_name,
+                field_name,
+            } => write!(
+                f,
+                "field {}.{}: table not in catalog",
+                table_name, field_name
+            ),
+            Issue::InvalidField {
+                table_name,
+                field_name,
+                reason,
+            } => write!(f, "field {}.{}: {}", table_name, field_name, reason),
+            Issue::InvalidBlock {
+                table_name,
+                block_num,
+            } => write!(
+                f,
+                "table {} block {}: invalid slot count",
+                table_name, block_num
+            ),
+            Issue::InvalidRecordType {
+                table_name,
+                rid,
+                value,
+            } => write!(
+                f,
+                "table {} {}: invalid record type {}",
+                table_name, rid, value
+            ),
+            Issue::InvalidCell { table_name, rid } => {
+                write!(f, "table {} {}: cell out of block", table_name, rid)
+            }
+            Issue::OrphanIndex {
+                index_name,
+                table_name,
+            } => write!(
+                f,
+                "index {} on {}: table or field not found",
+                index_name, table_name
+            ),
+            Issue::OrphanIndexEntry {
+                index_name,
+                key,
+                rid,
+            } => write!(
+                f,
+                "index {} key {}: {} is not a used slot",
+                index_name, key, rid
+            ),
+        }
+    }
+}
+
+/// Finding は見つかった問題と、それを直したかどうか
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub issue: Issue,
+    pub repaired: bool,
+}
+
+/// Report は整合性の検査の結果
+#[derive(Debug, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// is_consistent は直していない問題がなければ true を返す
+    pub fn is_consistent(&self) -> bool {
+        self.findings.iter().all(|finding| finding.repaired)
+    }
+
+    pub fn issues(&self) -> Vec<&Issue> {
+        self.findings.iter().map(|finding| &finding.issue).collect()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for finding in &self.findings {
+            let status = if finding.repaired {
+                "repaired"
+            } else {
+                "error"
+            };
+            writeln!(f, "{}: {}", status, finding.issue)?;
+        }
+        write!(f, "{} issues found", self.findings.len())
+    }
+}
+
+/// check はカタログとファイルの整合性を検査する
+///
+/// - カタログにあるテーブルのファイルがディスクにあるか
+/// - フィールドの型が正しく、オフセットがスロットの範囲に収まっているか
+/// - 各ブロックのスロットのレコードタイプとセルの位置が正しいか
+/// - 索引のエントリが使用中のスロットを指しているか
+///
+/// repair が true の場合は、直せる問題（Issue::is_repairable）を直す
+/// 索引の情報を読み込むときに統計情報のためにすべてのテーブルを開くので、
+/// フィールドの型が壊れているテーブルや、ファイルがないテーブルが残っている場合は索引を検査しない
+pub fn check(tx: Arc<Mutex<Transaction>>, repair: bool) -> Result<Report> {
+    let mut checker = Checker {
+        tx: tx.clone(),
+        table_manager: TableManager::new(false, tx)?,
+        repair,
+        report: Report::default(),
+    };
+    let tables = checker.check_catalog()?;
+    let mut usable = true;
+    for (table_name, valid) in &tables {
+        usable &= *valid && checker.check_table(table_name)?;
+    }
+    if usable {
+        checker.check_indexes(&tables)?;
+    }
+    Ok(checker.report)
+}
+
+struct Checker {
+    tx: Arc<Mutex<Transaction>>,
+    table_manager: TableManager,
+    repair: bool,
+    report: Report,
+}
+
+impl Checker {
+    /// report は問題を記録して、直す場合は fix を実行する
+    fn report(&mut self, issue: Issue, fix: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        let repaired = self.repair && issue.is_repairable();
+        if repaired {
+            fix(self)?;
+        }
+        self.report.findings.push(Finding { issue, repaired });
+        Ok(())
+    }
+
+    /// check_catalog は tblcat と fldcat を検査して、テーブル名とフィールドが正しいかどうかを返す
+    fn check_catalog(&mut self) -> Result<BTreeMap<String, bool>> {
+        let mut slot_sizes = HashMap::new();
+        let mut tcat = self.catalog_scan(TABLE_CATALOG)?;
+        while tcat.next()? {
+            slot_sizes.insert(
+                tcat.get_string(tblcat::TABLE_NAME)?,
+                tcat.get_int(tblcat::SLOT_SIZE)?,
+            );
+        }
+        tcat.close();
+
+        let mut tables: BTreeMap<String, bool> =
+            slot_sizes.keys().map(|name| (name.clone(), true)).collect();
+        let mut orphans = vec![];
+        let mut fcat = self.catalog_scan(FIELD_CATALOG)?;
+        while fcat.next()? {
+            let table_name = fcat.get_string(fldcat::TABLE_NAME)?;
+            let field_name = fcat.get_string(fldcat::FIELD_NAME)?;
+            let Some(&slot_size) = slot_sizes.get(&table_name) else {
+                orphans.push((fcat.get_rid()?, table_name, field_name));
+                continue;
+            };
+            let reason = Self::field_error(
+                fcat.get_int(fldcat::TYPE)?,
+                fcat.get_int(fldcat::LENGTH)?,
+                fcat.get_int(fldcat::OFFSET)?,
+                slot_size,
+            )?;
+            if let Some(reason) = reason {
+                tables.insert(table_name.clone(), false);
+                self.report(
+                    Issue::InvalidField {
+                        table_name,
+                        field_name,
+                        reason,
+                    },
+                    |_| Ok(()),
+                )?;
+            }
+        }
+        fcat.close();
+
+        for (rid, table_name, field_name) in orphans {
+            self.report(
+                Issue::OrphanField {
+                    table_name,
+                    field_name,
+                },
+                |checker| checker.delete_catalog_record(FIELD_CATALOG, rid),
+            )?;
+        }
+        Ok(tables)
+    }
+
+    /// field_error はフィールドの定義が正しくない場合にその理由を返す
+    fn field_error(code: i32, length: i32, offset: i32, slot_size: i32) -> Result<Option<String>> {
+        let Some(r#type) = FieldTypes::from_code(code) else {
+            return Ok(Some(format!("unknown type {}", code)));
+        };
+        let mut schema = Schema::default();
+        schema.add_field("field", r#type, length);
+        let end = offset + Layout::length_in_bytes(&schema, "field")?;
+        if offset < I32_SIZE as i32 || end > slot_size {
+            return Ok(Some(format!(
+                "offset {} out of slot size {}",
+                offset, slot_size
+            )));
+        }
+        Ok(None)
+    }
+
+    /// check_table はテーブルのファイルがあるかを確かめて、各ブロックのスロットを検査する
+    /// ファイルがなく、直してもいない場合は false を返す
+    fn check_table(&mut self, table_name: &str) -> Result<bool> {
+        let layout = Arc::new(self.table_manager.get_layout(table_name, self.tx.clone())?);
+        let filename = format!("{}.tbl", table_name);
+        if !unlock!(self.tx).file_exists(&filename) {
+            let issue = Issue::MissingTableFile {
+                table_name: table_name.to_string(),
+            };
+            self.report(issue, |checker| {
+                TableScan::new(checker.tx.clone(), table_name, layout.clone())?.close();
+                Ok(())
+            })?;
+            return Ok(self.repair);
+        }
+
+        let block_size = unlock!(self.tx).block_size();
+        let size = unlock!(self.tx).size(filename.clone())? as i32;
+        for block_num in 0..size {
+            let block = BlockId::new(filename.clone(), block_num);
+            let rp = RecordPage::new(self.tx.clone(), block.clone(), layout.clone())?;
+            let issues = Self::check_block(&rp, table_name, block_size);
+            unlock!(self.tx).unpin(&block);
+            for issue in issues? {
+                self.report(issue, |_| Ok(()))?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn check_block(rp: &RecordPage, table_name: &str, block_size: i32) -> Result<Vec<Issue>> {
+        let block_num = rp.block.num;
+        let slot_count = rp.slot_count()?;
+        let directory_end = HEADER_SIZE + SLOT_ENTRY_SIZE * slot_count;
+        if slot_count < 0 || directory_end > block_size {
+            return Ok(vec![Issue::InvalidBlock {
+                table_name: table_name.to_string(),
+                block_num,
+            }]);
+        }
+
+        let mut issues = vec![];
+        for slot in 0..slot_count {
+            let rid = RID::new(block_num, slot);
+            let value = rp.record_type_code(slot)?;
+            if value != 0 && value != 1 {
+                issues.push(Issue::InvalidRecordType {
+                    table_name: table_name.to_string(),
+                    rid,
+                    value,
+                });
+                continue;
+            }
+            let (cell, length) = (rp.cell_offset(slot)?, rp.cell_length(slot)?);
+            if value == 1 && (cell < directory_end || length < 0 || cell + length > block_size) {
+                issues.push(Issue::InvalidCell {
+                    table_name: table_name.to_string(),
+                    rid,
+                });
+            }
+        }
+        Ok(issues)
+    }
+
+    /// check_indexes は idxcat の索引がテーブルのフィールドを指しているかと、
+    /// 各索引のエントリが使用中のスロットを指しているかを検査する
+    fn check_indexes(&mut self, tables: &BTreeMap<String, bool>) -> Result<()> {
+        let mut indexes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut orphans = vec![];
+        let mut icat = self.catalog_scan(INDEX_CATALOG)?;
+        while icat.next()? {
+            let index_name = icat.get_string(idxcat::INDEX_NAME)?;
+            let table_name = icat.get_string(idxcat::TABLE_NAME)?;
+            let field_name = icat.get_string(idxcat::FIELD_NAME)?;
+            let has_field = tables.contains_key(&table_name)
+                && self
+                    .table_manager
+                    .get_layout(&table_name, self.tx.clone())?
+                    .schema
+                    .has_field(&field_name);
+            if has_field {
+                indexes.entry(table_name).or_default().push(index_name);
+            } else {
+                orphans.push((icat.get_rid()?, index_name, table_name));
+            }
+        }
+        icat.close();
+
+        for (rid, index_name, table_name) in orphans {
+            self.report(
+                Issue::OrphanIndex {
+                    index_name,
+                    table_name,
+                },
+                |checker| checker.delete_catalog_record(INDEX_CATALOG, rid),
+            )?;
+        }
+
+        let md = MetadataManager::new(false, self.tx.clone())?;
+        for (table_name, index_names) in indexes {
+            let layout = Arc::new(
+                self.table_manager
+                    .get_layout(&table_name, self.tx.clone())?,
+            );
+            let used = self.used_slots(&table_name, layout)?;
+            let mut infos = md.get_index_info(&table_name, self.tx.clone())?;
+            for index_name in index_names {
+                let Some(info) = infos.get_mut(&index_name) else {
+                    continue;
+                };
+                let mut index = info.open()?;
+                let entries = index.entries()?;
+                for (key, rid) in entries {
+                    if used.contains(&rid) {
+                        continue;
+                    }
+                    let issue = Issue::OrphanIndexEntry {
+                        index_name: index_name.clone(),
+                        key: key.clone(),
+                        rid,
+                    };
+                    self.report(issue, |_| index.delete(key, rid))?;
+                }
+                index.close();
+            }
+        }
+        Ok(())
+    }
+
+    /// used_slots はテーブルの使用中のスロットをすべて返す
+    fn used_slots(&self, table_name: &str, layout: Arc<Layout>) -> Result<HashSet<RID>> {
+        let filename = format!("{}.tbl", table_name);
+        let block_size = unlock!(self.tx).block_size();
+        let size = unlock!(self.tx).size(filename.clone())? as i32;
+        let mut used = HashSet::new();
+        for block_num in 0..size {
+            let block = BlockId::new(filename.clone(), block_num);
+            let rp = RecordPage::new(self.tx.clone(), block.clone(), layout.clone())?;
+            let result = Self::used_slots_in_block(&rp, block_size, &mut used);
+            unlock!(self.tx).unpin(&block);
+            result?;
+        }
+        Ok(used)
+    }
+
+    /// used_slots_in_block はブロックの使用中のスロットを used に追加する
+    /// スロット数が壊れているブロックは読まない
+    fn used_slots_in_block(
+        rp: &RecordPage,
+        block_size: i32,
+        used: &mut HashSet<RID>,
+    ) -> Result<()> {
+        let slot_count = rp.slot_count()?;
+        if slot_count >= 0 && HEADER_SIZE + SLOT_ENTRY_SIZE * slot_count <= block_size {
+            for slot in 0..slot_count {
+                if rp.record_type_code(slot)? == 1 {
+                    used.insert(RID::new(rp.block.num, slot));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn catalog_scan(&mut self, table_name: &str) -> Result<TableScan> {
+        let layout = self.table_manager.get_layout(table_name, self.tx.clone())?;
+        TableScan::new(self.tx.clone(), table_name, Arc::new(layout))
+    }
+
+    fn delete_catalog_record(&mut self, table_name: &str, rid: RID) -> Result<()> {
+        let mut ts = self.catalog_scan(table_name)?;
+        ts.move_to_rid(rid);
+        let result = ts.delete();
+        ts.close();
+        // 読み込んだメタデータが消したレコードを含んでいるかもしれないので、読み直すようにする
+        let snapshot = unlock!(self.tx).metadata_snapshot();
+        unlock!(snapshot).clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::db::TinyDB;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_report_and_repair_inconsistencies() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_report_and_repair_inconsistencies");
+        let mut db = TinyDB::new(test_directory, 400, 8)?;
+        db.init_planner()?;
+        let tx = db.transaction()?;
+        let planner = db.planner.clone().unwrap();
+        let mut planner = unlock!(planner);
+        planner.execute_update("create table T(A int, B varchar(10))", tx.clone())?;
+        planner.execute_update("create table U(C int)", tx.clone())?;
+        for i in 0..5 {
+            planner.execute_update(
+                &format!("insert into T(A, B) values ({}, 'b{}')", i, i),
+                tx.clone(),
+            )?;
+        }
+        planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+        assert!(check(tx.clone(), false)?.findings.is_empty());
+
+        // 索引を通さずにレコードを削除すると、索引のエントリが削除したスロットを指したままになる
+        let md = MetadataManager::new(false, tx.clone())?;
+        let layout = Arc::new(md.get_layout("T", tx.clone())?);
+        let mut ts = TableScan::new(tx.clone(), "T", layout.clone())?;
+        ts.move_to_rid(RID::new(0, 3));
+        ts.delete()?;
+        ts.close();
+        // 存在しないテーブルのフィールドをカタログに追加する
+        let fldcat_layout = Arc::new(md.get_layout("fldcat", tx.clone())?);
+        let mut fcat = TableScan::new(tx.clone(), "fldcat", fldcat_layout)?;
+        fcat.insert()?;
+        fcat.set_string("tblname", "ghost")?;
+        fcat.set_string("fldname", "X")?;
+        fcat.close();
+        // U のファイルを消して、削除した T のスロットのレコードタイプを壊す
+        unlock!(db.file_manager).remove_file("U.tbl")?;
+        let block = BlockId::new("T.tbl".into(), 0);
+        let rp = RecordPage::new(tx.clone(), block.clone(), layout).unwrap();
+        unlock!(tx).set_int(&block, rp.offset(3), 7, true)?;
+        unlock!(tx).unpin(&block);
+
+        let report = check(tx.clone(), false)?;
+        let expected = [
+            Issue::OrphanField {
+                table_name: "ghost".into(),
+                field_name: "X".into(),
+            },
+            Issue::InvalidRecordType {
+                table_name: "T".into(),
+                rid: RID::new(0, 3),
+                value: 7,
+            },
+            Issue::MissingTableFile {
+                table_name: "U".into(),
+            },
+            Issue::OrphanIndexEntry {
+                index_name: "t_a".into(),
+                key: Constant::Int(3),
+                rid: RID::new(0, 3),
+            },
+        ];
+        // ファイルがないテーブルが残っているので、索引は検査しない
+        assert_eq!(report.issues(), expected[..3].iter().collect::<Vec<_>>());
+        assert!(!report.is_consistent());
+
+        let report = check(tx.clone(), true)?;
+        assert_eq!(report.issues(), expected.iter().collect::<Vec<_>>());
+        let repaired: Vec<bool> = report.findings.iter().map(|f| f.repaired).collect();
+        assert_eq!(repaired, vec![true, false, true, true]);
+
+        // 壊れたレコードタイプはデータが失われるかもしれないので直さずに残す
+        let report = check(tx.clone(), false)?;
+        assert_eq!(report.issues(), vec![&expected[1]]);
+
+        unlock!(tx).commit()?;
+        Ok(())
+    }
+}
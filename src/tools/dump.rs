@@ -0,0 +1,184 @@
+use crate::{
+    index::IndexType,
+    metadata::{
+        catalog::{
+            clustcat, idxcat, tblcat, viewcat, CATALOG_TABLES, CLUSTER_CATALOG, INDEX_CATALOG,
+            TABLE_CATALOG, VIEW_CATALOG,
+        },
+        table_manager::TableManager,
+    },
+    query::scan::Scan as _,
+    record::{
+        collation::Collation,
+        layout::Layout,
+        schema::{FieldTypes, Schema},
+        table_scan::TableScan,
+        truncation::TruncationPolicy,
+    },
+    tx::transaction::Transaction,
+};
+use anyhow::{bail, Result};
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+/// dump はすべてのテーブル、ビュー、索引を作り直す SQL の文を writer に書き込む
+///
+/// 文は `;` と改行で区切り、テーブルの作成、レコードの挿入、ビューの作成、索引の作成の順に並べる
+/// キーの順に格納するテーブルのレコードは格納されている順に書き込むので、並んでいれば復元しても並んだままになる
+/// 書き込んだ文は restore でもう一度実行できるので、ファイルの形式が違うデータベースへの移行や、
+/// テストのデータの用意に使える
+/// 権限はユーザーごとの設定なので書き込まない
+pub fn dump(tx: Arc<Mutex<Transaction>>, writer: &mut impl Write) -> Result<()> {
+    let mut table_manager = TableManager::new(false, tx.clone())?;
+
+    let mut table_names = vec![];
+    let mut tcat = catalog_scan(&mut table_manager, TABLE_CATALOG, &tx)?;
+    while tcat.next()? {
+        let table_name = tcat.get_string(tblcat::TABLE_NAME)?;
+        if !CATALOG_TABLES.contains(&table_name.as_str()) {
+            table_names.push(table_name);
+        }
+    }
+    tcat.close();
+
+    // キーの順に格納するテーブルの一覧がない古いカタログでは、どのテーブルもキーを持たない
+    let mut cluster_keys = HashMap::new();
+    let mut ccat = catalog_scan(&mut table_manager, CLUSTER_CATALOG, &tx)?;
+    if ccat.has_field(clustcat::FIELD_NAME) {
+        while ccat.next()? {
+            cluster_keys.insert(
+                ccat.get_string(clustcat::TABLE_NAME)?,
+                ccat.get_string(clustcat::FIELD_NAME)?,
+            );
+        }
+    }
+    ccat.close();
+
+    for table_name in &table_names {
+        let layout = Arc::new(table_manager.get_layout(table_name, tx.clone())?);
+        let cluster = match cluster_keys.get(table_name) {
+            Some(field_name) => format!(" cluster by {}", field_name),
+            None => String::new(),
+        };
+        writeln!(
+            writer,
+            "create table {} ({}){};",
+            table_name,
+            field_defs(&layout.schema)?,
+            cluster
+        )?;
+        dump_records(table_name, layout, &tx, writer)?;
+    }
+
+    let mut vcat = catalog_scan(&mut table_manager, VIEW_CATALOG, &tx)?;
+    while vcat.next()? {
+        let view_name = vcat.get_string(viewcat::VIEW_NAME)?;
+        let view_def = vcat.get_string(viewcat::VIEW_DEF)?;
+        writeln!(writer, "create view {} as {};", view_name, view_def)?;
+    }
+    vcat.close();
+
+    let mut icat = catalog_scan(&mut table_manager, INDEX_CATALOG, &tx)?;
+    let has_options = icat.has_field(idxcat::INDEX_TYPE);
+    while icat.next()? {
+        let index_name = icat.get_string(idxcat::INDEX_NAME)?;
+        let table_name = icat.get_string(idxcat::TABLE_NAME)?;
+        let field_name = icat.get_string(idxcat::FIELD_NAME)?;
+        // 種類とオプションの列がない古いカタログの索引はハッシュ索引として書き込む
+        let (index_type, unique, prefix_length) = if has_options {
+            (
+                icat.get_string(idxcat::INDEX_TYPE)?.parse()?,
+                icat.get_int(idxcat::IS_UNIQUE)? != 0,
+                icat.get_int(idxcat::PREFIX_LENGTH)?,
+            )
+        } else {
+            (IndexType::Hash, false, 0)
+        };
+        let prefix = if prefix_length > 0 {
+            format!("({})", prefix_length)
+        } else {
+            String::new()
+        };
+        writeln!(
+            writer,
+            "create {}index {} on {} ({}{}) using {};",
+            if unique { "unique " } else { "" },
+            index_name,
+            table_name,
+            field_name,
+            prefix,
+            index_type
+        )?;
+    }
+    icat.close();
+
+    Ok(())
+}
+
+/// catalog_scan はカタログのテーブルを読み込む TableScan を返す
+fn catalog_scan(
+    table_manager: &mut TableManager,
+    table_name: &str,
+    tx: &Arc<Mutex<Transaction>>,
+) -> Result<TableScan> {
+    let layout = Arc::new(table_manager.get_layout(table_name, tx.clone())?);
+    TableScan::new(tx.clone(), table_name, layout)
+}
+
+/// field_defs は create table に書くフィールドの定義を返す
+fn field_defs(schema: &Schema) -> Result<String> {
+    let mut defs = vec![];
+    for field_name in &schema.fields {
+        let def = match schema.r#type(field_name) {
+            Some(FieldTypes::Integer) => "int".to_string(),
+            Some(FieldTypes::BigInt) => "bigint".to_string(),
+            Some(FieldTypes::Double) => "double".to_string(),
+            Some(FieldTypes::Boolean) => "boolean".to_string(),
+            Some(FieldTypes::Date) => "date".to_string(),
+            Some(FieldTypes::Varchar) => {
+                format!("varchar({})", schema.length(field_name).unwrap_or(0))
+            }
+            Some(FieldTypes::Text) => "text".to_string(),
+            Some(FieldTypes::Blob) => "blob".to_string(),
+            None => bail!("field type not found: {}", field_name),
+        };
+        let mut def = format!("{} {}", field_name, def);
+        if schema.collation(field_name) != Collation::Binary {
+            def = format!("{} collate {}", def, schema.collation(field_name));
+        }
+        if schema.truncation(field_name) != TruncationPolicy::Error {
+            def = format!("{} on overflow {}", def, schema.truncation(field_name));
+        }
+        defs.push(def);
+    }
+    Ok(defs.join(", "))
+}
+
+/// dump_records はテーブルのすべてのレコードを insert の文として書き込む
+fn dump_records(
+    table_name: &str,
+    layout: Arc<Layout>,
+    tx: &Arc<Mutex<Transaction>>,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let fields = layout.schema.fields.clone();
+    let mut ts = TableScan::new(tx.clone(), table_name, layout)?;
+    while ts.next()? {
+        let mut values = vec![];
+        for field_name in &fields {
+            values.push(ts.get_value(field_name)?.to_literal());
+        }
+        writeln!(
+            writer,
+            "insert into {} ({}) values ({});",
+            table_name,
+            fields.join(", "),
+            values.join(", ")
+        )?;
+    }
+    ts.close();
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use super::restore::split_statements;
+use crate::{
+    metadata::table_manager::TableManager,
+    parse::parser::Parser,
+    plan::planner::Planner,
+    query::{
+        constant::Constant,
+        scan::Scan as _,
+        statement::{CreateStatement, Statement},
+    },
+    record::{schema::FieldTypes, table_scan::TableScan},
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{bail, Context as _, Result};
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// MANIFEST はテーブルの定義を書いたファイルの名前
+pub const MANIFEST: &str = "schema.sql";
+
+/// import_csv はディレクトリにある CSV ファイルからテーブルを作ってレコードを読み込み、
+/// テーブルの名前と読み込んだレコード数を返す
+///
+/// ディレクトリには MANIFEST（schema.sql）に create table と create index の文を `;` で区切って書き、
+/// テーブルごとに `<table>.csv` を置く。CSV の1行目はフィールド名のヘッダで、CSV ファイルがないテーブルは空のまま作る
+/// レコードは文を解析せずに TableScan で直接挿入し、索引はすべてのレコードを読み込んでから作る
+pub fn import_csv(
+    planner: &Arc<Mutex<Planner>>,
+    tx: Arc<Mutex<Transaction>>,
+    dir: impl AsRef<Path>,
+) -> Result<Vec<(String, usize)>> {
+    let dir = dir.as_ref();
+    let manifest = fs::read_to_string(dir.join(MANIFEST))
+        .with_context(|| format!("failed to read {}", dir.join(MANIFEST).display()))?;
+
+    let mut table_names = vec![];
+    let mut indexes = vec![];
+    for sql in split_statements(&manifest) {
+        match Parser::new(sql).update_cmd()? {
+            Statement::Create(CreateStatement::CreateTable(data)) => {
+                table_names.push((data.table_name, data.cluster_key.is_some()));
+                unlock!(planner).execute_update(sql, tx.clone())?;
+            }
+            Statement::Create(CreateStatement::CreateIndex(_)) => {
+                indexes.push(sql);
+            }
+            _ => bail!("only create table and create index are allowed: {}", sql),
+        }
+    }
+
+    let mut result = vec![];
+    for (table_name, clustered) in table_names {
+        let path = dir.join(format!("{}.csv", table_name));
+        let count = if path.exists() {
+            let csv = fs::read_to_string(&path)?;
+            load_csv(&table_name, &csv, tx.clone())
+                .with_context(|| format!("failed to import {}", path.display()))?
+        } else {
+            0
+        };
+        // CSV のレコードは TableScan で直接挿入するので、キーの順に格納するテーブルは読み込んでから並べ直す
+        if clustered {
+            unlock!(planner).execute_update(&format!("cluster {}", table_name), tx.clone())?;
+        }
+        result.push((table_name, count));
+    }
+
+    for sql in indexes {
+        unlock!(planner).execute_update(sql, tx.clone())?;
+    }
+    Ok(result)
+}
+
+/// load_csv は CSV のレコードをテーブルに挿入して、挿入したレコード数を返す
+/// 挿入は一括処理として行い、バッファプールのほかのページを追い出さない
+fn load_csv(table_name: &str, csv: &str, tx: Arc<Mutex<Transaction>>) -> Result<usize> {
+    tx.lock().unwrap().set_bulk(true);
+    let result = insert_rows(table_name, csv, tx.clone());
+    tx.lock().unwrap().set_bulk(false);
+    result
+}
+
+fn insert_rows(table_name: &str, csv: &str, tx: Arc<Mutex<Transaction>>) -> Result<usize> {
+    let mut rows = parse_csv(csv)?.into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(0);
+    };
+    let layout =
+        Arc::new(TableManager::new(false, tx.clone())?.get_layout(table_name, tx.clone())?);
+    let mut types = vec![];
+    for field_name in &header {
+        let Some(field_type) = layout.schema.r#type(field_name) else {
+            bail!("field not found: {}", field_name);
+        };
+        if !matches!(
+            field_type,
+            FieldTypes::Integer
+                | FieldTypes::Boolean
+                | FieldTypes::Date
+                | FieldTypes::Varchar
+                | FieldTypes::Text
+                | FieldTypes::Blob
+        ) {
+            bail!("unsupported field type for import: {:?}", field_type);
+        }
+        types.push(field_type);
+    }
+
+    let mut ts = TableScan::new(tx, table_name, layout)?;
+    let mut count = 0;
+    for (i, row) in rows.enumerate() {
+        // ヘッダを1行目として数える
+        let line = i + 2;
+        if row.len() != header.len() {
+            bail!(
+                "record {}: expected {} values, found {}",
+                line,
+                header.len(),
+                row.len()
+            );
+        }
+        ts.insert()?;
+        for ((field_name, field_type), value) in header.iter().zip(&types).zip(row) {
+            let value = match field_type {
+                FieldTypes::Integer => Constant::Int(
+                    value
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("record {}: invalid int: {}", line, value))?,
+                ),
+                FieldTypes::Boolean => match value.trim().to_lowercase().as_str() {
+                    "true" => Constant::Bool(true),
+                    "false" => Constant::Bool(false),
+                    _ => bail!("record {}: invalid boolean: {}", line, value),
+                },
+                FieldTypes::Date => Constant::Date(
+                    value
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("record {}: invalid date: {}", line, value))?,
+                ),
+                _ => Constant::String(value),
+            };
+            ts.set_value(field_name, value)?;
+        }
+        count += 1;
+    }
+    ts.close();
+    Ok(count)
+}
+
+/// parse_csv は CSV をレコードごとの値の列に分ける
+///
+/// 値は `,` で、レコードは改行で区切る。`"` で囲んだ値には `,` や改行を含めることができ、
+/// 囲んだ値の中の `""` は1つの `"` として扱う。空の行は読み飛ばす
+pub fn parse_csv(input: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.next_if_eq(&'"').is_some() => value.push('"'),
+                '"' => quoted = false,
+                c => value.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if value.is_empty() => quoted = true,
+            ',' => row.push(std::mem::take(&mut value)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut value));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => value.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted value");
+    }
+    if !value.is_empty() || !row.is_empty() {
+        row.push(value);
+        rows.push(row);
+    }
+    rows.retain(|row| !(row.len() == 1 && row[0].is_empty()));
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata::metadata_manager::MetadataManager, server::db::TinyDB, unlock};
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_parse_csv() -> Result<()> {
+        let csv = "A,B\r\n1,\"x, \"\"y\"\"\"\n\n2,\"multi\nline\"\n3,";
+        assert_eq!(
+            parse_csv(csv)?,
+            vec![
+                vec!["A", "B"],
+                vec!["1", "x, \"y\""],
+                vec!["2", "multi\nline"],
+                vec!["3", ""],
+            ]
+        );
+        assert!(parse_csv("A\n\"unterminated").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_import_csv_directory() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_import_csv_directory");
+        let csv_dir = test_directory.join("csv");
+        fs::create_dir_all(&csv_dir)?;
+        fs::write(
+            csv_dir.join(MANIFEST),
+            "create table T (A int, B varchar(10));\n\
+             create table U (C int);\n\
+             create index t_a on T (A) using btree;\n",
+        )?;
+        fs::write(csv_dir.join("T.csv"), "B,A\nfoo,1\n\"b,ar\",2\n")?;
+
+        let mut db = TinyDB::new(test_directory.join("db"), 400, 8)?;
+        db.init_planner()?;
+        let imported = db.with_transaction(|tx, planner| import_csv(planner, tx, &csv_dir))?;
+        assert_eq!(imported, vec![("T".into(), 2), ("U".into(), 0)]);
+
+        let rows = db.with_transaction(|tx, planner| {
+            let plan = unlock!(planner).create_query_plan("select A, B from T", tx)?;
+            let scan = unlock!(plan).open()?;
+            let mut scan = unlock!(scan);
+            let mut rows = vec![];
+            while scan.next()? {
+                rows.push((scan.get_int("A")?, scan.get_string("B")?));
+            }
+            scan.close();
+            Ok(rows)
+        })?;
+        assert_eq!(rows, vec![(1, "foo".into()), (2, "b,ar".into())]);
+
+        // 索引はレコードを読み込んだ後に作られる
+        let entries = db.with_transaction(|tx, _| {
+            let md = MetadataManager::new(false, tx.clone())?;
+            let mut info = md.get_index_info("T", tx)?.remove("t_a").unwrap();
+            let mut index = info.open()?;
+            let entries = index.entries()?;
+            index.close();
+            Ok(entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>())
+        })?;
+        assert_eq!(entries, vec![Constant::Int(1), Constant::Int(2)]);
+
+        // 値の数が合わないレコードがある場合は失敗する
+        fs::write(csv_dir.join(MANIFEST), "create table V (A int);")?;
+        fs::write(csv_dir.join("V.csv"), "A\n1,2\n")?;
+        let result = db.with_transaction(|tx, planner| import_csv(planner, tx, &csv_dir));
+        assert!(format!("{:?}", result.unwrap_err()).contains("expected 1 values, found 2"));
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    index::{IndexOptions, IndexType},
+    metadata::metadata_manager::MetadataManager,
+    parse::parser::Parser,
+    plan::{better_query_plan::BetterQueryPlanner, query_planner::QueryPlanner as _},
+    query::query_data::QueryData,
+    record::schema::FieldTypes,
+    server::db::TinyDB,
+    tx::transaction::Transaction,
+    unlock,
+};
+use anyhow::{Context as _, Result};
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+/// Recommendation は作ると問い合わせのコストが下がる索引
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recommendation {
+    pub table_name: String,
+    pub field_name: String,
+    /// blocks_after は索引があるときに、すべての問い合わせでアクセスするブロック数の見積もりの合計
+    pub blocks_after: i64,
+    /// savings は索引を作ると減るブロック数の見積もり
+    pub savings: i64,
+}
+
+impl Recommendation {
+    pub fn index_name(&self) -> String {
+        index_name(&self.table_name, &self.field_name)
+    }
+
+    /// statement は索引を作る文を返す
+    pub fn statement(&self) -> String {
+        format!(
+            "create index {} on {} ({}) using btree",
+            self.index_name(),
+            self.table_name,
+            self.field_name
+        )
+    }
+}
+
+/// Advice は索引の提案の結果
+#[derive(Debug, Default)]
+pub struct Advice {
+    /// blocks は今の索引で、すべての問い合わせでアクセスするブロック数の見積もりの合計
+    pub blocks: i64,
+    /// recommendations は減るブロック数が多い順に並べた索引
+    pub recommendations: Vec<Recommendation>,
+}
+
+impl Display for Advice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "estimated blocks: {}", self.blocks)?;
+        for recommendation in &self.recommendations {
+            writeln!(
+                f,
+                "{}; -- blocks {} -> {}",
+                recommendation.statement(),
+                self.blocks,
+                recommendation.blocks_after
+            )?;
+        }
+        write!(f, "{} indexes recommended", self.recommendations.len())
+    }
+}
+
+/// advise は問い合わせの集まり（workload）を調べて、作るとアクセスするブロック数の見積もりが減る索引を返す
+///
+/// 候補は、述語でフィールドが定数かほかのフィールドと等しいことを表していて、まだ索引がない int と varchar のフィールド
+/// 候補ごとに仮想の索引を登録し、BetterQueryPlanner でプランを作り直してコストを見積もる
+/// 仮想の索引はカタログにもファイルにも書き込まないので、データベースは変わらない
+/// 問い合わせでない文は見積もれないので無視する
+pub fn advise(db: &TinyDB, workload: &[&str]) -> Result<Advice> {
+    let mut queries = vec![];
+    for sql in workload {
+        let mut parser = Parser::new(sql);
+        if parser.is_query() {
+            queries.push(
+                parser
+                    .query()
+                    .with_context(|| format!("invalid query: {}", sql))?,
+            );
+        }
+    }
+
+    let (blocks, candidates) = with_rollback(db, |md, tx| {
+        let blocks = estimate(&queries, md.clone(), tx.clone())?;
+        Ok((blocks, candidates(&queries, &md, tx)?))
+    })?;
+
+    let mut recommendations = vec![];
+    for (table_name, field_name) in candidates {
+        let blocks_after = with_rollback(db, |md, tx| {
+            let options = IndexOptions {
+                index_type: IndexType::BTree,
+                ..Default::default()
+            };
+            unlock!(md).create_virtual_index(
+                &index_name(&table_name, &field_name),
+                &table_name,
+                &field_name,
+                &options,
+                tx.clone(),
+            )?;
+            estimate(&queries, md, tx)
+        })?;
+        if blocks_after < blocks {
+            recommendations.push(Recommendation {
+                table_name,
+                field_name,
+                blocks_after,
+                savings: blocks - blocks_after,
+            });
+        }
+    }
+    recommendations.sort_by_key(|recommendation| Reverse(recommendation.savings));
+    Ok(Advice {
+        blocks,
+        recommendations,
+    })
+}
+
+/// with_rollback は新しいトランザクションとメタデータで f を実行し、結果に関係なくロールバックする
+/// 仮想の索引は新しいメタデータにだけ登録するので、ほかの見積もりやプランナーには見えない
+fn with_rollback<T>(
+    db: &TinyDB,
+    f: impl FnOnce(Arc<Mutex<MetadataManager>>, Arc<Mutex<Transaction>>) -> Result<T>,
+) -> Result<T> {
+    let tx = db.transaction()?;
+    let result = MetadataManager::new(false, tx.clone())
+        .and_then(|md| f(Arc::new(Mutex::new(md)), tx.clone()));
+    unlock!(tx).rollback()?;
+    result
+}
+
+fn index_name(table_name: &str, field_name: &str) -> String {
+    format!("{}_{}", table_name, field_name).to_lowercase()
+}
+
+/// estimate はすべての問い合わせのプランでアクセスするブロック数の見積もりの合計を返す
+fn estimate(
+    queries: &[QueryData],
+    md: Arc<Mutex<MetadataManager>>,
+    tx: Arc<Mutex<Transaction>>,
+) -> Result<i64> {
+    let mut planner = BetterQueryPlanner::new(md);
+    let mut blocks = 0;
+    for query in queries {
+        let plan = planner.create_plan(query.clone(), tx.clone())?;
+        blocks += unlock!(plan).blocks_accessed() as i64;
+    }
+    Ok(blocks)
+}
+
+/// candidates は索引の候補のテーブルとフィールドを、問い合わせに最初に現れた順に返す
+fn candidates(
+    queries: &[QueryData],
+    md: &Arc<Mutex<MetadataManager>>,
+    tx: Arc<Mutex<Transaction>>,
+) -> Result<Vec<(String, String)>> {
+    let mut candidates = vec![];
+    for query in queries {
+        for table_name in &query.tables {
+            if unlock!(md).get_view_def(table_name, tx.clone())?.is_some() {
+                continue;
+            }
+            let schema = unlock!(md).get_layout(table_name, tx.clone())?.schema;
+            let index_infos = unlock!(md).get_index_info(table_name, tx.clone())?;
+            for field_name in query.pred.field_names() {
+                let indexable = matches!(
+                    schema.r#type(&field_name),
+                    Some(FieldTypes::Integer | FieldTypes::Varchar)
+                );
+                let compared = query.pred.equates_with_constant(&field_name).is_some()
+                    || query.pred.equates_with_field(&field_name).is_some();
+                let indexed = index_infos
+                    .values()
+                    .any(|index_info| index_info.field_name() == field_name);
+                let candidate = (table_name.clone(), field_name);
+                if indexable && compared && !indexed && !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+    }
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_recommend_indexes_that_reduce_cost() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_recommend_indexes_that_reduce_cost");
+        let mut db = TinyDB::new(test_directory, 400, 8)?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("create table S(C int, D varchar(10))", tx.clone())?;
+            unlock!(planner).execute_update("create table L(A int, B varchar(10))", tx.clone())?;
+            for i in 0..3 {
+                unlock!(planner).execute_update(
+                    &format!("insert into S(C, D) values ({}, 's{}')", i, i),
+                    tx.clone(),
+                )?;
+            }
+            for i in 0..300 {
+                unlock!(planner).execute_update(
+                    &format!("insert into L(A, B) values ({}, 'l{}')", i % 50, i),
+                    tx.clone(),
+                )?;
+            }
+            Ok(())
+        })?;
+
+        let advice = advise(
+            &db,
+            &[
+                "select B from L where A = 7",
+                "select D, B from S, L where A = C",
+                "select D from S where C = 1",
+                "delete from L where A = 1",
+            ],
+        )?;
+        // 小さいテーブル S の索引はコストを下げないので提案しない
+        let statements: Vec<String> = advice
+            .recommendations
+            .iter()
+            .map(Recommendation::statement)
+            .collect();
+        assert_eq!(statements, vec!["create index l_a on L (A) using btree"]);
+        assert!(advice.recommendations[0].blocks_after < advice.blocks);
+        assert!(advice.to_string().ends_with("1 indexes recommended"));
+
+        // 見積もりのために登録した仮想の索引は残らない
+        let tx = db.transaction()?;
+        let md = MetadataManager::new(false, tx.clone())?;
+        assert!(md.get_index_info("L", tx.clone())?.is_empty());
+        unlock!(tx).commit()?;
+
+        assert!(advise(&db, &["select B from"]).is_err());
+        Ok(())
+    }
+}
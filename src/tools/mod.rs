@@ -0,0 +1,7 @@
+pub mod as_of;
+pub mod check;
+pub mod dump;
+pub mod import;
+pub mod index_advisor;
+pub mod recovery_report;
+pub mod restore;
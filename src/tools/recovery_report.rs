@@ -0,0 +1,88 @@
+use crate::{server::db::TinyDB, tx::recovery::recovery_manager::scan_log};
+use anyhow::Result;
+
+pub use crate::tx::recovery::recovery_manager::{RecoveryProgress, RecoveryReport, UndoEntry};
+
+/// recovery_report は db を開いたときのリカバリが取り消すログレコードを、ファイルを変更せずに調べる（ドライラン）
+///
+/// init_planner を呼ぶ前の db に使う。ログを読む進み具合は TinyDB::on_recovery_progress と同じように progress に届く
+/// リカバリは取り消し（undo）だけを行うので、報告にやり直す（redo）レコードは含まれない
+pub fn recovery_report(
+    db: &TinyDB,
+    mut progress: impl FnMut(RecoveryProgress),
+) -> Result<RecoveryReport> {
+    scan_log(&db.log_manager, None, &mut progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file::{block::BlockId, page::Page},
+        unlock,
+    };
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_report_recovery_without_modifying_files() -> Result<()> {
+        let test_directory = tempdir()?
+            .path()
+            .join("should_report_recovery_without_modifying_files");
+        let block = BlockId::new("testfile".into(), 0);
+        let (committed, pending) = {
+            let db = TinyDB::new(&test_directory, 400, 8)?;
+            let tx = db.transaction()?;
+            let mut tx = unlock!(tx);
+            tx.append("testfile".into())?;
+            tx.pin(&block)?;
+            tx.set_int(&block, 0, 1, true)?;
+            tx.unpin(&block);
+            tx.commit()?;
+
+            // コミットせずに終了したトランザクションの変更がディスクに書き込まれている
+            let tx = db.transaction()?;
+            let mut tx = unlock!(tx);
+            tx.pin(&block)?;
+            tx.set_int(&block, 0, 2, true)?;
+            tx.set_string(&block, 4, "abc".into(), true)?;
+            db.buffer_manager.flush_all(tx.tx_num());
+            (1, tx.tx_num())
+        };
+
+        let read = |db: &TinyDB| -> Result<i32> {
+            let mut page = Page::new(400);
+            unlock!(db.file_manager).read(&block, &mut page)?;
+            Ok(page.get_int(0))
+        };
+        let mut db = TinyDB::new(&test_directory, 400, 8)?;
+        let mut seen = vec![];
+        let report = recovery_report(&db, |p| seen.push(p))?;
+        assert_eq!(report.incomplete, vec![pending]);
+        assert_eq!(report.undo.len(), 2);
+        assert!(report.undo.iter().all(|entry| entry.tx_num == pending));
+        assert!(report.undo[0].record.starts_with("<SETSTRING"));
+        assert!(report.undo[1].record.starts_with("<SETINT"));
+        assert!(report.undo[0].lsn > report.undo[1].lsn);
+        assert_eq!(report.checkpoint, None);
+        assert_eq!(
+            seen,
+            vec![RecoveryProgress {
+                records: report.records,
+                lsn: 1,
+            }]
+        );
+        // ドライランでは何も取り消さない
+        assert_eq!(read(&db)?, 2);
+
+        let progress = Arc::new(Mutex::new(vec![]));
+        let recorded = progress.clone();
+        db.on_recovery_progress(move |p| unlock!(recorded).push(p));
+        db.init_planner()?;
+        assert_eq!(read(&db)?, committed);
+        let progress = unlock!(progress);
+        assert_eq!(progress.len(), 1);
+        assert!(progress[0].records >= report.records);
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{plan::planner::Planner, tx::transaction::Transaction, unlock};
+use anyhow::{Context as _, Result};
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+/// restore は dump で書き込んだ文を reader から読み込んで実行し、実行した文の数を返す
+///
+/// 文は `;` で区切る。引用符の中の `;` は区切りとして扱わない
+/// すべての文は tx の中で実行するので、失敗した場合は呼び出し側でロールバックすれば元に戻る
+pub fn restore(
+    planner: &Arc<Mutex<Planner>>,
+    tx: Arc<Mutex<Transaction>>,
+    reader: &mut impl Read,
+) -> Result<usize> {
+    let mut script = String::new();
+    reader.read_to_string(&mut script)?;
+
+    let mut count = 0;
+    for sql in split_statements(&script) {
+        unlock!(planner)
+            .execute_update(sql, tx.clone())
+            .with_context(|| format!("failed to restore: {}", sql))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// split_statements はスクリプトを `;` で文に分ける。空の文は返さない
+pub fn split_statements(script: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in script.char_indices() {
+        match c {
+            // 引用符の中の '' は引用符を2回切り替えるので、そのまま扱える
+            '\'' => quoted = !quoted,
+            ';' if !quoted => {
+                statements.push(&script[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&script[start..]);
+    statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|sql| !sql.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata::metadata_manager::MetadataManager, server::db::TinyDB, tools::dump::dump, unlock,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_restore_dumped_database() -> Result<()> {
+        let test_directory = tempdir()?.path().join("should_restore_dumped_database");
+        let mut db = TinyDB::new(test_directory.join("src"), 400, 8)?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            for sql in [
+                "create table T(A int, B varchar(10), C text) cluster by A",
+                "insert into T(A, B, C) values (1, 'it''s', 'a;b')",
+                "insert into T(A, B, C) values (2, 'x', '')",
+                "create view V as select A from T where B = 'x'",
+                "create unique index t_a on T (A) using btree",
+                "create index t_b on T (B(3)) using btree",
+            ] {
+                unlock!(planner).execute_update(sql, tx.clone())?;
+            }
+            Ok(())
+        })?;
+
+        let mut script = vec![];
+        db.with_transaction(|tx, _| dump(tx, &mut script))?;
+        let script = String::from_utf8(script)?;
+        assert_eq!(
+            script,
+            "create table T (A int, B varchar(10), C text) cluster by A;\n\
+             insert into T (A, B, C) values (1, 'it''s', 'a;b');\n\
+             insert into T (A, B, C) values (2, 'x', '');\n\
+             create view V as SELECT A FROM T WHERE B = 'x';\n\
+             create unique index t_a on T (A) using btree;\n\
+             create index t_b on T (B(3)) using btree;\n"
+        );
+
+        let mut restored = TinyDB::new(test_directory.join("dst"), 400, 8)?;
+        restored.init_planner()?;
+        let count = restored
+            .with_transaction(|tx, planner| restore(planner, tx, &mut script.as_bytes()))?;
+        assert_eq!(count, 6);
+
+        // もう一度書き込むと同じ文になる
+        let mut again = vec![];
+        restored.with_transaction(|tx, _| dump(tx, &mut again))?;
+        assert_eq!(String::from_utf8(again)?, script);
+
+        // 索引は既存のレコードから作られる
+        let entries = restored.with_transaction(|tx, _| {
+            let md = MetadataManager::new(false, tx.clone())?;
+            let mut info = md.get_index_info("T", tx)?.remove("t_a").unwrap();
+            let mut index = info.open()?;
+            let entries = index.entries()?;
+            index.close();
+            Ok(entries.len())
+        })?;
+        assert_eq!(entries, 2);
+
+        let rows = restored.with_transaction(|tx, planner| {
+            let plan = unlock!(planner).create_query_plan("select A from V", tx)?;
+            let scan = unlock!(plan).open()?;
+            let mut scan = unlock!(scan);
+            let mut rows = vec![];
+            while scan.next()? {
+                rows.push(scan.get_int("A")?);
+            }
+            scan.close();
+            Ok(rows)
+        })?;
+        assert_eq!(rows, vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_split_statements() {
+        let script = "create table T (A varchar(10));\ninsert into T (A) values ('a;b''c');\n\n";
+        assert_eq!(
+            split_statements(script),
+            vec![
+                "create table T (A varchar(10))",
+                "insert into T (A) values ('a;b''c')"
+            ]
+        );
+    }
+}
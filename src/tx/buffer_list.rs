@@ -5,7 +5,7 @@ use std::{
 };
 
 use crate::{
-    buffer::{buffer::Buffer, buffer_manager::BufferManager},
+    buffer::{buffer::Buffer, buffer_manager::BufferManager, buffer_ring::BufferRing},
     file::block::BlockId,
 };
 
@@ -13,27 +13,37 @@ use crate::{
 pub struct BufferList {
     buffers: HashMap<BlockId, Arc<Mutex<Buffer>>>,
     pins: Vec<BlockId>,
-    buffer_manager: Arc<Mutex<BufferManager>>,
+    buffer_manager: Arc<BufferManager>,
+    ring: Option<BufferRing>, // Some while the transaction runs a bulk operation
 }
 
 impl BufferList {
-    pub fn new(buffer_manager: Arc<Mutex<BufferManager>>) -> Self {
+    pub fn new(buffer_manager: Arc<BufferManager>) -> Self {
         Self {
             buffers: HashMap::new(),
             pins: Vec::new(),
             buffer_manager,
+            ring: None,
         }
     }
 
+    /// set_ring は新しいブロックをピンするときに使い回すバッファのリングを設定する
+    /// None の場合はバッファプール全体から選ぶ
+    pub fn set_ring(&mut self, ring: Option<BufferRing>) {
+        self.ring = ring;
+    }
+
+    /// pins はピンしたままのブロックを、ピンした回数だけ返す
+    pub fn pins(&self) -> &[BlockId] {
+        &self.pins
+    }
+
     pub fn get_buffer(&self, block: &BlockId) -> Option<&Arc<Mutex<Buffer>>> {
         self.buffers.get(block)
     }
 
     pub fn pin(&mut self, block: &BlockId) -> Result<()> {
-        let Ok(buffer) = self.buffer_manager.lock().unwrap().pin(block) else {
-            return Ok(());
-        };
-
+        let buffer = self.buffer_manager.pin_with(block, self.ring.as_mut())?;
         self.buffers.insert(block.clone(), buffer);
         self.pins.push(block.clone());
         Ok(())
@@ -41,7 +51,7 @@ impl BufferList {
 
     pub fn unpin(&mut self, block: &BlockId) -> Result<()> {
         if let Some(buffer) = self.buffers.get(block) {
-            self.buffer_manager.lock().unwrap().unpin(buffer.clone());
+            self.buffer_manager.unpin(buffer.clone());
         }
         self.pins.retain(|b| b.id != block.id);
         if !self.pins.contains(block) {
@@ -53,7 +63,7 @@ impl BufferList {
     pub fn unpin_all(&mut self) {
         for block in &self.pins {
             if let Some(buffer) = self.buffers.get(block) {
-                self.buffer_manager.lock().unwrap().unpin(buffer.clone());
+                self.buffer_manager.unpin(buffer.clone());
             }
         }
         self.buffers.clear();
@@ -0,0 +1,106 @@
+use super::transaction::Transaction;
+use anyhow::Result;
+use std::{
+    collections::BTreeSet,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+/// CommitEvent はコミットしたトランザクションの情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitEvent {
+    pub tx_num: i32,
+    /// tables はトランザクションが変更したテーブルの名前（昇順）
+    pub tables: Vec<String>,
+}
+
+impl CommitEvent {
+    /// from_files は変更したファイルの名前からテーブルの名前を取り出してイベントを作る
+    /// 一時テーブルのファイルは含めない
+    pub fn from_files(tx_num: i32, files: &BTreeSet<String>) -> Self {
+        let tables = files
+            .iter()
+            .filter(|file| !file.starts_with("temp"))
+            .filter_map(|file| file.strip_suffix(".tbl"))
+            .map(String::from)
+            .collect();
+        Self { tx_num, tables }
+    }
+}
+
+pub type CommitCallback = Arc<dyn Fn(&CommitEvent) + Send + Sync>;
+
+/// CommitListeners はコミット後に呼び出すコールバックのリスト
+/// clone したものは同じリストを共有するので、TinyDB に登録したコールバックはすべてのトランザクションで呼び出される
+#[derive(Clone, Default)]
+pub struct CommitListeners {
+    callbacks: Arc<Mutex<Vec<CommitCallback>>>,
+}
+
+impl CommitListeners {
+    pub fn add(&self, callback: impl Fn(&CommitEvent) + Send + Sync + 'static) {
+        self.callbacks.lock().unwrap().push(Arc::new(callback));
+    }
+
+    /// notify はコールバックを登録した順に呼び出す
+    /// コールバックの中でリスナーを追加できるように、ロックを解放してから呼び出す
+    pub fn notify(&self, event: &CommitEvent) {
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        for callback in callbacks {
+            callback(event);
+        }
+    }
+}
+
+impl Debug for CommitListeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommitListeners")
+            .field("len", &self.callbacks.lock().unwrap().len())
+            .finish()
+    }
+}
+
+pub type PreCommitHook = Box<dyn FnOnce(Arc<Mutex<Transaction>>) -> Result<()> + Send>;
+
+/// PreCommitHooks はトランザクションがコミットする前に1回だけ呼び出すフックのリスト
+/// フックはコミットのログを書く前に、まだ書き込んでいない変更を書き込むのに使う
+#[derive(Clone, Default)]
+pub struct PreCommitHooks {
+    hooks: Arc<Mutex<Vec<PreCommitHook>>>,
+}
+
+impl PreCommitHooks {
+    pub fn add(&self, hook: impl FnOnce(Arc<Mutex<Transaction>>) -> Result<()> + Send + 'static) {
+        self.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// take は登録した順にフックを取り出し、リストを空にする
+    pub fn take(&self) -> Vec<PreCommitHook> {
+        std::mem::take(&mut *self.hooks.lock().unwrap())
+    }
+}
+
+impl Debug for PreCommitHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PreCommitHooks")
+            .field("len", &self.hooks.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_collect_modified_tables() {
+        let files = BTreeSet::from([
+            "student.tbl".to_string(),
+            "idx.leaf".to_string(),
+            "temp1.tbl".to_string(),
+            "dept.tbl".to_string(),
+        ]);
+        let event = CommitEvent::from_files(3, &files);
+        assert_eq!(event.tables, vec!["dept", "student"]);
+    }
+}
@@ -1,42 +1,82 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use std::{
     collections::HashMap,
     sync::{Arc, Condvar, Mutex},
 };
 
-use crate::{file::block::BlockId, TIMEOUT};
+use crate::{file::block::BlockId, timeout::TimeoutKind};
 
 use super::lock_table::LockTable;
 
+/// ConcurrencyManager はトランザクションが持っているロックを記録し、ロックテーブルからロックを取得する
+///
+/// 1つのファイルのブロックロックがロックテーブルのしきい値に達すると、それらを1つのファイルロックに置き換える
+/// ファイルロックを持っているファイルのブロックは、ロックテーブルを使わずにロック済みとして扱う
+/// 他のトランザクションのロックと両立しないためにまとめられない場合は、ブロックロックのまま続ける
+///
+/// ロックテーブルがない場合（disabled）はロックを取らず、ロックの取得と解放は何もしない
+/// 1つのスレッドだけがデータベースを使う組み込みの用途で、ミューテックスや条件変数の負荷を省くために使う
 #[derive(Debug, Clone)]
 pub struct ConcurrencyManager {
-    lock_table: Arc<(Mutex<LockTable>, Condvar)>,
+    lock_table: Option<Arc<(Mutex<LockTable>, Condvar)>>, // None when locking is disabled
     locks: HashMap<BlockId, String>,
+    file_locks: HashMap<String, String>, // escalated locks for whole files
+    block_counts: HashMap<String, usize>, // number of block locks held on each file
 }
 
 impl ConcurrencyManager {
     pub fn new(lock_table: Arc<(Mutex<LockTable>, Condvar)>) -> Self {
         Self {
-            lock_table,
+            lock_table: Some(lock_table),
             locks: HashMap::new(),
+            file_locks: HashMap::new(),
+            block_counts: HashMap::new(),
         }
     }
 
+    /// disabled はロックを取らない ConcurrencyManager を返す
+    pub fn disabled() -> Self {
+        Self {
+            lock_table: None,
+            locks: HashMap::new(),
+            file_locks: HashMap::new(),
+            block_counts: HashMap::new(),
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.lock_table.is_none()
+    }
+
     pub fn s_lock(&mut self, block: &BlockId) -> Result<()> {
+        if self.has_file_lock(block) {
+            return Ok(());
+        }
         if !self.locks.contains_key(block) {
-            let (lock_table, cvar) = &*self.lock_table;
+            let Some(lock_table) = &self.lock_table else {
+                return Ok(());
+            };
+            let (lock_table, cvar) = &**lock_table;
             let mut locked_table = lock_table.lock().unwrap();
+            let clock = locked_table.clock();
+            let timeouts = locked_table.timeouts();
+            let start_time = clock.now();
 
-            let start_time = std::time::Instant::now();
-
-            while locked_table.has_x_lock(block) {
-                locked_table = cvar.wait_timeout(locked_table, TIMEOUT).unwrap().0;
-                if start_time.elapsed() > TIMEOUT {
-                    bail!("Lock timeout");
+            let mut attempt = 0;
+            while locked_table.has_x_lock(block)
+                || locked_table.is_blocked_by_file_lock(block, false)
+            {
+                let wait = clock.wait_time(timeouts.backoff(attempt));
+                locked_table = cvar.wait_timeout(locked_table, wait).unwrap().0;
+                attempt += 1;
+                if locked_table.waiting_too_long(start_time, TimeoutKind::SLock) {
+                    return Err(timeouts.error(TimeoutKind::SLock));
                 }
             }
             locked_table.s_lock(block)?;
             self.locks.insert(block.clone(), "S".to_string());
+            drop(locked_table);
+            self.count_block_lock(block);
         }
         Ok(())
     }
@@ -63,39 +103,200 @@ impl ConcurrencyManager {
     /// このようなデッドロックを検知するため、共有ロックを取得してから排他ロックを取得する
     /// 自分以外が握っている共有ロックがある場合、排他ロック時に一度タイムアウトになるまで待機する
     /// タイムアウト後はロック待ち失敗タイムアウトエラーを返す
+    ///
+    /// 2つのトランザクションが同じブロックの共有ロックを持ったまま排他ロックを要求すると、互いの共有ロックを待ち合う
+    /// そのため後から昇格を要求したトランザクションは待たずに LockTimeout を返し、先に要求したトランザクションに排他ロックを与える
+    ///
+    /// 共有ファイルロックを持っているファイルのブロックを排他ロックする場合は、ファイルロックを排他ロックにする
     pub fn x_lock(&mut self, block: &BlockId) -> Result<()> {
+        if self.is_disabled() {
+            return Ok(());
+        }
+        if self.has_file_lock(block) {
+            return self.upgrade_file_lock(&block.filename);
+        }
         if !self.has_x_lock(block) {
             self.s_lock(block)?;
-            let (lock_table, cvar) = &*self.lock_table;
+            // 共有ロックを取ったときにファイルロックにまとめた場合
+            if self.has_file_lock(block) {
+                return self.upgrade_file_lock(&block.filename);
+            }
+            let Some(lock_table) = &self.lock_table else {
+                return Ok(());
+            };
+            let (lock_table, cvar) = &**lock_table;
             let mut locked_table = lock_table.lock().unwrap();
-            let start_time = std::time::Instant::now();
+            let clock = locked_table.clock();
+            let timeouts = locked_table.timeouts();
+            let start_time = clock.now();
 
-            while locked_table.has_other_s_lock(block) {
-                locked_table = cvar.wait_timeout(locked_table, TIMEOUT).unwrap().0;
-                if start_time.elapsed() > TIMEOUT {
-                    bail!("Lock timeout");
+            locked_table.begin_upgrade(block)?;
+            let mut attempt = 0;
+            while locked_table.has_other_s_lock(block)
+                || locked_table.is_blocked_by_file_lock(block, true)
+            {
+                let wait = clock.wait_time(timeouts.backoff(attempt));
+                locked_table = cvar.wait_timeout(locked_table, wait).unwrap().0;
+                attempt += 1;
+                if locked_table.waiting_too_long(start_time, TimeoutKind::XLock) {
+                    locked_table.end_upgrade(block);
+                    return Err(timeouts.error(TimeoutKind::XLock));
                 }
             }
 
+            locked_table.end_upgrade(block);
             locked_table.x_lock(block)?;
             self.locks.insert(block.clone(), "X".to_string());
         }
         Ok(())
     }
 
+    /// x_lock_file はファイル全体の排他ロックを取る。持っているそのファイルのブロックロックはファイルロックに置き換える
+    /// 他のトランザクションがファイルやそのブロックをロックしている間は待つ
+    /// ファイルの末尾を表すダミーブロックはファイルロックに含まれないので、必要なら別にロックする
+    pub fn x_lock_file(&mut self, filename: &str) -> Result<()> {
+        if self.file_locks.contains_key(filename) {
+            return self.upgrade_file_lock(filename);
+        }
+        let Some(lock_table) = &self.lock_table else {
+            return Ok(());
+        };
+        let (lock_table, cvar) = &**lock_table;
+        let mut locked_table = lock_table.lock().unwrap();
+        let clock = locked_table.clock();
+        let timeouts = locked_table.timeouts();
+        let start_time = clock.now();
+        let own_locks: Vec<(BlockId, bool)> = self
+            .locks
+            .iter()
+            .filter(|(block, _)| block.num >= 0 && block.filename == filename)
+            .map(|(block, lock_type)| (block.clone(), lock_type == "X"))
+            .collect();
+        let mut attempt = 0;
+        while !locked_table.escalate(filename, &own_locks, true) {
+            let wait = clock.wait_time(timeouts.backoff(attempt));
+            locked_table = cvar.wait_timeout(locked_table, wait).unwrap().0;
+            attempt += 1;
+            if locked_table.waiting_too_long(start_time, TimeoutKind::XLock) {
+                return Err(timeouts.error(TimeoutKind::XLock));
+            }
+        }
+        drop(locked_table);
+        for (block, _) in &own_locks {
+            self.locks.remove(block);
+        }
+        self.block_counts.remove(filename);
+        self.file_locks
+            .insert(filename.to_string(), "X".to_string());
+        Ok(())
+    }
+
     pub fn release(&mut self) {
-        let (lock_table, cvar) = &*self.lock_table;
+        let Some(lock_table) = &self.lock_table else {
+            return;
+        };
+        let (lock_table, cvar) = &**lock_table;
         let mut locked_table = lock_table.lock().unwrap();
         for block in self.locks.keys() {
             locked_table.unlock(block);
         }
+        for filename in self.file_locks.keys() {
+            locked_table.unlock_file(filename);
+        }
 
         cvar.notify_all();
         self.locks.clear();
+        self.file_locks.clear();
+        self.block_counts.clear();
+    }
+
+    /// file_lock_count はエスカレーションで取得したファイルロックの数を返す
+    pub fn file_lock_count(&self) -> usize {
+        self.file_locks.len()
+    }
+
+    /// has_file_lock はブロックを含むファイルのロックを持っているかどうかを返す
+    fn has_file_lock(&self, block: &BlockId) -> bool {
+        block.num >= 0 && self.file_locks.contains_key(&block.filename)
+    }
+
+    fn count_block_lock(&mut self, block: &BlockId) {
+        if block.num < 0 {
+            return;
+        }
+        *self.block_counts.entry(block.filename.clone()).or_default() += 1;
+        self.try_escalate(&block.filename);
+    }
+
+    /// try_escalate はファイルのブロックロックがしきい値に達していれば、それらをファイルロックに置き換える
+    /// 持っているブロックロックに排他ロックがあれば排他ファイルロックにする
+    fn try_escalate(&mut self, filename: &str) {
+        let Some(lock_table) = &self.lock_table else {
+            return;
+        };
+        let (lock_table, _) = &**lock_table;
+        let mut locked_table = lock_table.lock().unwrap();
+        let threshold = locked_table.escalation_threshold();
+        let count = self.block_counts.get(filename).copied().unwrap_or(0);
+        if threshold == 0 || count < threshold {
+            return;
+        }
+        let own_locks: Vec<(BlockId, bool)> = self
+            .locks
+            .iter()
+            .filter(|(block, _)| block.num >= 0 && block.filename == filename)
+            .map(|(block, lock_type)| (block.clone(), lock_type == "X"))
+            .collect();
+        let exclusive = own_locks.iter().any(|(_, x)| *x);
+        if !locked_table.escalate(filename, &own_locks, exclusive) {
+            return;
+        }
+        for (block, _) in &own_locks {
+            self.locks.remove(block);
+        }
+        self.block_counts.remove(filename);
+        let lock_type = if exclusive { "X" } else { "S" };
+        self.file_locks
+            .insert(filename.to_string(), lock_type.to_string());
+    }
+
+    /// upgrade_file_lock は共有ファイルロックを排他ファイルロックにする
+    /// 他のトランザクションがファイルやそのブロックをロックしている間は待つ
+    fn upgrade_file_lock(&mut self, filename: &str) -> Result<()> {
+        if self.file_locks.get(filename).is_some_and(|t| t == "X") {
+            return Ok(());
+        }
+        let Some(lock_table) = &self.lock_table else {
+            return Ok(());
+        };
+        let (lock_table, cvar) = &**lock_table;
+        let mut locked_table = lock_table.lock().unwrap();
+        let clock = locked_table.clock();
+        let timeouts = locked_table.timeouts();
+        let start_time = clock.now();
+        locked_table.begin_file_upgrade(filename)?;
+        let mut attempt = 0;
+        while locked_table.has_other_file_holders(filename) {
+            let wait = clock.wait_time(timeouts.backoff(attempt));
+            locked_table = cvar.wait_timeout(locked_table, wait).unwrap().0;
+            attempt += 1;
+            if locked_table.waiting_too_long(start_time, TimeoutKind::XLock) {
+                locked_table.end_file_upgrade(filename);
+                return Err(timeouts.error(TimeoutKind::XLock));
+            }
+        }
+        locked_table.end_file_upgrade(filename);
+        locked_table.upgrade_file_lock(filename)?;
+        self.file_locks
+            .insert(filename.to_string(), "X".to_string());
+        Ok(())
     }
 
     // 同一トランザクションですでに排他ロックがある場合はtrueを返す
     pub fn has_x_lock(&self, block: &BlockId) -> bool {
+        if self.has_file_lock(block) {
+            return self.file_locks[&block.filename] == "X";
+        }
         let Some(lock_typee) = self.locks.get(block) else {
             return false;
         };
@@ -103,3 +304,204 @@ impl ConcurrencyManager {
         lock_typee == "X"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::MockClock,
+        timeout::{Timeouts, WaitTimeout, DEFAULT_TIMEOUT},
+        tx::concurrency::lock_timeout::LockTimeout,
+    };
+    use std::{
+        sync::Barrier,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn should_time_out_waiting_for_lock() {
+        let clock = Arc::new(MockClock::default());
+        let lock_table = Arc::new((Mutex::new(LockTable::new(clock.clone())), Condvar::new()));
+        let block = BlockId::new("test".to_string(), 0);
+        let mut tx1 = ConcurrencyManager::new(lock_table.clone());
+        let mut tx2 = ConcurrencyManager::new(lock_table);
+
+        tx1.x_lock(&block).unwrap();
+        let err = tx2.s_lock(&block).unwrap_err();
+        assert!(err.downcast_ref::<LockTimeout>().is_some());
+        assert_eq!(
+            err.downcast_ref::<WaitTimeout>().map(|t| t.kind),
+            Some(TimeoutKind::SLock)
+        );
+        assert!(clock.elapsed() > DEFAULT_TIMEOUT);
+
+        tx1.release();
+        tx2.s_lock(&block).unwrap();
+        let mut tx3 = ConcurrencyManager::new(tx1.lock_table.clone().unwrap());
+        tx3.s_lock(&block).unwrap();
+        assert!(tx3.x_lock(&block).is_err());
+    }
+
+    #[test]
+    fn should_not_lock_when_disabled() {
+        let lock_table = Arc::new((Mutex::new(LockTable::default()), Condvar::new()));
+        let block = BlockId::new("test".to_string(), 0);
+        let mut tx1 = ConcurrencyManager::new(lock_table.clone());
+        let mut tx2 = ConcurrencyManager::disabled();
+
+        tx1.s_lock(&block).unwrap();
+        tx2.x_lock(&block).unwrap();
+        assert!(!tx2.has_x_lock(&block));
+        tx2.release();
+        assert_eq!(lock_table.0.lock().unwrap().get_lock_value(&block), 1);
+    }
+
+    #[test]
+    fn should_use_separate_timeouts_for_s_and_x_locks() {
+        let clock = Arc::new(MockClock::default());
+        let timeouts = Timeouts {
+            s_lock: Duration::from_secs(10),
+            x_lock: Duration::from_secs(1),
+            ..Timeouts::default()
+        };
+        let lock_table = LockTable::new(clock.clone()).with_timeouts(timeouts);
+        let lock_table = Arc::new((Mutex::new(lock_table), Condvar::new()));
+        let block = BlockId::new("test".to_string(), 0);
+        let mut tx1 = ConcurrencyManager::new(lock_table.clone());
+        let mut tx2 = ConcurrencyManager::new(lock_table);
+
+        tx1.s_lock(&block).unwrap();
+        let err = tx2.x_lock(&block).unwrap_err();
+        assert_eq!(err.to_string(), "X-lock wait timed out after 1s");
+        let waited = clock.elapsed();
+        assert!(waited > timeouts.x_lock && waited < DEFAULT_TIMEOUT);
+
+        tx2.release();
+        tx1.x_lock(&block).unwrap();
+        let err = tx2.s_lock(&block).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WaitTimeout>().map(|t| t.kind),
+            Some(TimeoutKind::SLock)
+        );
+        assert!(clock.elapsed() - waited > timeouts.s_lock);
+    }
+
+    #[test]
+    fn should_grant_one_of_two_concurrent_upgrades() {
+        let lock_table = Arc::new((Mutex::new(LockTable::default()), Condvar::new()));
+        let block = BlockId::new("test".to_string(), 0);
+        let barrier = Arc::new(Barrier::new(2));
+        let start = Instant::now();
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let lock_table = lock_table.clone();
+                let block = block.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let mut tx = ConcurrencyManager::new(lock_table);
+                    tx.s_lock(&block).unwrap();
+                    barrier.wait();
+                    let result = tx.x_lock(&block);
+                    // 失敗した方はロールバックして共有ロックを解放する
+                    let granted = result.is_ok();
+                    if !granted {
+                        let err = result.unwrap_err();
+                        assert!(err.downcast_ref::<LockTimeout>().is_some());
+                        assert_eq!(
+                            err.downcast_ref::<WaitTimeout>().map(|t| t.kind),
+                            Some(TimeoutKind::XLock)
+                        );
+                        tx.release();
+                    }
+                    barrier.wait();
+                    tx.release();
+                    granted
+                })
+            })
+            .collect();
+        let granted: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // ちょうど1つの昇格が、タイムアウトを待たずに成功する
+        assert_eq!(granted.iter().filter(|granted| **granted).count(), 1);
+        assert!(start.elapsed() < DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn should_escalate_block_locks_to_file_lock() {
+        let clock = Arc::new(MockClock::default());
+        let lock_table = LockTable::new(clock).with_escalation_threshold(3);
+        let lock_table = Arc::new((Mutex::new(lock_table), Condvar::new()));
+        let blocks: Vec<_> = (0..4).map(|n| BlockId::new("t".to_string(), n)).collect();
+        let mut tx1 = ConcurrencyManager::new(lock_table.clone());
+        let mut tx2 = ConcurrencyManager::new(lock_table.clone());
+
+        // 3つ目の共有ロックで1つの共有ファイルロックにまとめる
+        tx1.s_lock(&blocks[0]).unwrap();
+        tx1.s_lock(&BlockId::new("t".to_string(), -1)).unwrap();
+        tx1.s_lock(&blocks[1]).unwrap();
+        assert_eq!(tx1.file_lock_count(), 0);
+        tx1.s_lock(&blocks[2]).unwrap();
+        assert_eq!(tx1.file_lock_count(), 1);
+        assert_eq!(tx1.locks.len(), 1);
+
+        // 共有ファイルロックは他のトランザクションの共有ロックと両立するが、排他ロックとは両立しない
+        tx2.s_lock(&blocks[3]).unwrap();
+        assert!(tx2.x_lock(&blocks[0]).is_err());
+
+        // 他のトランザクションがブロックをロックしている間は排他ファイルロックにできない
+        assert!(tx1.x_lock(&blocks[3]).is_err());
+        tx2.release();
+        tx1.x_lock(&blocks[3]).unwrap();
+        assert!(tx1.has_x_lock(&blocks[0]));
+        assert!(tx2.s_lock(&blocks[1]).is_err());
+
+        tx1.release();
+        tx2.s_lock(&blocks[1]).unwrap();
+        tx2.x_lock(&blocks[1]).unwrap();
+    }
+
+    #[test]
+    fn should_not_escalate_when_other_transaction_holds_lock() {
+        let lock_table = LockTable::default().with_escalation_threshold(2);
+        let lock_table = Arc::new((Mutex::new(lock_table), Condvar::new()));
+        let blocks: Vec<_> = (0..3).map(|n| BlockId::new("t".to_string(), n)).collect();
+        let mut tx1 = ConcurrencyManager::new(lock_table.clone());
+        let mut tx2 = ConcurrencyManager::new(lock_table);
+
+        tx2.s_lock(&blocks[2]).unwrap();
+        tx1.x_lock(&blocks[0]).unwrap();
+        tx1.x_lock(&blocks[1]).unwrap();
+        // tx2 の共有ロックと両立しないので、ブロックロックのまま続ける
+        assert_eq!(tx1.file_lock_count(), 0);
+        assert!(tx1.has_x_lock(&blocks[1]));
+        tx2.s_lock(&blocks[2]).unwrap();
+    }
+
+    #[test]
+    fn should_lock_whole_file_exclusively() {
+        let clock = Arc::new(MockClock::default());
+        let lock_table = Arc::new((Mutex::new(LockTable::new(clock)), Condvar::new()));
+        let blocks: Vec<_> = (0..2).map(|n| BlockId::new("t".to_string(), n)).collect();
+        let mut tx1 = ConcurrencyManager::new(lock_table.clone());
+        let mut tx2 = ConcurrencyManager::new(lock_table);
+
+        // 他のトランザクションがブロックをロックしている間は取れない
+        tx1.s_lock(&blocks[0]).unwrap();
+        tx2.s_lock(&blocks[1]).unwrap();
+        assert!(tx1.x_lock_file("t").is_err());
+        tx2.release();
+
+        // 自分のブロックロックはファイルロックに置き換える
+        tx1.x_lock_file("t").unwrap();
+        assert_eq!(tx1.file_lock_count(), 1);
+        assert!(tx1.locks.is_empty());
+        assert!(tx1.has_x_lock(&blocks[1]));
+        assert!(tx2.s_lock(&blocks[0]).is_err());
+        assert!(tx2.s_lock(&BlockId::new("u".to_string(), 0)).is_ok());
+
+        tx1.release();
+        tx2.s_lock(&blocks[0]).unwrap();
+    }
+}
@@ -1,16 +1,86 @@
-use crate::{file::block::BlockId, TIMEOUT};
-use anyhow::{bail, Result};
-use std::{collections::HashMap, time::SystemTime};
+use super::lock_timeout::LockTimeout;
+use crate::{
+    clock::{default_clock, Clock},
+    file::block::BlockId,
+    timeout::{TimeoutKind, Timeouts},
+};
+use anyhow::Result;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 
-#[derive(Debug, Default)]
+/// DEFAULT_ESCALATION_THRESHOLD は1つのファイルのブロックロックをファイルロックにまとめる既定の数
+pub const DEFAULT_ESCALATION_THRESHOLD: usize = 1000;
+
+/// LockTable はブロックごとのロックと、ブロックロックをまとめたファイルごとのロックを管理する
+///
+/// 1つのトランザクションが1つのファイルで escalation_threshold 個のブロックロックを持つと、
+/// ConcurrencyManager はそれらを1つのファイルロックに置き換える（ロックのエスカレーション）
+/// ファイルロックはそのファイルのすべてのブロックのロックとして扱い、他のトランザクションのブロックロックと両立するか確認する
+/// ファイルの末尾を表すダミーブロック（ブロック番号が負のもの）はファイルロックに含めない
+///
+/// 共有ロックから排他ロックへの昇格は、ブロックやファイルごとに1つのトランザクションだけが待てる
+/// 2つ目のトランザクションが同じものを昇格しようとすると、互いの共有ロックを待ち合うことになるので、すぐに LockTimeout を返す
+/// 先に昇格を待ち始めたトランザクションは、2つ目のトランザクションがロールバックして共有ロックを解放すれば排他ロックを得られる
+#[derive(Debug)]
 pub struct LockTable {
-    locks: HashMap<BlockId, i32>, // 1: S lock, -1: X lock
+    locks: HashMap<BlockId, i32>,     // 1: S lock, -1: X lock
+    file_locks: HashMap<String, i32>, // same as locks, but for whole files
+    upgrades: HashSet<BlockId>,       // blocks waiting for an S to X upgrade
+    file_upgrades: HashSet<String>,   // files waiting for an S to X upgrade
+    clock: Arc<dyn Clock>,            // used to decide lock wait timeouts
+    timeouts: Timeouts,
+    escalation_threshold: usize, // 0 disables escalation
+}
+
+impl Default for LockTable {
+    fn default() -> Self {
+        Self::new(default_clock())
+    }
 }
 
 impl LockTable {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            locks: HashMap::new(),
+            file_locks: HashMap::new(),
+            upgrades: HashSet::new(),
+            file_upgrades: HashSet::new(),
+            clock,
+            timeouts: Timeouts::default(),
+            escalation_threshold: DEFAULT_ESCALATION_THRESHOLD,
+        }
+    }
+
+    /// with_escalation_threshold はブロックロックをファイルロックにまとめる数を設定する。0 の場合はまとめない
+    pub fn with_escalation_threshold(mut self, threshold: usize) -> Self {
+        self.escalation_threshold = threshold;
+        self
+    }
+
+    /// with_timeouts は共有ロックと排他ロックを待つ時間と、確認し直す間隔を設定する
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    pub fn escalation_threshold(&self) -> usize {
+        self.escalation_threshold
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
     pub fn s_lock(&mut self, block: &BlockId) -> Result<()> {
-        if self.has_x_lock(block) {
-            bail!("Lock timeout")
+        if self.has_x_lock(block) || self.is_blocked_by_file_lock(block, false) {
+            return Err(LockTimeout.into());
         }
         let value = self.get_lock_value(block);
         self.locks.insert(block.clone(), value + 1);
@@ -18,8 +88,8 @@ impl LockTable {
     }
 
     pub fn x_lock(&mut self, block: &BlockId) -> Result<()> {
-        if self.has_other_s_lock(block) {
-            bail!("Lock timeout")
+        if self.has_other_s_lock(block) || self.is_blocked_by_file_lock(block, true) {
+            return Err(LockTimeout.into());
         }
         self.locks.insert(block.clone(), -1);
         Ok(())
@@ -42,11 +112,126 @@ impl LockTable {
         self.get_lock_value(block) > 1
     }
 
-    pub fn waiting_too_long(start_time: SystemTime) -> bool {
-        SystemTime::now().duration_since(start_time).unwrap() > TIMEOUT
+    pub fn waiting_too_long(&self, start_time: Instant, kind: TimeoutKind) -> bool {
+        self.clock.now().duration_since(start_time) > self.timeouts.get(kind)
     }
 
     pub fn get_lock_value(&self, block: &BlockId) -> i32 {
         *self.locks.get(block).unwrap_or(&0)
     }
+
+    /// is_blocked_by_file_lock は他のトランザクションのファイルロックがあるためにブロックをロックできないかどうかを返す
+    /// 自分のファイルロックで足りる場合は ConcurrencyManager がロックテーブルを使わない
+    pub fn is_blocked_by_file_lock(&self, block: &BlockId, exclusive: bool) -> bool {
+        if block.num < 0 {
+            return false;
+        }
+        if exclusive {
+            self.has_file_lock(&block.filename)
+        } else {
+            self.has_file_x_lock(&block.filename)
+        }
+    }
+
+    /// begin_upgrade はブロックの昇格を待ち始める
+    /// 他のトランザクションがすでに昇格を待っている場合はデッドロックになるので、排他ロックのタイムアウトのエラーを返す
+    pub fn begin_upgrade(&mut self, block: &BlockId) -> Result<()> {
+        if !self.upgrades.insert(block.clone()) {
+            return Err(self.timeouts.error(TimeoutKind::XLock));
+        }
+        Ok(())
+    }
+
+    pub fn end_upgrade(&mut self, block: &BlockId) {
+        self.upgrades.remove(block);
+    }
+
+    /// begin_file_upgrade はファイルロックの昇格を待ち始める。begin_upgrade のファイル版
+    pub fn begin_file_upgrade(&mut self, filename: &str) -> Result<()> {
+        if !self.file_upgrades.insert(filename.to_string()) {
+            return Err(self.timeouts.error(TimeoutKind::XLock));
+        }
+        Ok(())
+    }
+
+    pub fn end_file_upgrade(&mut self, filename: &str) {
+        self.file_upgrades.remove(filename);
+    }
+
+    /// has_file_x_lock はファイルの排他ロックがあるかどうかを返す
+    pub fn has_file_x_lock(&self, filename: &str) -> bool {
+        self.get_file_lock_value(filename) < 0
+    }
+
+    /// has_file_lock はファイルのロックが1つでもあるかどうかを返す
+    pub fn has_file_lock(&self, filename: &str) -> bool {
+        self.get_file_lock_value(filename) != 0
+    }
+
+    /// has_other_file_holders は自分の共有ファイルロック以外に、ファイルやそのブロックのロックがあるかどうかを返す
+    pub fn has_other_file_holders(&self, filename: &str) -> bool {
+        self.get_file_lock_value(filename) > 1
+            || self
+                .locks
+                .keys()
+                .any(|block| block.num >= 0 && block.filename == filename)
+    }
+
+    /// escalate は own_locks（ブロックと排他ロックかどうか）をファイルロックに置き換える
+    /// 他のトランザクションのロックと両立しない場合は何もせずに false を返す
+    pub fn escalate(
+        &mut self,
+        filename: &str,
+        own_locks: &[(BlockId, bool)],
+        exclusive: bool,
+    ) -> bool {
+        if self.has_file_x_lock(filename) || (exclusive && self.has_file_lock(filename)) {
+            return false;
+        }
+        let own: HashMap<&BlockId, bool> = own_locks.iter().map(|(block, x)| (block, *x)).collect();
+        let conflict = self
+            .locks
+            .iter()
+            .filter(|(block, _)| block.num >= 0 && block.filename == filename)
+            .any(|(block, &value)| match own.get(block) {
+                // 自分が排他ロックを持つブロックは他のトランザクションがロックできない
+                Some(true) => false,
+                // 自分の共有ロックを除いても残るロックがあるかどうか
+                Some(false) => exclusive && value > 1,
+                None => exclusive || value < 0,
+            });
+        if conflict {
+            return false;
+        }
+
+        for (block, _) in own_locks {
+            self.unlock(block);
+        }
+        let value = self.get_file_lock_value(filename);
+        let value = if exclusive { -1 } else { value + 1 };
+        self.file_locks.insert(filename.to_string(), value);
+        true
+    }
+
+    /// upgrade_file_lock は自分の共有ファイルロックを排他ファイルロックにする
+    pub fn upgrade_file_lock(&mut self, filename: &str) -> Result<()> {
+        if self.has_other_file_holders(filename) {
+            return Err(LockTimeout.into());
+        }
+        self.file_locks.insert(filename.to_string(), -1);
+        Ok(())
+    }
+
+    pub fn unlock_file(&mut self, filename: &str) {
+        let value = self.get_file_lock_value(filename);
+        if value > 1 {
+            self.file_locks.insert(filename.to_string(), value - 1);
+        } else {
+            self.file_locks.remove(filename);
+        }
+    }
+
+    fn get_file_lock_value(&self, filename: &str) -> i32 {
+        *self.file_locks.get(filename).unwrap_or(&0)
+    }
 }
@@ -0,0 +1,12 @@
+/// LockTimeout はロックを待っている間にタイムアウトしたことを表すエラー
+/// デッドロックの可能性があるので、トランザクションをロールバックしてやり直せば成功することがある
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockTimeout;
+
+impl std::fmt::Display for LockTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Lock timeout")
+    }
+}
+
+impl std::error::Error for LockTimeout {}
@@ -1,2 +1,3 @@
 pub mod concurrency_manager;
 pub mod lock_table;
+pub mod lock_timeout;
@@ -0,0 +1,39 @@
+use super::concurrency::lock_timeout::LockTimeout;
+
+/// TransientError はやり直せば成功する可能性がある一時的なエラーかどうかを判定する
+/// ロックのタイムアウト（デッドロックの犠牲になった場合も含む）は一時的なエラーで、
+/// 構文エラーや存在しないテーブルなどは何度やり直しても失敗する
+pub trait TransientError {
+    fn is_transient(&self) -> bool;
+}
+
+impl TransientError for LockTimeout {
+    fn is_transient(&self) -> bool {
+        true
+    }
+}
+
+impl TransientError for anyhow::Error {
+    /// 原因をたどって、一時的なエラーが含まれているかどうかを返す
+    fn is_transient(&self) -> bool {
+        self.chain().any(|cause| {
+            cause
+                .downcast_ref::<LockTimeout>()
+                .is_some_and(|err| err.is_transient())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_classify_transient_errors() {
+        let err = anyhow::Error::new(LockTimeout).context("insert failed");
+        assert!(err.is_transient());
+        assert_eq!(format!("{}", err.root_cause()), "Lock timeout");
+        assert!(!anyhow::anyhow!("Lock timeout").is_transient());
+        assert!(!anyhow::anyhow!("table not found").is_transient());
+    }
+}
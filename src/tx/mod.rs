@@ -1,4 +1,7 @@
 pub mod buffer_list;
+pub mod commit_listener;
 pub mod concurrency;
+pub mod error;
 pub mod recovery;
 pub mod transaction;
+pub mod undo_verifier;
@@ -0,0 +1,84 @@
+use crate::{
+    file::page::Page, log::log_manager::LogManager, tx::transaction::Transaction, I32_SIZE,
+};
+use anyhow::Result;
+
+use super::record::{LogRecord, LogRecordType};
+
+/// BulkLoadRecord は一括処理でファイルの start_block 番目から後にブロックを追加し始めたことを記録する論理的なログレコード
+///
+/// 追加したブロックは他のトランザクションが使えないので、その中の変更は値ごとにはログに記録しない
+/// 取り消すときはファイルを start_block 個のブロックに戻すので、ログは書き込んだデータの量によらない
+pub struct BulkLoadRecord {
+    tx_num: i32,
+    filename: String,
+    start_block: i32,
+}
+
+impl std::fmt::Display for BulkLoadRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<BULKLOAD {} {} {}>",
+            self.tx_num, self.filename, self.start_block
+        )
+    }
+}
+
+impl BulkLoadRecord {
+    pub fn new(page: &mut Page) -> Self {
+        let tpos = I32_SIZE;
+        let tx_num = page.get_int(tpos);
+
+        let fpos = tpos + I32_SIZE;
+        let filename = page.get_string(fpos);
+
+        let bpos = fpos + Page::max_length(filename.len());
+        let start_block = page.get_int(bpos);
+
+        Self {
+            tx_num,
+            filename,
+            start_block,
+        }
+    }
+
+    /// Write a bulk load record to the log
+    /// log record is formatted as follows:
+    /// ```markdown
+    /// | Type      | txnum     | filename length   | filename       | start block   |
+    /// | --------- | --------- | ----------------- | -------------- | ------------- |
+    /// | 4 bytes   | 4 bytes   | 4 bytes           | length bytes   | 4 bytes       |
+    /// ```
+    pub fn write_to_log(
+        log_manager: &mut LogManager,
+        tx_num: i32,
+        filename: &str,
+        start_block: i32,
+    ) -> Result<i32> {
+        let tpos = I32_SIZE;
+        let fpos = tpos + I32_SIZE;
+        let bpos = fpos + Page::max_length(filename.len());
+        let record_len = bpos + I32_SIZE;
+        let mut page = Page::new(record_len as i32);
+        page.set_int(0, LogRecordType::BulkLoad as i32);
+        page.set_int(tpos, tx_num);
+        page.set_string(fpos, filename);
+        page.set_int(bpos, start_block);
+        log_manager.append(page.contents())
+    }
+}
+
+impl LogRecord for BulkLoadRecord {
+    fn op(&self) -> LogRecordType {
+        LogRecordType::BulkLoad
+    }
+
+    fn tx_number(&self) -> i32 {
+        self.tx_num
+    }
+
+    fn undo(&mut self, tx: &mut Transaction) -> Result<()> {
+        tx.shrink_file(&self.filename, self.start_block)
+    }
+}
@@ -13,7 +13,7 @@ pub struct CommitRecord {
 
 impl CommitRecord {
     pub fn new(page: &mut Page) -> Self {
-        let tx_num = page.get_int(0);
+        let tx_num = page.get_int(I32_SIZE);
         Self { tx_num }
     }
 }
@@ -1,8 +1,15 @@
+pub mod bulk_load_record;
 pub mod checkpoint_record;
 pub mod commit_record;
 pub mod record;
 pub mod recovery_manager;
 pub mod rollback_record;
+pub mod set_bool_record;
+pub mod set_date_record;
+pub mod set_double_record;
 pub mod set_int_record;
+pub mod set_long_record;
 pub mod set_string_record;
 pub mod start_record;
+pub mod truncate_record;
+pub mod write_bytes_record;
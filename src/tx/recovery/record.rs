@@ -1,11 +1,21 @@
 use anyhow::{bail, Result};
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+};
 
-use crate::{file::page::Page, tx::transaction::Transaction};
+use crate::{
+    file::{block::BlockId, page::Page},
+    tx::transaction::Transaction,
+};
 
 use super::{
-    checkpoint_record::CheckpointRecord, commit_record::CommitRecord,
-    rollback_record::RollbackRecord, set_int_record::SetIntRecord,
-    set_string_record::SetStringRecord, start_record::StartRecord,
+    bulk_load_record::BulkLoadRecord, checkpoint_record::CheckpointRecord,
+    commit_record::CommitRecord, rollback_record::RollbackRecord, set_bool_record::SetBoolRecord,
+    set_date_record::SetDateRecord, set_double_record::SetDoubleRecord,
+    set_int_record::SetIntRecord, set_long_record::SetLongRecord,
+    set_string_record::SetStringRecord, start_record::StartRecord, truncate_record::TruncateRecord,
+    write_bytes_record::WriteBytesRecord,
 };
 
 #[derive(PartialEq, Eq)]
@@ -16,6 +26,15 @@ pub enum LogRecordType {
     Rollback = 3,
     SetInt = 4,
     SetString = 5,
+    WriteBytes = 6,
+    SetLong = 7,
+    SetDouble = 8,
+    SetBool = 9,
+    SetDate = 10,
+    Truncate = 11,
+    BulkLoad = 12,
+    /// Custom は register_log_record で登録した種類のレコード
+    Custom,
     Unknown,
 }
 
@@ -28,27 +47,239 @@ impl From<u8> for LogRecordType {
             3 => Self::Rollback,
             4 => Self::SetInt,
             5 => Self::SetString,
+            6 => Self::WriteBytes,
+            7 => Self::SetLong,
+            8 => Self::SetDouble,
+            9 => Self::SetBool,
+            10 => Self::SetDate,
+            11 => Self::Truncate,
+            12 => Self::BulkLoad,
             _ => Self::Unknown,
         }
     }
 }
 
-pub trait LogRecord {
+/// LogRecord はログに記録した1つの操作。Display はリカバリの報告などで使う1行の説明になる
+pub trait LogRecord: std::fmt::Display {
     fn op(&self) -> LogRecordType;
     fn tx_number(&self) -> i32;
     fn undo(&mut self, tx: &mut Transaction) -> Result<()>;
+    /// block はレコードが変更を記録したブロックを返す。ブロックを変更しないレコードは None を返す
+    fn block(&self) -> Option<&BlockId> {
+        None
+    }
+    /// cleanup はリカバリでコミット済みのトランザクションのレコードを読んだときに呼ぶ
+    /// コミットの後に行うはずだった後始末が、途中で止まって残っていれば行う
+    fn cleanup(&mut self, _tx: &mut Transaction) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// LogRecordDecoder はログのバイト列を読み込んだページから、その種類のログレコードを作る
+pub type LogRecordDecoder = fn(&mut Page) -> Result<Box<dyn LogRecord>>;
+
+/// FIRST_CUSTOM_RECORD_TYPE は register_log_record で登録できる最初の種類の番号
+/// それより小さい番号はこのクレートのログレコードのために取っておく
+pub const FIRST_CUSTOM_RECORD_TYPE: i32 = 64;
+
+/// decoders はログレコードの種類の番号から decoder を引く表を返す。最初に使うときにこのクレートのログレコードを登録する
+fn decoders() -> &'static RwLock<HashMap<i32, LogRecordDecoder>> {
+    static DECODERS: OnceLock<RwLock<HashMap<i32, LogRecordDecoder>>> = OnceLock::new();
+    DECODERS.get_or_init(|| {
+        let builtins: [(LogRecordType, LogRecordDecoder); 13] = [
+            (LogRecordType::Checkpoint, |_| {
+                Ok(Box::<CheckpointRecord>::default())
+            }),
+            (LogRecordType::Start, |page| {
+                Ok(Box::new(StartRecord::new(page)))
+            }),
+            (LogRecordType::Commit, |page| {
+                Ok(Box::new(CommitRecord::new(page)))
+            }),
+            (LogRecordType::Rollback, |page| {
+                Ok(Box::new(RollbackRecord::new(page)))
+            }),
+            (LogRecordType::SetInt, |page| {
+                Ok(Box::new(SetIntRecord::new(page)))
+            }),
+            (LogRecordType::SetString, |page| {
+                Ok(Box::new(SetStringRecord::new(page)))
+            }),
+            (LogRecordType::WriteBytes, |page| {
+                Ok(Box::new(WriteBytesRecord::new(page)))
+            }),
+            (LogRecordType::SetLong, |page| {
+                Ok(Box::new(SetLongRecord::new(page)))
+            }),
+            (LogRecordType::SetDouble, |page| {
+                Ok(Box::new(SetDoubleRecord::new(page)))
+            }),
+            (LogRecordType::SetBool, |page| {
+                Ok(Box::new(SetBoolRecord::new(page)))
+            }),
+            (LogRecordType::SetDate, |page| {
+                Ok(Box::new(SetDateRecord::new(page)))
+            }),
+            (LogRecordType::Truncate, |page| {
+                Ok(Box::new(TruncateRecord::new(page)))
+            }),
+            (LogRecordType::BulkLoad, |page| {
+                Ok(Box::new(BulkLoadRecord::new(page)))
+            }),
+        ];
+        let decoders = builtins
+            .into_iter()
+            .map(|(op, decoder)| (op as i32, decoder))
+            .collect();
+        RwLock::new(decoders)
+    })
+}
+
+/// register_log_record は番号 op の種類のログレコードを読み込む decoder を登録する
+///
+/// このクレートの外で新しい種類のログレコードを作るときに使う。レコードの先頭の4バイトには op を書き、
+/// LogRecord::op は LogRecordType::Custom を返すようにする。リカバリでは変更を記録したレコードと同じように取り消す
+/// op は FIRST_CUSTOM_RECORD_TYPE 以上で、まだ登録されていない番号でなければならない
+/// ログを読む前に（データベースを開く前に）登録しておくこと
+pub fn register_log_record(op: i32, decoder: LogRecordDecoder) -> Result<()> {
+    if op < FIRST_CUSTOM_RECORD_TYPE {
+        bail!(
+            "log record type {} is reserved (custom types start at {})",
+            op,
+            FIRST_CUSTOM_RECORD_TYPE
+        );
+    }
+    let mut decoders = decoders().write().unwrap();
+    if decoders.contains_key(&op) {
+        bail!("log record type {} is already registered", op);
+    }
+    decoders.insert(op, decoder);
+    Ok(())
 }
 
 pub fn create_log_record(bytes: &[u8]) -> Result<Box<dyn LogRecord>> {
     let mut page: Page = bytes.to_vec().into();
-    let op = page.get_int(0) as u8;
-    match LogRecordType::from(op) {
-        LogRecordType::Checkpoint => Ok(Box::<CheckpointRecord>::default()),
-        LogRecordType::Start => Ok(Box::new(StartRecord::new(&mut page))),
-        LogRecordType::Commit => Ok(Box::new(CommitRecord::new(&mut page))),
-        LogRecordType::Rollback => Ok(Box::new(RollbackRecord::new(&mut page))),
-        LogRecordType::SetInt => Ok(Box::new(SetIntRecord::new(&mut page))),
-        LogRecordType::SetString => Ok(Box::new(SetStringRecord::new(&mut page))),
-        LogRecordType::Unknown => bail!("Unknown log record type '{:X}'", op),
+    let op = page.get_int(0);
+    let Some(decoder) = decoders().read().unwrap().get(&op).copied() else {
+        bail!("Unknown log record type '{:X}'", op);
+    };
+    decoder(&mut page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file::{date::Date, file_manager::FileManager},
+        log::log_manager::LogManager,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn should_decode_typed_set_records() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 400)?));
+        let mut log_manager = LogManager::new(file_manager, "log".to_string())?;
+        let block = BlockId::new("testfile".into(), 3);
+        SetLongRecord::write_to_log(&mut log_manager, 7, &block, 0, i64::MIN)?;
+        SetDoubleRecord::write_to_log(&mut log_manager, 7, &block, 8, 0.5)?;
+        SetBoolRecord::write_to_log(&mut log_manager, 7, &block, 16, true)?;
+        SetDateRecord::write_to_log(&mut log_manager, 7, &block, 20, Date::from_days(-1))?;
+
+        // ログは新しい順に読み込まれる
+        let mut ops = vec![];
+        for bytes in log_manager.iter()? {
+            let record = create_log_record(&bytes?)?;
+            assert_eq!(record.tx_number(), 7);
+            assert_eq!(record.block(), Some(&block));
+            ops.push(record.op() as i32);
+        }
+        assert_eq!(
+            ops,
+            vec![
+                LogRecordType::SetDate as i32,
+                LogRecordType::SetBool as i32,
+                LogRecordType::SetDouble as i32,
+                LogRecordType::SetLong as i32,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_decode_logical_records() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 400)?));
+        let mut log_manager = LogManager::new(file_manager, "log".to_string())?;
+        TruncateRecord::write_to_log(&mut log_manager, 3, "T.tbl", "~3_0_T.tbl")?;
+        BulkLoadRecord::write_to_log(&mut log_manager, 3, "T.tbl", 5)?;
+
+        let records: Vec<String> = log_manager
+            .iter()?
+            .map(|bytes| create_log_record(&bytes?).map(|record| record.to_string()))
+            .collect::<Result<_>>()?;
+        assert_eq!(
+            records,
+            vec!["<BULKLOAD 3 T.tbl 5>", "<TRUNCATE 3 T.tbl ~3_0_T.tbl>"]
+        );
+        Ok(())
+    }
+
+    /// NoteRecord は登録して使うログレコードの例。取り消しても何も変更しない
+    struct NoteRecord {
+        tx_num: i32,
+        note: String,
+    }
+
+    impl std::fmt::Display for NoteRecord {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "<NOTE {} {}>", self.tx_num, self.note)
+        }
+    }
+
+    impl LogRecord for NoteRecord {
+        fn op(&self) -> LogRecordType {
+            LogRecordType::Custom
+        }
+
+        fn tx_number(&self) -> i32 {
+            self.tx_num
+        }
+
+        fn undo(&mut self, _tx: &mut Transaction) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_decode_registered_records() -> Result<()> {
+        const NOTE: i32 = FIRST_CUSTOM_RECORD_TYPE;
+        let decode: LogRecordDecoder = |page| {
+            Ok(Box::new(NoteRecord {
+                tx_num: page.get_int(4),
+                note: page.get_string(8),
+            }))
+        };
+        let tempdir = tempfile::tempdir()?;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(tempdir.path(), 400)?));
+        let mut log_manager = LogManager::new(file_manager, "log".to_string())?;
+        let mut page = Page::new(8 + Page::max_length(5) as i32);
+        page.set_int(0, NOTE);
+        page.set_int(4, 7);
+        page.set_string(8, "hello");
+        log_manager.append(page.contents())?;
+        let bytes = log_manager.iter()?.next().unwrap()?;
+        assert!(create_log_record(&bytes).is_err());
+
+        register_log_record(NOTE, decode)?;
+        let record = create_log_record(&bytes)?;
+        assert!(record.op() == LogRecordType::Custom);
+        assert_eq!(record.tx_number(), 7);
+        assert_eq!(record.to_string(), "<NOTE 7 hello>");
+
+        // 同じ番号やこのクレートのために取っておいた番号は登録できない
+        assert!(register_log_record(NOTE, decode).is_err());
+        assert!(register_log_record(LogRecordType::Commit as i32, decode).is_err());
+        Ok(())
     }
 }
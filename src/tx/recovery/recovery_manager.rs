@@ -1,6 +1,6 @@
 use anyhow::Result;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 
@@ -11,17 +11,61 @@ use crate::{
 };
 
 use super::{
+    bulk_load_record::BulkLoadRecord,
     commit_record::CommitRecord,
-    record::{create_log_record, LogRecordType},
+    record::{create_log_record, LogRecord, LogRecordType},
+    set_bool_record::SetBoolRecord,
+    set_date_record::SetDateRecord,
+    set_double_record::SetDoubleRecord,
     set_int_record::SetIntRecord,
+    set_long_record::SetLongRecord,
     set_string_record::SetStringRecord,
     start_record::StartRecord,
+    truncate_record::TruncateRecord,
+    write_bytes_record::WriteBytesRecord,
 };
 
+/// PROGRESS_INTERVAL はリカバリの進み具合を知らせる間隔（読んだログレコードの数）
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// RecoveryProgress はリカバリでログを読んだ進み具合
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryProgress {
+    /// records はこれまでに読んだログレコードの数
+    pub records: usize,
+    /// lsn は最後に読んだログレコードの LSN。ログは新しい順に読むので小さくなっていく
+    pub lsn: i32,
+}
+
+/// RecoveryReport はリカバリで読んだログと取り消したログレコードをまとめたもの
+///
+/// リカバリは完了していないトランザクションの変更を取り消す（undo）だけで、やり直す（redo）ことはない
+/// コミットした変更はコミットのときにディスクに書き込まれているため
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// records は読んだログレコードの数
+    pub records: usize,
+    /// checkpoint はログを読むのを止めたチェックポイントの LSN。チェックポイントがない場合はログの先頭まで読む
+    pub checkpoint: Option<i32>,
+    /// incomplete は完了していなかったトランザクションの番号
+    pub incomplete: Vec<i32>,
+    /// undo は取り消すログレコード。取り消す順（新しい順）に並ぶ
+    pub undo: Vec<UndoEntry>,
+}
+
+/// UndoEntry はリカバリで取り消すログレコード
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoEntry {
+    pub lsn: i32,
+    pub tx_num: i32,
+    /// record はログレコードの説明（`<SETINT 1 [file T.tbl, block 0] 4 0>` など）
+    pub record: String,
+}
+
 #[derive(Debug)]
 pub struct RecoveryManager {
     log_manager: Arc<Mutex<LogManager>>,
-    buffer_manager: Arc<Mutex<BufferManager>>,
+    buffer_manager: Arc<BufferManager>,
     tx_num: i32,
 }
 
@@ -29,7 +73,7 @@ impl RecoveryManager {
     pub fn new(
         tx_num: i32,
         log_manager: Arc<Mutex<LogManager>>,
-        buffer_manager: Arc<Mutex<BufferManager>>,
+        buffer_manager: Arc<BufferManager>,
     ) -> Result<RecoveryManager> {
         StartRecord::write_to_log(&mut log_manager.lock().unwrap(), tx_num)?;
         Ok(RecoveryManager {
@@ -40,21 +84,74 @@ impl RecoveryManager {
     }
 
     pub fn set_int(&self, buffer: &mut Buffer, offset: i32) -> Result<i32> {
-        let old_value = buffer.contents_mut().get_int(offset as usize);
+        let old_value = buffer.contents().get_int(offset as usize);
         let block = buffer.block().unwrap();
         let mut log_manager = self.log_manager.lock().unwrap();
         SetIntRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, old_value)
     }
 
     pub fn set_string(&self, buffer: &mut Buffer, offset: i32) -> Result<i32> {
-        let old_value = buffer.contents_mut().get_string(offset as usize);
+        let old_value = buffer.contents().get_string(offset as usize);
         let block = buffer.block().unwrap();
         let mut log_manager = self.log_manager.lock().unwrap();
         SetStringRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, old_value)
     }
 
+    pub fn set_long(&self, buffer: &mut Buffer, offset: i32) -> Result<i32> {
+        let old_value = buffer.contents().get_long(offset as usize);
+        let block = buffer.block().unwrap();
+        let mut log_manager = self.log_manager.lock().unwrap();
+        SetLongRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, old_value)
+    }
+
+    pub fn set_double(&self, buffer: &mut Buffer, offset: i32) -> Result<i32> {
+        let old_value = buffer.contents().get_double(offset as usize);
+        let block = buffer.block().unwrap();
+        let mut log_manager = self.log_manager.lock().unwrap();
+        SetDoubleRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, old_value)
+    }
+
+    pub fn set_bool(&self, buffer: &mut Buffer, offset: i32) -> Result<i32> {
+        let old_value = buffer.contents().get_bool(offset as usize);
+        let block = buffer.block().unwrap();
+        let mut log_manager = self.log_manager.lock().unwrap();
+        SetBoolRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, old_value)
+    }
+
+    pub fn set_date(&self, buffer: &mut Buffer, offset: i32) -> Result<i32> {
+        let old_value = buffer.contents().get_date(offset as usize);
+        let block = buffer.block().unwrap();
+        let mut log_manager = self.log_manager.lock().unwrap();
+        SetDateRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, old_value)
+    }
+
+    pub fn write_bytes(&self, buffer: &mut Buffer, offset: i32, len: usize) -> Result<i32> {
+        let old_value = buffer.contents().read_bytes(offset as usize, len)?;
+        let block = buffer.block().unwrap();
+        let mut log_manager = self.log_manager.lock().unwrap();
+        WriteBytesRecord::write_to_log(&mut log_manager, self.tx_num, block, offset, &old_value)
+    }
+
+    /// truncate はファイルを空にすることを記録する。ファイルを変える前に記録がディスクにあるように、ログを書き込む
+    pub fn truncate(&self, filename: &str, backup: &str) -> Result<i32> {
+        let mut log_manager = self.log_manager.lock().unwrap();
+        let lsn = TruncateRecord::write_to_log(&mut log_manager, self.tx_num, filename, backup)?;
+        log_manager.flush(lsn)?;
+        Ok(lsn)
+    }
+
+    /// bulk_load はファイルの start_block 番目から後に一括でブロックを追加し始めることを記録する
+    /// 追加したブロックの変更はログに記録しないので、ブロックを追加する前に記録をディスクに書き込む
+    pub fn bulk_load(&self, filename: &str, start_block: i32) -> Result<i32> {
+        let mut log_manager = self.log_manager.lock().unwrap();
+        let lsn =
+            BulkLoadRecord::write_to_log(&mut log_manager, self.tx_num, filename, start_block)?;
+        log_manager.flush(lsn)?;
+        Ok(lsn)
+    }
+
     pub fn commit(&mut self) -> Result<()> {
-        self.buffer_manager.lock().unwrap().flush_all(self.tx_num);
+        self.buffer_manager.flush_all(self.tx_num);
         let lm = &mut self.log_manager.lock().unwrap();
         let lsn = CommitRecord::write_to_log(lm, self.tx_num)?;
         lm.flush(lsn)?;
@@ -63,7 +160,7 @@ impl RecoveryManager {
 
     pub fn rollback(&mut self, tx: &mut Transaction) -> Result<()> {
         self.do_rollback(tx)?;
-        self.buffer_manager.lock().unwrap().flush_all(self.tx_num);
+        self.buffer_manager.flush_all(self.tx_num);
         let lm = &mut self.log_manager.lock().unwrap();
         let lsn = CommitRecord::write_to_log(lm, self.tx_num)?;
         lm.flush(lsn)?;
@@ -124,9 +221,9 @@ impl RecoveryManager {
     ///     2. `SETSTRING 1` レコードをロールバック
     ///     3. `SETINT 1` レコードをロールバック
     fn do_rollback(&mut self, tx: &mut Transaction) -> Result<()> {
-        let iter = self.log_manager.lock().unwrap().iter();
+        let iter = self.log_manager.lock().unwrap().iter()?;
         for bytes in iter {
-            let mut record = create_log_record(&bytes)?;
+            let mut record = create_log_record(&bytes?)?;
             if record.tx_number() == self.tx_num {
                 if record.op() == LogRecordType::Start {
                     break;
@@ -137,34 +234,111 @@ impl RecoveryManager {
         Ok(())
     }
 
-    pub fn recover(&mut self, tx: &mut Transaction) -> Result<()> {
-        self.do_recover(tx)?;
-        self.buffer_manager.lock().unwrap().flush_all(self.tx_num);
+    /// recover はリカバリ処理を行い、読んだログレコードの数と LSN を PROGRESS_INTERVAL ごとと最後に progress に知らせる
+    pub fn recover(
+        &mut self,
+        tx: &mut Transaction,
+        progress: &mut dyn FnMut(RecoveryProgress),
+    ) -> Result<RecoveryReport> {
+        let report = scan_log(&self.log_manager, Some(tx), progress)?;
+        self.buffer_manager.flush_all(self.tx_num);
         let lm = &mut self.log_manager.lock().unwrap();
         let lsn = CommitRecord::write_to_log(lm, self.tx_num)?;
         lm.flush(lsn)?;
-        Ok(())
+        Ok(report)
     }
+}
 
-    /// do_recover はリカバリ処理を行います
-    /// ログを逆順に読み取り、コミット済みとロールバック済み以外のトランザクションをロールバックします
-    fn do_recover(&mut self, tx: &mut Transaction) -> Result<()> {
-        let mut finished = HashMap::new();
-        let iter = self.log_manager.lock().unwrap().iter();
-        for bytes in iter {
-            let mut record = create_log_record(&bytes)?;
-            match record.op() {
-                LogRecordType::Checkpoint => break,
-                LogRecordType::Commit | LogRecordType::Rollback => {
-                    finished.insert(record.tx_number(), true);
-                }
-                _ => {
-                    if !finished.contains_key(&record.tx_number()) {
-                        record.undo(tx)?;
+/// scan_log はリカバリ処理を行います
+/// ログを逆順に読み取り、コミット済みとロールバック済み以外のトランザクションをロールバックします
+/// コミット済みのトランザクションのレコードは、コミットの後に行うはずだった後始末を行います
+///
+/// tx が None の場合は何も取り消さずに、取り消すはずのログレコードを報告するだけにする
+pub fn scan_log(
+    log_manager: &Arc<Mutex<LogManager>>,
+    mut tx: Option<&mut Transaction>,
+    progress: &mut dyn FnMut(RecoveryProgress),
+) -> Result<RecoveryReport> {
+    let mut report = RecoveryReport::default();
+    // コミットしたトランザクションは true、ロールバックしたトランザクションは false
+    let mut finished = HashMap::new();
+    // リカバリを行うトランザクション自身は取り消さない
+    if let Some(tx) = &tx {
+        finished.insert(tx.tx_num(), true);
+    }
+    let mut incomplete = HashSet::new();
+    let (iter, latest_lsn) = {
+        let mut log_manager = log_manager.lock().unwrap();
+        (log_manager.iter()?, log_manager.latest_lsn())
+    };
+    let mut lsn = latest_lsn + 1;
+    for bytes in iter {
+        let mut record = create_log_record(&bytes?)?;
+        lsn -= 1;
+        report.records += 1;
+        if report.records % PROGRESS_INTERVAL == 0 {
+            progress(RecoveryProgress {
+                records: report.records,
+                lsn,
+            });
+        }
+        match record.op() {
+            LogRecordType::Checkpoint => {
+                report.checkpoint = Some(lsn);
+                break;
+            }
+            LogRecordType::Commit => {
+                finished.insert(record.tx_number(), true);
+            }
+            LogRecordType::Rollback => {
+                finished.insert(record.tx_number(), false);
+            }
+            _ => match finished.get(&record.tx_number()) {
+                None => undo(
+                    &mut report,
+                    &mut incomplete,
+                    lsn,
+                    &mut record,
+                    tx.as_deref_mut(),
+                )?,
+                Some(true) => {
+                    if let Some(tx) = tx.as_deref_mut() {
+                        record.cleanup(tx)?;
                     }
                 }
-            }
+                Some(false) => {}
+            },
         }
-        Ok(())
     }
+    progress(RecoveryProgress {
+        records: report.records,
+        lsn,
+    });
+    Ok(report)
+}
+
+/// undo はログレコードを取り消して report に記録する。tx が None の場合は記録だけする
+fn undo(
+    report: &mut RecoveryReport,
+    incomplete: &mut HashSet<i32>,
+    lsn: i32,
+    record: &mut Box<dyn LogRecord>,
+    tx: Option<&mut Transaction>,
+) -> Result<()> {
+    if incomplete.insert(record.tx_number()) {
+        report.incomplete.push(record.tx_number());
+    }
+    // 開始のレコードは取り消す変更を持たない
+    if record.op() == LogRecordType::Start {
+        return Ok(());
+    }
+    report.undo.push(UndoEntry {
+        lsn,
+        tx_num: record.tx_number(),
+        record: record.to_string(),
+    });
+    if let Some(tx) = tx {
+        record.undo(tx)?;
+    }
+    Ok(())
 }
@@ -13,7 +13,7 @@ pub struct RollbackRecord {
 
 impl RollbackRecord {
     pub fn new(page: &mut Page) -> Self {
-        let tx_num = page.get_int(0);
+        let tx_num = page.get_int(I32_SIZE);
         Self { tx_num }
     }
 }
@@ -93,9 +93,13 @@ impl LogRecord for SetIntRecord {
     }
 
     fn undo(&mut self, tx: &mut Transaction) -> Result<()> {
-        tx.pin(&self.block);
+        tx.pin(&self.block)?;
         tx.set_int(&self.block, self.offset, self.value, false)?;
         tx.unpin(&self.block);
         Ok(())
     }
+
+    fn block(&self) -> Option<&BlockId> {
+        Some(&self.block)
+    }
 }
@@ -93,9 +93,13 @@ impl LogRecord for SetStringRecord {
     }
 
     fn undo(&mut self, tx: &mut Transaction) -> Result<()> {
-        tx.pin(&self.block);
+        tx.pin(&self.block)?;
         tx.set_string(&self.block, self.offset, self.value.clone(), false)?;
         tx.unpin(&self.block);
         Ok(())
     }
+
+    fn block(&self) -> Option<&BlockId> {
+        Some(&self.block)
+    }
 }
@@ -13,7 +13,7 @@ pub struct StartRecord {
 
 impl StartRecord {
     pub fn new(page: &mut Page) -> Self {
-        let tx_num = page.get_int(0);
+        let tx_num = page.get_int(I32_SIZE);
         Self { tx_num }
     }
 }
@@ -40,7 +40,7 @@ impl LogRecord for StartRecord {
 
 impl StartRecord {
     pub fn write_to_log(log_manager: &mut LogManager, tx_num: i32) -> Result<()> {
-        let record = vec![0; I32_SIZE];
+        let record = vec![0; 2 * I32_SIZE];
         let mut page: Page = record.into();
         page.set_int(0, LogRecordType::Start as i32);
         page.set_int(I32_SIZE, tx_num);
@@ -0,0 +1,88 @@
+use crate::{
+    file::page::Page, log::log_manager::LogManager, tx::transaction::Transaction, I32_SIZE,
+};
+use anyhow::Result;
+
+use super::record::{LogRecord, LogRecordType};
+
+/// TruncateRecord はファイルを空にしたことを記録する論理的なログレコード
+///
+/// ファイルを空にする前の内容は backup の名前のファイルに残しておき、取り消すときに元の名前に戻す
+/// ブロックごとの古い値を記録しないので、ファイルの大きさによらずログレコードは1つで済む
+pub struct TruncateRecord {
+    tx_num: i32,
+    filename: String,
+    backup: String,
+}
+
+impl std::fmt::Display for TruncateRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<TRUNCATE {} {} {}>",
+            self.tx_num, self.filename, self.backup
+        )
+    }
+}
+
+impl TruncateRecord {
+    pub fn new(page: &mut Page) -> Self {
+        let tpos = I32_SIZE;
+        let tx_num = page.get_int(tpos);
+
+        let fpos = tpos + I32_SIZE;
+        let filename = page.get_string(fpos);
+
+        let bpos = fpos + Page::max_length(filename.len());
+        let backup = page.get_string(bpos);
+
+        Self {
+            tx_num,
+            filename,
+            backup,
+        }
+    }
+
+    /// Write a truncate record to the log
+    /// log record is formatted as follows:
+    /// ```markdown
+    /// | Type      | txnum     | filename length   | filename       | backup length   | backup         |
+    /// | --------- | --------- | ----------------- | -------------- | --------------- | -------------- |
+    /// | 4 bytes   | 4 bytes   | 4 bytes           | length bytes   | 4 bytes         | length bytes   |
+    /// ```
+    pub fn write_to_log(
+        log_manager: &mut LogManager,
+        tx_num: i32,
+        filename: &str,
+        backup: &str,
+    ) -> Result<i32> {
+        let tpos = I32_SIZE;
+        let fpos = tpos + I32_SIZE;
+        let bpos = fpos + Page::max_length(filename.len());
+        let record_len = bpos + Page::max_length(backup.len());
+        let mut page = Page::new(record_len as i32);
+        page.set_int(0, LogRecordType::Truncate as i32);
+        page.set_int(tpos, tx_num);
+        page.set_string(fpos, filename);
+        page.set_string(bpos, backup);
+        log_manager.append(page.contents())
+    }
+}
+
+impl LogRecord for TruncateRecord {
+    fn op(&self) -> LogRecordType {
+        LogRecordType::Truncate
+    }
+
+    fn tx_number(&self) -> i32 {
+        self.tx_num
+    }
+
+    fn undo(&mut self, tx: &mut Transaction) -> Result<()> {
+        tx.restore_file(&self.filename, &self.backup)
+    }
+
+    fn cleanup(&mut self, tx: &mut Transaction) -> Result<()> {
+        tx.remove_backup(&self.backup)
+    }
+}
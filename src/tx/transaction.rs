@@ -1,38 +1,71 @@
 use anyhow::{bail, Result};
-use std::sync::{
-    atomic::{AtomicI32, Ordering},
-    Arc, Condvar, Mutex,
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Condvar, Mutex,
+    },
 };
 
 use crate::{
-    buffer::buffer_manager::BufferManager,
-    file::{block::BlockId, file_manager::FileManager},
+    buffer::{
+        buffer::Buffer,
+        buffer_manager::BufferManager,
+        buffer_ring::{BufferRing, BULK_RING_SIZE},
+    },
+    file::{
+        block::BlockId, date::Date, file_manager::FileManager, page::Page,
+        temp_file_manager::TempFileManager,
+    },
     log::log_manager::LogManager,
+    metadata::{
+        catalog,
+        catalog_cache::{CatalogCache, INDEX_CATALOGS, LAYOUT_CATALOGS},
+        metadata_snapshot::MetadataSnapshot,
+    },
 };
 
 use super::{
     buffer_list::BufferList,
+    commit_listener::{CommitEvent, CommitListeners, PreCommitHooks},
     concurrency::{concurrency_manager::ConcurrencyManager, lock_table::LockTable},
-    recovery::recovery_manager::RecoveryManager,
+    recovery::recovery_manager::{RecoveryManager, RecoveryProgress, RecoveryReport},
+    undo_verifier::{first_difference, UndoVerifier},
 };
 
 static NEXT_TX_NUM: AtomicI32 = AtomicI32::new(0);
 
-#[derive(Debug, Clone)]
+/// Transaction は複製できない。スキャンやプランナーは Arc<Mutex<Transaction>> で同じトランザクションを共有するので、
+/// どのスキャンで書き込んだ変更も、同じトランザクションのほかのスキャンからコミットする前に読める
+#[derive(Debug)]
 pub struct Transaction {
     recovery_manager: Arc<Mutex<RecoveryManager>>,
     concurrency_manager: ConcurrencyManager,
-    buffer_manager: Arc<Mutex<BufferManager>>,
+    buffer_manager: Arc<BufferManager>,
     file_manager: Arc<Mutex<FileManager>>,
     tx_num: i32,
     buffer_list: Arc<Mutex<BufferList>>,
+    modified_files: Arc<Mutex<BTreeSet<String>>>, // files modified by this transaction
+    truncated_files: Arc<Mutex<Vec<String>>>,     // backups of files truncated by this transaction
+    bulk_loaded: Arc<Mutex<HashMap<String, i32>>>, // first block appended by a bulk operation per file
+    bulk: bool,                                    // true while running a bulk operation
+    commit_listeners: CommitListeners,             // listeners shared with the database
+    commit_hooks: CommitListeners,                 // listeners only for this transaction
+    pre_commit_hooks: PreCommitHooks,              // hooks called before this transaction commits
+    user: Option<String>,                          // None means the administrator
+    // catalog metadata read by this transaction
+    metadata_snapshot: Arc<Mutex<MetadataSnapshot>>,
+    // catalog metadata shared with the database
+    catalog_cache: Arc<CatalogCache>,
+    // pages before the first logged write, kept only with the verify-undo feature
+    undo_verifier: Arc<Mutex<UndoVerifier>>,
 }
 
 impl Transaction {
     pub fn new(
         file_manager: Arc<Mutex<FileManager>>,
         log_manager: Arc<Mutex<LogManager>>,
-        buffer_manager: Arc<Mutex<BufferManager>>,
+        buffer_manager: Arc<BufferManager>,
         lock_table: Arc<(Mutex<LockTable>, Condvar)>,
     ) -> Result<Self> {
         let tx_num = NEXT_TX_NUM.fetch_add(1, Ordering::SeqCst);
@@ -48,60 +81,306 @@ impl Transaction {
             file_manager,
             tx_num,
             buffer_list,
+            modified_files: Default::default(),
+            truncated_files: Default::default(),
+            bulk_loaded: Default::default(),
+            bulk: false,
+            commit_listeners: Default::default(),
+            commit_hooks: Default::default(),
+            pre_commit_hooks: Default::default(),
+            user: None,
+            metadata_snapshot: Default::default(),
+            catalog_cache: Default::default(),
+            undo_verifier: Default::default(),
         })
     }
 
+    pub fn tx_num(&self) -> i32 {
+        self.tx_num
+    }
+
+    /// set_user はこのトランザクションで文を実行するユーザーを設定する
+    /// None の場合は管理者として、権限を確認せずに実行する
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    pub fn user(&self) -> Option<String> {
+        self.user.clone()
+    }
+
+    /// disable_locks はこのトランザクションでロックを取らないようにする
+    /// ほかのトランザクションと同時に使わない場合だけ使うこと
+    pub fn disable_locks(&mut self) {
+        self.concurrency_manager.release();
+        self.concurrency_manager = ConcurrencyManager::disabled();
+    }
+
+    /// metadata_snapshot はこのトランザクションがカタログから読み込んだメタデータを返す
+    pub fn metadata_snapshot(&self) -> Arc<Mutex<MetadataSnapshot>> {
+        self.metadata_snapshot.clone()
+    }
+
+    /// set_catalog_cache はデータベース全体で共有するカタログのキャッシュを設定する
+    pub fn set_catalog_cache(&mut self, catalog_cache: Arc<CatalogCache>) {
+        self.catalog_cache = catalog_cache;
+    }
+
+    /// catalog_cache はカタログのキャッシュを返す
+    /// このトランザクションがカタログを変更した場合は、キャッシュはコミットしたカタログと合わないので None を返す
+    pub fn catalog_cache(&self) -> Option<Arc<CatalogCache>> {
+        (!self.modified_catalog()).then(|| self.catalog_cache.clone())
+    }
+
+    /// modified_catalog はこのトランザクションがキャッシュするメタデータのカタログを変更したかを返す
+    fn modified_catalog(&self) -> bool {
+        let modified_files = self.modified_files.lock().unwrap();
+        LAYOUT_CATALOGS
+            .iter()
+            .chain(&INDEX_CATALOGS)
+            .any(|catalog| modified_files.contains(&catalog::file_name(catalog)))
+    }
+
+    /// s_lock_file はファイルのすべてのブロックとファイルの末尾の共有ロックを取る
+    /// ファイルを先頭から最後まで読むスキャンと同じロックを、ブロックを読まずに取るのに使う
+    /// ファイルがなければ何もしない
+    pub fn s_lock_file(&mut self, filename: &str) -> Result<()> {
+        if !self.file_exists(filename) {
+            return Ok(());
+        }
+        let size = self.size(filename.to_string())?;
+        for num in 0..size as i32 {
+            self.concurrency_manager
+                .s_lock(&BlockId::new(filename.to_string(), num))?;
+        }
+        Ok(())
+    }
+
+    /// set_commit_listeners はデータベース全体のコミットリスナーを設定する
+    pub fn set_commit_listeners(&mut self, listeners: CommitListeners) {
+        self.commit_listeners = listeners;
+    }
+
+    /// on_commit はこのトランザクションがコミットした後に呼び出すコールバックを登録する
+    pub fn on_commit(&mut self, callback: impl Fn(&CommitEvent) + Send + Sync + 'static) {
+        self.commit_hooks.add(callback);
+    }
+
+    /// before_commit はこのトランザクションがコミットする前に呼び出すフックを登録する
+    /// フックにはこのトランザクションと同じロックとバッファを使うトランザクションが渡される
+    /// ロールバックした場合は呼び出さずに捨てる
+    pub fn before_commit(
+        &mut self,
+        hook: impl FnOnce(Arc<Mutex<Transaction>>) -> Result<()> + Send + 'static,
+    ) {
+        self.pre_commit_hooks.add(hook);
+    }
+
+    /// commit はログをディスクに書き込んでからロックを解放し、コミットリスナーを呼び出す
+    /// コミットの前に呼び出すフックが失敗した場合は、ロールバックしてエラーを返す
     pub fn commit(&mut self) -> Result<()> {
+        if let Err(err) = self.run_pre_commit_hooks() {
+            self.rollback()?;
+            return Err(err);
+        }
+        // スキャンを閉じ忘れるとピンが残るので、テストで早く気づけるようにする
+        debug_assert!(
+            self.pins().is_empty(),
+            "transaction {} leaked pins: {:?}",
+            self.tx_num,
+            self.pins()
+        );
         self.recovery_manager.lock().unwrap().commit()?;
-        println!("transaction {} committed", self.tx_num);
+        // ロックを解放すると他のトランザクションが新しいカタログを読めるので、その前に古いメタデータを捨てる
+        if self.modified_catalog() {
+            self.catalog_cache.invalidate();
+        }
         self.concurrency_manager.release();
         self.buffer_list.lock().unwrap().unpin_all();
+        self.remove_temp_files()?;
+        self.remove_truncated_files()?;
+        self.bulk_loaded.lock().unwrap().clear();
+        self.metadata_snapshot.lock().unwrap().clear();
+        self.undo_verifier.lock().unwrap().take();
+
+        let files = std::mem::take(&mut *self.modified_files.lock().unwrap());
+        let event = CommitEvent::from_files(self.tx_num, &files);
+        self.commit_listeners.notify(&event);
+        self.commit_hooks.notify(&event);
         Ok(())
     }
 
+    /// run_pre_commit_hooks はコミットの前に呼び出すフックを登録した順に呼び出す
+    /// フックの中で登録したフックも、なくなるまで続けて呼び出す
+    fn run_pre_commit_hooks(&mut self) -> Result<()> {
+        let mut hooks = self.pre_commit_hooks.take();
+        if hooks.is_empty() {
+            return Ok(());
+        }
+        let tx = Arc::new(Mutex::new(self.lend()));
+        let mut result = Ok(());
+        while !hooks.is_empty() && result.is_ok() {
+            result = hooks.into_iter().try_for_each(|hook| hook(tx.clone()));
+            hooks = self.pre_commit_hooks.take();
+        }
+        // フックの中で取ったロックはこのトランザクションのものとして引き継ぎ、コミットで解放する
+        self.reclaim(&mut tx.lock().unwrap());
+        result
+    }
+
+    /// lend は Arc<Mutex<Transaction>> を受け取る処理に、このトランザクションとして使わせるハンドルを返す
+    ///
+    /// ハンドルはバッファリストやログ、変更したファイルの記録などをこのトランザクションと共有するので、
+    /// どちらで書き込んでも、まだコミットしていない変更をもう一方から読める
+    /// ロックの記録は共有できないのでハンドルに移す。貸している間はこのトランザクションを使わず、
+    /// 終わったら reclaim でロックの記録を戻すこと
+    fn lend(&mut self) -> Transaction {
+        let disabled = ConcurrencyManager::disabled();
+        Transaction {
+            recovery_manager: self.recovery_manager.clone(),
+            concurrency_manager: std::mem::replace(&mut self.concurrency_manager, disabled),
+            buffer_manager: self.buffer_manager.clone(),
+            file_manager: self.file_manager.clone(),
+            tx_num: self.tx_num,
+            buffer_list: self.buffer_list.clone(),
+            modified_files: self.modified_files.clone(),
+            truncated_files: self.truncated_files.clone(),
+            bulk_loaded: self.bulk_loaded.clone(),
+            bulk: self.bulk,
+            commit_listeners: self.commit_listeners.clone(),
+            commit_hooks: self.commit_hooks.clone(),
+            pre_commit_hooks: self.pre_commit_hooks.clone(),
+            user: self.user.clone(),
+            metadata_snapshot: self.metadata_snapshot.clone(),
+            catalog_cache: self.catalog_cache.clone(),
+            undo_verifier: self.undo_verifier.clone(),
+        }
+    }
+
+    /// reclaim は lend で貸したハンドルからロックの記録を戻す
+    /// 戻した後のハンドルはロックを取らないので、使い続けてはいけない
+    fn reclaim(&mut self, handle: &mut Transaction) {
+        let disabled = ConcurrencyManager::disabled();
+        self.concurrency_manager = std::mem::replace(&mut handle.concurrency_manager, disabled);
+        self.bulk = handle.bulk;
+    }
+
     pub fn rollback(&mut self) -> Result<()> {
-        self.recovery_manager
-            .lock()
-            .unwrap()
-            .rollback(&mut self.clone())?;
-        println!("transaction {} rolled back", self.tx_num);
+        drop(self.pre_commit_hooks.take());
+        // 取り消しはこのトランザクション自身で行い、取り消しで取ったロックもこのトランザクションが持つ
+        let recovery_manager = self.recovery_manager.clone();
+        recovery_manager.lock().unwrap().rollback(self)?;
+        self.verify_undo()?;
+        self.modified_files.lock().unwrap().clear();
+        // 空にしたファイルは取り消しで元に戻したので、残しておいた内容はもうない
+        self.truncated_files.lock().unwrap().clear();
+        self.bulk_loaded.lock().unwrap().clear();
+        // 取り消しでカタログも元に戻るので、読み込んだメタデータも捨てる
+        self.metadata_snapshot.lock().unwrap().clear();
         self.concurrency_manager.release();
         self.buffer_list.lock().unwrap().unpin_all();
+        self.remove_temp_files()?;
+        Ok(())
+    }
+
+    /// verify_undo は verify-undo 機能を有効にしている場合に、取り消したブロックを読み直して
+    /// 最初に書き込む前の内容に戻ったかを確かめ、戻っていなければパニックする
+    /// 一時ファイルと、一括処理の取り消しでファイルから切り落としたブロックは比べない
+    fn verify_undo(&mut self) -> Result<()> {
+        let snapshots = self.undo_verifier.lock().unwrap().take();
+        for (block, expected) in snapshots {
+            if TempFileManager::is_temp_file(&block.filename) || !self.file_exists(&block.filename)
+            {
+                continue;
+            }
+            let size = self
+                .file_manager
+                .lock()
+                .unwrap()
+                .block_count(&block.filename)?;
+            if block.num as u64 >= size {
+                continue;
+            }
+            self.pin(&block)?;
+            let actual = self.read_bytes(&block, 0, expected.len() as i32);
+            self.unpin(&block);
+            if let Some(offset) = first_difference(&expected, &actual?) {
+                panic!(
+                    "transaction {} did not undo {} at offset {}",
+                    self.tx_num, block, offset
+                );
+            }
+        }
         Ok(())
     }
 
     pub fn recover(&mut self) -> Result<()> {
-        self.buffer_manager.lock().unwrap().flush_all(self.tx_num);
-        self.recovery_manager
+        self.recover_with_progress(&mut |_| {})?;
+        Ok(())
+    }
+
+    /// recover_with_progress はリカバリ処理を行い、ログを読んだ進み具合を progress に知らせる
+    pub fn recover_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(RecoveryProgress),
+    ) -> Result<RecoveryReport> {
+        self.buffer_manager.flush_all(self.tx_num);
+        // 取り消しのために取ったロックはこのトランザクションが持ち、コミットで解放する
+        let recovery_manager = self.recovery_manager.clone();
+        let report = recovery_manager.lock().unwrap().recover(self, progress)?;
+        Ok(report)
+    }
+
+    /// set_bulk は一括の読み込みや書き込みを行う間 true にする
+    /// true の間は新しいブロックに少数のバッファを使い回し、バッファプールのほかのページを追い出さない
+    ///
+    /// true の間にファイルに追加したブロックの変更は、値ごとにはログに記録しない
+    /// 代わりにファイルごとに最初に追加するブロックを BulkLoadRecord に記録し、取り消すときはファイルをそのブロックの前まで短くする
+    pub fn set_bulk(&mut self, bulk: bool) {
+        self.bulk = bulk;
+        let ring = bulk.then(|| BufferRing::new(BULK_RING_SIZE));
+        self.buffer_list.lock().unwrap().set_ring(ring);
+    }
+
+    /// is_bulk_loaded はブロックがこのトランザクションの一括処理で追加したブロックかどうかを返す
+    /// 追加したブロックはファイルの末尾のロックで他のトランザクションから守られているので、変更をログに記録しなくてよい
+    fn is_bulk_loaded(&self, block: &BlockId) -> bool {
+        self.bulk_loaded
             .lock()
             .unwrap()
-            .recover(&mut self.clone())?;
-        Ok(())
+            .get(&block.filename)
+            .is_some_and(|start| block.num >= *start)
     }
 
-    pub fn pin(&mut self, block: &BlockId) {
-        self.buffer_list.lock().unwrap().pin(block).unwrap();
+    pub fn pin(&mut self, block: &BlockId) -> Result<()> {
+        self.buffer_list.lock().unwrap().pin(block)
     }
 
     pub fn unpin(&mut self, block: &BlockId) {
         self.buffer_list.lock().unwrap().unpin(block).unwrap();
     }
 
-    pub fn get_int(&mut self, block: &BlockId, offset: i32) -> i32 {
-        self.concurrency_manager.s_lock(block).unwrap();
+    /// get_int は共有ロックを取ってからブロックの整数を読み込む
+    /// ロックを待つ間にタイムアウトした場合はエラーを返す
+    pub fn get_int(&mut self, block: &BlockId, offset: i32) -> Result<i32> {
+        self.concurrency_manager.s_lock(block)?;
 
         let buffers = self.buffer_list.lock().unwrap();
-        let buffer = buffers.get_buffer(block).unwrap();
-        let mut buffer = buffer.lock().unwrap();
-        buffer.contents_mut().get_int(offset as usize)
+        let Some(buffer) = buffers.get_buffer(block) else {
+            bail!("buffer not found");
+        };
+        let buffer = buffer.lock().unwrap();
+        Ok(buffer.contents().get_int(offset as usize))
     }
 
-    pub fn get_string(&mut self, block: &BlockId, offset: i32) -> String {
-        self.concurrency_manager.s_lock(block).unwrap();
+    pub fn get_string(&mut self, block: &BlockId, offset: i32) -> Result<String> {
+        self.concurrency_manager.s_lock(block)?;
         let buffers = self.buffer_list.lock().unwrap();
-        let buffer = buffers.get_buffer(block).unwrap();
-        let mut buffer = buffer.lock().unwrap();
-        buffer.contents_mut().get_string(offset as usize)
+        let Some(buffer) = buffers.get_buffer(block) else {
+            bail!("buffer not found");
+        };
+        let buffer = buffer.lock().unwrap();
+        Ok(buffer.contents().get_string(offset as usize))
     }
 
     pub fn set_int(
@@ -112,6 +391,7 @@ impl Transaction {
         ok_to_log: bool,
     ) -> Result<()> {
         self.concurrency_manager.x_lock(block)?;
+        let ok_to_log = ok_to_log && !self.is_bulk_loaded(block);
 
         let buffer_list = self.buffer_list.lock().unwrap();
         let Some(buffer) = buffer_list.get_buffer(block) else {
@@ -121,6 +401,10 @@ impl Transaction {
         let mut buffer = buffer.lock().unwrap();
         let mut lsn = -1;
         if ok_to_log {
+            self.undo_verifier
+                .lock()
+                .unwrap()
+                .record(block, buffer.contents());
             lsn = self
                 .recovery_manager
                 .lock()
@@ -130,6 +414,7 @@ impl Transaction {
         let page = buffer.contents_mut();
         page.set_int(offset as usize, value);
         buffer.set_modified(self.tx_num, lsn);
+        self.record_modified(block);
         Ok(())
     }
 
@@ -140,7 +425,8 @@ impl Transaction {
         value: String,
         ok_to_log: bool,
     ) -> Result<()> {
-        self.concurrency_manager.x_lock(block).unwrap();
+        self.concurrency_manager.x_lock(block)?;
+        let ok_to_log = ok_to_log && !self.is_bulk_loaded(block);
 
         let buffer_list = self.buffer_list.lock().unwrap();
         let Some(buffer) = buffer_list.get_buffer(block) else {
@@ -150,6 +436,10 @@ impl Transaction {
         let mut buffer = buffer.lock().unwrap();
         let mut lsn = -1;
         if ok_to_log {
+            self.undo_verifier
+                .lock()
+                .unwrap()
+                .record(block, buffer.contents());
             lsn = self
                 .recovery_manager
                 .lock()
@@ -160,6 +450,174 @@ impl Transaction {
         let page = buffer.contents_mut();
         page.set_string(offset as usize, &value);
         buffer.set_modified(self.tx_num, lsn);
+        self.record_modified(block);
+        Ok(())
+    }
+
+    /// read_bytes は指定したオフセットから len バイトをそのまま読み込む
+    pub fn read_bytes(&mut self, block: &BlockId, offset: i32, len: i32) -> Result<Vec<u8>> {
+        self.concurrency_manager.s_lock(block)?;
+        let buffers = self.buffer_list.lock().unwrap();
+        let Some(buffer) = buffers.get_buffer(block) else {
+            bail!("buffer not found");
+        };
+        let buffer = buffer.lock().unwrap();
+        buffer.contents().read_bytes(offset as usize, len as usize)
+    }
+
+    /// write_bytes は指定したオフセットにバイト列をそのまま書き込む
+    /// ログには書き込む前のバイト列が記録される
+    pub fn write_bytes(
+        &mut self,
+        block: &BlockId,
+        offset: i32,
+        value: &[u8],
+        ok_to_log: bool,
+    ) -> Result<()> {
+        self.concurrency_manager.x_lock(block)?;
+        let ok_to_log = ok_to_log && !self.is_bulk_loaded(block);
+
+        let buffer_list = self.buffer_list.lock().unwrap();
+        let Some(buffer) = buffer_list.get_buffer(block) else {
+            bail!("buffer not found");
+        };
+
+        let mut buffer = buffer.lock().unwrap();
+        let mut lsn = -1;
+        if ok_to_log {
+            self.undo_verifier
+                .lock()
+                .unwrap()
+                .record(block, buffer.contents());
+            lsn = self.recovery_manager.lock().unwrap().write_bytes(
+                &mut buffer,
+                offset,
+                value.len(),
+            )?;
+        }
+        let page = buffer.contents_mut();
+        page.write_bytes(offset as usize, value)?;
+        buffer.set_modified(self.tx_num, lsn);
+        self.record_modified(block);
+        Ok(())
+    }
+
+    pub fn get_long(&mut self, block: &BlockId, offset: i32) -> Result<i64> {
+        self.read_value(block, |page| page.get_long(offset as usize))
+    }
+
+    pub fn get_double(&mut self, block: &BlockId, offset: i32) -> Result<f64> {
+        self.read_value(block, |page| page.get_double(offset as usize))
+    }
+
+    pub fn get_bool(&mut self, block: &BlockId, offset: i32) -> Result<bool> {
+        self.read_value(block, |page| page.get_bool(offset as usize))
+    }
+
+    pub fn get_date(&mut self, block: &BlockId, offset: i32) -> Result<Date> {
+        self.read_value(block, |page| page.get_date(offset as usize))
+    }
+
+    pub fn set_long(
+        &mut self,
+        block: &BlockId,
+        offset: i32,
+        value: i64,
+        ok_to_log: bool,
+    ) -> Result<()> {
+        self.write_value(
+            block,
+            ok_to_log,
+            |rm, buffer| rm.set_long(buffer, offset),
+            |page| page.set_long(offset as usize, value),
+        )
+    }
+
+    pub fn set_double(
+        &mut self,
+        block: &BlockId,
+        offset: i32,
+        value: f64,
+        ok_to_log: bool,
+    ) -> Result<()> {
+        self.write_value(
+            block,
+            ok_to_log,
+            |rm, buffer| rm.set_double(buffer, offset),
+            |page| page.set_double(offset as usize, value),
+        )
+    }
+
+    pub fn set_bool(
+        &mut self,
+        block: &BlockId,
+        offset: i32,
+        value: bool,
+        ok_to_log: bool,
+    ) -> Result<()> {
+        self.write_value(
+            block,
+            ok_to_log,
+            |rm, buffer| rm.set_bool(buffer, offset),
+            |page| page.set_bool(offset as usize, value),
+        )
+    }
+
+    pub fn set_date(
+        &mut self,
+        block: &BlockId,
+        offset: i32,
+        value: Date,
+        ok_to_log: bool,
+    ) -> Result<()> {
+        self.write_value(
+            block,
+            ok_to_log,
+            |rm, buffer| rm.set_date(buffer, offset),
+            |page| page.set_date(offset as usize, value),
+        )
+    }
+
+    /// read_value は共有ロックを取ってから、ブロックのページから値を読み込む
+    fn read_value<T>(&mut self, block: &BlockId, read: impl FnOnce(&Page) -> T) -> Result<T> {
+        self.concurrency_manager.s_lock(block)?;
+        let buffers = self.buffer_list.lock().unwrap();
+        let Some(buffer) = buffers.get_buffer(block) else {
+            bail!("buffer not found");
+        };
+        let buffer = buffer.lock().unwrap();
+        Ok(read(buffer.contents()))
+    }
+
+    /// write_value は排他ロックを取ってから、ブロックのページに値を書き込む
+    /// ok_to_log の場合は書き込む前に log で書き込む前の値をログに記録する
+    fn write_value(
+        &mut self,
+        block: &BlockId,
+        ok_to_log: bool,
+        log: impl FnOnce(&RecoveryManager, &mut Buffer) -> Result<i32>,
+        write: impl FnOnce(&mut Page),
+    ) -> Result<()> {
+        self.concurrency_manager.x_lock(block)?;
+        let ok_to_log = ok_to_log && !self.is_bulk_loaded(block);
+
+        let buffer_list = self.buffer_list.lock().unwrap();
+        let Some(buffer) = buffer_list.get_buffer(block) else {
+            bail!("buffer not found");
+        };
+
+        let mut buffer = buffer.lock().unwrap();
+        let mut lsn = -1;
+        if ok_to_log {
+            self.undo_verifier
+                .lock()
+                .unwrap()
+                .record(block, buffer.contents());
+            lsn = log(&self.recovery_manager.lock().unwrap(), &mut buffer)?;
+        }
+        write(buffer.contents_mut());
+        buffer.set_modified(self.tx_num, lsn);
+        self.record_modified(block);
         Ok(())
     }
 
@@ -173,14 +631,161 @@ impl Transaction {
         file_manager.block_count(&filename)
     }
 
+    /// file_exists は指定したファイルがディスクにあるかどうかを返す
+    /// size と違ってファイルを作らない
+    pub fn file_exists(&self, filename: &str) -> bool {
+        self.file_manager.lock().unwrap().exists(filename)
+    }
+
     /// append は指定したファイルに新しいブロックを追加して、そのブロックのIDを返す
     pub fn append(&mut self, filename: String) -> Result<BlockId> {
+        self.append_blocks(filename, 1)
+    }
+
+    /// append_blocks はファイルに 0 で埋めた count 個のブロックをまとめて追加して、最初のブロックを返す
+    /// ファイルの終端のロックを取るのは1回だけなので、大量に挿入するときに1ブロックずつ追加するより速い
+    pub fn append_blocks(&mut self, filename: String, count: i32) -> Result<BlockId> {
         // 複数のトランザクションが同時に同じファイルにブロックを追加するのを防ぐため
         // ダミーブロックを作成して排他ロックを取得する
         let dummy_block = BlockId::new(filename.clone(), -1);
         self.concurrency_manager.x_lock(&dummy_block)?;
+        if self.bulk && !self.bulk_loaded.lock().unwrap().contains_key(&filename) {
+            let start_block = self.file_manager.lock().unwrap().block_count(&filename)? as i32;
+            self.recovery_manager
+                .lock()
+                .unwrap()
+                .bulk_load(&filename, start_block)?;
+            self.bulk_loaded
+                .lock()
+                .unwrap()
+                .insert(filename.clone(), start_block);
+        }
+        let mut file_manager = self.file_manager.lock().unwrap();
+        file_manager.append_blocks(&filename, count)
+    }
+
+    /// create_temp_file はこのトランザクションの一時ファイルの名前を返す
+    /// 一時ファイルはコミットまたはロールバックの時に削除される
+    pub fn create_temp_file(&mut self) -> String {
+        self.file_manager
+            .lock()
+            .unwrap()
+            .temp_files
+            .create(self.tx_num)
+    }
+
+    /// drop_temp_file は一時ファイルのバッファを破棄してファイルを削除する
+    /// トランザクションの外で使い終わった一時テーブルを削除するときに使う
+    pub fn drop_temp_file(&mut self, name: &str) -> Result<()> {
+        self.buffer_manager.discard_temp_file(name);
+        self.file_manager.lock().unwrap().remove_temp_file(name)
+    }
+
+    fn remove_temp_files(&mut self) -> Result<()> {
+        let names = self
+            .file_manager
+            .lock()
+            .unwrap()
+            .temp_files
+            .release(self.tx_num);
+        for name in names {
+            self.drop_temp_file(&name)?;
+        }
+        Ok(())
+    }
+
+    /// truncate_file はファイルを空にする。ファイル全体の排他ロックを取るので、他のトランザクションが使い終わるまで待つ
+    ///
+    /// 取り消せるように、元の内容は名前を変えたファイルに残して TruncateRecord に記録し、コミットしたときに削除する
+    /// ファイルのブロックをピンしたままでは空にできない
+    pub fn truncate_file(&mut self, filename: &str) -> Result<()> {
+        if !self.file_exists(filename) {
+            return Ok(());
+        }
+        if self.pins().iter().any(|block| block.filename == filename) {
+            bail!("cannot truncate pinned file: {}", filename);
+        }
+        self.concurrency_manager.x_lock_file(filename)?;
+        let dummy_block = BlockId::new(filename.to_string(), -1);
+        self.concurrency_manager.x_lock(&dummy_block)?;
+
+        let backup = {
+            let mut truncated_files = self.truncated_files.lock().unwrap();
+            let backup = format!("~{}_{}_{}", self.tx_num, truncated_files.len(), filename);
+            truncated_files.push(backup.clone());
+            backup
+        };
+        self.buffer_manager.discard_file(filename)?;
+        self.undo_verifier.lock().unwrap().truncated(filename);
+        self.recovery_manager
+            .lock()
+            .unwrap()
+            .truncate(filename, &backup)?;
+        self.file_manager
+            .lock()
+            .unwrap()
+            .rename_file(filename, &backup)?;
+        self.record_modified(&dummy_block);
+        Ok(())
+    }
+
+    /// rename_file はファイルの名前を変える。ログに記録しないので、ロールバックしても元に戻らない
+    /// カタログの移行のように、途中で止まってもやり直せば同じ結果になる処理だけで使う
+    pub fn rename_file(&mut self, from: &str, to: &str) -> Result<()> {
+        self.buffer_manager.discard_file(from)?;
+        self.file_manager.lock().unwrap().rename_file(from, to)
+    }
+
+    /// restore_file は truncate_file で空にしたファイルを、残しておいた元の内容に戻す
+    /// 元の内容がない場合は、ファイルを空にする前に終了したので何もしない
+    pub fn restore_file(&mut self, filename: &str, backup: &str) -> Result<()> {
+        if !self.file_exists(backup) {
+            return Ok(());
+        }
+        self.buffer_manager.discard_file(filename)?;
+        self.file_manager
+            .lock()
+            .unwrap()
+            .rename_file(backup, filename)
+    }
+
+    /// remove_backup は truncate_file で残しておいた元の内容を削除する
+    /// コミットしてから削除するまでの間に止まったときに、リカバリで残った元の内容を消すために使う
+    pub fn remove_backup(&mut self, backup: &str) -> Result<()> {
+        if !self.file_exists(backup) {
+            return Ok(());
+        }
+        self.file_manager.lock().unwrap().remove_file(backup)
+    }
+
+    /// shrink_file は一括処理で追加したブロックを取り消して、ファイルを blocks 個のブロックに戻す
+    /// 取り消すブロックのバッファはディスクに書き込まずに破棄する
+    pub fn shrink_file(&mut self, filename: &str, blocks: i32) -> Result<()> {
+        if !self.file_exists(filename) {
+            return Ok(());
+        }
+        self.buffer_manager.discard_blocks(filename, blocks)?;
+        self.file_manager
+            .lock()
+            .unwrap()
+            .shrink(filename, blocks as u64)
+    }
+
+    /// remove_truncated_files はコミットしたトランザクションが空にしたファイルの元の内容を削除する
+    fn remove_truncated_files(&mut self) -> Result<()> {
+        let backups = std::mem::take(&mut *self.truncated_files.lock().unwrap());
         let mut file_manager = self.file_manager.lock().unwrap();
-        file_manager.append_block(&filename)
+        for backup in backups {
+            file_manager.remove_file(&backup)?;
+        }
+        Ok(())
+    }
+
+    fn record_modified(&self, block: &BlockId) {
+        self.modified_files
+            .lock()
+            .unwrap()
+            .insert(block.filename.clone());
     }
 
     pub fn block_size(&self) -> i32 {
@@ -188,6 +793,12 @@ impl Transaction {
     }
 
     pub fn available_buffers(&self) -> u64 {
-        self.buffer_manager.lock().unwrap().num_available
+        self.buffer_manager.num_available()
+    }
+
+    /// pins はこのトランザクションがピンしたままのブロックを返す
+    /// コミットするまでにすべて解放していないと、デバッグビルドではコミットでパニックする
+    pub fn pins(&self) -> Vec<BlockId> {
+        self.buffer_list.lock().unwrap().pins().to_vec()
     }
 }
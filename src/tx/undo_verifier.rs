@@ -0,0 +1,62 @@
+use crate::file::{block::BlockId, page::Page};
+use std::collections::{HashMap, HashSet};
+
+/// UndoVerifier は verify-undo 機能を有効にしたときに、ロールバックで取り消したブロックが
+/// トランザクションが最初に書き込む前の内容に戻ったかを確かめるためにページを覚えておく
+///
+/// ログに記録する書き込みでブロックに初めて書き込むときに、書き込む前のページを覚える
+/// ログに記録しない書き込み（新しいブロックのフォーマットなど）は取り消さないので、それまでの分は覚えたページに含まれる
+/// 空にしたファイルは取り消しで元の内容をまるごと戻すので、空にした後で初めて書き込むブロックは覚えない
+/// 機能を有効にしていなければ何も覚えないので、ふだんのビルドでは余計なメモリを使わない
+#[derive(Debug, Default)]
+pub struct UndoVerifier {
+    snapshots: HashMap<BlockId, Vec<u8>>,
+    truncated_files: HashSet<String>,
+}
+
+impl UndoVerifier {
+    pub const ENABLED: bool = cfg!(feature = "verify-undo");
+
+    /// record はブロックに初めてログに記録する書き込みをする前のページを覚える
+    pub fn record(&mut self, block: &BlockId, page: &Page) {
+        if Self::ENABLED && !self.truncated_files.contains(&block.filename) {
+            self.snapshots
+                .entry(block.clone())
+                .or_insert_with(|| page.contents().to_vec());
+        }
+    }
+
+    /// truncated はファイルを空にしたことを覚える
+    pub fn truncated(&mut self, filename: &str) {
+        if Self::ENABLED {
+            self.truncated_files.insert(filename.to_string());
+        }
+    }
+
+    /// take は覚えたページをすべて取り出す。コミットとロールバックのたびに呼んで空にする
+    pub fn take(&mut self) -> HashMap<BlockId, Vec<u8>> {
+        self.truncated_files.clear();
+        std::mem::take(&mut self.snapshots)
+    }
+}
+
+/// first_difference は2つのページの内容を比べて、最初に違うバイトの位置を返す
+pub fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| (expected.len() != actual.len()).then(|| expected.len().min(actual.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_find_first_difference() {
+        assert_eq!(first_difference(&[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(first_difference(&[1, 2, 3], &[1, 9, 9]), Some(1));
+        assert_eq!(first_difference(&[1, 2], &[1, 2, 3]), Some(2));
+    }
+}
@@ -6,7 +6,7 @@ use tinydb::{file::block::BlockId, server::db::TinyDB};
 fn buffer_manager_test() -> Result<()> {
     let test_directory = tempdir()?.path().join("buffer_manager_test");
     let db = TinyDB::new(test_directory, 400, 3)?;
-    let mut buffer_manager = db.buffer_manager.lock().unwrap();
+    let buffer_manager = db.buffer_manager.clone();
     let mut buffers = vec![
         buffer_manager.pin(&BlockId::new("testfile".into(), 0))?,
         buffer_manager.pin(&BlockId::new("testfile".into(), 1))?,
@@ -17,7 +17,7 @@ fn buffer_manager_test() -> Result<()> {
         buffer_manager.pin(&BlockId::new("testfile".into(), 1))?,
     ]);
 
-    println!("Available buffers: {}", buffer_manager.num_available);
+    println!("Available buffers: {}", buffer_manager.num_available());
     {
         println!("Attempting to pin block 3...");
         let result = buffer_manager.pin(&BlockId::new("testfile".into(), 3));
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+use tempfile::tempdir;
+
+fn tinydb(dir: &Path, args: &[&str]) -> Result<Output> {
+    Ok(Command::new(env!("CARGO_BIN_EXE_tinydb"))
+        .arg(dir)
+        .args(args)
+        .output()?)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_cli_batch_mode() -> Result<()> {
+    let test_directory = tempdir()?;
+    let dir = test_directory.path().join("db");
+    let script = test_directory.path().join("script.sql");
+    fs::write(
+        &script,
+        "create table T(A int, B varchar(10));\n\
+         insert into T(A, B) values (1, 'a,b');\n\
+         insert into T(A, B) values (2, 'x\"y');\n",
+    )?;
+
+    let output = tinydb(&dir, &["-f", script.to_str().unwrap()])?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "0 records affected\n1 records affected\n1 records affected\n"
+    );
+
+    let output = tinydb(&dir, &["--format=csv", "-c", "select A, B from T"])?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "A,B\n1,\"a,b\"\n2,\"x\"\"y\"\n");
+
+    let output = tinydb(
+        &dir,
+        &[
+            "--format=json",
+            "-c",
+            "select A, B from T where A = 2; delete from T where A = 1",
+        ],
+    )?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "[{\"A\": 2, \"B\": \"x\\\"y\"}]\n{\"updated\": 1}\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_exit_codes() -> Result<()> {
+    let test_directory = tempdir()?;
+    let dir = test_directory.path().join("db");
+    let output = tinydb(&dir, &["-c", "create table T(A int)"])?;
+    assert_eq!(output.status.code(), Some(0));
+
+    // 構文の誤りは 2、実行の失敗は 1 で終了し、失敗した文より後の文は実行しない
+    let output = tinydb(&dir, &["-c", "selec A from T"])?;
+    assert_eq!(output.status.code(), Some(2));
+    let output = tinydb(
+        &dir,
+        &["-c", "select X from T; insert into T(A) values (1)"],
+    )?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error: field not found: X"));
+
+    let output = tinydb(&dir, &["--format=csv", "-c", "select A from T"])?;
+    assert_eq!(stdout(&output), "A\n");
+
+    let output = tinydb(&dir, &["--format=xml", "-c", "select A from T"])?;
+    assert_ne!(output.status.code(), Some(0));
+    Ok(())
+}
@@ -59,16 +59,18 @@ fn concurrency_test() {
                         .unwrap();
                 let block1 = BlockId::new("testfile".into(), 1);
                 let block2 = BlockId::new("testfile".into(), 2);
-                transaction_a.pin(&block1);
-                transaction_a.pin(&block2);
+                transaction_a.pin(&block1).unwrap();
+                transaction_a.pin(&block2).unwrap();
                 println!("Transaction A: request slock 1");
-                transaction_a.get_int(&block1, 0);
+                transaction_a.get_int(&block1, 0).unwrap();
                 println!("Transaction A: receive slock 1");
                 println!("Transaction A: sleep 1000");
                 sleep(Duration::from_millis(1000));
                 println!("Transaction A: request slock 2");
-                transaction_a.get_int(&block2, 0);
+                transaction_a.get_int(&block2, 0).unwrap();
                 println!("Transaction A: receive slock 2");
+                transaction_a.unpin(&block1);
+                transaction_a.unpin(&block2);
                 transaction_a.commit().unwrap();
                 println!("Transaction A: commit");
             }
@@ -89,16 +91,18 @@ fn concurrency_test() {
                         .unwrap();
                 let block1 = BlockId::new("testfile".into(), 1);
                 let block2 = BlockId::new("testfile".into(), 2);
-                transaction_b.pin(&block1);
-                transaction_b.pin(&block2);
+                transaction_b.pin(&block1).unwrap();
+                transaction_b.pin(&block2).unwrap();
                 println!("Transaction B: request xlock 2");
                 transaction_b.set_int(&block2, 0, 0, false).unwrap();
                 println!("Transaction B: receive xlock 2");
                 println!("Transaction B: sleep 1000");
                 sleep(Duration::from_millis(1000));
                 println!("Transaction B: request slock 1");
-                transaction_b.get_int(&block1, 0);
+                transaction_b.get_int(&block1, 0).unwrap();
                 println!("Transaction B: received slock 1");
+                transaction_b.unpin(&block1);
+                transaction_b.unpin(&block2);
                 transaction_b.commit().unwrap();
                 println!("Transaction B: commit");
             }
@@ -119,8 +123,8 @@ fn concurrency_test() {
                         .unwrap();
                 let block1 = BlockId::new("testfile".into(), 1);
                 let block2 = BlockId::new("testfile".into(), 2);
-                transaction_c.pin(&block1);
-                transaction_c.pin(&block2);
+                transaction_c.pin(&block1).unwrap();
+                transaction_c.pin(&block2).unwrap();
                 sleep(Duration::from_millis(500));
                 println!("Transaction C: request xlock 1");
                 transaction_c.set_int(&block1, 0, 0, false).unwrap();
@@ -128,8 +132,10 @@ fn concurrency_test() {
                 println!("Transaction C: sleep 1000");
                 sleep(Duration::from_millis(1000));
                 println!("Transaction C: request slock 2");
-                transaction_c.get_int(&block2, 0);
+                transaction_c.get_int(&block2, 0).unwrap();
                 println!("Transaction C: received slock 2");
+                transaction_c.unpin(&block1);
+                transaction_c.unpin(&block2);
                 transaction_c.commit().unwrap();
                 println!("Transaction C: commit");
             }
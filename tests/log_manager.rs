@@ -46,9 +46,9 @@ fn print_log_records(
 ) -> Result<()> {
     println!("{}", msg);
     let mut index = 0;
-    let iterator = log_manager.iter();
+    let iterator = log_manager.iter()?;
     for bytes in iterator {
-        let mut page = Page::from(bytes);
+        let page = Page::from(bytes?);
         let string = page.get_string(0);
         let number_position = Page::max_length(string.len());
         let value = page.get_int(number_position);
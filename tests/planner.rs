@@ -1,6 +1,42 @@
 use anyhow::Result;
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 use tempfile::tempdir;
-use tinydb::{server::db::TinyDB, unlock};
+use tinydb::{
+    file::control_file::{ControlFile, CONTROL_FILE},
+    index::{
+        hash::{bucket_table, NUM_BUCKETS},
+        IndexOptions, IndexType,
+    },
+    metadata::{
+        catalog::{field_catalog_schema, legacy_table_catalog_schema, CATALOG_VERSION},
+        fake_metadata::FakeMetadata,
+        metadata_manager::MetadataManager,
+        stat_info::StatInfo,
+    },
+    parse::parser::Parser,
+    plan::{
+        basic_query_plan::BasicQueryPlanner, basic_update_planner::BasicUpdatePlanner,
+        better_query_plan::BetterQueryPlanner, materialized_blocks, planner::Planner,
+        product_plan::ProductPlan, project_plan::ProjectPlan, query_planner::QueryPlanner,
+        sort_plan::SortPlan, table_plan::TablePlan, ArcPlan, Plan,
+    },
+    query::{
+        constant::Constant,
+        scan::{Scan as _, ScanDirection},
+    },
+    record::{
+        collation::Collation, layout::Layout, rid::RID, schema::Schema, table_scan::TableScan,
+        truncation::TruncationPolicy,
+    },
+    server::db::TinyDB,
+    tools::dump::dump,
+    tx::transaction::Transaction,
+    unlock,
+};
 
 #[test]
 fn test_planner() -> Result<()> {
@@ -27,6 +63,7 @@ fn test_planner() -> Result<()> {
     scan.next()?;
     let b = scan.get_string("B")?;
     assert_eq!(b, "rec10");
+    scan.close();
 
     let query = "update T set B = 'updated' where A = 10";
     planner.execute_update(query, tx.clone())?;
@@ -38,6 +75,7 @@ fn test_planner() -> Result<()> {
     scan.next()?;
     let b = scan.get_string("B")?;
     assert_eq!(b, "updated");
+    scan.close();
 
     let query = "delete from T where A = 10";
     planner.execute_update(query, tx.clone())?;
@@ -47,8 +85,2552 @@ fn test_planner() -> Result<()> {
     let scan = plan.open()?;
     let mut scan = unlock!(scan);
     assert!(!scan.next()?);
+    scan.close();
 
     unlock!(tx).commit()?;
 
     Ok(())
 }
+
+#[test]
+fn test_planner_rid_virtual_column() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_rid_virtual_column");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(9))", tx.clone())?;
+
+    for i in 0..5 {
+        let query = format!("insert into T(A, B) values ({}, 'rec{}')", i, i);
+        planner.execute_update(&query, tx.clone())?;
+    }
+
+    let query = "select rid, B from T where A = 3";
+    let plan = planner.create_query_plan(query, tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(unlock!(scan).next()?);
+    let rid = unlock!(scan).get_string("rid")?;
+    assert_eq!(rid.parse::<RID>()?, RID::new(0, 3));
+    unlock!(scan).close();
+
+    let query = format!("delete from T where rid = '{}'", rid);
+    assert_eq!(planner.execute_update(&query, tx.clone())?, 1);
+
+    let query = "select B from T where A = 3";
+    let plan = planner.create_query_plan(query, tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(!unlock!(scan).next()?);
+    unlock!(scan).close();
+
+    unlock!(tx).commit()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_planner_large_varchar() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_large_varchar");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(2000))", tx.clone())?;
+
+    // ブロックより大きな値はオーバーフローページに格納される
+    for i in 0..3 {
+        let b = i.to_string().repeat(1500);
+        let query = format!("insert into T(A, B) values ({}, '{}')", i, b);
+        planner.execute_update(&query, tx.clone())?;
+    }
+
+    let plan = planner.create_query_plan("select A, B from T", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut count = 0;
+    while unlock!(scan).next()? {
+        let a = unlock!(scan).get_int("A")?;
+        let b = unlock!(scan).get_string("B")?;
+        assert_eq!(b, a.to_string().repeat(1500));
+        count += 1;
+    }
+    unlock!(scan).close();
+    assert_eq!(count, 3);
+
+    unlock!(tx).commit()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_planner_text_column() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_text_column");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table D(id int, body text)", tx.clone())?;
+
+    let body = "lorem ipsum ".repeat(200);
+    let query = format!("insert into D(id, body) values (1, '{}')", body);
+    planner.execute_update(&query, tx.clone())?;
+
+    let plan = planner.create_query_plan("select body from D where id = 1", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(unlock!(scan).next()?);
+    assert_eq!(unlock!(scan).get_string("body")?, body);
+    unlock!(scan).close();
+
+    unlock!(tx).commit()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_planner_update_reuses_overflow_pages() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_update_reuses_overflow_pages");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let tx = db.transaction()?;
+    unlock!(planner).execute_update("create table D(id int, body text)", tx.clone())?;
+    let query = format!("insert into D(id, body) values (1, '{}')", "a".repeat(2000));
+    unlock!(planner).execute_update(&query, tx.clone())?;
+    unlock!(tx).commit()?;
+    let size = unlock!(db.file_manager).block_count("D.ovf")?;
+
+    // 何度書き換えてもオーバーフローファイルは伸びない
+    for n in 0..10 {
+        let tx = db.transaction()?;
+        let query = format!(
+            "update D set body = '{}' where id = 1",
+            n.to_string().repeat(2000)
+        );
+        unlock!(planner).execute_update(&query, tx.clone())?;
+        unlock!(tx).commit()?;
+    }
+    assert_eq!(unlock!(db.file_manager).block_count("D.ovf")?, size);
+
+    // ロールバックすると使い直したブロックも元の値に戻る
+    let tx = db.transaction()?;
+    let query = format!("update D set body = '{}' where id = 1", "b".repeat(2000));
+    unlock!(planner).execute_update(&query, tx.clone())?;
+    unlock!(tx).rollback()?;
+
+    let tx = db.transaction()?;
+    let plan = unlock!(planner).create_query_plan("select body from D where id = 1", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(unlock!(scan).next()?);
+    assert_eq!(unlock!(scan).get_string("body")?, "9".repeat(2000));
+    unlock!(scan).close();
+    unlock!(tx).commit()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_planner_select_cast() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_select_cast");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table u(x int, s varchar(10))", tx.clone())?;
+    planner.execute_update("insert into u(x, s) values (12345, ' 42 ')", tx.clone())?;
+
+    let plan = planner.create_query_plan(
+        "select cast(x as varchar(3)), cast(s as int), x from u",
+        tx.clone(),
+    )?;
+    let schema = unlock!(plan).schema();
+    assert_eq!(
+        schema.fields,
+        vec!["cast(x as varchar(3))", "cast(s as int)", "x"]
+    );
+    assert_eq!(schema.length("cast(x as varchar(3))"), Some(3));
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    assert!(scan.next()?);
+    assert_eq!(scan.get_string("cast(x as varchar(3))")?, "123");
+    assert_eq!(scan.get_int("cast(s as int)")?, 42);
+    assert_eq!(scan.get_int("x")?, 12345);
+    assert!(!scan.next()?);
+    scan.close();
+
+    unlock!(tx).commit()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_planner_show_indexes() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_show_indexes");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(100))", tx.clone())?;
+    planner.execute_update("create index t_a on T (A)", tx.clone())?;
+    planner.execute_update(
+        "create unique index t_b on T (B(16)) using btree",
+        tx.clone(),
+    )?;
+    assert!(planner
+        .execute_update("create index t_c on T (B(16)) using hash", tx.clone())
+        .is_err());
+
+    let plan = planner.create_query_plan("show indexes on T", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut indexes = vec![];
+    while scan.next()? {
+        indexes.push((
+            scan.get_string("indexname")?,
+            scan.get_string("fieldname")?,
+            scan.get_string("indextype")?,
+            scan.get_int("isunique")?,
+            scan.get_int("prefixlen")?,
+        ));
+    }
+    scan.close();
+    indexes.sort();
+    assert_eq!(
+        indexes,
+        vec![
+            ("t_a".into(), "A".into(), "hash".into(), 0, 0),
+            ("t_b".into(), "B".into(), "btree".into(), 1, 16),
+        ]
+    );
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_unique_index() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_unique_index");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    // 失敗した文はトランザクションごとロールバックする
+    let execute =
+        |query: &str| db.with_transaction(|tx, planner| unlock!(planner).execute_update(query, tx));
+    execute("create table T(A int, B varchar(10))")?;
+    execute("insert into T(A, B) values (1, 'x')")?;
+    execute("insert into T(A, B) values (1, 'y')")?;
+
+    // 既存のレコードが重複していれば一意な索引は作れない
+    assert!(execute("create unique index t_a on T (A)").is_err());
+    execute("delete from T where B = 'y'")?;
+    execute("create unique index t_a on T (A)")?;
+
+    assert!(execute("insert into T(A, B) values (1, 'z')").is_err());
+    execute("insert into T(A, B) values (2, 'z')")?;
+    assert!(execute("update T set A = 1 where B = 'z'").is_err());
+    // 同じ値に変更するのは重複ではない
+    assert_eq!(execute("update T set A = 2 where B = 'z'")?, 1);
+    // 削除したキーは挿入し直せる
+    execute("delete from T where A = 1")?;
+    execute("insert into T(A, B) values (1, 'y')")?;
+
+    let mut rows = db.with_transaction(|tx, planner| {
+        select_literals(&mut unlock!(planner), "select A, B from T", tx)
+    })?;
+    rows.sort();
+    assert_eq!(rows, vec!["1, 'y'", "2, 'z'"]);
+
+    // 先頭だけを格納する索引でも、値全体が等しい場合だけを重複とみなす
+    execute("create table U(B varchar(10))")?;
+    execute("create unique index u_b on U (B(4)) using btree")?;
+    execute("insert into U(B) values ('abcdef')")?;
+    execute("insert into U(B) values ('abcdxx')")?;
+    assert!(execute("insert into U(B) values ('abcdef')").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_planner_show_tables() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_show_tables");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int)", tx.clone())?;
+
+    let plan = planner.create_query_plan("show tables", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut tables = vec![];
+    while scan.next()? {
+        tables.push((
+            scan.get_string("tblname")?,
+            scan.get_int("created")? > 0,
+            scan.get_int("analyzed")? > 0,
+            scan.get_int("numrecs")?,
+        ));
+    }
+    scan.close();
+    // 統計を取り直していないテーブルの analyzed は 0 になる
+    assert_eq!(tables[0], ("tblcat".into(), true, false, 0));
+    assert_eq!(tables.last(), Some(&("T".into(), true, false, 0)));
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_three_table_join_predicates() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_three_table_join_predicates");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(10))", tx.clone())?;
+    planner.execute_update("create table U(C int, D int)", tx.clone())?;
+    planner.execute_update("create table V(E int, F varchar(10))", tx.clone())?;
+    for i in 0..5 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, 'b{}')", i, i),
+            tx.clone(),
+        )?;
+        planner.execute_update(
+            &format!("insert into U(C, D) values ({}, {})", i, i * 10),
+            tx.clone(),
+        )?;
+        planner.execute_update(
+            &format!("insert into V(E, F) values ({}, 'f{}')", i * 10, i),
+            tx.clone(),
+        )?;
+    }
+
+    let query = "select B, F from T, U, V where A = C and D = E and B = 'b2'";
+    let rows = |plan: &ArcPlan| -> Result<Vec<(String, String)>> {
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push((scan.get_string("B")?, scan.get_string("F")?));
+        }
+        scan.close();
+        Ok(rows)
+    };
+
+    // 結合の条件は入力にまたがる直積の上で、テーブルの項はテーブルのすぐ上で評価する
+    let plan = planner.create_query_plan(query, tx.clone())?;
+    let lines: Vec<String> = unlock!(plan)
+        .explain()
+        .iter()
+        .map(|line| line.trim_start().to_string())
+        .collect();
+    assert!(lines[1].starts_with("Select where D = E"), "{:?}", lines);
+    assert!(lines[2].starts_with("Product"), "{:?}", lines);
+    assert!(lines[3].starts_with("Select where A = C"), "{:?}", lines);
+    let filter = lines
+        .iter()
+        .position(|line| line.starts_with("Select where B = 'b2'"))
+        .unwrap();
+    assert!(lines[filter + 1].starts_with("TableScan T"), "{:?}", lines);
+    assert_eq!(
+        lines
+            .iter()
+            .filter(|line| line.starts_with("Select"))
+            .count(),
+        3
+    );
+    assert_eq!(rows(&plan)?, vec![("b2".into(), "f2".into())]);
+
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let mut query_planner = BetterQueryPlanner::new(md);
+    let plan = query_planner.create_plan(Parser::new(query).query()?, tx.clone())?;
+    assert_eq!(rows(&plan)?, vec![("b2".into(), "f2".into())]);
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_hints() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_hints");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(10))", tx.clone())?;
+    planner.execute_update("create table U(C int)", tx.clone())?;
+    for i in 0..20 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, 'b{}')", i % 5, i),
+            tx.clone(),
+        )?;
+    }
+    planner.execute_update("insert into U(C) values (1)", tx.clone())?;
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+
+    let plan = planner.create_query_plan(
+        "select /*+ use_index(T t_a) leading(U T) */ B from T, U where A = 3",
+        tx.clone(),
+    )?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut values = vec![];
+    while scan.next()? {
+        values.push(scan.get_string("B")?);
+    }
+    scan.close();
+    values.sort();
+    assert_eq!(values, vec!["b13", "b18", "b3", "b8"]);
+
+    assert!(planner
+        .create_query_plan(
+            "select /*+ use_index(T t_x) */ B from T where A = 3",
+            tx.clone()
+        )
+        .is_err());
+    assert!(planner
+        .create_query_plan(
+            "select /*+ use_index(T t_a) */ B from T where B = 'b3'",
+            tx.clone()
+        )
+        .is_err());
+    assert!(planner
+        .create_query_plan("select /*+ leading(V) */ B from T", tx.clone())
+        .is_err());
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_backward_scan() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_backward_scan");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int)", tx.clone())?;
+    planner.execute_update("create table U(C int)", tx.clone())?;
+    for i in 0..4 {
+        planner.execute_update(&format!("insert into T(A) values ({})", i), tx.clone())?;
+    }
+    for i in 0..3 {
+        planner.execute_update(&format!("insert into U(C) values ({})", i), tx.clone())?;
+    }
+
+    let plan = planner.create_query_plan("select A, C from T, U where C = 1", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut rows = |direction| -> Result<Vec<(i32, i32)>> {
+        let mut rows = vec![];
+        scan.rewind(direction);
+        while scan.advance(direction)? {
+            rows.push((scan.get_int("A")?, scan.get_int("C")?));
+        }
+        Ok(rows)
+    };
+    let forward = rows(ScanDirection::Forward)?;
+    let mut backward = rows(ScanDirection::Backward)?;
+    assert_eq!(forward.len(), 4);
+    backward.reverse();
+    assert_eq!(forward, backward);
+    scan.close();
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_normalizes_predicate() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_normalizes_predicate");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B int)", tx.clone())?;
+    for i in 0..3 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, {})", i, i),
+            tx.clone(),
+        )?;
+    }
+
+    let mut count = |query: &str| -> Result<(i32, usize)> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let blocks = unlock!(plan).blocks_accessed();
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut count = 0;
+        while scan.next()? {
+            count += 1;
+        }
+        scan.close();
+        Ok((blocks, count))
+    };
+    assert_eq!(count("select A from T where 1 = 1")?.1, 3);
+    assert_eq!(count("select A from T where A = 1 and 1 = 1")?.1, 1);
+    assert_eq!(count("select A from T where A = 1 and A = 2")?, (0, 0));
+    assert_eq!(
+        count("select A from T where A = B and A = 1 and B = 2")?,
+        (0, 0)
+    );
+    assert_eq!(count("select A from T where 1 = 2")?, (0, 0));
+    assert_eq!(count("select A from T where A = null")?, (0, 0));
+    assert_eq!(count("select A from T where null = null")?, (0, 0));
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_update_unknown_column() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_update_unknown_column");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(9))", tx.clone())?;
+    planner.execute_update("insert into T(A, B) values (1, 'one')", tx.clone())?;
+
+    let mut error = |query: &str| -> String {
+        match planner.execute_update(query, tx.clone()) {
+            Ok(_) => panic!("should fail: {}", query),
+            Err(e) => e.to_string(),
+        }
+    };
+    assert_eq!(
+        error("delete from T where C = 1"),
+        "unknown column: C in table T"
+    );
+    assert_eq!(
+        error("update T set B = 'x' where A = 1 and D = 'y'"),
+        "unknown column: D in table T"
+    );
+    assert_eq!(
+        error("update T set C = 2 where A = 1"),
+        "unknown column: C in table T"
+    );
+    assert_eq!(error("update T set A = C"), "unknown column: C in table T");
+
+    // 失敗した文はレコードを変えていない
+    assert_eq!(
+        planner.execute_update("update T set B = 'uno' where A = 1", tx.clone())?,
+        1
+    );
+    assert_eq!(
+        planner.execute_update("delete from T where rid = '0:0'", tx.clone())?,
+        1
+    );
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_view_predicate_pushdown() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_view_predicate_pushdown");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B int)", tx.clone())?;
+    for i in 0..40 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, {})", i % 10, i % 2),
+            tx.clone(),
+        )?;
+    }
+    planner.execute_update(
+        "create view V as select A, B from T where B = 1",
+        tx.clone(),
+    )?;
+    planner.execute_update("create view W as select A, B from V", tx.clone())?;
+
+    // 挿入したレコードを統計情報に反映させるため、新しいメタデータでプランを作る
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let mut query_planner = BasicQueryPlanner::new(md);
+    let mut plan = |query: &str| query_planner.create_plan(Parser::new(query).query()?, tx.clone());
+
+    let all = plan("select A from W")?;
+    let filtered = plan("select A from W where A = 3")?;
+    assert!(unlock!(filtered).records_output() < unlock!(all).records_output());
+
+    let scan = unlock!(filtered).open()?;
+    let mut scan = unlock!(scan);
+    let mut count = 0;
+    while scan.next()? {
+        assert_eq!(scan.get_int("A")?, 3);
+        count += 1;
+    }
+    scan.close();
+    assert_eq!(count, 4);
+
+    // 外側の述語とビューの述語が矛盾する場合はテーブルを読まない
+    let contradiction = plan("select A from W where B = 0")?;
+    assert!(unlock!(all).blocks_accessed() > 0);
+    assert_eq!(unlock!(contradiction).blocks_accessed(), 0);
+    let scan = unlock!(contradiction).open()?;
+    assert!(!unlock!(scan).next()?);
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_product_plan_buffer_cost() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_product_plan_buffer_cost");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table S(A int)", tx.clone())?;
+    planner.execute_update("create table L(B varchar(30))", tx.clone())?;
+    for i in 0..2 {
+        planner.execute_update(&format!("insert into S(A) values ({})", i), tx.clone())?;
+    }
+    for i in 0..60 {
+        planner.execute_update(
+            &format!("insert into L(B) values ('{:030}')", i),
+            tx.clone(),
+        )?;
+    }
+
+    // 挿入したレコードを統計情報に反映させるため、新しいメタデータでプランを作る
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let small = Arc::new(Mutex::new(TablePlan::new(
+        "S".into(),
+        tx.clone(),
+        md.clone(),
+    )?)) as ArcPlan;
+    let large = Arc::new(Mutex::new(TablePlan::new("L".into(), tx.clone(), md)?)) as ArcPlan;
+    let blocks_s = unlock!(small).blocks_accessed();
+    let records_s = unlock!(small).records_output();
+    let blocks_l = unlock!(large).blocks_accessed();
+    let records_l = unlock!(large).records_output();
+    assert_eq!((blocks_s, records_s), (1, 2));
+    assert!(blocks_l > 2);
+
+    let cost = |plan1: &ArcPlan, plan2: &ArcPlan, buffers: Option<u64>| -> Result<i32> {
+        let plan = match buffers {
+            Some(buffers) => ProductPlan::with_buffers(plan1.clone(), plan2.clone(), buffers)?,
+            None => ProductPlan::new(plan1.clone(), plan2.clone())?,
+        };
+        Ok(plan.blocks_accessed())
+    };
+    // バッファを考えない場合は内側のテーブルを外側のレコードごとに読み直す
+    assert_eq!(cost(&small, &large, None)?, blocks_s + records_s * blocks_l);
+    assert_eq!(cost(&large, &small, None)?, blocks_l + records_l * blocks_s);
+
+    // バッファが少ないと大きいテーブルは収まらないので、小さいテーブルを内側にするほうが安い
+    let few = 2;
+    assert_eq!(
+        cost(&small, &large, Some(few))?,
+        blocks_s + records_s * blocks_l
+    );
+    assert_eq!(cost(&large, &small, Some(few))?, blocks_l + blocks_s);
+
+    // バッファが多いと大きいテーブルも収まるので、2回目以降はバッファから読める
+    let many = blocks_l as u64 + 1;
+    assert_eq!(cost(&small, &large, Some(many))?, blocks_s + blocks_l);
+    assert_eq!(cost(&large, &small, Some(many))?, blocks_l + blocks_s);
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_row_predicates() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_row_predicates");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(5))", tx.clone())?;
+    for (a, b) in [(1, "x"), (1, "y"), (2, "x"), (2, "y"), (3, "z")] {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, '{}')", a, b),
+            tx.clone(),
+        )?;
+    }
+
+    let mut select = |query: &str| -> Result<Vec<(i32, String)>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push((scan.get_int("A")?, scan.get_string("B")?));
+        }
+        scan.close();
+        Ok(rows)
+    };
+    assert_eq!(
+        select("select A, B from T where (A, B) = (2, 'x')")?,
+        vec![(2, "x".into())]
+    );
+    assert_eq!(
+        select("select A, B from T where (A, B) in ((1, 'y'), (2, 'x'), (3, 'x'))")?,
+        vec![(1, "y".into()), (2, "x".into())]
+    );
+    assert_eq!(
+        select("select A, B from T where (B) in (('z'), (null)) and A = 3")?,
+        vec![(3, "z".into())]
+    );
+    assert!(select("select A, B from T where (A, B) in ((1, null))")?.is_empty());
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_index_cost() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_index_cost");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table S(A int, B varchar(10))", tx.clone())?;
+    planner.execute_update("create table L(A int, B varchar(10))", tx.clone())?;
+    planner.execute_update("create index s_a on S (A) using btree", tx.clone())?;
+    planner.execute_update("create index l_a on L (A) using btree", tx.clone())?;
+    for i in 0..3 {
+        planner.execute_update(
+            &format!("insert into S(A, B) values ({}, 's{}')", i, i),
+            tx.clone(),
+        )?;
+    }
+    for i in 0..300 {
+        planner.execute_update(
+            &format!("insert into L(A, B) values ({}, 'l{}')", i % 50, i),
+            tx.clone(),
+        )?;
+    }
+    // 索引は更新に追従する
+    planner.execute_update("delete from L where A = 7", tx.clone())?;
+    planner.execute_update("update L set A = 7 where A = 8", tx.clone())?;
+    planner.execute_update("insert into L(A, B) values (7, 'new')", tx.clone())?;
+
+    // 挿入したレコードを統計情報に反映させるため、新しいメタデータでプランを作る
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let mut query_planner = BasicQueryPlanner::new(md);
+    let mut plan = |query: &str| query_planner.create_plan(Parser::new(query).query()?, tx.clone());
+
+    // 小さいテーブルでは索引を読むよりテーブル全体を読むほうが安い
+    let small = plan("select B from S where A = 1")?;
+    let lines = unlock!(small).explain();
+    assert_eq!(lines[0], "Project B");
+    assert!(lines[2].trim_start().starts_with("TableScan S"));
+    assert!(lines[3]
+        .trim_start()
+        .starts_with("rejected: IndexSelect S using s_a where A = 1"));
+
+    let large = plan("select B from L where A = 7")?;
+    let lines = unlock!(large).explain();
+    assert!(lines[2]
+        .trim_start()
+        .starts_with("IndexSelect L using l_a where A = 7"));
+    assert!(lines[3].trim_start().starts_with("rejected: TableScan L"));
+
+    let scan = unlock!(large).open()?;
+    let mut scan = unlock!(scan);
+    let mut values = vec![];
+    while scan.next()? {
+        values.push(scan.get_string("B")?);
+    }
+    scan.close();
+    values.sort();
+    assert_eq!(
+        values,
+        vec!["l108", "l158", "l208", "l258", "l58", "l8", "new"]
+    );
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_create_index_backfill() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_create_index_backfill");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(10))", tx.clone())?;
+    for i in 0..200 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, 'b{}')", i % 20, i % 7),
+            tx.clone(),
+        )?;
+    }
+
+    // 索引を作ると、それまでに挿入したレコードが索引に登録される
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    planner.execute_update("create index t_b on T (B) using hash", tx.clone())?;
+    let md = MetadataManager::new(false, tx.clone())?;
+    let mut index_infos = md.get_index_info("T", tx.clone())?;
+    for index_name in ["t_a", "t_b"] {
+        let mut index = index_infos.remove(index_name).unwrap().open()?;
+        assert_eq!(index.entries()?.len(), 200, "{}", index_name);
+        index.close();
+    }
+
+    let md = Arc::new(Mutex::new(md));
+    let mut query_planner = BasicQueryPlanner::new(md);
+    for (query, index_name, expected) in [
+        ("select B from T where A = 3", "t_a", 10),
+        ("select A from T where B = 'b4'", "t_b", 28),
+    ] {
+        let plan = query_planner.create_plan(Parser::new(query).query()?, tx.clone())?;
+        let lines = unlock!(plan).explain();
+        assert!(lines[2].contains(index_name), "{}: {:?}", query, lines);
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut count = 0;
+        while scan.next()? {
+            count += 1;
+        }
+        scan.close();
+        assert_eq!(count, expected, "{}", query);
+    }
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_clustered_table() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_clustered_table");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update(
+        "create table C(A int, B varchar(10)) cluster by A",
+        tx.clone(),
+    )?;
+    planner.execute_update("create index c_b on C (B) using btree", tx.clone())?;
+    assert!(planner
+        .execute_update("create table D(A text) cluster by A", tx.clone())
+        .is_err());
+
+    let sorted = |tx: &Arc<Mutex<Transaction>>| -> Result<bool> {
+        let md = MetadataManager::new(false, tx.clone())?;
+        Ok(md.get_cluster_info("C", tx.clone())?.unwrap().sorted)
+    };
+    // キーの順に挿入している間は並んだままになる
+    for i in 0..200 {
+        planner.execute_update(
+            &format!("insert into C(A, B) values ({}, 'b{}')", i / 2, i),
+            tx.clone(),
+        )?;
+    }
+    assert!(sorted(&tx)?);
+    planner.execute_update("insert into C(A, B) values (5, 'late')", tx.clone())?;
+    assert!(!sorted(&tx)?);
+
+    assert_eq!(planner.execute_update("cluster C", tx.clone())?, 201);
+    assert!(sorted(&tx)?);
+    assert!(planner.execute_update("cluster T", tx.clone()).is_err());
+
+    // 並べ直したテーブルはキーの順に読める
+    let plan = planner.create_query_plan("select A from C", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut keys = vec![];
+    while scan.next()? {
+        keys.push(scan.get_int("A")?);
+    }
+    scan.close();
+    assert_eq!(keys.len(), 201);
+    assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    // 挿入したレコードを統計情報に反映させるため、新しいメタデータでプランを作る
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let mut query_planner = BasicQueryPlanner::new(md);
+    let mut plan = |query: &str| query_planner.create_plan(Parser::new(query).query()?, tx.clone());
+
+    let cluster = plan("select B from C where A = 5")?;
+    let lines = unlock!(cluster).explain();
+    assert!(lines[2]
+        .trim_start()
+        .starts_with("ClusterSelect C where A = 5"));
+    let scan = unlock!(cluster).open()?;
+    let mut scan = unlock!(scan);
+    let mut values = vec![];
+    while scan.next()? {
+        values.push(scan.get_string("B")?);
+    }
+    scan.close();
+    values.sort();
+    assert_eq!(values, vec!["b10", "b11", "late"]);
+
+    // 並べ直した後も索引は新しい位置を指す
+    let index = plan("select /*+ use_index(C c_b) */ A from C where B = 'b150'")?;
+    let scan = unlock!(index).open()?;
+    let mut scan = unlock!(scan);
+    assert!(scan.next()?);
+    assert_eq!(scan.get_int("A")?, 75);
+    assert!(!scan.next()?);
+    scan.close();
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_index_join() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_index_join");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table S(C int, D varchar(10))", tx.clone())?;
+    planner.execute_update("create table L(A int, B varchar(10))", tx.clone())?;
+    for i in 0..3 {
+        planner.execute_update(
+            &format!("insert into S(C, D) values ({}, 's{}')", i * 10, i),
+            tx.clone(),
+        )?;
+    }
+    for i in 0..300 {
+        planner.execute_update(
+            &format!("insert into L(A, B) values ({}, 'l{}')", i % 50, i),
+            tx.clone(),
+        )?;
+    }
+    planner.execute_update("create index l_a on L (A) using btree", tx.clone())?;
+
+    // 挿入したレコードを統計情報に反映させるため、新しいメタデータでプランを作る
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let mut query_planner = BetterQueryPlanner::new(md);
+    let mut plan = |query: &str| query_planner.create_plan(Parser::new(query).query()?, tx.clone());
+    let rows = |plan: &ArcPlan| -> Result<Vec<(String, String)>> {
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push((scan.get_string("D")?, scan.get_string("B")?));
+        }
+        scan.close();
+        rows.sort();
+        Ok(rows)
+    };
+
+    // 小さいテーブルのレコードごとに大きいテーブルを索引で検索する
+    let join = plan("select D, B from S, L where A = C and C = 10")?;
+    let lines = unlock!(join).explain();
+    assert!(lines[2]
+        .trim_start()
+        .starts_with("IndexJoin L using l_a where A = C"));
+    // S の項は結合する前に評価する
+    assert!(lines[3].trim_start().starts_with("Select where C = 10"));
+    assert!(lines[4].trim_start().starts_with("TableScan S"));
+    let mut expected: Vec<(String, String)> = [10, 60, 110, 160, 210, 260]
+        .iter()
+        .map(|i| ("s1".to_string(), format!("l{}", i)))
+        .collect();
+    expected.sort();
+    assert_eq!(rows(&join)?, expected);
+
+    // 外側のテーブルが空なら内側を検索しない
+    planner.execute_update("delete from S", tx.clone())?;
+    assert!(rows(&join)?.is_empty());
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_virtual_index() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_virtual_index");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    {
+        let planner = db.planner.clone().unwrap();
+        let mut planner = unlock!(planner);
+        planner.execute_update("create table L(A int, B varchar(10))", tx.clone())?;
+        for i in 0..300 {
+            planner.execute_update(
+                &format!("insert into L(A, B) values ({}, 'l{}')", i % 50, i),
+                tx.clone(),
+            )?;
+        }
+    }
+
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let options = IndexOptions {
+        index_type: IndexType::BTree,
+        ..Default::default()
+    };
+    unlock!(md).create_virtual_index("l_a", "L", "A", &options, tx.clone())?;
+    assert!(unlock!(md)
+        .create_virtual_index("l_a", "L", "B", &options, tx.clone())
+        .is_err());
+    assert!(unlock!(md)
+        .create_virtual_index("l_c", "L", "C", &options, tx.clone())
+        .is_err());
+    let mut planner = Planner::new(
+        Arc::new(Mutex::new(BasicQueryPlanner::new(md.clone()))),
+        Arc::new(Mutex::new(BasicUpdatePlanner::new(md.clone()))),
+        md.clone(),
+    );
+
+    // 仮想の索引があった場合のプランを確かめられるが、実行はできない
+    let plan = planner.create_query_plan("select B from L where A = 7", tx.clone())?;
+    let lines = unlock!(plan).explain();
+    assert!(lines[2]
+        .trim_start()
+        .starts_with("IndexSelect L using l_a (virtual) where A = 7"));
+    assert!(unlock!(plan).open().is_err());
+
+    // 仮想の索引はレコードの変更で開かれず、カタログにも残らない
+    planner.execute_update("insert into L(A, B) values (7, 'new')", tx.clone())?;
+    planner.execute_update("update L set A = 8 where A = 7", tx.clone())?;
+    let catalog = MetadataManager::new(false, tx.clone())?;
+    assert!(catalog.get_index_info("L", tx.clone())?.is_empty());
+
+    unlock!(md).drop_virtual_index("l_a")?;
+    assert!(unlock!(md).drop_virtual_index("l_a").is_err());
+    let plan = planner.create_query_plan("select B from L where A = 7", tx.clone())?;
+    let lines = unlock!(plan).explain();
+    assert!(lines[2].trim_start().starts_with("TableScan L"));
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_insert_buffer() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_insert_buffer");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let tx = db.transaction()?;
+    unlock!(planner).execute_update("create table T(A int, B varchar(9))", tx.clone())?;
+    unlock!(planner).execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    unlock!(planner).buffer_inserts("T", Some(3), tx.clone())?;
+    assert!(unlock!(planner)
+        .buffer_inserts("U", Some(3), tx.clone())
+        .is_err());
+    unlock!(tx).commit()?;
+
+    // テーブルのファイルに書き込まれたレコードを、貯めたレコードを書き込まずに数える
+    let stored = |tx: &Arc<Mutex<Transaction>>| -> Result<usize> {
+        let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+        let mut scan = TablePlan::new("T".into(), tx.clone(), md)?.open_table_scan()?;
+        let mut count = 0;
+        while scan.next()? {
+            count += 1;
+        }
+        scan.close();
+        Ok(count)
+    };
+    let insert = |tx: &Arc<Mutex<Transaction>>, i: i32| -> Result<i32> {
+        unlock!(planner).execute_update(
+            &format!("insert into T(A, B) values ({}, 'rec{}')", i, i),
+            tx.clone(),
+        )
+    };
+
+    let tx = db.transaction()?;
+    insert(&tx, 0)?;
+    insert(&tx, 1)?;
+    assert_eq!(stored(&tx)?, 0);
+    // capacity に達したらまとめて書き込む
+    insert(&tx, 2)?;
+    assert_eq!(stored(&tx)?, 3);
+    // 問い合わせは貯めたレコードも読み込める
+    insert(&tx, 3)?;
+    let plan = unlock!(planner).create_query_plan("select B from T where A = 3", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(unlock!(scan).next()?);
+    assert_eq!(unlock!(scan).get_string("B")?, "rec3");
+    unlock!(scan).close();
+    // 残りはコミットの前に書き込む
+    insert(&tx, 4)?;
+    assert_eq!(stored(&tx)?, 4);
+    unlock!(tx).commit()?;
+
+    let tx = db.transaction()?;
+    assert_eq!(stored(&tx)?, 5);
+    insert(&tx, 5)?;
+    assert!(unlock!(planner)
+        .execute_update("insert into T(A, C) values (6, 'x')", tx.clone())
+        .is_err());
+    unlock!(tx).rollback()?;
+
+    // ロールバックしたトランザクションが貯めたレコードは捨てる
+    let tx = db.transaction()?;
+    assert_eq!(stored(&tx)?, 5);
+    unlock!(planner).buffer_inserts("T", None, tx.clone())?;
+    insert(&tx, 6)?;
+    assert_eq!(stored(&tx)?, 6);
+    let md = MetadataManager::new(false, tx.clone())?;
+    let mut index_info = md.get_index_info("T", tx.clone())?.remove("t_a").unwrap();
+    let mut index = index_info.open()?;
+    assert_eq!(index.entries()?.len(), 6);
+    index.close();
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_modify_each_record_once() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_modify_each_record_once");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B int)", tx.clone())?;
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    for i in 0..50 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, {})", i, i + 1),
+            tx.clone(),
+        )?;
+    }
+
+    // 変更したレコードをもう一度変更しないので、それぞれの A は1回だけ B に置き換わる
+    assert_eq!(
+        planner.execute_update("update T set A = B", tx.clone())?,
+        50
+    );
+    let md = MetadataManager::new(false, tx.clone())?;
+    let mut index_info = md.get_index_info("T", tx.clone())?.remove("t_a").unwrap();
+    let mut index = index_info.open()?;
+    let mut keys: Vec<Constant> = index.entries()?.into_iter().map(|(key, _)| key).collect();
+    index.close();
+    keys.sort();
+    assert_eq!(keys, (1..=50).map(Constant::Int).collect::<Vec<_>>());
+
+    assert_eq!(
+        planner.execute_update("delete from T where A = B", tx.clone())?,
+        50
+    );
+    assert_eq!(planner.execute_update("delete from T", tx.clone())?, 0);
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_column_pruning() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_column_pruning");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update(
+        "create table T(A int, B varchar(9), X varchar(100))",
+        tx.clone(),
+    )?;
+    planner.execute_update(
+        "create table U(C int, D varchar(9), Y varchar(100))",
+        tx.clone(),
+    )?;
+    for i in 0..3 {
+        planner.execute_update(
+            &format!("insert into T(A, B, X) values ({}, 'b{}', 'x')", i, i),
+            tx.clone(),
+        )?;
+        planner.execute_update(
+            &format!("insert into U(C, D, Y) values ({}, 'd{}', 'y')", i, i),
+            tx.clone(),
+        )?;
+    }
+
+    // 結合するそれぞれのテーブルは、結果と述語で使うフィールドだけを出す
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    for mut query_planner in [
+        Box::new(BasicQueryPlanner::new(md.clone())) as Box<dyn QueryPlanner>,
+        Box::new(BetterQueryPlanner::new(md.clone())),
+    ] {
+        let data = Parser::new("select B, D from T, U where A = C").query()?;
+        let plan = query_planner.create_plan(data, tx.clone())?;
+        let lines: Vec<String> = unlock!(plan)
+            .explain()
+            .iter()
+            .map(|line| line.trim_start().to_string())
+            .collect();
+        assert!(
+            lines.iter().any(|line| line == "Project A, B"),
+            "{:?}",
+            lines
+        );
+        assert!(
+            lines.iter().any(|line| line == "Project C, D"),
+            "{:?}",
+            lines
+        );
+
+        let scan = unlock!(plan).open()?;
+        let mut rows = vec![];
+        while unlock!(scan).next()? {
+            let mut scan = unlock!(scan);
+            rows.push((scan.get_string("B")?, scan.get_string("D")?));
+        }
+        unlock!(scan).close();
+        rows.sort();
+        assert_eq!(
+            rows,
+            (0..3)
+                .map(|i| (format!("b{}", i), format!("d{}", i)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    // 1つのテーブルだけを読む場合はそのまま
+    let plan = planner.create_query_plan("select B from T", tx.clone())?;
+    assert_eq!(unlock!(plan).explain().len(), 3);
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_deferred_index_updates() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_deferred_index_updates");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let index_keys = |tx: &Arc<Mutex<Transaction>>| -> Result<Vec<Constant>> {
+        let md = MetadataManager::new(false, tx.clone())?;
+        let mut index_info = md.get_index_info("T", tx.clone())?.remove("t_a").unwrap();
+        let mut index = index_info.open()?;
+        let mut keys: Vec<Constant> = index.entries()?.into_iter().map(|(key, _)| key).collect();
+        index.close();
+        keys.sort();
+        Ok(keys)
+    };
+
+    let tx = db.transaction()?;
+    {
+        let mut planner = unlock!(planner);
+        planner.execute_update("create table T(A int, B int)", tx.clone())?;
+        planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+        planner.defer_index_updates("T", true, tx.clone())?;
+        for i in 0..20 {
+            planner.execute_update(
+                &format!("insert into T(A, B) values ({}, {})", 19 - i, i),
+                tx.clone(),
+            )?;
+        }
+        planner.execute_update("update T set A = B where A = 3", tx.clone())?;
+        planner.execute_update("delete from T where A = 10", tx.clone())?;
+    }
+    // 貯めた変更はまだ索引に反映していない
+    assert!(index_keys(&tx)?.is_empty());
+    unlock!(tx).commit()?;
+
+    let tx = db.transaction()?;
+    let mut expected: Vec<Constant> = (0..20)
+        .filter(|a| *a != 3 && *a != 10)
+        .chain([16])
+        .map(Constant::Int)
+        .collect();
+    expected.sort();
+    assert_eq!(index_keys(&tx)?, expected);
+
+    // 問い合わせの前に反映するので、索引で検索しても変更が見える
+    let mut planner = unlock!(planner);
+    planner.execute_update("insert into T(A, B) values (100, 7)", tx.clone())?;
+    let plan = planner.create_query_plan("select B from T where A = 100", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut values = vec![];
+    while unlock!(scan).next()? {
+        values.push(unlock!(scan).get_int("B")?);
+    }
+    unlock!(scan).close();
+    assert_eq!(values, vec![7]);
+    assert_eq!(planner.flush_index_updates(&tx)?, 0);
+
+    // ロールバックすると貯めた変更を捨てる
+    planner.execute_update("delete from T where A = 100", tx.clone())?;
+    planner.execute_update("insert into T(A, B) values (200, 8)", tx.clone())?;
+    unlock!(tx).rollback()?;
+    let tx = db.transaction()?;
+    assert_eq!(index_keys(&tx)?, expected);
+
+    assert!(planner
+        .defer_index_updates("missing", true, tx.clone())
+        .is_err());
+    planner.defer_index_updates("T", false, tx.clone())?;
+    planner.execute_update("insert into T(A, B) values (300, 9)", tx.clone())?;
+    assert!(index_keys(&tx)?.contains(&Constant::Int(300)));
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_plan_record_size() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_plan_record_size");
+    let db = TinyDB::new(test_directory, 400, 8)?;
+    let tx = db.transaction()?;
+
+    let mut md = FakeMetadata::default();
+    let mut schema = Schema::default();
+    schema.add_int_field("A");
+    schema.add_string_field("B", 10);
+    let layout = Layout::try_from_schema(Arc::new(schema.clone()))?;
+    md.add_table("L", schema, StatInfo::new(1000, 5000))?;
+    let mut schema = Schema::default();
+    schema.add_int_field("C");
+    md.add_table("S", schema, StatInfo::new(1, 3))?;
+    let md = Arc::new(Mutex::new(md));
+
+    let large = Arc::new(Mutex::new(TablePlan::new(
+        "L".into(),
+        tx.clone(),
+        md.clone(),
+    )?)) as ArcPlan;
+    let small = Arc::new(Mutex::new(TablePlan::new("S".into(), tx.clone(), md)?)) as ArcPlan;
+    let record_size = unlock!(large).record_size();
+    assert_eq!(record_size, layout.slot_size);
+    assert_eq!(unlock!(small).record_size(), 8);
+
+    // 射影すると残したフィールドの分だけになり、積は両方のフィールドを持つ
+    let project = ProjectPlan::new(large.clone(), vec!["A".into()])?;
+    assert_eq!(project.record_size(), 8);
+    let product = ProductPlan::new(large.clone(), small.clone())?;
+    assert_eq!(product.record_size(), record_size + 4);
+
+    // ヘッダとスロットのエントリを除いた大きさに入るレコードの数から、一時テーブルのブロック数を見積もる
+    let per_block = (400 - 12) / (record_size + 12);
+    assert_eq!(
+        materialized_blocks(&*unlock!(large), 400),
+        (5000 + per_block - 1) / per_block
+    );
+    assert_eq!(materialized_blocks(&*unlock!(small), 400), 1);
+    assert!(materialized_blocks(&project, 400) < materialized_blocks(&*unlock!(large), 400));
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_sort_plan_in_memory_and_external() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_sort_plan_in_memory_and_external");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(10))", tx.clone())?;
+    for i in 0..40 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, 'b{}')", i % 4, 39 - i),
+            tx.clone(),
+        )?;
+    }
+
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let table = Arc::new(Mutex::new(TablePlan::new("T".into(), tx.clone(), md)?)) as ArcPlan;
+    let record_size = unlock!(table).record_size();
+    let mut expected: Vec<(i32, String)> =
+        (0..40).map(|i| (i % 4, format!("b{}", 39 - i))).collect();
+    expected.sort();
+    let sorted = |plan: &mut SortPlan| -> Result<Vec<(i32, String)>> {
+        let scan = plan.open()?;
+        let mut records = vec![];
+        let mut scan = unlock!(scan);
+        while scan.next()? {
+            records.push((scan.get_int("A")?, scan.get_string("B")?));
+        }
+        scan.close();
+        Ok(records)
+    };
+
+    // 入力が上限に収まる場合は一時テーブルを作らない
+    let mut plan = SortPlan::new(table.clone(), vec!["A".into(), "B".into()], tx.clone())?
+        .with_memory_budget(record_size * 40);
+    assert_eq!(sorted(&mut plan)?, expected);
+    assert_eq!(plan.runs(), 0);
+    assert!(plan.explain()[0].contains("in memory"));
+    assert_eq!(plan.blocks_accessed(), unlock!(table).blocks_accessed());
+
+    // 収まらない場合はランに分けて書き込み、併合して並べる
+    let mut plan = SortPlan::new(table.clone(), vec!["A".into(), "B".into()], tx.clone())?
+        .with_memory_budget(record_size * 6);
+    assert_eq!(sorted(&mut plan)?, expected);
+    assert_eq!(plan.runs(), 7);
+    assert!(plan.explain()[0].contains("external"));
+
+    // 最後の2つのランは読みながら併合するので、後ろからも読める
+    let scan = plan.open()?;
+    let mut records = vec![];
+    let mut scan = unlock!(scan);
+    scan.after_last();
+    while scan.previous()? {
+        records.push((scan.get_int("A")?, scan.get_string("B")?));
+    }
+    scan.close();
+    expected.reverse();
+    assert_eq!(records, expected);
+    assert!(plan.blocks_accessed() > unlock!(table).blocks_accessed());
+
+    assert!(SortPlan::new(table, vec!["X".into()], tx.clone()).is_err());
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_order_by() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_order_by");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B int)", tx.clone())?;
+    for i in 0..12 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, {})", i % 3, i),
+            tx.clone(),
+        )?;
+    }
+    let mut query = |query: &str| -> Result<Vec<i32>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut values = vec![];
+        let mut scan = unlock!(scan);
+        while scan.next()? {
+            values.push(scan.get_int("B")?);
+        }
+        scan.close();
+        Ok(values)
+    };
+
+    // 結果に出さないフィールドでも並べられ、キーごとに向きを変えられる
+    assert_eq!(
+        query("select B from T order by A desc, B")?,
+        vec![2, 5, 8, 11, 1, 4, 7, 10, 0, 3, 6, 9]
+    );
+    assert_eq!(
+        query("select B from T where A = 1 order by B desc")?,
+        vec![10, 7, 4, 1]
+    );
+    // 式で並べる。A - B が等しいレコードは次のキーで並べる
+    assert_eq!(
+        query("select B from T where B < 6 order by A + B")?,
+        vec![0, 1, 3, 2, 4, 5]
+    );
+    assert_eq!(
+        query("select B from T order by A - B desc, B desc")?,
+        vec![2, 1, 0, 5, 4, 3, 8, 7, 6, 11, 10, 9]
+    );
+    assert!(query("select B from T order by C").is_err());
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_with_distinct() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_aggregation_with_distinct");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B int)", tx.clone())?;
+    planner.execute_update("create table E(A int)", tx.clone())?;
+    for i in 0..12 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, {})", i % 3, i / 4),
+            tx.clone(),
+        )?;
+    }
+    let mut query = |query: &str| -> Result<(Vec<Vec<i32>>, Vec<String>)> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let fields = unlock!(plan).schema().fields.clone();
+        let explain = unlock!(plan).explain();
+        let scan = unlock!(plan).open()?;
+        let mut rows = vec![];
+        let mut scan = unlock!(scan);
+        while scan.next()? {
+            let row = fields.iter().map(|field| scan.get_int(field));
+            rows.push(row.collect::<Result<_>>()?);
+        }
+        scan.close();
+        Ok((rows, explain))
+    };
+
+    let (rows, _) =
+        query("select A, count(*), count(distinct B), sum(distinct B), sum(B) from T group by A")?;
+    assert_eq!(
+        rows,
+        vec![
+            vec![0, 4, 3, 3, 3],
+            vec![1, 4, 3, 3, 4],
+            vec![2, 4, 3, 3, 5]
+        ]
+    );
+
+    // distinct の集計は重複を除くためにグループの中を並べ替える
+    let (rows, explain) = query("select count(distinct B), count(A) from T")?;
+    assert_eq!(rows, vec![vec![3, 12]]);
+    assert!(explain
+        .iter()
+        .any(|line| line.contains("distinct sorted on B")));
+    assert!(explain
+        .iter()
+        .any(|line| line.trim_start().starts_with("Sort")));
+    let (rows, explain) = query("select count(B), max(B) from T")?;
+    assert_eq!(rows, vec![vec![12, 2]]);
+    assert!(!explain
+        .iter()
+        .any(|line| line.trim_start().starts_with("Sort")));
+
+    // GROUP BY がなければ入力が空でも1件を返す
+    let (rows, _) = query("select count(*), count(distinct A) from E")?;
+    assert_eq!(rows, vec![vec![0, 0]]);
+    let (rows, _) = query("select A, count(*) from T where B = 9 group by A")?;
+    assert!(rows.is_empty());
+
+    assert!(query("select A, count(*) from T").is_err());
+    assert!(query("select count(distinct C) from T").is_err());
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_with_fake_metadata() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_with_fake_metadata");
+    let db = TinyDB::new(test_directory, 400, 8)?;
+    let tx = db.transaction()?;
+
+    // カタログを作らずに、統計だけで大きなテーブルと小さなテーブルを表す
+    let mut md = FakeMetadata::default();
+    let mut schema = Schema::default();
+    schema.add_int_field("A");
+    schema.add_string_field("B", 10);
+    md.add_table("L", schema, StatInfo::new(1000, 50000))?;
+    let mut schema = Schema::default();
+    schema.add_int_field("C");
+    schema.add_string_field("D", 10);
+    md.add_table("S", schema, StatInfo::new(1, 3))?;
+    let options = IndexOptions {
+        index_type: IndexType::BTree,
+        ..Default::default()
+    };
+    md.add_index("l_a", "L", "A", options.clone())?
+        .add_index("s_c", "S", "C", options)?
+        .add_view("small", "select C, D from S");
+    assert!(md
+        .add_index("l_x", "L", "X", IndexOptions::default())
+        .is_err());
+
+    let mut query_planner = BetterQueryPlanner::new(Arc::new(Mutex::new(md)));
+    let mut explain = |query: &str| -> Result<Vec<String>> {
+        let plan = query_planner.create_plan(Parser::new(query).query()?, tx.clone())?;
+        let lines = unlock!(plan).explain();
+        Ok(lines
+            .iter()
+            .map(|line| line.trim_start().to_string())
+            .collect())
+    };
+
+    // 大きなテーブルは索引で検索し、小さなテーブルは全体を読む
+    let lines = explain("select B from L where A = 1")?;
+    assert!(
+        lines[2].starts_with("IndexSelect L using l_a where A = 1"),
+        "{:?}",
+        lines
+    );
+    let lines = explain("select D from S where C = 1")?;
+    assert!(lines[2].starts_with("TableScan S"), "{:?}", lines);
+
+    // 小さなテーブル（ビューを通しても）のレコードごとに大きなテーブルを索引で検索する
+    let lines = explain("select D, B from small, L where A = C")?;
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("IndexJoin L using l_a where A = C")),
+        "{:?}",
+        lines
+    );
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_range_reduction_factor() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_range_reduction_factor");
+    let db = TinyDB::new(test_directory, 400, 8)?;
+    let tx = db.transaction()?;
+
+    let mut md = FakeMetadata::default();
+    let mut schema = Schema::default();
+    schema.add_int_field("A");
+    schema.add_string_field("B", 10);
+    md.add_table("L", schema.clone(), StatInfo::new(1000, 50000))?;
+    md.add_table("S", schema, StatInfo::new(1, 3))?;
+    let md = Arc::new(Mutex::new(md));
+    let reduction_factor = |table: &str, pred: &str| -> Result<i32> {
+        let plan = Arc::new(Mutex::new(TablePlan::new(
+            table.into(),
+            tx.clone(),
+            md.clone(),
+        )?));
+        let query = Parser::new(&format!("select A from {} where {}", table, pred)).query()?;
+        Ok(query.pred.reduction_factor(plan))
+    };
+
+    assert_eq!(reduction_factor("L", "A = 5")?, 16667);
+    assert_eq!(reduction_factor("L", "A <> 5")?, 1);
+    assert_eq!(reduction_factor("L", "A < 5")?, 3);
+    assert_eq!(reduction_factor("L", "B >= 'x'")?, 3);
+    // 範囲の項を重ねると、それぞれの見積もりを足して見積もる
+    assert_eq!(reduction_factor("L", "A > 1 and A <= 9")?, 6);
+    // 値の種類が2つしかないフィールドの範囲の比較は、2分の1までしか減らない
+    assert_eq!(reduction_factor("S", "A > 1")?, 2);
+    // 定数どうしの比較は、満たすかどうかで全体か0件になる
+    assert_eq!(reduction_factor("L", "1 < 2")?, 1);
+    assert_eq!(reduction_factor("L", "'b' <= 'a'")?, i32::MAX);
+    assert_eq!(reduction_factor("L", "'b' < 1")?, i32::MAX);
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+/// プランの木の JSON をファイルに保存したものと比べる
+/// プランの形を変えた場合は UPDATE_GOLDEN=1 で実行してファイルを作り直す
+#[test]
+fn test_planner_describe_golden_file() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_describe_golden_file");
+    let db = TinyDB::new(test_directory, 400, 8)?;
+    let tx = db.transaction()?;
+
+    let mut md = FakeMetadata::default();
+    let mut schema = Schema::default();
+    schema.add_int_field("A");
+    schema.add_string_field("B", 10);
+    md.add_table("L", schema, StatInfo::new(1000, 50000))?;
+    let mut schema = Schema::default();
+    schema.add_int_field("C");
+    schema.add_string_field("D", 10);
+    md.add_table("S", schema, StatInfo::new(1, 3))?;
+    let options = IndexOptions {
+        index_type: IndexType::BTree,
+        ..Default::default()
+    };
+    md.add_index("l_a", "L", "A", options)?;
+
+    let mut query_planner = BetterQueryPlanner::new(Arc::new(Mutex::new(md)));
+    let query = "select D, B from S, L where A = C and D = 'x'";
+    let plan = query_planner.create_plan(Parser::new(query).query()?, tx.clone())?;
+    let node = unlock!(plan).describe();
+    assert_eq!(node.lines(), unlock!(plan).explain());
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/planner_index_join.json");
+    let json = node.to_json() + "\n";
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &json)?;
+    }
+    assert_eq!(json, fs::read_to_string(&path)?);
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_collation() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_collation");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    let tx = db.transaction()?;
+    for sql in [
+        "create table T(A varchar(10) collate nocase, B varchar(10), C int)",
+        "insert into T(A, B, C) values ('Apple', 'Apple', 1)",
+        "insert into T(A, B, C) values ('banana', 'banana', 2)",
+        "create index t_a on T (A) using btree",
+        "create index t_a_hash on T (A) using hash",
+    ] {
+        planner.execute_update(sql, tx.clone())?;
+    }
+    let mut select = |query: &str| -> Result<Vec<i32>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut values = vec![];
+        while unlock!(scan).next()? {
+            values.push(unlock!(scan).get_int("C")?);
+        }
+        unlock!(scan).close();
+        Ok(values)
+    };
+    // 照合順序を指定したフィールドは大文字と小文字を区別しない
+    assert_eq!(select("select C from T where A = 'apple'")?, vec![1]);
+    assert_eq!(select("select C from T where A = 'BANANA'")?, vec![2]);
+    assert_eq!(
+        select("select C from T where (A, C) in (('APPLE', 1), ('x', 2))")?,
+        vec![1]
+    );
+    assert_eq!(
+        select("select C from T where B = 'apple'")?,
+        Vec::<i32>::new()
+    );
+    assert_eq!(
+        select("select C from T where A = 'apple' and A = 'APPLE'")?,
+        vec![1]
+    );
+    for index_name in ["t_a", "t_a_hash"] {
+        let query = format!(
+            "select /*+ use_index(T {}) */ C from T where A = 'aPPLE'",
+            index_name
+        );
+        assert_eq!(select(&query)?, vec![1], "{}", index_name);
+    }
+    unlock!(tx).commit()?;
+
+    // 照合順序はカタログに記録され、ダンプにも書き込まれる
+    let tx = db.transaction()?;
+    let md = MetadataManager::new(false, tx.clone())?;
+    let layout = md.get_layout("T", tx.clone())?;
+    assert_eq!(layout.schema.collation("A"), Collation::NoCase);
+    assert_eq!(layout.schema.collation("B"), Collation::Binary);
+    let mut script = vec![];
+    dump(tx.clone(), &mut script)?;
+    assert!(String::from_utf8(script)?
+        .starts_with("create table T (A varchar(10) collate nocase, B varchar(10), C int);"));
+
+    assert!(planner
+        .execute_update(
+            "create table U(A varchar(10) collate nocase) cluster by A",
+            tx.clone()
+        )
+        .is_err());
+    assert!(planner
+        .execute_update("create index t_a3 on T (A(3)) using btree", tx.clone())
+        .is_err());
+    unlock!(tx).rollback()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_varchar_truncation() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_varchar_truncation");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    let tx = db.transaction()?;
+    for sql in [
+        "create table T(A varchar(3) on overflow truncate, B varchar(3), C int)",
+        "create index t_a on T (A) using btree",
+        "insert into T(A, B, C) values ('abc', 'abc', 1)",
+        "insert into T(A, B, C) values ('abcdef', '', 2)",
+        "insert into T(A, B, C) values ('あいうえお', 'あいう', 3)",
+        "update T set A = 'xyzw' where C = 1",
+    ] {
+        planner.execute_update(sql, tx.clone())?;
+    }
+    let mut select = |query: &str| -> Result<Vec<(String, String, i32)>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut rows = vec![];
+        while unlock!(scan).next()? {
+            let mut scan = unlock!(scan);
+            rows.push((
+                scan.get_string("A")?,
+                scan.get_string("B")?,
+                scan.get_int("C")?,
+            ));
+        }
+        unlock!(scan).close();
+        Ok(rows)
+    };
+    // 長さは文字数で数え、長すぎる値は切り詰めて書き込む
+    assert_eq!(
+        select("select A, B, C from T")?,
+        vec![
+            ("xyz".to_string(), "abc".to_string(), 1),
+            ("abc".to_string(), "".to_string(), 2),
+            ("あいう".to_string(), "あいう".to_string(), 3),
+        ]
+    );
+    // 索引にも切り詰めた値が入る
+    for (key, want) in [("xyz", vec![1]), ("abc", vec![2]), ("あいう", vec![3])] {
+        let query = format!(
+            "select /*+ use_index(T t_a) */ A, B, C from T where A = '{}'",
+            key
+        );
+        let rows = select(&query)?;
+        assert_eq!(
+            rows.iter().map(|row| row.2).collect::<Vec<_>>(),
+            want,
+            "{}",
+            key
+        );
+    }
+    unlock!(tx).commit()?;
+
+    // 長すぎる文字列の扱いはカタログに記録され、ダンプにも書き込まれる
+    let tx = db.transaction()?;
+    let md = MetadataManager::new(false, tx.clone())?;
+    let layout = md.get_layout("T", tx.clone())?;
+    assert_eq!(layout.schema.truncation("A"), TruncationPolicy::Truncate);
+    assert_eq!(layout.schema.truncation("B"), TruncationPolicy::Error);
+    let mut script = vec![];
+    dump(tx.clone(), &mut script)?;
+    assert!(String::from_utf8(script)?
+        .starts_with("create table T (A varchar(3) on overflow truncate, B varchar(3), C int);"));
+
+    // 切り詰めないフィールドに長すぎる値を書き込むとエラーになる
+    assert!(planner
+        .execute_update("insert into T(A, B, C) values ('a', 'abcd', 4)", tx.clone())
+        .is_err());
+    unlock!(tx).rollback()?;
+    let tx = db.transaction()?;
+    assert!(planner
+        .execute_update("update T set B = 'あいうえ' where C = 3", tx.clone())
+        .is_err());
+    unlock!(tx).rollback()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_empty_table() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_empty_table");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    let tx = db.transaction()?;
+    for sql in [
+        "create table T(A int)",
+        "create table U(B int)",
+        "insert into U(B) values (1)",
+    ] {
+        planner.execute_update(sql, tx.clone())?;
+    }
+    let select = |planner: &mut Planner, query: &str| -> Result<(Vec<String>, usize)> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let lines = unlock!(plan).explain();
+        let scan = unlock!(plan).open()?;
+        let mut count = 0;
+        while unlock!(scan).next()? {
+            count += 1;
+        }
+        unlock!(scan).close();
+        Ok((lines, count))
+    };
+    // 空のテーブルを含む結合は、どのテーブルも開かない空のプランになる
+    let (lines, count) = select(&mut planner, "select A, B from T, U where A = B")?;
+    assert_eq!(lines, vec!["Empty T (empty table)"]);
+    assert_eq!(count, 0);
+    let (lines, count) = select(&mut planner, "select B from U")?;
+    assert_eq!(lines[0], "Project B");
+    assert_eq!(count, 1);
+
+    // 統計が古くても、レコードを挿入したテーブルは空として扱わない
+    planner.execute_update("insert into T(A) values (1)", tx.clone())?;
+    let (lines, count) = select(&mut planner, "select A, B from T, U where A = B")?;
+    assert_ne!(lines[0], "Empty T (empty table)");
+    assert_eq!(count, 1);
+    // 削除した数が統計のレコード数の割合を超えたので、統計を取り直して空のテーブルとして扱う
+    planner.execute_update("delete from T where A = 1", tx.clone())?;
+    let (lines, count) = select(&mut planner, "select A from T")?;
+    assert_eq!(lines, vec!["Empty T (empty table)"]);
+    assert_eq!(count, 0);
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_delete_all_truncates_files() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_delete_all_truncates_files");
+    let mut db = TinyDB::new(&test_directory, 400, 8)?;
+    db.init_planner()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    let tx = db.transaction()?;
+    planner.execute_update("create table T(A int, B varchar(10))", tx.clone())?;
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    planner.execute_update("create index t_b on T (B) using hash", tx.clone())?;
+    for i in 0..100 {
+        planner.execute_update(
+            &format!("insert into T(A, B) values ({}, 'b{}')", i, i % 10),
+            tx.clone(),
+        )?;
+    }
+    unlock!(tx).commit()?;
+    let count = |planner: &mut Planner, tx: &Arc<Mutex<Transaction>>, query: &str| {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut count = 0;
+        while unlock!(scan).next()? {
+            count += 1;
+        }
+        unlock!(scan).close();
+        Ok::<_, anyhow::Error>(count)
+    };
+    let entries = |tx: &Arc<Mutex<Transaction>>, index_name: &str| -> Result<usize> {
+        let md = MetadataManager::new(false, tx.clone())?;
+        let mut index_info = md
+            .get_index_info("T", tx.clone())?
+            .remove(index_name)
+            .unwrap();
+        let mut index = index_info.open()?;
+        let entries = index.entries()?.len();
+        index.close();
+        Ok(entries)
+    };
+
+    // 述語のない delete はテーブルと索引のファイルを空にする
+    let tx = db.transaction()?;
+    assert_eq!(planner.execute_update("delete from T", tx.clone())?, 100);
+    assert_eq!(count(&mut planner, &tx, "select A from T")?, 0);
+    assert_eq!(count(&mut planner, &tx, "select A from T where A = 5")?, 0);
+    assert_eq!(entries(&tx, "t_a")?, 0);
+    assert_eq!(entries(&tx, "t_b")?, 0);
+    planner.execute_update("insert into T(A, B) values (5, 'x')", tx.clone())?;
+    assert_eq!(count(&mut planner, &tx, "select A from T where A = 5")?, 1);
+    unlock!(tx).rollback()?;
+
+    // ロールバックすると元のレコードと索引に戻る
+    let tx = db.transaction()?;
+    assert_eq!(count(&mut planner, &tx, "select A from T")?, 100);
+    assert_eq!(
+        count(&mut planner, &tx, "select A from T where B = 'b3'")?,
+        10
+    );
+    assert_eq!(entries(&tx, "t_a")?, 100);
+    assert_eq!(entries(&tx, "t_b")?, 100);
+    assert_eq!(planner.execute_update("delete from T", tx.clone())?, 100);
+    unlock!(tx).commit()?;
+
+    // コミットすると残しておいた元の内容を削除する
+    let tx = db.transaction()?;
+    assert_eq!(count(&mut planner, &tx, "select A from T")?, 0);
+    assert_eq!(entries(&tx, "t_a")?, 0);
+    unlock!(tx).commit()?;
+    let backups = fs::read_dir(&test_directory)?
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .is_ok_and(|entry| entry.file_name().to_string_lossy().starts_with('~'))
+        })
+        .count();
+    assert_eq!(backups, 0);
+    Ok(())
+}
+
+#[test]
+fn test_catalog_version_in_control_file() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_catalog_version_in_control_file");
+    {
+        let mut db = TinyDB::new(&test_directory, 400, 8)?;
+        db.init_planner()?;
+        assert_eq!(db.catalog_version(), CATALOG_VERSION);
+    }
+    assert_eq!(
+        ControlFile::read(&test_directory)?,
+        Some(ControlFile::new(CATALOG_VERSION))
+    );
+
+    // コントロールファイルを作る前のデータベースは版 1 として開き、今の版に上げる
+    fs::remove_file(test_directory.join(CONTROL_FILE))?;
+    {
+        let mut db = TinyDB::new(&test_directory, 400, 8)?;
+        assert_eq!(db.catalog_version(), 1);
+        db.init_planner()?;
+        assert_eq!(db.catalog_version(), CATALOG_VERSION);
+    }
+
+    // 今より新しい形式のカタログは開かない
+    ControlFile::new(CATALOG_VERSION + 1).write(&test_directory)?;
+    let err = TinyDB::new(&test_directory, 400, 8).err().unwrap();
+    assert!(err.to_string().contains("unsupported catalog version"));
+    Ok(())
+}
+
+#[test]
+fn test_migrate_legacy_table_catalog() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_migrate_legacy_table_catalog");
+    // 作成した時刻と統計の列がない tblcat のカタログを作り、コントロールファイルのない古いデータベースにする
+    {
+        let db = TinyDB::new(&test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut table_schema = Schema::default();
+        table_schema.add_int_field("A");
+        let tables = [
+            (
+                "tblcat",
+                Layout::try_from_schema(Arc::new(legacy_table_catalog_schema()))?,
+            ),
+            (
+                "fldcat",
+                Layout::try_from_schema(Arc::new(field_catalog_schema()))?,
+            ),
+            ("T", Layout::try_from_schema(Arc::new(table_schema))?),
+        ];
+        let mut tcat = TableScan::new(tx.clone(), "tblcat", Arc::new(tables[0].1.clone()))?;
+        let mut fcat = TableScan::new(tx.clone(), "fldcat", Arc::new(tables[1].1.clone()))?;
+        for (table_name, layout) in &tables {
+            tcat.insert()?;
+            tcat.set_string("tblname", table_name)?;
+            tcat.set_int("slotsize", layout.slot_size)?;
+            for field_name in &layout.schema.fields {
+                fcat.insert()?;
+                fcat.set_string("tblname", table_name)?;
+                fcat.set_string("fldname", field_name)?;
+                fcat.set_int("type", layout.schema.r#type(field_name).unwrap() as i32)?;
+                fcat.set_int("length", layout.schema.length(field_name).unwrap())?;
+                fcat.set_int("offset", layout.offset(field_name).unwrap())?;
+            }
+        }
+        tcat.close();
+        fcat.close();
+        let mut ts = TableScan::new(tx.clone(), "T", Arc::new(tables[2].1.clone()))?;
+        ts.insert()?;
+        ts.set_int("A", 7)?;
+        ts.close();
+        unlock!(tx).commit()?;
+    }
+    fs::remove_file(test_directory.join(CONTROL_FILE))?;
+
+    let mut db = TinyDB::new(&test_directory, 400, 8)?;
+    db.init_planner()?;
+    assert_eq!(db.catalog_version(), CATALOG_VERSION);
+    assert_eq!(
+        ControlFile::read(&test_directory)?,
+        Some(ControlFile::new(CATALOG_VERSION))
+    );
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    let tx = db.transaction()?;
+    let plan =
+        planner.create_query_plan("select tblname, slotsize, analyzed from tblcat", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut tables = vec![];
+    while unlock!(scan).next()? {
+        let mut scan = unlock!(scan);
+        assert_eq!(scan.get_int("analyzed")?, 0);
+        tables.push((scan.get_string("tblname")?, scan.get_int("slotsize")?));
+    }
+    unlock!(scan).close();
+    assert_eq!(tables[0], ("tblcat".to_string(), 44));
+    assert!(tables.iter().any(|(table_name, _)| table_name == "T"));
+
+    // 書き直したカタログで古いテーブルを読め、新しいテーブルを作れる
+    planner.execute_update("create table U(B int)", tx.clone())?;
+    planner.execute_update("insert into U(B) values (1)", tx.clone())?;
+    let plan = planner.create_query_plan("select A from T", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(unlock!(scan).next()?);
+    assert_eq!(unlock!(scan).get_int("A")?, 7);
+    unlock!(scan).close();
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_select_list_column_order() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_select_list_column_order");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(9))", tx.clone())?;
+    planner.execute_update("create table U(C int, D varchar(9))", tx.clone())?;
+    for i in 0..3 {
+        let query = format!("insert into T(A, B) values ({}, 'b{}')", i, i);
+        planner.execute_update(&query, tx.clone())?;
+        let query = format!("insert into U(C, D) values ({}, 'd{}')", i, i);
+        planner.execute_update(&query, tx.clone())?;
+    }
+
+    // 結合の順によらず、結果のフィールドは SELECT に書いた順に並ぶ。同じフィールドもテーブル名を付けたフィールドも書いた名前で出す
+    let fields = vec!["D", "A", "C", "A", "T.B", "U.C"];
+    let md = Arc::new(Mutex::new(MetadataManager::new(false, tx.clone())?));
+    let planners: Vec<Box<dyn QueryPlanner>> = vec![
+        Box::new(BasicQueryPlanner::new(md.clone())),
+        Box::new(BetterQueryPlanner::new(md)),
+    ];
+    for mut query_planner in planners {
+        for hint in ["", "/*+ leading(T U) */", "/*+ leading(U T) */"] {
+            let query = format!(
+                "select {} D, A, C, A, T.B, U.C from T, U where A = C and A = 1",
+                hint
+            );
+            let plan = query_planner.create_plan(Parser::new(&query).query()?, tx.clone())?;
+            let mut plan = unlock!(plan);
+            assert_eq!(plan.schema().fields, fields, "{}", query);
+            assert_eq!(plan.metadata().field_names(), fields, "{}", query);
+
+            let scan = plan.open()?;
+            let mut scan = unlock!(scan);
+            assert!(scan.next()?);
+            let values = fields
+                .iter()
+                .map(|field| scan.get_value(field))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(
+                values,
+                vec![
+                    Constant::String("d1".into()),
+                    Constant::Int(1),
+                    Constant::Int(1),
+                    Constant::Int(1),
+                    Constant::String("b1".into()),
+                    Constant::Int(1),
+                ]
+            );
+            assert!(!scan.next()?);
+            scan.close();
+        }
+
+        // テーブル名を付けたフィールドは、そのテーブルになければエラーになる
+        let query = Parser::new("select U.A from T, U").query()?;
+        assert!(query_planner.create_plan(query, tx.clone()).is_err());
+    }
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_or_and_not() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_or_and_not");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(5))", tx.clone())?;
+    planner.execute_update("create table U(C int)", tx.clone())?;
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    for (a, b) in [(1, "x"), (2, "y"), (3, "z"), (4, "w")] {
+        let query = format!("insert into T(A, B) values ({}, '{}')", a, b);
+        planner.execute_update(&query, tx.clone())?;
+    }
+    for c in [1, 3] {
+        planner.execute_update(&format!("insert into U(C) values ({})", c), tx.clone())?;
+    }
+    planner.execute_update(
+        "create view V as select A, B from T where A = 1 or B = 'z'",
+        tx.clone(),
+    )?;
+
+    let mut select = |query: &str| -> Result<Vec<String>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push(scan.get_string("B")?);
+        }
+        scan.close();
+        Ok(rows)
+    };
+    let cases = [
+        ("select B from T where A = 1 or B = 'z'", vec!["x", "z"]),
+        (
+            "select B from T where A = 1 or A = 2 and B = 'x'",
+            vec!["x"],
+        ),
+        (
+            "select B from T where (A = 1 or A = 2) and B = 'y'",
+            vec!["y"],
+        ),
+        ("select B from T where not (A = 1)", vec!["y", "z", "w"]),
+        (
+            "select B from T where not (A = 1 or B = 'y')",
+            vec!["z", "w"],
+        ),
+        ("select B from T where not not A = 2", vec!["y"]),
+        ("select B from T where A = 1 or 1 = 2", vec!["x"]),
+        ("select B from T where A = 5 or A = 6", vec![]),
+        // NULL との比較は Unknown で、NOT を付けても Unknown のまま選ばれない
+        ("select B from T where A = 1 or A = null", vec!["x"]),
+        ("select B from T where not (A = null)", vec![]),
+        ("select B from T where not (A = null or A = 2)", vec![]),
+        ("select B from V where A = 3 or B = 'x'", vec!["x", "z"]),
+        (
+            "select B from T, U where A = C and (C = 3 or B = 'x')",
+            vec!["x", "z"],
+        ),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(select(query)?, expected, "{}", query);
+    }
+
+    assert_eq!(
+        planner.execute_update("delete from T where A = 2 or not (B = 'x')", tx.clone())?,
+        3
+    );
+    assert_eq!(
+        planner.execute_update("update T set A = 9 where A = 1 or A = 3", tx.clone())?,
+        1
+    );
+    let plan = planner.create_query_plan("select A from T where A = 9 or B = 'w'", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    assert!(scan.next()?);
+    assert_eq!(scan.get_int("A")?, 9);
+    assert!(!scan.next()?);
+    scan.close();
+    drop(scan);
+
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_migrate_hash_index_buckets() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_migrate_hash_index_buckets");
+    let (buckets, free_bucket) = {
+        let mut db = TinyDB::new(&test_directory, 400, 8)?;
+        db.init_planner()?;
+        let tx = db.transaction()?;
+        let planner = db.planner.clone().unwrap();
+        let mut planner = unlock!(planner);
+        planner.execute_update("create table T(A int)", tx.clone())?;
+        planner.execute_update("create index ix on T (A) using hash", tx.clone())?;
+        for i in 0..10 {
+            planner.execute_update(&format!("insert into T(A) values ({})", i), tx.clone())?;
+        }
+        // バケットは索引のファイルの名前空間に置くので、同じ名前のテーブルを作っても重ならない
+        let buckets: Vec<u64> = (0..NUM_BUCKETS)
+            .filter(|&bucket| {
+                unlock!(tx).file_exists(&format!("{}.tbl", bucket_table("ix", bucket)))
+            })
+            .collect();
+        assert!(!buckets.is_empty());
+        let free_bucket = (0..NUM_BUCKETS)
+            .find(|bucket| !buckets.contains(bucket))
+            .unwrap();
+        planner.execute_update(
+            &format!("create table ix{}(B int)", free_bucket),
+            tx.clone(),
+        )?;
+        planner.execute_update(
+            &format!("insert into ix{}(B) values (42)", free_bucket),
+            tx.clone(),
+        )?;
+        assert!(planner
+            .execute_update("create table $ix(B int)", tx.clone())
+            .is_err());
+        unlock!(tx).commit()?;
+        (buckets, free_bucket)
+    };
+
+    // バケットを `<index><bucket>.tbl` に置いていた版 2 のデータベースにする
+    for bucket in &buckets {
+        fs::rename(
+            test_directory.join(format!("{}.tbl", bucket_table("ix", *bucket))),
+            test_directory.join(format!("ix{}.tbl", bucket)),
+        )?;
+    }
+    ControlFile::new(2).write(&test_directory)?;
+
+    let mut db = TinyDB::new(&test_directory, 400, 8)?;
+    db.init_planner()?;
+    assert_eq!(db.catalog_version(), CATALOG_VERSION);
+    for bucket in &buckets {
+        assert!(!test_directory.join(format!("ix{}.tbl", bucket)).exists());
+    }
+    let tx = db.transaction()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    let mut select = |query: &str, field: &str| -> Result<Vec<i32>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut values = vec![];
+        while scan.next()? {
+            values.push(scan.get_int(field)?);
+        }
+        scan.close();
+        Ok(values)
+    };
+    // 移したバケットから索引で検索でき、バケットと名前の似たテーブルはそのまま残る
+    for i in 0..10 {
+        let query = format!("select /*+ use_index(T ix) */ A from T where A = {}", i);
+        assert_eq!(select(&query, "A")?, vec![i]);
+    }
+    let query = format!("select B from ix{}", free_bucket);
+    assert_eq!(select(&query, "B")?, vec![42]);
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_in_list() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_in_list");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(5))", tx.clone())?;
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    for (a, b) in [(1, "x"), (2, "y"), (3, "z"), (4, "w")] {
+        let query = format!("insert into T(A, B) values ({}, '{}')", a, b);
+        planner.execute_update(&query, tx.clone())?;
+    }
+
+    let mut select = |query: &str| -> Result<Vec<String>> {
+        let plan = planner.create_query_plan(query, tx.clone())?;
+        let scan = unlock!(plan).open()?;
+        let mut scan = unlock!(scan);
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push(scan.get_string("B")?);
+        }
+        scan.close();
+        Ok(rows)
+    };
+    let cases = [
+        ("select B from T where A in (1, 3)", vec!["x", "z"]),
+        ("select B from T where B in ('w', 'y', 'v')", vec!["y", "w"]),
+        ("select B from T where A in (2) and B = 'y'", vec!["y"]),
+        ("select B from T where A in (5, 6)", vec![]),
+        ("select B from T where A not in (1, 2)", vec!["z", "w"]),
+        (
+            "select B from T where A in (1) or B in ('w')",
+            vec!["x", "w"],
+        ),
+        // NULL を含むリストは一致しない値で Unknown になり、NOT IN は何も選ばない
+        ("select B from T where A in (1, null)", vec!["x"]),
+        ("select B from T where A not in (1, null)", vec![]),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(select(query)?, expected, "{}", query);
+    }
+
+    assert_eq!(
+        planner.execute_update("delete from T where A in (2, 4)", tx.clone())?,
+        2
+    );
+    assert_eq!(
+        planner.execute_update("delete from T where A in (1, 2, 3, 4)", tx.clone())?,
+        2
+    );
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+/// select_literals はクエリの結果のフィールドの値を、NULL を含めてリテラルの文字列にして返す
+fn select_literals(
+    planner: &mut Planner,
+    query: &str,
+    tx: Arc<Mutex<Transaction>>,
+) -> Result<Vec<String>> {
+    let plan = planner.create_query_plan(query, tx)?;
+    let fields = unlock!(plan).schema().fields.clone();
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut rows = vec![];
+    while scan.next()? {
+        let mut values = vec![];
+        for field in &fields {
+            values.push(scan.get_value(field)?.to_literal());
+        }
+        rows.push(values.join(", "));
+    }
+    scan.close();
+    Ok(rows)
+}
+
+#[test]
+fn test_planner_null_values() -> Result<()> {
+    let test_directory = tempdir()?.path().join("test_planner_null_values");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(5))", tx.clone())?;
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    planner.execute_update("create index t_b on T (B)", tx.clone())?;
+    for (a, b) in [
+        ("1", "'x'"),
+        ("null", "'y'"),
+        ("3", "null"),
+        ("null", "null"),
+    ] {
+        let query = format!("insert into T(A, B) values ({}, {})", a, b);
+        planner.execute_update(&query, tx.clone())?;
+    }
+    planner.execute_update(
+        "create view V as select A, B from T where B is null",
+        tx.clone(),
+    )?;
+
+    let cases = [
+        (
+            "select A, B from T",
+            vec!["1, 'x'", "NULL, 'y'", "3, NULL", "NULL, NULL"],
+        ),
+        ("select B from T where A is null", vec!["'y'", "NULL"]),
+        (
+            "select A from T where A is not null and B is null",
+            vec!["3"],
+        ),
+        ("select B from T where not (A is null)", vec!["'x'", "NULL"]),
+        ("select B from T where A = 1", vec!["'x'"]),
+        ("select A from T where B = 'y'", vec!["NULL"]),
+        // NULL との比較は Unknown なので、IN や NOT IN でも NULL の行は選ばれない
+        ("select B from T where A = null", vec![]),
+        ("select B from T where A <> 1", vec!["NULL"]),
+        ("select B from T where A not in (1)", vec!["NULL"]),
+        ("select A from V", vec!["3", "NULL"]),
+        ("select A from T where A is null and A = 1", vec![]),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(
+            select_literals(&mut planner, query, tx.clone())?,
+            expected,
+            "{}",
+            query
+        );
+    }
+
+    // NULL に書き換えた値と NULL から書き換えた値も索引で検索できる
+    assert_eq!(
+        planner.execute_update("update T set B = null where A = 1", tx.clone())?,
+        1
+    );
+    assert_eq!(
+        planner.execute_update("update T set A = 5 where B = 'y'", tx.clone())?,
+        1
+    );
+    let cases = [
+        ("select A from T where B is null", vec!["1", "3", "NULL"]),
+        ("select B from T where A = 5", vec!["'y'"]),
+        ("select A from T where B = 'x'", vec![]),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(
+            select_literals(&mut planner, query, tx.clone())?,
+            expected,
+            "{}",
+            query
+        );
+    }
+    assert_eq!(
+        planner.execute_update("delete from T where A is null", tx.clone())?,
+        1
+    );
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_insert_omitted_columns_are_null() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_insert_omitted_columns_are_null");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update(
+        "create table T(A int, B varchar(5), C boolean, D date)",
+        tx.clone(),
+    )?;
+    planner.execute_update("insert into T(A) values (1)", tx.clone())?;
+    planner.execute_update("insert into T(B, A) values ('x', 2)", tx.clone())?;
+
+    // 列の一覧にないフィールドは 0 や空文字列ではなく NULL になる
+    let cases = [
+        ("select A from T where B is null", vec!["1"]),
+        (
+            "select A from T where C is null and D is null",
+            vec!["1", "2"],
+        ),
+        ("select A from T where B is not null", vec!["2"]),
+        (
+            "select B, C, D from T where A = 1",
+            vec!["NULL, NULL, NULL"],
+        ),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(
+            select_literals(&mut planner, query, tx.clone())?,
+            expected,
+            "{}",
+            query
+        );
+    }
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_planner_create_index_on_null_values() -> Result<()> {
+    let test_directory = tempdir()?
+        .path()
+        .join("test_planner_create_index_on_null_values");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let planner = db.planner.clone().unwrap();
+    let mut planner = unlock!(planner);
+    planner.execute_update("create table T(A int, B varchar(5))", tx.clone())?;
+    for (a, b) in [("1", "'x'"), ("null", "'y'"), ("3", "null"), ("0", "''")] {
+        let query = format!("insert into T(A, B) values ({}, {})", a, b);
+        planner.execute_update(&query, tx.clone())?;
+    }
+
+    // 既存のレコードから索引を作るときも NULL は 0 や空文字列として格納しない
+    planner.execute_update("create index t_a on T (A) using btree", tx.clone())?;
+    planner.execute_update("create index t_b on T (B) using hash", tx.clone())?;
+    let md = MetadataManager::new(false, tx.clone())?;
+    let mut index_infos = md.get_index_info("T", tx.clone())?;
+    for index_name in ["t_a", "t_b"] {
+        let mut index = index_infos.remove(index_name).unwrap().open()?;
+        assert_eq!(index.entries()?.len(), 3, "{}", index_name);
+        index.close();
+    }
+    let cases = [
+        ("select B from T where A = 0", vec!["''"]),
+        ("select A from T where B = ''", vec!["0"]),
+        ("select B from T where A = 3", vec!["NULL"]),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(
+            select_literals(&mut planner, query, tx.clone())?,
+            expected,
+            "{}",
+            query
+        );
+    }
+    unlock!(tx).commit()?;
+    Ok(())
+}
@@ -27,7 +27,8 @@ fn record_test() {
     }
 
     let block = BlockId::new("testfile".to_string(), 0);
-    let mut record_page = RecordPage::new(transaction.clone(), block, layout.clone());
+    let mut record_page =
+        RecordPage::new(transaction.clone(), block.clone(), layout.clone()).unwrap();
     record_page.format().unwrap();
 
     // Insert records into the page until it's full
@@ -55,7 +56,7 @@ fn record_test() {
     let mut slot = -1;
 
     loop {
-        slot = record_page.next_after(slot);
+        slot = record_page.next_after(slot).unwrap();
 
         if slot < 0 {
             break;
@@ -77,7 +78,7 @@ fn record_test() {
     let mut slot = -1;
 
     loop {
-        slot = record_page.next_after(slot);
+        slot = record_page.next_after(slot).unwrap();
 
         if slot < 0 {
             break;
@@ -90,5 +91,47 @@ fn record_test() {
         assert!(a >= 10, "Assertion failed for remaining records",);
     }
 
+    transaction.lock().unwrap().unpin(&block);
     transaction.lock().unwrap().commit().unwrap();
 }
+
+#[test]
+fn record_rollback_test() {
+    let test_directory = tempdir().unwrap().path().join("record_rollback_test");
+    let db = TinyDB::new(test_directory, 400, 8).unwrap();
+
+    let mut schema = Schema::default();
+    schema.add_int_field("A".to_string());
+    schema.add_string_field("B".to_string(), 20);
+    let layout = Arc::new(Layout::try_from_schema(Arc::new(schema)).unwrap());
+    let block = BlockId::new("testfile".to_string(), 0);
+
+    let tx1 = db.transaction().unwrap();
+    let mut record_page = RecordPage::new(tx1.clone(), block.clone(), layout.clone()).unwrap();
+    record_page.format().unwrap();
+    let slot = record_page.insert_after(-1).unwrap();
+    record_page.set_int(slot, "A", 1).unwrap();
+    record_page.set_string(slot, "B", "one".into()).unwrap();
+    tx1.lock().unwrap().unpin(&block);
+    tx1.lock().unwrap().commit().unwrap();
+
+    // セルの移動を伴う変更もロールバックで元に戻る
+    let tx2 = db.transaction().unwrap();
+    let mut record_page = RecordPage::new(tx2.clone(), block.clone(), layout.clone()).unwrap();
+    record_page
+        .set_string(slot, "B", "one hundred".into())
+        .unwrap();
+    let new_slot = record_page.insert_after(slot).unwrap();
+    record_page.set_int(new_slot, "A", 2).unwrap();
+    assert_eq!(record_page.get_string(slot, "B").unwrap(), "one hundred");
+    tx2.lock().unwrap().rollback().unwrap();
+
+    let tx3 = db.transaction().unwrap();
+    let record_page = RecordPage::new(tx3.clone(), block, layout).unwrap();
+    assert_eq!(record_page.slot_count().unwrap(), 1);
+    assert_eq!(record_page.get_int(slot, "A").unwrap(), 1);
+    assert_eq!(record_page.get_string(slot, "B").unwrap(), "one");
+    assert_eq!(record_page.next_after(slot).unwrap(), -1);
+    tx3.lock().unwrap().unpin(&record_page.block);
+    tx3.lock().unwrap().commit().unwrap();
+}
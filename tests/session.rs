@@ -0,0 +1,353 @@
+use anyhow::Result;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tempfile::tempdir;
+use tinydb::{
+    file::block::BlockId,
+    query::constant::Constant,
+    record::schema::FieldTypes,
+    server::{
+        db::{DbConfig, TinyDB},
+        retry::RetryPolicy,
+        session::{ExecuteResult, Session},
+    },
+    timeout::{Timeouts, WaitTimeout},
+    tx::transaction::Transaction,
+};
+
+fn rows(result: ExecuteResult) -> Vec<Vec<Constant>> {
+    match result {
+        ExecuteResult::Rows { rows, .. } => rows,
+        result => panic!("expected rows, found {:?}", result),
+    }
+}
+
+#[test]
+fn session_cursor_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_cursor_test");
+    let mut db = TinyDB::new(&test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut session = Session::new(&db)?;
+
+    session.execute("create table T(A int, B varchar(10))")?;
+    for i in 0..25 {
+        let query = format!("insert into T(A, B) values ({}, 'rec{}')", i, i);
+        assert_eq!(session.execute(&query)?, ExecuteResult::Updated(1));
+    }
+
+    assert_eq!(
+        session.execute("declare cursor c for select A, B from T")?,
+        ExecuteResult::Updated(25)
+    );
+    assert!(session
+        .execute("declare cursor c for select A from T")
+        .is_err());
+
+    // カーソルを開いている間も元のテーブルを更新できる
+    session.execute("update T set B = 'changed' where A = 12")?;
+    session.execute("delete from T where A = 20")?;
+
+    let mut fetched = vec![];
+    loop {
+        let page = rows(session.execute("fetch 10 from c")?);
+        assert!(page.len() <= 10);
+        if page.is_empty() {
+            break;
+        }
+        fetched.extend(page);
+    }
+    assert_eq!(fetched.len(), 25);
+    for (i, row) in fetched.iter().enumerate() {
+        // カーソルは宣言したときの結果を返す
+        assert_eq!(
+            row,
+            &vec![
+                Constant::Int(i as i32),
+                Constant::String(format!("rec{}", i))
+            ]
+        );
+    }
+
+    session.execute("close c")?;
+    assert!(session.execute("fetch 10 from c").is_err());
+    assert!(session.cursor_names().is_empty());
+    // カーソルを閉じると一時テーブルのファイルも削除される
+    let temp_files = std::fs::read_dir(&test_directory)?
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with("temp")
+        })
+        .count();
+    assert_eq!(temp_files, 0);
+
+    let result = session.execute("select B from T where A = 12")?;
+    let ExecuteResult::Rows { metadata, rows } = result else {
+        panic!("expected rows, found {:?}", result);
+    };
+    assert_eq!(metadata.field_names(), vec!["B".to_string()]);
+    assert_eq!(rows, vec![vec![Constant::String("changed".into())]]);
+
+    Ok(())
+}
+
+#[test]
+fn session_grant_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_grant_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut admin = Session::new(&db)?;
+    let mut alice = Session::new(&db)?;
+    alice.set_user(Some("alice".into()));
+
+    admin.execute("create table T(A int)")?;
+    admin.execute("insert into T(A) values (1)")?;
+    assert!(alice.execute("select A from T").is_err());
+    assert!(alice.execute("grant select on T to alice").is_err());
+
+    admin.execute("grant select, insert on T to alice")?;
+    alice.execute("insert into T(A) values (2)")?;
+    assert_eq!(rows(alice.execute("select A from T")?).len(), 2);
+    assert!(alice.execute("delete from T where A = 1").is_err());
+    assert_eq!(
+        rows(alice.execute("show grants on T")?),
+        vec![
+            vec![
+                Constant::String("alice".into()),
+                Constant::String("T".into()),
+                Constant::String("select".into())
+            ],
+            vec![
+                Constant::String("alice".into()),
+                Constant::String("T".into()),
+                Constant::String("insert".into())
+            ],
+        ]
+    );
+
+    assert_eq!(
+        admin.execute("revoke all on T from alice")?,
+        ExecuteResult::Updated(2)
+    );
+    assert!(alice.execute("select A from T").is_err());
+
+    // テーブルを作ったユーザーはすべての権限を持つ
+    alice.execute("create table U(B int)")?;
+    alice.execute("insert into U(B) values (1)")?;
+    alice.execute("delete from U where B = 1")?;
+
+    Ok(())
+}
+
+#[test]
+fn session_create_existing_name_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_create_existing_name_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut admin = Session::new(&db)?;
+    let mut mallory = Session::new(&db)?;
+    mallory.set_user(Some("mallory".into()));
+
+    admin.execute("create table secret(X int)")?;
+    admin.execute("insert into secret(X) values (42)")?;
+    admin.execute("create view secret_view as select X from secret")?;
+    assert!(mallory.execute("select X from secret").is_err());
+
+    // 既存のテーブルやビュー、カタログと同じ名前では作れず、権限も付与されない
+    assert!(mallory.execute("create table secret(Y int)").is_err());
+    assert!(mallory.execute("create table secret_view(Y int)").is_err());
+    assert!(mallory.execute("create table tblcat(Y int)").is_err());
+    assert!(admin
+        .execute("create view privcat as select X from secret")
+        .is_err());
+    assert!(admin
+        .execute("create view secret as select X from secret")
+        .is_err());
+    assert!(admin
+        .execute("create view secret_view as select X from secret")
+        .is_err());
+    assert!(mallory.execute("select X from secret").is_err());
+    assert!(rows(mallory.execute("show grants on secret")?).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn session_retry_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_retry_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut session = Session::new(&db)?;
+    session.execute("create table T(A int)")?;
+    session.execute("insert into T(A) values (1)")?;
+
+    // 別のトランザクションがタイムアウトより長くテーブルのブロックの共有ロックを持っている
+    let hold_lock = |tx: Arc<Mutex<Transaction>>| {
+        let block = BlockId::new("T.tbl".into(), 0);
+        let mut tx = tx.lock().unwrap();
+        tx.pin(&block).unwrap();
+        tx.get_int(&block, 0).unwrap();
+    };
+    let tx = db.transaction()?;
+    hold_lock(tx.clone());
+    assert!(session.execute("insert into T(A) values (2)").is_err());
+    tx.lock().unwrap().rollback()?;
+
+    // リトライポリシーを設定すると、ロックが解放された後にやり直して成功する
+    session.set_retry_policy(RetryPolicy::with_max_attempts(2));
+    let tx = db.transaction()?;
+    hold_lock(tx.clone());
+    let holder = thread::spawn(move || {
+        thread::sleep(Duration::from_secs(4));
+        tx.lock().unwrap().rollback().unwrap();
+    });
+    assert_eq!(
+        session.execute("insert into T(A) values (3)")?,
+        ExecuteResult::Updated(1)
+    );
+    holder.join().unwrap();
+    let result = session.execute("select A from T")?;
+    assert_eq!(
+        rows(result),
+        vec![vec![Constant::Int(1)], vec![Constant::Int(3)]]
+    );
+    Ok(())
+}
+
+#[test]
+fn session_read_retry_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_read_retry_test");
+    let timeouts = Timeouts {
+        s_lock: Duration::from_millis(500),
+        ..Timeouts::default()
+    };
+    let config = DbConfig::new(400, 8).with_timeouts(timeouts);
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    let mut session = Session::new(&db)?;
+    session.execute("create table T(A int)")?;
+    session.execute("insert into T(A) values (1)")?;
+
+    // 別のトランザクションがテーブルのブロックに書き込んで排他ロックを持っている
+    let hold_lock = |tx: Arc<Mutex<Transaction>>| {
+        let block = BlockId::new("T.tbl".into(), 0);
+        let mut tx = tx.lock().unwrap();
+        tx.pin(&block).unwrap();
+        let value = tx.get_int(&block, 0).unwrap();
+        tx.set_int(&block, 0, value, false).unwrap();
+    };
+    let tx = db.transaction()?;
+    hold_lock(tx.clone());
+    // 読み込みは共有ロックを待ってタイムアウトする
+    let err = session.execute("select A from T").unwrap_err();
+    assert!(err.downcast_ref::<WaitTimeout>().is_some());
+    tx.lock().unwrap().rollback()?;
+
+    // リトライポリシーを設定すると、ロックが解放された後に読み直して成功する
+    session.set_retry_policy(RetryPolicy::with_max_attempts(3));
+    let tx = db.transaction()?;
+    hold_lock(tx.clone());
+    let holder = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(700));
+        tx.lock().unwrap().rollback().unwrap();
+    });
+    let result = session.execute("select A from T")?;
+    holder.join().unwrap();
+    assert_eq!(rows(result), vec![vec![Constant::Int(1)]]);
+    Ok(())
+}
+
+#[test]
+fn session_explain_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_explain_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut session = Session::new(&db)?;
+
+    session.execute("create table T(A int, B varchar(10))")?;
+    session.execute("insert into T(A, B) values (1, 'x')")?;
+    session.execute("create index t_a on T (A) using btree")?;
+
+    let result = session.execute("explain select B from T where A = 1")?;
+    let ExecuteResult::Rows { metadata, rows } = result else {
+        panic!("expected rows, found {:?}", result);
+    };
+    assert_eq!(metadata.field_names(), vec!["plan".to_string()]);
+    let lines: Vec<String> = rows.into_iter().map(|row| row[0].to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "Project B",
+            "  Select where A = 1 (blocks=1, records=1)",
+            "    TableScan T (blocks=1, records=1)",
+            "      rejected: IndexSelect T using t_a where A = 1 (blocks=2, records=1)",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn session_listen_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_listen_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut listener = Session::new(&db)?;
+    let mut writer = Session::new(&db)?;
+
+    writer.execute("create table T(A int)")?;
+    writer.execute("create table U(A int)")?;
+    assert!(listener.notifications().is_empty());
+    listener.execute("listen T")?;
+
+    writer.execute("insert into T(A) values (1)")?;
+    writer.execute("insert into U(A) values (1)")?;
+    // 失敗した文はロールバックされるので知らせは届かない
+    assert!(writer.execute("insert into T(X) values (1)").is_err());
+    let notifications = listener.notifications();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].table_name, "T");
+    assert!(listener.notifications().is_empty());
+
+    listener.execute("unlisten T")?;
+    writer.execute("delete from T where A = 1")?;
+    assert!(listener.notifications().is_empty());
+    Ok(())
+}
+
+#[test]
+fn session_as_of_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("session_as_of_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let mut session = Session::new(&db)?;
+
+    session.execute("create table T(A int, B varchar(5))")?;
+    session.execute("insert into T(A, B) values (1, 'old')")?;
+    let lsn = db.log_manager.lock().unwrap().latest_lsn();
+    session.execute("update T set B = 'new' where A = 1")?;
+    session.execute("insert into T(A, B) values (2, 'x')")?;
+
+    let result = session.execute(&format!("select A, B from T as of lsn {}", lsn))?;
+    let ExecuteResult::Rows { metadata, rows } = result else {
+        panic!("expected rows, found {:?}", result);
+    };
+    assert_eq!(
+        metadata.field_names(),
+        vec!["A".to_string(), "B".to_string()]
+    );
+    // 列の情報はテーブルの定義から分かる
+    assert_eq!(metadata.columns()[0].field_type, FieldTypes::Integer);
+    assert_eq!(metadata.columns()[1].length, 5);
+    assert_eq!(metadata.columns()[1].display_size(), 5);
+    assert_eq!(
+        rows,
+        vec![vec![Constant::Int(1), Constant::String("old".into())]]
+    );
+    let ExecuteResult::Rows { rows, .. } = session.execute("select A, B from T")? else {
+        panic!("expected rows");
+    };
+    assert_eq!(rows.len(), 2);
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{io::Read, sync::Arc};
 
 use anyhow::Result;
 use tempfile::tempdir;
@@ -56,3 +56,78 @@ fn table_scan_test() -> Result<()> {
     tx.lock().unwrap().commit()?;
     Ok(())
 }
+
+/// Pattern は長さ len の決まったパターンのバイト列を少しずつ返す Reader
+struct Pattern {
+    pos: usize,
+    len: usize,
+}
+
+impl Read for Pattern {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.len - self.pos);
+        for (i, b) in buf[..n].iter_mut().enumerate() {
+            *b = ((self.pos + i) % 251) as u8;
+        }
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn table_scan_blob_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("table_scan_blob_test");
+    let db = TinyDB::new(test_directory, 400, 8)?;
+    let tx = db.transaction()?;
+
+    let mut sch = Schema::default();
+    sch.add_int_field("id");
+    sch.add_text_field("body");
+    sch.add_blob_field("data");
+    let layout = Arc::new(Layout::try_from_schema(Arc::new(sch))?);
+
+    let len = 100_000;
+    let mut ts = TableScan::new(tx.clone(), "docs", layout)?;
+    for n in 0..3 {
+        ts.insert()?;
+        ts.set_int("id", n)?;
+        ts.set_string("body", &format!("document {}", n))?;
+        ts.set_blob("data", &mut Pattern { pos: 0, len })?;
+    }
+    // 値を書かなかったフィールドは空
+    ts.insert()?;
+    ts.set_int("id", 3)?;
+
+    ts.before_first();
+    let mut count = 0;
+    while ts.next()? {
+        let id = ts.get_int("id")?;
+        if id == 3 {
+            assert_eq!(ts.get_string("body")?, "");
+            assert_eq!(ts.get_blob_reader("data")?.read(&mut [0; 10])?, 0);
+            continue;
+        }
+        assert_eq!(ts.get_string("body")?, format!("document {}", id));
+
+        let mut reader = ts.get_blob_reader("data")?;
+        let mut buf = [0; 1000];
+        let mut pos = 0;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            for (i, b) in buf[..n].iter().enumerate() {
+                assert_eq!(*b, ((pos + i) % 251) as u8);
+            }
+            pos += n;
+        }
+        assert_eq!(pos, len);
+        count += 1;
+    }
+    assert_eq!(count, 3);
+
+    ts.close();
+    tx.lock().unwrap().commit()?;
+    Ok(())
+}
@@ -1,5 +1,30 @@
+use anyhow::{anyhow, Result};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use tempfile::tempdir;
-use tinydb::{file::block::BlockId, server::db::TinyDB, tx::transaction::Transaction};
+use tinydb::{
+    clock::MockClock,
+    file::{block::BlockId, date::Date},
+    log::log_manager::DEFAULT_LOG_FILE,
+    plan::planner::Planner,
+    query::scan::Scan as _,
+    record::{schema::Schema, temp_table::TempTable},
+    server::db::{DbConfig, TinyDB},
+    timeout::{TimeoutKind, Timeouts, WaitTimeout},
+    tx::commit_listener::CommitEvent,
+    tx::concurrency::lock_timeout::LockTimeout,
+    tx::recovery::record::{create_log_record, LogRecordType},
+    tx::transaction::Transaction,
+    unlock,
+};
+
+/// owned_transaction は TinyDB が作ったトランザクションを共有せずに直接使うために取り出す
+fn owned_transaction(db: &TinyDB) -> Result<Transaction> {
+    let tx = Arc::try_unwrap(db.transaction()?).map_err(|_| anyhow!("transaction is shared"))?;
+    Ok(tx.into_inner().unwrap())
+}
 
 #[test]
 fn tx_test() {
@@ -19,9 +44,10 @@ fn tx_test() {
     .unwrap();
 
     let block = BlockId::new("testfile".into(), 1);
-    tx1.pin(&block);
+    tx1.pin(&block).unwrap();
     tx1.set_int(&block, 80, 1, false).unwrap();
     tx1.set_string(&block, 40, "one".into(), false).unwrap();
+    tx1.unpin(&block);
     tx1.commit().unwrap();
 
     let mut tx2 = Transaction::new(
@@ -31,9 +57,9 @@ fn tx_test() {
         lock_table.clone(),
     )
     .unwrap();
-    tx2.pin(&block);
-    let ivalue = tx2.get_int(&block, 80);
-    let svalue = tx2.get_string(&block, 40);
+    tx2.pin(&block).unwrap();
+    let ivalue = tx2.get_int(&block, 80).unwrap();
+    let svalue = tx2.get_string(&block, 40).unwrap();
     assert_eq!(ivalue, 1);
     assert_eq!(svalue, "one");
     println!("initial value at location 80 = {}", ivalue);
@@ -43,6 +69,7 @@ fn tx_test() {
     let newsvalue = svalue + "!";
     tx2.set_int(&block, 80, newvalue, false).unwrap();
     tx2.set_string(&block, 40, newsvalue, false).unwrap();
+    tx2.unpin(&block);
     tx2.commit().unwrap();
 
     let mut tx3 = Transaction::new(
@@ -52,9 +79,9 @@ fn tx_test() {
         lock_table.clone(),
     )
     .unwrap();
-    tx3.pin(&block);
-    let ivalue = tx3.get_int(&block, 80);
-    let svalue = tx3.get_string(&block, 40);
+    tx3.pin(&block).unwrap();
+    let ivalue = tx3.get_int(&block, 80).unwrap();
+    let svalue = tx3.get_string(&block, 40).unwrap();
     assert_eq!(ivalue, 2);
     assert_eq!(svalue, "one!");
     println!("new value at location 80 = {}", ivalue);
@@ -63,7 +90,7 @@ fn tx_test() {
     tx3.set_int(&block, 80, 9999, true).unwrap();
     println!(
         "pre-rollback value at location 80 = {}",
-        tx3.get_int(&block, 80)
+        tx3.get_int(&block, 80).unwrap()
     );
     tx3.rollback().unwrap();
 
@@ -74,10 +101,826 @@ fn tx_test() {
         lock_table.clone(),
     )
     .unwrap();
-    tx4.pin(&block);
+    tx4.pin(&block).unwrap();
     println!(
         "post-rollback value at location 80 = {}",
-        tx4.get_int(&block, 80)
+        tx4.get_int(&block, 80).unwrap()
     );
+    tx4.unpin(&block);
     tx4.commit().unwrap();
 }
+
+#[test]
+fn commit_listener_test() {
+    let test_directory = tempdir().unwrap().path().join("commit_listener_test");
+    let db = TinyDB::new(test_directory, 400, 8).unwrap();
+    let events: Arc<Mutex<Vec<CommitEvent>>> = Default::default();
+    let db_events = events.clone();
+    db.on_commit(move |event| db_events.lock().unwrap().push(event.clone()));
+
+    let tx = db.transaction().unwrap();
+    let hook_tables: Arc<Mutex<Vec<String>>> = Default::default();
+    let tx_tables = hook_tables.clone();
+    let mut tx = tx.lock().unwrap();
+    tx.on_commit(move |event| tx_tables.lock().unwrap().clone_from(&event.tables));
+
+    for filename in ["student.tbl", "dept.tbl", "idx.leaf"] {
+        let block = tx.append(filename.into()).unwrap();
+        tx.pin(&block).unwrap();
+        tx.set_int(&block, 80, 1, true).unwrap();
+        tx.unpin(&block);
+    }
+    tx.commit().unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].tables, vec!["dept", "student"]);
+    assert_eq!(*hook_tables.lock().unwrap(), vec!["dept", "student"]);
+}
+
+#[test]
+fn temp_file_test() {
+    let test_directory = tempdir().unwrap().path().join("temp_file_test");
+    let db = TinyDB::new(&test_directory, 400, 8).unwrap();
+
+    for commit in [true, false] {
+        let tx = db.transaction().unwrap();
+        let filename = {
+            let mut tx = tx.lock().unwrap();
+            let filename = tx.create_temp_file();
+            let block = tx.append(filename.clone()).unwrap();
+            tx.pin(&block).unwrap();
+            tx.set_int(&block, 0, 42, false).unwrap();
+            tx.unpin(&block);
+            filename
+        };
+        assert!(test_directory.join(&filename).exists());
+
+        let mut schema = Schema::default();
+        schema.add_int_field("A");
+        let table = TempTable::for_transaction(tx.clone(), Arc::new(schema)).unwrap();
+        let mut scan = table.open(tx.clone()).unwrap();
+        scan.insert().unwrap();
+        scan.set_int("A", 1).unwrap();
+        scan.close();
+        let table_file = test_directory.join(format!("{}.tbl", table.table_name));
+        assert!(table_file.exists());
+
+        let mut tx = tx.lock().unwrap();
+        if commit {
+            tx.commit().unwrap();
+        } else {
+            tx.rollback().unwrap();
+        }
+        assert!(!test_directory.join(&filename).exists());
+        assert!(!table_file.exists());
+    }
+    assert!(db.buffer_manager.dirty_pages().is_empty());
+}
+
+#[test]
+fn with_transaction_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("with_transaction_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx)
+    })?;
+
+    // ロックのタイムアウトで失敗した試行はロールバックされ、やり直した試行だけがコミットされる
+    let mut attempts = 0;
+    let updated = db.with_transaction(|tx, planner| {
+        attempts += 1;
+        let mut updated = 0;
+        for i in 0..3 {
+            let sql = format!("insert into T(A) values ({})", i + attempts * 10);
+            updated += unlock!(planner).execute_update(&sql, tx.clone())?;
+        }
+        if attempts == 1 {
+            return Err(anyhow!(LockTimeout).context("insert failed"));
+        }
+        Ok(updated)
+    })?;
+    assert_eq!((attempts, updated), (2, 3));
+
+    // ロックのタイムアウト以外のエラーはやり直さない
+    let mut attempts = 0;
+    let result = db.with_transaction(|tx, planner| {
+        attempts += 1;
+        unlock!(planner).execute_update("insert into T(A) values (99)", tx.clone())?;
+        unlock!(planner).execute_update("insert into X(A) values (1)", tx)
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+
+    let values = db.with_transaction(|tx, planner| {
+        let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+        // プランナーのロックは文ごとに取るので、読んでいる間もほかのスレッドがプランナーを使える
+        assert!(planner.try_lock().is_ok());
+        let scan = plan.lock().unwrap().open()?;
+        let mut scan = scan.lock().unwrap();
+        let mut values = vec![];
+        while scan.next()? {
+            values.push(scan.get_int("A")?);
+        }
+        scan.close();
+        Ok(values)
+    })?;
+    assert_eq!(values, vec![20, 21, 22]);
+    Ok(())
+}
+
+#[test]
+fn mock_clock_lock_timeout_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("mock_clock_lock_timeout_test");
+    let clock = Arc::new(MockClock::default());
+    let config = DbConfig::new(400, 8).with_clock(clock.clone());
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx)
+    })?;
+
+    // 別のトランザクションが共有ロックを持っていると、挿入はタイムアウトする
+    let holder = db.transaction()?;
+    let block = BlockId::new("T.tbl".into(), 0);
+    holder.lock().unwrap().pin(&block).unwrap();
+    holder.lock().unwrap().get_int(&block, 0).unwrap();
+
+    let start = Instant::now();
+    let result = db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+    });
+    let err = result.unwrap_err();
+    assert!(err.downcast_ref::<LockTimeout>().is_some());
+    // 時計を進めるだけなので実際には待たない
+    assert!(clock.elapsed() >= Duration::from_secs(3));
+    assert!(start.elapsed() < Duration::from_secs(3));
+    holder.lock().unwrap().rollback()?;
+    Ok(())
+}
+
+#[test]
+fn leaked_pin_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("leaked_pin_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx.clone())?;
+        unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+    })?;
+    assert_eq!(db.buffer_manager.pinned_count(), 0);
+
+    // 閉じたスキャンはピンを残さない
+    let tx = db.transaction()?;
+    let plan =
+        unlock!(db.planner.as_ref().unwrap()).create_query_plan("select A from T", tx.clone())?;
+    let scan = unlock!(plan).open()?;
+    assert!(unlock!(scan).next()?);
+    assert!(!unlock!(tx).pins().is_empty());
+    unlock!(scan).close();
+    assert!(unlock!(tx).pins().is_empty());
+    assert_eq!(db.buffer_manager.pinned_count(), 0);
+    unlock!(tx).commit()?;
+
+    // デバッグビルドでは、ピンを残したままコミットするとパニックする
+    if cfg!(debug_assertions) {
+        let mut tx = owned_transaction(&db)?;
+        let block = BlockId::new("T.tbl".into(), 0);
+        tx.pin(&block).unwrap();
+        assert_eq!(tx.pins(), vec![block.clone()]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tx.commit()));
+        assert!(result.is_err());
+        tx.rollback()?;
+    }
+    assert_eq!(db.buffer_manager.pinned_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn single_threaded_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("single_threaded_test");
+    let config = DbConfig::new(400, 8).single_threaded();
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx)
+    })?;
+
+    // ロックを取らないので、ほかのトランザクションが読んだブロックにも待たずに書き込める
+    let reader = db.transaction()?;
+    let block = BlockId::new("T.tbl".into(), 0);
+    reader.lock().unwrap().pin(&block).unwrap();
+    reader.lock().unwrap().get_int(&block, 0).unwrap();
+    let start = Instant::now();
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+    })?;
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert_eq!(db.lock_table.0.lock().unwrap().get_lock_value(&block), 0);
+    reader.lock().unwrap().unpin(&block);
+    reader.lock().unwrap().commit()?;
+
+    let count = db.with_transaction(|tx, planner| {
+        let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+        let scan = plan.lock().unwrap().open()?;
+        let mut count = 0;
+        while scan.lock().unwrap().next()? {
+            count += 1;
+        }
+        scan.lock().unwrap().close();
+        Ok(count)
+    })?;
+    assert_eq!(count, 1);
+    Ok(())
+}
+
+#[test]
+fn configured_lock_timeout_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("configured_lock_timeout_test");
+    let clock = Arc::new(MockClock::default());
+    let timeouts = Timeouts {
+        x_lock: Duration::from_millis(200),
+        ..Timeouts::default()
+    };
+    let config = DbConfig::new(400, 8)
+        .with_clock(clock.clone())
+        .with_timeouts(timeouts);
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx)
+    })?;
+
+    let holder = db.transaction()?;
+    let block = BlockId::new("T.tbl".into(), 0);
+    holder.lock().unwrap().pin(&block).unwrap();
+    holder.lock().unwrap().get_int(&block, 0).unwrap();
+
+    // 挿入は排他ロックを待つので、排他ロックのタイムアウトだけ待って失敗する
+    let err = db
+        .with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+        })
+        .unwrap_err();
+    let timeout = err.downcast_ref::<WaitTimeout>().unwrap();
+    assert_eq!(timeout.kind, TimeoutKind::XLock);
+    assert_eq!(timeout.timeout, timeouts.x_lock);
+    assert!(err.downcast_ref::<LockTimeout>().is_some());
+    // 一時的なエラーなので3回やり直すが、どれも既定の3秒より短く待つ
+    assert!(clock.elapsed() < Duration::from_secs(3));
+    holder.lock().unwrap().rollback()?;
+    Ok(())
+}
+
+#[test]
+fn read_lock_timeout_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("read_lock_timeout_test");
+    let clock = Arc::new(MockClock::default());
+    let timeouts = Timeouts {
+        s_lock: Duration::from_millis(200),
+        ..Timeouts::default()
+    };
+    let config = DbConfig::new(400, 8)
+        .with_clock(clock.clone())
+        .with_timeouts(timeouts);
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx.clone())?;
+        unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+    })?;
+
+    // 別のトランザクションがブロックに書き込んで排他ロックを持っている
+    let writer = db.transaction()?;
+    let block = BlockId::new("T.tbl".into(), 0);
+    writer.lock().unwrap().pin(&block)?;
+    let value = writer.lock().unwrap().get_int(&block, 0)?;
+    writer.lock().unwrap().set_int(&block, 0, value, false)?;
+
+    // 読み込みは共有ロックを待ってタイムアウトし、パニックせずにエラーを返す
+    let mut reader = owned_transaction(&db)?;
+    reader.pin(&block)?;
+    let err = reader.get_int(&block, 0).unwrap_err();
+    let timeout = err.downcast_ref::<WaitTimeout>().unwrap();
+    assert_eq!(timeout.kind, TimeoutKind::SLock);
+    assert!(err.downcast_ref::<LockTimeout>().is_some());
+    assert!(reader.get_string(&block, 4).is_err());
+    assert!(reader.get_long(&block, 0).is_err());
+    assert!(reader.get_date(&block, 0).is_err());
+    reader.unpin(&block);
+    reader.rollback()?;
+
+    // スキャンを通した読み込みもエラーになる
+    let err = db
+        .with_transaction(|tx, planner| {
+            let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+            let scan = plan.lock().unwrap().open()?;
+            let result = scan.lock().unwrap().next();
+            scan.lock().unwrap().close();
+            result
+        })
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<WaitTimeout>().unwrap().kind,
+        TimeoutKind::SLock
+    );
+    assert!(clock.elapsed() < Duration::from_secs(3));
+
+    writer.lock().unwrap().unpin(&block);
+    writer.lock().unwrap().rollback()?;
+    Ok(())
+}
+
+#[test]
+fn recover_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("recover_test");
+    let select = |db: &mut TinyDB| {
+        db.with_transaction(|tx, planner| {
+            let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+            let scan = plan.lock().unwrap().open()?;
+            let mut scan = scan.lock().unwrap();
+            let mut values = vec![];
+            while scan.next()? {
+                values.push(scan.get_int("A")?);
+            }
+            scan.close();
+            Ok(values)
+        })
+    };
+    {
+        let mut db = TinyDB::new(test_directory.clone(), 400, 8)?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("create table T(A int)", tx)
+        })?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("insert into T(A) values (1)", tx.clone())?;
+            unlock!(planner).execute_update("insert into T(A) values (2)", tx)
+        })?;
+    }
+
+    // 開き直したときの復旧でコミット済みの変更は取り消されず、復旧で取ったロックも残らない
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    assert_eq!(select(&mut db)?, vec![1, 2]);
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("insert into T(A) values (3)", tx)
+    })?;
+    assert_eq!(select(&mut db)?, vec![1, 2, 3]);
+    Ok(())
+}
+
+#[test]
+fn recover_truncate_backup_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("recover_truncate_backup_test");
+    let backup = {
+        let mut db = TinyDB::new(&test_directory, 400, 8)?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("create table T(A int)", tx.clone())?;
+            unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+        })?;
+        let mut tx = owned_transaction(&db)?;
+        tx.truncate_file("T.tbl")?;
+        let backup = format!("~{}_0_T.tbl", tx.tx_num());
+        assert!(test_directory.join(&backup).exists());
+        tx.commit()?;
+        assert!(!test_directory.join(&backup).exists());
+        backup
+    };
+
+    // コミットのレコードを書いてから元の内容を削除するまでの間に止まった状態を作る
+    std::fs::write(test_directory.join(&backup), [0; 400])?;
+    let mut db = TinyDB::new(&test_directory, 400, 8)?;
+    db.init_planner()?;
+    assert!(!test_directory.join(&backup).exists());
+    let count = db.with_transaction(|tx, planner| {
+        let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+        let scan = plan.lock().unwrap().open()?;
+        let mut scan = scan.lock().unwrap();
+        let mut count = 0;
+        while scan.next()? {
+            count += 1;
+        }
+        scan.close();
+        Ok(count)
+    })?;
+    assert_eq!(count, 0);
+    Ok(())
+}
+
+#[test]
+fn separate_log_dir_test() -> Result<()> {
+    let root = tempdir()?;
+    let (data_dir, log_dir) = (root.path().join("data"), root.path().join("wal"));
+    let config = || {
+        DbConfig::new(400, 8)
+            .with_log_dir(&log_dir)
+            .with_log_file("data.log")
+    };
+    {
+        let mut db = TinyDB::with_config(&data_dir, config())?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("create table T(A int)", tx)
+        })?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("insert into T(A) values (1)", tx)
+        })?;
+        assert_eq!(unlock!(db.log_manager).log_file(), "data.log");
+    }
+    // ログはデータベースのディレクトリではなく、設定したディレクトリに設定した名前で置かれる
+    assert!(log_dir.join("data.log").exists());
+    assert!(!data_dir.join("data.log").exists());
+    assert!(!data_dir.join(DEFAULT_LOG_FILE).exists());
+
+    // 開き直すと同じログを読んで復旧し、ログの続きに書き込む
+    let mut db = TinyDB::with_config(&data_dir, config())?;
+    db.init_planner()?;
+    let latest = unlock!(db.log_manager).latest_lsn();
+    assert!(latest > 0);
+    let values = db.with_transaction(|tx, planner| {
+        let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+        let scan = plan.lock().unwrap().open()?;
+        let mut scan = scan.lock().unwrap();
+        let mut values = vec![];
+        while scan.next()? {
+            values.push(scan.get_int("A")?);
+        }
+        scan.close();
+        Ok(values)
+    })?;
+    assert_eq!(values, vec![1]);
+    assert!(unlock!(db.log_manager).latest_lsn() > latest);
+    assert!(!data_dir.join(DEFAULT_LOG_FILE).exists());
+    Ok(())
+}
+
+#[test]
+fn segment_blocks_reopen_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("segment_blocks_reopen_test");
+    let select = |db: &TinyDB| {
+        db.with_transaction(|tx, planner| {
+            let plan = unlock!(planner).create_query_plan("select A from T", tx)?;
+            let scan = plan.lock().unwrap().open()?;
+            let mut scan = scan.lock().unwrap();
+            let mut values = vec![];
+            while scan.next()? {
+                values.push(scan.get_int("A")?);
+            }
+            scan.close();
+            Ok(values)
+        })
+    };
+    {
+        let config = DbConfig::new(400, 8).with_segment_blocks(2);
+        let mut db = TinyDB::with_config(&test_directory, config)?;
+        db.init_planner()?;
+        db.with_transaction(|tx, planner| {
+            unlock!(planner).execute_update("create table T(A int, B varchar(50))", tx.clone())?;
+            for n in 0..50 {
+                let query = format!("insert into T(A, B) values ({}, 'record')", n);
+                unlock!(planner).execute_update(&query, tx.clone())?;
+            }
+            Ok(())
+        })?;
+        assert_eq!(select(&db)?.len(), 50);
+    }
+    assert!(test_directory.join("T.tbl.1").exists());
+    assert!(!test_directory.join("T.tbl").exists());
+
+    // セグメントのブロック数はコントロールファイルに記録するので、設定せずに開き直しても読める
+    let mut db = TinyDB::new(&test_directory, 400, 8)?;
+    db.init_planner()?;
+    assert_eq!(select(&db)?, (0..50).collect::<Vec<_>>());
+    assert!(!test_directory.join("T.tbl").exists());
+    drop(db);
+
+    // 記録したものと異なるブロック数では開けない
+    let config = DbConfig::new(400, 8).with_segment_blocks(4);
+    assert!(TinyDB::with_config(&test_directory, config).is_err());
+    Ok(())
+}
+
+#[test]
+fn typed_values_undo_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("typed_values_undo_test");
+    let block = BlockId::new("testfile".into(), 1);
+    let date = Date::from_ymd(2024, 2, 29)?;
+    let set_values = |tx: &mut Transaction, long, double, bool, date, ok_to_log| -> Result<()> {
+        tx.set_long(&block, 0, long, ok_to_log)?;
+        tx.set_double(&block, 8, double, ok_to_log)?;
+        tx.set_bool(&block, 16, bool, ok_to_log)?;
+        tx.set_date(&block, 20, date, ok_to_log)
+    };
+    let get_values = |tx: &mut Transaction| -> Result<(i64, f64, bool, Date)> {
+        Ok((
+            tx.get_long(&block, 0)?,
+            tx.get_double(&block, 8)?,
+            tx.get_bool(&block, 16)?,
+            tx.get_date(&block, 20)?,
+        ))
+    };
+    {
+        let db = TinyDB::new(test_directory.clone(), 400, 8)?;
+        let tx = db.transaction()?;
+        let mut tx = tx.lock().unwrap();
+        tx.pin(&block).unwrap();
+        set_values(&mut tx, 1 << 40, 1.5, true, date, false)?;
+        tx.unpin(&block);
+        tx.commit()?;
+
+        // ロールバックすると書き込む前の値に戻る
+        let tx = db.transaction()?;
+        let mut tx = tx.lock().unwrap();
+        tx.pin(&block).unwrap();
+        set_values(&mut tx, -1, 0.0, false, Date::default(), true)?;
+        assert_eq!(get_values(&mut tx)?, (-1, 0.0, false, Date::default()));
+        tx.rollback()?;
+
+        // 変更したバッファがディスクに書き込まれた後、コミットしないまま終了する
+        let tx = db.transaction()?;
+        let mut tx = tx.lock().unwrap();
+        tx.pin(&block).unwrap();
+        set_values(&mut tx, 2, 2.5, false, Date::default(), true)?;
+        tx.unpin(&block);
+        db.buffer_manager.flush_all(tx.tx_num());
+    }
+
+    // 開き直したときの復旧で、コミットしていない変更を取り消す
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let tx = db.transaction()?;
+    let mut tx = tx.lock().unwrap();
+    tx.pin(&block).unwrap();
+    assert_eq!(get_values(&mut tx)?, (1 << 40, 1.5, true, date));
+    tx.unpin(&block);
+    tx.commit()?;
+    Ok(())
+}
+
+#[test]
+fn bulk_load_logging_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("bulk_load_logging_test");
+    let filename = "bulkfile".to_string();
+    let block0 = BlockId::new(filename.clone(), 0);
+    let log_records = |db: &TinyDB, tx_num: i32| -> Result<Vec<LogRecordType>> {
+        let mut ops = vec![];
+        for bytes in db.log_manager.lock().unwrap().iter()? {
+            let record = create_log_record(&bytes?)?;
+            if record.tx_number() == tx_num {
+                ops.push(record.op());
+            }
+        }
+        Ok(ops)
+    };
+    let bulk_load = |tx: &mut Transaction| -> Result<()> {
+        tx.set_bulk(true);
+        for i in 1..=20 {
+            let block = tx.append(filename.clone())?;
+            tx.pin(&block).unwrap();
+            for offset in (0..400).step_by(4) {
+                tx.set_int(&block, offset, i, true)?;
+            }
+            tx.unpin(&block);
+        }
+        tx.set_bulk(false);
+        // 一括処理の前からあるブロックの変更はいつも通り記録する
+        tx.pin(&block0).unwrap();
+        tx.set_int(&block0, 0, 99, true)?;
+        tx.unpin(&block0);
+        Ok(())
+    };
+
+    {
+        let db = TinyDB::new(&test_directory, 400, 8)?;
+        let tx = db.transaction()?;
+        let mut tx = unlock!(tx);
+        tx.append(filename.clone())?;
+        tx.pin(&block0).unwrap();
+        tx.set_int(&block0, 0, 1, true)?;
+        tx.unpin(&block0);
+        tx.commit()?;
+
+        // 追加したブロックの値は記録せず、ファイルごとに1つのレコードだけを書く
+        let tx = db.transaction()?;
+        let mut tx = unlock!(tx);
+        bulk_load(&mut tx)?;
+        assert_eq!(tx.size(filename.clone())?, 21);
+        assert!(log_records(&db, tx.tx_num())?.into_iter().eq([
+            LogRecordType::SetInt,
+            LogRecordType::BulkLoad,
+            LogRecordType::Start
+        ]));
+        tx.rollback()?;
+
+        // ロールバックすると追加したブロックを取り除く
+        let tx = db.transaction()?;
+        let mut tx = unlock!(tx);
+        assert_eq!(tx.size(filename.clone())?, 1);
+        tx.pin(&block0).unwrap();
+        assert_eq!(tx.get_int(&block0, 0).unwrap(), 1);
+        tx.unpin(&block0);
+        tx.commit()?;
+
+        // コミットせずに終了した一括処理の変更がディスクに書き込まれている
+        let tx = db.transaction()?;
+        let mut tx = unlock!(tx);
+        bulk_load(&mut tx)?;
+        db.buffer_manager.flush_all(tx.tx_num());
+    }
+
+    let db = TinyDB::new(&test_directory, 400, 8)?;
+    let tx = db.transaction()?;
+    let mut tx = unlock!(tx);
+    tx.recover()?;
+    assert_eq!(tx.size(filename.clone())?, 1);
+    tx.pin(&block0).unwrap();
+    assert_eq!(tx.get_int(&block0, 0).unwrap(), 1);
+    tx.unpin(&block0);
+    tx.commit()?;
+    Ok(())
+}
+
+#[test]
+fn metadata_snapshot_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("metadata_snapshot_test");
+    let clock = Arc::new(MockClock::default());
+    let config = DbConfig::new(400, 8).with_clock(clock);
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int, B int)", tx)
+    })?;
+
+    // クエリを計画したトランザクションはコミットするまでカタログの共有ロックを持ち続ける
+    let reader = db.transaction()?;
+    let planner = db.planner.clone().unwrap();
+    let plan = unlock!(planner).create_query_plan("select A from T", reader.clone())?;
+    let result = db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create index ta on T(A)", tx)
+    });
+    assert!(result.unwrap_err().downcast_ref::<LockTimeout>().is_some());
+
+    // 計画したときのレイアウトで読める
+    let scan = unlock!(plan).open()?;
+    unlock!(scan).before_first();
+    assert!(!unlock!(scan).next()?);
+    unlock!(scan).close();
+
+    // 同じトランザクションで作った索引はすぐにプランナーが考える
+    unlock!(planner).execute_update("create index tb on T(B)", reader.clone())?;
+    unlock!(planner).execute_update("insert into T(A, B) values (1, 2)", reader.clone())?;
+    let plan = unlock!(planner).create_query_plan("select A from T where B = 2", reader.clone())?;
+    let lines = unlock!(plan).describe().lines();
+    assert!(lines.iter().any(|line| line.contains("using tb")));
+    unlock!(reader).commit()?;
+
+    // コミットした後は他のトランザクションが索引を作れる
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create index ta on T(A)", tx)
+    })?;
+    Ok(())
+}
+
+#[test]
+fn verify_undo_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("verify_undo_test");
+    let mut db = TinyDB::new(test_directory, 400, 8)?;
+    db.init_planner()?;
+    let block = BlockId::new("testfile".into(), 0);
+    let mut tx = owned_transaction(&db)?;
+    tx.pin(&block).unwrap();
+    tx.set_int(&block, 0, 1, false)?;
+    tx.unpin(&block);
+    tx.commit()?;
+
+    // ログに記録した書き込みは取り消せるので、ロールバックした後の内容を確かめても問題ない
+    let mut tx = owned_transaction(&db)?;
+    tx.pin(&block).unwrap();
+    tx.set_int(&block, 0, 2, true)?;
+    tx.set_string(&block, 20, "two".into(), true)?;
+    tx.unpin(&block);
+    tx.rollback()?;
+
+    // ログに記録せずに書き換えると取り消せないので、verify-undo を有効にしているとパニックする
+    let mut tx = owned_transaction(&db)?;
+    tx.pin(&block).unwrap();
+    tx.set_int(&block, 0, 3, true)?;
+    tx.set_int(&block, 40, 4, false)?;
+    tx.unpin(&block);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tx.rollback()));
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn catalog_cache_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("catalog_cache_test");
+    let clock = Arc::new(MockClock::default());
+    let config = DbConfig::new(400, 8).with_clock(clock);
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int, B int)", tx.clone())?;
+        unlock!(planner).execute_update("insert into T(A, B) values (1, 2)", tx)
+    })?;
+    let planner = db.planner.clone().unwrap();
+
+    // 最初のトランザクションがカタログから読んだメタデータを、次のトランザクションはキャッシュから読む
+    for _ in 0..2 {
+        let tx = db.transaction()?;
+        let plan = unlock!(planner).create_query_plan("select A from T where B = 2", tx.clone())?;
+        let lines = unlock!(plan).describe().lines();
+        assert!(!lines.iter().any(|line| line.contains("using tb")));
+        unlock!(tx).commit()?;
+    }
+
+    // キャッシュから読んだトランザクションもカタログの共有ロックを持つので、索引は作れない
+    let reader = db.transaction()?;
+    unlock!(planner).create_query_plan("select A from T", reader.clone())?;
+    let result = db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create index tb on T(B)", tx)
+    });
+    assert!(result.unwrap_err().downcast_ref::<LockTimeout>().is_some());
+    unlock!(reader).commit()?;
+
+    // 索引を作ったトランザクションがコミットするとキャッシュを捨てるので、次のトランザクションは索引を使う
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create index tb on T(B)", tx)
+    })?;
+    let tx = db.transaction()?;
+    let plan = unlock!(planner).create_query_plan("select A from T where B = 2", tx.clone())?;
+    let lines = unlock!(plan).describe().lines();
+    assert!(lines.iter().any(|line| line.contains("using tb")));
+    unlock!(tx).commit()?;
+    Ok(())
+}
+
+/// select_values は T の A をすべて読み込む
+fn select_values(planner: &mut Planner, tx: Arc<Mutex<Transaction>>) -> Result<Vec<i32>> {
+    let plan = planner.create_query_plan("select A from T", tx)?;
+    let scan = unlock!(plan).open()?;
+    let mut scan = unlock!(scan);
+    let mut values = vec![];
+    while scan.next()? {
+        values.push(scan.get_int("A")?);
+    }
+    scan.close();
+    Ok(values)
+}
+
+#[test]
+fn read_your_writes_test() -> Result<()> {
+    let test_directory = tempdir()?.path().join("read_your_writes_test");
+    let clock = Arc::new(MockClock::default());
+    let config = DbConfig::new(400, 8).with_clock(clock);
+    let mut db = TinyDB::with_config(test_directory, config)?;
+    db.init_planner()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("create table T(A int)", tx)
+    })?;
+    let planner = db.planner.clone().unwrap();
+
+    // まだコミットしていない書き込みも、同じトランザクションで開いた別のスキャンから読める
+    let tx = db.transaction()?;
+    unlock!(planner).execute_update("insert into T(A) values (1)", tx.clone())?;
+    assert_eq!(select_values(&mut unlock!(planner), tx.clone())?, vec![1]);
+
+    // コミットの前に呼び出すフックに渡されるトランザクションでも、互いの書き込みを読める
+    let hook_planner = planner.clone();
+    unlock!(tx).before_commit(move |tx| {
+        assert_eq!(
+            select_values(&mut unlock!(hook_planner), tx.clone())?,
+            vec![1]
+        );
+        unlock!(hook_planner).execute_update("insert into T(A) values (2)", tx.clone())?;
+        assert_eq!(select_values(&mut unlock!(hook_planner), tx)?, vec![1, 2]);
+        Ok(())
+    });
+    unlock!(tx).commit()?;
+    assert!(unlock!(tx).pins().is_empty());
+
+    // フックの中で取ったロックもコミットで解放するので、他のトランザクションが書き込める
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("insert into T(A) values (3)", tx)
+    })?;
+    let values = db.with_transaction(|tx, planner| select_values(&mut unlock!(planner), tx))?;
+    assert_eq!(values, vec![1, 2, 3]);
+
+    // ロールバックすると書き込みを取り消してロックを解放する
+    let tx = db.transaction()?;
+    unlock!(planner).execute_update("insert into T(A) values (4)", tx.clone())?;
+    assert_eq!(
+        select_values(&mut unlock!(planner), tx.clone())?,
+        vec![1, 2, 3, 4]
+    );
+    unlock!(tx).rollback()?;
+    db.with_transaction(|tx, planner| {
+        unlock!(planner).execute_update("insert into T(A) values (5)", tx)
+    })?;
+    let values = db.with_transaction(|tx, planner| select_values(&mut unlock!(planner), tx))?;
+    assert_eq!(values, vec![1, 2, 3, 5]);
+    Ok(())
+}